@@ -157,6 +157,26 @@ rec {
       # File a bug if you depend on any for non-debug work!
       debug = internal.debugCrate { inherit packageId; };
     };
+    "poc_agent" = rec {
+      packageId = "poc_agent";
+      build = internal.buildRustCrateWithFeatures {
+        packageId = "poc_agent";
+      };
+
+      # Debug support which might change between releases.
+      # File a bug if you depend on any for non-debug work!
+      debug = internal.debugCrate { inherit packageId; };
+    };
+    "poc_data" = rec {
+      packageId = "poc_data";
+      build = internal.buildRustCrateWithFeatures {
+        packageId = "poc_data";
+      };
+
+      # Debug support which might change between releases.
+      # File a bug if you depend on any for non-debug work!
+      debug = internal.debugCrate { inherit packageId; };
+    };
     "spec_fuzz" = rec {
       packageId = "spec_fuzz";
       build = internal.buildRustCrateWithFeatures {
@@ -1588,6 +1608,7 @@ rec {
           {
             name = "ucode_dump";
             packageId = "ucode_dump";
+            usesDefaultFeatures = false;
           }
           {
             name = "uefi";
@@ -1677,6 +1698,26 @@ rec {
         ];
 
       };
+      "crc32fast" = rec {
+        crateName = "crc32fast";
+        version = "1.5.0";
+        edition = "2021";
+        sha256 = "04d51liy8rbssra92p0qnwjw8i9rm9c4m3bwy19wjamz1k4w30cl";
+        authors = [
+          "Sam Rijs <srijs@airpost.net>"
+          "Alex Crichton <alex@alexcrichton.com>"
+        ];
+        dependencies = [
+          {
+            name = "cfg-if";
+            packageId = "cfg-if";
+          }
+        ];
+        features = {
+          "default" = [ "std" ];
+        };
+        resolvedDefaultFeatures = [ "default" "std" ];
+      };
       "crossterm" = rec {
         crateName = "crossterm";
         version = "0.28.1";
@@ -1883,9 +1924,14 @@ rec {
             name = "log";
             packageId = "log";
           }
+          {
+            name = "ucode_compiler_dynamic";
+            packageId = "ucode_compiler_dynamic";
+          }
           {
             name = "ucode_dump";
             packageId = "ucode_dump";
+            usesDefaultFeatures = false;
           }
         ];
         buildDependencies = [
@@ -1996,9 +2042,19 @@ rec {
         version = "0.1.0";
         edition = "2021";
         src = lib.cleanSourceWith { filter = sourceFilter;  src = ./data_types; };
+        dependencies = [
+          {
+            name = "serde";
+            packageId = "serde";
+            optional = true;
+            usesDefaultFeatures = false;
+            features = [ "derive" ];
+          }
+        ];
         features = {
+          "serde" = [ "dep:serde" ];
         };
-        resolvedDefaultFeatures = [ "nostd" ];
+        resolvedDefaultFeatures = [ "nostd" "serde" ];
       };
       "deranged" = rec {
         crateName = "deranged";
@@ -2569,6 +2625,54 @@ rec {
         };
         resolvedDefaultFeatures = [ "env" "parse-value" "pear" "toml" ];
       };
+      "flate2" = rec {
+        crateName = "flate2";
+        version = "1.0.35";
+        edition = "2018";
+        sha256 = "0z6h0wa095wncpfngx75wyhyjnqwld7wax401gsvnzjhzgdbydn9";
+        authors = [
+          "Alex Crichton <alex@alexcrichton.com>"
+          "Josh Triplett <josh@joshtriplett.org>"
+        ];
+        dependencies = [
+          {
+            name = "crc32fast";
+            packageId = "crc32fast";
+          }
+          {
+            name = "miniz_oxide";
+            packageId = "miniz_oxide";
+            optional = true;
+            usesDefaultFeatures = false;
+            features = [ "with-alloc" ];
+          }
+          {
+            name = "miniz_oxide";
+            packageId = "miniz_oxide";
+            usesDefaultFeatures = false;
+            features = [ "with-alloc" ];
+            target = { target, features }: (("wasm32" == target."arch" or null) && (!("emscripten" == target."os" or null)));
+          }
+        ];
+        features = {
+          "any_zlib" = [ "any_impl" ];
+          "cloudflare-zlib-sys" = [ "dep:cloudflare-zlib-sys" ];
+          "cloudflare_zlib" = [ "any_zlib" "cloudflare-zlib-sys" ];
+          "default" = [ "rust_backend" ];
+          "libz-ng-sys" = [ "dep:libz-ng-sys" ];
+          "libz-rs-sys" = [ "dep:libz-rs-sys" ];
+          "libz-sys" = [ "dep:libz-sys" ];
+          "miniz-sys" = [ "rust_backend" ];
+          "miniz_oxide" = [ "dep:miniz_oxide" ];
+          "rust_backend" = [ "miniz_oxide" "any_impl" ];
+          "zlib" = [ "any_zlib" "libz-sys" ];
+          "zlib-default" = [ "any_zlib" "libz-sys/default" ];
+          "zlib-ng" = [ "any_zlib" "libz-ng-sys" ];
+          "zlib-ng-compat" = [ "zlib" "libz-sys/zlib-ng" ];
+          "zlib-rs" = [ "any_zlib" "libz-rs-sys" ];
+        };
+        resolvedDefaultFeatures = [ "any_impl" "default" "miniz_oxide" "rust_backend" ];
+      };
       "fnv" = rec {
         crateName = "fnv";
         version = "1.0.7";
@@ -2924,6 +3028,11 @@ rec {
         edition = "2021";
         src = lib.cleanSourceWith { filter = sourceFilter;  src = ./fuzzer_data; };
         dependencies = [
+          {
+            name = "crc32fast";
+            packageId = "crc32fast";
+            usesDefaultFeatures = false;
+          }
           {
             name = "hypervisor";
             packageId = "hypervisor";
@@ -2939,6 +3048,12 @@ rec {
             packageId = "log";
             features = [ "serde" ];
           }
+          {
+            name = "miniz_oxide";
+            packageId = "miniz_oxide";
+            usesDefaultFeatures = false;
+            features = [ "with-alloc" ];
+          }
           {
             name = "performance_timing";
             packageId = "performance_timing";
@@ -2965,16 +3080,24 @@ rec {
             usesDefaultFeatures = false;
             features = [ "alloc" "derive" ];
           }
+          {
+            name = "siphasher";
+            packageId = "siphasher";
+            usesDefaultFeatures = false;
+          }
           {
             name = "ucode_compiler_dynamic";
             packageId = "ucode_compiler_dynamic";
+            features = [ "serde" ];
           }
           {
             name = "x86_perf_counter";
             packageId = "x86_perf_counter";
           }
         ];
-
+        features = {
+        };
+        resolvedDefaultFeatures = [ "std" ];
       };
       "fuzzer_device" = rec {
         crateName = "fuzzer_device";
@@ -2994,6 +3117,11 @@ rec {
             packageId = "coverage";
             usesDefaultFeatures = false;
           }
+          {
+            name = "crc32fast";
+            packageId = "crc32fast";
+            usesDefaultFeatures = false;
+          }
           {
             name = "custom_processing_unit";
             packageId = "custom_processing_unit";
@@ -3034,6 +3162,16 @@ rec {
             name = "performance_timing";
             packageId = "performance_timing";
           }
+          {
+            name = "poc_agent";
+            packageId = "poc_agent";
+            optional = true;
+          }
+          {
+            name = "poc_data";
+            packageId = "poc_data";
+            optional = true;
+          }
           {
             name = "rand_core";
             packageId = "rand_core 0.9.2";
@@ -3058,6 +3196,17 @@ rec {
           {
             name = "ucode_dump";
             packageId = "ucode_dump";
+            usesDefaultFeatures = false;
+          }
+          {
+            name = "spec_fuzz";
+            packageId = "spec_fuzz";
+            optional = true;
+          }
+          {
+            name = "ucode_compiler_dynamic";
+            packageId = "ucode_compiler_dynamic";
+            optional = true;
           }
           {
             name = "uefi";
@@ -3107,11 +3256,13 @@ rec {
           "default" = [ "device_brix" "mutation_all" "rand_isaac" ];
           "device_bochs" = [ "platform_bochs" "bios_bochs" "uefi" ];
           "device_brix" = [ "platform_intel" "bios_ami" "uefi" ];
-          "mutation_all" = [ "mutation_random" ];
+          "mutation_all" = [ "mutation_random" "mutation_dictionary" "mutation_splice" ];
           "rand_isaac" = [ "dep:rand_isaac" ];
+          "scenario" = [ "dep:poc_agent" "dep:poc_data" ];
+          "speculation" = [ "uefi" "dep:spec_fuzz" "dep:ucode_compiler_dynamic" ];
           "uefi" = [ "coverage/uefi" "no_std" "dep:uefi" "dep:uefi_udp4" "dep:uefi-raw" ];
         };
-        resolvedDefaultFeatures = [ "__debug_bochs_pretend" "__debug_dont_reinitialize_fpu" "__debug_only_below_0x1000" "__debug_performance_trace" "__debug_pretend_no_coverage" "__debug_print_dissassembly" "__debug_print_events" "__debug_print_external_interrupt_notification" "__debug_print_mutation_info" "__debug_print_progress_net" "__debug_print_progress_print" "__debug_print_udp" "__device_bochs" "__device_brix" "bios_ami" "bios_bochs" "default" "device_bochs" "device_brix" "mutation_all" "mutation_random" "no_std" "platform_bochs" "platform_intel" "rand_isaac" "uefi" ];
+        resolvedDefaultFeatures = [ "__debug_bochs_pretend" "__debug_dont_reinitialize_fpu" "__debug_only_below_0x1000" "__debug_performance_trace" "__debug_pretend_no_coverage" "__debug_print_dissassembly" "__debug_print_events" "__debug_print_external_interrupt_notification" "__debug_print_mutation_info" "__debug_print_progress_net" "__debug_print_progress_print" "__debug_print_udp" "__debug_verify_memory_reset" "__device_bochs" "__device_brix" "bios_ami" "bios_bochs" "coverage_batch_selfcheck" "default" "device_bochs" "device_brix" "genetic_pareto" "mutation_all" "mutation_dictionary" "mutation_random" "mutation_splice" "no_std" "platform_bochs" "platform_intel" "rand_isaac" "scenario" "speculation" "uefi" "unsafe-msr" ];
       };
       "fuzzer_master" = rec {
         crateName = "fuzzer_master";
@@ -3169,11 +3320,16 @@ rec {
           {
             name = "data_types";
             packageId = "data_types";
+            features = [ "serde" ];
           }
           {
             name = "env_logger";
             packageId = "env_logger";
           }
+          {
+            name = "flate2";
+            packageId = "flate2";
+          }
           {
             name = "futures";
             packageId = "futures";
@@ -3181,6 +3337,7 @@ rec {
           {
             name = "fuzzer_data";
             packageId = "fuzzer_data";
+            features = [ "std" ];
           }
           {
             name = "hypervisor";
@@ -3216,6 +3373,11 @@ rec {
           {
             name = "performance_timing";
             packageId = "performance_timing";
+            features = [ "tui" ];
+          }
+          {
+            name = "poc_data";
+            packageId = "poc_data";
           }
           {
             name = "rand";
@@ -3225,6 +3387,10 @@ rec {
             name = "rand_isaac";
             packageId = "rand_isaac";
           }
+          {
+            name = "ratatui";
+            packageId = "ratatui";
+          }
           {
             name = "regex";
             packageId = "regex";
@@ -3249,13 +3415,19 @@ rec {
             packageId = "tokio";
             features = [ "full" ];
           }
+          {
+            name = "toml";
+            packageId = "toml";
+          }
           {
             name = "ucode_compiler_dynamic";
             packageId = "ucode_compiler_dynamic";
+            features = [ "serde" ];
           }
           {
             name = "ucode_dump";
             packageId = "ucode_dump";
+            features = [ "std" ];
           }
           {
             name = "x86_perf_counter";
@@ -6166,9 +6338,9 @@ rec {
       };
       "miniz_oxide" = rec {
         crateName = "miniz_oxide";
-        version = "0.8.3";
+        version = "0.8.9";
         edition = "2021";
-        sha256 = "093r1kd1r9dyf05cbvsibgmh96pxp3qhzfvpd6f15bpggamjqh5q";
+        sha256 = "05k3pdg8bjjzayq3rf0qhpirq9k37pxnasfn4arbs17phqn6m9qz";
         authors = [
           "Frommi <daniil.liferenko@gmail.com>"
           "oyvindln <oyvindln@users.noreply.github.com>"
@@ -6183,10 +6355,10 @@ rec {
         ];
         features = {
           "alloc" = [ "dep:alloc" ];
-          "compiler_builtins" = [ "dep:compiler_builtins" ];
           "core" = [ "dep:core" ];
           "default" = [ "with-alloc" ];
-          "rustc-dep-of-std" = [ "core" "alloc" "compiler_builtins" "adler2/rustc-dep-of-std" ];
+          "rustc-dep-of-std" = [ "core" "alloc" "adler2/rustc-dep-of-std" ];
+          "serde" = [ "dep:serde" ];
           "simd" = [ "simd-adler32" ];
           "simd-adler32" = [ "dep:simd-adler32" ];
         };
@@ -6782,6 +6954,11 @@ rec {
             name = "performance_timing_macros";
             packageId = "performance_timing_macros";
           }
+          {
+            name = "ratatui";
+            packageId = "ratatui";
+            optional = true;
+          }
           {
             name = "serde";
             packageId = "serde";
@@ -6799,7 +6976,10 @@ rec {
             target = { target, features }: ("x86_64" == target."arch" or null);
           }
         ];
-
+        features = {
+          "tui" = [ "dep:ratatui" ];
+        };
+        resolvedDefaultFeatures = [ "tui" ];
       };
       "performance_timing_macros" = rec {
         crateName = "performance_timing_macros";
@@ -6845,6 +7025,90 @@ rec {
         ];
 
       };
+      "poc_agent" = rec {
+        crateName = "poc_agent";
+        version = "0.1.0";
+        edition = "2021";
+        src = lib.cleanSourceWith { filter = sourceFilter;  src = ./evaluation/poc_agent; };
+        dependencies = [
+          {
+            name = "custom_processing_unit";
+            packageId = "custom_processing_unit";
+            features = [ "nostd" ];
+          }
+          {
+            name = "data_types";
+            packageId = "data_types";
+            features = [ "nostd" ];
+          }
+          {
+            name = "itertools";
+            packageId = "itertools 0.14.0";
+            usesDefaultFeatures = false;
+            features = [ "use_alloc" ];
+          }
+          {
+            name = "log";
+            packageId = "log";
+          }
+          {
+            name = "performance_timing";
+            packageId = "performance_timing";
+          }
+          {
+            name = "poc_data";
+            packageId = "poc_data";
+          }
+          {
+            name = "ucode_compiler_derive";
+            packageId = "ucode_compiler_derive";
+          }
+          {
+            name = "ucode_compiler_dynamic";
+            packageId = "ucode_compiler_dynamic";
+          }
+          {
+            name = "ucode_dump";
+            packageId = "ucode_dump";
+            usesDefaultFeatures = false;
+          }
+        ];
+
+      };
+      "poc_data" = rec {
+        crateName = "poc_data";
+        version = "0.1.0";
+        edition = "2021";
+        src = lib.cleanSourceWith { filter = sourceFilter;  src = ./evaluation/poc_data; };
+        dependencies = [
+          {
+            name = "clap";
+            packageId = "clap";
+            optional = true;
+            features = [ "derive" ];
+          }
+          {
+            name = "log";
+            packageId = "log";
+          }
+          {
+            name = "postcard";
+            packageId = "postcard";
+            usesDefaultFeatures = false;
+            features = [ "alloc" ];
+          }
+          {
+            name = "serde";
+            packageId = "serde";
+            usesDefaultFeatures = false;
+            features = [ "derive" ];
+          }
+        ];
+        features = {
+          "clap" = [ "dep:clap" ];
+        };
+        resolvedDefaultFeatures = [ "clap" ];
+      };
       "postcard" = rec {
         crateName = "postcard";
         version = "1.1.1";
@@ -9656,7 +9920,7 @@ rec {
         ];
         features = {
         };
-        resolvedDefaultFeatures = [ "__debug_performance_trace" "__debug_print_udp" ];
+        resolvedDefaultFeatures = [ "__debug_performance_trace" "__debug_print_udp" "unsafe-msr" ];
       };
       "speculation_ucode" = rec {
         crateName = "speculation_ucode";
@@ -11262,8 +11526,20 @@ rec {
         crateName = "ucode_compiler_bridge";
         version = "0.1.0";
         edition = "2021";
+        crateBin = [
+          {
+            name = "ucode_assemble";
+            path = "src/main_assemble.rs";
+            requiredFeatures = [ ];
+          }
+        ];
         src = lib.cleanSourceWith { filter = sourceFilter;  src = ./ucode_compiler_bridge; };
         dependencies = [
+          {
+            name = "clap";
+            packageId = "clap";
+            features = [ "derive" ];
+          }
           {
             name = "error-chain";
             packageId = "error-chain";
@@ -11326,6 +11602,7 @@ rec {
           {
             name = "serde";
             packageId = "serde";
+            optional = true;
             usesDefaultFeatures = false;
             features = [ "derive" ];
           }
@@ -11336,7 +11613,24 @@ rec {
             packageId = "itertools 0.14.0";
           }
         ];
-
+        devDependencies = [
+          {
+            name = "postcard";
+            packageId = "postcard";
+            usesDefaultFeatures = false;
+            features = [ "alloc" ];
+          }
+          {
+            name = "serde_json";
+            packageId = "serde_json";
+            usesDefaultFeatures = false;
+            features = [ "alloc" ];
+          }
+        ];
+        features = {
+          "serde" = [ "dep:serde" "data_types/serde" ];
+        };
+        resolvedDefaultFeatures = [ "serde" ];
       };
       "ucode_dump" = rec {
         crateName = "ucode_dump";
@@ -11348,8 +11642,15 @@ rec {
             name = "data_types";
             packageId = "data_types";
           }
+          {
+            name = "ucode_compiler_dynamic";
+            packageId = "ucode_compiler_dynamic";
+          }
         ];
-
+        features = {
+          "default" = [ "text_disassembly" ];
+        };
+        resolvedDefaultFeatures = [ "default" "std" "text_disassembly" ];
       };
       "ucs2" = rec {
         crateName = "ucs2";
@@ -83,9 +83,7 @@ fn main() {
         result.extend(process_binary(&path, &mut decoder));
     }
 
-    let corpus = InstructionCorpus {
-        instructions: result,
-    };
+    let corpus = InstructionCorpus::new(result);
 
     println!("Found {} unique instructions", corpus.instructions.len());
     println!(
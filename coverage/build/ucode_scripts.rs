@@ -20,6 +20,7 @@ pub fn build_ucode_scripts() -> ucode_compiler_bridge::Result<()> {
     let options = CompilerOptions {
         allow_unused: true,
         avoid_unknown_256: true,
+        strict: false,
         cpuid: None,
     };
 
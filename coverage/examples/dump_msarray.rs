@@ -9,7 +9,7 @@ use alloc::format;
 use alloc::string::String;
 use alloc::vec::Vec;
 use custom_processing_unit::{
-    apply_ldat_read_func, call_custom_ucode_function, CustomProcessingUnit,
+    apply_ldat_read_func, ms_array_read_raw, CustomProcessingUnit, LdatArray,
 };
 use log::info;
 use uefi::prelude::*;
@@ -33,11 +33,13 @@ unsafe fn main() -> Status {
         return Status::ABORTED;
     }
 
-    let lengths = [0x8000, 0x8000, 0x80, 0x20, 0x200];
-
-    for (i, len) in lengths.iter().enumerate() {
-        println!(" --- DUMPING MSRAM ARRAY {} ---", i);
-        let values = match dump_msram(*len, i as u8) {
+    for array in LdatArray::ALL {
+        println!(
+            " --- DUMPING MSRAM ARRAY {} ({}) ---",
+            array.selector(),
+            array
+        );
+        let values = match dump_msram(array) {
             Ok(values) => values,
             Err(e) => {
                 println!("Failed to dump msram: {:?}", e);
@@ -46,7 +48,7 @@ unsafe fn main() -> Status {
         };
         let hex = hex_format(values);
         hex.lines().take(4).for_each(|line| println!("{}", line));
-        if let Err(err) = write_file(i as u8, hex.as_str()) {
+        if let Err(err) = write_file(array.selector() as u8, hex.as_str()) {
             println!("Failed to write file: {:?}", err);
             return Status::ABORTED;
         }
@@ -111,24 +113,16 @@ fn hex_format(values: Vec<u64>) -> String {
     result
 }
 
-fn dump_msram(count: usize, array: u8) -> Result<Vec<u64>, String> {
+fn dump_msram(array: LdatArray) -> Result<Vec<u64>, String> {
     let mut result = Vec::new();
     let func = apply_ldat_read_func();
+    let count = array.size();
     for i in 0..count {
         if (i % 0x10) == 0 || i + 1 == count {
             print!("\rDumping MSRAM array {:04x}...", i);
         }
 
-        let dword_idx = 0;
-        let array_sel = array as usize;
-        let bank_sel = 0;
-
-        let array_bank_sel =
-            0x10000 | ((dword_idx & 0xf) << 12) | ((array_sel & 0xf) << 8) | (bank_sel & 0xf);
-        let array_addr = 0xC00000 | (i & 0xffff);
-
-        result
-            .push(call_custom_ucode_function(func, [0x6a0, array_bank_sel, array_addr]).rax as u64);
+        result.push(ms_array_read_raw(func, array, i) as u64);
     }
     print!("\r                                \r");
     Ok(result)
@@ -71,7 +71,7 @@ unsafe fn main() -> Status {
 
     interface.reset_coverage();
 
-    let mut harness = match CoverageHarness::new(&mut interface, &cpu) {
+    let mut harness = match CoverageHarness::new(&mut interface, &mut cpu) {
         Ok(harness) => harness,
         Err(e) => {
             info!("Failed to initiate harness {:?}", e);
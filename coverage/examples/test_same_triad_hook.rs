@@ -9,10 +9,9 @@ use coverage::harness::coverage_harness::CoverageHarness;
 use coverage::interface::safe::ComInterface;
 use coverage::{coverage_collector, coverage_collector_debug_tools, interface_definition};
 use custom_processing_unit::{
-    apply_patch, call_custom_ucode_function, lmfence, ms_seqw_read, CustomProcessingUnit,
-    FunctionResult,
+    apply_patch, lmfence, ms_hook_read, ms_seqw_read, CustomProcessingUnit, FunctionResult,
 };
-use data_types::addresses::{Address, UCInstructionAddress};
+use data_types::addresses::{Address, MSRAMHookIndex, UCInstructionAddress};
 use itertools::Itertools;
 use log::info;
 use uefi::boot::ScopedProtocol;
@@ -52,11 +51,7 @@ unsafe fn main() -> Status {
     let hooks = {
         let max_hooks = interface.description().max_number_of_hooks;
 
-        let device_max_hooks = match cpu.current_glm_version {
-            custom_processing_unit::GLM_OLD => 31,
-            custom_processing_unit::GLM_NEW => 32,
-            _ => 0,
-        };
+        let device_max_hooks = cpu.hook_count();
 
         max_hooks.min(device_max_hooks)
     };
@@ -70,12 +65,8 @@ unsafe fn main() -> Status {
 
     fn read_hooks() {
         print!("Hooks:    ");
-        for i in 0..8 {
-            let hook = call_custom_ucode_function(
-                coverage_collector_debug_tools::LABEL_FUNC_LDAT_READ_HOOKS,
-                [i, 0, 0],
-            )
-            .rax;
+        for index in MSRAMHookIndex::iter_all().take(8) {
+            let hook = ms_hook_read(coverage_collector_debug_tools::LABEL_FUNC_LDAT_READ, index);
             print!("{:08x}, ", hook);
         }
         println!();
@@ -93,7 +84,7 @@ unsafe fn main() -> Status {
         println!();
     }
 
-    let mut harness = match CoverageHarness::new(&mut interface, &cpu) {
+    let mut harness = match CoverageHarness::new(&mut interface, &mut cpu) {
         Ok(harness) => harness,
         Err(e) => {
             info!("Failed to initiate harness {:?}", e);
@@ -13,10 +13,10 @@ use coverage::harness::coverage_harness::CoverageHarness;
 use coverage::interface::safe::ComInterface;
 use coverage::{coverage_collector, coverage_collector_debug_tools, interface_definition};
 use custom_processing_unit::{
-    apply_patch, calculate_hook_value, call_custom_ucode_function, lmfence,
-    ms_patch_instruction_read, ms_seqw_read, CustomProcessingUnit, FunctionResult, HookGuard,
+    apply_patch, calculate_hook_value, lmfence, ms_hook_read, ms_patch_instruction_read,
+    ms_seqw_read, CustomProcessingUnit, FunctionResult, HookGuard,
 };
-use data_types::addresses::UCInstructionAddress;
+use data_types::addresses::{MSRAMHookIndex, UCInstructionAddress};
 use itertools::Itertools;
 use log::info;
 use ucode_compiler_dynamic::instruction::Instruction;
@@ -62,7 +62,7 @@ unsafe fn main() -> Status {
     };
 
     println!("Init harness");
-    let mut harness = match CoverageHarness::new(&mut interface, &cpu) {
+    let mut harness = match CoverageHarness::new(&mut interface, &mut cpu) {
         Ok(harness) => harness,
         Err(e) => {
             info!("Failed to initiate harness {:?}", e);
@@ -187,14 +187,10 @@ fn get_args() -> Option<Vec<UCInstructionAddress>> {
 
 fn print_status(max_count: usize, hooks: &[UCInstructionAddress]) {
     print!("Hooks     : ");
-    for i in 0..max_count
-        .min(interface_definition::COM_INTERFACE_DESCRIPTION.max_number_of_hooks as usize)
-    {
-        let hook = call_custom_ucode_function(
-            coverage_collector_debug_tools::LABEL_FUNC_LDAT_READ_HOOKS,
-            [i, 0, 0],
-        )
-        .rax;
+    for index in MSRAMHookIndex::iter_all().take(
+        max_count.min(interface_definition::COM_INTERFACE_DESCRIPTION.max_number_of_hooks as usize),
+    ) {
+        let hook = ms_hook_read(coverage_collector_debug_tools::LABEL_FUNC_LDAT_READ, index);
         print!("{:08x}, ", hook);
     }
     println!();
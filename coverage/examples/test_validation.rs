@@ -65,7 +65,7 @@ unsafe fn main() -> Status {
 
     println!("Hookable addresses: {:04x}", hookable_addresses.len() * 2);
 
-    let mut coverage_harness = match CoverageHarness::new(&mut interface, &cpu) {
+    let mut coverage_harness = match CoverageHarness::new(&mut interface, &mut cpu) {
         Ok(harness) => harness,
         Err(e) => {
             info!("Failed to initiate harness {:?}", e);
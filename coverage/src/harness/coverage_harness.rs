@@ -23,8 +23,8 @@ use alloc::vec::Vec;
 use core::fmt::Debug;
 #[cfg(feature = "ucode")]
 use custom_processing_unit::{
-    apply_patch, call_custom_ucode_function, disable_all_hooks, enable_hooks, lmfence,
-    CustomProcessingUnit, FunctionResult, HookGuard, PatchError,
+    call_custom_ucode_function, disable_all_hooks, enable_hooks, lmfence, AllocError,
+    CustomProcessingUnit, FunctionResult, HookGuard, HookTargetError,
 };
 use data_types::addresses::UCInstructionAddress;
 #[cfg(feature = "timing_measurement")]
@@ -47,6 +47,8 @@ pub enum CoverageError {
     AddressNotHookable(UCInstructionAddress, NotHookableReason),
     /// Failed to disassemble sequence word
     SequenceWordDissembleError(DisassembleError),
+    /// The address is not a valid hook target, see [`custom_processing_unit::validate_hook_target`]
+    InvalidHookTarget(HookTargetError),
 }
 
 /// Main harness for collecting coverage information
@@ -56,8 +58,8 @@ pub struct CoverageHarness<'a, 'b, 'c> {
     interface: &'a mut ComInterface<'b>,
     /// Previous hook settings to restore on drop
     previous_hook_settings: Option<HookGuard>,
-    /// Reference to the custom processing unit
-    custom_processing_unit: &'c CustomProcessingUnit,
+    /// Reference to the custom processing unit, its patch space holds the coverage patch
+    custom_processing_unit: &'c mut CustomProcessingUnit,
     /// Settings for the modification engine
     compile_mode: ModificationEngineSettings,
 }
@@ -73,12 +75,13 @@ impl<'a, 'b, 'c> CoverageHarness<'a, 'b, 'c> {
     ///
     /// # Returns
     ///
-    /// A new harness instance or a patch error if initialization fails
+    /// A new harness instance or an error if the coverage patch could not be applied
     pub fn new(
         interface: &'a mut ComInterface<'b>,
-        cpu: &'c CustomProcessingUnit,
-    ) -> Result<CoverageHarness<'a, 'b, 'c>, PatchError> {
-        apply_patch(&coverage_collector::PATCH)?;
+        cpu: &'c mut CustomProcessingUnit,
+    ) -> Result<CoverageHarness<'a, 'b, 'c>, AllocError> {
+        cpu.patch_space_mut()
+            .apply_fixed(&coverage_collector::PATCH)?;
         interface.zero_jump_table();
 
         Ok(CoverageHarness {
@@ -300,6 +303,12 @@ impl Drop for CoverageHarness<'_, '_, '_> {
 
         // dropping the hook guard will restore the hooks
         drop(self.previous_hook_settings.take());
+
+        // the patch RAM can be used by other patches afterward
+        let _ = self
+            .custom_processing_unit
+            .patch_space_mut()
+            .free(coverage_collector::PATCH.addr);
     }
 }
 
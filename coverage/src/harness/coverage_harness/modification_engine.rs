@@ -100,11 +100,10 @@ pub fn is_hookable(
 
     let address = address.align_even();
 
-    let triad = Triad::try_from(
-        rom.triad(address)
-            .ok_or(NotHookableReason::AddressNotInDump)?,
-    )
-    .map_err(NotHookableReason::ModificationFailedSequenceWordParse)?;
+    let triad = rom
+        .decoded_triad(address)
+        .ok_or(NotHookableReason::AddressNotInDump)?
+        .map_err(NotHookableReason::ModificationFailedSequenceWordParse)?;
 
     let instruction_pair = [
         triad.instructions[address.triad_offset() as usize + 0],
@@ -237,11 +236,10 @@ pub fn modify_triad_for_hooking(
 ) -> Result<Triad, NotHookableReason> {
     is_hookable(address, rom, mode)?;
 
-    let triad = Triad::try_from(
-        rom.triad(address)
-            .ok_or(NotHookableReason::AddressNotInDump)?,
-    )
-    .map_err(NotHookableReason::ModificationFailedSequenceWordParse)?;
+    let triad = rom
+        .decoded_triad(address)
+        .ok_or(NotHookableReason::AddressNotInDump)?
+        .map_err(NotHookableReason::ModificationFailedSequenceWordParse)?;
 
     let instruction = *triad
         .instructions
@@ -3,6 +3,7 @@
 
 use core::mem::align_of;
 use core::mem::size_of;
+use data_types::addresses::MSRAMHookIndex;
 
 /// Describes the communication interface layout and configuration
 ///
@@ -93,8 +94,12 @@ impl ComInterfaceDescription {
 
 /// Maximum number of hooks that can be installed
 ///
-/// This sets an upper limit on coverage collection capacity.
-const MAX_NUMBER_OF_HOOKS: usize = 1; // todo: change back to higher value
+/// This sets an upper limit on coverage collection capacity. Every hook adds handler and exit
+/// triads to the coverage patch, so the value is bounded by the MSRAM patch space.
+const MAX_NUMBER_OF_HOOKS: usize = 4;
+
+// every hook of the collector occupies an entry of the hook table
+const _: () = assert!(MAX_NUMBER_OF_HOOKS <= MSRAMHookIndex::COUNT);
 
 /// Type for storing coverage count values
 pub type CoverageCount = u16;
@@ -19,6 +19,7 @@ fn main() {
             allow_unused: false,
             cpuid: None,
             avoid_unknown_256: true,
+            strict: false,
         },
     ) {
         panic!("Failed to compile: {:?}", err);
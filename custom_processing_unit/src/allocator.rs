@@ -0,0 +1,657 @@
+//! Allocation of the MSRAM patch space
+//!
+//! The [`MsramAllocator`] keeps track of which triads of the patch space are in use, so that
+//! several components can place their patches without hard-coding non-overlapping addresses.
+//! The patches of this crate, e.g. `func_init`, are placed statically and are always reserved.
+//! Besides the regions, the triads occupied by each patch applied via [`crate::patch_ucode`] are
+//! tracked, so that overlapping patches are detected even across allocators. Freeing a region
+//! releases its triads again.
+
+use crate::{
+    patch_ucode, relocate_patch, validate_patch_range, AppliedPatch, PatchError, PATCH_SPACE_TRIADS,
+};
+#[cfg(feature = "nostd")]
+use alloc::collections::BTreeMap;
+use core::fmt;
+use core::fmt::{Display, Formatter};
+use core::sync::atomic::{AtomicU16, Ordering};
+use data_types::addresses::{Address, UCInstructionAddress};
+use data_types::patch::{Patch, UcodePatchBlob};
+#[cfg(not(feature = "nostd"))]
+use std::collections::BTreeMap;
+
+/// Errors of the [`MsramAllocator`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocError {
+    /// No free region of the requested size is left
+    OutOfSpace {
+        /// The requested size in triads
+        triads: usize,
+    },
+    /// The region overlaps a region in use
+    Overlap {
+        /// The start address of the region in use
+        existing: UCInstructionAddress,
+    },
+    /// No region was allocated at this address, or it is reserved for a patch of this crate
+    NotAllocated {
+        /// The address passed to the allocator
+        addr: UCInstructionAddress,
+    },
+    /// A patch was already applied to the region
+    AlreadyApplied {
+        /// The start address of the region
+        addr: UCInstructionAddress,
+    },
+    /// The patch is larger than the region
+    TooLarge {
+        /// The start address of the region
+        addr: UCInstructionAddress,
+        /// The length of the patch in triads
+        len: usize,
+    },
+    /// The region is misplaced or the patch could not be applied
+    Patch(PatchError),
+}
+
+impl Display for AllocError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AllocError::OutOfSpace { triads } => {
+                write!(f, "no free region of {} triads left in the MSRAM", triads)
+            }
+            AllocError::Overlap { existing } => {
+                write!(f, "region overlaps the region in use at {}", existing)
+            }
+            AllocError::NotAllocated { addr } => write!(f, "no region allocated at {}", addr),
+            AllocError::AlreadyApplied { addr } => {
+                write!(f, "a patch was already applied to the region at {}", addr)
+            }
+            AllocError::TooLarge { addr, len } => {
+                write!(f, "patch of {} triads exceeds the region at {}", len, addr)
+            }
+            AllocError::Patch(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// A region of the patch space in use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Region {
+    /// Length of the region in triads
+    triads: usize,
+    /// Whether the region holds a patch of this crate and can not be freed
+    fixed: bool,
+    /// Whether a patch was applied to the region via the allocator
+    applied: bool,
+}
+
+/// Tracks the regions of the MSRAM patch space in use
+#[derive(Debug, Clone)]
+pub struct MsramAllocator {
+    /// Regions in use by their start address
+    regions: BTreeMap<UCInstructionAddress, Region>,
+}
+
+impl Default for MsramAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MsramAllocator {
+    /// Creates an allocator with the statically placed patches of this crate reserved
+    pub fn new() -> Self {
+        let mut allocator = MsramAllocator {
+            regions: BTreeMap::new(),
+        };
+
+        for patch in [
+            crate::patches::func_init::PATCH,
+            crate::patches::func_init_glm_new::PATCH,
+            crate::patches::func_hook::PATCH,
+            crate::patches::func_ldat_read::PATCH,
+        ] {
+            allocator.reserve_fixed(patch.addr, patch.ucode_patch.len());
+        }
+        // the existing patch moved by [`crate::CustomProcessingUnit::apply_existing_patches`]
+        allocator.reserve_fixed(UCInstructionAddress::from_const(0x7dfc), 1);
+
+        allocator
+    }
+
+    /// Reserves a region for a patch of this crate, extending a region at the same address
+    fn reserve_fixed(&mut self, addr: UCInstructionAddress, triads: usize) {
+        let region = self.regions.entry(addr).or_insert(Region {
+            triads,
+            fixed: true,
+            applied: false,
+        });
+        region.triads = region.triads.max(triads);
+    }
+
+    /// Allocates a free region of the patch space
+    ///
+    /// # Arguments
+    ///
+    /// * `triads` - The size of the region in triads
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(UCInstructionAddress)` with the start address of the lowest free region
+    /// - `Err(AllocError::OutOfSpace)` if no free region of this size is left
+    pub fn alloc(&mut self, triads: usize) -> Result<UCInstructionAddress, AllocError> {
+        let mut addr = UCInstructionAddress::MSRAM_START;
+        loop {
+            if validate_patch_range(addr, triads).is_err() {
+                return Err(AllocError::OutOfSpace { triads });
+            }
+            match self.overlapping(addr, triads) {
+                Some((existing, region)) => addr = existing + region.triads * 4,
+                None => break,
+            }
+        }
+
+        self.insert(addr, triads);
+        Ok(addr)
+    }
+
+    /// Reserves a region at a fixed address, e.g. for a patch that is not relocatable
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The start address of the region, must be the start of a triad
+    /// * `triads` - The size of the region in triads
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` if the region was reserved, it can be released via [`Self::free`]
+    /// - `Err(AllocError::Overlap)` if the region overlaps a region in use
+    /// - `Err(AllocError::Patch)` if the region is misplaced, see [`crate::patch_ucode`]
+    pub fn reserve(&mut self, addr: UCInstructionAddress, triads: usize) -> Result<(), AllocError> {
+        validate_patch_range(addr, triads).map_err(AllocError::Patch)?;
+        if let Some((existing, _)) = self.overlapping(addr, triads) {
+            return Err(AllocError::Overlap { existing });
+        }
+
+        self.insert(addr, triads);
+        Ok(())
+    }
+
+    /// Releases a region allocated via [`Self::alloc`] or [`Self::reserve`]
+    ///
+    /// Patches applied to the region are forgotten, so that the region can be patched again.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The start address of the region
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` if the region was released
+    /// - `Err(AllocError::NotAllocated)` if no region starts at this address or it is reserved
+    ///   for a patch of this crate
+    pub fn free(&mut self, addr: UCInstructionAddress) -> Result<(), AllocError> {
+        match self.regions.get(&addr) {
+            Some(region) if !region.fixed => {
+                release_patch_range(addr, region.triads);
+                self.regions.remove(&addr);
+                Ok(())
+            }
+            _ => Err(AllocError::NotAllocated { addr }),
+        }
+    }
+
+    /// Applies a patch to an allocated region
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The start address of the region
+    /// * `ucode_patch` - The patch data to apply, must fit into the region
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` if the patch was applied successfully
+    /// - `Err(AllocError::NotAllocated)` if no region was allocated at this address
+    /// - `Err(AllocError::AlreadyApplied)` if a patch was already applied to the region
+    /// - `Err(AllocError::TooLarge)` if the patch does not fit into the region
+    /// - `Err(AllocError::Patch)` if the patch could not be applied, see [`crate::patch_ucode`]
+    pub fn apply(
+        &mut self,
+        addr: UCInstructionAddress,
+        ucode_patch: &UcodePatchBlob,
+    ) -> Result<(), AllocError> {
+        let region = match self.regions.get_mut(&addr) {
+            Some(region) if !region.fixed => region,
+            _ => return Err(AllocError::NotAllocated { addr }),
+        };
+        if region.applied {
+            return Err(AllocError::AlreadyApplied { addr });
+        }
+        if ucode_patch.len() > region.triads {
+            return Err(AllocError::TooLarge {
+                addr,
+                len: ucode_patch.len(),
+            });
+        }
+
+        patch_ucode(addr, ucode_patch, false).map_err(AllocError::Patch)?;
+        region.applied = true;
+        Ok(())
+    }
+
+    /// Reserves the region of a patch that is not relocatable and applies the patch there
+    ///
+    /// Unlike [`crate::apply_patch`], the patch can be removed again by passing its address to
+    /// [`Self::free`], so that the region can be patched by others afterward.
+    ///
+    /// # Arguments
+    ///
+    /// * `patch` - The patch to apply at its own address
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` if the patch was applied successfully
+    /// - `Err(AllocError::Overlap)` if the region of the patch overlaps a region in use
+    /// - `Err(AllocError::Patch)` if the region is misplaced or the patch could not be applied
+    pub fn apply_fixed(&mut self, patch: &Patch) -> Result<(), AllocError> {
+        self.reserve(patch.addr, patch.ucode_patch.len())?;
+
+        let result = self.apply(patch.addr, patch.ucode_patch);
+        if result.is_err() {
+            // nothing was claimed, the patches overlapping the region must stay claimed
+            self.regions.remove(&patch.addr);
+        }
+        result
+    }
+
+    /// Allocates a region for a patch and applies the patch relocated to this region
+    ///
+    /// # Arguments
+    ///
+    /// * `patch` - The patch to apply, see [`crate::apply_patch_at`]
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(AppliedPatch)` with the address and the relocated labels of the patch
+    /// - `Err(AllocError)` if no region is left or the patch could not be relocated or applied
+    pub fn apply_patch<'c>(
+        &mut self,
+        patch: &Patch<'_, '_, 'c>,
+    ) -> Result<AppliedPatch<'c>, AllocError> {
+        let addr = self.alloc(patch.ucode_patch.len())?;
+
+        let result = relocate_patch(patch, addr)
+            .map_err(AllocError::Patch)
+            .and_then(|(entries, applied)| self.apply(addr, &entries).map(|_| applied));
+        if result.is_err() {
+            let _ = self.free(addr);
+        }
+        result
+    }
+
+    /// Returns whether any triad of the patch space is in use at an address
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address to check
+    pub fn is_used(&self, addr: UCInstructionAddress) -> bool {
+        self.overlapping(addr.triad_base(), 1).is_some()
+    }
+
+    /// Returns the number of free triads
+    pub fn free_triads(&self) -> usize {
+        let used: usize = self.regions.values().map(|region| region.triads).sum();
+        PATCH_SPACE_TRIADS - used
+    }
+
+    /// Records a region as in use
+    fn insert(&mut self, addr: UCInstructionAddress, triads: usize) {
+        self.regions.insert(
+            addr,
+            Region {
+                triads,
+                fixed: false,
+                applied: false,
+            },
+        );
+    }
+
+    /// Returns the first region in use overlapping the given region
+    fn overlapping(
+        &self,
+        addr: UCInstructionAddress,
+        triads: usize,
+    ) -> Option<(UCInstructionAddress, Region)> {
+        let end = addr.address() + triads.max(1) * 4;
+        self.regions
+            .iter()
+            .find(|(start, region)| {
+                start.address() < end && addr.address() < start.address() + region.triads * 4
+            })
+            .map(|(start, region)| (*start, *region))
+    }
+}
+
+/// Records which triads of the MSRAM patch space are occupied by applied patches
+///
+/// Backs the overlap check of [`crate::patch_ucode`], regions freed via [`MsramAllocator::free`]
+/// are released from it. Each triad stores the patch occupying it, encoded as the index of its
+/// first triad plus one in the upper byte and its length in triads in the lower byte, or zero if
+/// it is unused.
+struct PatchTable {
+    /// The encoded patch occupying each triad
+    owners: [AtomicU16; PATCH_SPACE_TRIADS],
+}
+
+impl PatchTable {
+    /// Creates a table without any applied patches
+    const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const UNUSED: AtomicU16 = AtomicU16::new(0);
+        PatchTable {
+            owners: [UNUSED; PATCH_SPACE_TRIADS],
+        }
+    }
+
+    /// Marks the triads of a patch as occupied
+    ///
+    /// Applying a patch with the same address and length again is not considered an overlap.
+    /// Patches that are (partially) overwritten are removed from the table.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address the patch is applied at
+    /// * `len` - The length of the patch in triads
+    /// * `overwrite` - Whether overlapping previously applied patches is allowed
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` if the triads were marked as occupied
+    /// - `Err(PatchError)` if the patch is misplaced or overlaps a patch and `overwrite` is not set
+    fn claim(
+        &self,
+        addr: UCInstructionAddress,
+        len: usize,
+        overwrite: bool,
+    ) -> Result<(), PatchError> {
+        let start = validate_patch_range(addr, len)?;
+        if len == 0 {
+            return Ok(());
+        }
+        let owner = (((start + 1) << 8) | len) as u16;
+        let triads = &self.owners[start..start + len];
+
+        if !overwrite {
+            if let Some(existing) = triads
+                .iter()
+                .map(|triad| triad.load(Ordering::Relaxed))
+                .find(|existing| *existing != 0 && *existing != owner)
+            {
+                return Err(PatchError::Overlap {
+                    existing: Self::start_of(existing),
+                });
+            }
+        }
+
+        for triad in triads {
+            let existing = triad.load(Ordering::Relaxed);
+            if existing != 0 && existing != owner {
+                self.release(existing);
+            }
+        }
+        for triad in triads {
+            triad.store(owner, Ordering::Relaxed);
+        }
+
+        Ok(())
+    }
+
+    /// Removes a patch from all triads it still occupies
+    fn release(&self, owner: u16) {
+        let start = (owner >> 8) as usize - 1;
+        let len = (owner & 0xff) as usize;
+        for triad in &self.owners[start..(start + len).min(PATCH_SPACE_TRIADS)] {
+            let _ = triad.compare_exchange(owner, 0, Ordering::Relaxed, Ordering::Relaxed);
+        }
+    }
+
+    /// Removes all patches occupying a range of triads
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address of the first triad of the range
+    /// * `len` - The length of the range in triads
+    fn release_range(&self, addr: UCInstructionAddress, len: usize) {
+        let Ok(start) = validate_patch_range(addr, len) else {
+            return;
+        };
+        for triad in &self.owners[start..start + len] {
+            let existing = triad.load(Ordering::Relaxed);
+            if existing != 0 {
+                self.release(existing);
+            }
+        }
+    }
+
+    /// Returns the address of the first triad of an encoded patch
+    fn start_of(owner: u16) -> UCInstructionAddress {
+        UCInstructionAddress::MSRAM_START + ((owner >> 8) as usize - 1) * 4
+    }
+}
+
+/// Patches applied via [`crate::patch_ucode`]
+static APPLIED_PATCHES: PatchTable = PatchTable::new();
+
+/// Marks the triads of a patch applied via [`crate::patch_ucode`] as occupied
+///
+/// See [`PatchTable::claim`] for the arguments and errors.
+pub(crate) fn claim_patch_range(
+    addr: UCInstructionAddress,
+    len: usize,
+    overwrite: bool,
+) -> Result<(), PatchError> {
+    APPLIED_PATCHES.claim(addr, len, overwrite)
+}
+
+/// Forgets the patches applied to a range of triads, see [`MsramAllocator::free`]
+pub(crate) fn release_patch_range(addr: UCInstructionAddress, len: usize) {
+    APPLIED_PATCHES.release_range(addr, len);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::allocator::PatchTable;
+    use crate::{AllocError, MsramAllocator, PatchError};
+    use data_types::addresses::UCInstructionAddress;
+    #[cfg(feature = "emulation")]
+    use data_types::patch::Patch;
+
+    #[test]
+    fn test_msram_allocator() {
+        let _lock = crate::emulation::lock();
+        let mut allocator = MsramAllocator::new();
+        let address = UCInstructionAddress::from_const;
+
+        assert!(allocator.is_used(address(0x7d01)));
+        assert!(allocator.is_used(address(0x7da0)));
+        assert!(allocator.is_used(address(0x7dfe)));
+        assert!(!allocator.is_used(address(0x7c00)));
+
+        let free = allocator.free_triads();
+        assert_eq!(allocator.alloc(4), Ok(address(0x7c00)));
+        assert_eq!(allocator.alloc(2), Ok(address(0x7c10)));
+        assert_eq!(allocator.free_triads(), free - 6);
+
+        assert_eq!(
+            allocator.reserve(address(0x7c14), 1),
+            Err(AllocError::Overlap {
+                existing: address(0x7c10)
+            })
+        );
+        assert_eq!(
+            allocator.reserve(address(0x7c1a), 1),
+            Err(AllocError::Patch(PatchError::Misaligned))
+        );
+        assert_eq!(allocator.reserve(address(0x7c18), 1), Ok(()));
+
+        // freed regions are reused, regions in use are skipped
+        assert_eq!(allocator.free(address(0x7c10)), Ok(()));
+        assert_eq!(
+            allocator.free(address(0x7c10)),
+            Err(AllocError::NotAllocated {
+                addr: address(0x7c10)
+            })
+        );
+        assert_eq!(allocator.alloc(3), Ok(address(0x7c1c)));
+        assert_eq!(allocator.alloc(2), Ok(address(0x7c10)));
+
+        // the patches of this crate can not be freed
+        assert_eq!(
+            allocator.free(address(0x7d00)),
+            Err(AllocError::NotAllocated {
+                addr: address(0x7d00)
+            })
+        );
+        assert_eq!(
+            allocator.alloc(128),
+            Err(AllocError::OutOfSpace { triads: 128 })
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "emulation")]
+    fn test_msram_allocator_apply() {
+        let _lock = crate::emulation::lock();
+        let mut allocator = MsramAllocator::new();
+        let address = UCInstructionAddress::from_const;
+        let patch = [[0xa04337080235, 0, 0, 0x2460d200]; 2];
+
+        assert_eq!(allocator.reserve(address(0x7c80), 2), Ok(()));
+        assert_eq!(
+            allocator.apply(address(0x7c84), &patch),
+            Err(AllocError::NotAllocated {
+                addr: address(0x7c84)
+            })
+        );
+        assert_eq!(allocator.apply(address(0x7c80), &patch), Ok(()));
+
+        let mut image = [[0; 4]; 2];
+        crate::read_patch(crate::apply_ldat_read_func(), address(0x7c80), &mut image);
+        assert_eq!(image, patch);
+
+        assert_eq!(
+            allocator.apply(address(0x7c80), &patch),
+            Err(AllocError::AlreadyApplied {
+                addr: address(0x7c80)
+            })
+        );
+
+        assert_eq!(allocator.free(address(0x7c80)), Ok(()));
+        assert_eq!(allocator.reserve(address(0x7c80), 1), Ok(()));
+        assert_eq!(
+            allocator.apply(address(0x7c80), &patch),
+            Err(AllocError::TooLarge {
+                addr: address(0x7c80),
+                len: 2
+            })
+        );
+        assert_eq!(allocator.apply(address(0x7c80), &patch[..1]), Ok(()));
+    }
+
+    #[test]
+    #[cfg(feature = "emulation")]
+    fn test_msram_allocator_apply_fixed() {
+        let _lock = crate::emulation::lock();
+        let mut allocator = MsramAllocator::new();
+        let address = UCInstructionAddress::from_const;
+        let ucode_patch = [[0xa04337080235, 0, 0, 0x2460d200]; 2];
+        let patch = Patch {
+            addr: address(0x7c00),
+            hook_address: None,
+            hook_index: None,
+            ucode_patch: &ucode_patch,
+            labels: &[],
+        };
+
+        assert_eq!(allocator.apply_fixed(&patch), Ok(()));
+        assert_eq!(
+            allocator.apply_fixed(&patch),
+            Err(AllocError::Overlap {
+                existing: address(0x7c00)
+            })
+        );
+        assert_eq!(
+            crate::patch_ucode(address(0x7c04), &ucode_patch[..1], false),
+            Err(PatchError::Overlap {
+                existing: address(0x7c00)
+            })
+        );
+
+        // freeing the region releases the patch, others can be applied there
+        assert_eq!(allocator.free(address(0x7c00)), Ok(()));
+        assert_eq!(
+            crate::patch_ucode(address(0x7c04), &ucode_patch[..1], false),
+            Ok(())
+        );
+        assert_eq!(
+            allocator.apply_fixed(&patch),
+            Err(AllocError::Patch(PatchError::Overlap {
+                existing: address(0x7c04)
+            }))
+        );
+        assert!(!allocator.is_used(address(0x7c00)));
+        assert_eq!(
+            crate::patch_ucode(address(0x7c04), &ucode_patch, false),
+            Err(PatchError::Overlap {
+                existing: address(0x7c04)
+            })
+        );
+    }
+
+    #[test]
+    fn test_patch_table() {
+        let table = PatchTable::new();
+        let address = UCInstructionAddress::from_const;
+
+        assert_eq!(
+            table.claim(address(0x7bfc), 1, false),
+            Err(PatchError::OutOfRange {
+                addr: address(0x7bfc),
+                len: 1
+            })
+        );
+        assert_eq!(
+            table.claim(address(0x7dfc), 2, true),
+            Err(PatchError::OutOfRange {
+                addr: address(0x7dfc),
+                len: 2
+            })
+        );
+        assert_eq!(
+            table.claim(address(0x7c02), 1, false),
+            Err(PatchError::Misaligned)
+        );
+
+        assert_eq!(table.claim(address(0x7dfc), 1, false), Ok(()));
+        assert_eq!(table.claim(address(0x7c00), 128, true), Ok(()));
+        assert_eq!(table.claim(address(0x7c00), 128, false), Ok(()));
+
+        // a partially overwritten patch no longer occupies its other triads
+        assert_eq!(table.claim(address(0x7d00), 4, true), Ok(()));
+        assert_eq!(table.claim(address(0x7c00), 4, false), Ok(()));
+        assert_eq!(
+            table.claim(address(0x7cfc), 2, false),
+            Err(PatchError::Overlap {
+                existing: address(0x7d00)
+            })
+        );
+        assert_eq!(
+            table.claim(address(0x7c00), 2, false),
+            Err(PatchError::Overlap {
+                existing: address(0x7c00)
+            })
+        );
+        assert_eq!(table.claim(address(0x7c10), 2, false), Ok(()));
+    }
+}
@@ -0,0 +1,445 @@
+//! In-memory model of the MSRAM and the match & patch registers for emulation mode
+//!
+//! With the `emulation` feature the debug instructions are not executed. Instead, the CRBUS
+//! and staging buffer accesses of the helpers are applied to this model. Custom microcode
+//! functions can not be executed either: the functions in [`crate::patches`] are recognized
+//! by comparing the MSRAM contents at the invoked address against their patches, and their
+//! effect is applied to the model. Invoking any other address returns zeros.
+
+use crate::StagingBufferAddress::{RegTmp0, RegTmp1, RegTmp2};
+use crate::{patches, FunctionResult, LdatArray, MS_ARRAY_PDAT};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use data_types::addresses::{
+    Address, MSRAMInstructionPartReadAddress, MSRAMInstructionPartWriteAddress,
+    MSRAMSequenceWordAddress, UCInstructionAddress,
+};
+use data_types::patch::Patch;
+
+/// CRBUS register of the match & patch unit, bit 0 disables all hooks
+const MATCH_PATCH_CONTROL: usize = 0x692;
+/// CRBUS register of the LDAT port of the MS arrays selecting the array entry
+const LDAT_ADDRESS: usize = MS_ARRAY_PDAT;
+/// CRBUS register of the LDAT port of the MS arrays selecting the array
+const LDAT_SELECT: usize = MS_ARRAY_PDAT + 1;
+/// CRBUS register of the LDAT port of the MS arrays holding the low half of a write
+const LDAT_WRITE_LOW: usize = MS_ARRAY_PDAT + 4;
+/// CRBUS register of the LDAT port of the MS arrays, writing it stores the entry
+const LDAT_WRITE_HIGH: usize = MS_ARRAY_PDAT + 5;
+
+/// Debug command accessing the CRBUS
+const COMMAND_CRBUS: usize = 0;
+/// Debug command accessing the staging buffer
+const COMMAND_STAGING_BUFFER: usize = 0x80;
+
+/// Bits of an entry that are stored in the MS arrays
+const ENTRY_MASK: usize = 0xffff_ffff_ffff;
+
+/// Return value of the functions in [`crate::patches`]
+const FUNCTION_SUCCESS: usize = 0x0000133700001337;
+
+/// Hook entry written by [`crate::patches::func_init`], redirecting U4588 to U7dfc
+const GLM_OLD_INIT_HOOK: usize = 0x3efe4589;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY: AtomicUsize = AtomicUsize::new(0);
+
+/// Patch instructions, indexed by the write address
+static PATCH_INSTRUCTIONS: [AtomicUsize; 128 * 4] = [EMPTY; 128 * 4];
+/// Sequence words, indexed by the sequence word address
+static SEQUENCE_WORDS: [AtomicUsize; 128] = [EMPTY; 128];
+/// Match & patch hook table, indexed by the hook index
+static HOOKS: [AtomicUsize; 32] = [EMPTY; 32];
+
+/// CRBUS registers of the model, in the order of [`crbus_register`]
+static CRBUS: [AtomicUsize; 4] = [EMPTY; 4];
+/// The temporary registers of the staging buffer, in the order of [`staging_register`]
+static STAGING_BUFFER: [AtomicUsize; 3] = [EMPTY; 3];
+
+/// The functions of [`crate::patches`] the model can execute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    Hook,
+    Init,
+    InitGlmNew,
+    LdatRead,
+}
+
+fn crbus_register(address: usize) -> Option<&'static AtomicUsize> {
+    match address {
+        MATCH_PATCH_CONTROL => Some(&CRBUS[0]),
+        LDAT_ADDRESS => Some(&CRBUS[1]),
+        LDAT_SELECT => Some(&CRBUS[2]),
+        LDAT_WRITE_LOW => Some(&CRBUS[3]),
+        _ => None,
+    }
+}
+
+fn staging_register(address: usize) -> Option<&'static AtomicUsize> {
+    [RegTmp0, RegTmp1, RegTmp2]
+        .iter()
+        .position(|register| register.to_address() == address)
+        .map(|i| &STAGING_BUFFER[i])
+}
+
+/// Returns the entry of an MS array
+///
+/// # Arguments
+///
+/// * `array_sel` - The array selector of the LDAT port, the MSROM arrays are not modeled
+/// * `fast_addr` - The address written to the LDAT port, for the patch instructions the write
+///   address
+fn array_entry(array_sel: usize, fast_addr: usize) -> Option<&'static AtomicUsize> {
+    match LdatArray::from_selector(array_sel)? {
+        LdatArray::SequenceWordRam => SEQUENCE_WORDS.get(fast_addr),
+        LdatArray::MatchPatch if fast_addr & 1 == 0 => HOOKS.get(fast_addr / 2),
+        LdatArray::InstructionRam => PATCH_INSTRUCTIONS.get(fast_addr),
+        _ => None,
+    }
+}
+
+/// Emulates a read of the debug interface, see `udebug_read`
+pub(crate) fn udebug_read(command: usize, address: usize) -> usize {
+    let register = match command {
+        COMMAND_CRBUS => crbus_register(address),
+        COMMAND_STAGING_BUFFER => staging_register(address),
+        _ => None,
+    };
+
+    register.map_or(0, |register| register.load(Ordering::Relaxed))
+}
+
+/// Emulates a write to the debug interface, see `udebug_write`
+///
+/// Writing the high half of an LDAT entry stores the entry in the selected MS array.
+pub(crate) fn udebug_write(command: usize, address: usize, value: usize) {
+    if command == COMMAND_CRBUS && address == LDAT_WRITE_HIGH {
+        let array_sel = (udebug_read(COMMAND_CRBUS, LDAT_SELECT) >> 8) & 0xf;
+        let fast_addr = udebug_read(COMMAND_CRBUS, LDAT_ADDRESS) & 0xffff;
+        let low = udebug_read(COMMAND_CRBUS, LDAT_WRITE_LOW) & 0xffffffff;
+
+        if let Some(entry) = array_entry(array_sel, fast_addr) {
+            entry.store((low | (value << 32)) & ENTRY_MASK, Ordering::Relaxed);
+        }
+        return;
+    }
+
+    let register = match command {
+        COMMAND_CRBUS => crbus_register(address),
+        COMMAND_STAGING_BUFFER => staging_register(address),
+        _ => None,
+    };
+
+    if let Some(register) = register {
+        register.store(value, Ordering::Relaxed);
+    }
+}
+
+/// Emulates the invocation of a microcode function, see `udebug_invoke`
+///
+/// The arguments are taken from the staging buffer, like the functions in [`crate::patches`]
+/// do.
+///
+/// # Returns
+///
+/// The result of the function, or zeros if no known function is loaded at `address`
+pub(crate) fn invoke(address: UCInstructionAddress) -> FunctionResult {
+    let [arg0, arg1, arg2] = [0, 1, 2].map(|i| STAGING_BUFFER[i].load(Ordering::Relaxed));
+
+    let Some(function) = loaded_function(address) else {
+        return FunctionResult::default();
+    };
+
+    let rax = match function {
+        Function::Hook => {
+            if let Some(entry) = array_entry(LdatArray::MatchPatch.selector(), arg1) {
+                entry.store(arg0 & ENTRY_MASK, Ordering::Relaxed);
+            }
+            FUNCTION_SUCCESS
+        }
+        Function::Init => {
+            for entry in &HOOKS[..HOOKS.len() - 1] {
+                entry.store(0, Ordering::Relaxed);
+            }
+            HOOKS[HOOKS.len() - 1].store(GLM_OLD_INIT_HOOK, Ordering::Relaxed);
+            FUNCTION_SUCCESS
+        }
+        Function::InitGlmNew => {
+            for entry in &HOOKS {
+                entry.store(0, Ordering::Relaxed);
+            }
+            FUNCTION_SUCCESS
+        }
+        Function::LdatRead => {
+            let array_sel = (arg1 >> 8) & 0xf;
+            let mut fast_addr = arg2 & 0xffff;
+            if array_sel == LdatArray::InstructionRam.selector() {
+                fast_addr = MSRAMInstructionPartWriteAddress::from(
+                    MSRAMInstructionPartReadAddress::from_const(fast_addr),
+                )
+                .address();
+            }
+
+            array_entry(array_sel, fast_addr).map_or(0, |entry| entry.load(Ordering::Relaxed))
+        }
+    };
+
+    FunctionResult {
+        rax,
+        ..FunctionResult::default()
+    }
+}
+
+/// Returns the function of [`crate::patches`] loaded at an address
+fn loaded_function(address: UCInstructionAddress) -> Option<Function> {
+    [
+        (patches::func_hook::PATCH, Function::Hook),
+        (patches::func_init::PATCH, Function::Init),
+        (patches::func_init_glm_new::PATCH, Function::InitGlmNew),
+        (patches::func_ldat_read::PATCH, Function::LdatRead),
+    ]
+    .into_iter()
+    .find(|(patch, _)| patch.addr == address && is_loaded(patch))
+    .map(|(_, function)| function)
+}
+
+/// Checks whether the MSRAM holds a patch at its address
+fn is_loaded(patch: &Patch) -> bool {
+    let seqw: MSRAMSequenceWordAddress = patch.addr.into();
+
+    patch.ucode_patch.iter().enumerate().all(|(i, row)| {
+        let instructions = row[..3].iter().enumerate().all(|(offset, instruction)| {
+            let index: MSRAMInstructionPartWriteAddress =
+                patch.addr.patch_offset(i * 3 + offset).into();
+            PATCH_INSTRUCTIONS[index.address()].load(Ordering::Relaxed) == instruction & ENTRY_MASK
+        });
+
+        instructions
+            && SEQUENCE_WORDS[(seqw + i).address()].load(Ordering::Relaxed) == row[3] & ENTRY_MASK
+    })
+}
+
+/// Clears the model and forgets all applied patches
+#[cfg(test)]
+fn reset() {
+    for entry in PATCH_INSTRUCTIONS
+        .iter()
+        .chain(&SEQUENCE_WORDS)
+        .chain(&HOOKS)
+        .chain(&CRBUS)
+        .chain(&STAGING_BUFFER)
+    {
+        entry.store(0, Ordering::Relaxed);
+    }
+    crate::allocator::release_patch_range(
+        UCInstructionAddress::MSRAM_START,
+        crate::helpers::PATCH_SPACE_TRIADS,
+    );
+}
+
+/// Set while a test uses the model, see [`lock`]
+#[cfg(test)]
+static LOCKED: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+
+/// Exclusive access to the model for a test, released on drop
+#[cfg(test)]
+pub(crate) struct ModelLock;
+
+/// Waits until no other test uses the model and resets it
+///
+/// Tests run in parallel but share the model and the applied patches, so every test
+/// accessing them has to hold the lock.
+#[cfg(test)]
+pub(crate) fn lock() -> ModelLock {
+    while LOCKED
+        .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        core::hint::spin_loop();
+    }
+    reset();
+    ModelLock
+}
+
+#[cfg(test)]
+impl Drop for ModelLock {
+    fn drop(&mut self) {
+        LOCKED.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(all(test, feature = "emulation"))]
+mod tests {
+    use crate::emulation::lock;
+    use crate::{
+        apply_hook_patch_func, apply_ldat_read_func, calculate_hook_value, disable_all_hooks,
+        ms_hook_read, ms_hook_write, patch_ucode, read_patch, AllocError, CustomProcessingUnit,
+        Error, PatchError, ProcessorInfo, GLM_NEW, GLM_OLD,
+    };
+    use data_types::addresses::{MSRAMHookIndex, UCInstructionAddress};
+    use data_types::patch::Patch;
+
+    #[test]
+    fn test_apply_existing_patches() {
+        let _lock = lock();
+        let address = UCInstructionAddress::from_const(0x7dfc);
+        let mut image = [[0; 4]; 1];
+
+        let mut cpu = CustomProcessingUnit::with_version(GLM_NEW, ProcessorInfo::detect());
+        cpu.apply_existing_patches().unwrap();
+        read_patch(apply_ldat_read_func(), address, &mut image);
+        assert_eq!(image, [[0; 4]]);
+
+        let mut cpu = CustomProcessingUnit::with_version(GLM_OLD, ProcessorInfo::detect());
+        cpu.apply_existing_patches().unwrap();
+        read_patch(apply_ldat_read_func(), address, &mut image);
+        assert_eq!(image, [[0xa04337080235, 0, 0, 0x2460d200]]);
+    }
+
+    #[test]
+    fn test_zero_hooks() {
+        let _lock = lock();
+        for index in 0..32 {
+            ms_hook_write(MSRAMHookIndex::ZERO + index, 0x3e000001 | index << 1);
+        }
+        disable_all_hooks();
+
+        // the last entry redirects U4588 to the existing patch moved to U7dfc
+        let mut cpu = CustomProcessingUnit::with_version(GLM_OLD, ProcessorInfo::detect());
+        cpu.init().unwrap();
+        let state = cpu.snapshot_match_patch();
+        assert!(state.hooks_enabled);
+        assert_eq!(state.entries.len(), 32);
+        assert_eq!(state.set_entries().count(), 1);
+        assert_eq!(state.entries[31].index, MSRAMHookIndex::MAX);
+        assert_eq!(state.entries[31].value, 0x3efe4589);
+        assert_eq!(state.entries[31].patch_address(), 0x7dfcusize);
+
+        let mut cpu = CustomProcessingUnit::with_version(GLM_NEW, ProcessorInfo::detect());
+        cpu.zero_hooks().unwrap();
+        assert_eq!(cpu.snapshot_match_patch().set_entries().count(), 0);
+    }
+
+    #[test]
+    fn test_hook() {
+        let _lock = lock();
+        let mut cpu = CustomProcessingUnit::with_version(GLM_NEW, ProcessorInfo::detect());
+        cpu.init().unwrap();
+
+        let hook_function = apply_hook_patch_func();
+        let index = MSRAMHookIndex::ZERO + 1;
+        let rdrand = ucode_dump::dump::cpu_000506CA::RDRAND_XLAT;
+        let value = calculate_hook_value(rdrand, UCInstructionAddress::MSRAM_START, true).unwrap();
+
+        cpu.hook(
+            hook_function,
+            index,
+            rdrand,
+            UCInstructionAddress::MSRAM_START,
+            true,
+        )
+        .unwrap();
+        assert_eq!(ms_hook_read(apply_ldat_read_func(), index), value);
+        assert_eq!(
+            ms_hook_read(apply_ldat_read_func(), MSRAMHookIndex::ZERO),
+            0
+        );
+
+        // the entry in use is left untouched
+        assert!(matches!(
+            cpu.hook(hook_function, index, 0x42a, 0x7c04, true),
+            Err(Error::HookIndexInUse(i, address)) if i == index && address == rdrand
+        ));
+        assert_eq!(ms_hook_read(apply_ldat_read_func(), index), value);
+
+        assert!(matches!(
+            cpu.hooks_mut().remove(apply_hook_patch_func(), index),
+            Ok(Some(_))
+        ));
+        assert_eq!(ms_hook_read(apply_ldat_read_func(), index), 0);
+    }
+
+    #[test]
+    fn test_patch_ucode_overlap() {
+        let _lock = lock();
+        let read_function = apply_ldat_read_func();
+        let address = UCInstructionAddress::from_const;
+        let first = [[0xa04337080235, 0, 0, 0x2460d200]; 2];
+        let second = [[0x1, 0x2, 0x3, 0x4]; 2];
+        let mut image = [[0; 4]; 3];
+
+        assert_eq!(patch_ucode(address(0x7c00), &first, false), Ok(()));
+        assert_eq!(
+            patch_ucode(address(0x7c04), &second, false),
+            Err(PatchError::Overlap {
+                existing: address(0x7c00)
+            })
+        );
+        read_patch(read_function, address(0x7c00), &mut image);
+        assert_eq!(image, [first[0], first[1], [0; 4]]);
+
+        assert_eq!(patch_ucode(address(0x7c04), &second, true), Ok(()));
+        read_patch(read_function, address(0x7c00), &mut image);
+        assert_eq!(image, [first[0], second[0], second[1]]);
+    }
+
+    #[test]
+    fn test_patch_space_handover() {
+        let _lock = lock();
+        let address = UCInstructionAddress::from_const;
+        let coverage_patch = [[0xa04337080235, 0, 0, 0x2460d200]; 4];
+        let speculation_patch = [[0x1, 0x2, 0x3, 0x4]; 2];
+        let coverage = Patch {
+            addr: address(0x7c00),
+            hook_address: None,
+            hook_index: None,
+            ucode_patch: &coverage_patch,
+            labels: &[],
+        };
+        let speculation = Patch {
+            ucode_patch: &speculation_patch,
+            ..coverage
+        };
+        let mut image = [[0; 4]; 2];
+
+        // coverage collection is initialized
+        let mut coverage_cpu = CustomProcessingUnit::with_version(GLM_NEW, ProcessorInfo::detect());
+        assert_eq!(
+            coverage_cpu.patch_space_mut().apply_fixed(&coverage),
+            Ok(())
+        );
+
+        // the speculation patch is rejected while coverage collection is active
+        let mut speculation_cpu =
+            CustomProcessingUnit::with_version(GLM_NEW, ProcessorInfo::detect());
+        assert_eq!(
+            speculation_cpu.patch_space_mut().apply_fixed(&speculation),
+            Err(AllocError::Patch(PatchError::Overlap {
+                existing: address(0x7c00)
+            }))
+        );
+
+        // coverage collection is suspended for the speculation request
+        assert_eq!(coverage_cpu.patch_space_mut().free(coverage.addr), Ok(()));
+        drop(coverage_cpu);
+        assert_eq!(
+            speculation_cpu.patch_space_mut().apply_fixed(&speculation),
+            Ok(())
+        );
+        read_patch(apply_ldat_read_func(), address(0x7c00), &mut image);
+        assert_eq!(image, speculation_patch);
+        assert_eq!(
+            speculation_cpu.patch_space_mut().free(speculation.addr),
+            Ok(())
+        );
+        drop(speculation_cpu);
+
+        // coverage collection is initialized again
+        let mut coverage_cpu = CustomProcessingUnit::with_version(GLM_NEW, ProcessorInfo::detect());
+        assert_eq!(
+            coverage_cpu.patch_space_mut().apply_fixed(&coverage),
+            Ok(())
+        );
+        read_patch(apply_ldat_read_func(), address(0x7c00), &mut image);
+        assert_eq!(image, [coverage_patch[0], coverage_patch[1]]);
+    }
+}
@@ -8,25 +8,28 @@
 //! - Staging buffer operations
 //! - Array operations (LDAT)
 
-use crate::Error;
 use crate::StagingBufferAddress::{RegTmp0, RegTmp1, RegTmp2};
+use crate::{Error, LdatArray, LdatReadAddress, LdatWriteAddress};
 #[cfg(feature = "nostd")]
 use alloc::format;
 #[cfg(feature = "nostd")]
 use alloc::string::ToString;
+#[cfg(feature = "nostd")]
+use alloc::vec::Vec;
 use core::arch::asm;
 use core::fmt;
 use core::fmt::{Display, Formatter};
 use data_types::addresses::{
-    Address, MSRAMAddress, MSRAMHookIndex, MSRAMInstructionPartReadAddress,
-    MSRAMInstructionPartWriteAddress, MSRAMSequenceWordAddress, UCInstructionAddress,
+    Address, MSRAMHookIndex, MSRAMInstructionPartReadAddress, MSRAMInstructionPartWriteAddress,
+    MSRAMSequenceWordAddress, UCInstructionAddress,
 };
-use data_types::patch::{Patch, UcodePatchBlob};
+use data_types::patch::{Patch, PatchFile, UcodePatchBlob};
 use log::trace;
 #[cfg(not(feature = "nostd"))]
 use std::format;
 #[cfg(not(feature = "nostd"))]
 use std::string::ToString;
+use ucode_dump::RomDump;
 
 /// Represents addresses in the staging buffer
 #[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
@@ -125,18 +128,7 @@ fn barrier() {
 /// * <https://doi.org/10.1007/s11416-022-00438-x>
 fn udebug_read(command: usize, address: usize) -> usize {
     if cfg!(feature = "emulation") {
-        let res_high: usize = 0;
-        let res_low: usize;
-        unsafe {
-            asm!(
-            "nop",
-            out("rdx") res_low,
-            in("rcx") command,
-            in("rax") address,
-            options(nostack)
-            );
-        }
-        return res_low | (res_high << 32);
+        return crate::emulation::udebug_read(command, address);
     }
 
     let mut res_high: usize;
@@ -170,18 +162,7 @@ fn udebug_read(command: usize, address: usize) -> usize {
 /// * <https://doi.org/10.1007/s11416-022-00438-x>
 fn udebug_write(command: usize, address: usize, value: usize) {
     if cfg!(feature = "emulation") {
-        let val_low = value as u32;
-        lmfence();
-        unsafe {
-            asm!(
-            "nop",
-            in("rcx") command,
-            in("rax") address,
-            in("rdx") val_low,
-            options(nostack)
-            );
-        }
-        lmfence();
+        crate::emulation::udebug_write(command, address, value);
         return;
     }
 
@@ -223,21 +204,11 @@ pub fn udebug_invoke(
     res_d: &mut usize,
 ) {
     if cfg!(feature = "emulation") {
-        lmfence();
-        unsafe {
-            asm!(
-            "nop",
-            inout("rax") address.address() => *res_a,
-            inout("rcx") 0xd8usize => *res_c,
-            inout("rdx") 0usize => *res_d,
-            options(nostack)
-            );
-        }
-        lmfence();
-        *res_a = 0;
-        *res_b = 0;
-        *res_c = 0;
-        *res_d = 0;
+        let result = crate::emulation::invoke(address);
+        *res_a = result.rax;
+        *res_b = result.rbx;
+        *res_c = result.rcx;
+        *res_d = result.rdx;
         return;
     }
 
@@ -257,6 +228,129 @@ pub fn udebug_invoke(
     lmfence();
 }
 
+/// Invokes a microcode function and returns the whole register file
+///
+/// The frame pointer and the stack pointer are neither passed nor captured. All other
+/// registers are marked as clobbered, so the compiler saves and restores them as needed.
+/// In emulation mode the invocation is replaced by loading the result of the emulated function
+/// into `rax`-`rdx` and the register number into `rsi`, `rdi` and `r8`-`r15`. The registers
+/// are captured by the same instructions as on hardware, so that their plumbing can be tested.
+///
+/// # Arguments
+///
+/// * `address` - The address of the function to invoke
+///
+/// # Returns
+///
+/// The register file after the invocation in the [`UcodeCallResult`] struct
+///
+/// # Literature
+/// * <https://doi.org/10.1007/s11416-022-00438-x>
+pub fn udebug_invoke_full(address: UCInstructionAddress) -> UcodeCallResult {
+    let mut result = UcodeCallResult::default();
+
+    // rbx is reserved by the compiler and all other registers hold results, so the results
+    // are stored via the pointer in rdi, which is kept on the stack during the invocation.
+    // rbx is passed in rsi, the invocation is given as the instructions after the arguments
+    macro_rules! invoke_and_capture {
+        ($rax:expr, $rbx:expr, $rcx:expr, $rdx:expr, $($invoke:literal),+) => {
+            asm!(
+            "push rbx",
+            "push rdi",
+            "mov rbx, rsi",
+            $($invoke,)+
+            "pushfq",
+            "xchg rdi, [rsp + 8]",
+            "mov [rdi + {offset_rax}], rax",
+            "mov [rdi + {offset_rbx}], rbx",
+            "mov [rdi + {offset_rcx}], rcx",
+            "mov [rdi + {offset_rdx}], rdx",
+            "mov [rdi + {offset_rsi}], rsi",
+            "mov [rdi + {offset_r8}], r8",
+            "mov [rdi + {offset_r9}], r9",
+            "mov [rdi + {offset_r10}], r10",
+            "mov [rdi + {offset_r11}], r11",
+            "mov [rdi + {offset_r12}], r12",
+            "mov [rdi + {offset_r13}], r13",
+            "mov [rdi + {offset_r14}], r14",
+            "mov [rdi + {offset_r15}], r15",
+            "pop rax",
+            "mov [rdi + {offset_rflags}], rax",
+            "pop rax",
+            "mov [rdi + {offset_rdi}], rax",
+            "pop rbx",
+            inout("rax") $rax => _,
+            inout("rsi") $rbx => _,
+            inout("rcx") $rcx => _,
+            inout("rdx") $rdx => _,
+            inout("rdi") &mut result as *mut UcodeCallResult => _,
+            out("r8") _,
+            out("r9") _,
+            out("r10") _,
+            out("r11") _,
+            out("r12") _,
+            out("r13") _,
+            out("r14") _,
+            out("r15") _,
+            offset_rax = const core::mem::offset_of!(UcodeCallResult, rax),
+            offset_rbx = const core::mem::offset_of!(UcodeCallResult, rbx),
+            offset_rcx = const core::mem::offset_of!(UcodeCallResult, rcx),
+            offset_rdx = const core::mem::offset_of!(UcodeCallResult, rdx),
+            offset_rsi = const core::mem::offset_of!(UcodeCallResult, rsi),
+            offset_rdi = const core::mem::offset_of!(UcodeCallResult, rdi),
+            offset_r8 = const core::mem::offset_of!(UcodeCallResult, r8),
+            offset_r9 = const core::mem::offset_of!(UcodeCallResult, r9),
+            offset_r10 = const core::mem::offset_of!(UcodeCallResult, r10),
+            offset_r11 = const core::mem::offset_of!(UcodeCallResult, r11),
+            offset_r12 = const core::mem::offset_of!(UcodeCallResult, r12),
+            offset_r13 = const core::mem::offset_of!(UcodeCallResult, r13),
+            offset_r14 = const core::mem::offset_of!(UcodeCallResult, r14),
+            offset_r15 = const core::mem::offset_of!(UcodeCallResult, r15),
+            offset_rflags = const core::mem::offset_of!(UcodeCallResult, rflags),
+            )
+        };
+    }
+
+    if cfg!(feature = "emulation") {
+        let emulated = crate::emulation::invoke(address);
+        lmfence();
+        unsafe {
+            invoke_and_capture!(
+                emulated.rax,
+                emulated.rbx,
+                emulated.rcx,
+                emulated.rdx,
+                "mov esi, 6",
+                "mov edi, 7",
+                "mov r8d, 8",
+                "mov r9d, 9",
+                "mov r10d, 10",
+                "mov r11d, 11",
+                "mov r12d, 12",
+                "mov r13d, 13",
+                "mov r14d, 14",
+                "mov r15d, 15"
+            );
+        }
+        lmfence();
+        return result;
+    }
+
+    lmfence();
+    unsafe {
+        invoke_and_capture!(
+            address.address(),
+            0usize,
+            0xd8usize,
+            0usize,
+            ".byte 0x0f, 0x0f"
+        );
+    }
+    lmfence();
+
+    result
+}
+
 /// Writes to a Model Specific Register (MSR)
 ///
 /// # Arguments
@@ -293,6 +387,35 @@ fn wrmsr(msr: u32, value: u64) {
     }
 }
 
+/// Reads from a Model Specific Register (MSR)
+///
+/// # Arguments
+///
+/// * `msr` - The MSR number to read from
+///
+/// # Returns
+///
+/// The value of the MSR, zero in emulation mode
+#[inline(always)]
+fn rdmsr(msr: u32) -> u64 {
+    if cfg!(feature = "emulation") {
+        return 0;
+    }
+
+    let low: u32;
+    let high: u32;
+    unsafe {
+        asm!(
+        "rdmsr",
+        in("ecx") msr,
+        out("eax") low,
+        out("edx") high,
+        options(nostack, nomem, preserves_flags)
+        );
+    }
+    ((high as u64) << 32) | low as u64
+}
+
 /// Result of a CPUID instruction
 #[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct CpuidResult {
@@ -338,20 +461,11 @@ pub fn activate_udebug_insts() {
 
 /// Reads from the CRBUS at the specified address
 pub fn crbus_read(address: usize) -> usize {
-    if cfg!(feature = "emulation") {
-        // trace!("read CRBUS[{:08x}]", address);
-        return 0;
-    }
-
     core::hint::black_box(udebug_read(0, address))
 }
 
 /// Writes to the CRBUS at the specified address
 pub fn crbus_write(address: usize, value: usize) -> usize {
-    if cfg!(feature = "emulation") {
-        // trace!("CRBUS[{:08x}] = {:08x}", address, value);
-    }
-
     core::hint::black_box(udebug_write)(0, address, value);
     core::hint::black_box(udebug_read)(0, address)
 }
@@ -376,8 +490,23 @@ pub fn stgbuf_read(address: StagingBufferAddress) -> usize {
     stgbuf_read_raw(address.to_address())
 }
 
-/// Writes to the LDAT array
-fn ldat_array_write(
+/// LDAT port of the MS arrays, see [`LdatArray`]
+pub const MS_ARRAY_PDAT: usize = 0x6a0;
+
+/// Writes to an LDAT array
+///
+/// Prefer the typed [`ms_array_write`], this is an escape hatch for arrays and banks without
+/// an address type.
+///
+/// # Arguments
+///
+/// * `pdat_reg` - The CRBUS base register of the LDAT port, e.g. [`MS_ARRAY_PDAT`]
+/// * `array_sel` - The array selector, see [`LdatArray::selector`]
+/// * `bank_sel` - The bank of the array
+/// * `dword_idx` - The dword of the entry
+/// * `fast_addr` - The address of the entry
+/// * `val` - The value to write
+pub fn ldat_array_write_raw(
     pdat_reg: usize,
     array_sel: usize,
     bank_sel: usize,
@@ -449,8 +578,96 @@ pub fn call_custom_ucode_function(
     result
 }
 
-/// Reads from the LDAT array
-fn ldat_array_read(
+/// Register file after a microcode function call, see [`call_custom_ucode_function_full`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct UcodeCallResult {
+    /// RAX register value
+    pub rax: usize,
+    /// RBX register value
+    pub rbx: usize,
+    /// RCX register value
+    pub rcx: usize,
+    /// RDX register value
+    pub rdx: usize,
+    /// RSI register value
+    pub rsi: usize,
+    /// RDI register value
+    pub rdi: usize,
+    /// R8 register value
+    pub r8: usize,
+    /// R9 register value
+    pub r9: usize,
+    /// R10 register value
+    pub r10: usize,
+    /// R11 register value
+    pub r11: usize,
+    /// R12 register value
+    pub r12: usize,
+    /// R13 register value
+    pub r13: usize,
+    /// R14 register value
+    pub r14: usize,
+    /// R15 register value
+    pub r15: usize,
+    /// RFLAGS register value
+    pub rflags: usize,
+}
+
+impl From<&UcodeCallResult> for FunctionResult {
+    fn from(result: &UcodeCallResult) -> Self {
+        FunctionResult {
+            rax: result.rax,
+            rbx: result.rbx,
+            rcx: result.rcx,
+            rdx: result.rdx,
+        }
+    }
+}
+
+/// Calls a custom microcode function and returns the whole register file
+///
+/// Unlike [`call_custom_ucode_function`] this also captures the registers `rsi`, `rdi`,
+/// `r8`-`r15` and the flags, see [`udebug_invoke_full`].
+///
+/// # Arguments
+///
+/// * `func_address` - Address of the function to call
+/// * `args` - Array of 3 arguments to pass to the function
+///
+/// # Returns
+///
+/// The register file after the call in the [`UcodeCallResult`] struct
+pub fn call_custom_ucode_function_full(
+    func_address: UCInstructionAddress,
+    args: [usize; 3],
+) -> UcodeCallResult {
+    stgbuf_write(RegTmp0, args[0]);
+    stgbuf_write(RegTmp1, args[1]);
+    stgbuf_write(RegTmp2, args[2]);
+
+    let result = core::hint::black_box(udebug_invoke_full)(func_address);
+
+    stgbuf_write(RegTmp0, 0);
+    stgbuf_write(RegTmp1, 0);
+    stgbuf_write(RegTmp2, 0);
+
+    result
+}
+
+/// Reads from an LDAT array
+///
+/// Prefer the typed [`ms_array_read`], this is an escape hatch for arrays and banks without
+/// an address type.
+///
+/// # Arguments
+///
+/// * `ucode_read_function` - Function to use for reading, see [`apply_ldat_read_func`]
+/// * `pdat_reg` - The CRBUS base register of the LDAT port, e.g. [`MS_ARRAY_PDAT`]
+/// * `array_sel` - The array selector, see [`LdatArray::selector`]
+/// * `bank_sel` - The bank of the array
+/// * `dword_idx` - The dword of the entry
+/// * `fast_addr` - The address of the entry
+pub fn ldat_array_read_raw(
     ucode_read_function: UCInstructionAddress,
     pdat_reg: usize,
     array_sel: usize,
@@ -482,39 +699,48 @@ fn ldat_array_read(
     call_custom_ucode_function(ucode_read_function, [pdat_reg, array_bank_sel, array_addr]).rax
 }
 
-/// Writes to the MS array
-fn ms_array_write<A: MSRAMAddress>(
-    array_sel: usize,
-    bank_sel: usize,
-    dword_idx: usize,
-    fast_addr: A,
-    val: usize,
-) {
-    ldat_array_write(
-        0x6a0,
-        array_sel,
-        bank_sel,
-        dword_idx,
-        fast_addr.address(),
-        val,
-    )
+/// Writes to the MS array the address belongs to, see [`LdatWriteAddress`]
+pub fn ms_array_write<A: LdatWriteAddress>(addr: A, val: usize) {
+    ms_array_write_raw(A::ARRAY, addr.address(), val)
 }
 
-/// Reads from the MS array
-fn ms_array_read<A: MSRAMAddress>(
+/// Reads from the MS array the address belongs to, see [`LdatReadAddress`]
+///
+/// # Arguments
+///
+/// * `ucode_read_function` - Function to use for reading, see [`apply_ldat_read_func`]
+/// * `addr` - Address to read from
+pub fn ms_array_read<A: LdatReadAddress>(
     ucode_read_function: UCInstructionAddress,
-    array_sel: usize,
-    bank_sel: usize,
-    dword_idx: usize,
-    fast_addr: A,
+    addr: A,
+) -> usize {
+    ms_array_read_raw(ucode_read_function, A::ARRAY, addr.address())
+}
+
+/// Writes to an MS array at a raw address, e.g. to dump arrays without an address type
+pub fn ms_array_write_raw(array: LdatArray, fast_addr: usize, val: usize) {
+    ldat_array_write_raw(MS_ARRAY_PDAT, array.selector(), 0, 0, fast_addr, val)
+}
+
+/// Reads from an MS array at a raw address, e.g. to dump arrays without an address type
+///
+/// # Arguments
+///
+/// * `ucode_read_function` - Function to use for reading, see [`apply_ldat_read_func`]
+/// * `array` - The array to read from
+/// * `fast_addr` - The address in the array, see [`LdatArray::size`]
+pub fn ms_array_read_raw(
+    ucode_read_function: UCInstructionAddress,
+    array: LdatArray,
+    fast_addr: usize,
 ) -> usize {
-    ldat_array_read(
+    ldat_array_read_raw(
         ucode_read_function,
-        0x6a0,
-        array_sel,
-        bank_sel,
-        dword_idx,
-        fast_addr.address(),
+        MS_ARRAY_PDAT,
+        array.selector(),
+        0,
+        0,
+        fast_addr,
     )
 }
 
@@ -524,7 +750,7 @@ pub fn ms_patch_instruction_write<A: Into<MSRAMInstructionPartWriteAddress>>(add
     if cfg!(feature = "emulation") {
         trace!("Writing to MSRAM patch at {} = {:x}", addr, val);
     }
-    ms_array_write(4, 0, 0, addr, val)
+    ms_array_write(addr, val)
 }
 
 /// Reads an instruction from the MS patch array
@@ -536,7 +762,92 @@ pub fn ms_patch_instruction_read<A: Into<MSRAMInstructionPartReadAddress>>(
     if cfg!(feature = "emulation") {
         trace!("Reading from MSRAM at {:x}", addr.address());
     }
-    ms_array_read(ucode_read_function, 4, 0, 0, addr)
+    ms_array_read(ucode_read_function, addr)
+}
+
+/// Default number of retries of [`ms_patch_instruction_write_verified`]
+pub const DEFAULT_WRITE_VERIFY_RETRIES: usize = 3;
+
+/// Bits of an instruction that are stored in the MS patch array
+const PATCH_INSTRUCTION_MASK: usize = 0xffff_ffff_ffff;
+
+/// Error of a verified write to the MS patch array
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteVerifyError {
+    /// The address that was written
+    pub address: UCInstructionAddress,
+    /// The value that was written
+    pub expected: usize,
+    /// The value read back after the last attempt
+    pub found: usize,
+    /// The number of write attempts
+    pub attempts: usize,
+}
+
+impl Display for WriteVerifyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "write to {} did not take effect after {} attempts: wrote {:012x}, read back {:012x}",
+            self.address, self.attempts, self.expected, self.found
+        )
+    }
+}
+
+/// Writes an instruction to the MS patch array and reads it back to verify the write
+///
+/// # Arguments
+///
+/// * `ucode_read_function` - Function to use for reading, see [`apply_ldat_read_func`]
+/// * `addr` - Address to write to
+/// * `val` - The instruction to write
+/// * `retries` - How often the write is repeated if the value read back differs
+///
+/// # Returns
+///
+/// - `Ok(())` if the value read back matches the written value
+/// - `Err(WriteVerifyError)` with the last value read back if all attempts failed
+pub fn ms_patch_instruction_write_verified<A: Into<UCInstructionAddress>>(
+    ucode_read_function: UCInstructionAddress,
+    addr: A,
+    val: usize,
+    retries: usize,
+) -> Result<(), WriteVerifyError> {
+    let addr = addr.into();
+    write_verified(
+        addr,
+        val,
+        retries,
+        || ms_patch_instruction_write(addr, val),
+        || ms_patch_instruction_read(ucode_read_function, addr),
+    )
+}
+
+/// Repeats a write until the value read back matches, see [`ms_patch_instruction_write_verified`]
+fn write_verified<W: FnMut(), R: FnMut() -> usize>(
+    address: UCInstructionAddress,
+    val: usize,
+    retries: usize,
+    mut write: W,
+    mut read: R,
+) -> Result<(), WriteVerifyError> {
+    let expected = val & PATCH_INSTRUCTION_MASK;
+    let mut found = 0;
+
+    for _ in 0..=retries {
+        write();
+        found = read() & PATCH_INSTRUCTION_MASK;
+        if found == expected {
+            return Ok(());
+        }
+    }
+
+    Err(WriteVerifyError {
+        address,
+        expected,
+        found,
+        attempts: retries + 1,
+    })
 }
 
 /// Writes to a hook in the MS array
@@ -545,7 +856,7 @@ pub fn ms_hook_write<A: Into<MSRAMHookIndex>>(addr: A, val: usize) {
     if cfg!(feature = "emulation") {
         trace!("Writing to MSRAM hook at {:x} = {:x}", addr.address(), val);
     }
-    ms_array_write(3, 0, 0, addr, val)
+    ms_array_write(addr, val)
 }
 
 /// Reads from a hook in the MS array
@@ -557,7 +868,7 @@ pub fn ms_hook_read<A: Into<MSRAMHookIndex>>(
     if cfg!(feature = "emulation") {
         trace!("Reading from MSRAM hook at {:x}", addr.address());
     }
-    ms_array_read(ucode_read_function, 3, 0, 0, addr)
+    ms_array_read(ucode_read_function, addr)
 }
 
 /// Writes to a sequence word in the MS array
@@ -566,7 +877,7 @@ pub fn ms_seqw_write<A: Into<MSRAMSequenceWordAddress>>(addr: A, val: usize) {
     if cfg!(feature = "emulation") {
         trace!("Writing to MSRAM SEQW at {:x} = {:x}", addr.address(), val);
     }
-    ms_array_write(2, 0, 0, addr, val)
+    ms_array_write(addr, val)
 }
 
 /// Reads from a sequence word in the MS array
@@ -578,7 +889,7 @@ pub fn ms_seqw_read<A: Into<MSRAMSequenceWordAddress>>(
     if cfg!(feature = "emulation") {
         trace!("Reading from MSRAM SEQW at {:x}", addr.address());
     }
-    ms_array_read(ucode_read_function, 2, 0, 0, addr)
+    ms_array_read(ucode_read_function, addr)
 }
 
 /// Detects the GLM processor version
@@ -586,27 +897,159 @@ pub fn detect_glm_version() -> u32 {
     CpuidResult::query(0x1, 0).eax
 }
 
+/// Reads the revision of the currently loaded microcode update from IA32_BIOS_SIGN_ID
+pub fn read_ucode_revision() -> u32 {
+    // the revision is only updated in the MSR by executing CPUID after clearing it
+    wrmsr(0x8b, 0);
+    let _ = CpuidResult::query(0x1, 0);
+    (rdmsr(0x8b) >> 32) as u32
+}
+
+/// Identification of the processor and its loaded microcode
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ProcessorInfo {
+    /// CPUID signature (CPUID 1:EAX), e.g. [`crate::GLM_OLD`]
+    pub signature: u32,
+    /// Stepping of the processor
+    pub stepping: u8,
+    /// Revision of the currently loaded microcode update
+    pub ucode_revision: u32,
+}
+
+impl ProcessorInfo {
+    /// Detects the current processor and the revision of its loaded microcode
+    pub fn detect() -> Self {
+        let signature = detect_glm_version();
+        ProcessorInfo {
+            signature,
+            stepping: (signature & 0xf) as u8,
+            ucode_revision: read_ucode_revision(),
+        }
+    }
+}
+
+impl Display for ProcessorInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:08x} stepping {} with microcode revision {:08x}",
+            self.signature, self.stepping, self.ucode_revision
+        )
+    }
+}
+
 /// Error type for patch operations
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PatchError {
-    /// The patch is too large for the target location
-    PatchToLarge,
+    /// The patch does not fit into the MSRAM patch space
+    OutOfRange {
+        /// The address the patch should be applied at
+        addr: UCInstructionAddress,
+        /// The length of the patch in triads
+        len: usize,
+    },
+    /// The patch address is not the start of a triad
+    Misaligned,
+    /// The patch overlaps a previously applied patch
+    Overlap {
+        /// The address of the previously applied patch
+        existing: UCInstructionAddress,
+    },
+    /// A jump of the patch could not be moved to the new address, see [`crate::apply_patch_at`]
+    Relocation {
+        /// The address of the triad in the patch as compiled
+        address: UCInstructionAddress,
+    },
+    /// The patch file was made for another processor, see [`apply_patch_file`]
+    ProcessorMismatch {
+        /// The CPUID signature the patch was made for
+        target: u32,
+        /// The CPUID signature of the current processor
+        processor: u32,
+    },
+}
+
+impl Display for PatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::OutOfRange { addr, len } => write!(
+                f,
+                "patch of {} triads at {} exceeds the patch space {}..={}",
+                len,
+                addr,
+                UCInstructionAddress::MSRAM_START,
+                UCInstructionAddress::MAX
+            ),
+            PatchError::Misaligned => write!(f, "patch address is not triad aligned"),
+            PatchError::Overlap { existing } => {
+                write!(f, "patch overlaps the patch applied at {}", existing)
+            }
+            PatchError::Relocation { address } => {
+                write!(f, "failed to relocate the jump of the triad at {}", address)
+            }
+            PatchError::ProcessorMismatch { target, processor } => write!(
+                f,
+                "patch was made for processor {:08x}, not for {:08x}",
+                target, processor
+            ),
+        }
+    }
+}
+
+/// Number of triads in the MSRAM patch space
+pub(crate) const PATCH_SPACE_TRIADS: usize =
+    (UCInstructionAddress::MAX.to_const() + 1 - UCInstructionAddress::MSRAM_START.to_const()) / 4;
+
+/// Checks that a patch is triad aligned and fits into the MSRAM patch space
+///
+/// # Arguments
+///
+/// * `addr` - The address the patch should be applied at
+/// * `len` - The length of the patch in triads
+///
+/// # Returns
+///
+/// - `Ok(usize)` with the index of the first triad in the patch space
+/// - `Err(PatchError::OutOfRange)` if the patch exceeds the patch space
+/// - `Err(PatchError::Misaligned)` if the address is not the start of a triad
+pub(crate) fn validate_patch_range(
+    addr: UCInstructionAddress,
+    len: usize,
+) -> Result<usize, PatchError> {
+    if addr < UCInstructionAddress::MSRAM_START
+        || (UCInstructionAddress::MAX - addr).to_const() + 1 < len * 4
+    {
+        return Err(PatchError::OutOfRange { addr, len });
+    }
+    if addr.triad_offset() != 0 {
+        return Err(PatchError::Misaligned);
+    }
+
+    Ok((addr - UCInstructionAddress::MSRAM_START).to_const() / 4)
 }
 
 /// Applies a microcode patch at the specified address
 ///
+/// The triads of the patch stay claimed until they are overwritten. Patches that are removed
+/// again must be applied via a [`crate::MsramAllocator`], which releases them on
+/// [`crate::MsramAllocator::free`].
+///
 /// # Arguments
 ///
-/// * `addr` - The address to apply the patch at
+/// * `addr` - The address to apply the patch at, must be the start of a triad
 /// * `ucode_patch` - The patch data to apply
+/// * `overwrite` - Whether the patch may overwrite previously applied patches
 ///
 /// # Returns
 ///
 /// - `Ok(())` if the patch was applied successfully
-/// - `Err(PatchError)` if the patch could not be applied
+/// - `Err(PatchError::OutOfRange)` if the patch does not fit into the MSRAM patch space
+/// - `Err(PatchError::Misaligned)` if the address is not the start of a triad
+/// - `Err(PatchError::Overlap)` if the patch overlaps a previously applied patch and `overwrite` is not set
 pub fn patch_ucode<A: Into<UCInstructionAddress>>(
     addr: A,
     ucode_patch: &UcodePatchBlob,
+    overwrite: bool,
 ) -> Result<(), PatchError> {
     // format: uop0, uop1, uop2, seqword
     // uop3 is fixed to a nop and cannot be overridden
@@ -617,14 +1060,12 @@ pub fn patch_ucode<A: Into<UCInstructionAddress>>(
         trace!("Writing ucode patch to {}", addr);
     }
 
-    if (UCInstructionAddress::MAX - addr) < ucode_patch.len() * 4 {
-        return Err(PatchError::PatchToLarge);
-    }
+    crate::allocator::claim_patch_range(addr, ucode_patch.len(), overwrite)?;
 
     let seqw: MSRAMSequenceWordAddress = addr.into();
 
     for (i, row) in ucode_patch.iter().enumerate() {
-        for (offset, entry) in row.iter().enumerate() {
+        for (offset, entry) in row[..3].iter().enumerate() {
             let addr = addr.patch_offset(i * 3 + offset);
             ms_patch_instruction_write(addr, *entry);
         }
@@ -651,7 +1092,7 @@ pub fn read_patch(
     let seqw: MSRAMSequenceWordAddress = addr.into();
 
     for (i, row) in ucode_patch.iter_mut().enumerate() {
-        for (offset, entry) in row.iter_mut().enumerate() {
+        for (offset, entry) in row[..3].iter_mut().enumerate() {
             let read_val =
                 ms_patch_instruction_read(ucode_read_function, addr.patch_offset(i * 3 + offset));
             *entry = read_val;
@@ -698,6 +1139,147 @@ pub fn calculate_hook_value(
     Ok(patch_value)
 }
 
+/// ROM address that is redirected to U7dfc on [`crate::GLM_OLD`], see
+/// [`crate::CustomProcessingUnit::apply_existing_patches`]
+const GLM_OLD_PATCHED_HOOK: UCInstructionAddress = UCInstructionAddress::from_const(0x4588);
+
+/// Reasons why an address can not be hooked, see [`validate_hook_target`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HookTargetError {
+    /// The address is not a ROM address
+    OutOfBounds(UCInstructionAddress),
+    /// The address is odd, hooks can only be placed on even addresses
+    Misaligned(UCInstructionAddress),
+    /// The address is not present in the ROM dump
+    NotInDump(UCInstructionAddress),
+    /// The address is already redirected to the existing patch at U7dfc
+    Patched(UCInstructionAddress),
+    /// The address is on the blacklist
+    Blacklisted(UCInstructionAddress),
+}
+
+impl Display for HookTargetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            HookTargetError::OutOfBounds(address) => {
+                write!(f, "hook address {} is not a ROM address", address)
+            }
+            HookTargetError::Misaligned(address) => {
+                write!(
+                    f,
+                    "hook address {} is not aligned, it must be even",
+                    address
+                )
+            }
+            HookTargetError::NotInDump(address) => {
+                write!(f, "hook address {} is not present in the ROM dump", address)
+            }
+            HookTargetError::Patched(address) => write!(
+                f,
+                "hook address {} is already redirected to the existing patch at U7dfc",
+                address
+            ),
+            HookTargetError::Blacklisted(address) => {
+                write!(f, "hook address {} is blacklisted", address)
+            }
+        }
+    }
+}
+
+/// Validates a hook target address against a ROM dump
+///
+/// # Arguments
+///
+/// * `rom` - ROM dump of the current processor
+/// * `addr` - Address to hook
+///
+/// # Returns
+///
+/// - `Ok(())` if the address is an even ROM address present in the dump, which is not
+///   already hooked by the existing patch of [`crate::GLM_OLD`]
+/// - `Err(HookTargetError)` with the reason otherwise
+pub fn validate_hook_target(
+    rom: &RomDump,
+    addr: UCInstructionAddress,
+) -> Result<(), HookTargetError> {
+    validate_hook_target_excluding(rom, addr, |_| false)
+}
+
+/// Validates a hook target address against a ROM dump and a blacklist
+///
+/// # Arguments
+///
+/// * `rom` - ROM dump of the current processor
+/// * `addr` - Address to hook
+/// * `blacklisted` - Returns whether an address is blacklisted
+///
+/// # Returns
+///
+/// - `Ok(())` if the address passes [`validate_hook_target`] and is not blacklisted
+/// - `Err(HookTargetError)` with the reason otherwise
+pub fn validate_hook_target_excluding<F: Fn(UCInstructionAddress) -> bool>(
+    rom: &RomDump,
+    addr: UCInstructionAddress,
+    blacklisted: F,
+) -> Result<(), HookTargetError> {
+    if addr >= UCInstructionAddress::MSRAM_START {
+        return Err(HookTargetError::OutOfBounds(addr));
+    }
+    if !addr.hookable() {
+        return Err(HookTargetError::Misaligned(addr));
+    }
+    if rom.triad(addr).is_none() {
+        return Err(HookTargetError::NotInDump(addr));
+    }
+    if rom.model() == crate::GLM_OLD && addr == GLM_OLD_PATCHED_HOOK {
+        return Err(HookTargetError::Patched(addr));
+    }
+    if blacklisted(addr) {
+        return Err(HookTargetError::Blacklisted(addr));
+    }
+    Ok(())
+}
+
+/// Validates a hook source address against a ROM dump
+///
+/// # Arguments
+///
+/// * `rom` - ROM dump of the current processor
+/// * `to_hook_ucode_addr` - Address to hook
+///
+/// # Returns
+///
+/// - `Ok(())` if the address passes [`validate_hook_target`]
+/// - `Err(Error::HookFailed)` with the reason otherwise
+pub fn validate_hook_address(
+    rom: &RomDump,
+    to_hook_ucode_addr: UCInstructionAddress,
+) -> crate::Result<()> {
+    validate_hook_target(rom, to_hook_ucode_addr).map_err(|err| Error::HookFailed(err.to_string()))
+}
+
+/// Checks that a hook index is usable on the processor
+///
+/// # Arguments
+///
+/// * `hook_idx` - Index of the hook
+/// * `limit` - Number of usable hooks, see [`crate::hook_count`]
+///
+/// # Returns
+///
+/// - `Ok(())` if the index is below the limit
+/// - `Err(Error::HookFailed)` describing the limit otherwise
+pub fn validate_hook_index(hook_idx: MSRAMHookIndex, limit: usize) -> crate::Result<()> {
+    if hook_idx.index() >= limit {
+        return Err(Error::HookFailed(format!(
+            "hook index {} exceeds the {} usable hooks of the processor",
+            hook_idx.index(),
+            limit
+        )));
+    }
+    Ok(())
+}
+
 /// Sets up a hook in the microcode
 ///
 /// # Arguments
@@ -711,6 +1293,7 @@ pub fn calculate_hook_value(
 /// # Returns
 ///
 /// - `Ok(())` if the hook was set up successfully
+/// - `Err(Error::HookFailed)` if the index exceeds the hooks of the processor, see [`crate::hook_count`]
 /// - `Err(Error)` if the hook setup fails
 pub fn hook<A: Into<UCInstructionAddress>, B: Into<UCInstructionAddress>>(
     apply_hook_func: UCInstructionAddress,
@@ -719,12 +1302,39 @@ pub fn hook<A: Into<UCInstructionAddress>, B: Into<UCInstructionAddress>>(
     redirect_to_addr: B,
     enabled: bool,
 ) -> crate::Result<()> {
+    // unsupported processors, e.g. the host of the emulation, are only limited by the hook table
+    let limit = match crate::hook_count(detect_glm_version()) {
+        0 => MSRAMHookIndex::COUNT,
+        count => count,
+    };
+    validate_hook_index(hook_idx, limit)?;
+
     let patch_value =
         calculate_hook_value(to_hook_ucode_addr.into(), redirect_to_addr.into(), enabled)?;
 
-    let result = call_custom_ucode_function(apply_hook_func, [patch_value, hook_idx.address(), 0]);
+    write_hook_value(apply_hook_func, hook_idx, patch_value)
+}
+
+/// Writes a raw value to an entry of the hook table
+///
+/// # Arguments
+///
+/// * `apply_hook_func` - Function to use for applying the hook
+/// * `hook_idx` - Index of the hook entry
+/// * `value` - The value to write, see [`calculate_hook_value`], zero disables the entry
+///
+/// # Returns
+///
+/// - `Ok(())` if the entry was written successfully
+/// - `Err(Error)` if writing the entry fails
+pub fn write_hook_value(
+    apply_hook_func: UCInstructionAddress,
+    hook_idx: MSRAMHookIndex,
+    value: usize,
+) -> crate::Result<()> {
+    let result = call_custom_ucode_function(apply_hook_func, [value, hook_idx.address(), 0]);
 
-    if result.rax != 0x0000133700001337 && cfg!(not(feature = "emulation")) {
+    if result.rax != 0x0000133700001337 {
         return Err(Error::HookFailed(format!(
             "invoke({}) = {:016x}, {:016x}, {:016x}, {:016x}",
             apply_hook_func, result.rax, result.rbx, result.rcx, result.rdx
@@ -734,22 +1344,52 @@ pub fn hook<A: Into<UCInstructionAddress>, B: Into<UCInstructionAddress>>(
     Ok(())
 }
 
-/// Applies a patch to the microcode
+/// Applies a patch to the microcode, failing if it overlaps a previously applied patch
 pub fn apply_patch(patch: &Patch) -> Result<(), PatchError> {
-    patch_ucode(patch.addr, patch.ucode_patch)
+    patch_ucode(patch.addr, patch.ucode_patch, false)
+}
+
+/// Applies a patch to the microcode, replacing any previously applied patch it overlaps
+pub fn apply_patch_overwrite(patch: &Patch) -> Result<(), PatchError> {
+    patch_ucode(patch.addr, patch.ucode_patch, true)
+}
+
+/// Applies a patch loaded from a patch file, failing if it overlaps a previously applied patch
+///
+/// # Arguments
+///
+/// * `file` - The loaded patch file, see [`PatchFile::load`]
+///
+/// # Returns
+///
+/// - `Ok(())` if the patch was applied successfully
+/// - `Err(PatchError::ProcessorMismatch)` if the patch was made for another processor
+/// - `Err(PatchError)` if the patch could not be applied, see [`patch_ucode`]
+pub fn apply_patch_file(file: &PatchFile) -> Result<(), PatchError> {
+    if file.cpuid != 0 {
+        let processor = detect_glm_version();
+        if file.cpuid != processor {
+            return Err(PatchError::ProcessorMismatch {
+                target: file.cpuid,
+                processor,
+            });
+        }
+    }
+
+    apply_patch(&file.as_patch())
 }
 
 /// Returns the address of the hook patch function, that is uploaded to microcode RAM
 pub fn apply_hook_patch_func() -> UCInstructionAddress {
     let patch = crate::patches::func_hook::PATCH;
-    apply_patch(&patch).unwrap();
+    apply_patch_overwrite(&patch).unwrap();
     patch.addr
 }
 
 /// Returns the address of the LDAT read function, that is uploaded to microcode RAM
 pub fn apply_ldat_read_func() -> UCInstructionAddress {
     let patch = crate::patches::func_ldat_read::PATCH;
-    apply_patch(&patch).unwrap();
+    apply_patch_overwrite(&patch).unwrap();
     patch.addr
 }
 
@@ -794,21 +1434,77 @@ pub fn hooks_enabled() -> bool {
 }
 
 /// RAII guard for managing hook state
+///
+/// Guards either change the global hook state or the enable bit of individual hook entries.
+/// On drop exactly the changed state is restored, so guards can be nested.
 pub struct HookGuard {
-    previous_value: usize,
+    /// Previous global hook state, if changed by this guard
+    previous_value: Option<usize>,
+    /// Previous values of the hook entries changed by this guard
+    previous_entries: Vec<(MSRAMHookIndex, usize)>,
 }
 
 impl HookGuard {
     /// Creates a new guard that disables all hooks
     pub fn disable_all() -> Self {
         let previous_value = disable_all_hooks();
-        HookGuard { previous_value }
+        HookGuard {
+            previous_value: Some(previous_value),
+            previous_entries: Vec::new(),
+        }
     }
 
     /// Creates a new guard that enables all hooks
     pub fn enable_all() -> Self {
         let previous_value = enable_hooks();
-        HookGuard { previous_value }
+        HookGuard {
+            previous_value: Some(previous_value),
+            previous_entries: Vec::new(),
+        }
+    }
+
+    /// Creates a new guard that disables the hook entries at the given indices
+    ///
+    /// The global hook state is not changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `indices` - Indices of the hook entries to disable
+    pub fn disable(indices: &[MSRAMHookIndex]) -> Self {
+        Self::set_entries_enabled(indices, false)
+    }
+
+    /// Creates a new guard that enables the hook entries at the given indices
+    ///
+    /// The global hook state is not changed. Entries without an installed hook stay unset.
+    ///
+    /// # Arguments
+    ///
+    /// * `indices` - Indices of the hook entries to enable
+    pub fn enable(indices: &[MSRAMHookIndex]) -> Self {
+        Self::set_entries_enabled(indices, true)
+    }
+
+    fn set_entries_enabled(indices: &[MSRAMHookIndex], enabled: bool) -> Self {
+        let read_function = apply_ldat_read_func();
+        let previous_entries = indices
+            .iter()
+            .map(|index| {
+                let value = ms_hook_read(read_function, *index);
+                let new_value = match (enabled, value) {
+                    (_, 0) => 0,
+                    (true, value) => value | 1,
+                    (false, value) => value & !1,
+                };
+                ms_hook_write(*index, new_value);
+                (*index, value)
+            })
+            .collect();
+
+        HookGuard {
+            previous_value: None,
+            previous_entries,
+        }
     }
 
     /// Explicitly restores the previous hook state
@@ -819,7 +1515,13 @@ impl HookGuard {
 
 impl Drop for HookGuard {
     fn drop(&mut self) {
-        restore_hooks(self.previous_value);
+        // reverse order, so an index listed twice ends up with its first snapshot
+        for (index, value) in self.previous_entries.iter().rev() {
+            ms_hook_write(*index, *value);
+        }
+        if let Some(previous_value) = self.previous_value {
+            restore_hooks(previous_value);
+        }
     }
 }
 
@@ -842,3 +1544,171 @@ pub fn unwrap_ucode_clock(value: u64) -> u64 {
 pub fn read_unwrap_ucode_clock() -> u64 {
     unwrap_ucode_clock(read_ucode_clock())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::helpers::write_verified;
+    use crate::{
+        validate_hook_address, validate_hook_target, validate_hook_target_excluding, Error,
+        HookTargetError, PatchError, WriteVerifyError,
+    };
+    use core::cell::Cell;
+    use data_types::addresses::UCInstructionAddress;
+    use ucode_dump::dump::{ROM_cpu_000506C9, ROM_cpu_000506CA};
+
+    #[test]
+    fn test_validate_hook_address() {
+        let rom = &ROM_cpu_000506CA;
+
+        assert!(validate_hook_address(rom, ucode_dump::dump::cpu_000506CA::RDRAND_XLAT).is_ok());
+        assert!(validate_hook_address(rom, UCInstructionAddress::from_const(0x42a)).is_ok());
+
+        for address in [0x429, 0x42b, 0x7c00, 0x7c02] {
+            assert!(matches!(
+                validate_hook_address(rom, UCInstructionAddress::from_const(address)),
+                Err(Error::HookFailed(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_validate_hook_target() {
+        let address = UCInstructionAddress::from_const;
+
+        assert_eq!(
+            validate_hook_target(&ROM_cpu_000506CA, address(0x428)),
+            Ok(())
+        );
+        assert_eq!(
+            validate_hook_target(&ROM_cpu_000506CA, address(0x429)),
+            Err(HookTargetError::Misaligned(address(0x429)))
+        );
+        assert_eq!(
+            validate_hook_target(&ROM_cpu_000506CA, address(0x7c00)),
+            Err(HookTargetError::OutOfBounds(address(0x7c00)))
+        );
+
+        // the existing patch only hooks this address on GLM_OLD
+        assert_eq!(
+            validate_hook_target(&ROM_cpu_000506C9, address(0x4588)),
+            Err(HookTargetError::Patched(address(0x4588)))
+        );
+        assert_eq!(
+            validate_hook_target(&ROM_cpu_000506CA, address(0x4588)),
+            Ok(())
+        );
+
+        assert_eq!(
+            validate_hook_target_excluding(&ROM_cpu_000506CA, address(0x428), |address| {
+                address == 0x428usize
+            }),
+            Err(HookTargetError::Blacklisted(address(0x428)))
+        );
+    }
+
+    #[test]
+    fn test_write_verified() {
+        let address = UCInstructionAddress::MSRAM_START;
+
+        // the third write takes effect
+        let writes = Cell::new(0);
+        assert_eq!(
+            write_verified(
+                address,
+                0x1234,
+                2,
+                || writes.set(writes.get() + 1),
+                || if writes.get() < 3 { 0 } else { 0x1234 },
+            ),
+            Ok(())
+        );
+        assert_eq!(writes.get(), 3);
+
+        let mut writes = 0;
+        assert_eq!(
+            write_verified(address, 0xffff_0000_1234, 1, || writes += 1, || 0xdead),
+            Err(WriteVerifyError {
+                address,
+                expected: 0xffff_0000_1234,
+                found: 0xdead,
+                attempts: 2,
+            })
+        );
+        assert_eq!(writes, 2);
+    }
+
+    #[test]
+    #[cfg(feature = "emulation")]
+    fn test_call_custom_ucode_function_full() {
+        let result =
+            crate::call_custom_ucode_function_full(UCInstructionAddress::MSRAM_START, [1, 2, 3]);
+
+        // the register numbers are captured by the same instructions as on hardware
+        assert_eq!(result.rsi, 6);
+        assert_eq!(result.rdi, 7);
+        assert_eq!(result.r8, 8);
+        assert_eq!(result.r9, 9);
+        assert_eq!(result.r10, 10);
+        assert_eq!(result.r11, 11);
+        assert_eq!(result.r12, 12);
+        assert_eq!(result.r13, 13);
+        assert_eq!(result.r14, 14);
+        assert_eq!(result.r15, 15);
+        assert_eq!(
+            crate::FunctionResult::from(&result),
+            crate::FunctionResult::default()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "emulation")]
+    fn test_ms_patch_instruction_write_verified() {
+        let _lock = crate::emulation::lock();
+        let read_function = crate::apply_ldat_read_func();
+        let address = UCInstructionAddress::from_const(0x7c05);
+
+        assert_eq!(
+            crate::ms_patch_instruction_write_verified(read_function, address, 0xa04337080235, 0),
+            Ok(())
+        );
+        assert_eq!(
+            crate::ms_patch_instruction_read(read_function, address),
+            0xa04337080235
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "emulation")]
+    fn test_apply_patch_file() {
+        use data_types::patch::PatchFile;
+
+        let _lock = crate::emulation::lock();
+        let entries = [[0x6e75406aa00d, 0, 0, 0x3000000]];
+        let address = UCInstructionAddress::from_const(0x7c40);
+        let file = PatchFile {
+            cpuid: 0,
+            addr: address,
+            ucode_patch: entries.to_vec(),
+            labels: Default::default(),
+        };
+        let data = file.to_bytes().unwrap();
+        let loaded = PatchFile::load(&data).unwrap();
+
+        let other = PatchFile {
+            cpuid: !crate::detect_glm_version(),
+            ..loaded.clone()
+        };
+        assert_eq!(
+            crate::apply_patch_file(&other),
+            Err(PatchError::ProcessorMismatch {
+                target: !crate::detect_glm_version(),
+                processor: crate::detect_glm_version(),
+            })
+        );
+
+        assert_eq!(crate::apply_patch_file(&loaded), Ok(()));
+        let mut read = [[0; 4]; 1];
+        crate::read_patch(crate::apply_ldat_read_func(), address, &mut read);
+        assert_eq!(read, entries);
+    }
+}
@@ -0,0 +1,246 @@
+//! Bookkeeping of installed microcode hooks
+//!
+//! The [`HookRegistry`] installs hooks via the hook patch function and records which ROM
+//! address is redirected where, so that single hooks can be removed and installing two hooks
+//! on the same index is detected.
+
+use crate::{validate_hook_index, validate_hook_target_excluding, write_hook_value, Error};
+#[cfg(feature = "nostd")]
+use alloc::collections::{BTreeMap, BTreeSet};
+#[cfg(feature = "nostd")]
+use alloc::string::ToString;
+use data_types::addresses::{MSRAMHookIndex, UCInstructionAddress};
+#[cfg(not(feature = "nostd"))]
+use std::collections::{BTreeMap, BTreeSet};
+use ucode_dump::RomDump;
+
+/// A hook installed via a [`HookRegistry`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstalledHook {
+    /// Index of the hook in the hook table
+    pub index: MSRAMHookIndex,
+    /// The hooked ROM address
+    pub rom_address: UCInstructionAddress,
+    /// The address execution is redirected to
+    pub patch_address: UCInstructionAddress,
+    /// Whether the hook is enabled
+    pub enabled: bool,
+}
+
+/// Installs hooks and keeps track of the installed hooks
+pub struct HookRegistry {
+    /// ROM dump of the current processor, used to validate hook addresses
+    rom: &'static RomDump<'static, 'static>,
+    /// The installed hooks by their index
+    hooks: BTreeMap<MSRAMHookIndex, InstalledHook>,
+    /// ROM addresses that must not be hooked
+    blacklist: BTreeSet<UCInstructionAddress>,
+    /// Number of usable hooks of the current processor
+    hook_count: usize,
+}
+
+impl HookRegistry {
+    /// Creates a new registry without any installed hooks
+    ///
+    /// # Arguments
+    ///
+    /// * `rom` - ROM dump of the current processor
+    /// * `hook_count` - Number of usable hooks of the current processor, see [`crate::hook_count`]
+    pub fn new(rom: &'static RomDump<'static, 'static>, hook_count: usize) -> Self {
+        HookRegistry {
+            rom,
+            hooks: BTreeMap::new(),
+            blacklist: BTreeSet::new(),
+            hook_count,
+        }
+    }
+
+    /// Replaces the addresses that must not be hooked, e.g. addresses known to hang the device
+    ///
+    /// # Arguments
+    ///
+    /// * `blacklist` - The ROM addresses to refuse in [`Self::install`]
+    pub fn set_blacklist<I: IntoIterator<Item = UCInstructionAddress>>(&mut self, blacklist: I) {
+        self.blacklist = blacklist.into_iter().collect();
+    }
+
+    /// Installs a hook and records it
+    ///
+    /// # Arguments
+    ///
+    /// * `apply_hook_func` - Function to use for applying the hook, see [`crate::apply_hook_patch_func`]
+    /// * `index` - Index of the hook to set up
+    /// * `rom_address` - Address to hook
+    /// * `patch_label` - Address to redirect to
+    /// * `enabled` - Whether the hook should be enabled
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` if the hook was set up successfully
+    /// - `Err(Error::HookIndexInUse)` if a hook is already installed at this index
+    /// - `Err(Error::HookFailed)` if the index exceeds the usable hooks, the hook address is
+    ///   invalid, see [`crate::validate_hook_target`], blacklisted or the hook setup fails
+    pub fn install<A: Into<UCInstructionAddress>, B: Into<UCInstructionAddress>>(
+        &mut self,
+        apply_hook_func: UCInstructionAddress,
+        index: MSRAMHookIndex,
+        rom_address: A,
+        patch_label: B,
+        enabled: bool,
+    ) -> crate::Result<()> {
+        let hook = InstalledHook {
+            index,
+            rom_address: rom_address.into(),
+            patch_address: patch_label.into(),
+            enabled,
+        };
+
+        validate_hook_index(index, self.hook_count)?;
+        if let Some(installed) = self.hooks.get(&index) {
+            return Err(Error::HookIndexInUse(index, installed.rom_address));
+        }
+        validate_hook_target_excluding(self.rom, hook.rom_address, |address| {
+            self.blacklist.contains(&address)
+        })
+        .map_err(|err| Error::HookFailed(err.to_string()))?;
+
+        crate::hook(
+            apply_hook_func,
+            index,
+            hook.rom_address,
+            hook.patch_address,
+            enabled,
+        )?;
+        self.hooks.insert(index, hook);
+
+        Ok(())
+    }
+
+    /// Removes a hook by zeroing its entry in the hook table
+    ///
+    /// # Arguments
+    ///
+    /// * `apply_hook_func` - Function to use for applying the hook, see [`crate::apply_hook_patch_func`]
+    /// * `index` - Index of the hook to remove
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Some(InstalledHook))` with the removed hook
+    /// - `Ok(None)` if no hook is installed at this index
+    /// - `Err(Error::HookFailed)` if zeroing the entry fails
+    pub fn remove(
+        &mut self,
+        apply_hook_func: UCInstructionAddress,
+        index: MSRAMHookIndex,
+    ) -> crate::Result<Option<InstalledHook>> {
+        if !self.hooks.contains_key(&index) {
+            return Ok(None);
+        }
+
+        write_hook_value(apply_hook_func, index, 0)?;
+        Ok(self.hooks.remove(&index))
+    }
+
+    /// Returns the installed hook at an index
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Index of the hook
+    pub fn get(&self, index: MSRAMHookIndex) -> Option<&InstalledHook> {
+        self.hooks.get(&index)
+    }
+
+    /// Returns all installed hooks ordered by their index
+    pub fn list(&self) -> impl Iterator<Item = &InstalledHook> {
+        self.hooks.values()
+    }
+
+    /// Returns the number of usable hook table entries without an installed hook
+    pub fn free_count(&self) -> usize {
+        self.hook_count.saturating_sub(self.hooks.len())
+    }
+
+    /// Forgets all hooks, after the hook table was zeroed
+    pub(crate) fn clear(&mut self) {
+        self.hooks.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Error, HookRegistry, InstalledHook};
+    use data_types::addresses::{MSRAMHookIndex, UCInstructionAddress};
+    use ucode_dump::dump::ROM_cpu_000506CA;
+
+    #[test]
+    fn test_hook_registry_rejects_before_installing() {
+        let mut registry = HookRegistry::new(&ROM_cpu_000506CA, crate::hook_count(crate::GLM_NEW));
+        let rdrand = ucode_dump::dump::cpu_000506CA::RDRAND_XLAT;
+        let installed = InstalledHook {
+            index: MSRAMHookIndex::ZERO,
+            rom_address: rdrand,
+            patch_address: UCInstructionAddress::MSRAM_START,
+            enabled: true,
+        };
+        registry.hooks.insert(installed.index, installed);
+
+        assert!(matches!(
+            registry.install(
+                UCInstructionAddress::MSRAM_START,
+                MSRAMHookIndex::ZERO,
+                0x430,
+                UCInstructionAddress::MSRAM_START,
+                true,
+            ),
+            Err(Error::HookIndexInUse(MSRAMHookIndex::ZERO, address)) if address == rdrand
+        ));
+        assert!(matches!(
+            registry.install(
+                UCInstructionAddress::MSRAM_START,
+                MSRAMHookIndex::ZERO + 1,
+                0x429,
+                UCInstructionAddress::MSRAM_START,
+                true,
+            ),
+            Err(Error::HookFailed(_))
+        ));
+
+        let mut glm_old = HookRegistry::new(&ROM_cpu_000506CA, crate::hook_count(crate::GLM_OLD));
+        assert!(matches!(
+            glm_old.install(
+                UCInstructionAddress::MSRAM_START,
+                MSRAMHookIndex::MAX,
+                rdrand,
+                UCInstructionAddress::MSRAM_START,
+                true,
+            ),
+            Err(Error::HookFailed(reason)) if reason.contains("31 usable hooks")
+        ));
+
+        registry.set_blacklist([UCInstructionAddress::from_const(0x42a)]);
+        assert!(matches!(
+            registry.install(
+                UCInstructionAddress::MSRAM_START,
+                MSRAMHookIndex::ZERO + 1,
+                0x42a,
+                UCInstructionAddress::MSRAM_START,
+                true,
+            ),
+            Err(Error::HookFailed(reason)) if reason.contains("blacklisted")
+        ));
+
+        assert_eq!(registry.list().count(), 1);
+        assert_eq!(registry.free_count(), crate::hook_count(crate::GLM_NEW) - 1);
+        assert_eq!(registry.list().next(), Some(&installed));
+        assert_eq!(registry.get(MSRAMHookIndex::ZERO), Some(&installed));
+        assert_eq!(registry.get(MSRAMHookIndex::ZERO + 1), None);
+        assert!(matches!(
+            registry.remove(UCInstructionAddress::MSRAM_START, MSRAMHookIndex::ZERO + 1),
+            Ok(None)
+        ));
+
+        registry.clear();
+        assert_eq!(registry.list().count(), 0);
+        assert_eq!(registry.free_count(), crate::hook_count(crate::GLM_NEW));
+    }
+}
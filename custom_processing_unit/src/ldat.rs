@@ -0,0 +1,161 @@
+//! Typed selection of the MS arrays accessed via the LDAT port
+//!
+//! The MS arrays are selected by a number written to the LDAT port, see [`LdatArray`]. The
+//! MSRAM address types implement [`LdatReadAddress`] and [`LdatWriteAddress`], so that the
+//! typed helpers, e.g. [`crate::ms_array_read`], always select the array matching the address
+//! and its encoding. Reading the sequence words with an instruction address does not compile.
+
+use core::fmt;
+use core::fmt::{Display, Formatter};
+use data_types::addresses::{
+    Address, MSRAMHookIndex, MSRAMInstructionPartReadAddress, MSRAMInstructionPartWriteAddress,
+    MSRAMSequenceWordAddress,
+};
+
+/// The MS arrays accessible via the LDAT port at [`crate::MS_ARRAY_PDAT`]
+///
+/// The selectors are the same on [`crate::GLM_OLD`] and [`crate::GLM_NEW`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LdatArray {
+    /// Instructions of the MSROM
+    RomInstructions,
+    /// Sequence words of the MSROM
+    RomSequenceWords,
+    /// Sequence words of the MSRAM patch space
+    SequenceWordRam,
+    /// Entries of the match & patch hook table
+    MatchPatch,
+    /// Instructions of the MSRAM patch space
+    InstructionRam,
+}
+
+impl LdatArray {
+    /// All MS arrays, ordered by their selector
+    pub const ALL: [LdatArray; 5] = [
+        LdatArray::RomInstructions,
+        LdatArray::RomSequenceWords,
+        LdatArray::SequenceWordRam,
+        LdatArray::MatchPatch,
+        LdatArray::InstructionRam,
+    ];
+
+    /// Returns the array selector written to the LDAT port
+    pub const fn selector(self) -> usize {
+        match self {
+            LdatArray::RomInstructions => 0,
+            LdatArray::RomSequenceWords => 1,
+            LdatArray::SequenceWordRam => 2,
+            LdatArray::MatchPatch => 3,
+            LdatArray::InstructionRam => 4,
+        }
+    }
+
+    /// Returns the array of an array selector, see [`Self::selector`]
+    pub const fn from_selector(selector: usize) -> Option<LdatArray> {
+        match selector {
+            0 => Some(LdatArray::RomInstructions),
+            1 => Some(LdatArray::RomSequenceWords),
+            2 => Some(LdatArray::SequenceWordRam),
+            3 => Some(LdatArray::MatchPatch),
+            4 => Some(LdatArray::InstructionRam),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of addresses of the array
+    ///
+    /// The entries of the match & patch hook table are two addresses apart, see
+    /// [`MSRAMHookIndex`].
+    pub const fn size(self) -> usize {
+        match self {
+            LdatArray::RomInstructions => 0x8000,
+            LdatArray::RomSequenceWords => 0x8000,
+            LdatArray::SequenceWordRam => 0x80,
+            LdatArray::MatchPatch => 0x40,
+            LdatArray::InstructionRam => 0x200,
+        }
+    }
+}
+
+impl Display for LdatArray {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LdatArray::RomInstructions => "ROM instructions",
+            LdatArray::RomSequenceWords => "ROM sequence words",
+            LdatArray::SequenceWordRam => "RAM sequence words",
+            LdatArray::MatchPatch => "match & patch",
+            LdatArray::InstructionRam => "RAM instructions",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// An address that is read via the LDAT port, see [`crate::ms_array_read`]
+pub trait LdatReadAddress: Address {
+    /// The array the address belongs to
+    const ARRAY: LdatArray;
+}
+
+/// An address that is written via the LDAT port, see [`crate::ms_array_write`]
+pub trait LdatWriteAddress: Address {
+    /// The array the address belongs to
+    const ARRAY: LdatArray;
+}
+
+impl LdatReadAddress for MSRAMInstructionPartReadAddress {
+    const ARRAY: LdatArray = LdatArray::InstructionRam;
+}
+
+impl LdatWriteAddress for MSRAMInstructionPartWriteAddress {
+    const ARRAY: LdatArray = LdatArray::InstructionRam;
+}
+
+impl LdatReadAddress for MSRAMSequenceWordAddress {
+    const ARRAY: LdatArray = LdatArray::SequenceWordRam;
+}
+
+impl LdatWriteAddress for MSRAMSequenceWordAddress {
+    const ARRAY: LdatArray = LdatArray::SequenceWordRam;
+}
+
+impl LdatReadAddress for MSRAMHookIndex {
+    const ARRAY: LdatArray = LdatArray::MatchPatch;
+}
+
+impl LdatWriteAddress for MSRAMHookIndex {
+    const ARRAY: LdatArray = LdatArray::MatchPatch;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::LdatArray;
+
+    #[test]
+    fn test_ldat_array_selector() {
+        for (selector, array) in LdatArray::ALL.iter().enumerate() {
+            assert_eq!(array.selector(), selector);
+            assert_eq!(LdatArray::from_selector(selector), Some(*array));
+        }
+        assert_eq!(LdatArray::from_selector(5), None);
+    }
+
+    #[test]
+    #[cfg(feature = "emulation")]
+    fn test_ms_array_read_raw() {
+        use crate::{apply_ldat_read_func, ms_array_read_raw, ms_hook_write};
+        use data_types::addresses::MSRAMHookIndex;
+
+        let _lock = crate::emulation::lock();
+        ms_hook_write(MSRAMHookIndex::ZERO + 1, 0x3e7c0429);
+
+        let read_function = apply_ldat_read_func();
+        assert_eq!(
+            ms_array_read_raw(read_function, LdatArray::MatchPatch, 2),
+            0x3e7c0429
+        );
+        assert_eq!(
+            ms_array_read_raw(read_function, LdatArray::MatchPatch, 1),
+            0
+        );
+    }
+}
@@ -8,7 +8,8 @@
 //! ## Features
 //!
 //! - `nostd`: Enables no_std compatibility for embedded/kernel environments
-//! - `emulation`: Enables emulation mode for testing purposes
+//! - `emulation`: Enables emulation mode for testing purposes, backed by an in-memory model of
+//!   the MSRAM and the match & patch registers
 //!
 //! ## Acknowledgements
 //! This crate is based on the work of [@pietroborrello](https://github.com/pietroborrello/CustomProcessingUnit)
@@ -19,10 +20,21 @@ use data_types::patch::UcodePatchEntry;
 extern crate alloc;
 #[cfg(feature = "nostd")]
 use alloc::{format, string::String};
-use data_types::addresses::UCInstructionAddress;
+use data_types::addresses::{Address, MSRAMHookIndex, UCInstructionAddress};
 
+mod allocator;
+mod emulation;
 mod helpers;
+mod hook_registry;
+mod ldat;
+mod match_patch;
+mod relocation;
+pub use allocator::{AllocError, MsramAllocator};
 pub use helpers::*;
+pub use hook_registry::{HookRegistry, InstalledHook};
+pub use ldat::{LdatArray, LdatReadAddress, LdatWriteAddress};
+pub use match_patch::{MatchPatchEntry, MatchPatchState};
+pub use relocation::{apply_patch_at, relocate_patch, AppliedPatch};
 use ucode_dump::{dump, RomDump};
 
 pub mod patches;
@@ -36,8 +48,12 @@ pub enum Error {
     InitMatchAndPatchFailed(String),
     /// Failed to set up microcode hook
     HookFailed(String),
+    /// A hook is already installed at this index, hooking the contained address
+    HookIndexInUse(MSRAMHookIndex, UCInstructionAddress),
     /// Error during microcode patching operation
     PatchError(PatchError),
+    /// No region of the patch space could be allocated, see [`MsramAllocator`]
+    AllocError(AllocError),
     /// Other unspecified errors
     Other(String),
 }
@@ -50,7 +66,11 @@ impl core::fmt::Display for Error {
                 write!(f, "Failed to initialize match and patch: '{}'", t)
             }
             Error::HookFailed(t) => write!(f, "Failed to setup ucode hook: {}", t),
-            Error::PatchError(t) => write!(f, "Failed to patch ucode: {:?}", t),
+            Error::HookIndexInUse(index, address) => {
+                write!(f, "Hook {} is already in use, hooking {}", index, address)
+            }
+            Error::PatchError(t) => write!(f, "Failed to patch ucode: {}", t),
+            Error::AllocError(t) => write!(f, "Failed to allocate patch space: {}", t),
             Error::Other(t) => write!(f, "{}", t),
         }
     }
@@ -58,6 +78,15 @@ impl core::fmt::Display for Error {
 
 impl core::error::Error for Error {}
 
+impl From<AllocError> for Error {
+    fn from(error: AllocError) -> Self {
+        match error {
+            AllocError::Patch(error) => Error::PatchError(error),
+            error => Error::AllocError(error),
+        }
+    }
+}
+
 /// Result type for operations that can fail with a [`Error`]
 pub type Result<T> = core::result::Result<T, Error>;
 
@@ -66,45 +95,86 @@ pub const GLM_OLD: u32 = 0x506c9;
 /// GLM processor version identifier for the new model
 pub const GLM_NEW: u32 = 0x506ca;
 
+/// Returns the number of usable entries of the hook table on a processor
+///
+/// On [`GLM_OLD`], the last entry redirects to the patch moved by
+/// [`CustomProcessingUnit::apply_existing_patches`] and must not be overwritten.
+///
+/// # Arguments
+///
+/// * `glm_version` - The processor version, e.g. [`GLM_OLD`]
+///
+/// # Returns
+///
+/// The number of usable hooks, zero for unsupported processors
+pub const fn hook_count(glm_version: u32) -> usize {
+    match glm_version {
+        GLM_OLD => MSRAMHookIndex::COUNT - 1,
+        GLM_NEW => MSRAMHookIndex::COUNT,
+        _ => 0,
+    }
+}
+
 /// Main struct for managing processor microcode operations.
 ///
 /// This struct provides functionality functions that are processor version specific.
 pub struct CustomProcessingUnit {
     /// The current GLM processor version
     pub current_glm_version: u32,
+    /// The detected processor and its loaded microcode, see [`Self::processor_info`]
+    processor_info: ProcessorInfo,
+    /// The hooks installed via [`Self::hook`] or [`Self::hooks_mut`]
+    hooks: HookRegistry,
+    /// The regions of the MSRAM patch space in use, see [`Self::patch_space_mut`]
+    patch_space: MsramAllocator,
 }
 
 impl CustomProcessingUnit {
     /// Creates a new instance of [`CustomProcessingUnit`].
     ///
-    /// This function detects the current GLM processor version and validates
-    /// that it's supported. In emulation mode, it defaults to [`GLM_OLD`].
+    /// This function detects the current GLM processor version and the loaded microcode
+    /// revision, see [`Self::processor_info`], and validates that the processor is supported.
+    /// In emulation mode, it defaults to [`GLM_OLD`].
     ///
     /// # Returns
     ///
     /// - `Ok(CustomProcessingUnit)` if the processor is supported
     /// - `Err(Error::InvalidProcessor)` if the processor is not supported
     pub fn new() -> Result<CustomProcessingUnit> {
-        let current_glm_version = detect_glm_version();
+        let processor_info = ProcessorInfo::detect();
 
-        if matches!(current_glm_version, GLM_OLD | GLM_NEW) {
-            Ok(CustomProcessingUnit {
-                current_glm_version,
-            })
+        if matches!(processor_info.signature, GLM_OLD | GLM_NEW) {
+            Ok(CustomProcessingUnit::with_version(
+                processor_info.signature,
+                processor_info,
+            ))
         } else {
             if cfg!(feature = "emulation") {
-                return Ok(CustomProcessingUnit {
-                    current_glm_version: GLM_OLD,
-                });
+                return Ok(CustomProcessingUnit::with_version(GLM_OLD, processor_info));
             }
 
             Err(Error::InvalidProcessor(format!(
-                "Unsupported GLM version: '{:08x}'",
-                current_glm_version
+                "Unsupported GLM version: '{:08x}' with microcode revision {:08x}",
+                processor_info.signature, processor_info.ucode_revision
             )))
         }
     }
 
+    fn with_version(
+        current_glm_version: u32,
+        processor_info: ProcessorInfo,
+    ) -> CustomProcessingUnit {
+        CustomProcessingUnit {
+            current_glm_version,
+            processor_info,
+            hooks: HookRegistry::new(
+                Self::rom_for_version(current_glm_version),
+                hook_count(current_glm_version),
+            ),
+            patch_space: MsramAllocator::new(),
+        }
+    }
+
     /// Initializes the microcode operations.
     ///
     /// This function:
@@ -128,7 +198,7 @@ impl CustomProcessingUnit {
                 // U7dfc: WRITEURAM(tmp5, 0x0037, 32) m2=1, NOP, NOP, SEQ_GOTO U60d2
                 [0xa04337080235, 0, 0, 0x2460d200],
             ];
-            patch_ucode(0x7dfc, &EXISTING_PATCH).map_err(Error::PatchError)?;
+            patch_ucode(0x7dfc, &EXISTING_PATCH, true).map_err(Error::PatchError)?;
         }
         Ok(())
     }
@@ -146,13 +216,15 @@ impl CustomProcessingUnit {
             // write and execute the patch that will zero out match&patch moving
             // the 0xc entry to last entry, which will make the hook call our moved patch
             let init_patch = patches::func_init::PATCH;
-            patch_ucode(init_patch.addr, init_patch.ucode_patch).map_err(Error::PatchError)?;
+            patch_ucode(init_patch.addr, init_patch.ucode_patch, true)
+                .map_err(Error::PatchError)?;
 
             Ok(init_patch.addr)
         } else if self.current_glm_version == GLM_NEW {
             // write and execute the patch that will zero out match&patch
             let init_patch = patches::func_init_glm_new::PATCH;
-            patch_ucode(init_patch.addr, init_patch.ucode_patch).map_err(Error::PatchError)?;
+            patch_ucode(init_patch.addr, init_patch.ucode_patch, true)
+                .map_err(Error::PatchError)?;
 
             Ok(init_patch.addr)
         } else {
@@ -176,7 +248,7 @@ impl CustomProcessingUnit {
     pub fn zero_hooks_func(&mut self, zero_hooks_func: UCInstructionAddress) -> Result<()> {
         let result = call_custom_ucode_function(zero_hooks_func, [0; 3]);
 
-        if result.rax != 0x0000133700001337 && cfg!(not(feature = "emulation")) {
+        if result.rax != 0x0000133700001337 {
             return Err(Error::InitMatchAndPatchFailed(format!(
                 "invoke({}) = {:016x}, {:016x}, {:016x}, {:016x}",
                 zero_hooks_func, result.rax, result.rbx, result.rcx, result.rdx
@@ -189,7 +261,9 @@ impl CustomProcessingUnit {
     /// Zeros out all hook registers by first uploading then executing the zero hook function.
     pub fn zero_hooks(&mut self) -> Result<()> {
         let zero_func = self.apply_zero_hook_func()?;
-        self.zero_hooks_func(zero_func)
+        self.zero_hooks_func(zero_func)?;
+        self.hooks.clear();
+        Ok(())
     }
 
     /// Explicitly cleans up resources.
@@ -199,9 +273,136 @@ impl CustomProcessingUnit {
         drop(self)
     }
 
+    /// Sets up a hook in the microcode after validating the hook address against [`Self::rom`]
+    ///
+    /// The hook is recorded in [`Self::hooks`]. See [`hook`] for the arguments.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` if the hook was set up successfully
+    /// - `Err(Error::HookIndexInUse)` if a hook is already installed at this index
+    /// - `Err(Error::HookFailed)` if the hook address is invalid or the hook setup fails
+    pub fn hook<A: Into<UCInstructionAddress>, B: Into<UCInstructionAddress>>(
+        &mut self,
+        apply_hook_func: UCInstructionAddress,
+        hook_idx: MSRAMHookIndex,
+        to_hook_ucode_addr: A,
+        redirect_to_addr: B,
+        enabled: bool,
+    ) -> Result<()> {
+        self.hooks.install(
+            apply_hook_func,
+            hook_idx,
+            to_hook_ucode_addr,
+            redirect_to_addr,
+            enabled,
+        )
+    }
+
+    /// Returns the number of usable entries of the hook table, see [`hook_count`]
+    pub fn hook_count(&self) -> usize {
+        hook_count(self.current_glm_version)
+    }
+
+    /// Returns the number of usable entries of the hook table not occupied by [`Self::hooks`]
+    pub fn free_hook_count(&self) -> usize {
+        self.hooks.free_count()
+    }
+
+    /// Iterates over the usable indices of the hook table, see [`hook_count`]
+    pub fn hook_indices(&self) -> impl Iterator<Item = MSRAMHookIndex> {
+        MSRAMHookIndex::iter_all().take(self.hook_count())
+    }
+
+    /// Returns the processor and microcode revision detected at construction
+    pub fn processor_info(&self) -> &ProcessorInfo {
+        &self.processor_info
+    }
+
+    /// Returns the registry of the installed hooks
+    pub fn hooks(&self) -> &HookRegistry {
+        &self.hooks
+    }
+
+    /// Returns the registry of the installed hooks, to install or remove single hooks
+    pub fn hooks_mut(&mut self) -> &mut HookRegistry {
+        &mut self.hooks
+    }
+
+    /// Returns the allocator of the MSRAM patch space
+    pub fn patch_space(&self) -> &MsramAllocator {
+        &self.patch_space
+    }
+
+    /// Returns the allocator of the MSRAM patch space, to place patches without overlapping
+    /// the patches of other components
+    pub fn patch_space_mut(&mut self) -> &mut MsramAllocator {
+        &mut self.patch_space
+    }
+
+    /// Reads all entries of the hook table and the global hook state.
+    ///
+    /// Debug instructions have to be activated, see [`activate_udebug_insts`].
+    pub fn snapshot_match_patch(&self) -> MatchPatchState {
+        let read_function = apply_ldat_read_func();
+        // hook indices are spaced two LDAT addresses apart
+        let entries = (0..=MSRAMHookIndex::MAX.address() / 2)
+            .map(|index| {
+                let index = MSRAMHookIndex::ZERO + index;
+                MatchPatchEntry {
+                    index,
+                    value: ms_hook_read(read_function, index),
+                }
+            })
+            .collect();
+
+        MatchPatchState {
+            hooks_enabled: hooks_enabled(),
+            entries,
+        }
+    }
+
+    /// Writes all entries of the hook table and the global hook state from a snapshot.
+    ///
+    /// The hooks recorded in [`Self::hooks`] are forgotten, since the restored entries
+    /// were not installed by this instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The snapshot to restore, see [`Self::snapshot_match_patch`]
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` if all entries read back as written
+    /// - `Err(Error::HookFailed)` if an entry reads back a different value
+    pub fn restore_match_patch(&mut self, state: &MatchPatchState) -> Result<()> {
+        for entry in &state.entries {
+            ms_hook_write(entry.index, entry.value);
+        }
+        restore_hooks(if state.hooks_enabled { 0 } else { 1 });
+        self.hooks.clear();
+
+        let read_function = apply_ldat_read_func();
+        for entry in &state.entries {
+            let value = ms_hook_read(read_function, entry.index);
+            if value != entry.value {
+                return Err(Error::HookFailed(format!(
+                    "{} reads back {:08x} instead of {:08x}",
+                    entry.index, value, entry.value
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
     /// Returns a reference to the ROM dump for the current processor version.
     pub const fn rom(&self) -> &'static RomDump<'static, 'static> {
-        match self.current_glm_version {
+        Self::rom_for_version(self.current_glm_version)
+    }
+
+    const fn rom_for_version(glm_version: u32) -> &'static RomDump<'static, 'static> {
+        match glm_version {
             GLM_OLD => &dump::ROM_cpu_000506C9,
             GLM_NEW => &dump::ROM_cpu_000506CA,
             _x => unreachable!(),
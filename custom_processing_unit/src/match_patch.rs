@@ -0,0 +1,137 @@
+//! Snapshots of the match & patch state
+//!
+//! A [`MatchPatchState`] captures all entries of the hook table and the global hook state,
+//! e.g. to inspect stale hooks left behind by a crashed session. See
+//! [`crate::CustomProcessingUnit::snapshot_match_patch`] and
+//! [`crate::CustomProcessingUnit::restore_match_patch`].
+
+#[cfg(feature = "nostd")]
+use alloc::vec::Vec;
+use core::fmt;
+use data_types::addresses::{MSRAMHookIndex, UCInstructionAddress};
+
+/// An entry of the match & patch hook table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchPatchEntry {
+    /// Index of the entry in the hook table
+    pub index: MSRAMHookIndex,
+    /// The raw hook value, see [`crate::calculate_hook_value`]
+    pub value: usize,
+}
+
+impl MatchPatchEntry {
+    /// Returns whether a hook is set up in this entry
+    pub fn is_set(&self) -> bool {
+        self.value != 0
+    }
+
+    /// Returns the hooked ROM address
+    pub fn rom_address(&self) -> UCInstructionAddress {
+        UCInstructionAddress::from_const(self.value & 0x7ffe)
+    }
+
+    /// Returns the address execution is redirected to
+    pub fn patch_address(&self) -> UCInstructionAddress {
+        UCInstructionAddress::MSRAM_START + ((self.value >> 16) & 0xff) * 2
+    }
+
+    /// Returns whether the hook is enabled
+    pub fn enabled(&self) -> bool {
+        self.value & 1 != 0
+    }
+}
+
+impl fmt::Display for MatchPatchEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.is_set() {
+            return write!(f, "{} → unused", self.index);
+        }
+
+        write!(
+            f,
+            "{} → {} → {} → {}",
+            self.index,
+            self.rom_address(),
+            self.patch_address(),
+            if self.enabled() {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        )
+    }
+}
+
+/// The state of the match & patch unit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchPatchState {
+    /// Whether hooks are enabled globally
+    pub hooks_enabled: bool,
+    /// All entries of the hook table ordered by their index
+    pub entries: Vec<MatchPatchEntry>,
+}
+
+impl MatchPatchState {
+    /// Returns the entries with a hook set up
+    pub fn set_entries(&self) -> impl Iterator<Item = &MatchPatchEntry> {
+        self.entries.iter().filter(|entry| entry.is_set())
+    }
+}
+
+impl fmt::Display for MatchPatchState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Hooks enabled: {}", self.hooks_enabled)?;
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{calculate_hook_value, MatchPatchEntry, MatchPatchState};
+    #[cfg(feature = "nostd")]
+    use alloc::{format, vec};
+    use data_types::addresses::{MSRAMHookIndex, UCInstructionAddress};
+
+    #[test]
+    fn test_match_patch_state_display() {
+        let value = calculate_hook_value(
+            UCInstructionAddress::from_const(0x428),
+            UCInstructionAddress::from_const(0x7c10),
+            true,
+        )
+        .unwrap();
+        let state = MatchPatchState {
+            hooks_enabled: true,
+            entries: vec![
+                MatchPatchEntry {
+                    index: MSRAMHookIndex::ZERO,
+                    value,
+                },
+                MatchPatchEntry {
+                    index: MSRAMHookIndex::ZERO + 1,
+                    value: value & !1,
+                },
+                MatchPatchEntry {
+                    index: MSRAMHookIndex::ZERO + 2,
+                    value: 0,
+                },
+            ],
+        };
+
+        assert_eq!(state.entries[0].rom_address(), 0x428usize);
+        assert_eq!(state.entries[0].patch_address(), 0x7c10usize);
+        assert!(state.entries[0].enabled());
+        assert!(!state.entries[1].enabled());
+        assert_eq!(state.set_entries().count(), 2);
+        assert_eq!(
+            format!("{}", state),
+            "Hooks enabled: true\n\
+             H0000 → U0428 → U7c10 → enabled\n\
+             H0001 → U0428 → U7c10 → disabled\n\
+             H0002 → unused\n"
+        );
+    }
+}
@@ -0,0 +1,240 @@
+//! Relocation of patches to another address
+//!
+//! Patches are compiled for the address of their `.org` directive. [`apply_patch_at`] applies
+//! a patch at another address of the MSRAM by rebasing all jumps into the patch itself, so that
+//! several experiments can share the patch space.
+//!
+//! Rebased are the goto targets of sequence words and the targets of the direct jumps `UJMP`
+//! and `UJMPCC_DIRECT_*`. Targets outside of the patch, e.g. in the MSROM, are left untouched.
+
+use crate::{patch_ucode, validate_patch_range, Error, PatchError};
+#[cfg(feature = "nostd")]
+use alloc::vec::Vec;
+use data_types::addresses::{Address, UCInstructionAddress};
+use data_types::patch::{LabelMapping, Patch, UcodePatchEntry};
+use ucode_compiler_dynamic::instruction::{Instruction, Operand};
+use ucode_compiler_dynamic::opcodes::Opcode;
+use ucode_compiler_dynamic::sequence_word::SequenceWord;
+
+/// A patch applied at a relocated address, see [`apply_patch_at`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppliedPatch<'a> {
+    /// The address the patch was applied at
+    pub addr: UCInstructionAddress,
+    /// The labels of the patch, relocated to the new address
+    pub labels: Vec<LabelMapping<'a>>,
+}
+
+impl AppliedPatch<'_> {
+    /// Looks up the relocated address of a label
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the label
+    ///
+    /// # Returns
+    ///
+    /// The address of the label, or `None` if the patch has no such label
+    pub fn label(&self, name: &str) -> Option<UCInstructionAddress> {
+        self.labels
+            .iter()
+            .find(|(label, _)| *label == name)
+            .map(|(_, address)| *address)
+    }
+}
+
+/// Applies a patch at another address than it was compiled for
+///
+/// # Arguments
+///
+/// * `patch` - The patch to apply
+/// * `base` - The address to apply the patch at, must be the start of a triad
+///
+/// # Returns
+///
+/// - `Ok(AppliedPatch)` with the relocated labels if the patch was applied successfully
+/// - `Err(Error::PatchError)` if the patch could not be relocated or applied, see [`patch_ucode`]
+pub fn apply_patch_at<'c>(
+    patch: &Patch<'_, '_, 'c>,
+    base: UCInstructionAddress,
+) -> crate::Result<AppliedPatch<'c>> {
+    let (entries, applied) = relocate_patch(patch, base).map_err(Error::PatchError)?;
+    patch_ucode(base, &entries, false).map_err(Error::PatchError)?;
+    Ok(applied)
+}
+
+/// Rebases the jumps and labels of a patch to another address, without applying it
+///
+/// # Arguments
+///
+/// * `patch` - The patch to relocate
+/// * `base` - The new address of the patch, must be the start of a triad
+///
+/// # Returns
+///
+/// - `Ok((Vec<UcodePatchEntry>, AppliedPatch))` with the relocated entries and labels
+/// - `Err(PatchError::OutOfRange)` if the relocated patch does not fit into the MSRAM patch space
+/// - `Err(PatchError::Misaligned)` if the base address is not the start of a triad
+/// - `Err(PatchError::Relocation)` if a jump of the patch could not be re-encoded
+pub fn relocate_patch<'c>(
+    patch: &Patch<'_, '_, 'c>,
+    base: UCInstructionAddress,
+) -> Result<(Vec<UcodePatchEntry>, AppliedPatch<'c>), PatchError> {
+    validate_patch_range(base, patch.ucode_patch.len())?;
+
+    let start = patch.addr.address();
+    let end = start + patch.ucode_patch.len() * 4;
+    let rebase = |target: usize| base.address() + (target - start);
+
+    let mut entries = Vec::with_capacity(patch.ucode_patch.len());
+    for (index, entry) in patch.ucode_patch.iter().enumerate() {
+        let address = patch.addr + index * 4;
+        let mut entry = *entry;
+
+        for value in entry.iter_mut().take(3) {
+            let instruction = Instruction::disassemble(*value as u64);
+            if !is_direct_jump(instruction.opcode()) {
+                continue;
+            }
+            if let Operand::Immediate(target) = instruction.immediate() {
+                let target = target as usize;
+                if (start..end).contains(&target) {
+                    *value = instruction
+                        .with_immediate(rebase(target) as u64)
+                        .map_err(|_| PatchError::Relocation { address })?
+                        .assemble() as usize;
+                }
+            }
+        }
+
+        let mut sequence_word = SequenceWord::disassemble_no_crc_check(entry[3] as u32)
+            .map_err(|_| PatchError::Relocation { address })?;
+        if let Some(goto) = *sequence_word.goto() {
+            let target = goto.value.address();
+            if (start..end).contains(&target) {
+                sequence_word.set_goto(goto.apply_to_index, rebase(target));
+                entry[3] = sequence_word
+                    .assemble()
+                    .map_err(|_| PatchError::Relocation { address })?
+                    as usize;
+            }
+        }
+
+        entries.push(entry);
+    }
+
+    // labels may also mark the address directly after the patch
+    let labels = patch
+        .labels
+        .iter()
+        .map(|(name, address)| {
+            let target = address.address();
+            if (start..=end).contains(&target) {
+                (*name, UCInstructionAddress::from_const(rebase(target)))
+            } else {
+                (*name, *address)
+            }
+        })
+        .collect();
+
+    Ok((entries, AppliedPatch { addr: base, labels }))
+}
+
+/// Checks if the immediate of an instruction with this opcode is a jump target
+fn is_direct_jump(opcode: Opcode) -> bool {
+    opcode == Opcode::UJMP
+        || (opcode.is_conditional_jump()
+            && !matches!(
+                opcode,
+                Opcode::TESTUSTATE
+                    | Opcode::TESTUSTATE_2
+                    | Opcode::BTUJB_DIRECT_NOTTAKEN
+                    | Opcode::BTUJNB_DIRECT_NOTTAKEN
+                    | Opcode::CMPUJZ_DIRECT_NOTTAKEN
+                    | Opcode::CMPUJNZ_DIRECT_NOTTAKEN
+            ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{relocate_patch, PatchError};
+    use data_types::addresses::UCInstructionAddress;
+    use data_types::patch::Patch;
+    use ucode_compiler_dynamic::instruction::{Instruction, Operand};
+    use ucode_compiler_dynamic::sequence_word::SequenceWord;
+    use ucode_compiler_dynamic::Triad;
+
+    fn entry(instruction: Instruction, sequence_word: SequenceWord) -> [usize; 4] {
+        Triad {
+            instructions: [instruction, Instruction::NOP, Instruction::NOP],
+            sequence_word,
+        }
+        .assemble()
+        .unwrap()
+        .map(|value| value as usize)
+    }
+
+    #[test]
+    fn test_relocate_patch() {
+        let address = UCInstructionAddress::from_const;
+        let rom_jump = entry(
+            Instruction::UJMP(address(0x0428)),
+            SequenceWord::new().apply_goto(2, address(0x1234)),
+        );
+        let ucode_patch = [
+            entry(Instruction::UJMP(address(0x7c08)), SequenceWord::NOP),
+            entry(
+                Instruction::NOP,
+                SequenceWord::new().apply_goto(1, address(0x7c00)),
+            ),
+            rom_jump,
+        ];
+        let patch = Patch {
+            addr: address(0x7c00),
+            hook_address: None,
+            hook_index: None,
+            ucode_patch: &ucode_patch,
+            labels: &[
+                ("start", address(0x7c00)),
+                ("rom", address(0x0428)),
+                ("end", address(0x7c0c)),
+            ],
+        };
+
+        let (entries, applied) = relocate_patch(&patch, address(0x7d00)).unwrap();
+        assert_eq!(applied.addr, address(0x7d00));
+        assert_eq!(applied.label("start"), Some(address(0x7d00)));
+        assert_eq!(applied.label("rom"), Some(address(0x0428)));
+        assert_eq!(applied.label("end"), Some(address(0x7d0c)));
+        assert_eq!(applied.label("unknown"), None);
+
+        assert_eq!(
+            Instruction::disassemble(entries[0][0] as u64).immediate(),
+            Operand::Immediate(0x7d08)
+        );
+        assert_eq!(entries[0][1..], ucode_patch[0][1..]);
+        let sequence_word = SequenceWord::disassemble(entries[1][3] as u32).unwrap();
+        assert_eq!(sequence_word.goto_slot(), Some(1));
+        assert_eq!(
+            sequence_word.goto().map(|goto| goto.value),
+            Some(address(0x7d00))
+        );
+        assert_eq!(entries[2], rom_jump);
+
+        // relocating to the compiled address does not change the patch
+        let (entries, _) = relocate_patch(&patch, address(0x7c00)).unwrap();
+        assert_eq!(entries, ucode_patch);
+
+        assert_eq!(
+            relocate_patch(&patch, address(0x7dfc)).map(|_| ()),
+            Err(PatchError::OutOfRange {
+                addr: address(0x7dfc),
+                len: 3
+            })
+        );
+        assert_eq!(
+            relocate_patch(&patch, address(0x7c01)).map(|_| ()),
+            Err(PatchError::Misaligned)
+        );
+    }
+}
@@ -14,6 +14,7 @@
 
 use crate::cstd::fmt;
 use core::ops::{Add, Div, Mul, Sub};
+use core::str::FromStr;
 
 /// Common trait for all address types
 pub trait Address:
@@ -234,6 +235,21 @@ impl UCInstructionAddress {
         self.triad_offset() == offset
     }
 
+    /// Checks if the address is the first address of a triad
+    pub const fn is_triad_base(&self) -> bool {
+        self.0 & 3 == 0
+    }
+
+    /// Returns the base address of the triad following the triad containing this address
+    ///
+    /// # Panics
+    ///
+    /// Panics if the address is in the last triad of the MSRAM
+    #[track_caller]
+    pub const fn next_triad(&self) -> UCInstructionAddress {
+        UCInstructionAddress::from_const((self.0 & !3) + 4)
+    }
+
     /// The zero address (0x0000)
     pub const ZERO: UCInstructionAddress = UCInstructionAddress::from_const(0);
     /// The minimum valid address (0x0000)
@@ -256,6 +272,79 @@ impl fmt::Debug for UCInstructionAddress {
     }
 }
 
+/// Formats the raw address, e.g. `{:04x}` as `7dfc` and `{:#06x}` as `0x7dfc`
+impl fmt::LowerHex for UCInstructionAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
+    }
+}
+
+/// Error of parsing a [`UCInstructionAddress`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseAddressError {
+    /// The text is not a hexadecimal number
+    InvalidNumber,
+    /// The address is larger than [`UCInstructionAddress::MAX`]
+    OutOfRange,
+}
+
+impl fmt::Display for ParseAddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseAddressError::InvalidNumber => write!(f, "not a hexadecimal address"),
+            ParseAddressError::OutOfRange => {
+                write!(f, "address is larger than {}", UCInstructionAddress::MAX)
+            }
+        }
+    }
+}
+
+impl core::error::Error for ParseAddressError {}
+
+/// Parses an address in the U-notation `U7dfc`, as `0x7dfc` or as bare hex `7dfc`
+///
+/// The prefixes and the digits are accepted in both upper and lower case.
+impl FromStr for UCInstructionAddress {
+    type Err = ParseAddressError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let digits = text
+            .strip_prefix(['U', 'u'])
+            .or_else(|| text.strip_prefix("0x"))
+            .or_else(|| text.strip_prefix("0X"))
+            .unwrap_or(text);
+
+        if digits.is_empty() || !digits.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+            return Err(ParseAddressError::InvalidNumber);
+        }
+
+        match usize::from_str_radix(digits, 16) {
+            Ok(value) if value <= UCInstructionAddress::MAX.0 => Ok(UCInstructionAddress(value)),
+            _ => Err(ParseAddressError::OutOfRange),
+        }
+    }
+}
+
+/// Serializes the address as its raw value
+#[cfg(feature = "serde")]
+impl serde::Serialize for UCInstructionAddress {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.0 as u64)
+    }
+}
+
+/// Deserializes the address from its raw value, rejecting values beyond [`UCInstructionAddress::MAX`]
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for UCInstructionAddress {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = u64::deserialize(deserializer)?;
+        if value > UCInstructionAddress::MAX.0 as u64 {
+            return Err(serde::de::Error::custom(ParseAddressError::OutOfRange));
+        }
+        Ok(UCInstructionAddress(value as usize))
+    }
+}
+
 impl From<LinearAddress> for UCInstructionAddress {
     #[track_caller]
     fn from(value: LinearAddress) -> Self {
@@ -334,6 +423,114 @@ impl<T: Into<usize> + Copy> PartialOrd<T> for UCInstructionAddress {
     }
 }
 
+/// An inclusive range of instruction addresses
+///
+/// The range includes the start and end address. Iterating the range skips the
+/// fourth address of each triad (where addr % 4 == 3), see [`UCInstructionAddress`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct UCInstructionAddressRange {
+    start: UCInstructionAddress,
+    end: UCInstructionAddress,
+}
+
+impl UCInstructionAddressRange {
+    /// Creates a new range from `start` to `end`, both inclusive
+    ///
+    /// # Panics
+    ///
+    /// Panics if `start` is larger than `end`
+    #[track_caller]
+    pub const fn new(start: UCInstructionAddress, end: UCInstructionAddress) -> Self {
+        if start.0 > end.0 {
+            panic!("Range start must not be larger than range end.")
+        }
+
+        UCInstructionAddressRange { start, end }
+    }
+
+    /// Creates a range covering the triad containing `address`
+    pub const fn triad(address: UCInstructionAddress) -> Self {
+        let base = address.triad_base();
+        UCInstructionAddressRange::new(base, base.with_triad_offset(3))
+    }
+
+    /// The first address of the range
+    pub const fn start(&self) -> UCInstructionAddress {
+        self.start
+    }
+
+    /// The last address of the range
+    pub const fn end(&self) -> UCInstructionAddress {
+        self.end
+    }
+
+    /// Checks if the address is within the range
+    pub fn contains(&self, address: UCInstructionAddress) -> bool {
+        self.start <= address && address <= self.end
+    }
+
+    /// Checks if the two ranges share at least one address
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+
+    /// Returns the addresses contained in both ranges
+    ///
+    /// # Returns
+    ///
+    /// - `Some(range)` if the ranges overlap
+    /// - `None` otherwise
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        if !self.overlaps(other) {
+            return None;
+        }
+
+        Some(UCInstructionAddressRange::new(
+            self.start.max(other.start),
+            self.end.min(other.end),
+        ))
+    }
+
+    /// Iterates over all valid instruction addresses within the range
+    ///
+    /// Sequence: [7c00, 7c01, 7c02, 7c04, 7c05, ...]
+    pub fn iter_instructions(&self) -> impl Iterator<Item = UCInstructionAddress> {
+        (self.start.0..=self.end.0)
+            .filter(|address| address & 3 != 3)
+            .map(UCInstructionAddress::from_const)
+    }
+
+    /// Iterates over the base addresses of all triads overlapping the range
+    ///
+    /// A triad is included if any of its addresses is within the range, so the first
+    /// base address may be smaller than [`Self::start`].
+    pub fn iter_triad_bases(&self) -> impl Iterator<Item = UCInstructionAddress> {
+        (self.start.triad_base().0..=self.end.0)
+            .step_by(4)
+            .map(UCInstructionAddress::from_const)
+    }
+
+    /// The microcode ROM [0x0000; 0x7bff]
+    pub const MSROM: UCInstructionAddressRange = UCInstructionAddressRange::new(
+        UCInstructionAddress::ZERO,
+        UCInstructionAddress::from_const(0x7c00 - 1),
+    );
+    /// The microcode RAM [0x7c00; 0x7dff]
+    pub const MSRAM: UCInstructionAddressRange = UCInstructionAddressRange::new(
+        UCInstructionAddress::MSRAM_START,
+        UCInstructionAddress::MAX,
+    );
+    /// The complete addressing region [0x0000; 0x7dff]
+    pub const ALL: UCInstructionAddressRange =
+        UCInstructionAddressRange::new(UCInstructionAddress::ZERO, UCInstructionAddress::MAX);
+}
+
+impl fmt::Display for UCInstructionAddressRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}; {}]", self.start, self.end)
+    }
+}
+
 /// An address for writing to the instruction part of MSRAM
 ///
 /// Memory layout of the MSRAM is like this:
@@ -616,22 +813,49 @@ impl MSRAMHookIndex {
     ///
     /// # Panics
     ///
-    /// Panics if the index is out of bounds (>= 32)
+    /// Panics if the index is out of bounds (>= [`Self::COUNT`])
     #[track_caller]
     pub const fn from_const(value: usize) -> Self {
-        if value >= 32 {
+        if value >= MSRAMHookIndex::COUNT {
             panic!("Index out of bounds exception. Index must be smaller than 32.")
         }
 
         MSRAMHookIndex(value)
     }
 
+    /// Returns the index into the hook table, unlike [`Address::address`] not spaced
+    pub const fn index(&self) -> usize {
+        self.0
+    }
+
+    /// Adds to the index, checking the bounds of the hook table
+    ///
+    /// # Returns
+    ///
+    /// - `Some(MSRAMHookIndex)` if the result is a valid index
+    /// - `None` if the result is beyond [`Self::MAX`]
+    pub const fn checked_add(self, other: usize) -> Option<Self> {
+        match self.0.checked_add(other) {
+            Some(value) if value < MSRAMHookIndex::COUNT => Some(MSRAMHookIndex(value)),
+            _ => None,
+        }
+    }
+
+    /// Iterates over all indices of the hook table, in ascending order
+    ///
+    /// Not all entries are usable on every processor, see `custom_processing_unit::hook_count`.
+    pub fn iter_all() -> impl Iterator<Item = MSRAMHookIndex> {
+        (0..MSRAMHookIndex::COUNT).map(MSRAMHookIndex)
+    }
+
+    /// The number of entries in the hook table
+    pub const COUNT: usize = 32;
     /// The zero index (0x0000)
     pub const ZERO: Self = MSRAMHookIndex::from_const(0);
     /// The minimum valid index (0x0000)
     pub const MIN: Self = MSRAMHookIndex::ZERO;
     /// The maximum valid index (31)
-    pub const MAX: Self = MSRAMHookIndex::from_const(31);
+    pub const MAX: Self = MSRAMHookIndex::from_const(MSRAMHookIndex::COUNT - 1);
 }
 
 impl MSRAMAddress for MSRAMHookIndex {}
@@ -647,6 +871,27 @@ impl fmt::Debug for MSRAMHookIndex {
         write!(f, "H{:04x}", self.0)
     }
 }
+
+/// Serializes the hook index as the index, not as its LDAT address
+#[cfg(feature = "serde")]
+impl serde::Serialize for MSRAMHookIndex {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.0 as u64)
+    }
+}
+
+/// Deserializes the hook index, rejecting indices beyond [`MSRAMHookIndex::MAX`]
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for MSRAMHookIndex {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = u64::deserialize(deserializer)?;
+        if value > MSRAMHookIndex::MAX.0 as u64 {
+            return Err(serde::de::Error::custom(ParseAddressError::OutOfRange));
+        }
+        Ok(MSRAMHookIndex(value as usize))
+    }
+}
+
 impl From<LinearAddress> for MSRAMHookIndex {
     #[track_caller]
     fn from(value: LinearAddress) -> Self {
@@ -660,6 +905,7 @@ impl From<MSRAMHookIndex> for LinearAddress {
     }
 }
 
+/// Panics if the result is beyond [`MSRAMHookIndex::MAX`], see [`MSRAMHookIndex::checked_add`]
 impl Add<usize> for MSRAMHookIndex {
     type Output = Self;
 
@@ -686,6 +932,7 @@ impl From<&MSRAMHookIndex> for MSRAMHookIndex {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::format;
     use alloc::vec;
     use alloc::vec::Vec;
 
@@ -806,6 +1053,209 @@ mod tests {
         conversion_harness(&tests);
     }
 
+    #[test]
+    fn test_parse_uc_address() {
+        let address = UCInstructionAddress::from_const(0x7dfc);
+
+        for text in ["U7dfc", "u7DFC", "0x7dfc", "0X7DfC", "7dfc"] {
+            assert_eq!(text.parse(), Ok(address), "{}", text);
+        }
+        assert_eq!("U0000".parse(), Ok(UCInstructionAddress::ZERO));
+        assert_eq!("U7dff".parse(), Ok(UCInstructionAddress::MAX));
+
+        assert_eq!(
+            "U7e00".parse::<UCInstructionAddress>(),
+            Err(ParseAddressError::OutOfRange)
+        );
+        assert_eq!(
+            "0xffffffffffffffffffff".parse::<UCInstructionAddress>(),
+            Err(ParseAddressError::OutOfRange)
+        );
+        for text in ["", "U", "0x", "U+7dfc", "U 7dfc", "7dfg", "UU7dfc"] {
+            assert_eq!(
+                text.parse::<UCInstructionAddress>(),
+                Err(ParseAddressError::InvalidNumber),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_uc_address() {
+        let address = UCInstructionAddress::from_const(0x42a);
+
+        assert_eq!(format!("{}", address), "U042a");
+        assert_eq!(format!("{:04x}", address), "042a");
+        assert_eq!(format!("{:#06x}", address), "0x042a");
+
+        for value in [0, 0x42a, 0x7c00, 0x7dff] {
+            let address = UCInstructionAddress::from_const(value);
+            assert_eq!(format!("{}", address).parse(), Ok(address));
+            assert_eq!(format!("{:#x}", address).parse(), Ok(address));
+        }
+    }
+
+    #[test]
+    fn test_triad_helpers() {
+        let rom_end = UCInstructionAddress::from_const(0x7bfe);
+        assert!(!rom_end.is_triad_base());
+        assert!(UCInstructionAddress::from_const(0x7bfc).is_triad_base());
+        assert_eq!(rom_end.next_triad(), UCInstructionAddress::MSRAM_START);
+        assert_eq!(
+            UCInstructionAddress::from_const(0x7bfc).next_triad(),
+            UCInstructionAddress::MSRAM_START
+        );
+        assert_eq!(
+            UCInstructionAddress::MSRAM_START.next_triad(),
+            UCInstructionAddress::from_const(0x7c04)
+        );
+        assert_eq!(
+            UCInstructionAddress::from_const(0x7df9).next_triad(),
+            UCInstructionAddress::from_const(0x7dfc)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_next_triad_out_of_bounds() {
+        UCInstructionAddress::MAX.next_triad();
+    }
+
+    #[test]
+    fn test_range_contains() {
+        let msrom = UCInstructionAddressRange::MSROM;
+        let msram = UCInstructionAddressRange::MSRAM;
+
+        assert!(msrom.contains(UCInstructionAddress::ZERO));
+        assert!(msrom.contains(UCInstructionAddress::from_const(0x7bff)));
+        assert!(!msrom.contains(UCInstructionAddress::MSRAM_START));
+        assert!(!msram.contains(UCInstructionAddress::from_const(0x7bff)));
+        assert!(msram.contains(UCInstructionAddress::MSRAM_START));
+        assert!(msram.contains(UCInstructionAddress::MAX));
+        assert!(!msrom.overlaps(&msram));
+        assert!(UCInstructionAddressRange::ALL.overlaps(&msram));
+    }
+
+    #[test]
+    fn test_range_intersection() {
+        let range = |start, end| {
+            UCInstructionAddressRange::new(
+                UCInstructionAddress::from_const(start),
+                UCInstructionAddress::from_const(end),
+            )
+        };
+        let boundary = range(0x7bfc, 0x7c02);
+
+        assert_eq!(
+            boundary.intersection(&UCInstructionAddressRange::MSROM),
+            Some(range(0x7bfc, 0x7bff))
+        );
+        assert_eq!(
+            boundary.intersection(&UCInstructionAddressRange::MSRAM),
+            Some(range(0x7c00, 0x7c02))
+        );
+        assert_eq!(
+            UCInstructionAddressRange::MSROM.intersection(&UCInstructionAddressRange::MSRAM),
+            None
+        );
+        assert_eq!(
+            range(0x7bff, 0x7bff).intersection(&range(0x7c00, 0x7c00)),
+            None
+        );
+        assert_eq!(
+            range(0x7bff, 0x7c00).intersection(&range(0x7c00, 0x7dff)),
+            Some(range(0x7c00, 0x7c00))
+        );
+        assert_eq!(
+            UCInstructionAddressRange::ALL.intersection(&boundary),
+            Some(boundary)
+        );
+        assert_eq!(
+            UCInstructionAddressRange::triad(UCInstructionAddress::from_const(0x7c02)),
+            range(0x7c00, 0x7c03)
+        );
+    }
+
+    #[test]
+    fn test_range_iteration() {
+        let range = |start, end| {
+            UCInstructionAddressRange::new(
+                UCInstructionAddress::from_const(start),
+                UCInstructionAddress::from_const(end),
+            )
+        };
+
+        assert_eq!(
+            range(0x7bfa, 0x7c05)
+                .iter_instructions()
+                .collect::<Vec<_>>(),
+            [0x7bfa, 0x7bfc, 0x7bfd, 0x7bfe, 0x7c00, 0x7c01, 0x7c02, 0x7c04, 0x7c05]
+                .map(UCInstructionAddress::from_const)
+        );
+        assert_eq!(
+            range(0x7bfa, 0x7c05).iter_triad_bases().collect::<Vec<_>>(),
+            [0x7bf8, 0x7bfc, 0x7c00, 0x7c04].map(UCInstructionAddress::from_const)
+        );
+        assert_eq!(range(0x7bff, 0x7bff).iter_instructions().count(), 0);
+        assert_eq!(
+            range(0x7bff, 0x7bff).iter_triad_bases().collect::<Vec<_>>(),
+            [UCInstructionAddress::from_const(0x7bfc)]
+        );
+        assert_eq!(
+            range(0x7dfc, 0x7dff)
+                .iter_instructions()
+                .collect::<Vec<_>>(),
+            [0x7dfc, 0x7dfd, 0x7dfe].map(UCInstructionAddress::from_const)
+        );
+
+        let msram = UCInstructionAddressRange::MSRAM;
+        assert_eq!(msram.iter_instructions().count(), 128 * 3);
+        assert_eq!(msram.iter_triad_bases().count(), 128);
+        assert!(msram
+            .iter_triad_bases()
+            .all(|address| address.is_triad_base()));
+        assert_eq!(
+            msram.iter_triad_bases().last(),
+            Some(UCInstructionAddress::from_const(0x7dfc))
+        );
+        assert_eq!(
+            UCInstructionAddressRange::MSROM.iter_triad_bases().count(),
+            0x7c00 / 4
+        );
+        assert_eq!(
+            UCInstructionAddressRange::MSROM.iter_instructions().last(),
+            Some(UCInstructionAddress::from_const(0x7bfe))
+        );
+    }
+
+    #[test]
+    fn test_hook_index_bounds() {
+        let indices = MSRAMHookIndex::iter_all().collect::<Vec<_>>();
+        assert_eq!(indices.len(), MSRAMHookIndex::COUNT);
+        assert_eq!(indices.first(), Some(&MSRAMHookIndex::ZERO));
+        assert_eq!(indices.last(), Some(&MSRAMHookIndex::MAX));
+        assert!(indices
+            .iter()
+            .enumerate()
+            .all(|(i, index)| index.index() == i));
+
+        assert_eq!(
+            MSRAMHookIndex::ZERO.checked_add(31),
+            Some(MSRAMHookIndex::MAX)
+        );
+        assert_eq!(MSRAMHookIndex::ZERO.checked_add(32), None);
+        assert_eq!(MSRAMHookIndex::MAX.checked_add(1), None);
+        assert_eq!(MSRAMHookIndex::MAX.checked_add(usize::MAX), None);
+        assert_eq!(MSRAMHookIndex::MAX.address(), 62);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_hook_index_add_out_of_bounds() {
+        let _ = MSRAMHookIndex::MAX + 1;
+    }
+
     #[test]
     fn test_convert_la_hook() {
         let tests = vec![
@@ -6,6 +6,7 @@
 //! ## Features
 //!
 //! - `nostd`: Enables no_std compatibility
+//! - `serde`: Implements `Serialize` and `Deserialize` for the address and patch types
 //!
 //! ## Modules
 //!
@@ -5,12 +5,19 @@
 //! - Patch blobs (collections of patch entries)
 //! - Label mappings for symbolic references
 //! - Microcode patches (collection of blobs and labels)
+//! - Patch files (a binary container to save and load patches, see [`PatchFile`])
 
-use crate::addresses::{MSRAMHookIndex, UCInstructionAddress};
+use crate::addresses::{Address, MSRAMHookIndex, UCInstructionAddress};
+use crate::cstd::fmt;
+use alloc::vec::Vec;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// A single microcode patch entry consisting of 4 values:
 /// - 3 instructions (uop0, uop1, uop2)
 /// - 1 sequence word
+///
+/// As a plain array, it is serialized as a list of four numbers.
 pub type UcodePatchEntry = [usize; 4];
 
 /// A collection of microcode patch entries
@@ -20,6 +27,10 @@ pub type UcodePatchBlob = [UcodePatchEntry];
 pub type LabelMapping<'a> = (&'a str, UCInstructionAddress);
 
 /// Configuration for a microcode patch
+///
+/// With the `serde` feature, the patch can be serialized but not deserialized, since it
+/// borrows its content.
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct Patch<'a, 'b, 'c> {
     /// The address where the patch should be applied
     pub addr: UCInstructionAddress,
@@ -35,9 +46,421 @@ pub struct Patch<'a, 'b, 'c> {
 
 /// A triad of microcode instructions with a sequence word
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Triad {
     /// Three 64-bit instructions
     pub instructions: [u64; 3],
     /// 32-bit sequence word
     pub sequence_word: u32,
 }
+
+/// Magic bytes at the start of a patch file
+pub const PATCH_FILE_MAGIC: [u8; 4] = *b"UCPF";
+
+/// Version of the patch file format written by [`PatchFile::to_bytes`]
+pub const PATCH_FILE_VERSION: u16 = 1;
+
+/// A microcode patch in the binary patch file format
+///
+/// The format is little endian:
+/// - magic [`PATCH_FILE_MAGIC`] and version [`PATCH_FILE_VERSION`] (u16)
+/// - target CPUID signature (u32), zero if the patch is not bound to a processor
+/// - base address (u16) and number of triads (u16)
+/// - triads: three instructions (u64) and the sequence word (u32) each
+/// - number of labels (u16), each label as name length (u16), UTF-8 name and address (u16)
+/// - FNV-1a checksum (u32) over all preceding bytes
+///
+/// The labels of a loaded patch file borrow their names from the loaded bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchFile<'a> {
+    /// The CPUID signature the patch was made for, zero for any processor
+    pub cpuid: u32,
+    /// The address the patch should be applied at
+    pub addr: UCInstructionAddress,
+    /// The triads of the patch
+    pub ucode_patch: Vec<UcodePatchEntry>,
+    /// Label mappings for symbolic references in the patch
+    pub labels: Vec<LabelMapping<'a>>,
+}
+
+/// Errors when loading a patch file, see [`PatchFile::load`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchFileError {
+    /// The file does not start with [`PATCH_FILE_MAGIC`]
+    InvalidMagic,
+    /// The file was written in an unsupported version of the format
+    UnsupportedVersion(u16),
+    /// The file ends before the data announced by its header
+    Truncated,
+    /// The file contains data after the labels
+    TrailingData,
+    /// The checksum does not match the content of the file
+    ChecksumMismatch {
+        /// The checksum stored in the file
+        stored: u32,
+        /// The checksum of the content
+        computed: u32,
+    },
+    /// An address of the file is beyond [`UCInstructionAddress::MAX`]
+    InvalidAddress(usize),
+    /// A label name is not valid UTF-8
+    InvalidLabel,
+    /// A patch entry does not fit into the file format
+    InvalidEntry,
+}
+
+impl fmt::Display for PatchFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchFileError::InvalidMagic => write!(f, "not a patch file"),
+            PatchFileError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported patch file version {}, expected {}",
+                version, PATCH_FILE_VERSION
+            ),
+            PatchFileError::Truncated => write!(f, "patch file is truncated"),
+            PatchFileError::TrailingData => write!(f, "patch file has data after the labels"),
+            PatchFileError::ChecksumMismatch { stored, computed } => write!(
+                f,
+                "patch file checksum {:08x} does not match the content {:08x}",
+                stored, computed
+            ),
+            PatchFileError::InvalidAddress(address) => {
+                write!(f, "invalid address {:04x} in patch file", address)
+            }
+            PatchFileError::InvalidLabel => write!(f, "label name is not valid UTF-8"),
+            PatchFileError::InvalidEntry => {
+                write!(f, "patch entry does not fit into the patch file format")
+            }
+        }
+    }
+}
+
+impl core::error::Error for PatchFileError {}
+
+/// FNV-1a hash used as the checksum of patch files
+fn fnv1a(data: &[u8]) -> u32 {
+    data.iter().fold(0x811c9dc5, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+/// Reads the little endian fields of a patch file
+struct PatchFileReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> PatchFileReader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], PatchFileError> {
+        if self.data.len() < len {
+            return Err(PatchFileError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], PatchFileError> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    fn u16(&mut self) -> Result<u16, PatchFileError> {
+        self.array().map(u16::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, PatchFileError> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, PatchFileError> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    fn address(&mut self) -> Result<UCInstructionAddress, PatchFileError> {
+        let address = self.u16()? as usize;
+        if address > UCInstructionAddress::MAX.address() {
+            return Err(PatchFileError::InvalidAddress(address));
+        }
+        Ok(UCInstructionAddress::from_const(address))
+    }
+}
+
+impl<'a> PatchFile<'a> {
+    /// Creates a patch file from a patch
+    ///
+    /// # Arguments
+    ///
+    /// * `patch` - The patch to store, the hook of the patch is not part of the file
+    /// * `cpuid` - The CPUID signature the patch was made for, zero for any processor
+    pub fn new(patch: &Patch<'_, '_, 'a>, cpuid: u32) -> Self {
+        PatchFile {
+            cpuid,
+            addr: patch.addr,
+            ucode_patch: patch.ucode_patch.to_vec(),
+            labels: patch.labels.to_vec(),
+        }
+    }
+
+    /// Returns the patch to apply, without a hook
+    pub fn as_patch(&self) -> Patch<'_, '_, 'a> {
+        Patch {
+            addr: self.addr,
+            hook_address: None,
+            hook_index: None,
+            ucode_patch: &self.ucode_patch,
+            labels: &self.labels,
+        }
+    }
+
+    /// Loads a patch file
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The content of the patch file
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(PatchFile)` with the labels borrowed from `data`
+    /// - `Err(PatchFileError)` if the file is corrupt or of another version
+    pub fn load(data: &'a [u8]) -> Result<Self, PatchFileError> {
+        let mut reader = PatchFileReader { data };
+
+        if reader.array::<4>() != Ok(PATCH_FILE_MAGIC) {
+            return Err(PatchFileError::InvalidMagic);
+        }
+        let version = reader.u16()?;
+        if version != PATCH_FILE_VERSION {
+            return Err(PatchFileError::UnsupportedVersion(version));
+        }
+
+        // check the content before interpreting it
+        let content_len = data.len().checked_sub(4).ok_or(PatchFileError::Truncated)?;
+        let stored = PatchFileReader {
+            data: &data[content_len..],
+        }
+        .u32()?;
+        let computed = fnv1a(&data[..content_len]);
+        if stored != computed {
+            return Err(PatchFileError::ChecksumMismatch { stored, computed });
+        }
+        reader.data = reader
+            .data
+            .get(..reader.data.len().saturating_sub(4))
+            .ok_or(PatchFileError::Truncated)?;
+
+        let cpuid = reader.u32()?;
+        let addr = reader.address()?;
+
+        let mut ucode_patch = Vec::new();
+        for _ in 0..reader.u16()? {
+            let instructions = [reader.u64()?, reader.u64()?, reader.u64()?];
+            let sequence_word = reader.u32()?;
+            ucode_patch.push([
+                instructions[0] as usize,
+                instructions[1] as usize,
+                instructions[2] as usize,
+                sequence_word as usize,
+            ]);
+        }
+
+        let mut labels = Vec::new();
+        for _ in 0..reader.u16()? {
+            let len = reader.u16()? as usize;
+            let name = core::str::from_utf8(reader.bytes(len)?)
+                .map_err(|_| PatchFileError::InvalidLabel)?;
+            labels.push((name, reader.address()?));
+        }
+
+        if !reader.data.is_empty() {
+            return Err(PatchFileError::TrailingData);
+        }
+
+        Ok(PatchFile {
+            cpuid,
+            addr,
+            ucode_patch,
+            labels,
+        })
+    }
+
+    /// Encodes the patch file
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Vec<u8>)` with the content of the patch file
+    /// - `Err(PatchFileError::InvalidEntry)` if an entry, a label or the patch is too large
+    pub fn to_bytes(&self) -> Result<Vec<u8>, PatchFileError> {
+        let count = |len: usize| u16::try_from(len).map_err(|_| PatchFileError::InvalidEntry);
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&PATCH_FILE_MAGIC);
+        data.extend_from_slice(&PATCH_FILE_VERSION.to_le_bytes());
+        data.extend_from_slice(&self.cpuid.to_le_bytes());
+        data.extend_from_slice(&(self.addr.address() as u16).to_le_bytes());
+
+        data.extend_from_slice(&count(self.ucode_patch.len())?.to_le_bytes());
+        for entry in &self.ucode_patch {
+            for instruction in &entry[..3] {
+                data.extend_from_slice(&(*instruction as u64).to_le_bytes());
+            }
+            let sequence_word =
+                u32::try_from(entry[3]).map_err(|_| PatchFileError::InvalidEntry)?;
+            data.extend_from_slice(&sequence_word.to_le_bytes());
+        }
+
+        data.extend_from_slice(&count(self.labels.len())?.to_le_bytes());
+        for (name, address) in &self.labels {
+            data.extend_from_slice(&count(name.len())?.to_le_bytes());
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(&(address.address() as u16).to_le_bytes());
+        }
+
+        let checksum = fnv1a(&data);
+        data.extend_from_slice(&checksum.to_le_bytes());
+        Ok(data)
+    }
+
+    /// Writes the patch file, see [`Self::to_bytes`]
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The writer to write the patch file to
+    #[cfg(not(feature = "nostd"))]
+    pub fn save<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let data = self
+            .to_bytes()
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+        writer.write_all(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    const ENTRIES: [UcodePatchEntry; 2] = [
+        [0x6e75406aa00d, 0x0, 0x0, 0x3000000],
+        [0x300000000000, 0x7c7c, 0x1, 0x1010101],
+    ];
+    const LABELS: [LabelMapping<'static>; 2] = [
+        ("entry", UCInstructionAddress::MSRAM_START),
+        ("exit", UCInstructionAddress::from_const(0x7c05)),
+    ];
+
+    fn patch_file() -> PatchFile<'static> {
+        let patch = Patch {
+            addr: UCInstructionAddress::MSRAM_START,
+            hook_address: Some(UCInstructionAddress::from_const(0x428)),
+            hook_index: None,
+            ucode_patch: &ENTRIES,
+            labels: &LABELS,
+        };
+        PatchFile::new(&patch, 0x506c9)
+    }
+
+    #[test]
+    fn test_patch_file_round_trip() {
+        let file = patch_file();
+        let data = file.to_bytes().unwrap();
+        assert_eq!(data[..4], PATCH_FILE_MAGIC);
+
+        let loaded = PatchFile::load(&data).unwrap();
+        assert_eq!(loaded, file);
+
+        let patch = loaded.as_patch();
+        assert_eq!(patch.addr, UCInstructionAddress::MSRAM_START);
+        assert_eq!(patch.hook_address, None);
+        assert_eq!(patch.ucode_patch, &ENTRIES);
+        assert_eq!(patch.labels, &LABELS);
+
+        #[cfg(not(feature = "nostd"))]
+        {
+            let mut saved = Vec::new();
+            file.save(&mut saved).unwrap();
+            assert_eq!(saved, data);
+        }
+    }
+
+    /// Replaces the checksum of a modified patch file
+    fn reseal(data: &mut [u8]) {
+        let len = data.len() - 4;
+        let checksum = fnv1a(&data[..len]);
+        data[len..].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    #[test]
+    fn test_patch_file_rejects_corrupt_files() {
+        let data = patch_file().to_bytes().unwrap();
+
+        assert_eq!(PatchFile::load(&[]), Err(PatchFileError::InvalidMagic));
+        assert_eq!(
+            PatchFile::load(b"UCPX\x01\x00"),
+            Err(PatchFileError::InvalidMagic)
+        );
+        assert_eq!(PatchFile::load(&data[..5]), Err(PatchFileError::Truncated));
+        assert!(matches!(
+            PatchFile::load(&data[..data.len() - 5]),
+            Err(PatchFileError::ChecksumMismatch { .. })
+        ));
+
+        let mut flipped = data.clone();
+        flipped[20] ^= 1;
+        assert!(matches!(
+            PatchFile::load(&flipped),
+            Err(PatchFileError::ChecksumMismatch { .. })
+        ));
+
+        let mut version = data.clone();
+        version[4] = 2;
+        assert_eq!(
+            PatchFile::load(&version),
+            Err(PatchFileError::UnsupportedVersion(2))
+        );
+
+        let mut truncated = data[..data.len() - 7].to_vec();
+        truncated.extend_from_slice(&[0; 4]);
+        reseal(&mut truncated);
+        assert_eq!(PatchFile::load(&truncated), Err(PatchFileError::Truncated));
+
+        let mut trailing = data.clone();
+        trailing.insert(data.len() - 4, 0);
+        reseal(&mut trailing);
+        assert_eq!(
+            PatchFile::load(&trailing),
+            Err(PatchFileError::TrailingData)
+        );
+
+        let empty = PatchFile {
+            cpuid: 0,
+            addr: UCInstructionAddress::MSRAM_START,
+            ucode_patch: vec![],
+            labels: vec![("a", UCInstructionAddress::MSRAM_START)],
+        }
+        .to_bytes()
+        .unwrap();
+
+        let mut address = empty.clone();
+        address[10..12].copy_from_slice(&0x7e00u16.to_le_bytes());
+        reseal(&mut address);
+        assert_eq!(
+            PatchFile::load(&address),
+            Err(PatchFileError::InvalidAddress(0x7e00))
+        );
+
+        let mut label = empty.clone();
+        label[18] = 0xff;
+        reseal(&mut label);
+        assert_eq!(PatchFile::load(&label), Err(PatchFileError::InvalidLabel));
+
+        let too_large = PatchFile {
+            cpuid: 0,
+            addr: UCInstructionAddress::MSRAM_START,
+            ucode_patch: vec![[0, 0, 0, 1 << 32]],
+            labels: vec![],
+        };
+        assert_eq!(too_large.to_bytes(), Err(PatchFileError::InvalidEntry));
+    }
+}
@@ -1,7 +1,7 @@
 use alloc::format;
 use alloc::string::String;
 use core::arch::asm;
-use custom_processing_unit::{CustomProcessingUnit, apply_hook_patch_func, apply_patch, hook};
+use custom_processing_unit::{apply_hook_patch_func, apply_patch, hook, CustomProcessingUnit};
 use data_types::addresses::MSRAMHookIndex;
 use itertools::Itertools;
 use log::{error, trace};
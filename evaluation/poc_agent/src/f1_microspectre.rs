@@ -3,7 +3,7 @@ use alloc::format;
 use alloc::string::String;
 use core::arch::asm;
 use custom_processing_unit::{
-    CustomProcessingUnit, apply_hook_patch_func, apply_patch, hook, ms_seqw_write,
+    apply_hook_patch_func, apply_patch, hook, ms_seqw_write, CustomProcessingUnit,
 };
 use data_types::addresses::MSRAMHookIndex;
 use log::{error, trace};
@@ -3,15 +3,14 @@
 
 extern crate alloc;
 
-use alloc::vec;
-use alloc::vec::Vec;
+use alloc::string::ToString;
 use log::error;
-use poc_data::agent_execute_scenario;
+use poc_data::{agent_execute_scenario, ScenarioError, ScenarioResponse};
 
 pub mod f0_microcode;
 pub mod f1_microspectre;
 
-pub fn execute(scenario: &str, payload: &[u8]) -> Vec<u8> {
+pub fn execute(scenario: &str, payload: &[u8]) -> ScenarioResponse {
     match scenario {
         poc_data::f0_microcode::NAME => agent_execute_scenario(payload, f0_microcode::execute),
         poc_data::f1_microspectre::NAME => {
@@ -19,7 +18,7 @@ pub fn execute(scenario: &str, payload: &[u8]) -> Vec<u8> {
         }
         _ => {
             error!("Unknown scenario: {}", scenario);
-            vec![]
+            Err(ScenarioError::UnknownScenario(scenario.to_string()))
         }
     }
 }
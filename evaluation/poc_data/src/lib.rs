@@ -1,30 +1,57 @@
 #![cfg_attr(not(feature = "clap"), no_std)]
 extern crate alloc;
 
+use alloc::format;
 use alloc::string::String;
 use alloc::vec::Vec;
-use alloc::{format, vec};
+use core::fmt::{Display, Formatter};
 use log::error;
 use serde::{Deserialize, Serialize};
 
 pub mod f0_microcode;
 pub mod f1_microspectre;
 
+/// Names of all scenarios
+pub const SCENARIOS: [&str; 2] = [f0_microcode::NAME, f1_microspectre::NAME];
+
+/// Reasons a scenario could not be executed by the agent
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ScenarioError {
+    /// The agent does not know a scenario of this name
+    UnknownScenario(String),
+    /// The payload is not a valid payload of the scenario
+    InvalidPayload(String),
+    /// The result of the scenario could not be serialized
+    InvalidResult(String),
+}
+
+impl Display for ScenarioError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ScenarioError::UnknownScenario(name) => write!(f, "Unknown scenario: {}", name),
+            ScenarioError::InvalidPayload(err) => write!(f, "Invalid scenario payload: {}", err),
+            ScenarioError::InvalidResult(err) => write!(f, "Invalid scenario result: {}", err),
+        }
+    }
+}
+
+/// Response of the agent to a scenario request, transmitted serialized with [`serialize`]
+///
+/// Contains the serialized result of the scenario on success.
+pub type ScenarioResponse = Result<Vec<u8>, ScenarioError>;
+
 pub fn agent_execute_scenario<A: for<'a> Deserialize<'a>, B: Serialize, F: Fn(A) -> B>(
     payload: &[u8],
     func: F,
-) -> Vec<u8> {
-    let payload = match postcard::from_bytes(payload) {
-        Ok(payload) => payload,
-        Err(err) => {
-            error!("Failed to deserialize scenario payload: {:?}", err);
-            return vec![];
-        }
-    };
+) -> ScenarioResponse {
+    let payload = postcard::from_bytes(payload).map_err(|err| {
+        error!("Failed to deserialize scenario payload: {:?}", err);
+        ScenarioError::InvalidPayload(format!("{:?}", err))
+    })?;
     let result = func(payload);
-    postcard::to_allocvec(&result).unwrap_or_else(|err| {
+    postcard::to_allocvec(&result).map_err(|err| {
         error!("Failed to serialize poc agent result: {:?}", err);
-        vec![]
+        ScenarioError::InvalidResult(format!("{:?}", err))
     })
 }
 
@@ -0,0 +1,90 @@
+//! Authentication of OTA packets
+//!
+//! Packets can be authenticated with a pre-shared key, see [`OTA_AUTHENTICATED_PROTOCOL_VERSION`].
+//! The key is derived from a passphrase known to both the device (set at build time) and the
+//! controller. Authentication is optional, but a peer with a key never accepts unauthenticated
+//! packets, otherwise anyone on the network could downgrade the connection.
+//!
+//! [`OTA_AUTHENTICATED_PROTOCOL_VERSION`]: crate::OTA_AUTHENTICATED_PROTOCOL_VERSION
+
+use core::fmt::{Debug, Formatter};
+use core::hash::Hasher;
+use siphasher::sip::SipHasher24;
+use siphasher::sip128::{Hasher128, SipHasher24 as SipHasher128};
+
+/// Size of the message authentication code appended to authenticated packets
+pub const OTA_MAC_SIZE: usize = 8;
+
+/// Fixed key of the passphrase derivation, "ufuzz-ota-key-v1"
+const DERIVATION_KEY: [u8; 16] = *b"ufuzz-ota-key-v1";
+
+/// Pre-shared key authenticating OTA packets with a SipHash-2-4 message authentication code
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct OtaKey([u8; 16]);
+
+impl OtaKey {
+    /// Creates a key from raw key material
+    pub fn new(key: [u8; 16]) -> Self {
+        Self(key)
+    }
+
+    /// Derives a key from a passphrase
+    ///
+    /// # Arguments
+    ///
+    /// * `passphrase` - Passphrase shared by the device and the controller
+    ///
+    /// # Returns
+    ///
+    /// * `OtaKey` - The derived key, equal for equal passphrases
+    pub fn from_passphrase(passphrase: &str) -> Self {
+        let mut hasher = SipHasher128::new_with_key(&DERIVATION_KEY);
+        hasher.write(passphrase.as_bytes());
+        Self(hasher.finish128().as_bytes())
+    }
+
+    /// Computes the message authentication code of `data`
+    pub fn mac(&self, data: &[u8]) -> [u8; OTA_MAC_SIZE] {
+        let mut hasher = SipHasher24::new_with_key(&self.0);
+        hasher.write(data);
+        hasher.finish().to_le_bytes()
+    }
+
+    /// Verifies the message authentication code of `data`
+    ///
+    /// The comparison takes the same time regardless of where the codes differ.
+    pub fn verify(&self, data: &[u8], mac: &[u8]) -> bool {
+        mac.len() == OTA_MAC_SIZE
+            && self
+                .mac(data)
+                .iter()
+                .zip(mac)
+                .fold(0u8, |difference, (a, b)| difference | (a ^ b))
+                == 0
+    }
+}
+
+impl Debug for OtaKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        // never log the key material
+        write!(f, "OtaKey(..)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::OtaKey;
+
+    #[test]
+    pub fn test_key_derivation() {
+        let key = OtaKey::from_passphrase("lab");
+        assert_eq!(key, OtaKey::from_passphrase("lab"));
+        assert_ne!(key, OtaKey::from_passphrase("lab2"));
+
+        let mac = key.mac(b"packet");
+        assert!(key.verify(b"packet", &mac));
+        assert!(!key.verify(b"packet!", &mac));
+        assert!(!key.verify(b"packet", &mac[1..]));
+        assert!(!OtaKey::from_passphrase("lab2").verify(b"packet", &mac));
+    }
+}
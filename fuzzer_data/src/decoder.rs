@@ -4,10 +4,17 @@
 //! iced-x86 decoder. It maintains a mapping between instruction addresses and
 //! their decoded forms. This allows mapping bytes to decoded instruction stream.
 //! Further, the same memory allocation is reused accross different calls to the decoder.
+//! Optionally, decode summaries and detailed decodes are cached by sample fingerprint.
+//! Code can also be disassembled to a human readable listing.
 
-use alloc::collections::BTreeMap;
+use crate::genetic_pool::SampleProvenance;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
 use alloc::vec::Vec;
-use iced_x86::{Decoder, DecoderOptions};
+use core::fmt::Write;
+use iced_x86::{
+    Decoder, DecoderOptions, FlowControl, Formatter, Instruction, Mnemonic, NasmFormatter, OpKind,
+};
 
 /// A decoded instruction with its raw bytes
 pub struct InstructionWithBytes<'a> {
@@ -17,6 +24,178 @@ pub struct InstructionWithBytes<'a> {
     pub bytes: &'a [u8],
 }
 
+/// Summary of a decoded instruction sequence
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DecodeSummary {
+    /// Number of decoded instructions
+    pub instruction_count: usize,
+    /// Number of distinct mnemonics among the decoded instructions
+    pub diversity: usize,
+}
+
+/// Coarse class of an instruction, used to shape the rating of samples
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum InstructionCategory {
+    /// Bytes that do not decode to a valid instruction
+    Invalid,
+    /// Serializing or fencing instruction, e.g. `cpuid` or `lfence`
+    Serializing,
+    /// Instruction changing the control flow, e.g. jumps, calls and interrupts
+    Branch,
+    /// Instruction operating on MMX, vector or tile registers
+    Simd,
+    /// Instruction with an explicit memory operand
+    Memory,
+    /// Any other instruction
+    Other,
+}
+
+impl InstructionCategory {
+    /// Number of categories
+    pub const COUNT: usize = 6;
+
+    /// Mnemonics of serializing and fencing instructions
+    const SERIALIZING: [Mnemonic; 10] = [
+        Mnemonic::Cpuid,
+        Mnemonic::Serialize,
+        Mnemonic::Lfence,
+        Mnemonic::Mfence,
+        Mnemonic::Sfence,
+        Mnemonic::Invd,
+        Mnemonic::Wbinvd,
+        Mnemonic::Wrmsr,
+        Mnemonic::Xsetbv,
+        Mnemonic::Invlpg,
+    ];
+
+    /// Classifies a decoded instruction
+    ///
+    /// If an instruction falls into multiple categories, the first matching category in
+    /// declaration order is chosen.
+    ///
+    /// # Arguments
+    ///
+    /// * `instruction` - Decoded instruction
+    ///
+    /// # Returns
+    ///
+    /// * `InstructionCategory` - Category of the instruction
+    pub fn of(instruction: &Instruction) -> Self {
+        if instruction.is_invalid() {
+            return InstructionCategory::Invalid;
+        }
+        if Self::SERIALIZING.contains(&instruction.mnemonic()) {
+            return InstructionCategory::Serializing;
+        }
+        if instruction.flow_control() != FlowControl::Next {
+            return InstructionCategory::Branch;
+        }
+
+        let mut simd = false;
+        let mut memory = false;
+        for operand in 0..instruction.op_count() {
+            match instruction.op_kind(operand) {
+                OpKind::Register => {
+                    let register = instruction.op_register(operand);
+                    simd |= register.is_vector_register() || register.is_mm();
+                }
+                OpKind::Memory
+                | OpKind::MemorySegSI
+                | OpKind::MemorySegESI
+                | OpKind::MemorySegRSI
+                | OpKind::MemorySegDI
+                | OpKind::MemorySegEDI
+                | OpKind::MemorySegRDI
+                | OpKind::MemoryESDI
+                | OpKind::MemoryESEDI
+                | OpKind::MemoryESRDI => memory = true,
+                _ => {}
+            }
+        }
+
+        if simd {
+            InstructionCategory::Simd
+        } else if memory {
+            InstructionCategory::Memory
+        } else {
+            InstructionCategory::Other
+        }
+    }
+}
+
+/// Boundaries and category of a decoded instruction
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DecodedInstruction {
+    /// Offset of the first byte of the instruction within the decoded code
+    pub offset: usize,
+    /// Length of the instruction in bytes
+    pub length: usize,
+    /// Category of the instruction
+    pub category: InstructionCategory,
+}
+
+impl DecodedInstruction {
+    /// Get the bytes of the instruction
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The code this instruction was decoded from
+    pub fn bytes<'a>(&self, code: &'a [u8]) -> &'a [u8] {
+        &code[self.offset..self.offset + self.length]
+    }
+}
+
+/// Bounded least-recently-used cache keyed on sample fingerprint
+struct DecodeCache<K, V> {
+    /// Maximum number of cached entries
+    capacity: usize,
+    /// Map from key to value and last use
+    entries: BTreeMap<K, (V, u64)>,
+    /// Map from last use to key, oldest first
+    usage: BTreeMap<u64, K>,
+    /// Monotonic use counter
+    tick: u64,
+    /// Number of cache hits
+    hits: u64,
+}
+
+impl<K: Ord + Copy, V: Clone> DecodeCache<K, V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: BTreeMap::new(),
+            usage: BTreeMap::new(),
+            tick: 0,
+            hits: 0,
+        }
+    }
+
+    fn get(&mut self, fingerprint: K) -> Option<V> {
+        self.tick += 1;
+        let (value, last_use) = self.entries.get_mut(&fingerprint)?;
+        self.usage.remove(last_use);
+        self.usage.insert(self.tick, fingerprint);
+        *last_use = self.tick;
+        self.hits += 1;
+        Some(value.clone())
+    }
+
+    fn insert(&mut self, fingerprint: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        while self.entries.len() >= self.capacity {
+            let Some((_, oldest)) = self.usage.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+        self.tick += 1;
+        self.usage.insert(self.tick, fingerprint);
+        self.entries.insert(fingerprint, (value, self.tick));
+    }
+}
+
 /// State for decoding instructions
 #[derive(Default)]
 pub struct InstructionDecoder {
@@ -24,11 +203,18 @@ pub struct InstructionDecoder {
     buffer: Vec<InstructionWithBytes<'static>>,
     /// Map from instruction address to buffer index
     instruction_map: BTreeMap<usize, usize>,
+    /// Optional cache of decode summaries
+    cache: Option<DecodeCache<u64, DecodeSummary>>,
+    /// Optional cache of detailed decodes, keyed on fingerprint and instruction pointer
+    detailed_cache: Option<DecodeCache<(u64, u64), Vec<DecodedInstruction>>>,
 }
 
 impl Clone for InstructionDecoder {
     fn clone(&self) -> Self {
-        InstructionDecoder::default()
+        match &self.cache {
+            Some(cache) => InstructionDecoder::new().with_cache(cache.capacity),
+            None => InstructionDecoder::default(),
+        }
     }
 }
 
@@ -71,9 +257,110 @@ impl InstructionDecoder {
         Self {
             buffer: Vec::default(),
             instruction_map: BTreeMap::default(),
+            cache: None,
+            detailed_cache: None,
         }
     }
 
+    /// Enable caching of decode summaries and detailed decodes, holding at most `capacity`
+    /// entries each
+    pub fn with_cache(mut self, capacity: usize) -> Self {
+        self.cache = Some(DecodeCache::new(capacity));
+        self.detailed_cache = Some(DecodeCache::new(capacity));
+        self
+    }
+
+    /// Get the number of summaries and detailed decodes served from the cache
+    pub fn cache_hits(&self) -> u64 {
+        self.cache.as_ref().map_or(0, |cache| cache.hits)
+            + self.detailed_cache.as_ref().map_or(0, |cache| cache.hits)
+    }
+
+    /// Decode a sequence of instructions into their boundaries and categories
+    ///
+    /// If caching is enabled, the result is memoized on the fingerprint of the instructions
+    /// and the instruction pointer.
+    ///
+    /// # Arguments
+    ///
+    /// * `instructions` - The code to decode
+    /// * `ip` - Address of the first byte of the code
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<DecodedInstruction>` - Decoded instructions in order of their offset
+    pub fn decode_detailed(&mut self, instructions: &[u8], ip: u64) -> Vec<DecodedInstruction> {
+        let key = self
+            .detailed_cache
+            .as_ref()
+            .map(|_| (SampleProvenance::fingerprint(instructions), ip));
+
+        if let (Some(cache), Some(key)) = (self.detailed_cache.as_mut(), key) {
+            if let Some(decoded) = cache.get(key) {
+                return decoded;
+            }
+        }
+
+        let mut decoder = Decoder::with_ip(64, instructions, ip, DecoderOptions::NONE);
+        let mut instruction = Instruction::default();
+        let mut decoded = Vec::new();
+        while decoder.can_decode() {
+            let offset = decoder.position();
+            decoder.decode_out(&mut instruction);
+            decoded.push(DecodedInstruction {
+                offset,
+                length: instruction.len(),
+                category: InstructionCategory::of(&instruction),
+            });
+        }
+
+        if let (Some(cache), Some(key)) = (self.detailed_cache.as_mut(), key) {
+            cache.insert(key, decoded.clone());
+        }
+
+        decoded
+    }
+
+    /// Decode a sequence of instructions and summarize the result
+    ///
+    /// If caching is enabled, the summary is memoized on the fingerprint of the instructions.
+    pub fn summary(&mut self, instructions: &[u8]) -> DecodeSummary {
+        let fingerprint = self
+            .cache
+            .as_ref()
+            .map(|_| SampleProvenance::fingerprint(instructions));
+
+        if let (Some(cache), Some(fingerprint)) = (self.cache.as_mut(), fingerprint) {
+            if let Some(summary) = cache.get(fingerprint) {
+                return summary;
+            }
+        }
+
+        let summary = {
+            let result = self.decode(instructions, 0);
+            let mut mnemonics = BTreeSet::new();
+            for index in 0..result.len() {
+                mnemonics.insert(
+                    result
+                        .get(index)
+                        .expect("index is valid")
+                        .instruction
+                        .mnemonic(),
+                );
+            }
+            DecodeSummary {
+                instruction_count: result.len(),
+                diversity: mnemonics.len(),
+            }
+        };
+
+        if let (Some(cache), Some(fingerprint)) = (self.cache.as_mut(), fingerprint) {
+            cache.insert(fingerprint, summary);
+        }
+
+        summary
+    }
+
     /// Decode a sequence of instructions
     pub fn decode<'output, 'this: 'output, 'instructions: 'output>(
         &'this mut self,
@@ -108,3 +395,185 @@ impl InstructionDecoder {
         InstructionDecodeResult { decoder: self }
     }
 }
+
+/// Disassembles x86-64 code to a listing with instruction addresses and bytes
+///
+/// The code is decoded at address zero. If an instruction pointer is highlighted, every line is
+/// indented and the instruction containing it is marked with an arrow, also if the instruction
+/// pointer points into the middle of the instruction. An instruction pointer outside the code is
+/// noted after the listing.
+///
+/// # Arguments
+///
+/// * `code` - A slice of bytes containing the x86-64 machine code to disassemble
+/// * `highlight_rip` - Optional instruction pointer to mark, relative to the start of the code
+///
+/// # Returns
+///
+/// * `String` - The listing, one instruction per line
+pub fn disassemble_code_to_string(code: &[u8], highlight_rip: Option<u64>) -> String {
+    let mut decoder = Decoder::with_ip(64, code, 0, DecoderOptions::NONE);
+    let mut formatter = NasmFormatter::new();
+
+    formatter.options_mut().set_digit_separator("`");
+    formatter.options_mut().set_first_operand_char_index(10);
+    formatter.options_mut().set_show_useless_prefixes(true);
+
+    let mut listing = String::new();
+    let mut output = String::new();
+    let mut instruction = Instruction::default();
+    let mut highlighted = false;
+
+    while decoder.can_decode() {
+        decoder.decode_out(&mut instruction);
+        output.clear();
+        formatter.format(&instruction, &mut output);
+
+        if let Some(rip) = highlight_rip {
+            let contains_rip = (instruction.ip()..instruction.next_ip()).contains(&rip);
+            highlighted |= contains_rip;
+            listing.push_str(if contains_rip { "-> " } else { "   " });
+        }
+
+        // Eg. "00007FFAC46ACDB2 488DAC2400FFFFFF     lea       rbp,[rsp-100h]"
+        let _ = write!(listing, "{:016X} ", instruction.ip());
+        let start_index = instruction.ip() as usize;
+        let instr_bytes = &code[start_index..start_index + instruction.len()];
+        for b in instr_bytes.iter() {
+            let _ = write!(listing, "{:02X}", b);
+        }
+        for _ in instr_bytes.len()..10 {
+            listing.push_str("  ");
+        }
+        let _ = writeln!(listing, " {}", output);
+    }
+
+    if let Some(rip) = highlight_rip.filter(|_| !highlighted) {
+        let _ = writeln!(listing, "-> {:016X} is outside of the code", rip);
+    }
+
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decoder::{
+        disassemble_code_to_string, DecodeSummary, DecodedInstruction, InstructionCategory,
+        InstructionDecoder,
+    };
+    use alloc::format;
+    use alloc::vec::Vec;
+
+    #[test]
+    pub fn test_decode_cache() {
+        let mut decoder = InstructionDecoder::new().with_cache(2);
+        let code = [0x90, 0x90, 0x0f, 0xa2]; // nop; nop; cpuid
+
+        let expected = DecodeSummary {
+            instruction_count: 3,
+            diversity: 2,
+        };
+        assert_eq!(decoder.summary(&code), expected);
+        assert_eq!(decoder.cache_hits(), 0);
+        assert_eq!(decoder.summary(&code), expected);
+        assert_eq!(decoder.cache_hits(), 1);
+
+        // least recently used entry is evicted
+        decoder.summary(&[0x90]);
+        decoder.summary(&code);
+        decoder.summary(&[0x0f, 0xa2]);
+        assert_eq!(decoder.cache_hits(), 2);
+        decoder.summary(&[0x90]);
+        assert_eq!(decoder.cache_hits(), 2);
+        decoder.summary(&code);
+        assert_eq!(decoder.cache_hits(), 2);
+
+        let mut uncached = InstructionDecoder::new();
+        assert_eq!(uncached.summary(&code), expected);
+        assert_eq!(uncached.summary(&code), expected);
+        assert_eq!(uncached.cache_hits(), 0);
+    }
+
+    #[test]
+    pub fn test_decode_detailed() {
+        let code = [
+            0x0F, 0xA2, // cpuid
+            0x48, 0x8B, 0x03, // mov rax, [rbx]
+            0x66, 0x0F, 0xEF, 0xC0, // pxor xmm0, xmm0
+            0x74, 0x00, // je $+2
+            0xF3, 0xA4, // rep movsb
+            0x90, // nop
+            0x06, // push es, invalid in 64-bit mode
+        ];
+        let expected = [
+            (0, 2, InstructionCategory::Serializing),
+            (2, 3, InstructionCategory::Memory),
+            (5, 4, InstructionCategory::Simd),
+            (9, 2, InstructionCategory::Branch),
+            (11, 2, InstructionCategory::Memory),
+            (13, 1, InstructionCategory::Other),
+            (14, 1, InstructionCategory::Invalid),
+        ]
+        .map(|(offset, length, category)| DecodedInstruction {
+            offset,
+            length,
+            category,
+        });
+
+        let mut decoder = InstructionDecoder::new().with_cache(2);
+        let decoded = decoder.decode_detailed(&code, 0x1000);
+        assert_eq!(decoded, expected);
+        assert_eq!(decoded[2].bytes(&code), &[0x66, 0x0F, 0xEF, 0xC0]);
+        assert_eq!(decoder.cache_hits(), 0);
+
+        assert_eq!(decoder.decode_detailed(&code, 0x1000), expected);
+        assert_eq!(decoder.cache_hits(), 1);
+        assert_eq!(decoder.decode_detailed(&code, 0), expected);
+        assert_eq!(decoder.cache_hits(), 1);
+
+        // boundaries agree with the full decoder
+        let offsets = decoded
+            .iter()
+            .map(|instruction| instruction.offset)
+            .collect::<Vec<_>>();
+        let mut full_decoder = InstructionDecoder::new();
+        let result = full_decoder.decode(&code, 0);
+        assert_eq!(result.len(), offsets.len());
+        for (index, offset) in offsets.into_iter().enumerate() {
+            assert_eq!(result.get(index).unwrap().instruction.ip(), offset as u64);
+        }
+    }
+
+    #[test]
+    pub fn test_disassemble_code_to_string() {
+        let code = [0x90, 0x48, 0x8B, 0x03, 0x0F, 0xA2]; // nop; mov rax, [rbx]; cpuid
+
+        let listing = disassemble_code_to_string(&code, None);
+        let lines = listing.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("0000000000000001 488B03"));
+        assert!(lines[1].ends_with("rax,[rbx]"));
+
+        // instruction start and an address within the instruction are marked alike
+        for rip in [1, 3] {
+            let listing = disassemble_code_to_string(&code, Some(rip));
+            let marked = listing
+                .lines()
+                .filter(|line| line.starts_with("->"))
+                .collect::<Vec<_>>();
+            assert_eq!(marked, [format!("-> {}", lines[1])]);
+            assert!(listing.contains(&format!("   {}", lines[0])));
+        }
+
+        let listing = disassemble_code_to_string(&code, Some(0x1000));
+        assert!(listing.lines().take(3).all(|line| line.starts_with("   ")));
+        assert_eq!(
+            listing.lines().last(),
+            Some("-> 0000000000001000 is outside of the code")
+        );
+
+        // a truncated instruction at the end of the buffer
+        let listing = disassemble_code_to_string(&code[..3], Some(2));
+        assert!(listing.lines().last().unwrap().starts_with("-> "));
+    }
+}
@@ -0,0 +1,811 @@
+//! Fragmentation of large OTA payloads
+//!
+//! Payloads exceeding [`MAX_FRAGMENT_SIZE`] are split into [`Ota::ChunkedTransport`] fragments
+//! sharing the session and message identifier of the payload. The receiver tracks the received
+//! fragments in a [`FragmentAssembler`] and requests only the missing fragments with a
+//! `MissingFragments` message, which the sender answers from its [`FragmentSendBuffer`].
+//!
+//! Timestamps are milliseconds of a monotonic clock of the caller.
+
+use crate::{Ota, MAX_FRAGMENT_SIZE, MAX_PAYLOAD_SIZE};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+
+/// Maximum content size of a single fragment, leaving room for the packet header
+pub const FRAGMENT_CONTENT_SIZE: usize = MAX_FRAGMENT_SIZE as usize - 128;
+/// Maximum number of fragments of a payload of at most [`MAX_PAYLOAD_SIZE`] bytes
+pub const MAX_FRAGMENTS: u64 = MAX_PAYLOAD_SIZE.div_ceil(FRAGMENT_CONTENT_SIZE as u64);
+/// Maximum number of fragments requested by a single `MissingFragments` message
+pub const MAX_MISSING_FRAGMENTS: usize = 256;
+
+/// Errors that can occur when fragmenting or reassembling a payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FragmentError {
+    /// The fragment index is not below the total number of fragments
+    InvalidFragment {
+        /// Index of the fragment
+        fragment: u64,
+        /// Total number of fragments of the payload
+        total_fragments: u64,
+    },
+    /// The payload would exceed [`MAX_PAYLOAD_SIZE`]
+    PayloadTooLarge(u64),
+    /// The fragment disagrees with previously received fragments of the same payload
+    Inconsistent,
+    /// The payload is not (or no longer) buffered by the sender
+    UnknownPayload {
+        /// Session identifier of the payload
+        session: u16,
+        /// Message identifier of the payload
+        id: u64,
+    },
+}
+
+impl Display for FragmentError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FragmentError::InvalidFragment {
+                fragment,
+                total_fragments,
+            } => write!(f, "Invalid fragment {} of {}", fragment, total_fragments),
+            FragmentError::PayloadTooLarge(size) => {
+                write!(f, "Payload of {} bytes is too large", size)
+            }
+            FragmentError::Inconsistent => write!(f, "Fragment disagrees with its payload"),
+            FragmentError::UnknownPayload { session, id } => {
+                write!(f, "Payload {} of session {} is not buffered", id, session)
+            }
+        }
+    }
+}
+
+/// A payload buffered for retransmission
+#[derive(Debug)]
+struct BufferedPayload {
+    /// Whether the payload is compressed
+    compressed: bool,
+    /// Fragment contents
+    chunks: Vec<Vec<u8>>,
+    /// Time the payload was buffered
+    buffered_at: u64,
+}
+
+/// Sender side of chunked transports, buffers sent payloads to serve retransmission requests
+#[derive(Debug)]
+pub struct FragmentSendBuffer {
+    /// Buffered payloads by session and message identifier
+    payloads: BTreeMap<(u16, u64), BufferedPayload>,
+    /// Time a payload is kept after it was buffered
+    retention: u64,
+}
+
+impl FragmentSendBuffer {
+    /// Maximum number of buffered payloads, the oldest payload is dropped first
+    pub const MAX_BUFFERED: usize = 4;
+
+    /// Creates an empty send buffer
+    ///
+    /// # Arguments
+    ///
+    /// * `retention` - Time a payload is kept to serve retransmission requests
+    pub fn new(retention: u64) -> Self {
+        Self {
+            payloads: BTreeMap::new(),
+            retention,
+        }
+    }
+
+    /// Splits a payload into fragments and buffers it
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - Session identifier
+    /// * `id` - Message identifier of the payload
+    /// * `payload` - The encoded payload, see [`crate::encode_payload`]
+    /// * `compressed` - Whether the payload is compressed
+    /// * `now` - Current time
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(..)` - The total number of fragments
+    /// - `Err(FragmentError::PayloadTooLarge)` - The payload exceeds [`MAX_PAYLOAD_SIZE`]
+    pub fn insert(
+        &mut self,
+        session: u16,
+        id: u64,
+        payload: &[u8],
+        compressed: bool,
+        now: u64,
+    ) -> Result<u64, FragmentError> {
+        if payload.len() as u64 > MAX_PAYLOAD_SIZE {
+            return Err(FragmentError::PayloadTooLarge(payload.len() as u64));
+        }
+
+        self.expire(now);
+        while self.payloads.len() >= Self::MAX_BUFFERED {
+            let oldest = self
+                .payloads
+                .iter()
+                .min_by_key(|(_, payload)| payload.buffered_at)
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                self.payloads.remove(&oldest);
+            }
+        }
+
+        let chunks: Vec<Vec<u8>> = payload
+            .chunks(FRAGMENT_CONTENT_SIZE)
+            .map(|chunk| chunk.to_vec())
+            .collect();
+        let total_fragments = chunks.len() as u64;
+        self.payloads.insert(
+            (session, id),
+            BufferedPayload {
+                compressed,
+                chunks,
+                buffered_at: now,
+            },
+        );
+        Ok(total_fragments)
+    }
+
+    /// Builds the fragments of a buffered payload, e.g. to answer a retransmission request
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - Session identifier
+    /// * `id` - Message identifier of the payload
+    /// * `fragments` - Indices of the fragments
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(..)` - The fragments in the requested order
+    /// - `Err(FragmentError::UnknownPayload)` - The payload expired or was never buffered
+    /// - `Err(FragmentError::InvalidFragment)` - A fragment index is out of range
+    pub fn fragments<U, T>(
+        &self,
+        session: u16,
+        id: u64,
+        fragments: &[u64],
+    ) -> Result<Vec<Ota<U, T>>, FragmentError> {
+        let payload = self
+            .payloads
+            .get(&(session, id))
+            .ok_or(FragmentError::UnknownPayload { session, id })?;
+        let total_fragments = payload.chunks.len() as u64;
+
+        fragments
+            .iter()
+            .map(|&fragment| {
+                let content = payload.chunks.get(fragment as usize).ok_or(
+                    FragmentError::InvalidFragment {
+                        fragment,
+                        total_fragments,
+                    },
+                )?;
+                Ok(Ota::ChunkedTransport {
+                    session,
+                    id,
+                    fragment,
+                    total_fragments,
+                    compressed: payload.compressed,
+                    content: content.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Removes a payload, e.g. once it was acknowledged
+    pub fn remove(&mut self, session: u16, id: u64) {
+        self.payloads.remove(&(session, id));
+    }
+
+    /// Drops all payloads buffered longer than the retention time
+    pub fn expire(&mut self, now: u64) {
+        let retention = self.retention;
+        self.payloads
+            .retain(|_, payload| now.saturating_sub(payload.buffered_at) < retention);
+    }
+
+    /// Checks if a payload is buffered
+    pub fn contains(&self, session: u16, id: u64) -> bool {
+        self.payloads.contains_key(&(session, id))
+    }
+}
+
+/// A payload of which not all fragments were received yet
+#[derive(Debug)]
+struct PendingPayload {
+    /// Whether the payload is compressed
+    compressed: bool,
+    /// Received fragment contents
+    fragments: Vec<Option<Vec<u8>>>,
+    /// Number of received fragments
+    received: u64,
+    /// Time of the last received fragment or retransmission request
+    last_activity: u64,
+    /// Retransmission requests sent without receiving a new fragment
+    requests: u8,
+}
+
+impl PendingPayload {
+    /// Returns the indices of the missing fragments, at most [`MAX_MISSING_FRAGMENTS`]
+    fn missing(&self) -> Vec<u64> {
+        self.fragments
+            .iter()
+            .enumerate()
+            .filter(|(_, content)| content.is_none())
+            .map(|(index, _)| index as u64)
+            .take(MAX_MISSING_FRAGMENTS)
+            .collect()
+    }
+}
+
+/// Result of inserting a fragment into a [`FragmentAssembler`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FragmentStatus {
+    /// The payload is still missing fragments
+    Incomplete,
+    /// The fragment was already received
+    Duplicate {
+        /// Whether the payload was already completed, the sender may have missed its acknowledgement
+        completed: bool,
+    },
+    /// All fragments of the payload were received
+    Complete {
+        /// The reassembled payload
+        payload: Vec<u8>,
+        /// Whether the payload is compressed
+        compressed: bool,
+    },
+}
+
+/// Action required for a stalled payload, see [`FragmentAssembler::poll`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FragmentPoll {
+    /// Request the retransmission of the missing fragments
+    Missing {
+        /// Session identifier of the payload
+        session: u16,
+        /// Message identifier of the payload
+        id: u64,
+        /// Indices of the missing fragments
+        fragments: Vec<u64>,
+    },
+    /// The payload did not progress after all retransmission requests and was discarded
+    Expired {
+        /// Session identifier of the payload
+        session: u16,
+        /// Message identifier of the payload
+        id: u64,
+    },
+}
+
+/// Receiver side of chunked transports, tracks the received fragments of each payload
+#[derive(Debug)]
+pub struct FragmentAssembler {
+    /// Incomplete payloads by session and message identifier
+    pending: BTreeMap<(u16, u64), PendingPayload>,
+    /// Recently completed payloads, to recognize late duplicates
+    completed: VecDeque<(u16, u64)>,
+    /// Time without a new fragment after which missing fragments are requested
+    timeout: u64,
+    /// Number of retransmission requests before a payload is discarded
+    max_requests: u8,
+}
+
+impl FragmentAssembler {
+    /// Maximum number of incomplete payloads, the least recently active payload is dropped first
+    pub const MAX_PENDING: usize = 4;
+    /// Number of completed payloads remembered to recognize late duplicates
+    const COMPLETED_HISTORY: usize = 16;
+
+    /// Creates an empty assembler
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Time without a new fragment after which missing fragments are requested
+    /// * `max_requests` - Number of retransmission requests before a payload is discarded
+    pub fn new(timeout: u64, max_requests: u8) -> Self {
+        Self {
+            pending: BTreeMap::new(),
+            completed: VecDeque::new(),
+            timeout,
+            max_requests,
+        }
+    }
+
+    /// Inserts a received fragment
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - Session identifier of the payload
+    /// * `id` - Message identifier of the payload
+    /// * `fragment` - Index of the fragment
+    /// * `total_fragments` - Total number of fragments of the payload
+    /// * `compressed` - Whether the payload is compressed
+    /// * `content` - Fragment content
+    /// * `now` - Current time
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(FragmentStatus)` - Status of the payload
+    /// - `Err(FragmentError)` - The fragment is invalid, it was discarded
+    #[allow(clippy::too_many_arguments)]
+    pub fn insert(
+        &mut self,
+        session: u16,
+        id: u64,
+        fragment: u64,
+        total_fragments: u64,
+        compressed: bool,
+        content: Vec<u8>,
+        now: u64,
+    ) -> Result<FragmentStatus, FragmentError> {
+        if fragment >= total_fragments {
+            return Err(FragmentError::InvalidFragment {
+                fragment,
+                total_fragments,
+            });
+        }
+        // the last fragment may be partial, the exact size is checked once the payload is complete
+        if total_fragments > MAX_FRAGMENTS || content.len() > FRAGMENT_CONTENT_SIZE {
+            return Err(FragmentError::PayloadTooLarge(
+                total_fragments.saturating_mul(FRAGMENT_CONTENT_SIZE as u64),
+            ));
+        }
+        if self.completed.contains(&(session, id)) {
+            return Ok(FragmentStatus::Duplicate { completed: true });
+        }
+
+        if !self.pending.contains_key(&(session, id)) {
+            while self.pending.len() >= Self::MAX_PENDING {
+                let oldest = self
+                    .pending
+                    .iter()
+                    .min_by_key(|(_, payload)| payload.last_activity)
+                    .map(|(key, _)| *key);
+                if let Some(oldest) = oldest {
+                    self.pending.remove(&oldest);
+                }
+            }
+            let mut fragments = Vec::new();
+            fragments.resize(total_fragments as usize, None);
+            self.pending.insert(
+                (session, id),
+                PendingPayload {
+                    compressed,
+                    fragments,
+                    received: 0,
+                    last_activity: now,
+                    requests: 0,
+                },
+            );
+        }
+
+        let payload = self
+            .pending
+            .get_mut(&(session, id))
+            .expect("inserted above");
+        if payload.fragments.len() as u64 != total_fragments || payload.compressed != compressed {
+            return Err(FragmentError::Inconsistent);
+        }
+
+        let slot = &mut payload.fragments[fragment as usize];
+        if slot.is_some() {
+            return Ok(FragmentStatus::Duplicate { completed: false });
+        }
+        *slot = Some(content);
+        payload.received += 1;
+        payload.last_activity = now;
+        payload.requests = 0;
+
+        if payload.received < total_fragments {
+            return Ok(FragmentStatus::Incomplete);
+        }
+
+        let payload = self.pending.remove(&(session, id)).expect("checked above");
+        let compressed = payload.compressed;
+        let payload: Vec<u8> = payload.fragments.into_iter().flatten().flatten().collect();
+        if payload.len() as u64 > MAX_PAYLOAD_SIZE {
+            return Err(FragmentError::PayloadTooLarge(payload.len() as u64));
+        }
+        if self.completed.len() >= Self::COMPLETED_HISTORY {
+            self.completed.pop_front();
+        }
+        self.completed.push_back((session, id));
+
+        Ok(FragmentStatus::Complete {
+            payload,
+            compressed,
+        })
+    }
+
+    /// Returns the missing fragments of an incomplete payload, at most [`MAX_MISSING_FRAGMENTS`]
+    pub fn missing(&self, session: u16, id: u64) -> Option<Vec<u64>> {
+        self.pending
+            .get(&(session, id))
+            .map(PendingPayload::missing)
+    }
+
+    /// Checks if any payload is incomplete
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Checks all incomplete payloads for stalls
+    ///
+    /// Payloads without a new fragment for the timeout request their missing fragments. Payloads
+    /// that did not progress after all requests are discarded.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Current time
+    ///
+    /// # Returns
+    ///
+    /// The actions required for the stalled payloads
+    pub fn poll(&mut self, now: u64) -> Vec<FragmentPoll> {
+        let mut actions = Vec::new();
+        let timeout = self.timeout;
+        let max_requests = self.max_requests;
+
+        self.pending.retain(|&(session, id), payload| {
+            if now.saturating_sub(payload.last_activity) < timeout {
+                return true;
+            }
+            if payload.requests >= max_requests {
+                actions.push(FragmentPoll::Expired { session, id });
+                return false;
+            }
+            payload.requests += 1;
+            payload.last_activity = now;
+            actions.push(FragmentPoll::Missing {
+                session,
+                id,
+                fragments: payload.missing(),
+            });
+            true
+        });
+
+        actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::OtaKey;
+    use crate::fragments::{
+        FragmentAssembler, FragmentError, FragmentPoll, FragmentSendBuffer, FragmentStatus,
+        FRAGMENT_CONTENT_SIZE, MAX_FRAGMENTS,
+    };
+    use crate::{
+        encode_payload, Ota, OtaD2C, OtaD2CTransport, MAX_FRAGMENT_SIZE, MAX_PAYLOAD_SIZE,
+    };
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    const TIMEOUT: u64 = 100;
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    /// Delivers fragments to the assembler, dropping those rejected by `lossy`
+    fn deliver<F: FnMut(u64) -> bool>(
+        assembler: &mut FragmentAssembler,
+        fragments: Vec<OtaD2C>,
+        now: u64,
+        mut lossy: F,
+    ) -> Option<FragmentStatus> {
+        let mut last = None;
+        for packet in fragments {
+            if let Ota::ChunkedTransport {
+                session,
+                id,
+                fragment,
+                total_fragments,
+                compressed,
+                content,
+            } = packet
+            {
+                if lossy(fragment) {
+                    continue;
+                }
+                last = Some(
+                    assembler
+                        .insert(
+                            session,
+                            id,
+                            fragment,
+                            total_fragments,
+                            compressed,
+                            content,
+                            now,
+                        )
+                        .unwrap(),
+                );
+            }
+        }
+        last
+    }
+
+    #[test]
+    fn test_fragments_out_of_order_and_duplicates() {
+        let data = payload(3 * FRAGMENT_CONTENT_SIZE + 17);
+        let mut sender = FragmentSendBuffer::new(1000);
+        let total = sender.insert(1, 9, &data, false, 0).unwrap();
+        assert_eq!(total, 4);
+
+        let mut assembler = FragmentAssembler::new(TIMEOUT, 3);
+        let fragments = sender.fragments::<_, _>(1, 9, &[3, 1, 0, 1]).unwrap();
+        let status = deliver(&mut assembler, fragments, 0, |_| false);
+        assert_eq!(status, Some(FragmentStatus::Duplicate { completed: false }));
+        assert_eq!(assembler.missing(1, 9), Some(vec![2]));
+
+        let fragments: Vec<OtaD2C> = sender.fragments(1, 9, &[2]).unwrap();
+        let status = deliver(&mut assembler, fragments, 0, |_| false);
+        assert_eq!(
+            status,
+            Some(FragmentStatus::Complete {
+                payload: data,
+                compressed: false
+            })
+        );
+        assert!(!assembler.is_pending());
+
+        // a late duplicate of a completed payload is recognized
+        let fragments: Vec<OtaD2C> = sender.fragments(1, 9, &[0]).unwrap();
+        let status = deliver(&mut assembler, fragments, 0, |_| false);
+        assert_eq!(status, Some(FragmentStatus::Duplicate { completed: true }));
+        assert!(!assembler.is_pending());
+    }
+
+    #[test]
+    fn test_fragments_multi_megabyte_payload() {
+        // random coverage entries, so that the payload is hardly compressible
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let coverage = (0..500_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state as u16, (state >> 16) as u16)
+            })
+            .collect::<Vec<_>>();
+        let packet = OtaD2C::Transport {
+            session: u16::MAX,
+            id: u64::MAX,
+            content: OtaD2CTransport::Coverage {
+                coverage: coverage.clone(),
+            },
+        };
+        let data = packet.serialize().unwrap();
+        assert!(data.len() > 2_000_000);
+
+        let (payload, compressed) = encode_payload(&data);
+        let mut sender = FragmentSendBuffer::new(1000);
+        let total = sender
+            .insert(u16::MAX, u64::MAX, &payload, compressed, 0)
+            .unwrap();
+        assert_eq!(total, payload.len().div_ceil(FRAGMENT_CONTENT_SIZE) as u64);
+
+        let all_fragments = (0..total).collect::<Vec<u64>>();
+        let fragments: Vec<OtaD2C> = sender
+            .fragments(u16::MAX, u64::MAX, &all_fragments)
+            .unwrap();
+        assert_eq!(fragments.len() as u64, total);
+
+        // every fragment fits into a packet, even with the largest identifiers and a trailer
+        let key = OtaKey::from_passphrase("fragments");
+        for fragment in fragments.iter() {
+            let serialized = fragment.serialize_with_key(Some(&key)).unwrap();
+            assert!(serialized.len() as u64 <= MAX_FRAGMENT_SIZE);
+        }
+
+        let mut assembler = FragmentAssembler::new(TIMEOUT, 3);
+        match deliver(&mut assembler, fragments, 0, |_| false) {
+            Some(FragmentStatus::Complete {
+                payload,
+                compressed,
+            }) => match OtaD2C::deserialize_payload(&payload, compressed).unwrap() {
+                Ota::Transport {
+                    session: u16::MAX,
+                    id: u64::MAX,
+                    content: OtaD2CTransport::Coverage { coverage: received },
+                } => assert_eq!(received, coverage),
+                x => panic!("Unexpected packet: {:?}", x),
+            },
+            x => panic!("Unexpected status: {:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_fragments_lossy_channel() {
+        let data = payload(40 * FRAGMENT_CONTENT_SIZE);
+        let mut sender = FragmentSendBuffer::new(10 * TIMEOUT);
+        let total = sender.insert(2, 100, &data, true, 0).unwrap();
+        let mut assembler = FragmentAssembler::new(TIMEOUT, 3);
+
+        // every third fragment is lost on the first transmission
+        let all: Vec<u64> = (0..total).collect();
+        let fragments: Vec<OtaD2C> = sender.fragments(2, 100, &all).unwrap();
+        let status = deliver(&mut assembler, fragments, 0, |f| f % 3 == 0);
+        assert_eq!(status, Some(FragmentStatus::Incomplete));
+
+        // no request before the timeout
+        assert_eq!(assembler.poll(TIMEOUT - 1), vec![]);
+
+        let mut now = TIMEOUT;
+        let mut retransmitted = 0;
+        let mut rounds = 0;
+        let result = loop {
+            let requested = match assembler.poll(now).as_slice() {
+                [FragmentPoll::Missing {
+                    session: 2,
+                    id: 100,
+                    fragments,
+                }] => fragments.clone(),
+                x => panic!("Unexpected poll: {:?}", x),
+            };
+            rounds += 1;
+            // only the missing fragments are retransmitted, half of them are lost again
+            assert!(requested.iter().all(|f| f % 3 == 0));
+            retransmitted += requested.len();
+            let fragments: Vec<OtaD2C> = sender.fragments(2, 100, &requested).unwrap();
+            let mut lost = false;
+            match deliver(&mut assembler, fragments, now, |_| {
+                lost = !lost;
+                rounds == 1 && lost
+            }) {
+                Some(FragmentStatus::Complete {
+                    payload,
+                    compressed,
+                }) => break (payload, compressed),
+                _ => now += TIMEOUT,
+            }
+        };
+
+        assert_eq!(result, (data, true));
+        assert_eq!(rounds, 2);
+        assert_eq!(retransmitted, 14 + 7);
+    }
+
+    #[test]
+    fn test_fragments_expire_without_progress() {
+        let data = payload(2 * FRAGMENT_CONTENT_SIZE);
+        let mut sender = FragmentSendBuffer::new(1000);
+        sender.insert(3, 1, &data, false, 0).unwrap();
+        let mut assembler = FragmentAssembler::new(TIMEOUT, 2);
+
+        let fragments: Vec<OtaD2C> = sender.fragments(3, 1, &[0]).unwrap();
+        deliver(&mut assembler, fragments, 0, |_| false);
+
+        // all requests are lost
+        for request in 1..=2 {
+            let actions = assembler.poll(request * TIMEOUT);
+            assert_eq!(
+                actions,
+                vec![FragmentPoll::Missing {
+                    session: 3,
+                    id: 1,
+                    fragments: vec![1]
+                }]
+            );
+        }
+        assert_eq!(
+            assembler.poll(3 * TIMEOUT),
+            vec![FragmentPoll::Expired { session: 3, id: 1 }]
+        );
+        assert!(!assembler.is_pending());
+    }
+
+    #[test]
+    fn test_fragments_request_after_buffer_expired() {
+        let data = payload(2 * FRAGMENT_CONTENT_SIZE);
+        let mut sender = FragmentSendBuffer::new(500);
+        sender.insert(4, 2, &data, false, 0).unwrap();
+        assert!(sender.contains(4, 2));
+
+        sender.expire(499);
+        assert!(sender.fragments::<(), ()>(4, 2, &[1]).is_ok());
+        assert_eq!(
+            sender.fragments::<(), ()>(4, 2, &[2]).unwrap_err(),
+            FragmentError::InvalidFragment {
+                fragment: 2,
+                total_fragments: 2
+            }
+        );
+
+        sender.expire(500);
+        assert_eq!(
+            sender.fragments::<(), ()>(4, 2, &[1]).unwrap_err(),
+            FragmentError::UnknownPayload { session: 4, id: 2 }
+        );
+    }
+
+    #[test]
+    fn test_fragments_invalid() {
+        let mut assembler = FragmentAssembler::new(TIMEOUT, 3);
+        assert!(matches!(
+            assembler.insert(1, 1, 2, 2, false, vec![0], 0),
+            Err(FragmentError::InvalidFragment { .. })
+        ));
+        assert!(matches!(
+            assembler.insert(1, 1, 0, u64::MAX, false, vec![0], 0),
+            Err(FragmentError::PayloadTooLarge(_))
+        ));
+        assert_eq!(
+            assembler.insert(1, 1, 0, 2, false, vec![0], 0),
+            Ok(FragmentStatus::Incomplete)
+        );
+        assert_eq!(
+            assembler.insert(1, 1, 1, 3, false, vec![0], 0),
+            Err(FragmentError::Inconsistent)
+        );
+
+        let mut sender = FragmentSendBuffer::new(1000);
+        assert!(matches!(
+            sender.insert(1, 1, &vec![0; 4_000_001], false, 0),
+            Err(FragmentError::PayloadTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn test_fragments_max_payload_size() {
+        // the last fragment is partial, the fragments could hold more than the maximum size
+        let data = payload(MAX_PAYLOAD_SIZE as usize);
+        let mut sender = FragmentSendBuffer::new(1000);
+        let total = sender.insert(1, 1, &data, false, 0).unwrap();
+        assert_eq!(total, MAX_FRAGMENTS);
+        assert!(total * FRAGMENT_CONTENT_SIZE as u64 > MAX_PAYLOAD_SIZE);
+
+        let all_fragments = (0..total).collect::<Vec<u64>>();
+        let fragments: Vec<OtaD2C> = sender.fragments(1, 1, &all_fragments).unwrap();
+        let mut assembler = FragmentAssembler::new(TIMEOUT, 3);
+        assert_eq!(
+            deliver(&mut assembler, fragments, 0, |_| false),
+            Some(FragmentStatus::Complete {
+                payload: data,
+                compressed: false
+            })
+        );
+
+        // full fragments beyond the maximum size are rejected once the payload is complete
+        let mut assembler = FragmentAssembler::new(TIMEOUT, 3);
+        for fragment in 0..MAX_FRAGMENTS - 1 {
+            assert_eq!(
+                assembler.insert(
+                    2,
+                    1,
+                    fragment,
+                    MAX_FRAGMENTS,
+                    false,
+                    vec![0; FRAGMENT_CONTENT_SIZE],
+                    0
+                ),
+                Ok(FragmentStatus::Incomplete)
+            );
+        }
+        assert_eq!(
+            assembler.insert(
+                2,
+                1,
+                MAX_FRAGMENTS - 1,
+                MAX_FRAGMENTS,
+                false,
+                vec![0; FRAGMENT_CONTENT_SIZE],
+                0
+            ),
+            Err(FragmentError::PayloadTooLarge(
+                MAX_FRAGMENTS * FRAGMENT_CONTENT_SIZE as u64
+            ))
+        );
+        assert!(!assembler.is_pending());
+        assert!(matches!(
+            assembler.insert(2, 1, 0, MAX_FRAGMENTS + 1, false, vec![0], 0),
+            Err(FragmentError::PayloadTooLarge(_))
+        ));
+    }
+}
@@ -5,27 +5,209 @@
 //!
 //! You should maybe consider to use AFL as mutator/input generator instead
 
-use crate::instruction_corpus::CorpusInstruction;
+use crate::instruction_corpus::InstructionCorpus;
+use crate::MAX_PAYLOAD_SIZE;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::cmp::Ordering;
+use core::fmt::{Display, Formatter};
 use log::warn;
 use rand::prelude::SliceRandom;
 use rand_core::RngCore;
 use serde::{Deserialize, Serialize};
 
+/// Strategy to select the parents of the next generation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum SelectionStrategy {
+    /// Parents are drawn uniformly from the best `keep_best_x_solutions` samples
+    /// and the random samples of the generation
+    #[default]
+    Truncation,
+    /// Each parent is the best of `size` samples drawn uniformly from the whole population
+    Tournament {
+        /// Number of samples competing in each tournament
+        size: usize,
+    },
+}
+
+/// Mode to compare the ratings of samples
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum RatingMode {
+    /// Ratings are ordered by their metrics in turn, see [`GeneticSampleRating::cmp`]
+    #[default]
+    Lexicographic,
+    /// Ratings are ordered by Pareto dominance of their metrics, see
+    /// [`GeneticSampleRating::dominates`]; samples of the same front are ordered by
+    /// crowding distance, preferring samples in sparsely populated regions
+    Pareto,
+}
+
+/// Escalation of the mutation rate while the fuzzer does not find new coverage
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AdaptiveMutationSettings {
+    /// Iterations without new coverage until the mutation rate is raised by one level
+    pub stagnation_threshold: u64,
+    /// Maximum number of mutations per sample, reached at the highest level
+    pub max_mutations: u32,
+    /// Probability of structural mutations at the highest level (0.0 to 1.0)
+    pub max_structural_mutation_chance: f64,
+}
+
+impl Default for AdaptiveMutationSettings {
+    fn default() -> Self {
+        Self {
+            stagnation_threshold: 500,
+            max_mutations: 8,
+            max_structural_mutation_chance: 0.5,
+        }
+    }
+}
+
+/// Mutation strategy applied by a single mutation of a child
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MutationStrategy {
+    /// Overwrites, inserts or deletes random bytes
+    Havoc,
+    /// Inserts a whole instruction drawn from a dictionary at an instruction boundary
+    Dictionary,
+    /// Joins a prefix of the child and a suffix of the other parent at instruction boundaries
+    Splice,
+}
+
+/// Relative weights of the mutation strategies
+///
+/// Only havoc mutations are applied by default. The other strategies require an
+/// [`InstructionMutator`], see [`GeneticPool::evolution_with_mutator`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MutationWeights {
+    /// Weight of [`MutationStrategy::Havoc`]
+    pub havoc: u32,
+    /// Weight of [`MutationStrategy::Dictionary`]
+    pub dictionary: u32,
+    /// Weight of [`MutationStrategy::Splice`]
+    pub splice: u32,
+}
+
+impl Default for MutationWeights {
+    fn default() -> Self {
+        Self {
+            havoc: 1,
+            dictionary: 0,
+            splice: 0,
+        }
+    }
+}
+
+impl MutationWeights {
+    /// Chooses a mutation strategy according to the weights
+    ///
+    /// No randomness is consumed if havoc is the only strategy with a weight, so that
+    /// evolutions with the default weights are reproducible across versions.
+    ///
+    /// # Arguments
+    ///
+    /// * `random` - Random source for the choice
+    ///
+    /// # Returns
+    ///
+    /// * `MutationStrategy` - The chosen strategy, havoc if all weights are zero
+    pub fn choose<R: RngCore>(&self, random: &mut R) -> MutationStrategy {
+        let total = self.havoc as u64 + self.dictionary as u64 + self.splice as u64;
+        if total == self.havoc as u64 {
+            return MutationStrategy::Havoc;
+        }
+
+        let target = random.next_u64() % total;
+        if target < self.dictionary as u64 {
+            MutationStrategy::Dictionary
+        } else if target < self.dictionary as u64 + self.splice as u64 {
+            MutationStrategy::Splice
+        } else {
+            MutationStrategy::Havoc
+        }
+    }
+}
+
+/// Instruction aware mutations, provided by the side executing the evolution
+pub trait InstructionMutator<R: RngCore> {
+    /// Applies a mutation strategy to the code of a child
+    ///
+    /// # Arguments
+    ///
+    /// * `strategy` - Strategy to apply, never [`MutationStrategy::Havoc`]
+    /// * `code` - Code of the child to mutate
+    /// * `other_parent` - Code of the second parent of the child
+    /// * `random` - Random source for the mutation
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - Whether the mutation was applied, a havoc mutation is applied otherwise
+    fn mutate(
+        &mut self,
+        strategy: MutationStrategy,
+        code: &mut Vec<u8>,
+        other_parent: &[u8],
+        random: &mut R,
+    ) -> bool;
+}
+
+/// Mutation rate applied to the children of an evolution
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MutationRate {
+    /// Number of mutation attempts per sample, each applied with the random mutation chance
+    pub mutations: u32,
+    /// Probability that a mutation inserts or deletes bytes instead of overwriting them
+    pub structural_chance: f64,
+}
+
+impl Display for MutationRate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{} mutations, {:.0}% structural",
+            self.mutations,
+            self.structural_chance * 100.0
+        )
+    }
+}
+
+/// Reserve for the packet header surrounding a [`PoolSnapshot`] in an OTA message
+const SNAPSHOT_PACKET_RESERVE: u64 = 1024;
+
 /// Settings for the genetic algorithm pool
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct GeneticPoolSettings {
     /// Size of the population to maintain
     pub population_size: usize,
     /// Size of each code sample in bytes
     pub code_size: usize,
+    /// Minimum size of a code sample in bytes, shorter samples are padded with random bytes
+    pub min_code_size: usize,
+    /// Maximum size of a code sample in bytes, longer samples are truncated
+    pub max_code_size: usize,
     /// Number of random solutions to add each generation
     pub random_solutions_each_generation: usize,
     /// Number of best solutions to keep each generation
     pub keep_best_x_solutions: usize,
     /// Probability of random mutation (0.0 to 1.0)
     pub random_mutation_chance: f64,
+    /// Strategy to select the parents of the next generation
+    pub selection: SelectionStrategy,
+    /// Number of best samples copied unchanged into the next generation
+    ///
+    /// With [`RatingMode::Pareto`] at least the whole non-dominated front survives.
+    pub elitism_count: usize,
+    /// Mode to compare the ratings of samples
+    pub rating_mode: RatingMode,
+    /// Probability that a mutation inserts or deletes bytes instead of overwriting them
+    #[serde(default)]
+    pub structural_mutation_chance: f64,
+    /// Raise the mutation rate while no new coverage is found, disabled if `None`
+    #[serde(default)]
+    pub adaptive_mutation: Option<AdaptiveMutationSettings>,
+    /// Relative weights of the mutation strategies
+    #[serde(default)]
+    pub mutation_weights: MutationWeights,
 }
 
 impl Default for GeneticPoolSettings {
@@ -34,19 +216,49 @@ impl Default for GeneticPoolSettings {
             population_size: 100,
             random_solutions_each_generation: 2,
             code_size: 32,
+            min_code_size: 1,
+            max_code_size: 64,
             keep_best_x_solutions: 10,
             random_mutation_chance: 0.01,
+            selection: SelectionStrategy::Truncation,
+            elitism_count: 10,
+            rating_mode: RatingMode::Lexicographic,
+            structural_mutation_chance: 0.0,
+            adaptive_mutation: None,
+            mutation_weights: MutationWeights::default(),
+        }
+    }
+}
+
+impl GeneticPoolSettings {
+    /// Enforces the configured size bounds on a code sample
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - Code bytes to constrain
+    /// * `random` - Random source used to pad too short samples
+    fn constrain<R: RngCore>(&self, code: &mut Vec<u8>, random: &mut R) {
+        code.truncate(self.max_code_size);
+        while code.len() < self.min_code_size {
+            code.push(random.next_u32() as u8);
         }
     }
 }
 
 /// A pool of samples for fuzzing
-#[derive(Clone, Default)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct GeneticPool {
     /// Current population of samples
     population: Vec<Sample>,
     /// Settings for the genetic algorithm
     settings: GeneticPoolSettings,
+    /// Seed of the random source, used to tag samples with their provenance
+    seed: Option<u64>,
+    /// Current generation of the population
+    generation: u64,
+    /// Iterations without new coverage, drives the adaptive mutation rate
+    #[serde(default)]
+    stagnation: u64,
 }
 
 impl GeneticPool {
@@ -57,37 +269,158 @@ impl GeneticPool {
     ) -> Self {
         let mut population = Vec::with_capacity(settings.population_size);
         for _ in 0..settings.population_size {
-            population.push(Sample::random(settings.code_size, random));
+            let mut sample = Sample::random(settings.code_size, random);
+            settings.constrain(&mut sample.code_blob, random);
+            population.push(sample);
         }
         Self {
             population,
             settings,
+            seed: None,
+            generation: 0,
+            stagnation: 0,
         }
     }
 
     /// Create a new pool with random population from an instruction corpus
+    ///
+    /// Instructions are picked by their weight, see [`InstructionCorpus::sample_weighted`].
     pub fn new_random_population_from_corpus<R: RngCore>(
         settings: GeneticPoolSettings,
         random: &mut R,
-        corpus: &Vec<CorpusInstruction>,
+        corpus: &InstructionCorpus,
     ) -> Self {
         let mut population = Vec::with_capacity(settings.population_size);
         for _ in 0..settings.population_size {
             let mut code = Vec::new();
             while code.len() < settings.code_size {
-                let instruction = corpus
-                    .get(random.next_u32() as usize % corpus.len())
-                    .expect("should always be inbound");
-                code.extend_from_slice(&instruction.bytes);
+                let instruction = corpus.sample_weighted(random, 1);
+                if instruction.is_empty() {
+                    break; // empty corpus, padded with random bytes
+                }
+                code.extend_from_slice(&instruction);
             }
+            settings.constrain(&mut code, random);
             population.push(Sample::new(code));
         }
         Self {
             population,
             settings,
+            seed: None,
+            generation: 0,
+            stagnation: 0,
+        }
+    }
+
+    /// Tag all samples of this pool with their provenance
+    ///
+    /// Samples of the current population and all samples created by later evolutions
+    /// carry the seed, their generation and the fingerprint of their parent.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - Seed the random source of this pool was created with
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        for sample in self.population.iter_mut() {
+            sample.provenance = Some(SampleProvenance {
+                seed,
+                generation: self.generation,
+                parent: None,
+            });
+        }
+        self
+    }
+
+    /// Get the current generation of the population
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Get the number of iterations without new coverage
+    pub fn stagnation(&self) -> u64 {
+        self.stagnation
+    }
+
+    /// Set the number of iterations without new coverage
+    ///
+    /// The counter is maintained by the fuzzing loop, which knows the coverage of the
+    /// executed samples; reset it to zero once new coverage is found.
+    ///
+    /// # Arguments
+    ///
+    /// * `iterations` - Iterations since the last coverage gain
+    pub fn set_stagnation(&mut self, iterations: u64) {
+        self.stagnation = iterations;
+    }
+
+    /// Get the mutation rate of the next evolution
+    ///
+    /// Without adaptive mutation, or before the stagnation threshold is reached, this is
+    /// a single mutation per sample with the configured structural mutation chance. Each
+    /// further threshold of stagnation adds a mutation and raises the structural chance
+    /// linearly, until the configured maximum is reached.
+    pub fn mutation_rate(&self) -> MutationRate {
+        let baseline = MutationRate {
+            mutations: 1,
+            structural_chance: self.settings.structural_mutation_chance,
+        };
+        let Some(adaptive) = self.settings.adaptive_mutation else {
+            return baseline;
+        };
+        let max_level = adaptive.max_mutations.saturating_sub(1) as u64;
+        if max_level == 0 {
+            return baseline;
+        }
+        let level = (self.stagnation / adaptive.stagnation_threshold.max(1)).min(max_level);
+        let structural_range = adaptive.max_structural_mutation_chance - baseline.structural_chance;
+        MutationRate {
+            mutations: 1 + level as u32,
+            structural_chance: baseline.structural_chance
+                + structural_range.max(0.0) * level as f64 / max_level as f64,
+        }
+    }
+
+    /// Take a snapshot of the pool to resume it later, e.g. after a reboot of the device
+    ///
+    /// Snapshots are trimmed to fit into a single OTA message, see [`PoolSnapshot::trim_to`].
+    pub fn snapshot(&self) -> PoolSnapshot {
+        let mut snapshot = PoolSnapshot {
+            settings: self.settings.clone(),
+            population: self.population.clone(),
+            seed: self.seed,
+            generation: self.generation,
+        };
+        snapshot.trim_to(MAX_PAYLOAD_SIZE - SNAPSHOT_PACKET_RESERVE);
+        snapshot
+    }
+
+    /// Resume a pool from a snapshot
+    ///
+    /// Trimmed populations are refilled by the next evolution.
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshot` - Snapshot taken by [`GeneticPool::snapshot`]
+    pub fn from_snapshot(snapshot: PoolSnapshot) -> Self {
+        Self {
+            population: snapshot.population,
+            settings: snapshot.settings,
+            seed: snapshot.seed,
+            generation: snapshot.generation,
+            stagnation: 0,
         }
     }
 
+    /// Provenance for a sample created in the current generation
+    fn provenance(&self, parent: Option<&Sample>) -> Option<SampleProvenance> {
+        self.seed.map(|seed| SampleProvenance {
+            seed,
+            generation: self.generation,
+            parent: parent.map(Sample::fingerprint),
+        })
+    }
+
     /// Get all samples in the pool
     pub fn all_samples(&self) -> &[Sample] {
         &self.population
@@ -99,66 +432,251 @@ impl GeneticPool {
     }
 
     /// Evolve the population by one generation
+    ///
+    /// The best `elitism_count` samples survive unchanged, the remaining population is
+    /// filled with random samples and children of parents chosen by the configured
+    /// [`SelectionStrategy`].
+    ///
+    /// # Arguments
+    ///
+    /// * `random` - Random source for selection, crossover and mutation
+    /// * `fuzzing_feedback` - Whether to rank samples by their rating, otherwise the
+    ///   population is shuffled
     pub fn evolution<R: RngCore>(&mut self, random: &mut R, fuzzing_feedback: bool) {
+        self.evolution_with_mutator(random, fuzzing_feedback, None);
+    }
+
+    /// Evolve the population by one generation with instruction aware mutations
+    ///
+    /// Like [`GeneticPool::evolution`], but each mutation applies a strategy chosen by the
+    /// configured [`MutationWeights`]. Strategies other than havoc are delegated to the
+    /// mutator, a havoc mutation is applied if there is none or it declines.
+    ///
+    /// # Arguments
+    ///
+    /// * `random` - Random source for selection, crossover and mutation
+    /// * `fuzzing_feedback` - Whether to rank samples by their rating, otherwise the
+    ///   population is shuffled
+    /// * `mutator` - Implementation of the dictionary and splice strategies
+    pub fn evolution_with_mutator<R: RngCore>(
+        &mut self,
+        random: &mut R,
+        fuzzing_feedback: bool,
+        mut mutator: Option<&mut dyn InstructionMutator<R>>,
+    ) {
+        let mut elitism_count = self.settings.elitism_count;
         if fuzzing_feedback {
-            self.population.sort();
-            self.population.reverse();
+            let front = self.rank();
+            if self.settings.rating_mode == RatingMode::Pareto {
+                elitism_count = elitism_count.max(front).min(self.settings.population_size);
+            }
         } else {
             self.population.shuffle(random);
             warn!("Using random population shuffel!");
         }
 
-        self.population
-            .truncate(self.settings.keep_best_x_solutions);
+        let mut parents = match self.settings.selection {
+            SelectionStrategy::Truncation => self
+                .population
+                .iter()
+                .take(self.settings.keep_best_x_solutions)
+                .cloned()
+                .collect(),
+            SelectionStrategy::Tournament { .. } => self.population.clone(),
+        };
+
+        self.population.truncate(elitism_count);
+
+        self.generation += 1;
 
         for _ in 0..self.settings.random_solutions_each_generation {
-            self.population
-                .push(Sample::random(self.settings.code_size, random));
+            let mut sample = Sample::random(self.settings.code_size, random);
+            self.settings.constrain(&mut sample.code_blob, random);
+            sample.provenance = self.provenance(None);
+            parents.push(sample.clone());
+            self.population.push(sample);
+        }
+
+        if parents.is_empty() {
+            return;
         }
 
+        let rate = self.mutation_rate();
         let target_len = self.settings.population_size;
         while self.population.len() < target_len {
-            let parent1 = &self.population[random.next_u32() as usize
-                % (self.settings.keep_best_x_solutions
-                    + self.settings.random_solutions_each_generation)];
-            let parent2 = &self.population[random.next_u32() as usize
-                % (self.settings.keep_best_x_solutions
-                    + self.settings.random_solutions_each_generation)];
+            let parent1 = self.select(&parents, random);
+            let parent2 = self.select(&parents, random);
             let mut child = parent1.clone();
-            for j in (random.next_u32() as usize % self.settings.code_size)..self.settings.code_size
-            {
-                child.code_blob[j] = parent2.code_blob[j];
+            child.rating = None;
+            child.provenance = self.provenance(Some(parent1));
+            let crossover_end = child.code_blob.len().min(parent2.code_blob.len());
+            if crossover_end > 0 {
+                for j in (random.next_u32() as usize % crossover_end)..crossover_end {
+                    child.code_blob[j] = parent2.code_blob[j];
+                }
             }
-            if (random.next_u32() as f64 / u32::MAX as f64) < self.settings.random_mutation_chance {
-                let length = (random.next_u32() % 16).min(self.settings.code_size as u32) as usize;
-                let offset = if self.settings.code_size == length {
-                    0
-                } else {
-                    random.next_u32() as usize % (self.settings.code_size - length)
+            for _ in 0..rate.mutations {
+                if (random.next_u32() as f64 / u32::MAX as f64)
+                    >= self.settings.random_mutation_chance
+                {
+                    continue;
+                }
+                let applied = match (
+                    self.settings.mutation_weights.choose(random),
+                    mutator.as_deref_mut(),
+                ) {
+                    (MutationStrategy::Havoc, _) | (_, None) => false,
+                    (strategy, Some(mutator)) => {
+                        mutator.mutate(strategy, &mut child.code_blob, parent2.code(), random)
+                    }
                 };
-                for i in 0..length {
-                    child.code_blob[i + offset] = random.next_u32() as u8;
+                if applied {
+                    continue;
+                }
+                if rate.structural_chance > 0.0
+                    && (random.next_u32() as f64 / u32::MAX as f64) < rate.structural_chance
+                {
+                    mutate_structure(&mut child.code_blob, random);
+                } else {
+                    mutate_bytes(&mut child.code_blob, random);
                 }
             }
+            self.settings.constrain(&mut child.code_blob, random);
             self.population.push(child);
         }
     }
 
+    /// Select a parent according to the configured selection strategy
+    ///
+    /// # Arguments
+    ///
+    /// * `parents` - Candidate parents ordered best first, must not be empty
+    /// * `random` - Random source for the selection
+    ///
+    /// # Returns
+    ///
+    /// * `&Sample` - The selected parent
+    fn select<'a, R: RngCore>(&self, parents: &'a [Sample], random: &mut R) -> &'a Sample {
+        let index = match self.settings.selection {
+            SelectionStrategy::Truncation => random.next_u32() as usize % parents.len(),
+            SelectionStrategy::Tournament { size } => (0..size.max(1))
+                .map(|_| random.next_u32() as usize % parents.len())
+                .min()
+                .expect("tournament has at least one contestant"),
+        };
+        &parents[index]
+    }
+
+    /// Sorts the population best first according to the configured rating mode
+    ///
+    /// # Returns
+    ///
+    /// * `usize` - Number of samples in the best front, the leading samples that are not
+    ///   worse than any other sample
+    fn rank(&mut self) -> usize {
+        match self.settings.rating_mode {
+            RatingMode::Lexicographic => {
+                self.population.sort();
+                self.population.reverse();
+                self.population.len().min(1)
+            }
+            RatingMode::Pareto => {
+                let fronts = non_dominated_fronts(&self.population);
+                let front = fronts.first().map_or(0, Vec::len);
+
+                let mut slots = core::mem::take(&mut self.population)
+                    .into_iter()
+                    .map(Some)
+                    .collect::<Vec<_>>();
+                for indices in fronts {
+                    let front = indices
+                        .iter()
+                        .map(|&index| slots[index].take().expect("each sample is in one front"))
+                        .collect::<Vec<_>>();
+                    let distances = crowding_distances(&front);
+                    let mut front = front.into_iter().zip(distances).collect::<Vec<_>>();
+                    front.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+                    self.population
+                        .extend(front.into_iter().map(|(sample, _)| sample));
+                }
+                front
+            }
+        }
+    }
+
+    /// Get the samples not dominated by any other sample of the population
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<&Sample>` - The non-dominated front, unrated samples only if no sample is rated
+    pub fn pareto_front(&self) -> Vec<&Sample> {
+        non_dominated_fronts(&self.population)
+            .first()
+            .map(|front| front.iter().map(|&index| &self.population[index]).collect())
+            .unwrap_or_default()
+    }
+
     /// Get the final sorted (by fitness) population
+    ///
+    /// With [`RatingMode::Pareto`] the population is ordered by fronts, the non-dominated
+    /// front first.
     pub fn result(mut self) -> Vec<Sample> {
+        self.rank();
+        self.population
+    }
+}
+
+/// Serializable state of a [`GeneticPool`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PoolSnapshot {
+    /// Settings of the pool
+    pub settings: GeneticPoolSettings,
+    /// Population of the pool
+    pub population: Vec<Sample>,
+    /// Seed of the random source of the pool, if known
+    pub seed: Option<u64>,
+    /// Generation of the population
+    pub generation: u64,
+}
+
+impl PoolSnapshot {
+    /// Drops the lowest rated samples until the serialized snapshot fits into `max_size`
+    ///
+    /// # Arguments
+    ///
+    /// * `max_size` - Maximum size of the serialized snapshot in bytes
+    pub fn trim_to(&mut self, max_size: u64) {
+        let mut size = serialized_size(self);
+        if size <= max_size {
+            return;
+        }
+
         self.population.sort();
         self.population.reverse();
-        self.population
+        // removing samples only shrinks the length prefix, the estimate stays an upper bound
+        while size > max_size {
+            match self.population.pop() {
+                Some(sample) => size = size.saturating_sub(serialized_size(&sample)),
+                None => break,
+            }
+        }
     }
 }
 
+/// Size of the postcard serialization of a value in bytes
+fn serialized_size<T: Serialize>(value: &T) -> u64 {
+    postcard::experimental::serialized_size(value).map_or(u64::MAX, |size| size as u64)
+}
+
 /// A single sample in the genetic pool
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct Sample {
     /// Raw code bytes
     code_blob: Vec<u8>,
     /// Rating from execution (if available)
     pub rating: Option<GeneticSampleRating>,
+    /// How the sample was generated (if known)
+    pub provenance: Option<SampleProvenance>,
 }
 
 impl Sample {
@@ -167,6 +685,7 @@ impl Sample {
         Self {
             code_blob,
             rating: None,
+            provenance: None,
         }
     }
 
@@ -179,6 +698,7 @@ impl Sample {
         Self {
             code_blob,
             rating: None,
+            provenance: None,
         }
     }
 
@@ -186,6 +706,163 @@ impl Sample {
     pub fn code(&self) -> &[u8] {
         &self.code_blob
     }
+
+    /// Get the fingerprint of the code bytes
+    pub fn fingerprint(&self) -> u64 {
+        SampleProvenance::fingerprint(&self.code_blob)
+    }
+}
+
+/// Origin of a sample, required to reproduce a finding
+#[derive(Clone, PartialEq, Eq, Debug, Hash, Serialize, Deserialize)]
+pub struct SampleProvenance {
+    /// Seed of the random source the sample was generated with
+    pub seed: u64,
+    /// Generation (evolution) the sample was created in
+    pub generation: u64,
+    /// Fingerprint of the parent sample, `None` for randomly generated samples
+    pub parent: Option<u64>,
+}
+
+impl SampleProvenance {
+    /// Computes the fingerprint of a code sample (64 bit FNV-1a)
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - Code bytes of the sample
+    ///
+    /// # Returns
+    ///
+    /// * `u64` - Fingerprint of the code
+    pub fn fingerprint(code: &[u8]) -> u64 {
+        code.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+}
+
+/// Overwrites up to 16 consecutive bytes of a code sample with random bytes
+fn mutate_bytes<R: RngCore>(code: &mut [u8], random: &mut R) {
+    let length = (random.next_u32() % 16).min(code.len() as u32) as usize;
+    let offset = if code.len() == length {
+        0
+    } else {
+        random.next_u32() as usize % (code.len() - length)
+    };
+    for byte in code[offset..offset + length].iter_mut() {
+        *byte = random.next_u32() as u8;
+    }
+}
+
+/// Inserts or deletes up to 4 consecutive random bytes of a code sample
+fn mutate_structure<R: RngCore>(code: &mut Vec<u8>, random: &mut R) {
+    let length = 1 + random.next_u32() as usize % 4;
+    let offset = random.next_u32() as usize % (code.len() + 1);
+    if random.next_u32() & 1 == 0 {
+        let bytes = (0..length).map(|_| random.next_u32() as u8);
+        code.splice(offset..offset, bytes);
+    } else {
+        code.drain(offset..(offset + length).min(code.len()));
+    }
+}
+
+/// Checks if the rating of a sample Pareto-dominates the rating of another sample
+///
+/// Unrated samples are dominated by every rated sample.
+fn sample_dominates(sample: &Sample, other: &Sample) -> bool {
+    match (&sample.rating, &other.rating) {
+        (Some(rating), Some(other)) => rating.dominates(other),
+        (Some(_), None) => true,
+        (None, _) => false,
+    }
+}
+
+/// Sorts samples into fronts of non-dominated samples (fast non-dominated sort)
+///
+/// # Arguments
+///
+/// * `samples` - Samples to sort
+///
+/// # Returns
+///
+/// * `Vec<Vec<usize>>` - Indices of the samples in each front, the non-dominated front first
+fn non_dominated_fronts(samples: &[Sample]) -> Vec<Vec<usize>> {
+    let mut dominated_by = vec![0usize; samples.len()];
+    let mut dominates = vec![Vec::new(); samples.len()];
+    for (i, sample) in samples.iter().enumerate() {
+        for (j, other) in samples.iter().enumerate().skip(i + 1) {
+            if sample_dominates(sample, other) {
+                dominates[i].push(j);
+                dominated_by[j] += 1;
+            } else if sample_dominates(other, sample) {
+                dominates[j].push(i);
+                dominated_by[i] += 1;
+            }
+        }
+    }
+
+    let mut fronts = Vec::new();
+    let mut front = (0..samples.len())
+        .filter(|&i| dominated_by[i] == 0)
+        .collect::<Vec<_>>();
+    while !front.is_empty() {
+        let mut next = Vec::new();
+        for &i in &front {
+            for &j in &dominates[i] {
+                dominated_by[j] -= 1;
+                if dominated_by[j] == 0 {
+                    next.push(j);
+                }
+            }
+        }
+        next.sort_unstable();
+        fronts.push(front);
+        front = next;
+    }
+    fronts
+}
+
+/// Computes the crowding distance of each sample of a front
+///
+/// The distance is the sum over all objectives of the normalized distance between the
+/// neighbors of a sample; the extreme samples of each objective get an infinite distance.
+///
+/// # Arguments
+///
+/// * `front` - Samples of a single front
+///
+/// # Returns
+///
+/// * `Vec<f64>` - Crowding distance of each sample, zero for unrated samples
+fn crowding_distances(front: &[Sample]) -> Vec<f64> {
+    let mut distances = vec![0.0; front.len()];
+    let objectives = front
+        .iter()
+        .map(|sample| sample.rating.as_ref().map(GeneticSampleRating::objectives))
+        .collect::<Option<Vec<_>>>();
+    let Some(objectives) = objectives else {
+        return distances;
+    };
+    if front.len() <= 2 {
+        return vec![f64::INFINITY; front.len()];
+    }
+
+    for objective in 0..GeneticSampleRating::OBJECTIVES {
+        let values = objectives.iter().map(|x| x[objective]).collect::<Vec<_>>();
+        let mut order = (0..front.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| values[a].partial_cmp(&values[b]).unwrap_or(Ordering::Equal));
+        let min = values[order[0]];
+        let max = values[order[order.len() - 1]];
+        distances[order[0]] = f64::INFINITY;
+        distances[order[order.len() - 1]] = f64::INFINITY;
+        if max <= min {
+            continue;
+        }
+        for window in order.windows(3) {
+            distances[window[1]] += (values[window[2]] - values[window[0]]) / (max - min);
+        }
+    }
+    distances
 }
 
 impl Ord for Sample {
@@ -209,6 +886,9 @@ pub struct GeneticSampleRating {
     pub total_address_coverage: u32,
     /// Program utilization percentage (0-100)
     pub program_utilization: u8,
+    /// Number of distinct instruction categories among the executed instructions
+    #[serde(default)]
+    pub instruction_diversity: u8,
     /// Number of loops executed
     pub loop_count: u64,
 }
@@ -219,8 +899,44 @@ impl GeneticSampleRating {
         unique_address_coverage: 0,
         total_address_coverage: 0,
         program_utilization: 0,
+        instruction_diversity: 0,
         loop_count: 0,
     };
+
+    /// Number of objectives of a rating, see [`GeneticSampleRating::objectives`]
+    const OBJECTIVES: usize = 5;
+
+    /// Metrics of the rating as objectives to maximize
+    ///
+    /// Coverage, utilization and diversity are maximized, the loop count is minimized.
+    fn objectives(&self) -> [f64; Self::OBJECTIVES] {
+        [
+            self.unique_address_coverage as f64,
+            self.total_address_coverage as f64,
+            self.program_utilization as f64,
+            self.instruction_diversity as f64,
+            -(self.loop_count as f64),
+        ]
+    }
+
+    /// Checks if this rating Pareto-dominates another rating
+    ///
+    /// A rating dominates another if it is not worse in any objective and better in at
+    /// least one; higher coverage, utilization and diversity and lower loop counts are better.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The rating to compare with
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if this rating dominates `other`
+    pub fn dominates(&self, other: &Self) -> bool {
+        let objectives = self.objectives();
+        let other = other.objectives();
+        objectives.iter().zip(other.iter()).all(|(a, b)| a >= b)
+            && objectives.iter().zip(other.iter()).any(|(a, b)| a > b)
+    }
 }
 
 impl Ord for GeneticSampleRating {
@@ -250,6 +966,13 @@ impl Ord for GeneticSampleRating {
             return program_utilization_order;
         }
 
+        let instruction_diversity_order =
+            self.instruction_diversity.cmp(&other.instruction_diversity);
+
+        if instruction_diversity_order != Ordering::Equal {
+            return instruction_diversity_order;
+        }
+
         self.loop_count.cmp(&other.loop_count)
     }
 }
@@ -262,8 +985,38 @@ impl PartialOrd for GeneticSampleRating {
 
 #[cfg(test)]
 mod tests {
-    use crate::genetic_pool::{GeneticPool, GeneticSampleRating, Sample};
+    use crate::genetic_pool::{
+        crowding_distances, non_dominated_fronts, AdaptiveMutationSettings, GeneticPool,
+        GeneticPoolSettings, GeneticSampleRating, InstructionMutator, MutationRate,
+        MutationStrategy, MutationWeights, PoolSnapshot, RatingMode, Sample, SampleProvenance,
+        SelectionStrategy,
+    };
+    use crate::instruction_corpus::{CorpusInstruction, InstructionCorpus};
     use alloc::vec;
+    use alloc::vec::Vec;
+    use rand_core::RngCore;
+
+    /// Deterministic xorshift random source for tests
+    struct TestRng(u64);
+
+    impl RngCore for TestRng {
+        fn next_u32(&mut self) -> u32 {
+            self.next_u64() as u32
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn fill_bytes(&mut self, dst: &mut [u8]) {
+            for byte in dst.iter_mut() {
+                *byte = self.next_u32() as u8;
+            }
+        }
+    }
 
     #[test]
     pub fn test_genetic_pool() {
@@ -272,25 +1025,583 @@ mod tests {
                 unique_address_coverage: 10,
                 total_address_coverage: 100,
                 program_utilization: 50,
+                instruction_diversity: 0,
                 loop_count: 5,
             }),
             code_blob: vec![1],
+            provenance: None,
         };
         let sample2 = Sample {
             rating: Some(GeneticSampleRating {
                 unique_address_coverage: 10,
                 total_address_coverage: 110,
                 program_utilization: 50,
+                instruction_diversity: 0,
                 loop_count: 5,
             }),
             code_blob: vec![2],
+            provenance: None,
         };
         let mut pool = GeneticPool {
             settings: Default::default(),
             population: vec![sample1, sample2],
+            seed: None,
+            generation: 0,
+            stagnation: 0,
         };
         pool.population.sort();
         pool.population.reverse();
         assert_eq!(pool.population[0].code_blob, vec![2]);
     }
+
+    #[test]
+    pub fn test_sample_provenance_round_trip() {
+        let provenance = SampleProvenance {
+            seed: 0x1234_5678,
+            generation: 3,
+            parent: Some(SampleProvenance::fingerprint(&[1, 2, 3])),
+        };
+        let data = postcard::to_allocvec(&provenance).unwrap();
+        assert_eq!(
+            postcard::from_bytes::<SampleProvenance>(&data).unwrap(),
+            provenance
+        );
+
+        let data = postcard::to_allocvec(&Some(provenance.clone())).unwrap();
+        assert_eq!(
+            postcard::from_bytes::<Option<SampleProvenance>>(&data).unwrap(),
+            Some(provenance)
+        );
+    }
+
+    #[test]
+    pub fn test_sample_provenance_survives_evolution() {
+        let mut random = TestRng(42);
+        let mut pool =
+            GeneticPool::new_random_population(GeneticPoolSettings::default(), &mut random)
+                .with_seed(42);
+        assert!(pool.all_samples().iter().all(|x| x.provenance
+            == Some(SampleProvenance {
+                seed: 42,
+                generation: 0,
+                parent: None,
+            })));
+
+        for (i, sample) in pool.all_samples_mut().iter_mut().enumerate() {
+            sample.rating = Some(GeneticSampleRating {
+                unique_address_coverage: i as u16,
+                ..Default::default()
+            });
+        }
+        pool.evolution(&mut random, true);
+        assert_eq!(pool.generation(), 1);
+
+        let parents = pool
+            .all_samples()
+            .iter()
+            .map(Sample::fingerprint)
+            .collect::<Vec<_>>();
+
+        let children = pool
+            .all_samples()
+            .iter()
+            .filter_map(|x| x.provenance.as_ref())
+            .filter(|x| x.generation == 1)
+            .collect::<Vec<_>>();
+        assert!(!children.is_empty());
+        assert!(children.iter().all(|x| x.seed == 42));
+        assert!(children
+            .iter()
+            .filter_map(|x| x.parent)
+            .all(|parent| parents.contains(&parent)));
+        assert!(children.iter().any(|x| x.parent.is_some()));
+        assert!(pool.all_samples().iter().all(|x| x.provenance.is_some()));
+    }
+
+    #[test]
+    pub fn test_sample_size_bounds() {
+        let settings = GeneticPoolSettings {
+            population_size: 30,
+            code_size: 16,
+            min_code_size: 8,
+            max_code_size: 20,
+            random_mutation_chance: 1.0,
+            ..Default::default()
+        };
+        let corpus = InstructionCorpus::new((0..40u8).map(|i| CorpusInstruction {
+            bytes: vec![0x90; 1 + i as usize % 15],
+            valid: true,
+        }));
+
+        let mut random = TestRng(7);
+        for mut pool in [
+            GeneticPool::new_random_population(settings.clone(), &mut random),
+            GeneticPool::new_random_population_from_corpus(settings.clone(), &mut random, &corpus),
+        ] {
+            for evolution in 0..20 {
+                assert!(pool
+                    .all_samples()
+                    .iter()
+                    .all(|x| (settings.min_code_size..=settings.max_code_size)
+                        .contains(&x.code().len())));
+                for sample in pool.all_samples_mut() {
+                    sample.rating = Some(GeneticSampleRating {
+                        instruction_diversity: 0,
+                        loop_count: random.next_u64() % (evolution + 1),
+                        ..Default::default()
+                    });
+                }
+                pool.evolution(&mut random, evolution % 2 == 0);
+            }
+        }
+    }
+
+    /// Creates a pool whose sample `i` has code `[i]` and `i` unique addresses covered
+    fn rated_pool(settings: GeneticPoolSettings) -> GeneticPool {
+        let population = (0..settings.population_size)
+            .map(|i| Sample {
+                code_blob: vec![i as u8],
+                rating: Some(GeneticSampleRating {
+                    unique_address_coverage: i as u16,
+                    ..Default::default()
+                }),
+                provenance: None,
+            })
+            .collect();
+        GeneticPool {
+            population,
+            settings,
+            seed: None,
+            generation: 0,
+            stagnation: 0,
+        }
+    }
+
+    #[test]
+    pub fn test_elites_survive_evolution() {
+        let settings = GeneticPoolSettings {
+            population_size: 20,
+            code_size: 1,
+            min_code_size: 1,
+            max_code_size: 1,
+            random_mutation_chance: 1.0,
+            selection: SelectionStrategy::Tournament { size: 3 },
+            elitism_count: 4,
+            ..Default::default()
+        };
+        let mut pool = rated_pool(settings);
+
+        let mut random = TestRng(11);
+        pool.evolution(&mut random, true);
+
+        assert_eq!(pool.all_samples().len(), 20);
+        for (sample, expected) in pool.all_samples().iter().zip([19u8, 18, 17, 16]) {
+            assert_eq!(sample.code(), &[expected]);
+            assert_eq!(
+                sample.rating.as_ref().map(|x| x.unique_address_coverage),
+                Some(expected as u16)
+            );
+        }
+        assert!(pool.all_samples()[4..].iter().all(|x| x.rating.is_none()));
+    }
+
+    #[test]
+    pub fn test_tournament_selection_probability() {
+        const TRIALS: usize = 20000;
+
+        let settings = GeneticPoolSettings {
+            population_size: 10,
+            selection: SelectionStrategy::Tournament { size: 2 },
+            ..Default::default()
+        };
+        let mut pool = rated_pool(settings);
+        pool.rank();
+
+        let mut random = TestRng(3);
+        let mut wins = [0usize; 10];
+        for _ in 0..TRIALS {
+            wins[pool.select(&pool.population, &mut random).code()[0] as usize] += 1;
+        }
+
+        // sample i wins if it is the best of both contestants: ((i + 1)^2 - i^2) / n^2
+        for (i, wins) in wins.iter().enumerate() {
+            let expected = (2 * i + 1) as f64 / 100.0;
+            let observed = *wins as f64 / TRIALS as f64;
+            assert!(
+                (observed - expected).abs() < 0.015,
+                "sample {i}: observed {observed}, expected {expected}"
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_settings_round_trip() {
+        let settings = GeneticPoolSettings {
+            selection: SelectionStrategy::Tournament { size: 4 },
+            elitism_count: 2,
+            ..Default::default()
+        };
+        let data = postcard::to_allocvec(&settings).unwrap();
+        assert_eq!(
+            postcard::from_bytes::<GeneticPoolSettings>(&data).unwrap(),
+            settings
+        );
+    }
+
+    /// Creates a sample with code `[id]` rated with the given metrics
+    fn sample(id: u8, unique: u16, total: u32, loop_count: u64) -> Sample {
+        Sample {
+            code_blob: vec![id],
+            rating: Some(GeneticSampleRating {
+                unique_address_coverage: unique,
+                total_address_coverage: total,
+                program_utilization: 50,
+                instruction_diversity: 0,
+                loop_count,
+            }),
+            provenance: None,
+        }
+    }
+
+    #[test]
+    pub fn test_pareto_dominance() {
+        let a = sample(0, 10, 100, 5).rating.unwrap();
+        let b = sample(1, 10, 100, 8).rating.unwrap();
+        let c = sample(2, 12, 90, 5).rating.unwrap();
+
+        assert!(a.dominates(&b));
+        assert!(!b.dominates(&a));
+        assert!(!a.dominates(&c));
+        assert!(!c.dominates(&a));
+        assert!(!a.dominates(&a));
+    }
+
+    #[test]
+    pub fn test_pareto_front() {
+        let population = vec![
+            sample(0, 10, 100, 5), // front 0: best total coverage
+            sample(1, 20, 50, 10), // front 0: best unique coverage
+            sample(2, 5, 60, 1),   // front 0: fewest loops
+            sample(3, 9, 90, 6),   // front 1: dominated by 0
+            sample(4, 15, 40, 12), // front 1: dominated by 1
+            sample(5, 4, 30, 20),  // front 2: dominated by 3 and 4
+            Sample::new(vec![6]),  // unrated, dominated by all
+        ];
+
+        assert_eq!(
+            non_dominated_fronts(&population),
+            vec![vec![0, 1, 2], vec![3, 4], vec![5], vec![6]]
+        );
+
+        let mut pool = GeneticPool {
+            population,
+            settings: GeneticPoolSettings {
+                rating_mode: RatingMode::Pareto,
+                ..Default::default()
+            },
+            seed: None,
+            generation: 0,
+            stagnation: 0,
+        };
+        let mut front = pool
+            .pareto_front()
+            .iter()
+            .map(|x| x.code()[0])
+            .collect::<Vec<_>>();
+        front.sort();
+        assert_eq!(front, vec![0, 1, 2]);
+
+        // the lexicographic order would rank sample 4 second
+        assert_eq!(pool.rank(), 3);
+        let order = pool
+            .all_samples()
+            .iter()
+            .map(|x| x.code()[0])
+            .collect::<Vec<_>>();
+        assert_eq!(&order[3..], &[3, 4, 5, 6]);
+    }
+
+    #[test]
+    pub fn test_crowding_distance() {
+        let front = vec![
+            sample(0, 0, 40, 0),
+            sample(1, 1, 30, 0),
+            sample(2, 3, 10, 0),
+            sample(3, 4, 0, 0),
+        ];
+        let distances = crowding_distances(&front);
+
+        assert_eq!(distances[0], f64::INFINITY);
+        assert_eq!(distances[3], f64::INFINITY);
+        // sample 1: (3 - 0) / 4 + (40 - 10) / 40
+        assert_eq!(distances[1], 0.75 + 0.75);
+        // sample 2: (4 - 1) / 4 + (30 - 0) / 40
+        assert_eq!(distances[2], 0.75 + 0.75);
+    }
+
+    #[test]
+    pub fn test_pareto_evolution_keeps_front() {
+        let mut population = (0..10u8)
+            .map(|i| sample(i, i as u16, 100 - i as u32, 0))
+            .collect::<Vec<_>>();
+        population.extend((10..20u8).map(|i| sample(i, 0, 0, 100)));
+
+        let mut pool = GeneticPool {
+            population,
+            settings: GeneticPoolSettings {
+                population_size: 20,
+                elitism_count: 2,
+                rating_mode: RatingMode::Pareto,
+                ..Default::default()
+            },
+            seed: None,
+            generation: 0,
+            stagnation: 0,
+        };
+        let mut random = TestRng(5);
+        pool.evolution(&mut random, true);
+
+        let mut survivors = pool.all_samples()[..10]
+            .iter()
+            .map(|x| x.code()[0])
+            .collect::<Vec<_>>();
+        survivors.sort();
+        assert_eq!(survivors, (0..10).collect::<Vec<_>>());
+        assert_eq!(pool.all_samples().len(), 20);
+        assert!(pool.all_samples()[10..].iter().all(|x| x.rating.is_none()));
+    }
+
+    #[test]
+    pub fn test_pool_snapshot_round_trip() {
+        let mut random = TestRng(9);
+        let mut pool =
+            GeneticPool::new_random_population(GeneticPoolSettings::default(), &mut random)
+                .with_seed(9);
+        for (i, sample) in pool.all_samples_mut().iter_mut().enumerate() {
+            sample.rating = Some(GeneticSampleRating {
+                instruction_diversity: 0,
+                loop_count: i as u64,
+                ..Default::default()
+            });
+        }
+        pool.evolution(&mut random, true);
+
+        let snapshot = pool.snapshot();
+        let data = postcard::to_allocvec(&snapshot).unwrap();
+        let resumed = GeneticPool::from_snapshot(postcard::from_bytes(&data).unwrap());
+
+        assert_eq!(resumed.generation(), pool.generation());
+        assert_eq!(resumed.all_samples(), pool.all_samples());
+        assert_eq!(resumed.snapshot(), snapshot);
+    }
+
+    #[test]
+    pub fn test_pool_snapshot_trim() {
+        let mut snapshot = PoolSnapshot {
+            settings: GeneticPoolSettings::default(),
+            population: (0..100u8)
+                .map(|i| Sample {
+                    code_blob: vec![i; 64],
+                    rating: Some(GeneticSampleRating {
+                        unique_address_coverage: (i as u16 * 37) % 100,
+                        ..Default::default()
+                    }),
+                    provenance: None,
+                })
+                .collect(),
+            seed: None,
+            generation: 0,
+        };
+        let size = postcard::to_allocvec(&snapshot).unwrap().len() as u64;
+
+        let mut untouched = snapshot.clone();
+        untouched.trim_to(size);
+        assert_eq!(untouched, snapshot);
+
+        snapshot.trim_to(size / 2);
+        assert!(postcard::to_allocvec(&snapshot).unwrap().len() as u64 <= size / 2);
+        assert!(snapshot.population.len() >= 45);
+        // only the top rated samples are kept
+        let lowest = snapshot
+            .population
+            .iter()
+            .filter_map(|x| x.rating.as_ref())
+            .map(|x| x.unique_address_coverage)
+            .min()
+            .unwrap();
+        assert_eq!(lowest as usize, 100 - snapshot.population.len());
+    }
+
+    #[test]
+    pub fn test_adaptive_mutation_rate() {
+        let settings = GeneticPoolSettings {
+            structural_mutation_chance: 0.1,
+            adaptive_mutation: Some(AdaptiveMutationSettings {
+                stagnation_threshold: 100,
+                max_mutations: 5,
+                max_structural_mutation_chance: 0.5,
+            }),
+            ..Default::default()
+        };
+        let mut pool = GeneticPool::new_random_population(settings, &mut TestRng(1));
+        let baseline = MutationRate {
+            mutations: 1,
+            structural_chance: 0.1,
+        };
+        assert_eq!(pool.mutation_rate(), baseline);
+
+        pool.set_stagnation(99);
+        assert_eq!(pool.mutation_rate(), baseline);
+
+        pool.set_stagnation(200);
+        assert_eq!(pool.mutation_rate().mutations, 3);
+        assert!((pool.mutation_rate().structural_chance - 0.3).abs() < 1e-9);
+
+        pool.set_stagnation(10_000);
+        assert_eq!(pool.mutation_rate().mutations, 5);
+        assert!((pool.mutation_rate().structural_chance - 0.5).abs() < 1e-9);
+
+        // new coverage falls back to the baseline
+        pool.set_stagnation(0);
+        assert_eq!(pool.mutation_rate(), baseline);
+
+        pool.settings.adaptive_mutation = None;
+        pool.set_stagnation(10_000);
+        assert_eq!(pool.mutation_rate(), baseline);
+    }
+
+    #[test]
+    pub fn test_structural_mutations() {
+        let settings = GeneticPoolSettings {
+            population_size: 50,
+            code_size: 16,
+            min_code_size: 1,
+            max_code_size: 64,
+            random_mutation_chance: 1.0,
+            adaptive_mutation: Some(AdaptiveMutationSettings {
+                stagnation_threshold: 1,
+                max_mutations: 4,
+                max_structural_mutation_chance: 1.0,
+            }),
+            ..Default::default()
+        };
+        let mut random = TestRng(21);
+        let mut pool = GeneticPool::new_random_population(settings.clone(), &mut random);
+        pool.evolution(&mut random, true);
+        assert!(pool.all_samples().iter().all(|x| x.code().len() == 16));
+
+        let mut pool = GeneticPool::new_random_population(settings, &mut random);
+        pool.set_stagnation(1000);
+        pool.evolution(&mut random, true);
+        assert!(pool.all_samples().iter().any(|x| x.code().len() != 16));
+        assert!(pool
+            .all_samples()
+            .iter()
+            .all(|x| (1..=64).contains(&x.code().len())));
+    }
+
+    /// Mutator replacing the code with a marker, declining splices
+    #[derive(Default)]
+    struct MarkerMutator {
+        dictionary: usize,
+        splice: usize,
+    }
+
+    impl<R: RngCore> InstructionMutator<R> for MarkerMutator {
+        fn mutate(
+            &mut self,
+            strategy: MutationStrategy,
+            code: &mut Vec<u8>,
+            _other_parent: &[u8],
+            _random: &mut R,
+        ) -> bool {
+            match strategy {
+                MutationStrategy::Havoc => panic!("havoc is not delegated"),
+                MutationStrategy::Dictionary => {
+                    self.dictionary += 1;
+                    *code = vec![0xCC; 4];
+                    true
+                }
+                MutationStrategy::Splice => {
+                    self.splice += 1;
+                    false
+                }
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_mutation_weights() {
+        let mut random = TestRng(5);
+        let state = random.0;
+        assert_eq!(
+            MutationWeights::default().choose(&mut random),
+            MutationStrategy::Havoc
+        );
+        assert_eq!(random.0, state, "havoc only weights consume no randomness");
+
+        let weights = MutationWeights {
+            havoc: 0,
+            dictionary: 0,
+            splice: 0,
+        };
+        assert_eq!(weights.choose(&mut random), MutationStrategy::Havoc);
+
+        let weights = MutationWeights {
+            havoc: 1,
+            dictionary: 1,
+            splice: 1,
+        };
+        let chosen = (0..300)
+            .map(|_| weights.choose(&mut random))
+            .collect::<Vec<_>>();
+        for strategy in [
+            MutationStrategy::Havoc,
+            MutationStrategy::Dictionary,
+            MutationStrategy::Splice,
+        ] {
+            assert!(chosen.iter().filter(|x| **x == strategy).count() > 50);
+        }
+    }
+
+    #[test]
+    pub fn test_evolution_with_mutator() {
+        let settings = GeneticPoolSettings {
+            population_size: 50,
+            code_size: 16,
+            random_mutation_chance: 1.0,
+            elitism_count: 0,
+            random_solutions_each_generation: 0,
+            mutation_weights: MutationWeights {
+                havoc: 0,
+                dictionary: 1,
+                splice: 1,
+            },
+            ..Default::default()
+        };
+        let mut random = TestRng(8);
+        let mut pool = GeneticPool::new_random_population(settings.clone(), &mut random);
+        let mut mutator = MarkerMutator::default();
+        pool.evolution_with_mutator(&mut random, true, Some(&mut mutator));
+
+        assert_eq!(mutator.dictionary + mutator.splice, 50);
+        assert!(mutator.dictionary > 0 && mutator.splice > 0);
+        // declined splices fall back to havoc and keep the size
+        let markers = pool
+            .all_samples()
+            .iter()
+            .filter(|x| x.code() == [0xCC; 4])
+            .count();
+        assert_eq!(markers, mutator.dictionary);
+        assert!(pool
+            .all_samples()
+            .iter()
+            .filter(|x| x.code() != [0xCC; 4])
+            .all(|x| x.code().len() == 16));
+
+        // without a mutator every mutation is a havoc mutation
+        let mut pool = GeneticPool::new_random_population(settings, &mut random);
+        pool.evolution(&mut random, true);
+        assert!(pool.all_samples().iter().all(|x| x.code().len() == 16));
+    }
 }
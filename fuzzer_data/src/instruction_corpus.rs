@@ -2,9 +2,13 @@
 //!
 //! This module provides types for managing an initial corpus of instructions used in fuzzing.
 
-use alloc::collections::BTreeSet;
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::vec::Vec;
 use core::cmp::Ordering;
+#[cfg(feature = "std")]
+use iced_x86::{Decoder, DecoderOptions, IcedError};
+use iced_x86::{FlowControl, Instruction};
+use rand_core::RngCore;
 use serde::{Deserialize, Serialize};
 
 /// A single instruction in the corpus
@@ -37,8 +41,471 @@ impl PartialEq for CorpusInstruction {
 impl Eq for CorpusInstruction {}
 
 /// A collection of unique instructions
+///
+/// Duplicate byte sequences are dropped when the corpus is created or loaded. Instructions
+/// are sampled by weight, see [`InstructionCorpus::weight`].
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "InstructionCorpusData")]
 pub struct InstructionCorpus {
     /// Set of unique instructions
     pub instructions: BTreeSet<CorpusInstruction>,
+    /// Sampling weights overriding the default weight of an instruction (bytes -> weight)
+    pub weights: BTreeMap<Vec<u8>, f64>,
+    /// Number of occurrences of each instruction in the binaries the corpus was built from
+    pub frequencies: BTreeMap<Vec<u8>, u64>,
+    /// Instructions and their cumulative weights, in order of `instructions`
+    #[serde(skip)]
+    sampling_table: Vec<(Vec<u8>, f64)>,
+}
+
+/// Serialized form of an [`InstructionCorpus`]
+#[derive(Deserialize)]
+struct InstructionCorpusData {
+    /// Instructions of the corpus, may contain duplicates
+    instructions: Vec<CorpusInstruction>,
+    /// Sampling weight overrides
+    #[serde(default)]
+    weights: BTreeMap<Vec<u8>, f64>,
+    /// Occurrence counts of the instructions
+    #[serde(default)]
+    frequencies: BTreeMap<Vec<u8>, u64>,
+}
+
+impl From<InstructionCorpusData> for InstructionCorpus {
+    fn from(data: InstructionCorpusData) -> Self {
+        let mut corpus = Self::new(data.instructions).with_weights(data.weights);
+        corpus.frequencies = data.frequencies;
+        corpus
+    }
+}
+
+impl InstructionCorpus {
+    /// Creates a corpus from a list of instructions, dropping duplicate byte sequences
+    ///
+    /// # Arguments
+    ///
+    /// * `instructions` - Instructions of the corpus
+    pub fn new<I: IntoIterator<Item = CorpusInstruction>>(instructions: I) -> Self {
+        let mut corpus = Self {
+            instructions: instructions.into_iter().collect(),
+            weights: BTreeMap::new(),
+            frequencies: BTreeMap::new(),
+            sampling_table: Vec::new(),
+        };
+        corpus.update_sampling_table();
+        corpus
+    }
+
+    /// Builds a corpus from raw machine code, see [`default_instruction_filter`]
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - Raw machine code, e.g. the `.text` section of a binary
+    /// * `bitness` - Bitness of the code: 16, 32 or 64
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(InstructionCorpus)` - Corpus of the decoded instructions with their frequencies
+    /// * `Err(IcedError)` - The bitness is not supported
+    #[cfg(feature = "std")]
+    pub fn from_binary(code: &[u8], bitness: u32) -> Result<Self, IcedError> {
+        Self::from_binary_filtered(code, bitness, default_instruction_filter)
+    }
+
+    /// Builds a corpus from raw machine code, keeping only instructions accepted by `filter`
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - Raw machine code, e.g. the `.text` section of a binary
+    /// * `bitness` - Bitness of the code: 16, 32 or 64
+    /// * `filter` - Returns whether a decoded instruction should be part of the corpus
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(InstructionCorpus)` - Corpus of the decoded instructions with their frequencies
+    /// * `Err(IcedError)` - The bitness is not supported
+    #[cfg(feature = "std")]
+    pub fn from_binary_filtered<F: Fn(&Instruction) -> bool>(
+        code: &[u8],
+        bitness: u32,
+        filter: F,
+    ) -> Result<Self, IcedError> {
+        let mut decoder = Decoder::try_new(bitness, code, DecoderOptions::NONE)?;
+        let mut instruction = Instruction::default();
+        let mut instructions = Vec::new();
+        let mut frequencies = BTreeMap::new();
+
+        while decoder.can_decode() {
+            let position = decoder.position();
+            decoder.decode_out(&mut instruction);
+            if !filter(&instruction) {
+                continue;
+            }
+
+            let bytes = code[position..decoder.position()].to_vec();
+            *frequencies.entry(bytes.clone()).or_default() += 1;
+            instructions.push(CorpusInstruction {
+                bytes,
+                valid: !instruction.is_invalid(),
+            });
+        }
+
+        let mut corpus = Self::new(instructions);
+        corpus.frequencies = frequencies;
+        Ok(corpus)
+    }
+
+    /// Adds the instructions and frequencies of another corpus to this one
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - Corpus to merge into this one; its weights take precedence
+    pub fn merge(&mut self, other: InstructionCorpus) {
+        self.instructions.extend(other.instructions);
+        self.weights.extend(other.weights);
+        for (bytes, count) in other.frequencies {
+            *self.frequencies.entry(bytes).or_default() += count;
+        }
+        self.update_sampling_table();
+    }
+
+    /// Overrides the sampling weights of instructions
+    ///
+    /// # Arguments
+    ///
+    /// * `weights` - Weights of instructions, by their bytes
+    pub fn with_weights(mut self, weights: BTreeMap<Vec<u8>, f64>) -> Self {
+        self.weights = weights;
+        self.update_sampling_table();
+        self
+    }
+
+    /// Gets the sampling weight of an instruction
+    ///
+    /// Unless overridden in `weights`, the weight is the inverse frequency of the opcode of
+    /// the instruction in the corpus, so that each opcode is sampled equally often
+    /// regardless of the number of operand variants in the corpus.
+    ///
+    /// # Arguments
+    ///
+    /// * `instruction` - Bytes of the instruction
+    ///
+    /// # Returns
+    ///
+    /// * `f64` - Weight of the instruction, zero if it is not part of the corpus
+    pub fn weight(&self, instruction: &[u8]) -> f64 {
+        let index = self
+            .sampling_table
+            .partition_point(|(bytes, _)| bytes.as_slice() < instruction);
+        match self.sampling_table.get(index) {
+            Some((bytes, cumulative)) if bytes.as_slice() == instruction => {
+                let previous = index
+                    .checked_sub(1)
+                    .map_or(0.0, |index| self.sampling_table[index].1);
+                cumulative - previous
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Assembles a byte sequence from instructions picked by weight
+    ///
+    /// # Arguments
+    ///
+    /// * `random` - Random source for the picks
+    /// * `count` - Number of instructions to pick
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<u8>` - Concatenated bytes of the picked instructions, empty for an empty corpus
+    pub fn sample_weighted<R: RngCore>(&self, random: &mut R, count: usize) -> Vec<u8> {
+        let mut code = Vec::new();
+        let Some((_, total)) = self.sampling_table.last() else {
+            return code;
+        };
+        for _ in 0..count {
+            let target = (random.next_u64() >> 11) as f64 / (1u64 << 53) as f64 * total;
+            let index = self
+                .sampling_table
+                .partition_point(|(_, cumulative)| *cumulative <= target)
+                .min(self.sampling_table.len() - 1);
+            code.extend_from_slice(&self.sampling_table[index].0);
+        }
+        code
+    }
+
+    /// Recomputes the cumulative weights used for sampling
+    fn update_sampling_table(&mut self) {
+        let mut opcode_frequency = BTreeMap::<&[u8], usize>::new();
+        for instruction in &self.instructions {
+            *opcode_frequency
+                .entry(opcode(&instruction.bytes))
+                .or_default() += 1;
+        }
+
+        let mut cumulative = 0.0;
+        let mut table = Vec::with_capacity(self.instructions.len());
+        for instruction in &self.instructions {
+            let weight = match self.weights.get(&instruction.bytes) {
+                Some(weight) => weight.max(0.0),
+                None => 1.0 / opcode_frequency[opcode(&instruction.bytes)] as f64,
+            };
+            cumulative += weight;
+            table.push((instruction.bytes.clone(), cumulative));
+        }
+        self.sampling_table = table;
+    }
+}
+
+/// Default filter when building a corpus from a binary
+///
+/// Privileged instructions would fault in the fuzzing context and control flow transfers
+/// branch to targets that are meaningless outside of the original binary, so both are dropped.
+///
+/// # Arguments
+///
+/// * `instruction` - Decoded instruction
+///
+/// # Returns
+///
+/// * `bool` - Whether the instruction should be part of the corpus
+pub fn default_instruction_filter(instruction: &Instruction) -> bool {
+    !instruction.is_privileged()
+        && !matches!(
+            instruction.flow_control(),
+            FlowControl::Call
+                | FlowControl::IndirectCall
+                | FlowControl::Return
+                | FlowControl::IndirectBranch
+                | FlowControl::ConditionalBranch
+                | FlowControl::UnconditionalBranch
+        )
+}
+
+/// Legacy prefixes of x86 instructions
+const LEGACY_PREFIXES: [u8; 11] = [
+    0x26, 0x2E, 0x36, 0x3E, 0x64, 0x65, 0x66, 0x67, 0xF0, 0xF2, 0xF3,
+];
+
+/// Extracts the opcode bytes of an x86 instruction, skipping legacy and REX prefixes
+///
+/// # Arguments
+///
+/// * `bytes` - Bytes of the instruction
+///
+/// # Returns
+///
+/// * `&[u8]` - One to three opcode bytes, fewer if the instruction is truncated
+fn opcode(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|byte| !LEGACY_PREFIXES.contains(byte) && byte & 0xF0 != 0x40)
+        .unwrap_or(bytes.len());
+    let length = match bytes[start..] {
+        [0x0F, 0x38 | 0x3A, ..] => 3,
+        [0x0F, ..] => 2,
+        _ => 1,
+    };
+    &bytes[start..(start + length).min(bytes.len())]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instruction_corpus::{opcode, CorpusInstruction, InstructionCorpus};
+    use alloc::collections::BTreeMap;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use rand_core::RngCore;
+
+    /// Deterministic xorshift random source for tests
+    struct TestRng(u64);
+
+    impl RngCore for TestRng {
+        fn next_u32(&mut self) -> u32 {
+            self.next_u64() as u32
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn fill_bytes(&mut self, dst: &mut [u8]) {
+            for byte in dst.iter_mut() {
+                *byte = self.next_u32() as u8;
+            }
+        }
+    }
+
+    fn instruction(bytes: &[u8]) -> CorpusInstruction {
+        CorpusInstruction {
+            bytes: bytes.to_vec(),
+            valid: true,
+        }
+    }
+
+    /// 90 operand variants of `mov r/m32, r32` and 10 instructions with unique opcodes
+    fn synthetic_corpus() -> (Vec<CorpusInstruction>, Vec<Vec<u8>>) {
+        let rare = vec![
+            vec![0xF4],
+            vec![0xCC],
+            vec![0xC3],
+            vec![0x9C],
+            vec![0x9D],
+            vec![0xFC],
+            vec![0x0F, 0xA2],
+            vec![0x0F, 0x0B],
+            vec![0x0F, 0x31],
+            vec![0x66, 0x0F, 0x38, 0x00, 0xC0],
+        ];
+        let instructions = (0..90u8)
+            .map(|modrm| instruction(&[0x89, modrm]))
+            .chain(rare.iter().map(|bytes| instruction(bytes)))
+            .collect();
+        (instructions, rare)
+    }
+
+    #[test]
+    pub fn test_opcode() {
+        assert_eq!(opcode(&[0x89, 0xC0]), &[0x89]);
+        assert_eq!(opcode(&[0x48, 0x89, 0xC0]), &[0x89]);
+        assert_eq!(opcode(&[0xF3, 0x0F, 0xB8, 0xC0]), &[0x0F, 0xB8]);
+        assert_eq!(
+            opcode(&[0x66, 0x0F, 0x3A, 0x0F, 0xC1, 0x08]),
+            &[0x0F, 0x3A, 0x0F]
+        );
+        assert_eq!(opcode(&[0x66]), &[] as &[u8]);
+    }
+
+    #[test]
+    pub fn test_deduplication() {
+        let (mut instructions, _) = synthetic_corpus();
+        instructions.extend(instructions.clone());
+        assert_eq!(
+            InstructionCorpus::new(instructions.clone())
+                .instructions
+                .len(),
+            100
+        );
+
+        let data = postcard::to_allocvec(&(
+            instructions,
+            BTreeMap::<Vec<u8>, f64>::new(),
+            BTreeMap::<Vec<u8>, u64>::new(),
+        ))
+        .unwrap();
+        let corpus = postcard::from_bytes::<InstructionCorpus>(&data).unwrap();
+        assert_eq!(corpus.instructions.len(), 100);
+        assert_eq!(corpus.weight(&[0xF4]), 1.0);
+    }
+
+    #[test]
+    pub fn test_inverse_frequency_weights() {
+        let (instructions, _) = synthetic_corpus();
+        let corpus = InstructionCorpus::new(instructions);
+        assert!((corpus.weight(&[0x89, 0x00]) - 1.0 / 90.0).abs() < 1e-12);
+        assert_eq!(corpus.weight(&[0x0F, 0xA2]), 1.0);
+        assert_eq!(corpus.weight(&[0x0F, 0xA3]), 0.0);
+
+        let corpus = corpus.with_weights(BTreeMap::from([(vec![0x0F, 0xA2], 5.0)]));
+        assert_eq!(corpus.weight(&[0x0F, 0xA2]), 5.0);
+        assert_eq!(corpus.weight(&[0xF4]), 1.0);
+    }
+
+    #[test]
+    pub fn test_rare_instructions_over_represented() {
+        const PICKS: usize = 10000;
+
+        let (instructions, rare) = synthetic_corpus();
+        let corpus = InstructionCorpus::new(instructions);
+
+        let mut random = TestRng(17);
+        let mut rare_picks = 0;
+        for _ in 0..PICKS {
+            let code = corpus.sample_weighted(&mut random, 1);
+            if rare.contains(&code) {
+                rare_picks += 1;
+            }
+        }
+
+        // uniform sampling picks a rare instruction with 10%, weighted sampling with 10/11
+        let fraction = rare_picks as f64 / PICKS as f64;
+        assert!(fraction > 0.85, "rare fraction {fraction}");
+
+        let code = corpus.sample_weighted(&mut random, 4);
+        assert!(code.len() >= 4);
+        assert!(InstructionCorpus::new(Vec::new())
+            .sample_weighted(&mut random, 4)
+            .is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    pub fn test_from_binary() {
+        let code = [
+            0x48, 0x89, 0xC8, // mov rax, rcx
+            0x90, // nop
+            0xE8, 0x00, 0x00, 0x00, 0x00, // call $+5
+            0x0F, 0xA2, // cpuid
+            0xFA, // cli
+            0x90, // nop
+            0x74, 0x00, // je $+2
+            0xC3, // ret
+        ];
+
+        let corpus = InstructionCorpus::from_binary(&code, 64).unwrap();
+        let bytes = corpus
+            .instructions
+            .iter()
+            .map(|instruction| instruction.bytes.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            bytes,
+            vec![vec![0x0F, 0xA2], vec![0x48, 0x89, 0xC8], vec![0x90]]
+        );
+        assert!(corpus
+            .instructions
+            .iter()
+            .all(|instruction| instruction.valid));
+        assert_eq!(corpus.frequencies[&vec![0x90]], 2);
+        assert_eq!(corpus.frequencies[&vec![0x0F, 0xA2]], 1);
+
+        let corpus = InstructionCorpus::from_binary_filtered(&code, 64, |_| true).unwrap();
+        assert_eq!(corpus.instructions.len(), 7);
+        assert!(corpus
+            .frequencies
+            .contains_key(&vec![0xE8, 0x00, 0x00, 0x00, 0x00]));
+        assert!(corpus.frequencies.contains_key(&vec![0xFA]));
+
+        assert!(InstructionCorpus::from_binary(&code, 8).is_err());
+    }
+
+    #[test]
+    pub fn test_merge() {
+        let mut corpus = InstructionCorpus::new(vec![CorpusInstruction {
+            bytes: vec![0x90],
+            valid: true,
+        }]);
+        corpus.frequencies.insert(vec![0x90], 2);
+
+        let mut other = InstructionCorpus::new(vec![
+            CorpusInstruction {
+                bytes: vec![0x90],
+                valid: true,
+            },
+            CorpusInstruction {
+                bytes: vec![0x0F, 0xA2],
+                valid: true,
+            },
+        ]);
+        other.frequencies.insert(vec![0x90], 3);
+        other.frequencies.insert(vec![0x0F, 0xA2], 1);
+
+        corpus.merge(other);
+        assert_eq!(corpus.instructions.len(), 2);
+        assert_eq!(corpus.frequencies[&vec![0x90]], 5);
+        assert_eq!(corpus.weight(&[0x0F, 0xA2]), 1.0);
+    }
 }
@@ -13,14 +13,19 @@
 //! for communication between components.
 #![no_std]
 
-use crate::genetic_pool::GeneticSampleRating;
+use crate::auth::{OtaKey, OTA_MAC_SIZE};
+use crate::genetic_pool::{GeneticSampleRating, PoolSnapshot};
+use crate::instruction_corpus::CorpusInstruction;
+use alloc::borrow::Cow;
 use alloc::collections::BTreeMap;
 use alloc::format;
 use alloc::string::String;
 use alloc::vec::Vec;
-use core::fmt::Debug;
+use core::fmt::{Debug, Display, Formatter};
+use core::str::FromStr;
 use hypervisor::state::{GuestRegisters, VmExitReason, VmState};
 use performance_timing::measurements::MeasureValues;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use ucode_compiler_dynamic::instruction::Instruction;
 use ucode_compiler_dynamic::sequence_word::SequenceWord;
@@ -28,7 +33,9 @@ use x86_perf_counter::PerfEventSpecifier;
 
 extern crate alloc;
 
+pub mod auth;
 pub mod decoder;
+pub mod fragments;
 pub mod genetic_pool;
 pub mod instruction_corpus;
 
@@ -65,6 +72,27 @@ pub enum ReportExecutionProblem {
     VeryLikelyBug,
     /// Access to coverage measurement area
     AccessCoverageArea,
+    /// Performance counter reported an impossible value
+    PmcAnomaly {
+        /// Index of the performance counter
+        counter_index: u8,
+        /// Event the performance counter was configured with
+        event: PerfEventSpecifier,
+        /// Observed counter value
+        observed: u64,
+        /// Inclusive range of plausible counter values
+        expected_range: (u64, u64),
+    },
+    /// Executions with two randomized initial register files diverge in a way that is not
+    /// explained by the differing registers
+    InitialStateSensitivity {
+        /// Seeds of the initial register files of both executions
+        state_seeds: (u64, u64),
+        /// Exit reasons of both executions, if they differ although no randomized register is read
+        exits: Option<(VmExitReason, VmExitReason)>,
+        /// Registers with unexplained differences
+        registers: Vec<String>,
+    },
 }
 
 impl ReportExecutionProblem {
@@ -72,6 +100,16 @@ impl ReportExecutionProblem {
     pub const MAX_PER_PACKET: usize = 3;
 }
 
+/// Upper bound of plausible performance counter values of a single speculation sample
+///
+/// A sample executes only a handful of instructions, values above are counter jumps.
+pub const PMC_PLAUSIBLE_MAXIMUM: u64 = 1_000_000_000;
+
+/// Width of the general purpose performance counters in bits
+///
+/// Counter values wrap around at `2^PMC_COUNTER_WIDTH`.
+pub const PMC_COUNTER_WIDTH: u32 = 48;
+
 /// Result of executing a fuzzing operation
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ExecutionResult {
@@ -125,8 +163,33 @@ pub enum OtaD2CUnreliable {
     Ack(u64),
     /// Log message with level
     LogMessage { level: log::Level, message: String },
+    /// Request to retransmit fragments of a chunked transport, see [`fragments`]
+    MissingFragments {
+        /// Session identifier of the payload
+        session: u16,
+        /// Message identifier of the payload
+        id: u64,
+        /// Indices of the missing fragments
+        fragments: Vec<u64>,
+    },
+    /// Periodic liveness and health report, sent every [`HEARTBEAT_INTERVAL_MS`]
+    Heartbeat {
+        /// Milliseconds since the device started
+        uptime_ms: u64,
+        /// Free memory available to the device allocator
+        free_heap_bytes: u64,
+        /// Number of samples executed since the device started
+        samples_executed: u64,
+        /// Last error reported to the controller
+        last_error: Option<String>,
+        /// Health statistics of the connection to the controller
+        connection_stats: ConnectionStats,
+    },
 }
 
+/// Interval in which the device sends [`OtaD2CUnreliable::Heartbeat`] messages while it is idle
+pub const HEARTBEAT_INTERVAL_MS: u64 = 2000;
+
 /// Reliable device-to-controller transport messages
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum OtaD2CTransport {
@@ -155,6 +218,9 @@ pub enum OtaD2CTransport {
     Capabilities {
         /// Whether coverage collection is supported
         coverage_collection: bool,
+        /// Number of microcode addresses hooked simultaneously per coverage collection run,
+        /// `0` if coverage collection is not supported
+        coverage_batch_size: u16,
         /// CPU manufacturer string
         manufacturer: String,
         /// Number of performance counters
@@ -167,6 +233,8 @@ pub enum OtaD2CTransport {
         processor_version_ecx: u32,
         /// CPUID 1:EDX (feature flags)
         processor_version_edx: u32,
+        /// Revision of the loaded microcode update (IA32_BIOS_SIGN_ID)
+        ucode_revision: u32,
     },
     /// Coverage information
     Coverage {
@@ -198,11 +266,110 @@ pub enum OtaD2CTransport {
         pmc_stable: Vec<bool>,
     },
     /// Result of microcode speculation test
-    UCodeSpeculationResult(SpeculationResult),
+    UCodeSpeculationResult {
+        /// Result of the tested triad
+        result: SpeculationResult,
+        /// Result of the baseline triad, if one was requested
+        baseline: Option<SpeculationResult>,
+    },
     /// Tracing result
     TraceResult(TraceResult),
     /// Evaluation Scenario Result, arbitrary data
     ScenarioResult(String, Vec<u8>),
+    /// Response to a latency measurement request
+    Pong {
+        /// Nonce of the corresponding ping
+        nonce: u64,
+        /// Unix time of the controller in nanoseconds when sending the ping (echoed)
+        sent_timestamp: u64,
+        /// Timestamp counter of the device when the ping was received
+        device_cycles: u64,
+    },
+    /// Link statistics of the device
+    LinkStats(LinkStats),
+    /// Result of a coverage confirmation
+    CoverageConfirmation {
+        /// Confirmed microcode address
+        address: u16,
+        /// Whether the address was hit, `None` if coverage could not be collected
+        hit: Option<bool>,
+    },
+    /// Answer to [`OtaC2DTransport::Hello`]
+    HelloAck {
+        /// [`OTA_MESSAGE_VERSION`] of the device
+        version: u16,
+    },
+    /// Answer to [`OtaC2DTransport::ReadMsr`] and [`OtaC2DTransport::WriteMsr`]
+    MsrValue {
+        /// Model specific register that was accessed
+        msr: u32,
+        /// Value read from the register, for writes the written value
+        value: u64,
+    },
+    /// Answer to a refused or faulting MSR access
+    MsrAccessFailed {
+        /// Model specific register that was accessed
+        msr: u32,
+        /// Reason of the failure
+        error: MsrAccessError,
+    },
+    /// Snapshot of the genetic pool of the device, sent before risky operations
+    PoolSnapshot(PoolSnapshot),
+    /// Answer to [`OtaC2DTransport::SetExecutionConfig`], the effective configuration
+    ExecutionConfig(ExecutionConfig),
+    /// The previous run of the device ended while executing a sample, sent once after booting
+    CrashReport {
+        /// Number of runs that ended while executing a sample, including the previous run
+        crash_count: u32,
+        /// Fingerprint of the sample executed last, see
+        /// [`genetic_pool::SampleProvenance::fingerprint`]
+        sample_fingerprint: u64,
+        /// Phase of the execution the device crashed in
+        phase: CrashPhase,
+    },
+}
+
+/// Phase of a sample execution the device crashed in, see [`OtaD2CTransport::CrashReport`]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CrashPhase {
+    /// Execution of the sample without coverage hooks
+    Execution,
+    /// Coverage collection with a hook on the address
+    CoverageCollection(u16),
+    /// Coverage collection with several hooks, the first one on the address
+    CoverageCollectionBatch(u16),
+}
+
+/// Model specific registers the controller may write to, see [`OtaC2DTransport::WriteMsr`]
+///
+/// Devices built with the `unsafe-msr` feature accept writes to any register.
+pub const WRITABLE_MSRS: &[u32] = &[
+    0x48,  // IA32_SPEC_CTRL
+    0x49,  // IA32_PRED_CMD
+    0x10B, // IA32_FLUSH_CMD
+    0x122, // IA32_TSX_CTRL
+    0x123, // IA32_MCU_OPT_CTRL
+    0x1A4, // MSR_MISC_FEATURE_CONTROL, prefetcher disable bits
+];
+
+/// Reasons for a failed access of a model specific register
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum MsrAccessError {
+    /// The register is not in [`WRITABLE_MSRS`]
+    NotAllowed,
+    /// The access raised a general protection fault, e.g. the register does not exist
+    GeneralProtectionFault,
+}
+
+impl Display for MsrAccessError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            MsrAccessError::NotAllowed => write!(f, "writing this MSR is not allowed"),
+            MsrAccessError::GeneralProtectionFault => {
+                write!(f, "the MSR access raised a general protection fault")
+            }
+        }
+    }
 }
 
 /// Result of a speculation test
@@ -214,6 +381,108 @@ pub struct SpeculationResult {
     pub arch_after: GuestRegisters,
     /// Performance counter values
     pub perf_counters: Vec<u64>,
+    /// Whether each performance counter overflowed during the measurement
+    #[serde(default)]
+    pub overflowed: Vec<bool>,
+}
+
+impl SpeculationResult {
+    /// Executes a triad and, if given, a baseline triad with the same executor
+    ///
+    /// The test triad is always executed first, so that both runs observe the same
+    /// ordering regardless of whether a baseline was requested.
+    ///
+    /// # Arguments
+    ///
+    /// * `triad` - Triad to test
+    /// * `baseline_triad` - Optional triad to compare against
+    /// * `execute` - Function executing a single triad
+    ///
+    /// # Returns
+    ///
+    /// * `(SpeculationResult, Option<SpeculationResult>)` - Result of the test triad and of the baseline triad
+    pub fn execute_paired<F: FnMut([Instruction; 3]) -> SpeculationResult>(
+        triad: [Instruction; 3],
+        baseline_triad: Option<[Instruction; 3]>,
+        mut execute: F,
+    ) -> (SpeculationResult, Option<SpeculationResult>) {
+        let result = execute(triad);
+        let baseline = baseline_triad.map(execute);
+        (result, baseline)
+    }
+
+    /// Computes the difference of each performance counter to a baseline
+    ///
+    /// Counters wrap around at [`PMC_COUNTER_WIDTH`] bits, so the difference is taken modulo
+    /// the counter width and interpreted as signed value: a counter that passed the wraparound
+    /// boundary since the baseline yields a small positive delta instead of a huge negative one.
+    ///
+    /// # Arguments
+    ///
+    /// * `baseline` - Result to compare against, using the same counter setup
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<i64>` - Delta of each counter present in both results
+    pub fn counter_deltas(&self, baseline: &SpeculationResult) -> Vec<i64> {
+        const MASK: u64 = (1 << PMC_COUNTER_WIDTH) - 1;
+        const SIGN: u64 = 1 << (PMC_COUNTER_WIDTH - 1);
+
+        self.perf_counters
+            .iter()
+            .zip(baseline.perf_counters.iter())
+            .map(|(value, baseline)| {
+                let delta = value.wrapping_sub(*baseline) & MASK;
+                if delta & SIGN != 0 {
+                    delta as i64 - (1 << PMC_COUNTER_WIDTH)
+                } else {
+                    delta as i64
+                }
+            })
+            .collect()
+    }
+
+    /// Checks the performance counter values for impossible behavior
+    ///
+    /// Every sample retires instructions, so a zero instructions-retired count is reported
+    /// as well as any counter exceeding [`PMC_PLAUSIBLE_MAXIMUM`].
+    ///
+    /// # Arguments
+    ///
+    /// * `perf_counter_setup` - Events the performance counters were configured with
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<ReportExecutionProblem>` - A [`ReportExecutionProblem::PmcAnomaly`] per anomalous counter
+    pub fn pmc_anomalies(
+        &self,
+        perf_counter_setup: &[PerfEventSpecifier],
+    ) -> Vec<ReportExecutionProblem> {
+        perf_counter_setup
+            .iter()
+            .zip(self.perf_counters.iter())
+            .enumerate()
+            .filter_map(|(index, (event, observed))| {
+                let minimum = if *event == x86_perf_counter::INSTRUCTIONS_RETIRED {
+                    1
+                } else {
+                    0
+                };
+                let expected_range = (minimum, PMC_PLAUSIBLE_MAXIMUM);
+
+                if (expected_range.0..=expected_range.1).contains(observed) {
+                    None
+                } else {
+                    Some(ReportExecutionProblem::PmcAnomaly {
+                        counter_index: index as u8,
+                        event: *event,
+                        observed: *observed,
+                        expected_range,
+                    })
+                }
+            })
+            .collect()
+    }
 }
 
 /// Type alias for code bytes
@@ -226,6 +495,15 @@ pub enum OtaC2DUnreliable {
     NOP,
     /// Acknowledgment of a message
     Ack(u64),
+    /// Request to retransmit fragments of a chunked transport, see [`fragments`]
+    MissingFragments {
+        /// Session identifier of the payload
+        session: u16,
+        /// Message identifier of the payload
+        id: u64,
+        /// Indices of the missing fragments
+        fragments: Vec<u64>,
+    },
 }
 
 /// Reliable controller-to-device transport messages
@@ -250,7 +528,11 @@ pub enum OtaC2DTransport {
     /// Request list of blacklisted addresses
     GiveMeYourBlacklistedAddresses,
     /// Request performance timing report
-    ReportPerformanceTiming,
+    ReportPerformanceTiming {
+        /// Report the measurements taken since the previous report of this run instead of the
+        /// lifetime totals
+        delta: bool,
+    },
     /// Set random number generator seed
     SetRandomSeed {
         /// Random seed value
@@ -280,6 +562,8 @@ pub enum OtaC2DTransport {
         sequence_word: SequenceWord,
         /// Performance counter configuration
         perf_counter_setup: Vec<PerfEventSpecifier>,
+        /// Triad to execute as baseline, e.g. a fence; no baseline is run if `None`
+        baseline_triad: Option<[Instruction; 3]>,
     },
     /// Test PMC stability
     TestIfPMCStable {
@@ -292,14 +576,525 @@ pub enum OtaC2DTransport {
     RunScenario(String, Vec<u8>),
     /// Reset blacklist
     ResetBlacklist,
+    /// Set the execution policy of the device
+    SetExecutionPolicy {
+        /// Granularity of the microcode coverage collection
+        coverage_granularity: CoverageGranularity,
+    },
+    /// Latency measurement request, the device answers with a pong
+    Ping {
+        /// Nonce to match the pong to this request
+        nonce: u64,
+        /// Unix time of the controller in nanoseconds when sending the ping
+        sent_timestamp: u64,
+    },
+    /// Request the link statistics of the device
+    GetLinkStats,
+    /// Execute a code sample with a hook only on a single address to confirm its coverage
+    ConfirmCoverage {
+        /// Code to execute
+        sample: Code,
+        /// Microcode address to confirm
+        address: u16,
+    },
+    /// Handshake announcing the protocol version, the device answers with a hello-ack
+    Hello {
+        /// [`OTA_MESSAGE_VERSION`] of the controller
+        version: u16,
+    },
+    /// Read a model specific register
+    ReadMsr {
+        /// Model specific register to read
+        msr: u32,
+    },
+    /// Write a model specific register, only registers in [`WRITABLE_MSRS`] are accepted
+    WriteMsr {
+        /// Model specific register to write
+        msr: u32,
+        /// Value to write
+        value: u64,
+    },
+    /// Restore a genetic pool snapshot previously sent by the device
+    RestorePool(PoolSnapshot),
+    /// Set the execution configuration of the device, the device answers with the effective
+    /// configuration, see [`ExecutionConfig::clamped`]
+    SetExecutionConfig {
+        /// Maximum number of VM steps of the trace of a sample
+        max_vm_iterations: u64,
+        /// Maximum number of states recorded when state tracing a sample
+        state_trace_len: u16,
+        /// Seed to randomize the initial general purpose registers, `None` for the default state
+        initial_reg_seed: Option<u64>,
+        /// Number of coverage entries sent per coverage message
+        coverage_batch: u8,
+        /// Maximum duration of a single VM entry of a sample in TSC ticks
+        sample_timeout_tsc: u64,
+        /// Execute every sample again with two randomized initial register files
+        differential_execution: bool,
+        /// Restore the writable guest memory from a snapshot before every execution
+        reset_guest_memory: bool,
+    },
+    /// Add instructions to the dictionary of the genetic pool mutations, see
+    /// [`genetic_pool::MutationStrategy::Dictionary`]
+    ExtendDictionary {
+        /// Instructions to add
+        instructions: Vec<CorpusInstruction>,
+    },
+}
+
+/// Granularity of the microcode coverage collection
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum CoverageGranularity {
+    /// Hook every hookable microcode instruction
+    #[default]
+    Instruction,
+    /// Hook only the first instruction of every triad; execution within a triad is not
+    /// resolved further
+    Triad,
+}
+
+impl CoverageGranularity {
+    /// Checks if a hookable microcode address is collected with this granularity
+    pub fn includes_address(&self, address: u16) -> bool {
+        match self {
+            CoverageGranularity::Instruction => true,
+            CoverageGranularity::Triad => address % 4 == 0,
+        }
+    }
+}
+
+impl FromStr for CoverageGranularity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "instruction" => Ok(CoverageGranularity::Instruction),
+            "triad" => Ok(CoverageGranularity::Triad),
+            _ => Err(format!(
+                "Unknown coverage granularity: {}, expected instruction or triad",
+                s
+            )),
+        }
+    }
+}
+
+/// Upper bound of [`ExecutionConfig::max_vm_iterations`]
+pub const MAX_VM_ITERATIONS: u64 = 100_000;
+/// Upper bound of [`ExecutionConfig::state_trace_len`]
+pub const MAX_STATE_TRACE_LEN: u16 = 1024;
+/// Upper bound of [`ExecutionConfig::coverage_batch`], limited by the payload size of a message
+pub const MAX_COVERAGE_BATCH: u8 = 200;
+/// Lower bound of [`ExecutionConfig::sample_timeout_tsc`], shorter timeouts preempt the VM entry itself
+pub const MIN_SAMPLE_TIMEOUT_TSC: u64 = 10_000;
+/// Upper bound of [`ExecutionConfig::sample_timeout_tsc`], a few seconds on common processors
+pub const MAX_SAMPLE_TIMEOUT_TSC: u64 = 10_000_000_000;
+
+/// Execution configuration of the device, set by [`OtaC2DTransport::SetExecutionConfig`]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct ExecutionConfig {
+    /// Maximum number of VM steps of the trace of a sample
+    pub max_vm_iterations: u64,
+    /// Maximum number of states recorded when state tracing a sample
+    pub state_trace_len: u16,
+    /// Seed to randomize the initial general purpose registers, `None` for the default state
+    pub initial_reg_seed: Option<u64>,
+    /// Number of coverage entries sent per coverage message
+    pub coverage_batch: u8,
+    /// Maximum duration of a single VM entry of a sample in TSC ticks, enforced by the
+    /// VMX-preemption timer
+    pub sample_timeout_tsc: u64,
+    /// Execute every sample again with two randomized initial register files, seeded by
+    /// [`OtaC2DTransport::SetRandomSeed`], and report unexplained divergences
+    pub differential_execution: bool,
+    /// Restore the writable guest memory from a snapshot before every execution, so residual
+    /// memory of a previous sample can not influence the next one
+    pub reset_guest_memory: bool,
+}
+
+impl Default for ExecutionConfig {
+    fn default() -> Self {
+        Self {
+            max_vm_iterations: 500,
+            state_trace_len: 300,
+            initial_reg_seed: None,
+            coverage_batch: MAX_COVERAGE_BATCH,
+            sample_timeout_tsc: 1_000_000,
+            differential_execution: false,
+            reset_guest_memory: false,
+        }
+    }
+}
+
+impl ExecutionConfig {
+    /// Clamps the configuration to values the device can handle
+    ///
+    /// A zero iteration limit would trace without bound, and a zero batch size would never
+    /// transmit coverage, so all limits are at least one.
+    ///
+    /// # Returns
+    ///
+    /// * `ExecutionConfig` - The effective configuration
+    pub fn clamped(&self) -> Self {
+        Self {
+            max_vm_iterations: self.max_vm_iterations.clamp(1, MAX_VM_ITERATIONS),
+            state_trace_len: self.state_trace_len.clamp(1, MAX_STATE_TRACE_LEN),
+            initial_reg_seed: self.initial_reg_seed,
+            coverage_batch: self.coverage_batch.clamp(1, MAX_COVERAGE_BATCH),
+            sample_timeout_tsc: self
+                .sample_timeout_tsc
+                .clamp(MIN_SAMPLE_TIMEOUT_TSC, MAX_SAMPLE_TIMEOUT_TSC),
+            differential_execution: self.differential_execution,
+            reset_guest_memory: self.reset_guest_memory,
+        }
+    }
+}
+
+/// Link quality statistics of one side of a connection
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct LinkStats {
+    /// Number of transmitted packets, including retransmissions
+    pub sent: u64,
+    /// Number of reliable packets that were acknowledged by the remote
+    pub acked: u64,
+    /// Number of retransmissions of reliable packets
+    pub retransmitted: u64,
+    /// Number of reliable packets that were not acknowledged after all attempts
+    pub dropped: u64,
+    /// Number of received packets dropped due to a missing or invalid authentication code
+    pub unauthenticated: u64,
+}
+
+impl LinkStats {
+    /// Records the transmission of a packet
+    ///
+    /// # Arguments
+    ///
+    /// * `attempt` - Zero-based transmission attempt of this packet, attempts >0 are retransmissions
+    pub fn record_sent(&mut self, attempt: u8) {
+        self.sent += 1;
+        if attempt > 0 {
+            self.retransmitted += 1;
+        }
+    }
+
+    /// Records the outcome of a reliable transmission, shared by the connections of both ends
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - `Some(Ok(()))` if the remote acknowledged the packet, `Some(Err(_))` if the
+    ///   transmission failed locally and `None` if no attempt was acknowledged
+    /// * `not_acknowledged` - Error reported if no attempt was acknowledged
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), E>` - Result of the transmission
+    pub fn record_outcome<E>(
+        &mut self,
+        status: Option<Result<(), E>>,
+        not_acknowledged: E,
+    ) -> Result<(), E> {
+        match status {
+            Some(Ok(())) => self.acked += 1,
+            Some(Err(_)) => {}
+            None => self.dropped += 1,
+        }
+        status.unwrap_or(Err(not_acknowledged))
+    }
+
+    /// Records a received packet that failed authentication, see [`auth`]
+    pub fn record_unauthenticated(&mut self) {
+        self.unauthenticated += 1;
+    }
+}
+
+impl Display for LinkStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "sent: {}, acked: {}, retransmitted: {}, dropped: {}, unauthenticated: {}",
+            self.sent, self.acked, self.retransmitted, self.dropped, self.unauthenticated
+        )
+    }
+}
+
+/// Connection health statistics of the device, reported in [`OtaD2CUnreliable::Heartbeat`]s
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct ConnectionStats {
+    /// Statistics of the transmitted packets
+    pub link: LinkStats,
+    /// Number of packets received from the controller
+    pub received: u64,
+    /// Number of acknowledgements that did not arrive before the retransmission timeout
+    pub acks_lost: u64,
+    /// Number of chunked transports that could not be reassembled
+    pub reassembly_failures: u64,
+}
+
+impl Display for ConnectionStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{}, received: {}, acks lost: {}, reassembly failures: {}",
+            self.link, self.received, self.acks_lost, self.reassembly_failures
+        )
+    }
+}
+
+/// Calculates the time to wait for the acknowledgement of a transmission attempt
+///
+/// The timeout doubles with each retransmission up to a maximum, so that a briefly unreachable
+/// remote is not flooded. Up to a quarter of the timeout is added as jitter, so that peers
+/// losing packets at the same time do not retransmit in lockstep.
+///
+/// # Arguments
+///
+/// * `base_timeout` - Timeout of the first transmission attempt
+/// * `max_timeout` - Upper bound of the timeout before the jitter is added
+/// * `attempt` - Zero-based transmission attempt
+/// * `random` - Random value selecting the jitter
+///
+/// # Returns
+///
+/// * `u64` - The timeout, in the unit of `base_timeout`
+pub fn retransmit_timeout(base_timeout: u64, max_timeout: u64, attempt: u8, random: u64) -> u64 {
+    let timeout = base_timeout
+        .checked_shl(attempt as u32)
+        .filter(|timeout| timeout >> attempt == base_timeout)
+        .unwrap_or(u64::MAX)
+        .min(max_timeout.max(base_timeout));
+    let jitter = timeout / 4;
+    timeout.saturating_add(if jitter > 0 { random % (jitter + 1) } else { 0 })
 }
 
 /// Maximum size of a message fragment
 pub const MAX_FRAGMENT_SIZE: u64 = 1200;
 /// Maximum size of a complete payload
 pub const MAX_PAYLOAD_SIZE: u64 = 4_000_000; // ~4MB
+/// Deflate compression level of chunked transport payloads
+const PAYLOAD_COMPRESSION_LEVEL: u8 = 6;
+
+/// Encodes the payload of a chunked transport message
+///
+/// The payload is deflate compressed if this reduces its size.
+///
+/// # Arguments
+///
+/// * `data` - The serialized message
+///
+/// # Returns
+///
+/// The encoded payload and whether it is compressed
+pub fn encode_payload(data: &[u8]) -> (Cow<'_, [u8]>, bool) {
+    let compressed = miniz_oxide::deflate::compress_to_vec(data, PAYLOAD_COMPRESSION_LEVEL);
+    if compressed.len() < data.len() {
+        (Cow::Owned(compressed), true)
+    } else {
+        (Cow::Borrowed(data), false)
+    }
+}
+
+/// Decodes the reassembled payload of a chunked transport message, see [`encode_payload`]
+///
+/// # Arguments
+///
+/// * `data` - The reassembled payload
+/// * `compressed` - Whether the payload is compressed
+///
+/// # Returns
+///
+/// - `Ok(..)` - The serialized message, at most [`MAX_PAYLOAD_SIZE`] bytes
+/// - `Err(..)` - The payload is too large or could not be decompressed
+pub fn decode_payload(data: &[u8], compressed: bool) -> Result<Cow<'_, [u8]>, String> {
+    let payload = if compressed {
+        miniz_oxide::inflate::decompress_to_vec_with_limit(data, MAX_PAYLOAD_SIZE as usize)
+            .map(Cow::Owned)
+            .map_err(|e| format!("Failed to decompress OTA payload: {}", e))?
+    } else {
+        Cow::Borrowed(data)
+    };
+
+    if payload.len() as u64 > MAX_PAYLOAD_SIZE {
+        return Err(format!(
+            "OTA payload of {} bytes exceeds the maximum of {} bytes",
+            payload.len(),
+            MAX_PAYLOAD_SIZE
+        ));
+    }
+
+    Ok(payload)
+}
+
+/// Version of the OTA protocol
+///
+/// - `1`: packets are sent without checksum
+/// - `2`: packets are followed by a trailer of the protocol version and a CRC32 over the
+///   serialized packet, see [`OtaD2C::serialize`]. The trailer is ignored by version `1` peers.
+///
+/// Authenticated packets use [`OTA_AUTHENTICATED_PROTOCOL_VERSION`] instead.
+pub const OTA_PROTOCOL_VERSION: u8 = 2;
+/// Version of the OTA protocol of authenticated packets
+///
+/// Packets are followed by a trailer of the protocol version and a message authentication code
+/// over the serialized packet and the version, keyed with a pre-shared [`OtaKey`], see
+/// [`OtaD2C::serialize_with_key`]. The code also detects corruption, so no checksum is sent.
+pub const OTA_AUTHENTICATED_PROTOCOL_VERSION: u8 = 3;
+/// Version of the OTA message definitions, exchanged by the [`OtaC2DTransport::Hello`] handshake
+///
+/// Must be increased whenever the OTA message enums change, so that a device and controller
+/// built from different revisions refuse to talk to each other instead of misinterpreting
+/// messages.
+pub const OTA_MESSAGE_VERSION: u16 = 15;
+/// Size of the trailer of [`OTA_PROTOCOL_VERSION`] `2` packets
+const OTA_TRAILER_SIZE: usize = 5;
+/// Size of the trailer of [`OTA_AUTHENTICATED_PROTOCOL_VERSION`] packets
+const OTA_AUTHENTICATED_TRAILER_SIZE: usize = 1 + OTA_MAC_SIZE;
+
+/// Errors that can occur when decoding a received OTA packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OtaDecodeError {
+    /// The packet could not be deserialized
+    Malformed(String),
+    /// The checksum of the packet does not match its content, the packet was corrupted
+    ChecksumMismatch {
+        /// Checksum sent with the packet
+        stored: u32,
+        /// Checksum of the received content
+        computed: u32,
+    },
+    /// The packet was sent by a peer speaking an unknown protocol version
+    UnsupportedVersion(u8),
+    /// A key is configured, but the packet is not authenticated
+    Unauthenticated,
+    /// The authentication code does not match, the packet was forged, corrupted or sent with a
+    /// different key
+    AuthenticationFailed,
+    /// The packet is authenticated, but no key is configured to verify it
+    KeyRequired,
+}
+
+impl OtaDecodeError {
+    /// Checks if the packet was rejected by the authentication, see [`auth`]
+    pub fn is_authentication_error(&self) -> bool {
+        matches!(
+            self,
+            OtaDecodeError::Unauthenticated
+                | OtaDecodeError::AuthenticationFailed
+                | OtaDecodeError::KeyRequired
+        )
+    }
+}
+
+impl Display for OtaDecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            OtaDecodeError::Malformed(message) => write!(f, "{}", message),
+            OtaDecodeError::ChecksumMismatch { stored, computed } => write!(
+                f,
+                "OTA packet checksum mismatch: stored {:08x}, computed {:08x}",
+                stored, computed
+            ),
+            OtaDecodeError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported OTA protocol version: {}", version)
+            }
+            OtaDecodeError::Unauthenticated => {
+                write!(
+                    f,
+                    "OTA packet is not authenticated, but a key is configured"
+                )
+            }
+            OtaDecodeError::AuthenticationFailed => {
+                write!(f, "OTA packet authentication failed")
+            }
+            OtaDecodeError::KeyRequired => {
+                write!(f, "OTA packet is authenticated, but no key is configured")
+            }
+        }
+    }
+}
+
+/// Serializes a packet followed by the trailer of the current [`OTA_PROTOCOL_VERSION`], or of
+/// [`OTA_AUTHENTICATED_PROTOCOL_VERSION`] if a key is given
+fn serialize_packet<T: Serialize>(packet: &T, key: Option<&OtaKey>) -> Result<Vec<u8>, String> {
+    let mut data = postcard::to_allocvec(packet)
+        .map_err(|e| format!("Failed to serialize OTA packet: {:?}", e))?;
+    match key {
+        Some(key) => {
+            data.push(OTA_AUTHENTICATED_PROTOCOL_VERSION);
+            let mac = key.mac(&data);
+            data.extend_from_slice(&mac);
+        }
+        None => {
+            let checksum = crc32fast::hash(&data);
+            data.push(OTA_PROTOCOL_VERSION);
+            data.extend_from_slice(&checksum.to_le_bytes());
+        }
+    }
+    Ok(data)
+}
+
+/// Deserializes a packet and verifies its trailer, see [`serialize_packet`]
+///
+/// Packets of version `1` peers carry no trailer and are accepted without verification, unless a
+/// key is given. With a key only authenticated packets are accepted.
+fn deserialize_packet<T: DeserializeOwned>(
+    data: &[u8],
+    key: Option<&OtaKey>,
+) -> Result<T, OtaDecodeError> {
+    let malformed =
+        |e| OtaDecodeError::Malformed(format!("Failed to deserialize OTA packet: {:?}", e));
+
+    if let Some(key) = key {
+        if data.len() < OTA_AUTHENTICATED_TRAILER_SIZE {
+            return Err(OtaDecodeError::Unauthenticated);
+        }
+        let (authenticated, mac) = data.split_at(data.len() - OTA_MAC_SIZE);
+        if authenticated.last() != Some(&OTA_AUTHENTICATED_PROTOCOL_VERSION) {
+            return Err(OtaDecodeError::Unauthenticated);
+        }
+        if !key.verify(authenticated, mac) {
+            return Err(OtaDecodeError::AuthenticationFailed);
+        }
+        return postcard::from_bytes(&authenticated[..authenticated.len() - 1]).map_err(malformed);
+    }
+
+    let mut mismatch = None;
+    if data.len() >= OTA_TRAILER_SIZE {
+        let (content, trailer) = data.split_at(data.len() - OTA_TRAILER_SIZE);
+        if trailer[0] == OTA_PROTOCOL_VERSION {
+            let stored = u32::from_le_bytes([trailer[1], trailer[2], trailer[3], trailer[4]]);
+            let computed = crc32fast::hash(content);
+            if stored == computed {
+                return postcard::from_bytes(content).map_err(malformed);
+            }
+            mismatch = Some(OtaDecodeError::ChecksumMismatch { stored, computed });
+        }
+    }
+
+    match (postcard::take_from_bytes::<T>(data), mismatch) {
+        // version 1 packet without trailer
+        (Ok((packet, [])), _) => Ok(packet),
+        (_, Some(mismatch)) => Err(mismatch),
+        (Ok((_, rest)), None) if rest.len() == OTA_TRAILER_SIZE => {
+            Err(OtaDecodeError::UnsupportedVersion(rest[0]))
+        }
+        (Ok((_, rest)), None)
+            if rest.len() == OTA_AUTHENTICATED_TRAILER_SIZE
+                && rest[0] == OTA_AUTHENTICATED_PROTOCOL_VERSION =>
+        {
+            Err(OtaDecodeError::KeyRequired)
+        }
+        (Ok(_), None) => Err(OtaDecodeError::Malformed(
+            "Trailing data after OTA packet".into(),
+        )),
+        (Err(e), None) => Err(malformed(e)),
+    }
+}
 
 /// Over-the-air message container
+///
+/// Every packet is protected by a checksum, see [`OTA_PROTOCOL_VERSION`], or by an authentication
+/// code, see [`OTA_AUTHENTICATED_PROTOCOL_VERSION`]. Corrupted fragments of a
+/// [`Ota::ChunkedTransport`] are dropped and requested again, see [`fragments`].
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Ota<Unreliable, Transport> {
     /// Unreliable message
@@ -313,16 +1108,18 @@ pub enum Ota<Unreliable, Transport> {
         /// Message content
         content: Transport,
     },
-    /// Chunked transport message
+    /// Chunked transport message, acknowledged once all fragments were received
     ChunkedTransport {
         /// Session identifier
         session: u16,
-        /// Message identifier
+        /// Message identifier, shared by all fragments of the payload
         id: u64,
         /// Fragment number
         fragment: u64,
         /// Total number of fragments
         total_fragments: u64,
+        /// Whether the reassembled payload is compressed, see [`encode_payload`]
+        compressed: bool,
         /// Fragment content
         content: Vec<u8>,
     },
@@ -412,12 +1209,31 @@ impl OtaD2C {
         }
     }
 
+    /// Serializes the packet followed by its checksum, see [`OTA_PROTOCOL_VERSION`]
     pub fn serialize(&self) -> Result<Vec<u8>, String> {
-        postcard::to_allocvec(self).map_err(|e| format!("Failed to serialize OTA packet: {:?}", e))
+        serialize_packet(self, None)
+    }
+
+    /// Serializes the packet followed by its authentication code if a key is given, see
+    /// [`OTA_AUTHENTICATED_PROTOCOL_VERSION`]
+    pub fn serialize_with_key(&self, key: Option<&OtaKey>) -> Result<Vec<u8>, String> {
+        serialize_packet(self, key)
+    }
+
+    /// Deserializes a packet and verifies its checksum, see [`OTA_PROTOCOL_VERSION`]
+    pub fn deserialize(data: &[u8]) -> Result<Self, OtaDecodeError> {
+        deserialize_packet(data, None)
     }
 
-    pub fn deserialize(data: &[u8]) -> Result<Self, String> {
-        postcard::from_bytes(data).map_err(|e| format!("Failed to deserialize OTA packet: {:?}", e))
+    /// Deserializes a packet and verifies its authentication code if a key is given, see
+    /// [`OTA_AUTHENTICATED_PROTOCOL_VERSION`]
+    pub fn deserialize_with_key(data: &[u8], key: Option<&OtaKey>) -> Result<Self, OtaDecodeError> {
+        deserialize_packet(data, key)
+    }
+
+    /// Deserializes the reassembled payload of chunked transport messages, see [`decode_payload`]
+    pub fn deserialize_payload(data: &[u8], compressed: bool) -> Result<Self, OtaDecodeError> {
+        Self::deserialize(&decode_payload(data, compressed).map_err(OtaDecodeError::Malformed)?)
     }
 }
 
@@ -430,11 +1246,619 @@ impl OtaC2D {
         }
     }
 
+    /// Serializes the packet followed by its checksum, see [`OTA_PROTOCOL_VERSION`]
     pub fn serialize(&self) -> Result<Vec<u8>, String> {
-        postcard::to_allocvec(self).map_err(|e| format!("Failed to serialize OTA packet: {:?}", e))
+        serialize_packet(self, None)
+    }
+
+    /// Serializes the packet followed by its authentication code if a key is given, see
+    /// [`OTA_AUTHENTICATED_PROTOCOL_VERSION`]
+    pub fn serialize_with_key(&self, key: Option<&OtaKey>) -> Result<Vec<u8>, String> {
+        serialize_packet(self, key)
     }
 
-    pub fn deserialize(data: &[u8]) -> Result<Self, String> {
-        postcard::from_bytes(data).map_err(|e| format!("Failed to deserialize OTA packet: {:?}", e))
+    /// Deserializes a packet and verifies its checksum, see [`OTA_PROTOCOL_VERSION`]
+    pub fn deserialize(data: &[u8]) -> Result<Self, OtaDecodeError> {
+        deserialize_packet(data, None)
+    }
+
+    /// Deserializes a packet and verifies its authentication code if a key is given, see
+    /// [`OTA_AUTHENTICATED_PROTOCOL_VERSION`]
+    pub fn deserialize_with_key(data: &[u8], key: Option<&OtaKey>) -> Result<Self, OtaDecodeError> {
+        deserialize_packet(data, key)
+    }
+
+    /// Deserializes the reassembled payload of chunked transport messages, see [`decode_payload`]
+    pub fn deserialize_payload(data: &[u8], compressed: bool) -> Result<Self, OtaDecodeError> {
+        Self::deserialize(&decode_payload(data, compressed).map_err(OtaDecodeError::Malformed)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::OtaKey;
+    use crate::instruction_corpus::CorpusInstruction;
+    use crate::{
+        decode_payload, encode_payload, retransmit_timeout, ConnectionStats, CoverageGranularity,
+        CrashPhase, ExecutionConfig, LinkStats, MsrAccessError, Ota, OtaC2D, OtaC2DTransport,
+        OtaC2DUnreliable, OtaD2C, OtaD2CTransport, OtaD2CUnreliable, OtaDecodeError, OtaPacket,
+        ReportExecutionProblem, SpeculationResult, MAX_FRAGMENT_SIZE, MAX_PAYLOAD_SIZE,
+        MAX_STATE_TRACE_LEN, MIN_SAMPLE_TIMEOUT_TSC, OTA_MESSAGE_VERSION, PMC_COUNTER_WIDTH,
+        PMC_PLAUSIBLE_MAXIMUM,
+    };
+    use alloc::format;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use hypervisor::state::GuestRegisters;
+    use ucode_compiler_dynamic::instruction::Instruction;
+    use ucode_compiler_dynamic::opcodes::Opcode;
+    use ucode_compiler_dynamic::sequence_word::SequenceWord;
+
+    fn assert_c2d_round_trip(packet: OtaC2D) {
+        let data = packet.serialize().unwrap();
+        assert_eq!(OtaC2D::deserialize(&data).unwrap(), packet);
+    }
+
+    fn assert_d2c_round_trip(packet: OtaD2C) {
+        // OtaD2CTransport is not comparable, compare the debug output instead
+        let data = packet.serialize().unwrap();
+        assert_eq!(
+            format!("{:?}", OtaD2C::deserialize(&data).unwrap()),
+            format!("{:?}", packet)
+        );
+    }
+
+    #[test]
+    pub fn test_ota_round_trip() {
+        let speculation_result = SpeculationResult {
+            arch_before: GuestRegisters::default(),
+            arch_after: GuestRegisters::default(),
+            perf_counters: vec![1, 2, 3, 4],
+            overflowed: vec![false, true, false, false],
+        };
+
+        let c2d = [
+            OtaC2DTransport::ReportPerformanceTiming { delta: true },
+            OtaC2DTransport::UCodeSpeculation {
+                triad: [
+                    Instruction::from_opcode(Opcode::ADD_DSZ32),
+                    Instruction::NOP,
+                    Instruction::NOP,
+                ],
+                sequence_word: SequenceWord::NOP,
+                perf_counter_setup: Vec::new(),
+                baseline_triad: Some([Instruction::NOP; 3]),
+            },
+            OtaC2DTransport::SetExecutionPolicy {
+                coverage_granularity: CoverageGranularity::Triad,
+            },
+            OtaC2DTransport::Ping {
+                nonce: 0xdead_beef,
+                sent_timestamp: 1234,
+            },
+            OtaC2DTransport::GetLinkStats,
+            OtaC2DTransport::ConfirmCoverage {
+                sample: vec![0x0f, 0xa2],
+                address: 0x428,
+            },
+            OtaC2DTransport::Hello {
+                version: OTA_MESSAGE_VERSION,
+            },
+            OtaC2DTransport::ReadMsr { msr: 0x10 },
+            OtaC2DTransport::WriteMsr {
+                msr: 0x48,
+                value: 0x1,
+            },
+            OtaC2DTransport::SetExecutionConfig {
+                max_vm_iterations: 100,
+                state_trace_len: 16,
+                initial_reg_seed: Some(7),
+                coverage_batch: 4,
+                sample_timeout_tsc: MIN_SAMPLE_TIMEOUT_TSC,
+                differential_execution: true,
+                reset_guest_memory: true,
+            },
+            OtaC2DTransport::ExtendDictionary {
+                instructions: vec![CorpusInstruction {
+                    bytes: vec![0x0f, 0xa2],
+                    valid: true,
+                }],
+            },
+        ];
+        for (id, content) in c2d.into_iter().enumerate() {
+            assert_c2d_round_trip(OtaC2D::Transport {
+                session: 1,
+                id: id as u64,
+                content,
+            });
+        }
+        assert_c2d_round_trip(OtaC2D::Unreliable(OtaC2DUnreliable::MissingFragments {
+            session: 1,
+            id: 2,
+            fragments: vec![0, 3],
+        }));
+
+        let d2c = [
+            OtaD2CTransport::UCodeSpeculationResult {
+                result: speculation_result.clone(),
+                baseline: Some(speculation_result),
+            },
+            OtaD2CTransport::Pong {
+                nonce: 0xdead_beef,
+                sent_timestamp: 1234,
+                device_cycles: 5678,
+            },
+            OtaD2CTransport::LinkStats(LinkStats {
+                sent: 5,
+                acked: 1,
+                retransmitted: 3,
+                dropped: 1,
+                unauthenticated: 2,
+            }),
+            OtaD2CTransport::CoverageConfirmation {
+                address: 0x428,
+                hit: Some(true),
+            },
+            OtaD2CTransport::CoverageConfirmation {
+                address: 0x428,
+                hit: Some(false),
+            },
+            OtaD2CTransport::CoverageConfirmation {
+                address: 0x428,
+                hit: None,
+            },
+            OtaD2CTransport::HelloAck {
+                version: OTA_MESSAGE_VERSION,
+            },
+            OtaD2CTransport::MsrValue {
+                msr: 0x10,
+                value: 0x1234,
+            },
+            OtaD2CTransport::MsrAccessFailed {
+                msr: 0xDEAD,
+                error: MsrAccessError::GeneralProtectionFault,
+            },
+            OtaD2CTransport::ExecutionConfig(ExecutionConfig::default()),
+            OtaD2CTransport::CrashReport {
+                crash_count: 2,
+                sample_fingerprint: 0x1234_5678,
+                phase: CrashPhase::CoverageCollectionBatch(0x428),
+            },
+        ];
+        for (id, content) in d2c.into_iter().enumerate() {
+            assert_d2c_round_trip(OtaD2C::Transport {
+                session: 1,
+                id: id as u64,
+                content,
+            });
+        }
+        for unreliable in [
+            OtaD2CUnreliable::MissingFragments {
+                session: 1,
+                id: 2,
+                fragments: vec![0, 3],
+            },
+            OtaD2CUnreliable::Heartbeat {
+                uptime_ms: 42_000,
+                free_heap_bytes: 1 << 30,
+                samples_executed: 1234,
+                last_error: Some("Failed to send event".into()),
+                connection_stats: ConnectionStats {
+                    received: 7,
+                    acks_lost: 2,
+                    ..Default::default()
+                },
+            },
+        ] {
+            assert_d2c_round_trip(OtaD2C::Unreliable(unreliable));
+        }
+    }
+
+    #[test]
+    pub fn test_link_stats_retransmits() {
+        let mut stats = LinkStats::default();
+
+        // acknowledged on the third attempt
+        for attempt in 0..3 {
+            stats.record_sent(attempt);
+        }
+        assert_eq!(stats.record_outcome(Some(Ok(())), "no ack"), Ok(()));
+
+        // never acknowledged
+        for attempt in 0..2 {
+            stats.record_sent(attempt);
+        }
+        assert_eq!(stats.record_outcome(None, "no ack"), Err("no ack"));
+
+        // failed locally, the remote is not to blame
+        assert_eq!(
+            stats.record_outcome(Some(Err("socket closed")), "no ack"),
+            Err("socket closed")
+        );
+
+        assert_eq!(
+            stats,
+            LinkStats {
+                sent: 5,
+                acked: 1,
+                retransmitted: 3,
+                dropped: 1,
+                unauthenticated: 0,
+            }
+        );
+    }
+
+    #[test]
+    pub fn test_retransmit_timeout() {
+        // without jitter the timeout doubles up to the maximum
+        let timeouts = (0..6)
+            .map(|attempt| retransmit_timeout(200, 1000, attempt, 0))
+            .collect::<Vec<u64>>();
+        assert_eq!(timeouts, [200, 400, 800, 1000, 1000, 1000]);
+
+        // the jitter adds at most a quarter of the timeout
+        for random in [1, 49, 50, 51, 12345, u64::MAX] {
+            let timeout = retransmit_timeout(200, 1000, 0, random);
+            assert!((200..=250).contains(&timeout), "{}", timeout);
+        }
+        assert_eq!(retransmit_timeout(200, 1000, 0, 50), 250);
+
+        // no overflow for large attempts
+        assert_eq!(retransmit_timeout(200, 1000, u8::MAX, 0), 1000);
+        assert_eq!(retransmit_timeout(u64::MAX / 2, u64::MAX, 3, 0), u64::MAX);
+        // the maximum never undercuts the base timeout
+        assert_eq!(retransmit_timeout(200, 100, 2, 0), 200);
+    }
+
+    #[test]
+    pub fn test_payload_compression_round_trip() {
+        let result = SpeculationResult {
+            arch_before: GuestRegisters::default(),
+            arch_after: GuestRegisters::default(),
+            perf_counters: vec![0; 2048],
+            overflowed: Vec::new(),
+        };
+        let response = OtaD2C::Transport {
+            session: 1,
+            id: 2,
+            content: OtaD2CTransport::UCodeSpeculationResult {
+                result: result.clone(),
+                baseline: None,
+            },
+        };
+        let data = response.serialize().unwrap();
+
+        let (payload, compressed) = encode_payload(&data);
+        assert!(compressed);
+        assert!(payload.len() < data.len());
+        match OtaD2C::deserialize_payload(&payload, compressed).unwrap() {
+            Ota::Transport {
+                session: 1,
+                id: 2,
+                content:
+                    OtaD2CTransport::UCodeSpeculationResult {
+                        result: received,
+                        baseline: None,
+                    },
+            } => assert_eq!(received, result),
+            x => panic!("Unexpected packet: {:?}", x),
+        }
+
+        // incompressible payloads are sent as is
+        let (payload, compressed) = encode_payload(&[0x42]);
+        assert!(!compressed);
+        assert_eq!(decode_payload(&payload, compressed).unwrap()[..], [0x42]);
+    }
+
+    #[test]
+    pub fn test_payload_decode_errors() {
+        assert!(decode_payload(&[0xff; 16], true).is_err());
+        assert!(OtaC2D::deserialize_payload(&[0xff; 16], true).is_err());
+
+        let oversized = vec![0u8; MAX_PAYLOAD_SIZE as usize + 1];
+        assert!(decode_payload(&oversized, false).is_err());
+        let (payload, compressed) = encode_payload(&oversized);
+        assert!(compressed);
+        assert!(decode_payload(&payload, compressed).is_err());
+    }
+
+    #[test]
+    pub fn test_ota_checksum_detects_corruption() {
+        let fragment = OtaD2C::ChunkedTransport {
+            session: 1,
+            id: 5,
+            fragment: 0,
+            total_fragments: 2,
+            compressed: false,
+            content: vec![0x11; 64],
+        };
+        let data = fragment.serialize().unwrap();
+        match OtaD2C::deserialize(&data).unwrap() {
+            Ota::ChunkedTransport {
+                session: 1,
+                id: 5,
+                fragment: 0,
+                total_fragments: 2,
+                compressed: false,
+                content,
+            } => assert_eq!(content, vec![0x11; 64]),
+            x => panic!("Unexpected packet: {:?}", x),
+        }
+        // fragments are acknowledged once the payload is complete, see `fragments`
+        assert_eq!(fragment.ack(), None);
+
+        for bit in [0, 8 * 20 + 3, 8 * (data.len() - 1)] {
+            let mut corrupted = data.clone();
+            corrupted[bit / 8] ^= 1 << (bit % 8);
+            assert!(matches!(
+                OtaD2C::deserialize(&corrupted),
+                Err(OtaDecodeError::ChecksumMismatch { .. })
+            ));
+        }
+    }
+
+    #[test]
+    pub fn test_ota_authentication_rejects_tampering() {
+        let key = OtaKey::from_passphrase("lab network");
+        let packet = OtaC2D::Transport {
+            session: 3,
+            id: 4,
+            content: OtaC2DTransport::Reboot,
+        };
+        let data = packet.serialize_with_key(Some(&key)).unwrap();
+        assert_eq!(
+            OtaC2D::deserialize_with_key(&data, Some(&key)).unwrap(),
+            packet
+        );
+
+        for index in 0..data.len() {
+            let mut tampered = data.clone();
+            tampered[index] ^= 0x01;
+            assert!(OtaC2D::deserialize_with_key(&tampered, Some(&key))
+                .unwrap_err()
+                .is_authentication_error());
+        }
+        assert!(
+            OtaC2D::deserialize_with_key(&data[..data.len() - 1], Some(&key))
+                .unwrap_err()
+                .is_authentication_error()
+        );
+
+        let other = OtaKey::from_passphrase("stray broadcast");
+        assert_eq!(
+            OtaC2D::deserialize_with_key(&data, Some(&other)),
+            Err(OtaDecodeError::AuthenticationFailed)
+        );
+    }
+
+    #[test]
+    pub fn test_ota_authentication_negotiation() {
+        let key = OtaKey::from_passphrase("lab network");
+        let packet = OtaD2C::Transport {
+            session: 1,
+            id: 2,
+            content: OtaD2CTransport::ResetSession,
+        };
+
+        // both sides unauthenticated
+        let plain = packet.serialize_with_key(None).unwrap();
+        assert!(matches!(
+            OtaD2C::deserialize_with_key(&plain, None).unwrap(),
+            Ota::Transport {
+                session: 1,
+                id: 2,
+                content: OtaD2CTransport::ResetSession,
+            }
+        ));
+
+        // a keyed receiver never falls back to unauthenticated packets
+        let legacy = postcard::to_allocvec(&packet).unwrap();
+        for data in [&plain, &legacy] {
+            assert!(OtaD2C::deserialize_with_key(data, Some(&key))
+                .unwrap_err()
+                .is_authentication_error());
+        }
+
+        let authenticated = packet.serialize_with_key(Some(&key)).unwrap();
+        assert!(matches!(
+            OtaD2C::deserialize(&authenticated),
+            Err(OtaDecodeError::KeyRequired)
+        ));
+
+        let mut stats = LinkStats::default();
+        stats.record_unauthenticated();
+        assert_eq!(stats.unauthenticated, 1);
+    }
+
+    #[test]
+    pub fn test_ota_protocol_version_interop() {
+        let packet = OtaC2D::Transport {
+            session: 3,
+            id: 4,
+            content: OtaC2DTransport::AreYouThere,
+        };
+
+        // packets of version 1 peers carry no checksum
+        let legacy = postcard::to_allocvec(&packet).unwrap();
+        assert_eq!(OtaC2D::deserialize(&legacy).unwrap(), packet);
+
+        // version 1 peers ignore the trailer
+        let data = packet.serialize().unwrap();
+        assert_eq!(postcard::from_bytes::<OtaC2D>(&data).unwrap(), packet);
+
+        let mut future = legacy.clone();
+        future.extend_from_slice(&[0x7f, 0, 0, 0, 0]);
+        assert_eq!(
+            OtaC2D::deserialize(&future),
+            Err(OtaDecodeError::UnsupportedVersion(0x7f))
+        );
+    }
+
+    #[test]
+    pub fn test_heartbeat_is_unreliable() {
+        let heartbeat = OtaD2CUnreliable::Heartbeat {
+            uptime_ms: 42_000,
+            free_heap_bytes: 1 << 30,
+            samples_executed: 1234,
+            last_error: Some("Failed to send event".into()),
+            connection_stats: ConnectionStats {
+                received: 7,
+                acks_lost: 2,
+                ..Default::default()
+            },
+        };
+        assert!(!heartbeat.reliable_transport());
+
+        let packet = heartbeat.to_packet(1, 1);
+        assert!(packet.ack().is_none());
+    }
+
+    #[test]
+    pub fn test_execution_config_clamped() {
+        assert_eq!(
+            ExecutionConfig::default().clamped(),
+            ExecutionConfig::default()
+        );
+
+        let config = ExecutionConfig {
+            max_vm_iterations: 0,
+            state_trace_len: u16::MAX,
+            initial_reg_seed: Some(7),
+            coverage_batch: 0,
+            sample_timeout_tsc: 0,
+            differential_execution: true,
+            reset_guest_memory: true,
+        }
+        .clamped();
+        assert_eq!(config.max_vm_iterations, 1);
+        assert_eq!(config.state_trace_len, MAX_STATE_TRACE_LEN);
+        assert_eq!(config.initial_reg_seed, Some(7));
+        assert_eq!(config.coverage_batch, 1);
+        assert_eq!(config.sample_timeout_tsc, MIN_SAMPLE_TIMEOUT_TSC);
+        assert!(config.differential_execution);
+        assert!(config.reset_guest_memory);
+    }
+
+    #[test]
+    pub fn test_coverage_granularity() {
+        // hookable addresses spanning three triads
+        let addresses = (0x100u16..0x10c).filter(|a| a % 2 == 0);
+
+        let instruction = addresses
+            .clone()
+            .filter(|a| CoverageGranularity::Instruction.includes_address(*a))
+            .count();
+        let triad = addresses
+            .filter(|a| CoverageGranularity::Triad.includes_address(*a))
+            .count();
+
+        assert_eq!(instruction, 6);
+        assert_eq!(triad, 3);
+
+        assert_eq!("triad".parse(), Ok(CoverageGranularity::Triad));
+        assert_eq!("instruction".parse(), Ok(CoverageGranularity::Instruction));
+        assert!("block".parse::<CoverageGranularity>().is_err());
+    }
+
+    #[test]
+    pub fn test_speculation_paired_results() {
+        let test = [
+            Instruction::from_opcode(Opcode::ADD_DSZ32),
+            Instruction::NOP,
+            Instruction::NOP,
+        ];
+        let baseline = [Instruction::NOP; 3];
+
+        let mut executed = Vec::new();
+        let (result, paired) = SpeculationResult::execute_paired(test, Some(baseline), |triad| {
+            executed.push(triad);
+            SpeculationResult {
+                arch_before: GuestRegisters::default(),
+                arch_after: GuestRegisters::default(),
+                perf_counters: vec![executed.len() as u64],
+                overflowed: Vec::new(),
+            }
+        });
+
+        assert_eq!(executed, vec![test, baseline]);
+        assert_eq!(result.perf_counters, vec![1]);
+        assert_eq!(paired.map(|b| b.perf_counters), Some(vec![2]));
+
+        let (_, paired) = SpeculationResult::execute_paired(test, None, |_| SpeculationResult {
+            arch_before: GuestRegisters::default(),
+            arch_after: GuestRegisters::default(),
+            perf_counters: Vec::new(),
+            overflowed: Vec::new(),
+        });
+        assert!(paired.is_none());
+    }
+
+    #[test]
+    pub fn test_counter_deltas() {
+        let sample = |perf_counters: Vec<u64>| SpeculationResult {
+            arch_before: GuestRegisters::default(),
+            arch_after: GuestRegisters::default(),
+            perf_counters,
+            overflowed: Vec::new(),
+        };
+        let wrap = 1u64 << PMC_COUNTER_WIDTH;
+
+        let baseline = sample(vec![10, 25, wrap - 10, 5, 3]);
+        let result = sample(vec![12, 10, 5, wrap - 5]);
+        assert_eq!(result.counter_deltas(&baseline), vec![2, -15, 15, -10]);
+
+        // bits above the counter width are ignored
+        let result = sample(vec![wrap + 12]);
+        assert_eq!(result.counter_deltas(&baseline), vec![2]);
+
+        // deltas of half the counter range and more are ambiguous and treated as negative
+        let baseline = sample(vec![0, 0]);
+        let result = sample(vec![wrap / 2 - 1, wrap / 2]);
+        assert_eq!(
+            result.counter_deltas(&baseline),
+            vec![(wrap / 2 - 1) as i64, -((wrap / 2) as i64)]
+        );
+    }
+
+    #[test]
+    pub fn test_pmc_anomalies() {
+        let setup = [
+            x86_perf_counter::INSTRUCTIONS_RETIRED,
+            x86_perf_counter::MS_DECODED_MS_ENTRY,
+            x86_perf_counter::UOPS_ISSUED_ANY,
+        ];
+        let sample = |perf_counters: Vec<u64>| SpeculationResult {
+            arch_before: GuestRegisters::default(),
+            arch_after: GuestRegisters::default(),
+            perf_counters,
+            overflowed: Vec::new(),
+        };
+
+        assert!(sample(vec![12, 0, 40, 0]).pmc_anomalies(&setup).is_empty());
+
+        let anomalies = sample(vec![0, 3, 5_000_000_000]).pmc_anomalies(&setup);
+        assert_eq!(
+            anomalies,
+            vec![
+                ReportExecutionProblem::PmcAnomaly {
+                    counter_index: 0,
+                    event: x86_perf_counter::INSTRUCTIONS_RETIRED,
+                    observed: 0,
+                    expected_range: (1, PMC_PLAUSIBLE_MAXIMUM),
+                },
+                ReportExecutionProblem::PmcAnomaly {
+                    counter_index: 2,
+                    event: x86_perf_counter::UOPS_ISSUED_ANY,
+                    observed: 5_000_000_000,
+                    expected_range: (0, PMC_PLAUSIBLE_MAXIMUM),
+                },
+            ]
+        );
+
+        // the chunks sent by spec_fuzz, which does not fragment messages, must fit into a packet
+        let chunk = vec![anomalies[1].clone(); ReportExecutionProblem::MAX_PER_PACKET];
+        let packet = OtaD2CTransport::ExecutionEvents(chunk).to_packet(u64::MAX, u16::MAX);
+        let serialized = packet.serialize().unwrap();
+        assert!(serialized.len() as u64 <= MAX_FRAGMENT_SIZE);
     }
 }
@@ -14,7 +14,7 @@ use uefi::boot::ScopedProtocol;
 use uefi::proto::loaded_image::LoadedImage;
 use uefi::{entry, println, Status};
 
-const STRING_LEN: usize = 20;
+const STRING_LEN: usize = 15;
 #[repr(C)]
 struct CmosActualData {
     length: u8,
@@ -81,10 +81,10 @@ unsafe fn main() -> Status {
         .unwrap(),
         &cmos.raw_data()
     );
-    println!("Checksum {:x}", cmos.read_checksum());
+    println!("Sequence {:?}", cmos.sequence());
 
     if !cmos.is_data_valid() {
-        println!("Checksum not valid, resetting CMOS RAM...");
+        println!("No valid slot, resetting CMOS RAM...");
         cmos.reset();
     }
 
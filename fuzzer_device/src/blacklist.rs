@@ -0,0 +1,252 @@
+//! Blacklist Module
+//!
+//! Binary file format of the addresses excluded from coverage collection. A file consists of a
+//! magic, the format version, the number of addresses, the strictly ascending addresses and a
+//! CRC32 over all preceding bytes. All numbers are little endian.
+
+pub mod storage;
+
+use alloc::collections::BTreeSet;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use data_types::addresses::{Address, UCInstructionAddress};
+
+/// Magic at the start of a blacklist file
+pub const BLACKLIST_MAGIC: [u8; 4] = *b"UFBL";
+/// Version of the blacklist file format
+pub const BLACKLIST_VERSION: u16 = 1;
+
+/// Size of the header: magic, version and number of addresses
+const HEADER_SIZE: usize = 4 + 2 + 4;
+/// Size of the trailing CRC32
+const CRC_SIZE: usize = 4;
+
+/// Reasons a blacklist file is rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlacklistError {
+    /// The file is shorter than the header and the checksum
+    Truncated,
+    /// The file does not start with [`BLACKLIST_MAGIC`]
+    InvalidMagic,
+    /// The file was written in an unsupported format version
+    UnsupportedVersion(u16),
+    /// The file size does not match the number of addresses of the header
+    InvalidLength {
+        /// Size implied by the header
+        expected: usize,
+        /// Actual size of the file
+        actual: usize,
+    },
+    /// The stored checksum does not match the content
+    ChecksumMismatch {
+        /// Checksum stored in the file
+        stored: u32,
+        /// Checksum of the content
+        computed: u32,
+    },
+    /// The addresses are not strictly ascending
+    Unsorted,
+}
+
+impl Display for BlacklistError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            BlacklistError::Truncated => write!(f, "truncated file"),
+            BlacklistError::InvalidMagic => write!(f, "invalid magic"),
+            BlacklistError::UnsupportedVersion(version) => {
+                write!(f, "unsupported version {}", version)
+            }
+            BlacklistError::InvalidLength { expected, actual } => {
+                write!(f, "expected {} bytes, found {}", expected, actual)
+            }
+            BlacklistError::ChecksumMismatch { stored, computed } => write!(
+                f,
+                "checksum mismatch: stored {:08x}, computed {:08x}",
+                stored, computed
+            ),
+            BlacklistError::Unsorted => write!(f, "addresses are not strictly ascending"),
+        }
+    }
+}
+
+/// Encodes addresses in the binary blacklist format
+///
+/// # Arguments
+///
+/// * `addresses` - The excluded addresses
+///
+/// # Returns
+///
+/// * `Vec<u8>` - Content of the blacklist file
+pub fn encode(addresses: &BTreeSet<u16>) -> Vec<u8> {
+    let mut data = Vec::with_capacity(HEADER_SIZE + addresses.len() * 2 + CRC_SIZE);
+    data.extend_from_slice(&BLACKLIST_MAGIC);
+    data.extend_from_slice(&BLACKLIST_VERSION.to_le_bytes());
+    data.extend_from_slice(&(addresses.len() as u32).to_le_bytes());
+    for address in addresses {
+        data.extend_from_slice(&address.to_le_bytes());
+    }
+    let crc = crc32fast::hash(&data);
+    data.extend_from_slice(&crc.to_le_bytes());
+    data
+}
+
+/// Decodes a file in the binary blacklist format
+///
+/// # Arguments
+///
+/// * `data` - Content of the blacklist file
+///
+/// # Returns
+///
+/// * `Result<BTreeSet<u16>, BlacklistError>` - The excluded addresses or the reason the file is rejected
+pub fn decode(data: &[u8]) -> Result<BTreeSet<u16>, BlacklistError> {
+    if data.len() < HEADER_SIZE + CRC_SIZE {
+        return Err(BlacklistError::Truncated);
+    }
+    if data[0..4] != BLACKLIST_MAGIC {
+        return Err(BlacklistError::InvalidMagic);
+    }
+    let version = u16::from_le_bytes([data[4], data[5]]);
+    if version != BLACKLIST_VERSION {
+        return Err(BlacklistError::UnsupportedVersion(version));
+    }
+
+    let count = u32::from_le_bytes([data[6], data[7], data[8], data[9]]) as usize;
+    let expected = count
+        .checked_mul(2)
+        .and_then(|size| size.checked_add(HEADER_SIZE + CRC_SIZE))
+        .unwrap_or(usize::MAX);
+    if data.len() != expected {
+        return Err(BlacklistError::InvalidLength {
+            expected,
+            actual: data.len(),
+        });
+    }
+
+    let (content, crc) = data.split_at(data.len() - CRC_SIZE);
+    let stored = u32::from_le_bytes([crc[0], crc[1], crc[2], crc[3]]);
+    let computed = crc32fast::hash(content);
+    if stored != computed {
+        return Err(BlacklistError::ChecksumMismatch { stored, computed });
+    }
+
+    let mut addresses = BTreeSet::new();
+    for address in content[HEADER_SIZE..]
+        .chunks_exact(2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+    {
+        if addresses.last().is_some_and(|last| *last >= address) {
+            return Err(BlacklistError::Unsorted);
+        }
+        addresses.insert(address);
+    }
+
+    Ok(addresses)
+}
+
+/// A blacklist in the former text format, see [`parse_text`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TextBlacklist {
+    /// The excluded addresses
+    pub addresses: BTreeSet<u16>,
+    /// The lines that are not an address, they are skipped
+    pub malformed_lines: Vec<String>,
+}
+
+/// Parses a blacklist in the former text format, one hexadecimal address per line
+///
+/// Empty lines, comments starting with `//` or `#` and the zero bytes the former format was
+/// padded with are ignored. Malformed lines are skipped and collected, a single typo must not
+/// discard the rest of the blacklist.
+///
+/// # Arguments
+///
+/// * `text` - Content of the text blacklist
+///
+/// # Returns
+///
+/// * `TextBlacklist` - The excluded addresses and the skipped malformed lines
+pub fn parse_text(text: &str) -> TextBlacklist {
+    let mut blacklist = TextBlacklist::default();
+    for line in text
+        .lines()
+        .map(|line| line.trim_matches(|c: char| c.is_whitespace() || c == '\0'))
+    {
+        if line.starts_with("//") || line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+
+        match line.parse::<UCInstructionAddress>() {
+            Ok(address) => {
+                blacklist.addresses.insert(address.address() as u16);
+            }
+            Err(_) => blacklist.malformed_lines.push(line.to_string()),
+        }
+    }
+
+    blacklist
+}
+
+#[cfg(test)]
+mod test {
+    use crate::blacklist::{decode, encode, parse_text, BlacklistError};
+    use alloc::collections::BTreeSet;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    #[test]
+    fn test_roundtrip() {
+        for addresses in [
+            BTreeSet::new(),
+            BTreeSet::from([0x0000, 0x1234, 0x7fff, 0xffff]),
+        ] {
+            assert_eq!(decode(&encode(&addresses)), Ok(addresses));
+        }
+    }
+
+    #[test]
+    fn test_corrupted() {
+        let data = encode(&BTreeSet::from([0x0010, 0x0020]));
+
+        let mut flipped = data.clone();
+        flipped[10] ^= 0x01;
+        assert!(matches!(
+            decode(&flipped),
+            Err(BlacklistError::ChecksumMismatch { .. })
+        ));
+
+        assert!(matches!(
+            decode(&data[..data.len() - 1]),
+            Err(BlacklistError::InvalidLength { .. })
+        ));
+        assert_eq!(decode(&data[..8]), Err(BlacklistError::Truncated));
+
+        let mut padded = data.clone();
+        padded.extend_from_slice(&[0; 128]);
+        assert!(matches!(
+            decode(&padded),
+            Err(BlacklistError::InvalidLength { .. })
+        ));
+
+        let mut magic = data;
+        magic[0] = b'X';
+        assert_eq!(decode(&magic), Err(BlacklistError::InvalidMagic));
+    }
+
+    #[test]
+    fn test_parse_text() {
+        let blacklist = parse_text("# comment\n0010\n\n  U0020 \n// comment\n0010\n\0\0\0\0");
+        assert_eq!(blacklist.addresses, BTreeSet::from([0x0010, 0x0020]));
+        assert!(blacklist.malformed_lines.is_empty());
+
+        // malformed lines are skipped, the other lines are kept
+        let blacklist = parse_text("0010\nxyz\n 0x0030\nU0020x\n");
+        assert_eq!(blacklist.addresses, BTreeSet::from([0x0010, 0x0030]));
+        assert_eq!(
+            blacklist.malformed_lines,
+            vec!["xyz".to_string(), "U0020x".to_string()]
+        );
+    }
+}
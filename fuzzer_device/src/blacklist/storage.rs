@@ -0,0 +1,204 @@
+//! Blacklist Storage
+//!
+//! Persists the blacklist in the binary format of [`crate::blacklist`] on the volume the fuzzer
+//! was loaded from. Every file is written to a temporary name and renamed afterward, a backup
+//! copy is written after every successful save of a successfully loaded blacklist. Files that
+//! fail the integrity check are renamed aside instead of being overwritten.
+
+use crate::blacklist::{decode, encode, parse_text};
+use crate::file_system::{delete_existing, into_regular_file, open_existing, read_file, rename};
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::string::String;
+use log::{error, info, warn};
+use uefi::proto::media::file::{Directory, File, FileAttribute, FileMode};
+use uefi::{CStr16, CString16};
+
+/// Name of the blacklist file
+pub const FILENAME: &str = "blacklist.bin";
+/// Name of the backup copy of the blacklist file
+pub const BACKUP_FILENAME: &str = "blacklist.bak";
+/// Name of the blacklist file while it is written
+const TEMPORARY_FILENAME: &str = "blacklist.tmp";
+/// Name of the blacklist file of the former text format, imported once
+pub const TEXT_FILENAME: &str = "blacklist.txt";
+/// Suffix appended to the name of a file that failed the integrity check
+pub const REJECTED_SUFFIX: &str = ".bad";
+
+fn filename(name: &str) -> uefi::Result<CString16> {
+    CString16::try_from(name).map_err(|_| uefi::Error::from(uefi::Status::UNSUPPORTED))
+}
+
+/// Addresses excluded from coverage collection, persisted across runs
+#[derive(Debug, Default)]
+pub struct ExcludedAddresses {
+    /// The excluded addresses
+    pub addresses: BTreeSet<u16>,
+    /// Whether the blacklist was loaded from the volume, only then the backup copy is replaced
+    loaded: bool,
+}
+
+impl ExcludedAddresses {
+    /// Loads the blacklist
+    ///
+    /// A blacklist file that fails the integrity check is renamed aside with
+    /// [`REJECTED_SUFFIX`] and ignored in favour of the backup copy. If neither exists, a
+    /// blacklist of the former text format is imported and saved in the binary format.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Self, uefi::Error>` - The blacklist or error, `CRC_ERROR` if the blacklist
+    ///   file and its backup copy exist but are both corrupted
+    pub fn load_file() -> Result<Self, uefi::Error> {
+        let mut proto = uefi::boot::get_image_file_system(uefi::boot::image_handle())?;
+        let mut root_dir = proto.open_volume()?;
+
+        let mut found = false;
+        for name in [FILENAME, BACKUP_FILENAME] {
+            let Some(data) = read_file(&mut root_dir, filename(name)?.as_ref())? else {
+                continue;
+            };
+            found = true;
+
+            match decode(&data) {
+                Ok(addresses) => {
+                    if name == BACKUP_FILENAME {
+                        warn!("Restored the blacklist from {}", BACKUP_FILENAME);
+                    }
+                    return Ok(Self {
+                        addresses,
+                        loaded: true,
+                    });
+                }
+                Err(err) => {
+                    error!("Rejected {}: {}", name, err);
+                    // keep the rejected copy for inspection, a later save must not replace it
+                    if let Err(err) = set_aside(&mut root_dir, name) {
+                        error!("Failed to rename {} aside: {:?}", name, err);
+                    }
+                }
+            }
+        }
+
+        if found {
+            return Err(uefi::Error::from(uefi::Status::CRC_ERROR));
+        }
+
+        let text_filename = filename(TEXT_FILENAME)?;
+        let Some(data) = read_file(&mut root_dir, text_filename.as_ref())? else {
+            return Ok(Self {
+                addresses: BTreeSet::new(),
+                loaded: true,
+            });
+        };
+
+        let text: String = data.iter().map(|byte| *byte as char).collect();
+        let parsed = parse_text(&text);
+        for line in &parsed.malformed_lines {
+            warn!("Skipping malformed line in {}: {:?}", TEXT_FILENAME, line);
+        }
+        info!(
+            "Importing {} addresses from {}, skipped {} malformed lines",
+            parsed.addresses.len(),
+            TEXT_FILENAME,
+            parsed.malformed_lines.len()
+        );
+
+        let blacklist = Self {
+            addresses: parsed.addresses,
+            loaded: true,
+        };
+        blacklist.save_file()?;
+        if let Err(err) = delete_existing(&mut root_dir, text_filename.as_ref()) {
+            warn!("Failed to delete {}: {:?}", TEXT_FILENAME, err);
+        }
+
+        Ok(blacklist)
+    }
+
+    /// Excludes an address from coverage collection, not persisted until [`Self::save_file`]
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Microcode address to exclude
+    pub fn exclude_address<A: Into<u16>>(&mut self, address: A) {
+        self.addresses.insert(address.into());
+    }
+
+    /// Saves the blacklist and afterward its backup copy
+    ///
+    /// The backup copy is only replaced if the blacklist was loaded by [`Self::load_file`], so
+    /// that a blacklist started empty after a failed load does not replace the last good copy.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), uefi::Error>` - Success or error
+    pub fn save_file(&self) -> Result<(), uefi::Error> {
+        let data = encode(&self.addresses);
+
+        let mut proto = uefi::boot::get_image_file_system(uefi::boot::image_handle())?;
+        let mut root_dir = proto.open_volume()?;
+
+        // the backup keeps the previous blacklist until the new one is in place
+        write_atomically(&mut root_dir, filename(FILENAME)?.as_ref(), &data)?;
+        if self.loaded {
+            write_atomically(&mut root_dir, filename(BACKUP_FILENAME)?.as_ref(), &data)?;
+        }
+
+        root_dir.flush()?;
+        root_dir.close();
+
+        Ok(())
+    }
+}
+
+/// Renames a file that failed the integrity check to its name with [`REJECTED_SUFFIX`]
+///
+/// # Arguments
+///
+/// * `root_dir` - Directory of the file
+/// * `name` - Name of the rejected file
+fn set_aside(root_dir: &mut Directory, name: &str) -> uefi::Result<()> {
+    let Some(file) = open_existing(root_dir, filename(name)?.as_ref())? else {
+        return Ok(());
+    };
+    let mut file = into_regular_file(file)?;
+
+    let rejected_filename = filename(&format!("{}{}", name, REJECTED_SUFFIX))?;
+    delete_existing(root_dir, rejected_filename.as_ref())?;
+    rename(&mut file, rejected_filename.as_ref())?;
+    file.flush()?;
+
+    Ok(())
+}
+
+/// Replaces a file by writing a temporary file and renaming it
+///
+/// # Arguments
+///
+/// * `root_dir` - Directory of the file
+/// * `name` - Name of the file to replace
+/// * `data` - New content of the file
+fn write_atomically(root_dir: &mut Directory, name: &CStr16, data: &[u8]) -> uefi::Result<()> {
+    let temporary_filename = filename(TEMPORARY_FILENAME)?;
+
+    // a leftover of an interrupted save
+    delete_existing(root_dir, temporary_filename.as_ref())?;
+
+    let file = root_dir.open(
+        temporary_filename.as_ref(),
+        FileMode::CreateReadWrite,
+        FileAttribute::empty(),
+    )?;
+    let mut temporary_file = into_regular_file(file)?;
+    temporary_file
+        .write(data)
+        .map_err(|_| uefi::Error::from(uefi::Status::WARN_WRITE_FAILURE))?;
+    temporary_file.flush()?;
+
+    delete_existing(root_dir, name)?;
+    rename(&mut temporary_file, name)?;
+    temporary_file.flush()?;
+
+    Ok(())
+}
@@ -29,8 +29,76 @@ use crate::cmos;
 #[cfg(feature = "platform_bochs")]
 pub use platform_bochs::*;
 
+/// Size of the header of a slot: sequence number and CRC, both little endian
+const SLOT_HEADER_SIZE: usize = 4;
+/// Size of each of the two slots the data is alternately written to
+const CMOS_SLOT_SIZE: usize = (CMOS_DATA_SIZE / 2) as usize;
 /// Maximum size of user data that can be stored in CMOS memory
-const CMOS_USER_DATA_SIZE: usize = (CMOS_DATA_SIZE - 4) as usize;
+pub const CMOS_USER_DATA_SIZE: usize = CMOS_SLOT_SIZE - SLOT_HEADER_SIZE;
+
+/// Byte addressable backing store of the CMOS data
+pub trait CMOSStorage {
+    /// Reads a byte
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - Offset of the byte from the start of the CMOS data
+    fn read(&mut self, offset: usize) -> u8;
+
+    /// Writes a byte
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - Offset of the byte from the start of the CMOS data
+    /// * `value` - Value to write
+    fn write(&mut self, offset: usize, value: u8);
+}
+
+impl<S: CMOSStorage> CMOSStorage for &mut S {
+    fn read(&mut self, offset: usize) -> u8 {
+        (**self).read(offset)
+    }
+
+    fn write(&mut self, offset: usize, value: u8) {
+        (**self).write(offset, value)
+    }
+}
+
+/// CMOS RAM of the platform, accessed through its IO ports
+pub struct CMOSRam {
+    /// Whether NMI is disabled during CMOS operations
+    disable_nmi: bool,
+}
+
+impl CMOSStorage for CMOSRam {
+    fn read(&mut self, offset: usize) -> u8 {
+        unsafe { read_cmos_ram(CMOS_DATA_OFFSET + offset as u8, self.disable_nmi) }
+    }
+
+    fn write(&mut self, offset: usize, value: u8) {
+        unsafe { write_cmos_ram(CMOS_DATA_OFFSET + offset as u8, value, self.disable_nmi) }
+    }
+}
+
+/// CRC-16/CCITT-FALSE of a byte sequence
+fn crc16<'a, I: IntoIterator<Item = &'a u8>>(bytes: I) -> u16 {
+    bytes.into_iter().fold(0xFFFF, |crc, byte| {
+        let mut crc = crc ^ ((*byte as u16) << 8);
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// Checks if a sequence number is newer than another one, accounting for the wrap around
+fn is_newer_sequence(sequence: u16, other: u16) -> bool {
+    (sequence.wrapping_sub(other) as i16) > 0
+}
 
 /// Internal union type for storing data in CMOS memory
 ///
@@ -50,7 +118,7 @@ impl<T> CMOSData<T>
 where
     [(); size_of::<T>()]:,
 {
-    /// Verifies that the type T fits within the available CMOS memory
+    /// Verifies that the type T fits into a slot of the CMOS memory
     #[allow(dead_code)]
     const fn size_check() {
         assert!(
@@ -83,14 +151,20 @@ where
 /// This structure provides a safe interface for reading and writing data to CMOS memory,
 /// including checksum validation and NMI management.
 ///
+/// The CMOS memory is split into two slots, each holding a sequence number, a CRC and a copy of
+/// the data. Writes always go to the slot not holding the latest data and write the sequence
+/// number last, so a write torn by a power cut or a hang leaves the previous data intact.
+/// Reads use the valid slot with the higher sequence number.
+///
 /// # Type Parameters
 ///
 /// * `T` - The type of data to store in CMOS memory
+/// * `S` - Backing store of the data
 ///
 /// # Safety
 ///
 /// The type `T` must be `repr(C)` to ensure proper memory layout when stored in CMOS.
-pub struct CMOS<T>
+pub struct CMOS<T, S: CMOSStorage = CMOSRam>
 where
     [(); size_of::<T>()]:,
 {
@@ -98,13 +172,15 @@ where
     data: CMOSData<T>,
     /// Whether the stored data is valid (checksum matches)
     valid: bool,
-    /// Whether NMI is disabled during CMOS operations
-    disable_nmi: bool,
+    /// Backing store of the data
+    storage: S,
+    /// Slot and sequence number of the latest written data, `None` if no slot is valid
+    active: Option<(usize, u16)>,
     /// Guard for managing NMI state
     nmi_guard: Option<NMIGuard>,
 }
 
-impl<T> CMOS<T>
+impl<T, S: CMOSStorage> CMOS<T, S>
 where
     [(); size_of::<T>()]:,
 {
@@ -114,6 +190,28 @@ where
         CMOSData::<T>::size_check();
     }
 
+    /// Reads data from a backing store into a new instance
+    ///
+    /// NMI is not managed and the data is only written back on request or by a
+    /// [`CMOSDataHandle`].
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - Backing store of the data
+    pub fn from_storage(storage: S) -> Self {
+        let mut data = Self {
+            data: CMOSData {
+                raw: [0; size_of::<T>()],
+            },
+            valid: false,
+            storage,
+            active: None,
+            nmi_guard: None,
+        };
+        data.read_cmos_ram();
+        data
+    }
+
     /// Marks the stored data as valid
     ///
     /// # Safety
@@ -129,57 +227,68 @@ where
         self.valid
     }
 
-    /// Reads data from CMOS memory into a new instance
+    /// Returns the sequence number of the latest written data, `None` if no slot is valid
+    pub fn sequence(&self) -> Option<u16> {
+        self.active.map(|(_, sequence)| sequence)
+    }
+
+    /// Reads a slot and validates its CRC
     ///
     /// # Arguments
     ///
-    /// * `disable_nmi` - Whether to disable NMI during the use of the returned data
-    pub fn read_from_ram(disable_nmi: bool) -> Self {
-        let mut data = Self {
-            data: CMOSData {
-                raw: [0; size_of::<T>()],
-            },
-            disable_nmi,
-            valid: false,
-            nmi_guard: Some(NMIGuard::disable_nmi(disable_nmi)),
-        };
-        data.read_cmos_ram();
-        data
-    }
+    /// * `slot` - Index of the slot, `0` or `1`
+    /// * `raw` - Buffer for the data of the slot
+    ///
+    /// # Returns
+    ///
+    /// * `Option<u16>` - Sequence number of the slot, `None` if the CRC does not match
+    fn read_slot(&mut self, slot: usize, raw: &mut [u8; size_of::<T>()]) -> Option<u16> {
+        let base = slot * CMOS_SLOT_SIZE;
+        let mut header = [0u8; SLOT_HEADER_SIZE];
+        for (i, byte) in header.iter_mut().enumerate() {
+            *byte = self.storage.read(base + i);
+        }
+        for (i, byte) in raw.iter_mut().enumerate() {
+            *byte = self.storage.read(base + SLOT_HEADER_SIZE + i);
+        }
 
-    /// Reads the checksum from CMOS memory
-    pub fn read_checksum(&mut self) -> u32 {
-        let mut checksum: u32 = 0;
-        checksum |= unsafe { read_cmos_ram(CMOS_DATA_OFFSET + 0, self.disable_nmi) as u32 } << 0;
-        checksum |= unsafe { read_cmos_ram(CMOS_DATA_OFFSET + 1, self.disable_nmi) as u32 } << 8;
-        checksum |= unsafe { read_cmos_ram(CMOS_DATA_OFFSET + 2, self.disable_nmi) as u32 } << 16;
-        checksum |= unsafe { read_cmos_ram(CMOS_DATA_OFFSET + 3, self.disable_nmi) as u32 } << 24;
-        checksum
+        let sequence = [header[0], header[1]];
+        let crc = u16::from_le_bytes([header[2], header[3]]);
+        (crc == crc16(sequence.iter().chain(raw.iter()))).then(|| u16::from_le_bytes(sequence))
     }
 
     /// Reads data from CMOS memory and validates it
+    ///
+    /// The valid slot with the higher sequence number is used.
     pub fn read_cmos_ram(&mut self) {
-        let checksum = self.read_checksum();
-
         assert!(
             size_of::<T>() <= CMOS_USER_DATA_SIZE,
             "Size of T is too large for CMOS"
         );
 
-        for i in 0..size_of::<T>() {
-            unsafe {
-                self.data.raw[i] = read_cmos_ram(
-                    i as u8 + CMOS_DATA_OFFSET + size_of::<u32>() as u8,
-                    self.disable_nmi,
-                );
+        self.active = None;
+        for slot in 0..2 {
+            let mut raw = [0u8; size_of::<T>()];
+            let Some(sequence) = self.read_slot(slot, &mut raw) else {
+                continue;
+            };
+            if self
+                .active
+                .map_or(true, |(_, newest)| is_newer_sequence(sequence, newest))
+            {
+                self.active = Some((slot, sequence));
+                self.data.raw = raw;
             }
         }
 
-        self.valid = checksum == self.calculate_checksum();
+        self.valid = self.active.is_some();
     }
 
     /// Writes data to CMOS memory if it is valid
-    pub fn write_cmos_ram(&self) {
+    ///
+    /// The data is written to the slot not holding the latest data, followed by the CRC and
+    /// the incremented sequence number.
+    pub fn write_cmos_ram(&mut self) {
         if !self.valid {
             error!("Tried to write invalid CMOS data; Skipped writing");
             return;
@@ -190,48 +299,27 @@ where
             "Size of T is too large for CMOS"
         );
 
-        let checksum = self.calculate_checksum();
+        let (slot, sequence) = match self.active {
+            Some((slot, sequence)) => (1 - slot, sequence.wrapping_add(1)),
+            None => (0, 0),
+        };
+        let base = slot * CMOS_SLOT_SIZE;
+        let raw = unsafe { self.data.raw };
+        let sequence_bytes = sequence.to_le_bytes();
+        let crc = crc16(sequence_bytes.iter().chain(raw.iter()));
 
-        unsafe {
-            write_cmos_ram(
-                CMOS_DATA_OFFSET + 0,
-                ((checksum >> 0) & 0xFF) as u8,
-                self.disable_nmi,
-            );
-            write_cmos_ram(
-                CMOS_DATA_OFFSET + 1,
-                ((checksum >> 8) & 0xFF) as u8,
-                self.disable_nmi,
-            );
-            write_cmos_ram(
-                CMOS_DATA_OFFSET + 2,
-                ((checksum >> 16) & 0xFF) as u8,
-                self.disable_nmi,
-            );
-            write_cmos_ram(
-                CMOS_DATA_OFFSET + 3,
-                ((checksum >> 24) & 0xFF) as u8,
-                self.disable_nmi,
-            );
-
-            for i in 0..size_of::<T>() {
-                write_cmos_ram(
-                    i as u8 + CMOS_DATA_OFFSET + size_of::<u32>() as u8,
-                    self.data.raw[i as usize],
-                    self.disable_nmi,
-                );
-            }
+        for (i, byte) in raw.iter().enumerate() {
+            self.storage.write(base + SLOT_HEADER_SIZE + i, *byte);
+        }
+        for (i, byte) in crc.to_le_bytes().into_iter().enumerate() {
+            self.storage.write(base + 2 + i, byte);
+        }
+        // the slot only becomes valid once its sequence number is complete
+        for (i, byte) in sequence_bytes.into_iter().enumerate() {
+            self.storage.write(base + i, byte);
         }
-    }
 
-    /// Calculates the checksum of the stored data
-    fn calculate_checksum(&self) -> u32 {
-        let result = unsafe {
-            self.data.raw.iter().fold((0u32, 0u8), |(sum, xor), &x| {
-                (sum.wrapping_add(x as u32), xor ^ x)
-            })
-        };
-        result.0 ^ ((result.1 as u32) << 24)
+        self.active = Some((slot, sequence));
     }
 
     /// Returns a reference to the stored data if it is valid
@@ -244,7 +332,7 @@ where
     }
 
     /// Returns a mutable handle to the stored data if it is valid
-    pub fn data_mut(&mut self) -> Option<CMOSDataHandle<T>> {
+    pub fn data_mut(&mut self) -> Option<CMOSDataHandle<T, S>> {
         if self.valid {
             Some(CMOSDataHandle::new(self))
         } else {
@@ -260,10 +348,34 @@ where
     pub unsafe fn raw_data(&mut self) -> &mut [u8; size_of::<T>()] {
         &mut self.data.raw
     }
+}
+
+impl<T> CMOS<T, CMOSRam>
+where
+    [(); size_of::<T>()]:,
+{
+    /// Reads data from CMOS memory into a new instance
+    ///
+    /// # Arguments
+    ///
+    /// * `disable_nmi` - Whether to disable NMI during the use of the returned data
+    pub fn read_from_ram(disable_nmi: bool) -> Self {
+        let mut data = Self {
+            data: CMOSData {
+                raw: [0; size_of::<T>()],
+            },
+            valid: false,
+            storage: CMOSRam { disable_nmi },
+            active: None,
+            nmi_guard: Some(NMIGuard::disable_nmi(disable_nmi)),
+        };
+        data.read_cmos_ram();
+        data
+    }
 
     /// Sets whether NMI should be disabled during operations
     pub fn disable_nmi(&mut self, disable_nmi: bool) {
-        self.disable_nmi = disable_nmi;
+        self.storage.disable_nmi = disable_nmi;
         unsafe {
             cmos::disable_nmi(disable_nmi);
         }
@@ -276,12 +388,12 @@ where
     }
 }
 
-impl<T: Default> CMOS<T>
+impl<T: Default, S: CMOSStorage> CMOS<T, S>
 where
     [(); size_of::<T>()]:,
 {
     /// Resets the stored data to its default value
-    pub fn reset(&mut self) -> CMOSDataHandle<T> {
+    pub fn reset(&mut self) -> CMOSDataHandle<T, S> {
         self.data = CMOSData {
             data: core::mem::ManuallyDrop::new(T::default()),
         };
@@ -290,7 +402,7 @@ where
     }
 
     /// Returns a mutable handle to the data, creating default data if invalid
-    pub fn data_mut_or_insert(&mut self) -> CMOSDataHandle<T> {
+    pub fn data_mut_or_insert(&mut self) -> CMOSDataHandle<T, S> {
         if !self.valid {
             self.reset()
         } else {
@@ -307,7 +419,7 @@ where
     }
 }
 
-impl<T: Default> Default for CMOS<T>
+impl<T: Default> Default for CMOS<T, CMOSRam>
 where
     [(); size_of::<T>()]:,
 {
@@ -318,14 +430,15 @@ where
                 data: core::mem::ManuallyDrop::new(T::default()),
             },
             valid: true,
-            disable_nmi: nmi,
+            storage: CMOSRam { disable_nmi: nmi },
+            active: None,
             nmi_guard: Some(NMIGuard::disable_nmi(nmi)),
         };
         new
     }
 }
 
-impl<T: Debug> Debug for CMOS<T>
+impl<T: Debug, S: CMOSStorage> Debug for CMOS<T, S>
 where
     [(); size_of::<T>()]:,
 {
@@ -344,7 +457,7 @@ where
     }
 }
 
-impl<T> Drop for CMOS<T>
+impl<T, S: CMOSStorage> Drop for CMOS<T, S>
 where
     [(); size_of::<T>()]:,
 {
@@ -360,24 +473,24 @@ where
 ///
 /// This structure provides a safe way to modify CMOS data while ensuring
 /// proper cleanup and validation.
-pub struct CMOSDataHandle<'a, T>
+pub struct CMOSDataHandle<'a, T, S: CMOSStorage = CMOSRam>
 where
     [(); size_of::<T>()]:,
 {
     /// Reference to the parent CMOS instance
-    cmos: &'a mut CMOS<T>,
+    cmos: &'a mut CMOS<T, S>,
 }
 
-impl<'a, T> CMOSDataHandle<'a, T>
+impl<'a, T, S: CMOSStorage> CMOSDataHandle<'a, T, S>
 where
     [(); size_of::<T>()]:,
 {
-    fn new(cmos: &'a mut CMOS<T>) -> Self {
+    fn new(cmos: &'a mut CMOS<T, S>) -> Self {
         Self { cmos }
     }
 }
 
-impl<'a, T> DerefMut for CMOSDataHandle<'a, T>
+impl<'a, T, S: CMOSStorage> DerefMut for CMOSDataHandle<'a, T, S>
 where
     [(); size_of::<T>()]:,
 {
@@ -386,7 +499,7 @@ where
     }
 }
 
-impl<'a, T> Deref for CMOSDataHandle<'a, T>
+impl<'a, T, S: CMOSStorage> Deref for CMOSDataHandle<'a, T, S>
 where
     [(); size_of::<T>()]:,
 {
@@ -397,7 +510,7 @@ where
     }
 }
 
-impl<'a, T> Drop for CMOSDataHandle<'a, T>
+impl<'a, T, S: CMOSStorage> Drop for CMOSDataHandle<'a, T, S>
 where
     [(); size_of::<T>()]:,
 {
@@ -429,3 +542,141 @@ impl Drop for NMIGuard {
         unsafe { cmos::disable_nmi(self.previous_disable_nmi) };
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::cmos::{is_newer_sequence, CMOSStorage, CMOS, CMOS_DATA_SIZE, SLOT_HEADER_SIZE};
+
+    #[repr(C)]
+    #[derive(Default, Debug)]
+    struct TestData {
+        counter: u32,
+        payload: [u8; 8],
+    }
+
+    /// CMOS backing array that drops all writes once a number of bytes was written
+    struct MockStorage {
+        bytes: [u8; CMOS_DATA_SIZE as usize],
+        writes_left: Option<usize>,
+    }
+
+    impl Default for MockStorage {
+        fn default() -> Self {
+            Self {
+                bytes: [0; CMOS_DATA_SIZE as usize],
+                writes_left: None,
+            }
+        }
+    }
+
+    impl CMOSStorage for MockStorage {
+        fn read(&mut self, offset: usize) -> u8 {
+            self.bytes[offset]
+        }
+
+        fn write(&mut self, offset: usize, value: u8) {
+            match self.writes_left.as_mut() {
+                Some(0) => return,
+                Some(left) => *left -= 1,
+                None => {}
+            }
+            self.bytes[offset] = value;
+        }
+    }
+
+    #[test]
+    fn test_torn_writes() {
+        let data_size = size_of::<TestData>();
+        let full_write = data_size + SLOT_HEADER_SIZE;
+
+        for torn_at in 0..=full_write {
+            let mut storage = MockStorage::default();
+            {
+                let mut cmos = CMOS::<TestData, &mut MockStorage>::from_storage(&mut storage);
+                assert!(!cmos.is_data_valid());
+
+                let mut data = cmos.data_mut_or_insert();
+                data.counter = 1;
+                data.payload = [1; 8];
+                drop(data);
+                let mut data = cmos.data_mut_or_insert();
+                data.counter = 2;
+                data.payload = [2; 8];
+                drop(data);
+
+                // power cut after `torn_at` bytes of the next write
+                cmos.storage.writes_left = Some(torn_at);
+                let mut data = cmos.data_mut_or_insert();
+                data.counter = 3;
+                data.payload = [0xA5, 0x5A, 3, 4, 5, 6, 7, 8];
+            }
+
+            let cmos = CMOS::<TestData, &mut MockStorage>::from_storage(&mut storage);
+            let data = cmos
+                .data()
+                .expect("the previous data survives a torn write");
+            if torn_at < data_size + 2 {
+                // the sequence number was not touched
+                assert_eq!(data.counter, 2, "torn at {}", torn_at);
+                assert_eq!(data.payload, [2; 8]);
+            } else if torn_at == full_write {
+                assert_eq!(data.counter, 3);
+                assert_eq!(data.payload, [0xA5, 0x5A, 3, 4, 5, 6, 7, 8]);
+            } else {
+                assert!(
+                    data.counter == 2 || data.counter == 3,
+                    "torn at {}",
+                    torn_at
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_sequence_wrap_around() {
+        assert!(is_newer_sequence(1, 0));
+        assert!(is_newer_sequence(0, u16::MAX));
+        assert!(!is_newer_sequence(u16::MAX, 0));
+        assert!(!is_newer_sequence(7, 7));
+
+        let mut storage = MockStorage::default();
+        {
+            let mut cmos = CMOS::<TestData, &mut MockStorage>::from_storage(&mut storage);
+            cmos.data_mut_or_insert().counter = 1;
+            assert_eq!(cmos.sequence(), Some(0));
+
+            cmos.active = Some((0, u16::MAX - 1));
+            cmos.data_mut_or_insert().counter = 2;
+            assert_eq!(cmos.sequence(), Some(u16::MAX));
+            cmos.data_mut_or_insert().counter = 3;
+            assert_eq!(cmos.sequence(), Some(0));
+        }
+
+        let cmos = CMOS::<TestData, &mut MockStorage>::from_storage(&mut storage);
+        assert_eq!(cmos.sequence(), Some(0));
+        assert_eq!(cmos.data().map(|data| data.counter), Some(3));
+    }
+
+    #[test]
+    fn test_corrupted_slots() {
+        let mut storage = MockStorage::default();
+        {
+            let mut cmos = CMOS::<TestData, &mut MockStorage>::from_storage(&mut storage);
+            cmos.data_mut_or_insert().counter = 1;
+            cmos.data_mut_or_insert().counter = 2;
+        }
+
+        // corrupting the newer slot falls back to the older one
+        let newer = CMOS_DATA_SIZE as usize / 2;
+        storage.bytes[newer + SLOT_HEADER_SIZE] ^= 0xFF;
+        let cmos = CMOS::<TestData, &mut MockStorage>::from_storage(&mut storage);
+        assert_eq!(cmos.data().map(|data| data.counter), Some(1));
+        drop(cmos);
+
+        // without a valid slot the data is reset on insertion
+        storage.bytes[SLOT_HEADER_SIZE] ^= 0xFF;
+        let mut cmos = CMOS::<TestData, &mut MockStorage>::from_storage(&mut storage);
+        assert!(cmos.data().is_none());
+        assert_eq!(cmos.data_or_insert().counter, 0);
+    }
+}
@@ -11,16 +11,23 @@
 use alloc::boxed::Box;
 use alloc::collections::VecDeque;
 use alloc::format;
+use alloc::string::String;
 use alloc::vec::Vec;
 use core::fmt::Display;
 use core::pin::Pin;
+use fuzzer_data::auth::OtaKey;
+use fuzzer_data::fragments::{
+    FragmentAssembler, FragmentError, FragmentPoll, FragmentSendBuffer, FragmentStatus,
+};
 use fuzzer_data::{
-    Ota, OtaC2D, OtaC2DUnreliable, OtaD2C, OtaD2CTransport, OtaD2CUnreliable, OtaPacket,
-    MAX_FRAGMENT_SIZE, MAX_PAYLOAD_SIZE,
+    encode_payload, retransmit_timeout, ConnectionStats, LinkStats, Ota, OtaC2D, OtaC2DTransport,
+    OtaC2DUnreliable, OtaD2C, OtaD2CTransport, OtaD2CUnreliable, OtaDecodeError, OtaPacket,
+    HEARTBEAT_INTERVAL_MS, MAX_FRAGMENT_SIZE, OTA_MESSAGE_VERSION,
 };
 use log::{error, info, trace, warn};
 #[cfg(feature = "__debug_performance_trace")]
 use performance_timing::track_time;
+use performance_timing::Instant;
 use uefi::boot::ScopedProtocol;
 use uefi_raw::Ipv4Address;
 use uefi_udp4::uefi::proto::network::udp4::proto::{
@@ -70,6 +77,26 @@ pub enum ConnectionError {
     FragmentOutOfOrder,
     /// Fragment session was closed
     FragmentSessionClosed,
+    /// Fragments of a chunked transport were still missing after all retransmission requests
+    FragmentsMissing,
+
+    /// The controller speaks a different protocol version, see [`OTA_MESSAGE_VERSION`]
+    ProtocolVersionMismatch {
+        /// Protocol version of the controller
+        controller: u16,
+        /// Protocol version of this device
+        device: u16,
+    },
+}
+
+/// Current time in milliseconds, used to time the retransmission of fragments
+fn now_millis() -> u64 {
+    match performance_timing::try_instance() {
+        Some(timing) => {
+            (timing.duration_to_seconds(timing.now() - Instant::default()) * 1000.0) as u64
+        }
+        None => 0,
+    }
 }
 
 /// Configuration settings for the controller connection
@@ -84,12 +111,17 @@ pub struct ConnectionSettings {
     pub remote_port: u16,
     /// UDP port of the local device
     pub source_port: u16,
-    /// Number of retransmission attempts
+    /// Number of transmission attempts, afterwards a reliable packet is given up on
     pub resent_attempts: u8,
-    /// Timeout for acknowledgment in milliseconds
+    /// Timeout for acknowledgment of the first attempt in milliseconds
     pub ack_timeout: u64,
+    /// Upper bound of the exponentially increasing acknowledgment timeout in milliseconds, see
+    /// [`retransmit_timeout`]
+    pub max_ack_timeout: u64,
     /// Timeout for fragment reception in milliseconds
     pub fragment_timeout: u64,
+    /// Pre-shared key authenticating the packets, `None` to communicate unauthenticated
+    pub key: Option<OtaKey>,
 }
 
 impl Default for ConnectionSettings {
@@ -105,7 +137,9 @@ impl Default for ConnectionSettings {
             source_port: 4444,
             resent_attempts: 10,
             ack_timeout: 200,
+            max_ack_timeout: 1000,
             fragment_timeout: 1000,
+            key: option_env!("UFUZZ_OTA_KEY").map(OtaKey::from_passphrase),
         }
     }
 }
@@ -125,10 +159,14 @@ pub struct ControllerConnection {
 
     /// Buffer for received messages
     virtual_receive_buffer: VecDeque<OtaC2D>,
-    /// Number of retransmission attempts
+    /// Number of transmission attempts
     resent_attempts: u8,
-    /// Timeout for acknowledgment in milliseconds
+    /// Timeout for acknowledgment of the first attempt in milliseconds
     ack_timeout: u64,
+    /// Upper bound of the acknowledgment timeout in milliseconds
+    max_ack_timeout: u64,
+    /// State of the generator randomizing the retransmission timeouts
+    jitter_state: u64,
     /// Timeout for fragment reception in milliseconds
     fragment_timeout: u64,
 
@@ -138,6 +176,20 @@ pub struct ControllerConnection {
     sequence_number_rx: u64,
     /// Next sequence number for transmitted messages
     sequence_number_tx: u64,
+    /// Health statistics of this connection
+    stats: ConnectionStats,
+    /// Protocol version announced by the controller, `None` before the handshake
+    controller_version: Option<u16>,
+    /// Pre-shared key authenticating the packets
+    key: Option<OtaKey>,
+    /// Reassembly of received chunked transports
+    assembler: FragmentAssembler,
+    /// Sent chunked transports, kept to serve retransmission requests
+    send_buffer: FragmentSendBuffer,
+    /// Last error logged to the controller, reported in heartbeats
+    last_error: Option<String>,
+    /// Time the last heartbeat was sent, in milliseconds
+    last_heartbeat: u64,
 }
 
 #[cfg_attr(feature = "__debug_performance_trace", track_time)]
@@ -288,6 +340,8 @@ impl ControllerConnection {
             channel: Some(channel),
             network: Some(net),
             ack_timeout: settings.ack_timeout,
+            max_ack_timeout: settings.max_ack_timeout,
+            jitter_state: now_millis() ^ 0x9E37_79B9_7F4A_7C15,
             fragment_timeout: settings.fragment_timeout,
             resent_attempts: settings.resent_attempts,
             virtual_receive_buffer: VecDeque::new(),
@@ -295,11 +349,24 @@ impl ControllerConnection {
             remote_session: 0,
             sequence_number_rx: 0,
             sequence_number_tx: 0,
+            stats: ConnectionStats::default(),
+            controller_version: None,
+            key: settings.key,
+            assembler: FragmentAssembler::new(settings.fragment_timeout, settings.resent_attempts),
+            send_buffer: FragmentSendBuffer::new(
+                settings.fragment_timeout * (settings.resent_attempts as u64 + 1),
+            ),
+            last_error: None,
+            last_heartbeat: 0,
         })
     }
 
     /// Sends a packet to the controller
     ///
+    /// Packets exceeding [`MAX_FRAGMENT_SIZE`] are split into fragments, see
+    /// [`Self::send_chunked`], so messages of any size up to [`fuzzer_data::MAX_PAYLOAD_SIZE`]
+    /// can be sent at once.
+    ///
     /// # Arguments
     ///
     /// * `data` - The packet to send
@@ -308,12 +375,11 @@ impl ControllerConnection {
     ///
     /// * `Ok(())` - Packet was sent successfully
     /// * `Err(ConnectionError)` - An error occurred during transmission
-    #[allow(unreachable_code)]
     pub fn send<Packet: OtaPacket<OtaD2CUnreliable, OtaD2CTransport>>(
         &mut self,
         data: Packet,
     ) -> Result<(), ConnectionError> {
-        let packet = if data.reliable_transport() {
+        let mut packet = if data.reliable_transport() {
             self.sequence_number_tx += 1;
 
             data.to_packet(self.sequence_number_tx, self.remote_session)
@@ -321,13 +387,12 @@ impl ControllerConnection {
             data.to_packet(0, 0)
         };
 
-        let buf = packet.serialize().expect("Must always serialize");
+        let buf = packet
+            .serialize_with_key(self.key.as_ref())
+            .expect("Must always serialize");
 
         if buf.len() as u64 > MAX_FRAGMENT_SIZE {
             // fragment
-
-            return Err(ConnectionError::TransmitLengthExceeded); // todo
-
             if let Ota::Transport { id, session, .. } = &mut packet {
                 *id = 0;
                 *session = 0;
@@ -335,29 +400,122 @@ impl ControllerConnection {
 
             drop(buf);
             let buf = packet.serialize().expect("Must always serialize");
-
-            let chunks = buf
-                .chunks(MAX_FRAGMENT_SIZE as usize - 128)
-                .collect::<Vec<&[u8]>>();
-            for (i, chunk) in chunks.iter().enumerate() {
-                self.sequence_number_tx += 1;
-                let packet = OtaD2C::ChunkedTransport {
-                    session: self.remote_session,
-                    id: self.sequence_number_tx,
-                    fragment: i as u64,
-                    total_fragments: chunks.len() as u64,
-                    content: chunk.to_vec(),
-                };
-                let buf = packet.serialize().expect("Must always serialize");
-                self.send_native(&buf, true)?;
-            }
-            Ok(())
+            self.send_chunked(&buf)
         } else {
             // just send
             self.send_native(&buf, matches!(packet, Ota::Transport { .. }))
         }
     }
 
+    /// Sends a packet exceeding [`MAX_FRAGMENT_SIZE`] as chunked transport
+    ///
+    /// All fragments are sent at once, afterwards only the fragments requested by the controller
+    /// are retransmitted. If the payload is not acknowledged, it stays buffered to serve late
+    /// requests, see [`FragmentSendBuffer`].
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The serialized packet
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - The controller received the complete payload
+    /// * `Err(ConnectionError)` - An error occurred during transmission
+    fn send_chunked(&mut self, data: &[u8]) -> Result<(), ConnectionError> {
+        let (payload, compressed) = encode_payload(data);
+        self.sequence_number_tx += 1;
+        let id = self.sequence_number_tx;
+        let session = self.remote_session;
+
+        let total_fragments = self
+            .send_buffer
+            .insert(session, id, &payload, compressed, now_millis())
+            .map_err(|_| ConnectionError::TransmitLengthExceeded)?;
+        let all_fragments = (0..total_fragments).collect::<Vec<u64>>();
+        self.send_fragments(session, id, &all_fragments)?;
+
+        let mut virtual_receive_buffer = VecDeque::new();
+
+        let mut status = None;
+        let mut attempt = 0;
+        while status.is_none() && attempt < self.resent_attempts {
+            let timeout = self.ack_timeout_of(attempt);
+            match self.receive(Some(timeout)) {
+                Ok(Some(OtaC2D::Unreliable(OtaC2DUnreliable::Ack(sequence_number))))
+                    if sequence_number == id =>
+                {
+                    // OK received acknowledgement of the complete payload
+                    self.send_buffer.remove(session, id);
+                    status = Some(Ok(()));
+                }
+                Ok(Some(packet)) => virtual_receive_buffer.push_back(packet),
+                Ok(None) => {}
+                Err(ConnectionError::ReceiveTimeout) => {
+                    // probe with the last fragment, the controller answers with an ack or requests
+                    // the missing fragments
+                    self.stats.acks_lost += 1;
+                    attempt += 1;
+                    if let Err(err) = self.send_fragments(session, id, &[total_fragments - 1]) {
+                        status = Some(Err(err));
+                    }
+                }
+                Err(err) => {
+                    error!("Failed to transmit data: {:?}", err);
+                    status = Some(Err(ConnectionError::TransmitWaitForAckFailedHard(
+                        Box::new(err),
+                    )));
+                }
+            }
+        }
+
+        // requeue the received packets
+        for packet in virtual_receive_buffer.into_iter().rev() {
+            self.virtual_receive_buffer.push_front(packet);
+        }
+
+        self.stats
+            .link
+            .record_outcome(status, ConnectionError::TransmitNotAcknowledged)
+    }
+
+    /// Sends fragments of a buffered chunked transport without waiting for acknowledgment
+    ///
+    /// Requests for payloads that are no longer buffered are ignored, the controller gives up
+    /// on them after its retransmission requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - Session identifier of the payload
+    /// * `id` - Message identifier of the payload
+    /// * `fragments` - Indices of the fragments to send
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Fragments were sent or the request was ignored
+    /// * `Err(ConnectionError)` - An error occurred during transmission
+    fn send_fragments(
+        &mut self,
+        session: u16,
+        id: u64,
+        fragments: &[u64],
+    ) -> Result<(), ConnectionError> {
+        let packets: Vec<OtaD2C> = match self.send_buffer.fragments(session, id, fragments) {
+            Ok(packets) => packets,
+            Err(err) => {
+                warn!("Ignored request for fragments: {}", err);
+                return Ok(());
+            }
+        };
+
+        for packet in packets {
+            let buf = packet
+                .serialize_with_key(self.key.as_ref())
+                .expect("Must always serialize");
+            self.send_native(&buf, false)?;
+        }
+        Ok(())
+    }
+
     /// Sends raw data to the controller
     ///
     /// # Arguments
@@ -377,24 +535,25 @@ impl ControllerConnection {
         let mut virtual_receive_buffer = VecDeque::new();
 
         let mut status = None;
-        'attempt_loop: for _attempt in 0..self.resent_attempts {
+        'attempt_loop: for attempt in 0..self.resent_attempts {
             // initial packet sending
             if let Err(err) = self.channel.as_mut().unwrap().transmit(None, None, data) {
                 error!("Failed to transmit data: {:?}", err);
                 status = Some(Err(ConnectionError::TransmitPacket(err)));
                 break 'attempt_loop;
             }
+            self.stats.link.record_sent(attempt);
 
             // check if requires ack
             if !require_ack {
                 // does not require ack
-                status = Some(Ok(()));
-                break 'attempt_loop;
+                return Ok(());
             }
 
             // wait for ack
+            let timeout = self.ack_timeout_of(attempt);
             'wait_for_ack: while let Some(received_packet) = {
-                match self.receive(Some(self.ack_timeout)) {
+                match self.receive(Some(timeout)) {
                     Ok(None) => continue 'wait_for_ack,
                     Ok(Some(packet)) => Some(packet),
                     Err(err) => {
@@ -405,6 +564,7 @@ impl ControllerConnection {
                             )));
                             break 'attempt_loop;
                         }
+                        self.stats.acks_lost += 1;
                         continue 'attempt_loop;
                     }
                 }
@@ -437,7 +597,9 @@ impl ControllerConnection {
             self.virtual_receive_buffer.push_front(packet);
         }
 
-        status.unwrap_or(Err(ConnectionError::TransmitNotAcknowledged))
+        self.stats
+            .link
+            .record_outcome(status, ConnectionError::TransmitNotAcknowledged)
     }
 
     /// Receives raw data from the controller
@@ -470,8 +632,21 @@ impl ControllerConnection {
             }
         };
 
-        let data: OtaC2D = match OtaC2D::deserialize(&data) {
+        self.stats.received += 1;
+
+        let data: OtaC2D = match OtaC2D::deserialize_with_key(&data, self.key.as_ref()) {
             Ok(data) => data,
+            Err(e @ OtaDecodeError::ChecksumMismatch { .. }) => {
+                // drop without acknowledgement, the controller retransmits the packet
+                warn!("Dropped corrupted packet: {}", e);
+                return Ok(None);
+            }
+            Err(e) if e.is_authentication_error() => {
+                // not from the controller, or corrupted and retransmitted by the controller
+                warn!("Dropped unauthenticated packet: {}", e);
+                self.stats.link.record_unauthenticated();
+                return Ok(None);
+            }
             Err(e) => {
                 error!("Failed to deserialize data: {:?}", e);
                 return Err(ConnectionError::ReceiveNotDeserializable);
@@ -494,9 +669,8 @@ impl ControllerConnection {
             );
         }
 
-        if let Ota::Transport { session, id, .. } | Ota::ChunkedTransport { session, id, .. } =
-            &data
-        {
+        // fragments share the identifier of their payload, see `receive_fragments`
+        if let Ota::Transport { session, id, .. } = &data {
             if *session != self.remote_session {
                 #[cfg(feature = "__debug_print_udp")]
                 warn!("Received packet with new session: {}", session);
@@ -535,8 +709,6 @@ impl ControllerConnection {
     /// * `Ok(Some(OtaC2D))` - Received packet
     /// * `Ok(None)` - No packet received within timeout
     /// * `Err(ConnectionError)` - An error occurred during reception
-    #[allow(unreachable_code)]
-    #[allow(unused_variables)]
     pub fn receive(
         &mut self,
         timeout_millis: Option<u64>,
@@ -547,73 +719,325 @@ impl ControllerConnection {
         };
 
         match initial_packet {
-            OtaC2D::Transport { .. } | OtaC2D::Unreliable(_) => Ok(Some(initial_packet)),
+            OtaC2D::Unreliable(OtaC2DUnreliable::MissingFragments {
+                session,
+                id,
+                fragments,
+            }) => {
+                self.send_fragments(session, id, &fragments)?;
+                Ok(None)
+            }
+            OtaC2D::Transport { .. } | OtaC2D::Unreliable(_) => {
+                self.handle_handshake(initial_packet)
+            }
             OtaC2D::ChunkedTransport {
                 session,
                 id,
                 content,
                 fragment,
                 total_fragments,
+                compressed,
             } => {
-                return Err(ConnectionError::TransmitLengthExceeded); // todo
-
-                if total_fragments.saturating_mul(MAX_FRAGMENT_SIZE as u64)
-                    > MAX_PAYLOAD_SIZE as u64
-                {
-                    return Err(ConnectionError::PacketTooLarge);
-                }
+                let packet = match self.insert_fragment(
+                    session,
+                    id,
+                    fragment,
+                    total_fragments,
+                    compressed,
+                    content,
+                )? {
+                    Some(packet) => Some(packet),
+                    None => self.receive_fragments()?,
+                };
 
-                if fragment != 0 {
-                    return Err(ConnectionError::FragmentOutOfOrder);
+                match packet {
+                    Some(packet) => self.handle_handshake(packet),
+                    None => Ok(None),
                 }
+            }
+        }
+    }
 
-                let mut packet_content: Vec<u8> = content;
+    /// Receives the remaining fragments of incomplete chunked transports
+    ///
+    /// Missing fragments are requested from the controller if no fragment was received for the
+    /// fragment timeout, see [`FragmentAssembler::poll`].
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(OtaC2D))` - Reassembled packet
+    /// * `Ok(None)` - No payload was completed
+    /// * `Err(ConnectionError)` - An error occurred during reception
+    fn receive_fragments(&mut self) -> Result<Option<OtaC2D>, ConnectionError> {
+        let mut virtual_receive_buffer = VecDeque::new();
 
-                for i in 1..total_fragments {
-                    'wait_for_next_fragment: while let Some(received_packet) = {
-                        match self.receive_native(Some(self.fragment_timeout)) {
-                            Ok(None) => continue 'wait_for_next_fragment,
-                            Ok(Some(packet)) => Some(packet),
-                            Err(err) => {
-                                return Err(err);
-                            }
-                        }
-                    } {
-                        if let OtaC2D::ChunkedTransport {
-                            session: received_session,
-                            id: received_id,
-                            content: received_content,
-                            fragment: received_fragment,
-                            total_fragments: received_total_fragments,
-                        } = received_packet
-                        {
-                            if session != received_session {
-                                error!("Received packet with new session: {}", received_session);
-                                return Err(ConnectionError::FragmentSessionClosed);
-                            }
-                            if i != received_fragment {
-                                return Err(ConnectionError::FragmentOutOfOrder);
-                            }
-                            packet_content.extend(received_content);
-                        } else {
-                            // received other package
-                            trace!("Dropped packet: {:?}", received_packet);
-                        }
+        let mut result = Ok(None);
+        while self.assembler.is_pending() {
+            match self.receive_native(Some(self.fragment_timeout)) {
+                Ok(Some(OtaC2D::ChunkedTransport {
+                    session,
+                    id,
+                    content,
+                    fragment,
+                    total_fragments,
+                    compressed,
+                })) => {
+                    result = self.insert_fragment(
+                        session,
+                        id,
+                        fragment,
+                        total_fragments,
+                        compressed,
+                        content,
+                    );
+                    if !matches!(result, Ok(None)) {
+                        break;
                     }
                 }
+                Ok(Some(OtaC2D::Unreliable(OtaC2DUnreliable::MissingFragments {
+                    session,
+                    id,
+                    fragments,
+                }))) => {
+                    if let Err(err) = self.send_fragments(session, id, &fragments) {
+                        result = Err(err);
+                        break;
+                    }
+                }
+                Ok(Some(packet)) => {
+                    // received other package
+                    trace!("Deferred packet: {:?}", packet);
+                    virtual_receive_buffer.push_back(packet);
+                }
+                Ok(None) | Err(ConnectionError::ReceiveTimeout) => {}
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+
+            if let Err(err) = self.poll_fragments() {
+                result = Err(err);
+                break;
+            }
+        }
+
+        // requeue the received packets
+        for packet in virtual_receive_buffer.into_iter().rev() {
+            self.virtual_receive_buffer.push_front(packet);
+        }
 
-                let data: OtaC2D = match OtaC2D::deserialize(&packet_content) {
-                    Ok(data) => data,
+        result
+    }
+
+    /// Inserts a received fragment, acknowledging its payload once it is complete
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - Session identifier of the payload
+    /// * `id` - Message identifier of the payload
+    /// * `fragment` - Index of the fragment
+    /// * `total_fragments` - Total number of fragments of the payload
+    /// * `compressed` - Whether the payload is compressed
+    /// * `content` - Fragment content
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(OtaC2D))` - Reassembled packet
+    /// * `Ok(None)` - The payload is incomplete or the fragment was dropped
+    /// * `Err(ConnectionError)` - The payload is too large or could not be deserialized
+    fn insert_fragment(
+        &mut self,
+        session: u16,
+        id: u64,
+        fragment: u64,
+        total_fragments: u64,
+        compressed: bool,
+        content: Vec<u8>,
+    ) -> Result<Option<OtaC2D>, ConnectionError> {
+        match self.assembler.insert(
+            session,
+            id,
+            fragment,
+            total_fragments,
+            compressed,
+            content,
+            now_millis(),
+        ) {
+            Ok(FragmentStatus::Incomplete) | Ok(FragmentStatus::Duplicate { completed: false }) => {
+                Ok(None)
+            }
+            Ok(FragmentStatus::Duplicate { completed: true }) => {
+                // the controller missed the acknowledgement
+                self.send(OtaD2CUnreliable::Ack(id))?;
+                Ok(None)
+            }
+            Ok(FragmentStatus::Complete {
+                payload,
+                compressed,
+            }) => {
+                self.send(OtaD2CUnreliable::Ack(id))?;
+                match OtaC2D::deserialize_payload(&payload, compressed) {
+                    Ok(data) => Ok(Some(data)),
                     Err(e) => {
                         error!("Failed to deserialize data: {:?}", e);
-                        return Err(ConnectionError::ReceiveNotDeserializable);
+                        self.stats.reassembly_failures += 1;
+                        Err(ConnectionError::ReceiveNotDeserializable)
                     }
-                };
-                Ok(Some(data))
+                }
+            }
+            Err(FragmentError::PayloadTooLarge(_)) => {
+                self.stats.reassembly_failures += 1;
+                Err(ConnectionError::PacketTooLarge)
+            }
+            Err(err) => {
+                warn!("Dropped fragment: {}", err);
+                Ok(None)
             }
         }
     }
 
+    /// Requests the missing fragments of stalled chunked transports, see [`FragmentAssembler::poll`]
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - All stalled payloads requested their missing fragments
+    /// * `Err(ConnectionError)` - A payload was given up on or a request could not be sent
+    fn poll_fragments(&mut self) -> Result<(), ConnectionError> {
+        let mut result = Ok(());
+        for action in self.assembler.poll(now_millis()) {
+            match action {
+                FragmentPoll::Missing {
+                    session,
+                    id,
+                    fragments,
+                } => {
+                    self.send(OtaD2CUnreliable::MissingFragments {
+                        session,
+                        id,
+                        fragments,
+                    })?;
+                }
+                FragmentPoll::Expired { session, id } => {
+                    error!("Gave up receiving payload {} of session {}", id, session);
+                    self.stats.reassembly_failures += 1;
+                    result = Err(ConnectionError::FragmentsMissing);
+                }
+            }
+        }
+        result
+    }
+
+    /// Answers the [`OtaC2DTransport::Hello`] handshake of the controller
+    ///
+    /// Once the controller announced a different [`OTA_MESSAGE_VERSION`], all its packets are
+    /// refused until it announces a matching version.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The received packet
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(OtaC2D))` - Packet to be processed by the caller
+    /// * `Ok(None)` - The packet was a handshake with a matching version
+    /// * `Err(ConnectionError)` - The protocol versions disagree or the answer could not be sent
+    fn handle_handshake(&mut self, packet: OtaC2D) -> Result<Option<OtaC2D>, ConnectionError> {
+        if let OtaC2D::Transport {
+            content: OtaC2DTransport::Hello { version },
+            ..
+        } = &packet
+        {
+            self.controller_version = Some(*version);
+            self.send(OtaD2CTransport::HelloAck {
+                version: OTA_MESSAGE_VERSION,
+            })?;
+            if *version == OTA_MESSAGE_VERSION {
+                info!("Controller speaks protocol version {}", version);
+                return Ok(None);
+            }
+        }
+
+        match self.controller_version {
+            Some(controller) if controller != OTA_MESSAGE_VERSION => {
+                error!(
+                    "Protocol version mismatch: controller {}, device {}",
+                    controller, OTA_MESSAGE_VERSION
+                );
+                Err(ConnectionError::ProtocolVersionMismatch {
+                    controller,
+                    device: OTA_MESSAGE_VERSION,
+                })
+            }
+            _ => Ok(Some(packet)),
+        }
+    }
+
+    /// Returns the link quality statistics of this connection
+    pub fn link_stats(&self) -> LinkStats {
+        self.stats.link
+    }
+
+    /// Returns the health statistics of this connection
+    pub fn stats(&self) -> ConnectionStats {
+        self.stats
+    }
+
+    /// Calculates the acknowledgment timeout of a transmission attempt, see [`retransmit_timeout`]
+    ///
+    /// # Arguments
+    ///
+    /// * `attempt` - Zero-based transmission attempt
+    ///
+    /// # Returns
+    ///
+    /// * `u64` - The timeout in milliseconds
+    fn ack_timeout_of(&mut self, attempt: u8) -> u64 {
+        // xorshift, the jitter only needs to differ between retransmissions
+        self.jitter_state ^= self.jitter_state << 13;
+        self.jitter_state ^= self.jitter_state >> 7;
+        self.jitter_state ^= self.jitter_state << 17;
+        retransmit_timeout(
+            self.ack_timeout,
+            self.max_ack_timeout,
+            attempt,
+            self.jitter_state,
+        )
+    }
+
+    /// Sends a heartbeat to the controller, if the last one is older than [`HEARTBEAT_INTERVAL_MS`]
+    ///
+    /// Heartbeats are sent unreliably, a lost heartbeat is superseded by the next one.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples_executed` - Number of samples executed since the device started
+    /// * `free_heap_bytes` - Free memory available to the allocator
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Heartbeat was sent or is not due yet
+    /// * `Err(ConnectionError)` - An error occurred during transmission
+    pub fn send_heartbeat_if_due(
+        &mut self,
+        samples_executed: u64,
+        free_heap_bytes: u64,
+    ) -> Result<(), ConnectionError> {
+        let now = now_millis();
+        if now.saturating_sub(self.last_heartbeat) < HEARTBEAT_INTERVAL_MS {
+            return Ok(());
+        }
+        self.last_heartbeat = now;
+
+        self.send(OtaD2CUnreliable::Heartbeat {
+            uptime_ms: now,
+            free_heap_bytes,
+            samples_executed,
+            last_error: self.last_error.clone(),
+            connection_stats: self.stats,
+        })
+    }
+
     /// Sends an unreliable log message to the controller
     ///
     /// # Arguments
@@ -637,10 +1061,11 @@ impl ControllerConnection {
         if let Some(find) = file_name.rfind("/") {
             file_name = &file_name[find + 1..];
         }
-        self.send(OtaD2CUnreliable::LogMessage {
-            level,
-            message: format!("[{}:{}] {}", file_name, location.column(), message),
-        })
+        let message = format!("[{}:{}] {}", file_name, location.column(), message);
+        if level == log::Level::Error {
+            self.last_error = Some(message.clone());
+        }
+        self.send(OtaD2CUnreliable::LogMessage { level, message })
     }
 
     /// Sends a log message to the controller, requiring acknowledgment
@@ -666,10 +1091,11 @@ impl ControllerConnection {
         if let Some(find) = file_name.rfind("/") {
             file_name = &file_name[find + 1..];
         }
-        self.send(OtaD2CTransport::LogMessage {
-            level,
-            message: format!("[{}:{}] {}", file_name, location.column(), message),
-        })
+        let message = format!("[{}:{}] {}", file_name, location.column(), message);
+        if level == log::Level::Error {
+            self.last_error = Some(message.clone());
+        }
+        self.send(OtaD2CTransport::LogMessage { level, message })
     }
 }
 
@@ -4,6 +4,7 @@
 
 #[cfg(not(feature = "__debug_bochs_pretend"))]
 mod coverage_collection;
+mod differential;
 #[cfg(feature = "__debug_bochs_pretend")]
 mod fake_coverage_collection;
 #[cfg(feature = "__debug_bochs_pretend")]
@@ -15,22 +16,25 @@ use crate::controller_connection::ControllerConnection;
 #[cfg(feature = "__debug_print_dissassembly")]
 use crate::disassemble_code;
 use crate::executor::coverage_collection::CoverageCollector;
+use crate::executor::differential::RegisterUsage;
 use crate::executor::hypervisor::Hypervisor;
 use crate::mutation_engine::serialize::{SerializeError, Serializer};
 use crate::{cmos, PersistentApplicationData, PersistentApplicationState, StateTrace, Trace};
 use ::hypervisor::error::HypervisorError;
-use ::hypervisor::state::{VmExitReason, VmState};
+use ::hypervisor::state::{GuestRegisters, VmExitReason, VmState};
 use alloc::collections::{btree_map, BTreeMap, BTreeSet};
 use alloc::rc::Rc;
+use alloc::string::String;
 use alloc::vec::Vec;
 use core::cell::RefCell;
 use core::fmt::Debug;
 use coverage::harness::coverage_harness::{CoverageExecutionResult, ExecutionResultEntry};
 use coverage::harness::iteration_harness::IterationHarness;
 use coverage::interface_definition::{ComInterfaceDescription, CoverageCount};
-use custom_processing_unit::lmfence;
+use custom_processing_unit::{lmfence, PatchError};
 use data_types::addresses::{Address, UCInstructionAddress};
-use fuzzer_data::{MemoryAccess, ReportExecutionProblem};
+use fuzzer_data::genetic_pool::SampleProvenance;
+use fuzzer_data::{CoverageGranularity, ExecutionConfig, MemoryAccess, ReportExecutionProblem};
 use log::trace;
 #[cfg(feature = "__debug_print_progress_net")]
 use log::Level;
@@ -64,6 +68,20 @@ pub struct SampleExecutor {
     serializer: Serializer,
     /// Description of the coverage interface
     coverage_interface: &'static ComInterfaceDescription,
+    /// Granularity of the coverage collection
+    coverage_granularity: CoverageGranularity,
+    /// Error that prevented uploading the coverage collection patch, reported with the next execution
+    coverage_patch_error: Option<PatchError>,
+    /// Maximum number of VM steps of the trace of a sample
+    max_vm_iterations: usize,
+    /// Maximum duration of a single VM entry of a sample in TSC ticks
+    sample_timeout_tsc: u64,
+    /// Initial VM state of the hypervisor before any register randomization
+    default_initial_state: VmState,
+    /// Execute every sample a second time with two randomized initial register files
+    differential_execution: bool,
+    /// Seed of the randomized initial register files of the differential execution
+    differential_seed: u64,
 }
 
 fn disable_all_hooks() {
@@ -72,6 +90,54 @@ fn disable_all_hooks() {
     custom_processing_unit::disable_all_hooks();
 }
 
+/// Checks if the coverage information reports a hit of the address
+fn is_covered(
+    coverage_information: &[ExecutionResultEntry],
+    address: UCInstructionAddress,
+) -> bool {
+    coverage_information.iter().any(|entry| {
+        matches!(entry, ExecutionResultEntry::Covered { count, .. } if *count > 0)
+            && entry.address() == address
+    })
+}
+
+/// SplitMix64 step, used to derive register values from a seed
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Returns the general purpose registers that are randomized, all except the stack pointer
+fn general_purpose_registers_mut(registers: &mut GuestRegisters) -> [&mut u64; 15] {
+    [
+        &mut registers.rax,
+        &mut registers.rbx,
+        &mut registers.rcx,
+        &mut registers.rdx,
+        &mut registers.rdi,
+        &mut registers.rsi,
+        &mut registers.rbp,
+        &mut registers.r8,
+        &mut registers.r9,
+        &mut registers.r10,
+        &mut registers.r11,
+        &mut registers.r12,
+        &mut registers.r13,
+        &mut registers.r14,
+        &mut registers.r15,
+    ]
+}
+
+/// FNV-1a hash of a code sample, used to derive per sample seeds
+fn sample_hash(sample: &[u8]) -> u64 {
+    sample.iter().fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
 /// Result of executing a single code sample
 pub struct ExecutionSampleResult {
     /// Serialized version of the executed sample, if successful
@@ -113,8 +179,16 @@ impl SampleExecutor {
 
         execution_result.reset(&self.hypervisor.initial_state);
 
+        if let Some(error) = self.coverage_patch_error.take() {
+            execution_result
+                .events
+                .push(ExecutionEvent::CoveragePatchError { error });
+        }
+
         // load code sample to hypervisor memory
         self.hypervisor.load_code_blob(sample);
+        cmos.data_mut_or_insert()
+            .begin_sample(sample, PersistentApplicationState::ExecutingSample);
 
         #[cfg(feature = "__debug_print_dissassembly")]
         disassemble_code(sample);
@@ -123,7 +197,9 @@ impl SampleExecutor {
 
         // do a trace
         self.hypervisor.prepare_vm_state();
-        self.hypervisor.trace_vm(&mut execution_result.trace, 500);
+        self.hypervisor
+            .trace_vm(&mut execution_result.trace, self.max_vm_iterations);
+        execution_result.trace.set_sample_length(sample.len());
 
         let serialized_sample = self
             .serializer
@@ -162,6 +238,18 @@ impl SampleExecutor {
 
         self.hypervisor.capture_state(&mut execution_result.state);
         execution_result.exit = no_coverage_vm_exit;
+        cmos.data_mut_or_insert().state = PersistentApplicationState::Idle;
+
+        if execution_result.exit == VmExitReason::PreemptionTimeout {
+            // every further execution would hang for the full timeout as well
+            execution_result.events.push(ExecutionEvent::SampleTimeout {
+                timeout_tsc: self.sample_timeout_tsc,
+            });
+
+            return ExecutionSampleResult {
+                serialized_sample: None,
+            };
+        }
 
         if let VmExitReason::EPTPageFault(fault) = &execution_result.exit {
             if ((fault.data_read && !fault.was_readable)
@@ -187,14 +275,15 @@ impl SampleExecutor {
                 // plan the following executions to collect coverage
                 let execution_plan = coverage.planner.execute_for_all_addresses(
                     |addresses: &[UCInstructionAddress]| {
-                        if addresses.len() == 1 {
-                            cmos.data_mut_or_insert().state =
-                                PersistentApplicationState::CollectingCoverage(
-                                    addresses[0].address() as u16
-                                );
+                        cmos.data_mut_or_insert().state = if addresses.len() == 1 {
+                            PersistentApplicationState::CollectingCoverage(
+                                addresses[0].address() as u16,
+                            )
                         } else {
-                            todo!("Implement multi-address coverage collection");
-                        }
+                            PersistentApplicationState::CollectingCoverageBatch(
+                                addresses[0].address() as u16,
+                            )
+                        };
 
                         #[cfg(feature = "__debug_print_progress_print")]
                         print!("{}\r", addresses[0]);
@@ -215,10 +304,8 @@ impl SampleExecutor {
                                             self.hypervisor.run_with_callback(true, disable_all_hooks);
                                         // hooks are now disabled, so coverage collection stopped
 
-                                        if addresses.len() == 1 {
-                                            cmos.data_mut_or_insert().state =
-                                                PersistentApplicationState::Idle;
-                                        }
+                                        cmos.data_mut_or_insert().state =
+                                            PersistentApplicationState::Idle;
 
                                         let mut state = self.hypervisor.initial_state.clone();
                                         self.hypervisor.capture_state(&mut state);
@@ -302,6 +389,7 @@ impl SampleExecutor {
                             };
 
                             if exit.is_some() || state.is_some() {
+                                // with several hooks the mismatch is reported for the first one
                                 execution_result.events.push(
                                     ExecutionEvent::VmMismatchCoverageCollection {
                                         address: hooked_addresses[0].address() as u16,
@@ -340,8 +428,34 @@ impl SampleExecutor {
 
             #[cfg(feature = "__debug_print_progress_print")]
             print!("     \r");
+
+            #[cfg(feature = "coverage_batch_selfcheck")]
+            if collect_coverage && self.coverage_batch_size() > 1 {
+                let reference = self.collect_single_hook_coverage(cmos);
+                let addresses = execution_result
+                    .coverage
+                    .keys()
+                    .chain(reference.keys())
+                    .copied()
+                    .collect::<BTreeSet<_>>();
+                for address in addresses {
+                    let batched = execution_result.coverage.get(&address).copied();
+                    let single = reference.get(&address).copied();
+                    if batched != single {
+                        execution_result
+                            .events
+                            .push(ExecutionEvent::CoverageBatchMismatch {
+                                address: address.address() as u16,
+                                batched,
+                                single,
+                            });
+                    }
+                }
+            }
         }
 
+        cmos.data_mut_or_insert().state = PersistentApplicationState::ExecutingSample;
+
         // do a serialized execution
         if let Some(ref serialized_code) = serialized_sample {
             self.hypervisor.load_code_blob(serialized_code.as_slice());
@@ -396,6 +510,14 @@ impl SampleExecutor {
             }
         }
 
+        // do a differential execution
+        if self.differential_execution {
+            self.hypervisor.load_code_blob(sample);
+            self.execute_differential(sample, execution_result);
+        }
+
+        cmos.data_mut_or_insert().state = PersistentApplicationState::Idle;
+
         // re-enable Non-Maskable Interrupts
         drop(nmi_guard);
 
@@ -418,6 +540,8 @@ impl SampleExecutor {
     ) -> Result<SampleExecutor, HypervisorError> {
         trace!("Initializing coverage collection");
 
+        let mut coverage_patch_error = None;
+
         #[cfg(not(feature = "__debug_pretend_no_coverage"))]
         let coverage_collector =
             coverage_collection::CoverageCollector::initialize(excluded_addresses)
@@ -425,10 +549,14 @@ impl SampleExecutor {
                 .unwrap_or_else(|error| {
                     warn!("Failed to initialize custom processing unit: {:?}", error);
                     warn!("Coverage collection will be disabled");
+                    if let custom_processing_unit::Error::PatchError(error) = error {
+                        coverage_patch_error = Some(error);
+                    }
                     None
                 })
                 .map(|collector| {
-                    let hookable_addresses = collector.get_iteration_harness();
+                    let hookable_addresses =
+                        collector.get_iteration_harness(CoverageGranularity::default());
                     CoverageCollectorData {
                         collector,
                         planner: hookable_addresses,
@@ -441,7 +569,10 @@ impl SampleExecutor {
         trace!("Coverage collection initialized");
 
         trace!("Initializing hypervisor");
-        let hypervisor = Hypervisor::new(coverage_interface)?;
+        let mut hypervisor = Hypervisor::new(coverage_interface)?;
+        let default_config = ExecutionConfig::default();
+        hypervisor.set_sample_timeout(default_config.sample_timeout_tsc);
+        let default_initial_state = hypervisor.initial_state.clone();
         trace!("Hypervisor initialized");
 
         Ok(Self {
@@ -449,13 +580,380 @@ impl SampleExecutor {
             coverage: coverage_collector,
             serializer: Serializer::default(),
             coverage_interface,
+            coverage_granularity: CoverageGranularity::default(),
+            coverage_patch_error,
+            max_vm_iterations: default_config.max_vm_iterations as usize,
+            sample_timeout_tsc: default_config.sample_timeout_tsc,
+            default_initial_state,
+            differential_execution: default_config.differential_execution,
+            differential_seed: 0,
         })
     }
 
+    /// Returns the number of addresses hooked simultaneously during coverage collection
+    ///
+    /// # Returns
+    ///
+    /// * `usize` - The effective batch size, `0` if coverage collection is not supported
+    pub fn coverage_batch_size(&self) -> usize {
+        self.coverage
+            .as_ref()
+            .map_or(0, |coverage| coverage.collector.batch_size())
+    }
+
+    /// Sets the number of addresses hooked simultaneously during coverage collection
+    ///
+    /// The batch size is limited by the free hooks of the processor and the coverage interface.
+    /// A batch size of one attributes a crash during coverage collection to a single address.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch_size` - Requested number of simultaneous hooks
+    pub fn set_coverage_batch_size(&mut self, batch_size: usize) {
+        if let Some(coverage) = self.coverage.as_mut() {
+            if coverage.collector.batch_size() != batch_size {
+                coverage.collector.set_batch_size(batch_size);
+                self.update_excluded_addresses();
+            }
+        }
+    }
+
+    /// Collects the coverage of the loaded sample with one hook per execution
+    ///
+    /// Reference for the batched coverage collection, external interrupts are retried.
+    ///
+    /// # Arguments
+    ///
+    /// * `cmos` - CMOS storage for persistent data
+    ///
+    /// # Returns
+    ///
+    /// * `BTreeMap<UCInstructionAddress, CoverageCount>` - Hit counts of the covered addresses
+    #[cfg(feature = "coverage_batch_selfcheck")]
+    fn collect_single_hook_coverage(
+        &mut self,
+        cmos: &mut cmos::CMOS<PersistentApplicationData>,
+    ) -> BTreeMap<UCInstructionAddress, CoverageCount> {
+        let mut result = BTreeMap::new();
+        let Some(coverage) = self.coverage.as_mut() else {
+            return result;
+        };
+
+        let execution_plan = coverage.planner.execute_for_all_addresses_with_size(
+            1,
+            |addresses: &[UCInstructionAddress]| {
+                let mut iteration: usize = 0;
+                loop {
+                    iteration += 1;
+
+                    cmos.data_mut_or_insert().state =
+                        PersistentApplicationState::CollectingCoverage(
+                            addresses[0].address() as u16
+                        );
+                    self.hypervisor.prepare_vm_state();
+                    let result = coverage
+                        .collector
+                        .execute_coverage_collection(addresses, || {
+                            let vm_exit =
+                                self.hypervisor.run_with_callback(true, disable_all_hooks);
+                            cmos.data_mut_or_insert().state = PersistentApplicationState::Idle;
+                            vm_exit
+                        });
+
+                    match result {
+                        Ok(result)
+                            if result.result == VmExitReason::ExternalInterrupt
+                                && iteration < 10 =>
+                        {
+                            continue;
+                        }
+                        result => return result,
+                    }
+                }
+            },
+        );
+
+        for entry in execution_plan.flatten().flat_map(|result| result.hooks) {
+            if let ExecutionResultEntry::Covered { address, count, .. } = entry {
+                result.insert(address, count);
+            }
+        }
+
+        result
+    }
+
+    /// Releases the patch RAM and the hooks of the coverage collection while executing a function
+    ///
+    /// The coverage collection is torn down before and initialized again after the function,
+    /// keeping the batch size and the excluded addresses. Tearing it down frees the region of the
+    /// coverage patch, see [`custom_processing_unit::MsramAllocator::free`]. This allows other
+    /// microcode patches to be installed temporarily, they must be freed again before the
+    /// function returns.
+    ///
+    /// # Arguments
+    ///
+    /// * `func` - Function to execute without coverage collection
+    ///
+    /// # Returns
+    ///
+    /// * `R` - Result of the function
+    pub fn with_coverage_suspended<R, F: FnOnce() -> R>(&mut self, func: F) -> R {
+        let previous = self.coverage.take().map(|coverage| {
+            (
+                coverage.collector.excluded_addresses(),
+                coverage.collector.batch_size(),
+            )
+        });
+
+        let result = func();
+
+        if let Some((excluded_addresses, batch_size)) = previous {
+            match CoverageCollector::initialize(excluded_addresses) {
+                Ok(mut collector) => {
+                    collector.set_batch_size(batch_size);
+                    let planner = collector.get_iteration_harness(self.coverage_granularity);
+                    self.coverage = Some(CoverageCollectorData { collector, planner });
+                }
+                Err(error) => {
+                    error!("Failed to reinitialize coverage collection: {:?}", error);
+                    if let custom_processing_unit::Error::PatchError(error) = error {
+                        self.coverage_patch_error = Some(error);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
     /// Updates the set of excluded addresses for coverage collection
     pub fn update_excluded_addresses(&mut self) {
         if let Some(coverage) = self.coverage.as_mut() {
-            coverage.planner = coverage.collector.get_iteration_harness();
+            coverage.planner = coverage
+                .collector
+                .get_iteration_harness(self.coverage_granularity);
+        }
+    }
+
+    /// Returns the granularity of the coverage collection
+    pub fn coverage_granularity(&self) -> CoverageGranularity {
+        self.coverage_granularity
+    }
+
+    /// Sets the granularity of the coverage collection
+    ///
+    /// A coarser granularity hooks fewer addresses, trading feedback resolution for speed.
+    pub fn set_coverage_granularity(&mut self, granularity: CoverageGranularity) {
+        if self.coverage_granularity != granularity {
+            self.coverage_granularity = granularity;
+            self.update_excluded_addresses();
+        }
+    }
+
+    /// Applies an execution configuration
+    ///
+    /// The configuration is expected to be clamped, see [`ExecutionConfig::clamped`]. With a
+    /// register seed the general purpose registers of the initial state are randomized, without
+    /// one the default initial state is restored. The stack pointer, instruction pointer and
+    /// flags are never randomized. The sample timeout bounds every following VM entry. With
+    /// differential execution every sample is executed again with two randomized register files.
+    /// With memory reset the writable guest memory is restored before every execution.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The execution configuration to apply
+    pub fn set_execution_config(&mut self, config: &ExecutionConfig) {
+        self.max_vm_iterations = config.max_vm_iterations as usize;
+        self.sample_timeout_tsc = config.sample_timeout_tsc;
+        self.hypervisor
+            .set_sample_timeout(config.sample_timeout_tsc);
+
+        let mut state = self.default_initial_state.clone();
+        if let Some(mut seed) = config.initial_reg_seed {
+            for register in general_purpose_registers_mut(&mut state.standard_registers) {
+                *register = splitmix64(&mut seed);
+            }
+        }
+        self.hypervisor.initial_state = state;
+        self.differential_execution = config.differential_execution;
+        self.set_memory_reset(config.reset_guest_memory);
+    }
+
+    /// Returns if the writable guest memory is restored from a snapshot before every execution
+    pub fn memory_reset(&self) -> bool {
+        self.hypervisor.memory_reset()
+    }
+
+    /// Sets if the writable guest memory is restored from a snapshot before every execution
+    ///
+    /// Prevents residual memory of a previous sample from influencing the next one.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether to reset the guest memory
+    pub fn set_memory_reset(&mut self, enabled: bool) {
+        self.hypervisor.set_memory_reset(enabled);
+    }
+
+    /// Sets the seed of the randomized initial register files of the differential execution
+    ///
+    /// The register files of a sample are derived from this seed and the sample itself, so
+    /// executions are reproducible with the same seed.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - The seed, set by the controller through `SetRandomSeed`
+    pub fn set_differential_seed(&mut self, seed: u64) {
+        self.differential_seed = seed;
+    }
+
+    /// Executes the loaded sample twice with two randomized initial register files
+    ///
+    /// Divergences of the exit reason or the final registers that are not explained by the
+    /// differing initial registers are reported as [`ExecutionEvent::InitialStateSensitivity`].
+    /// The initial state of the hypervisor is restored afterward.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample` - The loaded code sample
+    /// * `execution_result` - Where to store the event
+    fn execute_differential(&mut self, sample: &[u8], execution_result: &mut ExecutionResult) {
+        let initial_state = self.hypervisor.initial_state.clone();
+
+        let mut seed = self.differential_seed ^ sample_hash(sample);
+        let state_seeds = [splitmix64(&mut seed), splitmix64(&mut seed)];
+
+        let mut usage = RegisterUsage::default();
+        let mut complete = true;
+        let mut trace = Trace::default();
+        let mut initial = [initial_state.clone(), initial_state.clone()];
+        let mut results = [initial_state.clone(), initial_state.clone()];
+        let mut exits = [VmExitReason::default(), VmExitReason::default()];
+
+        for (index, state_seed) in state_seeds.into_iter().enumerate() {
+            differential::randomize_initial_state(&mut initial[index], state_seed);
+            self.hypervisor.initial_state.clone_from(&initial[index]);
+
+            // the executed instructions may differ between both register files
+            self.hypervisor.prepare_vm_state();
+            let trace_exit = self.hypervisor.trace_vm(&mut trace, self.max_vm_iterations);
+            trace.set_sample_length(sample.len());
+            complete &= trace_exit != VmExitReason::MonitorTrap;
+            usage.collect(sample, &trace);
+
+            exits[index] = {
+                let mut iteration = 0;
+                loop {
+                    iteration += 1;
+
+                    self.hypervisor.prepare_vm_state();
+                    let vm_exit = self.hypervisor.run_vm(false);
+
+                    if iteration < 100 && vm_exit == VmExitReason::ExternalInterrupt {
+                        #[cfg(feature = "__debug_print_external_interrupt_notification")]
+                        trace!(
+                            "External interrupt detected (differential). Retrying... {}",
+                            iteration
+                        );
+                        continue;
+                    } else {
+                        break vm_exit;
+                    }
+                }
+            };
+            self.hypervisor.capture_state(&mut results[index]);
+        }
+
+        self.hypervisor.initial_state = initial_state;
+
+        if !complete {
+            // instructions beyond the trace limit are unknown, differences can not be explained
+            return;
+        }
+
+        let [exit_a, exit_b] = exits;
+        let exits = if !usage.is_input_dependent() && !exit_a.is_same_kind(&exit_b) {
+            Some((exit_a, exit_b))
+        } else {
+            None
+        };
+
+        let registers = usage.unexplained_differences(
+            [
+                &initial[0].standard_registers,
+                &initial[1].standard_registers,
+            ],
+            [
+                &results[0].standard_registers,
+                &results[1].standard_registers,
+            ],
+        );
+
+        if exits.is_some() || !registers.is_empty() {
+            execution_result
+                .events
+                .push(ExecutionEvent::InitialStateSensitivity {
+                    state_seeds: (state_seeds[0], state_seeds[1]),
+                    exits,
+                    registers,
+                });
+        }
+    }
+
+    /// Executes a code sample with a single hook on `address` to confirm its coverage
+    ///
+    /// # Arguments
+    ///
+    /// * `sample` - The code sample to execute
+    /// * `address` - The microcode address to hook
+    /// * `cmos` - CMOS storage for persistent data
+    ///
+    /// # Returns
+    ///
+    /// * `Option<bool>` - Whether the address was hit, `None` if coverage collection is not supported or failed
+    pub fn confirm_coverage(
+        &mut self,
+        sample: &[u8],
+        address: u16,
+        cmos: &mut cmos::CMOS<PersistentApplicationData>,
+    ) -> Option<bool> {
+        let coverage = self.coverage.as_mut()?;
+
+        // try to disable Non-Maskable Interrupts
+        let _nmi_guard = NMIGuard::disable_nmi(true);
+
+        self.hypervisor.load_code_blob(sample);
+        cmos.data_mut_or_insert().last_sample_hash = SampleProvenance::fingerprint(sample);
+
+        let hooks = [UCInstructionAddress::from_const(address as usize)];
+
+        let mut iteration: usize = 0;
+        loop {
+            iteration += 1;
+
+            cmos.data_mut_or_insert().state =
+                PersistentApplicationState::CollectingCoverage(address);
+
+            self.hypervisor.prepare_vm_state();
+            let result = coverage.collector.execute_coverage_collection(&hooks, || {
+                let vm_exit = self.hypervisor.run_with_callback(true, disable_all_hooks);
+                cmos.data_mut_or_insert().state = PersistentApplicationState::Idle;
+                vm_exit
+            });
+
+            match result {
+                Err(error) => {
+                    cmos.data_mut_or_insert().state = PersistentApplicationState::Idle;
+                    error!("Failed to confirm coverage of {:04x}: {:?}", address, error);
+                    return None;
+                }
+                Ok(result) => {
+                    if result.result == VmExitReason::ExternalInterrupt && iteration < 10 {
+                        continue;
+                    }
+                    return Some(is_covered(&result.hooks, hooks[0]));
+                }
+            }
         }
     }
 
@@ -478,7 +976,9 @@ impl SampleExecutor {
     ) -> VmExitReason {
         self.hypervisor.load_code_blob(sample);
         self.hypervisor.prepare_vm_state();
-        self.hypervisor.trace_vm(trace_result, max_trace_length)
+        let exit = self.hypervisor.trace_vm(trace_result, max_trace_length);
+        trace_result.set_sample_length(sample.len());
+        exit
     }
 
     /// Traces the state changes during sample execution
@@ -535,7 +1035,7 @@ impl SampleExecutor {
     pub fn selfcheck(&mut self) -> bool {
         for _ in 0..10 {
             // can fail due to ExternalInterrupts
-            if self.hypervisor.selfcheck() {
+            if self.hypervisor.selfcheck() && self.hypervisor.selfcheck_memory_reset() {
                 return true;
             }
             warn!("Selfcheck failed...");
@@ -561,6 +1061,11 @@ pub enum ExecutionEvent {
         /// The error that occurred
         error: coverage::harness::coverage_harness::CoverageError,
     },
+    /// The coverage collection patch could not be applied, coverage collection is disabled
+    CoveragePatchError {
+        /// The error that occurred
+        error: PatchError,
+    },
     /// Mismatch between serialized and normal execution
     SerializedMismatch {
         /// Exit reason from serialized execution, set if different from normal execution
@@ -581,6 +1086,31 @@ pub enum ExecutionEvent {
     },
     /// Access to coverage collection area
     AccessCoverageArea,
+    /// Batched coverage collection disagrees with the collection with a single hook
+    CoverageBatchMismatch {
+        /// Address with differing hit counts
+        address: u16,
+        /// Hit count of the batched collection, `None` if not covered
+        batched: Option<CoverageCount>,
+        /// Hit count of the single hook collection, `None` if not covered
+        single: Option<CoverageCount>,
+    },
+    /// The sample was preempted after running for the configured timeout
+    SampleTimeout {
+        /// Timeout of a single VM entry in TSC ticks
+        timeout_tsc: u64,
+    },
+    /// Executions with two randomized initial register files diverge in a way that is not
+    /// explained by the differing registers
+    InitialStateSensitivity {
+        /// Seeds of the initial register files of both executions
+        state_seeds: (u64, u64),
+        /// Exit reasons of both executions, set if they differ although the sample reads none
+        /// of the randomized registers
+        exits: Option<(VmExitReason, VmExitReason)>,
+        /// Registers with unexplained differences
+        registers: Vec<&'static str>,
+    },
 }
 
 impl From<ExecutionEvent> for Option<ReportExecutionProblem> {
@@ -594,6 +1124,7 @@ impl From<ExecutionEvent> for Option<ReportExecutionProblem> {
                 serialized_state,
             }),
             ExecutionEvent::CoverageCollectionError { .. } => None,
+            ExecutionEvent::CoveragePatchError { .. } => None,
             ExecutionEvent::VmMismatchCoverageCollection {
                 address,
                 coverage_exit,
@@ -614,6 +1145,24 @@ impl From<ExecutionEvent> for Option<ReportExecutionProblem> {
             }),
             ExecutionEvent::VeryLikelyBug => Some(ReportExecutionProblem::VeryLikelyBug),
             ExecutionEvent::AccessCoverageArea => Some(ReportExecutionProblem::AccessCoverageArea),
+            ExecutionEvent::CoverageBatchMismatch { address, .. } => {
+                Some(ReportExecutionProblem::CoverageProblem {
+                    address,
+                    coverage_exit: None,
+                    coverage_state: None,
+                })
+            }
+            // already reported through the exit reason
+            ExecutionEvent::SampleTimeout { .. } => None,
+            ExecutionEvent::InitialStateSensitivity {
+                state_seeds,
+                exits,
+                registers,
+            } => Some(ReportExecutionProblem::InitialStateSensitivity {
+                state_seeds,
+                exits,
+                registers: registers.into_iter().map(String::from).collect(),
+            }),
         }
     }
 }
@@ -647,3 +1196,28 @@ impl ExecutionResult {
         self.exit = VmExitReason::default();
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::executor::is_covered;
+    use coverage::harness::coverage_harness::ExecutionResultEntry;
+    use data_types::addresses::UCInstructionAddress;
+
+    #[test]
+    fn test_confirm_coverage_hit_miss() {
+        let hooked = UCInstructionAddress::from_const(0x428);
+        let other = UCInstructionAddress::from_const(0x42a);
+
+        let hit = [ExecutionResultEntry::Covered {
+            address: hooked,
+            count: 3,
+            last_rip: 0,
+        }];
+        assert!(is_covered(&hit, hooked));
+        assert!(!is_covered(&hit, other));
+
+        let miss = [ExecutionResultEntry::NotCovered { address: hooked }];
+        assert!(!is_covered(&miss, hooked));
+        assert!(!is_covered(&[], hooked));
+    }
+}
@@ -4,7 +4,7 @@ use alloc::format;
 use alloc::rc::Rc;
 use alloc::string::ToString;
 use core::cell::RefCell;
-use core::ops::{Deref, DerefMut};
+use core::ops::DerefMut;
 use core::pin::Pin;
 use coverage::harness::coverage_harness::hookable_address_iterator::HookableAddressIterator;
 use coverage::harness::coverage_harness::modification_engine::ModificationEngineSettings;
@@ -16,6 +16,8 @@ use coverage::interface::safe::ComInterface;
 use coverage::interface_definition;
 use custom_processing_unit::CustomProcessingUnit;
 use data_types::addresses::{Address, UCInstructionAddress};
+use fuzzer_data::CoverageGranularity;
+use log::{error, info, log_enabled, trace, warn, Level};
 #[cfg(feature = "__debug_performance_trace")]
 use performance_timing::track_time;
 use ucode_dump::RomDump;
@@ -31,6 +33,7 @@ pub struct CoverageCollector {
     rom: &'static RomDump<'static, 'static>,
 
     hooks: usize,
+    batch_size: usize,
     modification_engine_settings: ModificationEngineSettings,
 
     excluded_addresses: Rc<RefCell<BTreeSet<u16>>>,
@@ -43,10 +46,19 @@ impl CoverageCollector {
     ) -> Result<Self, custom_processing_unit::Error> {
         let mut cpu = CustomProcessingUnit::new()?;
 
+        // hooks left behind by a previous session are cleared by init and zero_hooks
+        custom_processing_unit::activate_udebug_insts();
+        let match_patch = cpu.snapshot_match_patch();
+        if match_patch.set_entries().next().is_some() {
+            warn!("Found stale match & patch state:\n{}", match_patch);
+        } else {
+            info!("Match & patch state is clean");
+        }
+
         cpu.init()?;
         cpu.zero_hooks()?;
 
-        let cpu = Box::pin(cpu);
+        let mut cpu = Box::pin(cpu);
         let rom = cpu.rom();
 
         #[allow(unused_unsafe)]
@@ -65,11 +77,8 @@ impl CoverageCollector {
         let hooks = {
             let max_hooks = interface.description().max_number_of_hooks;
 
-            let device_max_hooks = match cpu.current_glm_version {
-                custom_processing_unit::GLM_OLD => 31,
-                custom_processing_unit::GLM_NEW => 32,
-                _ => 0,
-            };
+            // hooks installed by someone else must survive the coverage collection
+            let device_max_hooks = cpu.free_hook_count();
 
             max_hooks.min(device_max_hooks)
         };
@@ -82,23 +91,16 @@ impl CoverageCollector {
 
         // break the self-referential cycle
         let mut interface_static_tmp = interface.as_mut();
-        let cpu_static_tmp = cpu.as_ref();
+        let mut cpu_static_tmp = cpu.as_mut();
         let interface_static: &mut ComInterface = interface_static_tmp.deref_mut();
-        let cpu_static: &CustomProcessingUnit = cpu_static_tmp.deref();
+        let cpu_static: &mut CustomProcessingUnit = cpu_static_tmp.deref_mut();
 
         let interface_static: &'static mut ComInterface =
             unsafe { core::mem::transmute(interface_static) };
-        let cpu_static: &'static CustomProcessingUnit = unsafe { core::mem::transmute(cpu_static) };
-
-        let harness = match CoverageHarness::new(interface_static, cpu_static) {
-            Ok(harness) => harness,
-            Err(e) => {
-                return Err(custom_processing_unit::Error::Other(format!(
-                    "Failed to initiate harness {:?}",
-                    e
-                )));
-            }
-        };
+        let cpu_static: &'static mut CustomProcessingUnit =
+            unsafe { core::mem::transmute(cpu_static) };
+
+        let harness = CoverageHarness::new(interface_static, cpu_static)?;
 
         Ok(Self {
             custom_processing_unit: Some(cpu),
@@ -107,19 +109,43 @@ impl CoverageCollector {
             modification_engine_settings: ModificationEngineSettings::default(),
             rom,
             hooks,
+            batch_size: hooks,
             excluded_addresses,
         })
     }
 
-    pub fn get_iteration_harness(&self) -> IterationHarness {
+    /// Maximum number of addresses that can be hooked simultaneously
+    pub fn max_batch_size(&self) -> usize {
+        self.hooks
+    }
+
+    /// Number of addresses hooked simultaneously per execution
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    /// Sets the number of addresses hooked simultaneously, clamped to `1..=max_batch_size()`
+    ///
+    /// Takes effect with the next [`Self::get_iteration_harness`].
+    pub fn set_batch_size(&mut self, batch_size: usize) {
+        self.batch_size = batch_size.clamp(1, self.hooks);
+    }
+
+    /// Addresses excluded from the coverage collection, shared with the sample executor
+    pub fn excluded_addresses(&self) -> Rc<RefCell<BTreeSet<u16>>> {
+        self.excluded_addresses.clone()
+    }
+
+    pub fn get_iteration_harness(&self, granularity: CoverageGranularity) -> IterationHarness {
         let borrow = self.excluded_addresses.borrow();
 
         let hookable_addresses = HookableAddressIterator::construct(
             self.rom,
             &self.modification_engine_settings,
-            self.hooks,
+            self.batch_size,
             |address| {
                 !borrow.contains(&(address.address() as u16))
+                    && granularity.includes_address(address.address() as u16)
                     && (address.address() < 0x1000 || !cfg!(feature = "__debug_only_below_0x1000"))
             },
         );
@@ -132,6 +158,32 @@ impl CoverageCollector {
         hooks: &[UCInstructionAddress],
         func: F,
     ) -> Result<CoverageExecutionResult<FuncResult>, CoverageError> {
+        // coverage hooks cover the instruction pair of the even address
+        let excluded = self.excluded_addresses.borrow();
+        for hook in hooks {
+            if let Err(err) = custom_processing_unit::validate_hook_target_excluding(
+                self.rom,
+                hook.align_even(),
+                |_| excluded.contains(&(hook.address() as u16)),
+            ) {
+                error!("Refusing to hook {}: {}", hook, err);
+                return Err(CoverageError::InvalidHookTarget(err));
+            }
+        }
+        drop(excluded);
+
+        if log_enabled!(Level::Trace) {
+            let symbols = self.rom.symbols();
+            for hook in hooks {
+                trace!(
+                    "Hooking {} ({}):\n{}",
+                    hook,
+                    symbols.symbol_for(*hook).unwrap_or("unknown"),
+                    self.rom.disassemble_range(*hook, 1)
+                );
+            }
+        }
+
         match &mut self.coverage_harness {
             Some(harness) => harness.execute(hooks, func),
             None => unreachable!("since coverage_harness is always Some, until dropped"),
@@ -0,0 +1,354 @@
+//! Differential Execution Module
+//!
+//! A sample is executed with two randomized initial register files. This module determines
+//! which registers a sample depends on and which differences between both executions can not
+//! be explained by the differing initial states.
+
+use crate::Trace;
+use ::hypervisor::state::{GuestRegisters, VmState, M128A};
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use iced_x86::{Decoder, DecoderOptions, InstructionInfoFactory, OpAccess, Register, RflagsBits};
+
+/// Arithmetic flags of the rflags register: CF, PF, AF, ZF, SF and OF
+pub const RFLAGS_ARITHMETIC_MASK: u64 = 0x8D5;
+
+/// Registers compared after a differential execution, XMM registers are compared in two halves
+const COMPARED_REGISTERS: [(&str, &str); 49] = [
+    ("rax", "rax"),
+    ("rbx", "rbx"),
+    ("rcx", "rcx"),
+    ("rdx", "rdx"),
+    ("rdi", "rdi"),
+    ("rsi", "rsi"),
+    ("rbp", "rbp"),
+    ("r8", "r8"),
+    ("r9", "r9"),
+    ("r10", "r10"),
+    ("r11", "r11"),
+    ("r12", "r12"),
+    ("r13", "r13"),
+    ("r14", "r14"),
+    ("r15", "r15"),
+    ("rsp", "rsp"),
+    ("rflags", "rflags"),
+    ("xmm0", "xmm0.low"),
+    ("xmm0", "xmm0.high"),
+    ("xmm1", "xmm1.low"),
+    ("xmm1", "xmm1.high"),
+    ("xmm2", "xmm2.low"),
+    ("xmm2", "xmm2.high"),
+    ("xmm3", "xmm3.low"),
+    ("xmm3", "xmm3.high"),
+    ("xmm4", "xmm4.low"),
+    ("xmm4", "xmm4.high"),
+    ("xmm5", "xmm5.low"),
+    ("xmm5", "xmm5.high"),
+    ("xmm6", "xmm6.low"),
+    ("xmm6", "xmm6.high"),
+    ("xmm7", "xmm7.low"),
+    ("xmm7", "xmm7.high"),
+    ("xmm8", "xmm8.low"),
+    ("xmm8", "xmm8.high"),
+    ("xmm9", "xmm9.low"),
+    ("xmm9", "xmm9.high"),
+    ("xmm10", "xmm10.low"),
+    ("xmm10", "xmm10.high"),
+    ("xmm11", "xmm11.low"),
+    ("xmm11", "xmm11.high"),
+    ("xmm12", "xmm12.low"),
+    ("xmm12", "xmm12.high"),
+    ("xmm13", "xmm13.low"),
+    ("xmm13", "xmm13.high"),
+    ("xmm14", "xmm14.low"),
+    ("xmm14", "xmm14.high"),
+    ("xmm15", "xmm15.low"),
+    ("xmm15", "xmm15.high"),
+];
+
+/// Maps a full register to the name used by [`GuestRegisters::get_field`]
+///
+/// Returns `None` for registers that are not part of the guest register file.
+fn register_name(register: Register) -> Option<&'static str> {
+    let name = match register.full_register() {
+        Register::RAX => "rax",
+        Register::RBX => "rbx",
+        Register::RCX => "rcx",
+        Register::RDX => "rdx",
+        Register::RDI => "rdi",
+        Register::RSI => "rsi",
+        Register::RBP => "rbp",
+        Register::R8 => "r8",
+        Register::R9 => "r9",
+        Register::R10 => "r10",
+        Register::R11 => "r11",
+        Register::R12 => "r12",
+        Register::R13 => "r13",
+        Register::R14 => "r14",
+        Register::R15 => "r15",
+        Register::RSP => "rsp",
+        Register::ZMM0 => "xmm0",
+        Register::ZMM1 => "xmm1",
+        Register::ZMM2 => "xmm2",
+        Register::ZMM3 => "xmm3",
+        Register::ZMM4 => "xmm4",
+        Register::ZMM5 => "xmm5",
+        Register::ZMM6 => "xmm6",
+        Register::ZMM7 => "xmm7",
+        Register::ZMM8 => "xmm8",
+        Register::ZMM9 => "xmm9",
+        Register::ZMM10 => "xmm10",
+        Register::ZMM11 => "xmm11",
+        Register::ZMM12 => "xmm12",
+        Register::ZMM13 => "xmm13",
+        Register::ZMM14 => "xmm14",
+        Register::ZMM15 => "xmm15",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Converts iced rflags bits to the bit positions of the rflags register
+fn rflags_bits(iced_flags: u32) -> u64 {
+    [
+        (RflagsBits::CF, 1 << 0),
+        (RflagsBits::PF, 1 << 2),
+        (RflagsBits::AF, 1 << 4),
+        (RflagsBits::ZF, 1 << 6),
+        (RflagsBits::SF, 1 << 7),
+        (RflagsBits::DF, 1 << 10),
+        (RflagsBits::OF, 1 << 11),
+    ]
+    .into_iter()
+    .filter(|(iced, _)| iced_flags & iced != 0)
+    .fold(0, |bits, (_, bit)| bits | bit)
+}
+
+/// Randomizes the initial register file used by a differential execution
+///
+/// The general purpose registers except the stack pointer, the arithmetic flags and the XMM
+/// registers are derived from the seed.
+///
+/// # Arguments
+///
+/// * `state` - The state to randomize
+/// * `seed` - Seed of the register values
+pub fn randomize_initial_state(state: &mut VmState, mut seed: u64) {
+    let registers = &mut state.standard_registers;
+    for register in super::general_purpose_registers_mut(registers) {
+        *register = super::splitmix64(&mut seed);
+    }
+
+    registers.rflags = (registers.rflags & !RFLAGS_ARITHMETIC_MASK)
+        | (super::splitmix64(&mut seed) & RFLAGS_ARITHMETIC_MASK);
+
+    for xmm in [
+        &mut registers.xmm0,
+        &mut registers.xmm1,
+        &mut registers.xmm2,
+        &mut registers.xmm3,
+        &mut registers.xmm4,
+        &mut registers.xmm5,
+        &mut registers.xmm6,
+        &mut registers.xmm7,
+        &mut registers.xmm8,
+        &mut registers.xmm9,
+        &mut registers.xmm10,
+        &mut registers.xmm11,
+        &mut registers.xmm12,
+        &mut registers.xmm13,
+        &mut registers.xmm14,
+        &mut registers.xmm15,
+    ] {
+        *xmm = M128A {
+            low: super::splitmix64(&mut seed),
+            high: super::splitmix64(&mut seed) as i64,
+        };
+    }
+}
+
+/// Registers and flags accessed by the executed instructions of a sample
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RegisterUsage {
+    /// Registers whose initial value may influence the execution
+    pub read: BTreeSet<&'static str>,
+    /// Registers that are written by the sample
+    pub written: BTreeSet<&'static str>,
+    /// Bits of the rflags register that are read by the sample
+    pub flags_read: u64,
+    /// Bits of the rflags register that are written by the sample
+    pub flags_written: u64,
+}
+
+impl RegisterUsage {
+    /// Collects the register usage of all instructions executed in a trace
+    ///
+    /// Conditional and partial register writes keep parts of the initial value, such
+    /// registers are considered read as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample` - The traced code sample
+    /// * `trace` - Trace of the sample
+    pub fn collect(&mut self, sample: &[u8], trace: &Trace) {
+        let mut factory = InstructionInfoFactory::new();
+
+        for offset in trace.hit.keys().filter_map(|ip| trace.source_offset(*ip)) {
+            let mut decoder =
+                Decoder::with_ip(64, &sample[offset..], offset as u64, DecoderOptions::NONE);
+            let instruction = decoder.decode();
+            if instruction.is_invalid() {
+                continue;
+            }
+
+            self.flags_read |= rflags_bits(instruction.rflags_read());
+            self.flags_written |= rflags_bits(instruction.rflags_modified());
+
+            for used in factory.info(&instruction).used_registers() {
+                let Some(name) = register_name(used.register()) else {
+                    continue;
+                };
+                let partial = used.register().is_gpr() && used.register().size() < 4;
+
+                match used.access() {
+                    OpAccess::Read | OpAccess::CondRead => {
+                        self.read.insert(name);
+                    }
+                    OpAccess::Write if !partial => {
+                        self.written.insert(name);
+                    }
+                    OpAccess::Write
+                    | OpAccess::CondWrite
+                    | OpAccess::ReadWrite
+                    | OpAccess::ReadCondWrite => {
+                        self.read.insert(name);
+                        self.written.insert(name);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Checks if the execution may depend on the randomized initial registers
+    pub fn is_input_dependent(&self) -> bool {
+        self.read.iter().any(|name| *name != "rsp") || self.flags_read & RFLAGS_ARITHMETIC_MASK != 0
+    }
+
+    /// Determines the registers that differ between two executions without being explained by
+    /// the differing initial states
+    ///
+    /// Registers the sample does not write must keep their initial value in both executions.
+    /// Registers the sample writes must be equal if the sample reads none of the randomized
+    /// registers.
+    ///
+    /// # Arguments
+    ///
+    /// * `initial` - Initial registers of both executions
+    /// * `result` - Final registers of both executions
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<&'static str>` - Names of the diverging registers
+    pub fn unexplained_differences(
+        &self,
+        initial: [&GuestRegisters; 2],
+        result: [&GuestRegisters; 2],
+    ) -> Vec<&'static str> {
+        let input_dependent = self.is_input_dependent();
+        let mut differences: Vec<&'static str> = Vec::new();
+
+        for (name, field) in COMPARED_REGISTERS {
+            if differences.last() == Some(&name) {
+                continue;
+            }
+
+            let written_mask = if name == "rflags" {
+                self.flags_written
+            } else if self.written.contains(name) {
+                u64::MAX
+            } else {
+                0
+            };
+
+            let value = |registers: &GuestRegisters| registers.get_field(field).unwrap_or(0);
+            let delta =
+                |index: usize| (value(result[index]) ^ value(initial[index])) & !written_mask;
+
+            let mut diverges = delta(0) != delta(1);
+            if !input_dependent {
+                diverges |= (value(result[0]) ^ value(result[1])) & written_mask != 0;
+            }
+
+            if diverges {
+                differences.push(name);
+            }
+        }
+
+        differences
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::executor::differential::RegisterUsage;
+    use hypervisor::state::GuestRegisters;
+
+    #[test]
+    fn test_unexplained_differences() {
+        let initial_a = GuestRegisters {
+            rax: 1,
+            rbx: 2,
+            ..Default::default()
+        };
+        let initial_b = GuestRegisters {
+            rax: 3,
+            rbx: 4,
+            ..Default::default()
+        };
+
+        // mov rbx, 5: rax is passed through, rbx is written independently of the inputs
+        let usage = RegisterUsage {
+            written: ["rbx"].into_iter().collect(),
+            ..Default::default()
+        };
+        let result_a = GuestRegisters {
+            rbx: 5,
+            ..initial_a.clone()
+        };
+        let result_b = GuestRegisters {
+            rbx: 5,
+            ..initial_b.clone()
+        };
+        assert!(usage
+            .unexplained_differences([&initial_a, &initial_b], [&result_a, &result_b])
+            .is_empty());
+
+        // rbx does not depend on any input, but differs
+        let result_b = GuestRegisters {
+            rbx: 6,
+            ..initial_b.clone()
+        };
+        assert_eq!(
+            usage.unexplained_differences([&initial_a, &initial_b], [&result_a, &result_b]),
+            ["rbx"]
+        );
+
+        // add rbx, rax: rbx depends on the inputs
+        let usage = RegisterUsage {
+            read: ["rax", "rbx"].into_iter().collect(),
+            written: ["rbx"].into_iter().collect(),
+            ..Default::default()
+        };
+        assert!(usage
+            .unexplained_differences([&initial_a, &initial_b], [&result_a, &result_b])
+            .is_empty());
+
+        // rcx is never written, but changed in one execution
+        let result_b = GuestRegisters { rcx: 1, ..result_b };
+        assert_eq!(
+            usage.unexplained_differences([&initial_a, &initial_b], [&result_a, &result_b]),
+            ["rcx"]
+        );
+    }
+}
@@ -8,27 +8,50 @@ use coverage::harness::coverage_harness::{
 };
 use coverage::harness::iteration_harness::IterationHarness;
 use data_types::addresses::{Address, UCInstructionAddress};
+use fuzzer_data::CoverageGranularity;
 use uefi::println;
 
+/// Number of addresses the stub pretends to hook simultaneously
+const MAX_BATCH_SIZE: usize = 0x2000;
+
 // Bochs stub for coverage collection
 pub struct CoverageCollector {
     excluded_addresses: Rc<RefCell<BTreeSet<u16>>>,
+    batch_size: usize,
 }
 
 impl CoverageCollector {
     pub fn initialize(
         excluded_addresses: Rc<RefCell<BTreeSet<u16>>>,
     ) -> Result<CoverageCollector, custom_processing_unit::Error> {
-        Ok(Self { excluded_addresses })
+        Ok(Self {
+            excluded_addresses,
+            batch_size: MAX_BATCH_SIZE,
+        })
+    }
+
+    pub fn max_batch_size(&self) -> usize {
+        MAX_BATCH_SIZE
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    pub fn set_batch_size(&mut self, batch_size: usize) {
+        self.batch_size = batch_size.clamp(1, MAX_BATCH_SIZE);
     }
 
-    pub fn get_iteration_harness(&self) -> IterationHarness {
+    pub fn get_iteration_harness(&self, granularity: CoverageGranularity) -> IterationHarness {
         let borrow = self.excluded_addresses.borrow();
         let hookable_addresses = HookableAddressIterator::construct(
             &ucode_dump::dump::ROM_cpu_000506CA,
             &ModificationEngineSettings::default(),
-            0x2000,
-            |x| !borrow.contains(&(x.address() as u16)),
+            self.batch_size,
+            |x| {
+                !borrow.contains(&(x.address() as u16))
+                    && granularity.includes_address(x.address() as u16)
+            },
         );
 
         IterationHarness::new(hookable_addresses)
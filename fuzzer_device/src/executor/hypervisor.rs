@@ -46,6 +46,9 @@ pub struct Hypervisor {
 
     vm: Vm,
     pub initial_state: VmState,
+
+    /// Restore the writable guest pages from the snapshot before every execution
+    reset_memory: bool,
 }
 
 // MEMORY LAYOUT IN PAGES
@@ -275,6 +278,12 @@ impl Hypervisor {
             }
         }
 
+        // the coverage page is guest writable as well, but written by the coverage collection
+        if let Err(err) = vm.snapshot_guest_memory(&[STACK_PAGE_INDEX << BASE_PAGE_SHIFT]) {
+            error!("Failed to snapshot guest memory: {:?}", err);
+            return Err(err);
+        }
+
         Ok(Self {
             memory_code_page: Pin::from(code_page),
             memory_code_entry_page: Pin::from(code_entry_page),
@@ -285,6 +294,7 @@ impl Hypervisor {
             memory_page_table_3: Pin::from(page_table_3_page),
             vm,
             initial_state: state,
+            reset_memory: false,
         })
     }
 
@@ -304,12 +314,44 @@ impl Hypervisor {
         // is a full vm reset required? -> YES, interrupt state or smth is transferred across runs
         self.vm.initialize().expect("it also worked the first time");
         self.vm.vt.load_state(&self.initial_state);
-        self.vm.vt.set_preemption_timer(1e6 as u64);
 
-        self.memory_stack_page.zero();
+        if self.reset_memory {
+            self.reset_guest_memory();
+        } else {
+            self.memory_stack_page.zero();
+        }
         // unsafe { (*(0x1000 as *const Page as *mut Page)).zero(); } // todo!
     }
 
+    /// Restores the writable guest pages from the snapshot taken after initialization
+    ///
+    /// Only modified pages are copied. With `__debug_verify_memory_reset` the guest memory
+    /// is compared against the snapshot afterward.
+    pub fn reset_guest_memory(&mut self) {
+        self.vm.reset_guest_memory();
+
+        #[cfg(feature = "__debug_verify_memory_reset")]
+        assert!(
+            self.vm.guest_memory_matches_snapshot(),
+            "guest memory differs from the snapshot after reset"
+        );
+    }
+
+    /// Returns if the guest memory is reset before every execution
+    pub fn memory_reset(&self) -> bool {
+        self.reset_memory
+    }
+
+    /// Sets if the guest memory is reset before every execution, see [`Self::reset_guest_memory`]
+    pub fn set_memory_reset(&mut self, enabled: bool) {
+        self.reset_memory = enabled;
+    }
+
+    /// Bounds a single VM entry to `timeout_in_tsc` TSC ticks, applied on the next preparation
+    pub fn set_sample_timeout(&mut self, timeout_in_tsc: u64) {
+        self.vm.set_preemption_timeout(timeout_in_tsc);
+    }
+
     pub fn run_vm(&mut self, coverage_collection: bool) -> VmExitReason {
         self.switch_coverage_mode(coverage_collection);
         self.vm.vt.run()
@@ -588,7 +630,7 @@ impl Hypervisor {
         self.vm.vt.save_state(state);
     }
 
-    pub fn selfcheck(&mut self) -> bool {
+    fn selfcheck_code() -> Vec<u8> {
         let mut assembler = CodeAssembler::new(64).unwrap();
 
         let mut start_sequence = assembler.create_label();
@@ -608,9 +650,13 @@ impl Hypervisor {
         assembler.set_label(&mut label_loop).unwrap();
         assembler.jmp(label_loop).unwrap();
 
-        let code = assembler
+        assembler
             .assemble(0)
-            .expect("failed to assemble selfcheck code");
+            .expect("failed to assemble selfcheck code")
+    }
+
+    pub fn selfcheck(&mut self) -> bool {
+        let code = Self::selfcheck_code();
 
         crate::disassemble_code(&code);
 
@@ -649,6 +695,43 @@ impl Hypervisor {
         }
     }
 
+    /// Checks that resetting the guest memory does not change the execution result
+    ///
+    /// The selfcheck code is executed without and with memory reset, both executions must
+    /// exit equally with equal states and the memory must match the snapshot after reset.
+    pub fn selfcheck_memory_reset(&mut self) -> bool {
+        let reset_memory = self.reset_memory;
+        self.load_code_blob(&Self::selfcheck_code());
+
+        let mut results = Vec::new();
+        for enabled in [false, true] {
+            self.reset_memory = enabled;
+            self.prepare_vm_state();
+
+            if enabled && !self.vm.guest_memory_matches_snapshot() {
+                error!("Selfcheck: Guest memory differs from the snapshot after reset");
+                self.reset_memory = reset_memory;
+                return false;
+            }
+
+            let exit = self.vm.vt.run();
+            let mut state = self.initial_state.clone();
+            self.vm.vt.save_state(&mut state);
+            results.push((exit, state));
+        }
+        self.reset_memory = reset_memory;
+
+        if results[0] != results[1] {
+            error!(
+                "Selfcheck: Memory reset changed the execution result: {:x?} -> {:x?}",
+                results[0], results[1]
+            );
+            return false;
+        }
+
+        true
+    }
+
     fn generate_code_entry(code_entry: u64, current_rip: u64) -> Vec<u8> {
         let mut assembler = CodeAssembler::new(64).unwrap();
 
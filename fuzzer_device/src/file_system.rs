@@ -0,0 +1,95 @@
+//! File System Module
+//!
+//! Helpers for the files on the volume the fuzzer was loaded from.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use uefi::proto::media::file::{Directory, File, FileHandle, FileInfo, FileMode, RegularFile};
+use uefi::CStr16;
+use uefi_raw::protocol::file_system::FileAttribute;
+use uefi_raw::Status;
+
+/// Opens a file for reading and writing if it exists
+pub fn open_existing(
+    root_dir: &mut Directory,
+    filename: &CStr16,
+) -> uefi::Result<Option<FileHandle>> {
+    match root_dir.open(filename, FileMode::ReadWrite, FileAttribute::empty()) {
+        Ok(file) => Ok(Some(file)),
+        Err(e) if e.status() == Status::NOT_FOUND => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Converts a file handle into a regular file
+///
+/// # Returns
+///
+/// * `uefi::Result<RegularFile>` - The regular file, `UNSUPPORTED` if the handle is a directory
+pub fn into_regular_file(file: FileHandle) -> uefi::Result<RegularFile> {
+    file.into_regular_file()
+        .ok_or_else(|| uefi::Error::from(uefi::Status::UNSUPPORTED))
+}
+
+/// Reads the content of a file
+///
+/// # Returns
+///
+/// * `uefi::Result<Option<Vec<u8>>>` - Content of the file, `None` if it does not exist
+pub fn read_file(root_dir: &mut Directory, filename: &CStr16) -> uefi::Result<Option<Vec<u8>>> {
+    let file = match root_dir.open(filename, FileMode::Read, FileAttribute::empty()) {
+        Ok(file) => file,
+        Err(e) if e.status() == Status::NOT_FOUND => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut regular_file = into_regular_file(file)?;
+
+    let mut buffer = [0u8; 4096];
+    let mut data = Vec::new();
+
+    loop {
+        let read = regular_file.read(&mut buffer)?;
+
+        if read == 0 {
+            break;
+        }
+
+        data.extend_from_slice(&buffer[..read]);
+    }
+
+    Ok(Some(data))
+}
+
+/// Deletes a file if it exists
+pub fn delete_existing(root_dir: &mut Directory, filename: &CStr16) -> uefi::Result<()> {
+    if let Some(file) = open_existing(root_dir, filename)? {
+        file.delete()?;
+    }
+    Ok(())
+}
+
+/// Renames a file within its directory
+///
+/// An existing file of the new name must be deleted beforehand.
+///
+/// # Arguments
+///
+/// * `file` - The file to rename
+/// * `filename` - New name of the file
+pub fn rename(file: &mut RegularFile, filename: &CStr16) -> uefi::Result<()> {
+    let info = file.get_boxed_info::<FileInfo>()?;
+    // space for the new name and the alignment of the info structure
+    let mut storage = vec![0u8; core::mem::size_of_val(&*info) + filename.num_bytes() + 8];
+    let renamed = FileInfo::new(
+        &mut storage,
+        info.file_size(),
+        info.physical_size(),
+        *info.create_time(),
+        *info.last_access_time(),
+        *info.modification_time(),
+        info.attribute(),
+        filename,
+    )
+    .map_err(|_| uefi::Error::from(uefi::Status::BUFFER_TOO_SMALL))?;
+    file.set_info(renamed)
+}
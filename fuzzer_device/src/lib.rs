@@ -9,25 +9,31 @@
 #![feature(stmt_expr_attributes)]
 #![feature(proc_macro_hygiene)]
 
+pub mod blacklist;
 pub mod cmos;
 pub mod controller_connection;
 pub mod executor;
+mod file_system;
 pub mod heuristic;
 pub mod mutation_engine;
 pub mod perf_monitor;
+#[cfg(feature = "speculation")]
+pub mod speculation;
 
 extern crate alloc;
 
 use crate::cmos::CMOS;
 use alloc::collections::BTreeMap;
-use alloc::string::String;
 use alloc::vec::Vec;
 use core::fmt::Debug;
-use iced_x86::{Decoder, DecoderOptions, Formatter, Instruction, NasmFormatter};
+use fuzzer_data::genetic_pool::SampleProvenance;
+use fuzzer_data::CrashPhase;
 
 use hypervisor::state::VmState;
 #[cfg(feature = "uefi")]
-use uefi::{print, println};
+use uefi::print;
+
+pub use fuzzer_data::decoder::disassemble_code_to_string;
 
 /// Disassembles and prints x86-64 code with instruction addresses and bytes
 ///
@@ -35,45 +41,28 @@ use uefi::{print, println};
 ///
 /// * `code` - A slice of bytes containing the x86-64 machine code to disassemble
 pub fn disassemble_code(code: &[u8]) {
-    let mut decoder = Decoder::with_ip(64, code, 0, DecoderOptions::NONE);
-    let mut formatter = NasmFormatter::new();
-
-    formatter.options_mut().set_digit_separator("`");
-    formatter.options_mut().set_first_operand_char_index(10);
-    formatter.options_mut().set_show_useless_prefixes(true);
-
-    let mut output = String::new();
-    let mut instruction = Instruction::default();
-
-    while decoder.can_decode() {
-        decoder.decode_out(&mut instruction);
-        output.clear();
-        formatter.format(&instruction, &mut output);
-
-        // Eg. "00007FFAC46ACDB2 488DAC2400FFFFFF     lea       rbp,[rsp-100h]"
-        print!("{:016X} ", instruction.ip());
-        let start_index = (instruction.ip() - 0) as usize;
-        let instr_bytes = &code[start_index..start_index + instruction.len()];
-        for b in instr_bytes.iter() {
-            print!("{:02X}", b);
-        }
-        if instr_bytes.len() < 10 {
-            for _ in 0..10 - instr_bytes.len() {
-                print!("  ");
-            }
-        }
-        println!(" {}", output);
-    }
+    print!("{}", disassemble_code_to_string(code, None));
 }
 
+/// Version of the memory layout of [`PersistentApplicationData`]
+///
+/// Must be increased whenever the layout changes, stored data of another layout is erased.
+pub const PERSISTENT_DATA_LAYOUT_VERSION: u16 = 3;
+
 /// Persistent data structure for storing application state across executions
 ///
 /// This structure is stored in CMOS memory and contains version information
-/// and application state that persists between program runs.
+/// and application state that persists between program runs. It must fit into a single
+/// CMOS slot, see [`cmos::CMOS_USER_DATA_SIZE`].
 #[repr(C)]
 pub struct PersistentApplicationData {
-    /// Version identifier for the application
-    version: u32,
+    /// Fingerprint of the sample executed last, see
+    /// [`fuzzer_data::genetic_pool::SampleProvenance::fingerprint`]
+    pub last_sample_hash: u64,
+    /// Version identifier of the application and the data layout, see [`Self::stored_version`]
+    version: u16,
+    /// Number of runs that ended while executing a sample, only ever increased
+    pub crash_count: u16,
     /// Current state of the application
     pub state: PersistentApplicationState,
 }
@@ -95,16 +84,41 @@ impl PersistentApplicationData {
             bytes.next().unwrap(),
         ])
     }
-    /// Checks if the stored version matches the current application version
+
+    /// Returns the version identifier stored in CMOS
+    ///
+    /// The application version is folded to 16 bits and combined with the layout version.
+    fn stored_version() -> u16 {
+        let version = Self::this_app_version();
+        ((version >> 16) as u16 ^ version as u16).wrapping_add(PERSISTENT_DATA_LAYOUT_VERSION)
+    }
+
+    /// Checks if the stored version and layout match the current application version
     pub fn is_same_program_version(&self) -> bool {
-        self.version == Self::this_app_version()
+        self.version == Self::stored_version()
+    }
+
+    /// Marks the beginning of the execution of a sample
+    ///
+    /// A crash before the state is [`PersistentApplicationState::Idle`] again is attributed to
+    /// the sample.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample` - The sample to execute
+    /// * `state` - Phase of the execution
+    pub fn begin_sample(&mut self, sample: &[u8], state: PersistentApplicationState) {
+        self.last_sample_hash = SampleProvenance::fingerprint(sample);
+        self.state = state;
     }
 }
 
 impl Default for PersistentApplicationData {
     fn default() -> Self {
         Self {
-            version: Self::this_app_version(),
+            last_sample_hash: 0,
+            version: Self::stored_version(),
+            crash_count: 0,
             state: PersistentApplicationState::Idle,
         }
     }
@@ -119,6 +133,31 @@ pub enum PersistentApplicationState {
     Idle = 0,
     /// Application is collecting coverage data with specified coverage ID
     CollectingCoverage(u16) = 1,
+    /// Application is collecting coverage data of several addresses at once, starting with the
+    /// specified coverage ID; a crash cannot be attributed to a single address
+    CollectingCoverageBatch(u16) = 2,
+    /// Application is executing a sample without coverage hooks
+    ExecutingSample = 3,
+}
+
+impl PersistentApplicationState {
+    /// Gets the execution phase a crash in this state happened in
+    ///
+    /// # Returns
+    ///
+    /// * `Option<CrashPhase>` - The phase, `None` if no sample is executed in this state
+    pub fn crash_phase(&self) -> Option<CrashPhase> {
+        match self {
+            PersistentApplicationState::Idle => None,
+            PersistentApplicationState::CollectingCoverage(address) => {
+                Some(CrashPhase::CoverageCollection(*address))
+            }
+            PersistentApplicationState::CollectingCoverageBatch(address) => {
+                Some(CrashPhase::CoverageCollectionBatch(*address))
+            }
+            PersistentApplicationState::ExecutingSample => Some(CrashPhase::Execution),
+        }
+    }
 }
 
 /// Represents a trace of executed instructions
@@ -131,6 +170,8 @@ pub struct Trace {
     pub sequence: Vec<u64>,
     /// Map of instruction pointers to their execution counts
     pub hit: BTreeMap<u64, u64>,
+    /// Length of the traced sample in bytes
+    pub sample_length: usize,
 }
 
 impl Debug for Trace {
@@ -144,35 +185,52 @@ impl Trace {
     ///
     /// Normalizes all instruction pointers to a 1GB address space and
     /// counts their occurrences.
-    pub fn new(mut data: Vec<u64>) -> Self {
-        data.iter_mut().for_each(|x| *x = *x % (1u64 << 30)); // map to 1GB page
-
-        let mut hit = BTreeMap::new();
-        for &ip in data.iter() {
-            *hit.entry(ip).or_insert(0) += 1;
+    pub fn new(data: Vec<u64>) -> Self {
+        let mut trace = Self::default();
+        for ip in data {
+            trace.push(ip);
         }
+        trace
+    }
 
-        Self {
-            sequence: data,
-            hit,
-        }
+    /// Normalizes an instruction pointer to the 1GB address space
+    pub const fn normalize(ip: u64) -> u64 {
+        ip % (1u64 << 30) // map to 1GB page
     }
+
     /// Checks if an instruction pointer was executed
     pub fn was_executed(&self, ip: u64) -> bool {
         self.hit.contains_key(&ip)
     }
 
+    /// Maps a normalized instruction pointer to the byte offset within the traced sample
+    ///
+    /// Returns `None` if the instruction pointer lies outside the sample.
+    pub fn source_offset(&self, ip: u64) -> Option<usize> {
+        if ip < self.sample_length as u64 {
+            Some(ip as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Sets the length of the traced sample, used to map instruction pointers back to the sample
+    pub fn set_sample_length(&mut self, sample_length: usize) {
+        self.sample_length = sample_length;
+    }
+
     /// Clears the trace data
     pub fn clear(&mut self) {
         self.sequence.clear();
         self.hit.clear();
+        self.sample_length = 0;
     }
 
     /// Adds an instruction pointer value to the trace
     ///
     /// Normalizes the instruction pointer to a 1GB address space.
     pub fn push(&mut self, ip: u64) {
-        let ip = ip % (1u64 << 30);
+        let ip = Self::normalize(ip);
         self.sequence.push(ip);
         *self.hit.entry(ip).or_insert(0) += 1;
     }
@@ -260,3 +318,20 @@ impl StateTrace<VmState> {
         self.state.get(index)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::Trace;
+    use alloc::vec;
+
+    #[test]
+    fn test_trace_source_offset() {
+        let mut trace = Trace::new(vec![0x4000_0000, 0x4000_0003, 0x4000_0003, 0x4000_1000]);
+        trace.set_sample_length(8);
+
+        assert_eq!(trace.sequence[1], 0x3);
+        assert_eq!(trace.source_offset(trace.sequence[1]), Some(3));
+        assert_eq!(trace.source_offset(trace.sequence[3]), None);
+        assert_eq!(trace.hit.get(&0x3), Some(&2));
+    }
+}
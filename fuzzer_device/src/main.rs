@@ -16,12 +16,18 @@ use core::cell::RefCell;
 use core::ops::DerefMut;
 use coverage::interface_definition::{CoverageCount, COM_INTERFACE_DESCRIPTION};
 use data_types::addresses::{Address, UCInstructionAddress};
-use fuzzer_data::decoder::InstructionDecoder;
-use fuzzer_data::genetic_pool::{GeneticPool, GeneticPoolSettings, GeneticSampleRating};
+use fuzzer_data::decoder::{InstructionCategory, InstructionDecoder};
+use fuzzer_data::genetic_pool::{
+    GeneticPool, GeneticPoolSettings, GeneticSampleRating, MutationWeights, PoolSnapshot,
+};
+use fuzzer_data::instruction_corpus::InstructionCorpus;
+#[cfg(feature = "speculation")]
+use fuzzer_data::SpeculationResult;
 use fuzzer_data::{
-    genetic_pool, MemoryAccess, OtaC2D, OtaC2DTransport, OtaD2CTransport, ReportExecutionProblem,
-    TraceResult,
+    genetic_pool, ExecutionConfig, MemoryAccess, MsrAccessError, OtaC2D, OtaC2DTransport,
+    OtaD2CTransport, ReportExecutionProblem, TraceResult, HEARTBEAT_INTERVAL_MS, WRITABLE_MSRS,
 };
+use fuzzer_device::blacklist::storage::ExcludedAddresses;
 use fuzzer_device::cmos::CMOS;
 use fuzzer_device::controller_connection::{
     ConnectionError, ConnectionSettings, ControllerConnection,
@@ -29,11 +35,16 @@ use fuzzer_device::controller_connection::{
 use fuzzer_device::executor::{
     ExecutionEvent, ExecutionResult, ExecutionSampleResult, SampleExecutor,
 };
+use fuzzer_device::mutation_engine::PoolMutator;
 use fuzzer_device::perf_monitor::PerfMonitor;
+#[cfg(feature = "speculation")]
+use fuzzer_device::speculation;
 use fuzzer_device::{
-    disassemble_code, PersistentApplicationData, PersistentApplicationState, StateTrace,
+    disassemble_code, disassemble_code_to_string, PersistentApplicationData,
+    PersistentApplicationState, StateTrace, Trace,
 };
-use hypervisor::state::VmState;
+use hypervisor::state::{DifferenceFormatter, StateDifference, VmState};
+use hypervisor::x86_instructions::{rdmsr_safe, wrmsr_safe};
 use itertools::Itertools;
 use log::{debug, error, info, trace, warn, Level};
 use performance_timing::measurements::MeasureValues;
@@ -42,9 +53,9 @@ use performance_timing::track_time;
 use performance_timing::TimeMeasurement;
 use rand_core::{RngCore, SeedableRng};
 use uefi::boot::ScopedProtocol;
+use uefi::mem::memory_map::{MemoryMap, MemoryType};
 use uefi::proto::loaded_image::LoadedImage;
-use uefi::proto::media::file::{File, FileAttribute, FileInfo, FileMode};
-use uefi::{entry, println, CString16, Error, Status};
+use uefi::{entry, print, println, CString16, Status};
 use uefi_raw::table::runtime::ResetType;
 use uefi_raw::Ipv4Address;
 use uefi_udp4::Ipv4AddressExt;
@@ -56,6 +67,13 @@ const P0_FREQ: f64 = 1.09e9;
 #[cfg(feature = "device_bochs")]
 const P0_FREQ: f64 = 1.0e9;
 
+/// Number of decode summaries cached while rating samples
+const DECODE_CACHE_SIZE: usize = 1024;
+/// Number of rotated performance measurement files
+const PERF_FILE_COUNT: usize = 4;
+/// Number of performance measurement snapshots kept in memory
+const PERF_HISTORY_LENGTH: usize = 32;
+
 fn get_program_args() -> Vec<String> {
     let loaded_image_proto: ScopedProtocol<LoadedImage> =
         match uefi::boot::open_protocol_exclusive(uefi::boot::image_handle()) {
@@ -174,7 +192,8 @@ unsafe fn main() -> Status {
 
     uefi::boot::stall(1000000);
 
-    let perf_monitor = match PerfMonitor::new("perf.json") {
+    let mut perf_monitor = match PerfMonitor::new("perf.json", PERF_FILE_COUNT, PERF_HISTORY_LENGTH)
+    {
         Ok(x) => x,
         Err(e) => {
             error!("Failed to create perf monitor: {:?}", e);
@@ -184,11 +203,14 @@ unsafe fn main() -> Status {
 
     let disable_nmi = true;
     let mut cmos = CMOS::<PersistentApplicationData>::read_from_ram(disable_nmi);
+    // a broken blacklist must not keep the device from booting, it is reported once connected
+    let mut blacklist_load_error = None;
     let mut excluded_addresses = match ExcludedAddresses::load_file() {
         Ok(excluded_addresses) => excluded_addresses,
         Err(e) => {
-            error!("Failed to load excluded addresses: {:?}", e);
-            return Status::ABORTED;
+            error!("Failed to load excluded addresses, starting empty: {:?}", e);
+            blacklist_load_error = Some(e);
+            ExcludedAddresses::default()
         }
     };
     // Read application data from last run from cmos
@@ -203,6 +225,19 @@ unsafe fn main() -> Status {
         trace!("Program version changed. Erasing CMOS data.");
         *cmos_data.deref_mut() = PersistentApplicationData::default();
     }
+    // the previous run ended while executing a sample
+    let crash_report = cmos_data.state.crash_phase().map(|phase| {
+        cmos_data.crash_count = cmos_data.crash_count.saturating_add(1);
+        warn!(
+            "Last run crashed while executing sample {:016x} ({:?}), {} crashes so far",
+            cmos_data.last_sample_hash, phase, cmos_data.crash_count
+        );
+        OtaD2CTransport::CrashReport {
+            crash_count: cmos_data.crash_count.into(),
+            sample_fingerprint: cmos_data.last_sample_hash,
+            phase,
+        }
+    });
     let mut excluded_last_run = None;
     if let PersistentApplicationState::CollectingCoverage(address) = cmos_data.state {
         // failed to collect coverage from that address
@@ -218,6 +253,19 @@ unsafe fn main() -> Status {
         }
         cmos_data.state = PersistentApplicationState::Idle;
     }
+    let mut single_hook_coverage = false;
+    if let PersistentApplicationState::CollectingCoverageBatch(address) = cmos_data.state {
+        // the culprit is one of several hooked addresses, isolate it with the next crash
+        single_hook_coverage = true;
+        info!(
+            "Last run failed to collect coverage from a batch starting at address: {:x?}",
+            address
+        );
+        cmos_data.state = PersistentApplicationState::Idle;
+    }
+    if let PersistentApplicationState::ExecutingSample = cmos_data.state {
+        cmos_data.state = PersistentApplicationState::Idle;
+    }
     let excluded_last_run = excluded_last_run;
     drop(cmos_data);
 
@@ -249,6 +297,11 @@ unsafe fn main() -> Status {
                 return Status::ABORTED;
             }
         };
+    if single_hook_coverage {
+        warn!("Collecting coverage with a single hook per execution");
+        executor.set_coverage_batch_size(1);
+    }
+    info!("Coverage batch size: {}", executor.coverage_batch_size());
     info!("Doing hypervisor selfcheck");
     if !executor.selfcheck() {
         println!("Executor selfcheck failed");
@@ -265,9 +318,23 @@ unsafe fn main() -> Status {
             &mut executor,
             &mut cmos,
             0,
-            GeneticPoolSettings::default(),
+            GeneticPoolSettings {
+                rating_mode: if cfg!(feature = "genetic_pareto") {
+                    genetic_pool::RatingMode::Pareto
+                } else {
+                    genetic_pool::RatingMode::Lexicographic
+                },
+                mutation_weights: MutationWeights {
+                    havoc: 2,
+                    dictionary: 1,
+                    splice: 1,
+                },
+                ..Default::default()
+            },
             10,
             None,
+            None,
+            &mut PoolMutator::default(),
         );
     }
 
@@ -280,12 +347,33 @@ unsafe fn main() -> Status {
         let mut state_trace_scratchpad_serialized = StateTrace::default();
         let mut state_trace_with_memory: StateTrace<(VmState, Vec<MemoryAccess>)> =
             StateTrace::default();
-        let mut decoder = InstructionDecoder::new();
+        let mut decoder = InstructionDecoder::new().with_cache(DECODE_CACHE_SIZE);
+
+        let mut samples_executed: u64 = 0;
+        let mut pool_snapshot: Option<PoolSnapshot> = None;
+        let mut pool_mutator = PoolMutator::default();
+        let mut execution_config = ExecutionConfig::default();
 
         if let Err(err) = udp.send(OtaD2CTransport::ResetSession) {
             error!("Failed to send reset-session: {:?}", err);
         }
 
+        if let Some(crash_report) = crash_report {
+            if let Err(err) = udp.send(crash_report) {
+                error!("Failed to send the crash report: {:?}", err);
+            }
+        }
+
+        if let Some(err) = blacklist_load_error {
+            let _ = udp.log_reliable(
+                Level::Error,
+                format!(
+                    "Failed to load excluded addresses, started empty: {:?}",
+                    err
+                ),
+            );
+        }
+
         trace!("Waiting for command...");
         #[cfg_attr(
             feature = "__debug_performance_trace",
@@ -301,8 +389,12 @@ unsafe fn main() -> Status {
                 );
             }
 
-            let packet = match udp.receive(None) {
-                Ok(None) => continue,
+            if let Err(err) = udp.send_heartbeat_if_due(samples_executed, free_memory()) {
+                error!("Failed to send heartbeat: {:?}", err);
+            }
+
+            let packet = match udp.receive(Some(HEARTBEAT_INTERVAL_MS)) {
+                Ok(None) | Err(ConnectionError::ReceiveTimeout) => continue,
                 Ok(Some(packet)) => packet,
                 Err(err) => {
                     error!("Failed to receive packet: {:?}", err);
@@ -326,12 +418,14 @@ unsafe fn main() -> Status {
                     let processor_version = cpuid!(leaf, node);
                     let capabilities = OtaD2CTransport::Capabilities {
                         coverage_collection: executor.supports_coverage_collection(),
+                        coverage_batch_size: executor.coverage_batch_size() as u16,
                         manufacturer: vendor_str,
                         pmc_number: PerformanceCounter::number_of_counters(),
                         processor_version_eax: processor_version.eax,
                         processor_version_ebx: processor_version.ebx,
                         processor_version_ecx: processor_version.ecx,
                         processor_version_edx: processor_version.edx,
+                        ucode_revision: custom_processing_unit::read_ucode_revision(),
                     };
                     if let Err(err) = udp.send(capabilities) {
                         error!("Failed to send capabilities: {:?}", err);
@@ -359,11 +453,7 @@ unsafe fn main() -> Status {
                     excluded_addresses.borrow_mut().clear();
                     executor.update_excluded_addresses();
                     for _ in 0..50 {
-                        match (ExcludedAddresses {
-                            addresses: BTreeSet::new(),
-                        })
-                        .save_file()
-                        {
+                        match ExcludedAddresses::default().save_file() {
                             Ok(_) => break,
                             Err(err) => {
                                 error!("Failed to reset excluded-addresses: {:?}", err);
@@ -388,12 +478,125 @@ unsafe fn main() -> Status {
                     }
                 }
                 OtaC2DTransport::Reboot => {
+                    // hand the population back, it is lost during the reboot
+                    if let Some(snapshot) = pool_snapshot.take() {
+                        if let Err(err) = udp.send(OtaD2CTransport::PoolSnapshot(snapshot)) {
+                            error!("Failed to send the pool snapshot: {:?}", err);
+                        }
+                    }
                     #[cfg(feature = "device_bochs")]
                     uefi::runtime::reset(ResetType::SHUTDOWN, Status::SUCCESS, None);
                     #[cfg(not(feature = "device_bochs"))]
                     break;
                 }
                 OtaC2DTransport::AreYouThere => {}
+                OtaC2DTransport::RestorePool(snapshot) => {
+                    trace!(
+                        "Restored pool snapshot of generation {} with {} samples",
+                        snapshot.generation,
+                        snapshot.population.len()
+                    );
+                    pool_snapshot = Some(snapshot);
+                }
+                OtaC2DTransport::ExtendDictionary { instructions } => {
+                    pool_mutator.extend_dictionary(InstructionCorpus::new(instructions));
+                    trace!(
+                        "Dictionary contains {} instructions",
+                        pool_mutator.dictionary().instructions.len()
+                    );
+                }
+                OtaC2DTransport::Hello { .. } => {
+                    // answered by the connection
+                }
+                OtaC2DTransport::ReadMsr { msr } => {
+                    if let Err(err) = udp.send(access_msr(msr, None)) {
+                        error!("Failed to send msr value: {:?}", err);
+                        let _ = udp.log_reliable(
+                            Level::Error,
+                            format!("Failed to send msr value: {:?}", err),
+                        );
+                    }
+                }
+                OtaC2DTransport::WriteMsr { msr, value } => {
+                    let reply = access_msr(msr, Some(value));
+                    if let OtaD2CTransport::MsrValue { .. } = reply {
+                        warn!("Wrote {:#x} to msr {:#x}", value, msr);
+                    }
+                    if let Err(err) = udp.send(reply) {
+                        error!("Failed to send msr value: {:?}", err);
+                        let _ = udp.log_reliable(
+                            Level::Error,
+                            format!("Failed to send msr value: {:?}", err),
+                        );
+                    }
+                }
+                OtaC2DTransport::SetExecutionPolicy {
+                    coverage_granularity,
+                } => {
+                    executor.set_coverage_granularity(coverage_granularity);
+                }
+                OtaC2DTransport::SetExecutionConfig {
+                    max_vm_iterations,
+                    state_trace_len,
+                    initial_reg_seed,
+                    coverage_batch,
+                    sample_timeout_tsc,
+                    differential_execution,
+                    reset_guest_memory,
+                } => {
+                    execution_config = ExecutionConfig {
+                        max_vm_iterations,
+                        state_trace_len,
+                        initial_reg_seed,
+                        coverage_batch,
+                        sample_timeout_tsc,
+                        differential_execution,
+                        reset_guest_memory,
+                    }
+                    .clamped();
+                    executor.set_execution_config(&execution_config);
+
+                    if let Err(err) = udp.send(OtaD2CTransport::ExecutionConfig(execution_config)) {
+                        error!("Failed to send execution config: {:?}", err);
+                        let _ = udp.log_reliable(
+                            Level::Error,
+                            format!("Failed to send execution config: {:?}", err),
+                        );
+                    }
+                }
+                OtaC2DTransport::Ping {
+                    nonce,
+                    sent_timestamp,
+                } => {
+                    let pong = OtaD2CTransport::Pong {
+                        nonce,
+                        sent_timestamp,
+                        device_cycles: unsafe { x86::time::rdtsc() },
+                    };
+                    if let Err(err) = udp.send(pong) {
+                        error!("Failed to send pong: {:?}", err);
+                        let _ = udp
+                            .log_reliable(Level::Error, format!("Failed to send pong: {:?}", err));
+                    }
+                }
+                OtaC2DTransport::GetLinkStats => {
+                    let stats = OtaD2CTransport::LinkStats(udp.link_stats());
+                    if let Err(err) = udp.send(stats) {
+                        error!("Failed to send link stats: {:?}", err);
+                    }
+                }
+                OtaC2DTransport::ConfirmCoverage { sample, address } => {
+                    let hit = executor.confirm_coverage(&sample, address, &mut cmos);
+                    if let Err(err) =
+                        udp.send(OtaD2CTransport::CoverageConfirmation { address, hit })
+                    {
+                        error!("Failed to send coverage confirmation: {:?}", err);
+                        let _ = udp.log_reliable(
+                            Level::Error,
+                            format!("Failed to send coverage confirmation: {:?}", err),
+                        );
+                    }
+                }
                 OtaC2DTransport::GiveMeYourBlacklistedAddresses =>
                 #[cfg_attr(
                     feature = "__debug_performance_trace",
@@ -414,40 +617,31 @@ unsafe fn main() -> Status {
                         }
                     }
                 }
-                OtaC2DTransport::ReportPerformanceTiming => {
-                    let measurements = perf_monitor.measurement_data.accumulate();
-                    for chunk in &measurements.into_iter().chunks(5) {
-                        let data: BTreeMap<String, MeasureValues<f64>> = chunk
+                OtaC2DTransport::ReportPerformanceTiming { delta } => {
+                    let measurements = if delta {
+                        perf_monitor.update_values_from_monitor();
+                        perf_monitor.record_snapshot();
+                        perf_monitor.latest_delta().unwrap_or_default()
+                    } else {
+                        perf_monitor.measurement_data.accumulate()
+                    };
+                    let timing = OtaD2CTransport::PerformanceTiming {
+                        measurements: measurements
+                            .into_iter()
                             .map(|(k, v)| (k, MeasureValues::<f64>::from(&v)))
-                            .collect::<BTreeMap<String, MeasureValues<f64>>>();
-                        let measurements = OtaD2CTransport::PerformanceTiming {
-                            measurements: data.clone(),
-                        };
-                        if let Err(err) = udp.send(measurements.clone()) {
-                            if matches!(err, ConnectionError::PacketTooLarge) {
-                                for m in data.into_iter() {
-                                    if let Err(err) = udp.send(OtaD2CTransport::PerformanceTiming {
-                                        measurements: vec![m].into_iter().collect(),
-                                    }) {
-                                        error!("Failed to send performance timing: {:?}", err);
-                                        let _ = udp.log_reliable(
-                                            Level::Error,
-                                            format!("Failed to send performance timing: {:?}", err),
-                                        );
-                                    }
-                                }
-                            } else {
-                                error!("Failed to send performance timing: {:?}", err);
-                                let _ = udp.log_reliable(
-                                    Level::Error,
-                                    format!("Failed to send performance timing: {:?}", err),
-                                );
-                            }
-                        }
+                            .collect::<BTreeMap<String, MeasureValues<f64>>>(),
+                    };
+                    if let Err(err) = udp.send(timing) {
+                        error!("Failed to send performance timing: {:?}", err);
+                        let _ = udp.log_reliable(
+                            Level::Error,
+                            format!("Failed to send performance timing: {:?}", err),
+                        );
                     }
                 }
                 OtaC2DTransport::SetRandomSeed { seed } => {
                     random = random_source(seed);
+                    executor.set_differential_seed(seed);
                 }
                 OtaC2DTransport::ExecuteSample { code, coverage } =>
                 #[cfg_attr(
@@ -456,6 +650,7 @@ unsafe fn main() -> Status {
                 )]
                 {
                     println!("Executing sample");
+                    samples_executed += 1;
                     let _ = udp.log_unreliable(Level::Trace, "Executing sample");
 
                     let ExecutionSampleResult { serialized_sample } = executor.execute_sample(
@@ -471,34 +666,15 @@ unsafe fn main() -> Status {
                         .events
                         .iter()
                         .cloned()
-                        .filter_map(Option::<ReportExecutionProblem>::from);
-                    for event in (&events
-                        .into_iter()
-                        .chunks(ReportExecutionProblem::MAX_PER_PACKET))
-                        .into_iter()
-                        .map(|chunk| chunk.collect_vec())
-                    {
-                        if let Err(err) = udp.send(OtaD2CTransport::ExecutionEvents(event.clone()))
-                        {
+                        .filter_map(Option::<ReportExecutionProblem>::from)
+                        .collect_vec();
+                    if !events.is_empty() {
+                        if let Err(err) = udp.send(OtaD2CTransport::ExecutionEvents(events)) {
                             error!("Failed to send event: {:?}", err);
                             let _ = udp.log_reliable(
                                 Level::Error,
                                 format!("Failed to send event: {:?}", err),
                             );
-
-                            if matches!(err, ConnectionError::PacketTooLarge) {
-                                for event in event {
-                                    if let Err(err) =
-                                        udp.send(OtaD2CTransport::ExecutionEvents(vec![event]))
-                                    {
-                                        error!("Failed to send event: {:?}", err);
-                                        let _ = udp.log_reliable(
-                                            Level::Error,
-                                            format!("Failed to send event: {:?}", err),
-                                        );
-                                    }
-                                }
-                            }
                         }
                     }
                     for event in &execution_result.events {
@@ -513,12 +689,12 @@ unsafe fn main() -> Status {
                             executor.state_trace_sample(
                                 &code,
                                 &mut state_trace_scratchpad_normal,
-                                300,
+                                execution_config.state_trace_len as usize,
                             );
                             executor.state_trace_sample(
                                 serialized_sample.as_ref().unwrap(),
                                 &mut state_trace_scratchpad_serialized,
-                                300,
+                                execution_config.state_trace_len as usize,
                             );
 
                             let difference = state_trace_scratchpad_normal
@@ -586,7 +762,7 @@ unsafe fn main() -> Status {
                         .iter()
                         .map(|(k, v)| (k.address() as u16, *v))
                         .collect_vec()
-                        .chunks(200)
+                        .chunks(execution_config.coverage_batch as usize)
                     {
                         if let Err(err) = udp.send(OtaD2CTransport::Coverage {
                             coverage: cov.to_vec(),
@@ -616,6 +792,7 @@ unsafe fn main() -> Status {
                     max_iterations,
                     record_memory_access,
                 } => {
+                    samples_executed += 1;
                     if record_memory_access {
                         let exit = executor.state_trace_sample_mem(
                             code.as_slice(),
@@ -662,17 +839,108 @@ unsafe fn main() -> Status {
                         state_trace_scratchpad_normal.clear();
                     }
                 }
+                #[cfg(feature = "speculation")]
+                OtaC2DTransport::UCodeSpeculation {
+                    triad,
+                    sequence_word,
+                    perf_counter_setup,
+                    baseline_triad,
+                } => {
+                    let result = speculation::with_speculation_patch(&mut executor, || {
+                        SpeculationResult::execute_paired(triad, baseline_triad, |triad| {
+                            speculation::execute_speculation(
+                                &mut udp,
+                                triad,
+                                sequence_word,
+                                perf_counter_setup.clone(),
+                            )
+                        })
+                    });
+
+                    match result {
+                        Ok((result, baseline)) => {
+                            let events = result
+                                .pmc_anomalies(&perf_counter_setup)
+                                .into_iter()
+                                .chain(baseline.iter().flat_map(|baseline| {
+                                    baseline.pmc_anomalies(&perf_counter_setup)
+                                }))
+                                .collect_vec();
+                            if !events.is_empty() {
+                                if let Err(err) = udp.send(OtaD2CTransport::ExecutionEvents(events))
+                                {
+                                    error!("Failed to send events: {:?}", err);
+                                }
+                            }
+
+                            if let Err(err) = udp
+                                .send(OtaD2CTransport::UCodeSpeculationResult { result, baseline })
+                            {
+                                error!("Failed to send speculation results: {:?}", err);
+                            }
+                        }
+                        Err(err) => {
+                            error!("Failed to install speculation patch: {:?}", err);
+                            let _ = udp.log_reliable(
+                                Level::Error,
+                                format!("Failed to install speculation patch: {:?}", err),
+                            );
+                        }
+                    }
+                }
+                #[cfg(feature = "speculation")]
+                OtaC2DTransport::TestIfPMCStable { perf_counter_setup } => {
+                    match speculation::with_speculation_patch(&mut executor, || {
+                        speculation::check_if_pmc_stable(&mut udp, perf_counter_setup)
+                    }) {
+                        Ok(pmc_stable) => {
+                            if let Err(err) =
+                                udp.send(OtaD2CTransport::PMCStableCheckResults { pmc_stable })
+                            {
+                                error!("Failed to send pmc stable check results: {:?}", err);
+                            }
+                        }
+                        Err(err) => {
+                            error!("Failed to install speculation patch: {:?}", err);
+                            let _ = udp.log_reliable(
+                                Level::Error,
+                                format!("Failed to install speculation patch: {:?}", err),
+                            );
+                        }
+                    }
+                }
+                #[cfg(not(feature = "speculation"))]
                 OtaC2DTransport::UCodeSpeculation { .. }
                 | OtaC2DTransport::TestIfPMCStable { .. } => {
                     let _ = udp.log_reliable(Level::Error, "Method not supported");
                     error!("Method not supported");
                 }
+                #[cfg(feature = "scenario")]
+                OtaC2DTransport::RunScenario(name, payload) => {
+                    info!("Running scenario {}", name);
+                    let response = poc_agent::execute(&name, payload.as_slice());
+                    if let Err(err) = &response {
+                        error!("Failed to run scenario {}: {}", name, err);
+                    }
+
+                    match poc_data::serialize(&response) {
+                        Ok(data) => {
+                            if let Err(err) = udp.send(OtaD2CTransport::ScenarioResult(name, data))
+                            {
+                                error!("Failed to send scenario result: {:?}", err);
+                            }
+                        }
+                        Err(err) => {
+                            error!("Failed to serialize scenario result: {}", err);
+                            let _ = udp.log_reliable(
+                                Level::Error,
+                                format!("Failed to serialize scenario result: {}", err),
+                            );
+                        }
+                    }
+                }
+                #[cfg(not(feature = "scenario"))]
                 OtaC2DTransport::RunScenario(_name, _payload) => {
-                    /*info!("Running scenario {}", name);
-                    let result = poc_agent::execute(&name, payload.as_slice());
-                    if let Err(err) = udp.send(OtaD2CTransport::ScenarioResult(name, result)) {
-                        error!("Failed to send result: {:?}", err);
-                    }*/
                     let _ = udp.log_reliable(Level::Error, "Method not supported");
                     error!("Method not supported");
                 }
@@ -702,6 +970,8 @@ fn genetic_pool_fuzzing(
     pool_settings: GeneticPoolSettings,
     evolutions: u64,
     mut network: Option<&mut ControllerConnection>,
+    resume: Option<PoolSnapshot>,
+    mutator: &mut PoolMutator,
 ) -> Vec<genetic_pool::Sample> {
     let mut random = random_source(seed);
 
@@ -715,7 +985,10 @@ fn genetic_pool_fuzzing(
     let mut global_stats = GlobalStats::default();
 
     // Samples
-    let mut genetic_pool = GeneticPool::new_random_population(pool_settings, &mut random);
+    let mut genetic_pool = match resume {
+        Some(snapshot) => GeneticPool::from_snapshot(snapshot),
+        None => GeneticPool::new_random_population(pool_settings, &mut random),
+    };
 
     // Execute initial sample to get ground truth coverage
     let ground_truth_coverage =
@@ -725,10 +998,22 @@ fn genetic_pool_fuzzing(
     let mut state_trace_scratchpad_serialized: StateTrace<VmState> = StateTrace::default();
     let mut state_trace_with_memory: StateTrace<(VmState, Vec<MemoryAccess>)> =
         StateTrace::default();
-    let mut decoder = InstructionDecoder::new();
+    let mut decoder = InstructionDecoder::new().with_cache(DECODE_CACHE_SIZE);
+
+    let serialized_difference_formatter = DifferenceFormatter::default()
+        .with_quoted_fields()
+        .with_labels("normal ", ", ", "serialized ")
+        .with_marker(|field| field == "rip");
 
     // Main fuzzing loop
     for evolution_count in 0..evolutions {
+        // Ship the population before executing it, the device might hang
+        if let Some(network) = network.as_deref_mut() {
+            if let Err(err) = network.send(OtaD2CTransport::PoolSnapshot(genetic_pool.snapshot())) {
+                error!("Failed to send the pool snapshot: {:?}", err);
+            }
+        }
+
         for sample in genetic_pool.all_samples_mut() {
             global_stats.iteration_count += 1;
 
@@ -752,6 +1037,9 @@ fn genetic_pool_fuzzing(
                             error
                         );
                     }
+                    ExecutionEvent::CoveragePatchError { error } => {
+                        error!("Failed to apply the coverage collection patch: {}", error);
+                    }
                     ExecutionEvent::VmMismatchCoverageCollection {
                         address,
                         coverage_exit,
@@ -764,14 +1052,13 @@ fn genetic_pool_fuzzing(
                         println!("We should have exited with {:#x?}", execution_result.exit);
                         if let Some(coverage_state) = coverage_state {
                             println!("The state difference was:");
-                            for (field, expected, result) in
-                                execution_result.state.difference(coverage_state)
-                            {
-                                println!(
-                                    " - {:?}: expected {:x?}, got {:x?}",
-                                    field, expected, result
-                                );
-                            }
+                            print!(
+                                "{}",
+                                DifferenceFormatter::default()
+                                    .with_quoted_fields()
+                                    .with_labels("expected ", ", ", "got ")
+                                    .format(&execution_result.state.difference(coverage_state))
+                            );
                         }
                         println!();
                     }
@@ -791,7 +1078,15 @@ fn genetic_pool_fuzzing(
                             serialized_exit
                         );
                         println!("Code:");
-                        disassemble_code(sample.code());
+                        print!(
+                            "{}",
+                            disassemble_code_to_string(
+                                sample.code(),
+                                Some(Trace::normalize(
+                                    execution_result.state.standard_registers.rip
+                                ))
+                            )
+                        );
                         state_trace_scratchpad_normal.clear();
                         executor.state_trace_sample(
                             sample.code(),
@@ -803,7 +1098,19 @@ fn genetic_pool_fuzzing(
                             state_trace_scratchpad_normal.trace_vec()
                         );
                         println!("Serialized code:");
-                        disassemble_code(&serialized_sample.as_ref().unwrap());
+                        // the serialized state is only reported if it differs
+                        let serialized_rip = serialized_state
+                            .as_ref()
+                            .unwrap_or(&execution_result.state)
+                            .standard_registers
+                            .rip;
+                        print!(
+                            "{}",
+                            disassemble_code_to_string(
+                                serialized_sample.as_ref().unwrap(),
+                                Some(Trace::normalize(serialized_rip))
+                            )
+                        );
                         state_trace_scratchpad_serialized.clear();
                         executor.state_trace_sample(
                             serialized_sample.as_ref().unwrap(),
@@ -816,15 +1123,11 @@ fn genetic_pool_fuzzing(
                         );
                         if let Some(serialized_state) = serialized_state {
                             println!("The difference was:");
-                            for (field, expected, result) in
-                                execution_result.state.difference(serialized_state)
-                            {
-                                let symbol = if field == "rip" { "*" } else { "-" };
-                                println!(
-                                    " {} {:?}: normal {:x?}, serialized {:x?}",
-                                    symbol, field, expected, result
-                                );
-                            }
+                            print!(
+                                "{}",
+                                serialized_difference_formatter
+                                    .format(&execution_result.state.difference(serialized_state))
+                            );
                             println!("Difference occurred at:");
                             let difference = state_trace_scratchpad_normal
                                 .first_difference_no_addresses(&state_trace_scratchpad_serialized);
@@ -848,15 +1151,11 @@ fn genetic_pool_fuzzing(
                                         if let (Some(normal), Some(serialized)) =
                                             (normal, serialized)
                                         {
-                                            for (field, expected, result) in
-                                                normal.difference(serialized)
-                                            {
-                                                let symbol = if field == "rip" { "*" } else { "-" };
-                                                println!(
-                                                    " {} {:?}: normal {:x?}, serialized {:x?}",
-                                                    symbol, field, expected, result
-                                                );
-                                            }
+                                            print!(
+                                                "{}",
+                                                serialized_difference_formatter
+                                                    .format(&normal.difference(serialized))
+                                            );
                                         } else {
                                             println!("Execution for one of the traces stopped");
                                             break;
@@ -874,14 +1173,10 @@ fn genetic_pool_fuzzing(
                     .events
                     .iter()
                     .cloned()
-                    .filter_map(Option::<ReportExecutionProblem>::from);
-                for event in (&events
-                    .into_iter()
-                    .chunks(ReportExecutionProblem::MAX_PER_PACKET))
-                    .into_iter()
-                    .map(|chunk| chunk.collect_vec())
-                {
-                    if let Err(err) = net.send(OtaD2CTransport::ExecutionEvents(event)) {
+                    .filter_map(Option::<ReportExecutionProblem>::from)
+                    .collect_vec();
+                if !events.is_empty() {
+                    if let Err(err) = net.send(OtaD2CTransport::ExecutionEvents(events)) {
                         error!("Failed to send event: {:?}", err);
                     }
                 }
@@ -916,7 +1211,9 @@ fn genetic_pool_fuzzing(
         }
 
         if evolution_count + 1 < evolutions {
-            genetic_pool.evolution(&mut random, true);
+            genetic_pool.set_stagnation(global_stats.iterations_since_last_gain);
+            global_stats.mutation_rate = Some(genetic_pool.mutation_rate());
+            genetic_pool.evolution_with_mutator(&mut random, true, Some(&mut *mutator));
         }
     }
 
@@ -933,6 +1230,54 @@ fn genetic_pool_fuzzing(
     result
 }
 
+/// Reads or writes a model specific register on behalf of the controller
+///
+/// Writes are restricted to [`WRITABLE_MSRS`] unless the `unsafe-msr` feature is enabled.
+///
+/// # Arguments
+///
+/// * `msr` - Model specific register to access
+/// * `write` - Value to write, the register is read if `None`
+///
+/// # Returns
+///
+/// * `OtaD2CTransport` - The reply to the controller
+fn access_msr(msr: u32, write: Option<u64>) -> OtaD2CTransport {
+    let result = match write {
+        None => rdmsr_safe(msr).map_err(|_| MsrAccessError::GeneralProtectionFault),
+        Some(_) if !cfg!(feature = "unsafe-msr") && !WRITABLE_MSRS.contains(&msr) => {
+            Err(MsrAccessError::NotAllowed)
+        }
+        Some(value) => wrmsr_safe(msr, value)
+            .map(|_| value)
+            .map_err(|_| MsrAccessError::GeneralProtectionFault),
+    };
+
+    match result {
+        Ok(value) => OtaD2CTransport::MsrValue { msr, value },
+        Err(error) => OtaD2CTransport::MsrAccessFailed { msr, error },
+    }
+}
+
+/// Sums the free conventional memory, from which the UEFI allocator serves the heap
+///
+/// # Returns
+///
+/// * `u64` - Free memory in bytes, zero if the memory map is unavailable
+fn free_memory() -> u64 {
+    match uefi::boot::memory_map(MemoryType::LOADER_DATA) {
+        Ok(memory_map) => memory_map
+            .entries()
+            .filter(|entry| entry.ty == MemoryType::CONVENTIONAL)
+            .map(|entry| entry.page_count * uefi::boot::PAGE_SIZE as u64)
+            .sum(),
+        Err(err) => {
+            error!("Failed to get the memory map: {:?}", err);
+            0
+        }
+    }
+}
+
 fn ground_truth_coverage<R: RngCore>(
     executor: &mut SampleExecutor,
     execution_result: &mut ExecutionResult,
@@ -962,7 +1307,17 @@ pub fn rate_sample_from_execution(
     decoder: &mut InstructionDecoder,
     execution_result: &ExecutionResult,
 ) -> GeneticSampleRating {
-    let number_of_instructions = decoder.decode(code, 0).len();
+    // a hanging sample has no meaningful coverage, do not breed from it
+    if execution_result
+        .events
+        .iter()
+        .any(|event| matches!(event, ExecutionEvent::SampleTimeout { .. }))
+    {
+        return GeneticSampleRating::MIN;
+    }
+
+    let instructions = decoder.decode_detailed(code, 0);
+    let number_of_instructions = instructions.len();
 
     // expects existing entries to all have values >0
     let unique_address_coverage = execution_result.coverage.keys().count();
@@ -975,19 +1330,33 @@ pub fn rate_sample_from_execution(
         .trace
         .hit
         .keys()
-        .filter(|&ip| *ip < code.len() as u64)
+        .filter(|&ip| execution_result.trace.source_offset(*ip).is_some())
         .count();
     let program_utilization = (f32::clamp(
         unique_trace_addresses as f32 / number_of_instructions as f32,
         0.0,
         1.0,
     ) * 100.0) as usize;
+    let executed_offsets = execution_result
+        .trace
+        .hit
+        .keys()
+        .filter_map(|&ip| execution_result.trace.source_offset(ip))
+        .collect::<BTreeSet<_>>();
+    let instruction_diversity = instructions
+        .iter()
+        .filter(|instruction| executed_offsets.contains(&instruction.offset))
+        .map(|instruction| instruction.category)
+        .filter(|category| *category != InstructionCategory::Invalid)
+        .collect::<BTreeSet<_>>()
+        .len();
     let loop_count = execution_result.trace.hit.values().max().unwrap_or(&1) - 1;
 
     GeneticSampleRating {
         unique_address_coverage: unique_address_coverage as u16,
         total_address_coverage,
         program_utilization: program_utilization as u8,
+        instruction_diversity: instruction_diversity as u8,
         loop_count,
     }
 }
@@ -1002,6 +1371,7 @@ pub struct GlobalStats {
     pub iteration_count: u64,
     pub iterations_since_last_gain: u64,
     pub coverage_sofar: usize,
+    pub mutation_rate: Option<genetic_pool::MutationRate>,
 }
 
 impl GlobalStats {
@@ -1017,6 +1387,9 @@ impl GlobalStats {
         );
         println!(" - since last: {:e}", self.iterations_since_last_gain);
         println!(" - coverage: {}", self.coverage_sofar);
+        if let Some(mutation_rate) = &self.mutation_rate {
+            println!(" - mutation rate: {}", mutation_rate);
+        }
     }
 
     pub fn annonce_new_sample(&self, sample: &[u8], new_coverage: usize) {
@@ -1041,118 +1414,3 @@ fn prepare_gdb() {
         debug!("System table: {:x?}", system_table.as_ptr() as usize);
     }
 }
-
-#[derive(Debug, Default)]
-struct ExcludedAddresses {
-    addresses: BTreeSet<u16>,
-}
-
-impl ExcludedAddresses {
-    const FILENAME: &'static str = "blacklist.txt";
-    fn filename() -> Result<CString16, Error> {
-        CString16::try_from(Self::FILENAME)
-            .map_err(|_| uefi::Error::from(uefi::Status::UNSUPPORTED))
-    }
-    pub fn load_file() -> Result<Self, uefi::Error> {
-        let mut proto = uefi::boot::get_image_file_system(uefi::boot::image_handle())?;
-        let mut root_dir = proto.open_volume()?;
-        let file = match root_dir.open(
-            Self::filename()?.as_ref(),
-            FileMode::Read,
-            FileAttribute::empty(),
-        ) {
-            Ok(file) => file,
-            Err(e) => {
-                return if e.status() == Status::NOT_FOUND {
-                    Ok(Self {
-                        addresses: BTreeSet::new(),
-                    })
-                } else {
-                    Err(e)
-                }
-            }
-        };
-        let mut regular_file = file
-            .into_regular_file()
-            .ok_or_else(|| uefi::Error::from(uefi::Status::UNSUPPORTED))?;
-
-        let mut buffer = [0u8; 4096];
-        let mut data = String::new();
-
-        loop {
-            let read = regular_file.read(&mut buffer)?;
-
-            if read == 0 {
-                break;
-            }
-
-            for i in 0..read {
-                if buffer[i] != 0 {
-                    data.push(buffer[i] as char);
-                }
-            }
-        }
-
-        let excluded = data
-            .lines()
-            .filter(|line| !(line.starts_with("//") || line.starts_with("#") || line.is_empty()))
-            .filter_map(|line| {
-                u16::from_str_radix(line, 16)
-                    .map_err(|_| uefi::Error::from(uefi::Status::UNSUPPORTED))
-                    .ok()
-            });
-
-        Ok(Self {
-            addresses: BTreeSet::from_iter(excluded),
-        })
-    }
-
-    pub fn exclude_address<A: Into<u16>>(&mut self, address: A) {
-        self.addresses.insert(address.into());
-    }
-
-    pub fn save_file(&self) -> Result<(), uefi::Error> {
-        let mut proto = uefi::boot::get_image_file_system(uefi::boot::image_handle())?;
-        let mut root_dir = proto.open_volume()?;
-        let file = root_dir.open(
-            Self::filename().unwrap().as_ref(),
-            FileMode::CreateReadWrite,
-            FileAttribute::empty(),
-        )?;
-        let mut regular_file = file
-            .into_regular_file()
-            .ok_or_else(|| uefi::Error::from(uefi::Status::UNSUPPORTED))?;
-        let mut data = String::new();
-        for address in &self.addresses {
-            data.push_str(&format!("{:04x}\n", address));
-        }
-        regular_file
-            .write(data.as_bytes())
-            .map_err(|_| uefi::Error::from(uefi::Status::WARN_WRITE_FAILURE))?;
-        regular_file.flush()?;
-
-        let mut buf = Vec::new();
-        let fileinfo_size = regular_file
-            .get_info::<FileInfo>(&mut buf)
-            .unwrap_err()
-            .data()
-            .ok_or(uefi::Error::from(uefi::Status::UNSUPPORTED))?;
-        buf.resize(fileinfo_size, 0);
-        let file_size = regular_file
-            .get_info::<FileInfo>(&mut buf)
-            .map_err(|_| uefi::Error::from(uefi::Status::WARN_WRITE_FAILURE))?
-            .file_size();
-        let current_position = regular_file.get_position()?;
-
-        for _ in 0..(file_size - current_position).div_ceil(128) {
-            regular_file
-                .write(&[0; 128])
-                .map_err(|_| uefi::Error::from(uefi::Status::WARN_WRITE_FAILURE))?;
-        }
-
-        root_dir.flush()?;
-        root_dir.close();
-
-        Ok(())
-    }
-}
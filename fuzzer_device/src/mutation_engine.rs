@@ -1,10 +1,15 @@
+pub mod dictionary;
 mod random_mutation;
 pub mod serialize;
+pub mod splice;
 
 use crate::heuristic::Sample;
 use crate::mutation_engine::random_mutation::RandomMutation;
 use alloc::boxed::Box;
 use alloc::vec::Vec;
+use fuzzer_data::decoder::{InstructionCategory, InstructionDecoder};
+use fuzzer_data::genetic_pool::{InstructionMutator, MutationStrategy};
+use fuzzer_data::instruction_corpus::InstructionCorpus;
 use rand_core::RngCore;
 
 pub const NUMBER_OF_MUTATION_OPERATIONS: usize = {
@@ -66,3 +71,121 @@ impl<R: RngCore> MutationEngine<R> {
         (mutation_index, result)
     }
 }
+
+/// Offsets in the code at which an instruction starts or the code ends after a complete instruction
+///
+/// # Arguments
+///
+/// * `decoder` - Decoder to find the instructions
+/// * `code` - Code to decode
+///
+/// # Returns
+///
+/// * `Vec<usize>` - Boundaries in ascending order, never empty
+fn instruction_boundaries(decoder: &mut InstructionDecoder, code: &[u8]) -> Vec<usize> {
+    let decoded = decoder.decode_detailed(code, 0);
+    let complete = !matches!(
+        decoded.last(),
+        Some(last) if last.category == InstructionCategory::Invalid
+    );
+
+    let mut boundaries = decoded
+        .iter()
+        .map(|instruction| instruction.offset)
+        .collect::<Vec<_>>();
+    if complete {
+        boundaries.push(code.len());
+    }
+    boundaries
+}
+
+/// Instruction aware mutations of the genetic pool: dictionary insertion and splicing
+///
+/// Strategies disabled by the `mutation_dictionary` and `mutation_splice` features are
+/// declined, the genetic pool applies a havoc mutation instead.
+pub struct PoolMutator {
+    /// Decoder to find instruction boundaries
+    decoder: InstructionDecoder,
+    /// Instructions inserted by dictionary mutations
+    dictionary: InstructionCorpus,
+}
+
+impl Default for PoolMutator {
+    fn default() -> Self {
+        Self {
+            decoder: InstructionDecoder::new(),
+            dictionary: InstructionCorpus::new(Vec::new()),
+        }
+    }
+}
+
+impl PoolMutator {
+    /// Get the instructions inserted by dictionary mutations
+    pub fn dictionary(&self) -> &InstructionCorpus {
+        &self.dictionary
+    }
+
+    /// Adds instructions to the dictionary
+    ///
+    /// # Arguments
+    ///
+    /// * `instructions` - Corpus to merge into the dictionary
+    pub fn extend_dictionary(&mut self, instructions: InstructionCorpus) {
+        self.dictionary.merge(instructions);
+    }
+}
+
+impl<R: RngCore> InstructionMutator<R> for PoolMutator {
+    fn mutate(
+        &mut self,
+        strategy: MutationStrategy,
+        code: &mut Vec<u8>,
+        other_parent: &[u8],
+        random: &mut R,
+    ) -> bool {
+        match strategy {
+            MutationStrategy::Havoc => false,
+            MutationStrategy::Dictionary => {
+                cfg!(feature = "mutation_dictionary")
+                    && dictionary::insert_instruction(
+                        code,
+                        &self.dictionary,
+                        &mut self.decoder,
+                        random,
+                    )
+                    .is_some()
+            }
+            MutationStrategy::Splice => {
+                cfg!(feature = "mutation_splice")
+                    && splice::splice(code, other_parent, &mut self.decoder, random).is_some()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand_core::RngCore;
+
+    /// Deterministic xorshift random source for tests
+    pub struct TestRng(pub u64);
+
+    impl RngCore for TestRng {
+        fn next_u32(&mut self) -> u32 {
+            self.next_u64() as u32
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn fill_bytes(&mut self, dst: &mut [u8]) {
+            for byte in dst.iter_mut() {
+                *byte = self.next_u32() as u8;
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Dictionary Mutation Module
+//!
+//! Inserts whole instructions drawn from an instruction corpus. Byte havoc rarely synthesizes a
+//! valid multi-byte instruction, a dictionary insertion always adds one.
+
+use crate::mutation_engine::instruction_boundaries;
+use alloc::vec::Vec;
+use fuzzer_data::decoder::InstructionDecoder;
+use fuzzer_data::instruction_corpus::InstructionCorpus;
+use rand_core::RngCore;
+
+/// Inserts an instruction of the dictionary at an instruction boundary of the code
+///
+/// # Arguments
+///
+/// * `code` - Code to insert the instruction into
+/// * `dictionary` - Instructions to choose from, picked by their weight
+/// * `decoder` - Decoder to find the instruction boundaries of the code
+/// * `random` - Random source for the instruction and the insertion point
+///
+/// # Returns
+///
+/// * `Some(usize)` - Offset of the inserted instruction
+/// * `None` - The dictionary is empty, the code is unchanged
+pub fn insert_instruction<R: RngCore>(
+    code: &mut Vec<u8>,
+    dictionary: &InstructionCorpus,
+    decoder: &mut InstructionDecoder,
+    random: &mut R,
+) -> Option<usize> {
+    let instruction = dictionary.sample_weighted(random, 1);
+    if instruction.is_empty() {
+        return None;
+    }
+
+    let boundaries = instruction_boundaries(decoder, code);
+    let offset = boundaries[random.next_u32() as usize % boundaries.len()];
+    code.splice(offset..offset, instruction);
+    Some(offset)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mutation_engine::dictionary::insert_instruction;
+    use crate::mutation_engine::test::TestRng;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use fuzzer_data::decoder::InstructionDecoder;
+    use fuzzer_data::instruction_corpus::{CorpusInstruction, InstructionCorpus};
+
+    #[test]
+    fn test_insertions_on_instruction_boundaries() {
+        let dictionary = InstructionCorpus::new(
+            [
+                vec![0x0F, 0xA2],                         // cpuid
+                vec![0x48, 0x8B, 0x03],                   // mov rax, [rbx]
+                vec![0x66, 0x0F, 0xEF, 0xC0],             // pxor xmm0, xmm0
+                vec![0x48, 0xB8, 1, 2, 3, 4, 5, 6, 7, 8], // mov rax, imm64
+            ]
+            .into_iter()
+            .map(|bytes| CorpusInstruction { bytes, valid: true }),
+        );
+        let mut decoder = InstructionDecoder::new();
+        let mut random = TestRng(3);
+
+        let mut code = vec![
+            0x48, 0x01, 0xD8, // add rax, rbx
+            0x90, // nop
+            0xF3, 0xA4, // rep movsb
+        ];
+        for _ in 0..50 {
+            let before = code.clone();
+            let boundaries = decoder
+                .decode_detailed(&before, 0)
+                .iter()
+                .map(|instruction| instruction.offset)
+                .chain([before.len()])
+                .collect::<Vec<_>>();
+
+            let offset = insert_instruction(&mut code, &dictionary, &mut decoder, &mut random)
+                .expect("dictionary is not empty");
+            assert!(boundaries.contains(&offset));
+
+            let length = code.len() - before.len();
+            let inserted = &code[offset..offset + length];
+            assert!(dictionary.instructions.iter().any(|x| x.bytes == inserted));
+            assert_eq!(code[..offset], before[..offset]);
+            assert_eq!(code[offset + length..], before[offset..]);
+
+            // the inserted instruction decodes as a whole
+            let decoded = decoder.decode_detailed(&code, 0);
+            assert!(decoded
+                .iter()
+                .any(|instruction| instruction.offset == offset && instruction.length == length));
+        }
+
+        let mut code = Vec::new();
+        assert_eq!(
+            insert_instruction(&mut code, &dictionary, &mut decoder, &mut random),
+            Some(0)
+        );
+
+        let empty = InstructionCorpus::new(Vec::new());
+        let before = code.clone();
+        assert_eq!(
+            insert_instruction(&mut code, &empty, &mut decoder, &mut random),
+            None
+        );
+        assert_eq!(code, before);
+    }
+}
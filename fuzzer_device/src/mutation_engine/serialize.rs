@@ -7,9 +7,10 @@ use crate::Trace;
 use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
 use core::fmt::Display;
-use fuzzer_data::decoder::{InstructionDecoder, InstructionWithBytes};
+use fuzzer_data::decoder::{InstructionCategory, InstructionDecoder};
 use iced_x86::{
-    BlockEncoder, BlockEncoderOptions, Code, FlowControl, IcedError, Instruction, InstructionBlock,
+    BlockEncoder, BlockEncoderOptions, Code, Decoder, DecoderOptions, FlowControl, IcedError,
+    Instruction, InstructionBlock,
 };
 use log::warn;
 #[cfg(feature = "__debug_performance_trace")]
@@ -94,34 +95,36 @@ impl Serializer {
         struct Output<'a> {
             pub original_ip: u64,
             pub fence_instruction: &'static [u8],
-            pub payload: &'a InstructionWithBytes<'a>,
+            pub bytes: &'a [u8],
+            pub instruction: Option<Instruction>,
             pub override_instruction: Option<Vec<u8>>,
         }
 
-        let decoded = self.instruction_decoder.decode(code, 0);
+        let decoded = self.instruction_decoder.decode_detailed(code, 0);
 
         let mut target_program = Vec::with_capacity(decoded.len() * 2);
 
         let mut map_old_to_new_ip = BTreeMap::new();
 
         let mut current_ip = 0;
-        for index in 0..decoded.len() {
-            let instruction = decoded.get(index).expect("<= len");
+        for decoded_instruction in &decoded {
+            let bytes = decoded_instruction.bytes(code);
+            let original_ip = decoded_instruction.offset as u64;
             let fence_instruction =
                 FENCE_INSTRUCTIONS[random.next_u32() as usize % FENCE_INSTRUCTIONS.len()];
 
             let fence_ip = current_ip;
             current_ip += fence_instruction.len() as u64;
             let instruction_ip = current_ip;
-            current_ip += instruction.bytes.len() as u64;
+            current_ip += bytes.len() as u64;
 
-            let was_executed = trace.was_executed(instruction.instruction.ip());
+            let was_executed = trace.was_executed(original_ip);
 
             let info = InstructionInfo {
                 new_ip_serialize_operation: fence_ip,
                 new_ip_original_instruction: instruction_ip,
-                ip_original_instruction: instruction.instruction.ip(),
-                original_instruction_length: instruction.bytes.len() as u64,
+                ip_original_instruction: original_ip,
+                original_instruction_length: bytes.len() as u64,
                 was_executed,
             };
 
@@ -129,22 +132,28 @@ impl Serializer {
                 map_old_to_new_ip.insert(info.ip_original_instruction + l as u64, info);
             }
 
+            // only instructions with memory operands or control flow may need patching,
+            // so only those are decoded again
+            let instruction = match decoded_instruction.category {
+                InstructionCategory::Invalid | InstructionCategory::Other => None,
+                _ => Some(Decoder::with_ip(64, bytes, original_ip, DecoderOptions::NONE).decode()),
+            };
+
             target_program.push(Output {
-                original_ip: instruction.instruction.ip(),
+                original_ip,
                 fence_instruction,
-                payload: instruction,
+                bytes,
+                instruction,
                 override_instruction: None,
             });
         }
         let current_ip = current_ip; // make read-only
 
         for item in &mut target_program {
-            let instruction = &item.payload.instruction;
-            let data = map_old_to_new_ip.get(&item.original_ip).unwrap();
-
-            if instruction.is_invalid() {
+            let Some(instruction) = &item.instruction else {
                 continue;
-            }
+            };
+            let data = map_old_to_new_ip.get(&item.original_ip).unwrap();
 
             // paradigma: do best effort for non-executed instructions
             // if an instruction that was executed can not be changed -> abort
@@ -227,7 +236,7 @@ impl Serializer {
                     output.extend_from_slice(instr);
                 }
                 None => {
-                    output.extend_from_slice(item.payload.bytes);
+                    output.extend_from_slice(item.bytes);
                 }
             }
         }
@@ -0,0 +1,102 @@
+//! Splice Mutation Module
+//!
+//! Joins a prefix of one sample with a suffix of another sample. Both are cut at decoded
+//! instruction boundaries, so no partial instruction is left at the junction.
+
+use crate::mutation_engine::instruction_boundaries;
+use alloc::vec::Vec;
+use fuzzer_data::decoder::InstructionDecoder;
+use rand_core::RngCore;
+
+/// Replaces a suffix of the code by a suffix of another sample
+///
+/// # Arguments
+///
+/// * `code` - Code providing the prefix, replaced by the spliced code
+/// * `other` - Code providing the suffix
+/// * `decoder` - Decoder to find the instruction boundaries of both samples
+/// * `random` - Random source for the cut points
+///
+/// # Returns
+///
+/// * `Some((usize, usize))` - Length of the kept prefix and offset of the suffix in `other`
+/// * `None` - The other sample is empty, the code is unchanged
+pub fn splice<R: RngCore>(
+    code: &mut Vec<u8>,
+    other: &[u8],
+    decoder: &mut InstructionDecoder,
+    random: &mut R,
+) -> Option<(usize, usize)> {
+    if other.is_empty() {
+        return None;
+    }
+
+    let prefix_boundaries = instruction_boundaries(decoder, code);
+    let suffix_boundaries = instruction_boundaries(decoder, other);
+    let prefix = prefix_boundaries[random.next_u32() as usize % prefix_boundaries.len()];
+    let suffix = suffix_boundaries[random.next_u32() as usize % suffix_boundaries.len()];
+
+    code.truncate(prefix);
+    code.extend_from_slice(&other[suffix..]);
+    Some((prefix, suffix))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mutation_engine::splice::splice;
+    use crate::mutation_engine::test::TestRng;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use fuzzer_data::decoder::InstructionDecoder;
+
+    #[test]
+    fn test_splice_on_instruction_boundaries() {
+        let parent = vec![
+            0x48, 0xB8, 1, 2, 3, 4, 5, 6, 7, 8, // mov rax, imm64
+            0x0F, 0xA2, // cpuid
+            0x48, 0x01, 0xD8, // add rax, rbx
+            0x66, 0x0F, 0xEF, // truncated pxor
+        ];
+        let other = vec![
+            0x66, 0x0F, 0xEF, 0xC0, // pxor xmm0, xmm0
+            0x90, // nop
+            0x48, 0x8B, 0x03, // mov rax, [rbx]
+            0x06, // push es, invalid in 64-bit mode
+            0xF3, 0xA4, // rep movsb
+        ];
+        let mut decoder = InstructionDecoder::new();
+        let mut random = TestRng(11);
+
+        for _ in 0..50 {
+            let mut code = parent.clone();
+            let (prefix, suffix) =
+                splice(&mut code, &other, &mut decoder, &mut random).expect("other is not empty");
+            assert!(prefix <= 15, "the truncated pxor is never kept");
+            assert_eq!(code[..prefix], parent[..prefix]);
+            assert_eq!(code[prefix..], other[suffix..]);
+
+            // decoding the spliced code passes through the junction
+            let offsets = |code: &[u8], decoder: &mut InstructionDecoder| {
+                decoder
+                    .decode_detailed(code, 0)
+                    .iter()
+                    .map(|instruction| (instruction.offset, instruction.length))
+                    .collect::<Vec<_>>()
+            };
+            let spliced = offsets(&code, &mut decoder);
+            let expected = offsets(&parent[..prefix], &mut decoder)
+                .into_iter()
+                .chain(
+                    offsets(&other[suffix..], &mut decoder)
+                        .into_iter()
+                        .map(|(offset, length)| (offset + prefix, length)),
+                )
+                .collect::<Vec<_>>();
+            assert_eq!(spliced, expected);
+        }
+
+        let mut code = parent.clone();
+        assert_eq!(splice(&mut code, &[], &mut decoder, &mut random), None);
+        assert_eq!(code, parent);
+    }
+}
@@ -3,8 +3,11 @@
 //! This module provides functionality for monitoring and recording performance metrics
 //! during fuzzing operations.
 
-use alloc::collections::BTreeMap;
+use crate::file_system::{delete_existing, into_regular_file, open_existing, read_file, rename};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
 use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 use log::error;
 use performance_timing::measurements::{MeasureValues, MeasurementCollection, MeasurementData};
 #[cfg(feature = "__debug_performance_trace")]
@@ -13,88 +16,112 @@ use performance_timing::Instant;
 use uefi::proto::media::file::{File, FileMode};
 use uefi::CString16;
 use uefi_raw::protocol::file_system::FileAttribute;
-use uefi_raw::Status;
+
+/// Snapshot of the measurements of the current run
+#[derive(Clone)]
+pub struct PerfSnapshot {
+    /// Time the snapshot was taken
+    pub timestamp: Instant,
+    /// Measurements accumulated since the start of the current run
+    pub values: MeasurementData<u64>,
+}
+
+impl PerfSnapshot {
+    /// Calculates the measurements taken between an earlier snapshot and this one
+    ///
+    /// # Arguments
+    ///
+    /// * `earlier` - Snapshot taken before this one
+    ///
+    /// # Returns
+    ///
+    /// * `MeasurementData<u64>` - Measurements taken in between, unchanged entries are omitted
+    pub fn since(&self, earlier: &PerfSnapshot) -> MeasurementData<u64> {
+        self.values
+            .iter()
+            .map(|(name, values)| match earlier.values.get(name) {
+                Some(earlier) => (name.clone(), values.since(earlier)),
+                None => (name.clone(), *values),
+            })
+            .filter(|(_, values)| values.number_of_measurements > 0)
+            .collect()
+    }
+}
 
 /// Performance monitoring and measurement collection
 ///
 /// This structure manages the collection and storage of performance measurements,
 /// providing functionality for updating measurements and saving them to a file.
+/// Saved files are rotated, the newest file has the index `0`, e.g. `perf.0.json`.
 pub struct PerfMonitor {
     /// Collection of performance measurements
     pub measurement_data: MeasurementCollection<u64>,
-    /// Path to the measurement data file
-    pub filename: CString16,
+    /// Paths of the rotated measurement data files, the newest first
+    pub filenames: Vec<CString16>,
+    /// Path of the file written before it replaces the newest measurement data file
+    pub temporary_filename: CString16,
     /// Timestamp of the last save operation
     pub last_save: Instant,
+    /// Snapshots of the measurements of the current run, the oldest first
+    history: VecDeque<PerfSnapshot>,
+    /// Maximum number of snapshots kept in the history
+    history_length: usize,
 }
 
 impl PerfMonitor {
-    /// Creates a new performance monitor instance by loading it from a file (if it exists)
+    /// Creates a new performance monitor instance by loading it from the newest readable
+    /// measurement data file (if one exists)
     ///
     /// # Arguments
     ///
-    /// * `filepath` - Path to the measurement data file
+    /// * `filepath` - Path of the measurement data file, the rotation index is inserted before
+    ///   the extension
+    /// * `file_count` - Number of rotated measurement data files, at least one is kept
+    /// * `history_length` - Maximum number of snapshots kept in memory
     ///
     /// # Returns
     ///
     /// * `uefi::Result<PerfMonitor>` - New monitor instance or error
-    pub fn new(filepath: &str) -> uefi::Result<PerfMonitor> {
+    pub fn new(
+        filepath: &str,
+        file_count: usize,
+        history_length: usize,
+    ) -> uefi::Result<PerfMonitor> {
+        let filenames = (0..file_count.max(1))
+            .map(|index| rotated_filename(filepath, index))
+            .collect::<uefi::Result<Vec<CString16>>>()?;
+        let temporary_filename = rotated_filename(filepath, "tmp")?;
+
         let mut proto = uefi::boot::get_image_file_system(uefi::boot::image_handle())?;
         let mut root_dir = proto.open_volume()?;
-        let filename = CString16::try_from(filepath)
-            .map_err(|_| uefi::Error::from(uefi::Status::UNSUPPORTED))?;
-        let file = match root_dir.open(filename.as_ref(), FileMode::Read, FileAttribute::empty()) {
-            Ok(file) => file,
-            Err(e) => {
-                return if e.status() == Status::NOT_FOUND {
-                    let mut result = Self {
-                        measurement_data: MeasurementCollection::default(),
-                        filename,
-                        last_save: performance_timing::instance().now(),
-                    };
-                    result
-                        .measurement_data
-                        .data
-                        .push(MeasurementData::default());
-                    Ok(result)
-                } else {
-                    Err(e)
-                }
-            }
-        };
-        let mut regular_file = file
-            .into_regular_file()
-            .ok_or_else(|| uefi::Error::from(uefi::Status::UNSUPPORTED))?;
 
-        let mut buffer = [0u8; 4096];
-        let mut data = String::new();
-
-        loop {
-            let read = regular_file.read(&mut buffer)?;
-
-            if read == 0 {
-                break;
-            }
-
-            for i in 0..read {
-                data.push(buffer[i] as char);
+        let mut data = None;
+        for filename in filenames.iter() {
+            let Some(content) = read_file(&mut root_dir, filename)? else {
+                continue;
+            };
+            match serde_json::from_slice::<MeasurementCollection<u64>>(&content) {
+                Ok(collection) => {
+                    data = Some(collection);
+                    break;
+                }
+                Err(e) => {
+                    // a run might have died while the file was written
+                    error!("Json deserialize error in {}: {:?}", filename, e);
+                }
             }
         }
 
-        let mut data: MeasurementCollection<u64> = serde_json::from_str(data.as_str())
-            .unwrap_or_else(|e| {
-                error!("Json deserialize error: {:?}", e);
-                let mut result = MeasurementCollection::default();
-                result.data.push(MeasurementData::default());
-                result
-            });
-
+        let mut data = data.unwrap_or_default();
         data.data.push(MeasurementData::default());
 
         Ok(Self {
             measurement_data: data,
-            filename,
+            filenames,
+            temporary_filename,
             last_save: performance_timing::instance().now(),
+            history: VecDeque::with_capacity(history_length),
+            history_length,
         })
     }
 
@@ -127,6 +154,47 @@ impl PerfMonitor {
         last.clone_from(data);
     }
 
+    /// Records a snapshot of the current measurements in the history
+    ///
+    /// The oldest snapshot is dropped if the history is full.
+    pub fn record_snapshot(&mut self) {
+        if self.history_length == 0 {
+            return;
+        }
+        if self.history.len() >= self.history_length {
+            self.history.pop_front();
+        }
+        self.history.push_back(PerfSnapshot {
+            timestamp: performance_timing::instance().now(),
+            values: self
+                .measurement_data
+                .data
+                .last()
+                .cloned()
+                .unwrap_or_default(),
+        });
+    }
+
+    /// Gets the recorded snapshots of the current run, the oldest first
+    pub fn history(&self) -> &VecDeque<PerfSnapshot> {
+        &self.history
+    }
+
+    /// Calculates the measurements taken between the two newest snapshots
+    ///
+    /// # Returns
+    ///
+    /// * `Option<MeasurementData<u64>>` - Measurements taken in between, all measurements of the
+    ///   current run if there is only one snapshot, `None` if there is none
+    pub fn latest_delta(&self) -> Option<MeasurementData<u64>> {
+        let mut newest_first = self.history.iter().rev();
+        let newest = newest_first.next()?;
+        Some(match newest_first.next() {
+            Some(earlier) => newest.since(earlier),
+            None => newest.values.clone(),
+        })
+    }
+
     /// Attempts to save measurements to file if enough time has passed
     ///
     /// Measurements are saved if more than 30 seconds have passed since the last save.
@@ -148,8 +216,8 @@ impl PerfMonitor {
 
     /// Updates measurements and saves to file if enough time has passed
     ///
-    /// This function combines updating measurements from the monitor and saving
-    /// to file if more than 30 seconds have passed since the last save.
+    /// This function combines updating measurements from the monitor, recording a
+    /// snapshot and saving to file if more than 30 seconds have passed since the last save.
     ///
     /// # Returns
     ///
@@ -161,6 +229,7 @@ impl PerfMonitor {
         if duration_s > 30.0 {
             self.last_save = now;
             self.update_values_from_monitor();
+            self.record_snapshot();
             self.save_file()
         } else {
             Ok(())
@@ -169,32 +238,48 @@ impl PerfMonitor {
 
     /// Saves the current measurements to file
     ///
+    /// The measurements are written to a temporary file first, which replaces the newest
+    /// measurement data file after the older files were rotated. A run that dies while saving
+    /// therefore never truncates the previous measurements.
+    ///
     /// # Returns
     ///
     /// * `uefi::Result<()>` - Success or error
     #[cfg_attr(feature = "__debug_performance_trace", track_time("perf::save_file"))]
     pub fn save_file(&self) -> uefi::Result<()> {
+        let data = serde_json::to_string(&self.measurement_data).map_err(|e| {
+            error!("Failed to serialize measurement data: {:?}", e);
+            uefi::Status::ABORTED
+        })?;
+
         let mut proto = uefi::boot::get_image_file_system(uefi::boot::image_handle())?;
         let mut root_dir = proto.open_volume()?;
+
+        // a leftover of an interrupted save
+        delete_existing(&mut root_dir, &self.temporary_filename)?;
+
         let file = root_dir.open(
-            self.filename.as_ref(),
+            self.temporary_filename.as_ref(),
             FileMode::CreateReadWrite,
             FileAttribute::empty(),
         )?;
-        let mut regular_file = file
-            .into_regular_file()
-            .ok_or_else(|| uefi::Error::from(uefi::Status::UNSUPPORTED))?;
-        let data = serde_json::to_string(&self.measurement_data).map_err(|e| {
-            error!("Failed to serialize measurement data: {:?}", e);
-            uefi::Status::ABORTED
-        })?;
-
+        let mut temporary_file = into_regular_file(file)?;
         for data_chunked in data.as_bytes().chunks(4096) {
-            regular_file
+            temporary_file
                 .write(data_chunked)
                 .map_err(|_| uefi::Error::from(uefi::Status::WARN_WRITE_FAILURE))?;
         }
-        regular_file.flush()?;
+        temporary_file.flush()?;
+
+        // the oldest file is replaced, all others move one index up
+        delete_existing(&mut root_dir, self.filenames.last().unwrap())?;
+        for index in (1..self.filenames.len()).rev() {
+            if let Some(file) = open_existing(&mut root_dir, &self.filenames[index - 1])? {
+                rename(&mut into_regular_file(file)?, &self.filenames[index])?;
+            }
+        }
+
+        rename(&mut temporary_file, &self.filenames[0])?;
 
         root_dir.flush()?;
         root_dir.close();
@@ -202,3 +287,21 @@ impl PerfMonitor {
         Ok(())
     }
 }
+
+/// Builds the path of a rotated file by inserting the index before the extension
+///
+/// # Arguments
+///
+/// * `filepath` - Path of the file, e.g. `perf.json`
+/// * `index` - Index of the rotated file, e.g. `0` for `perf.0.json`
+///
+/// # Returns
+///
+/// * `uefi::Result<CString16>` - Path of the rotated file or error
+fn rotated_filename(filepath: &str, index: impl core::fmt::Display) -> uefi::Result<CString16> {
+    let filename = match filepath.rsplit_once('.') {
+        Some((stem, extension)) => format!("{}.{}.{}", stem, index, extension),
+        None => format!("{}.{}", filepath, index),
+    };
+    CString16::try_from(filename.as_str()).map_err(|_| uefi::Error::from(uefi::Status::UNSUPPORTED))
+}
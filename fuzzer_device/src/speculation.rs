@@ -0,0 +1,239 @@
+//! Speculation Module
+//!
+//! Executes microcode triads in a speculative window and measures them with the performance
+//! counters, using the patch of the `spec_fuzz` crate. The patch occupies the same patch RAM as
+//! the coverage collection, therefore it is only installed for the duration of a single request.
+
+use crate::controller_connection::ControllerConnection;
+use crate::executor::SampleExecutor;
+use alloc::format;
+use alloc::vec::Vec;
+use core::arch::asm;
+use custom_processing_unit::{
+    apply_hook_patch_func, apply_ldat_read_func, ms_patch_instruction_write_verified,
+    CustomProcessingUnit, HookGuard, DEFAULT_WRITE_VERIFY_RETRIES,
+};
+use fuzzer_data::SpeculationResult;
+use hypervisor::state::GuestRegisters;
+use itertools::Itertools;
+use log::{trace, Level};
+use spec_fuzz::{collect_perf_counters_values, patches, EXPERIMENT_HOOK, SYNCFULL_HOOK};
+use ucode_compiler_dynamic::instruction::Instruction;
+use ucode_compiler_dynamic::listing::format_listing;
+use ucode_compiler_dynamic::sequence_word::SequenceWord;
+use ucode_compiler_dynamic::Triad;
+use x86_perf_counter::PerfEventSpecifier;
+
+/// Installed speculation patch, the hooks and the patch RAM are released when dropped
+pub struct SpeculationPatch {
+    // fields are dropped in declaration order: restore the hook enable state, then zero the hooks
+    _hooks_disabled: HookGuard,
+    custom_processing_unit: CustomProcessingUnit,
+}
+
+impl SpeculationPatch {
+    /// Applies the speculation patch and hooks `rdrand` and `rdseed`
+    ///
+    /// The hooks stay disabled until a measurement enables them.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<SpeculationPatch, custom_processing_unit::Error>` - The installed patch or error
+    pub fn install() -> Result<Self, custom_processing_unit::Error> {
+        let mut cpu = CustomProcessingUnit::new()?;
+        cpu.init()?;
+
+        cpu.patch_space_mut().apply_fixed(&patches::patch::PATCH)?;
+
+        // hook rdrand -> EXPERIMENT
+        let hooked = cpu
+            .hook(
+                apply_hook_patch_func(),
+                EXPERIMENT_HOOK,
+                0x428,
+                patches::patch::LABEL_ENTRY,
+                true,
+            )
+            // hook rdseed -> SYNCFULL
+            .and_then(|_| {
+                cpu.hook(
+                    apply_hook_patch_func(),
+                    SYNCFULL_HOOK,
+                    0x430,
+                    patches::patch::LABEL_SYNCFULL,
+                    true,
+                )
+            });
+        if let Err(err) = hooked {
+            let _ = cpu.patch_space_mut().free(patches::patch::PATCH.addr);
+            return Err(err);
+        }
+
+        Ok(Self {
+            _hooks_disabled: HookGuard::disable_all(),
+            custom_processing_unit: cpu,
+        })
+    }
+}
+
+impl Drop for SpeculationPatch {
+    fn drop(&mut self) {
+        // the patch RAM can be used by the coverage collection again
+        let _ = self
+            .custom_processing_unit
+            .patch_space_mut()
+            .free(patches::patch::PATCH.addr);
+    }
+}
+
+/// Executes a function with the speculation patch installed
+///
+/// The coverage collection of the executor is suspended meanwhile and the hooks of the patch
+/// are removed afterward, so that samples can be executed again within the same session.
+///
+/// # Arguments
+///
+/// * `executor` - The sample executor whose coverage collection is suspended
+/// * `func` - Function to execute with the installed patch
+///
+/// # Returns
+///
+/// * `Result<R, custom_processing_unit::Error>` - Result of the function or error if the patch could not be installed
+pub fn with_speculation_patch<R, F: FnOnce() -> R>(
+    executor: &mut SampleExecutor,
+    func: F,
+) -> Result<R, custom_processing_unit::Error> {
+    executor.with_coverage_suspended(|| {
+        SpeculationPatch::install().map(|patch| {
+            let result = func();
+            drop(patch);
+            result
+        })
+    })
+}
+
+/// Checks if the performance monitoring counters (PMCs) are stable
+///
+/// Executes NOP triads speculatively multiple times and checks if the counter values
+/// remain consistent across executions. Requires an installed [`SpeculationPatch`].
+///
+/// # Arguments
+///
+/// * `udp` - The controller connection to use for logging
+/// * `perf_counter_setup` - Vector of performance event specifiers to monitor
+///
+/// # Returns
+///
+/// * `Vec<bool>` - Whether each performance counter is stable
+pub fn check_if_pmc_stable(
+    udp: &mut ControllerConnection,
+    perf_counter_setup: Vec<PerfEventSpecifier>,
+) -> Vec<bool> {
+    let _ = udp.log_reliable(Level::Trace, "check pmc stable");
+
+    let mut results = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+
+    for _ in 0..10 {
+        let result = execute_speculation(
+            udp,
+            [Instruction::NOP, Instruction::NOP, Instruction::NOP],
+            SequenceWord::NOP,
+            perf_counter_setup.clone(),
+        )
+        .perf_counters;
+
+        for (counter, value) in results.iter_mut().zip(result) {
+            counter.push(value);
+        }
+    }
+
+    results
+        .into_iter()
+        .map(|x| x.iter().all_equal())
+        .collect_vec()
+}
+
+/// Executes a speculative instruction sequence and collects performance data
+///
+/// The first instruction of the triad is written to the speculative window of the patch.
+/// Requires an installed [`SpeculationPatch`].
+///
+/// # Arguments
+///
+/// * `udp` - The controller connection to use for logging
+/// * `triad` - Array of three instructions to execute
+/// * `sequence_word` - The sequence word to use
+/// * `perf_counter_setup` - Vector of performance event specifiers to monitor
+///
+/// # Returns
+///
+/// * `SpeculationResult` - Architectural state before and after the execution and the counter values
+pub fn execute_speculation(
+    udp: &mut ControllerConnection,
+    triad: [Instruction; 3],
+    sequence_word: SequenceWord,
+    perf_counter_setup: Vec<PerfEventSpecifier>,
+) -> SpeculationResult {
+    let _ = udp.log_reliable(
+        Level::Info,
+        format!(
+            "execute speculation_x86: {} {:04x}",
+            triad[0].opcode(),
+            triad[0].assemble()
+        ),
+    );
+    trace!(
+        "Execute speculation_x86:\n{}",
+        format_listing(
+            &[Triad {
+                instructions: triad,
+                sequence_word,
+            }],
+            patches::patch::LABEL_SPECULATIVE_WINDOW
+        )
+    );
+
+    let failed = SpeculationResult {
+        arch_before: GuestRegisters::default(),
+        arch_after: GuestRegisters::default(),
+        perf_counters: Vec::new(),
+        overflowed: Vec::new(),
+    };
+
+    if let Err(e) = sequence_word.assemble() {
+        let _ = udp.log_reliable(
+            Level::Error,
+            format!("Failed to assemble sequence word: {:?}", e),
+        );
+        return failed;
+    }
+
+    // a failed write would measure whatever instruction was left in the speculative window
+    if let Err(err) = ms_patch_instruction_write_verified(
+        apply_ldat_read_func(),
+        patches::patch::LABEL_SPECULATIVE_WINDOW,
+        triad[0].assemble() as usize,
+        DEFAULT_WRITE_VERIFY_RETRIES,
+    ) {
+        let _ = udp.log_reliable(
+            Level::Error,
+            format!("Failed to patch speculative window: {}", err),
+        );
+        return failed;
+    }
+
+    unsafe {
+        asm!("rdseed rax", out("rax")_); // SYNCFULL
+    }
+
+    let mut setup = perf_counter_setup.into_iter().map(Some).collect_vec();
+    setup.resize(4, None);
+    let setup: [Option<PerfEventSpecifier>; 4] = [
+        setup[0].clone(),
+        setup[1].clone(),
+        setup[2].clone(),
+        setup[3].clone(),
+    ];
+
+    collect_perf_counters_values(setup)
+}
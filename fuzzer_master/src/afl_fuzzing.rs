@@ -3,11 +3,11 @@
 //! This module implements integration with the libafl framework.
 
 use crate::database::Database;
-use crate::device_connection::DeviceConnection;
+use crate::device_connection::{DeviceConnection, ThroughputMonitor};
 use crate::fuzzer_node_bridge::FuzzerNodeInterface;
 use crate::net::{net_execute_sample, net_fuzzing_pretext, ExecuteSampleResult};
 use crate::{guarantee_initial_state, power_on, CommandExitResult};
-use fuzzer_data::instruction_corpus::CorpusInstruction;
+use fuzzer_data::instruction_corpus::InstructionCorpus;
 use fuzzer_data::{Code, ExecutionResult, OtaC2DTransport, ReportExecutionProblem};
 use hypervisor::state::VmExitReason;
 use libafl::corpus::{CachedOnDiskCorpus, OnDiskCorpus};
@@ -27,6 +27,7 @@ use libafl_bolts::rands::{Rand, StdRand};
 use libafl_bolts::tuples::{tuple_list, HasConstLen, RefIndexable};
 use libafl_bolts::{nonzero, ErrorBacktrace, HasLen, Named};
 use log::{error, info, warn};
+use rand::SeedableRng;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::collections::BTreeMap;
@@ -72,6 +73,8 @@ pub struct AFLExecutor<'a, OT, I, S> {
     last_reported_exclusion: Option<(Option<u16>, u16)>, // address, times
     /// Timeout for fuzzing operations
     timeout_at: Option<Instant>,
+    /// Sample throughput reported by the device
+    throughput: ThroughputMonitor,
 }
 
 impl<'a, OT, I, S> AFLExecutor<'a, OT, I, S>
@@ -97,6 +100,7 @@ where
             seed,
             timeout_at: timeout_hours
                 .map(|x| Instant::now() + Duration::from_secs((x as u64) * 60 * 60)),
+            throughput: ThroughputMonitor::default(),
         }
     }
 }
@@ -298,6 +302,7 @@ where
             }
             Result::Ok(result) => {
                 let (exit, problems) = result?;
+                self.throughput.update(self.udp);
 
                 let mut afl_exit = match exit.exit {
                     VmExitReason::Exception(_)
@@ -321,7 +326,9 @@ where
                     VmExitReason::VMEntryFailure(_, _)
                     | VmExitReason::Unexpected(_)
                     | VmExitReason::ExternalInterrupt => ExitKind::Crash,
-                    VmExitReason::TimerExpiration => ExitKind::Timeout,
+                    VmExitReason::TimerExpiration | VmExitReason::PreemptionTimeout => {
+                        ExitKind::Timeout
+                    }
                 };
                 if afl_exit != ExitKind::Timeout {
                     if problems.iter().any(|x| {
@@ -587,11 +594,11 @@ impl AsMut<Self> for DifferenceObserver {
 /// a predefined corpus.
 struct CorpusGenerator {
     /// Corpus of instructions to generate from
-    corpus: Vec<CorpusInstruction>,
+    corpus: InstructionCorpus,
 }
 
 impl CorpusGenerator {
-    pub fn new(corpus: Vec<CorpusInstruction>) -> Self {
+    pub fn new(corpus: InstructionCorpus) -> Self {
         CorpusGenerator { corpus }
     }
 }
@@ -601,13 +608,14 @@ where
     S: HasRand,
 {
     fn generate(&mut self, state: &mut S) -> Result<BytesInput, libafl::Error> {
+        let mut random = rand_isaac::Isaac64Rng::seed_from_u64(state.rand_mut().next());
         let mut code = Vec::new();
         while code.len() < 32 {
-            let instruction = self
-                .corpus
-                .get(state.rand_mut().between(0, self.corpus.len() - 1))
-                .expect("couldn't get instruction");
-            code.extend_from_slice(&instruction.bytes);
+            let instruction = self.corpus.sample_weighted(&mut random, 1);
+            if instruction.is_empty() {
+                return Err(libafl::Error::empty("instruction corpus is empty"));
+            }
+            code.extend_from_slice(&instruction);
         }
         Ok(BytesInput::new(code))
     }
@@ -622,7 +630,7 @@ pub async fn afl_main(
     interface: &Arc<FuzzerNodeInterface>,
     db: &mut Database,
     corpus: Option<PathBuf>,
-    initial_corpus: Option<Vec<CorpusInstruction>>,
+    initial_corpus: Option<InstructionCorpus>,
     solution: Option<PathBuf>,
     timeout_hours: Option<u32>,
     disable_feedback: bool,
@@ -0,0 +1,306 @@
+//! Rig Configuration Module
+//!
+//! This module provides a configuration file format for the connection and rig parameters
+//! of a fuzzing setup (fuzzer instrumentor, fuzzer agent, timings). Values given on the
+//! command line take precedence over values from the environment, which take precedence over
+//! values from the configuration file.
+
+use clap::Args;
+use fuzzer_data::auth::OtaKey;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// Default address of the fuzzer instrumentor
+pub const DEFAULT_INSTRUMENTOR: &str = "http://10.83.3.198:8000";
+/// Default address of the fuzzer agent
+pub const DEFAULT_AGENT: &str = "10.83.3.6:4444";
+/// Default time to wait after pressing the power button until the BIOS screen is shown
+pub const DEFAULT_POWER_ON_BOOT_WAIT: Duration = Duration::from_secs(50);
+/// Default time to wait after skipping the BIOS until the UEFI app is running
+pub const DEFAULT_POWER_ON_UEFI_WAIT: Duration = Duration::from_secs(40);
+/// Default number of retransmission attempts for reliable messages
+pub const DEFAULT_RESENT_ATTEMPTS: u8 = 10;
+/// Default timeout for acknowledgment of reliable messages
+pub const DEFAULT_ACK_TIMEOUT: Duration = Duration::from_millis(200);
+/// Default time without progress until missing fragments of chunked messages are requested
+pub const DEFAULT_FRAGMENT_TIMEOUT: Duration = Duration::from_secs(1);
+/// Default time without heartbeat until the device is considered stalled
+pub const DEFAULT_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(20);
+/// Environment variable of the passphrase authenticating the packets, see [`RigConfig::ota_key`]
+pub const OTA_KEY_ENV: &str = "UFUZZ_OTA_KEY";
+
+/// Connection and rig parameters of a fuzzing setup
+///
+/// All values are optional, unset values fall back to the defaults of this module.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RigConfig {
+    /// Address of the fuzzer instrumentor
+    pub instrumentor: Option<String>,
+    /// Address of the fuzzer agent
+    pub agent: Option<String>,
+    /// Base frequency of the host timestamp counter
+    pub p0_freq: Option<f64>,
+    /// Seconds to wait after pressing the power button until the BIOS screen is shown
+    pub power_on_boot_wait_secs: Option<u64>,
+    /// Seconds to wait after skipping the BIOS until the UEFI app is running
+    pub power_on_uefi_wait_secs: Option<u64>,
+    /// Number of retransmission attempts for reliable messages
+    pub resent_attempts: Option<u8>,
+    /// Timeout for acknowledgment of reliable messages in milliseconds
+    pub ack_timeout_ms: Option<u64>,
+    /// Passphrase of the pre-shared key authenticating the packets, must match the
+    /// `UFUZZ_OTA_KEY` the agent was built with
+    pub ota_key: Option<String>,
+}
+
+/// Command line flags of the rig parameters, see [`RigConfig`]
+#[derive(Args, Debug, Clone, Default)]
+pub struct RigConfigArgs {
+    /// Address of the fuzzer instrumentor [default: http://10.83.3.198:8000]
+    #[arg(long)]
+    pub instrumentor: Option<String>,
+    /// Address of the fuzzer agent [default: 10.83.3.6:4444]
+    #[arg(long)]
+    pub agent: Option<String>,
+    /// Base frequency of the host timestamp counter
+    #[arg(long)]
+    pub p0_freq: Option<f64>,
+    /// Seconds to wait after pressing the power button until the BIOS screen is shown [default: 50]
+    #[arg(long)]
+    pub power_on_boot_wait_secs: Option<u64>,
+    /// Seconds to wait after skipping the BIOS until the UEFI app is running [default: 40]
+    #[arg(long)]
+    pub power_on_uefi_wait_secs: Option<u64>,
+    /// Number of retransmission attempts for reliable messages [default: 10]
+    #[arg(long)]
+    pub resent_attempts: Option<u8>,
+    /// Timeout for acknowledgment of reliable messages in milliseconds [default: 200]
+    #[arg(long)]
+    pub ack_timeout_ms: Option<u64>,
+    /// Passphrase authenticating the packets, must match the `UFUZZ_OTA_KEY` of the agent build.
+    /// Visible in the process list, prefer the configuration file or the `UFUZZ_OTA_KEY`
+    /// environment variable
+    #[arg(long)]
+    pub ota_key: Option<String>,
+}
+
+impl RigConfigArgs {
+    /// Builds the configuration of the given flags, unset flags are left unset
+    ///
+    /// # Returns
+    ///
+    /// * `RigConfig` - Configuration to override the configuration file with
+    pub fn to_config(&self) -> RigConfig {
+        RigConfig {
+            instrumentor: self.instrumentor.clone(),
+            agent: self.agent.clone(),
+            p0_freq: self.p0_freq,
+            power_on_boot_wait_secs: self.power_on_boot_wait_secs,
+            power_on_uefi_wait_secs: self.power_on_uefi_wait_secs,
+            resent_attempts: self.resent_attempts,
+            ack_timeout_ms: self.ack_timeout_ms,
+            ota_key: self.ota_key.clone(),
+        }
+    }
+}
+
+impl RigConfig {
+    /// Loads a rig configuration from a TOML file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the configuration file
+    ///
+    /// # Returns
+    ///
+    /// * `io::Result<RigConfig>` - Loaded configuration or error
+    pub fn from_file<A: AsRef<Path>>(path: A) -> io::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        toml::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Builds the configuration of the environment, currently only the passphrase of
+    /// [`OTA_KEY_ENV`]
+    ///
+    /// # Returns
+    ///
+    /// * `RigConfig` - Configuration to override the configuration file with
+    pub fn from_env() -> Self {
+        RigConfig {
+            ota_key: std::env::var(OTA_KEY_ENV).ok(),
+            ..Default::default()
+        }
+    }
+
+    /// Overrides the values of this configuration with all values set in `other`
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - Configuration taking precedence, e.g. given on the command line
+    ///
+    /// # Returns
+    ///
+    /// * `RigConfig` - Merged configuration
+    pub fn overridden_by(self, other: RigConfig) -> RigConfig {
+        RigConfig {
+            instrumentor: other.instrumentor.or(self.instrumentor),
+            agent: other.agent.or(self.agent),
+            p0_freq: other.p0_freq.or(self.p0_freq),
+            power_on_boot_wait_secs: other
+                .power_on_boot_wait_secs
+                .or(self.power_on_boot_wait_secs),
+            power_on_uefi_wait_secs: other
+                .power_on_uefi_wait_secs
+                .or(self.power_on_uefi_wait_secs),
+            resent_attempts: other.resent_attempts.or(self.resent_attempts),
+            ack_timeout_ms: other.ack_timeout_ms.or(self.ack_timeout_ms),
+            ota_key: other.ota_key.or(self.ota_key),
+        }
+    }
+
+    /// Address of the fuzzer instrumentor
+    pub fn instrumentor(&self) -> &str {
+        self.instrumentor.as_deref().unwrap_or(DEFAULT_INSTRUMENTOR)
+    }
+
+    /// Address of the fuzzer agent
+    pub fn agent(&self) -> &str {
+        self.agent.as_deref().unwrap_or(DEFAULT_AGENT)
+    }
+
+    /// Base frequency of the host timestamp counter
+    pub fn p0_freq(&self) -> f64 {
+        self.p0_freq.unwrap_or(crate::P0_FREQ)
+    }
+
+    /// Time to wait after pressing the power button until the BIOS screen is shown
+    pub fn power_on_boot_wait(&self) -> Duration {
+        self.power_on_boot_wait_secs
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_POWER_ON_BOOT_WAIT)
+    }
+
+    /// Time to wait after skipping the BIOS until the UEFI app is running
+    pub fn power_on_uefi_wait(&self) -> Duration {
+        self.power_on_uefi_wait_secs
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_POWER_ON_UEFI_WAIT)
+    }
+
+    /// Number of retransmission attempts for reliable messages
+    pub fn resent_attempts(&self) -> u8 {
+        self.resent_attempts.unwrap_or(DEFAULT_RESENT_ATTEMPTS)
+    }
+
+    /// Timeout for acknowledgment of reliable messages
+    pub fn ack_timeout(&self) -> Duration {
+        self.ack_timeout_ms
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_ACK_TIMEOUT)
+    }
+
+    /// Pre-shared key authenticating the packets, packets are unauthenticated if not set
+    pub fn ota_key(&self) -> Option<OtaKey> {
+        self.ota_key.as_deref().map(OtaKey::from_passphrase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{RigConfig, RigConfigArgs, OTA_KEY_ENV};
+    use clap::Parser;
+    use fuzzer_data::auth::OtaKey;
+    use std::time::Duration;
+
+    #[derive(Parser)]
+    struct TestArgs {
+        #[command(flatten)]
+        rig: RigConfigArgs,
+    }
+
+    #[test]
+    pub fn test_config_flag_precedence() {
+        let path = std::env::temp_dir().join("fuzzer_master_test_rig_config.toml");
+        std::fs::write(
+            &path,
+            "instrumentor = \"http://10.0.0.1:8000\"\n\
+             agent = \"10.0.0.2:4444\"\n\
+             p0_freq = 1.0e9\n\
+             power_on_boot_wait_secs = 30\n\
+             ack_timeout_ms = 500\n\
+             ota_key = \"lab\"\n",
+        )
+        .unwrap();
+        let file = RigConfig::from_file(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let flags = RigConfig {
+            agent: Some("10.0.0.3:4444".to_string()),
+            ..Default::default()
+        };
+        let config = file.overridden_by(flags);
+
+        assert_eq!(config.instrumentor(), "http://10.0.0.1:8000");
+        assert_eq!(config.agent(), "10.0.0.3:4444");
+        assert_eq!(config.p0_freq(), 1.0e9);
+        assert_eq!(config.power_on_boot_wait(), Duration::from_secs(30));
+        assert_eq!(config.ack_timeout(), Duration::from_millis(500));
+        assert_eq!(config.resent_attempts(), 10);
+        assert_eq!(config.ota_key(), Some(OtaKey::from_passphrase("lab")));
+        assert_eq!(RigConfig::default().ota_key(), None);
+    }
+
+    #[test]
+    pub fn test_config_flags() {
+        let args = TestArgs::parse_from([
+            "fuzz_master",
+            "--power-on-boot-wait-secs",
+            "70",
+            "--power-on-uefi-wait-secs",
+            "20",
+            "--resent-attempts",
+            "3",
+            "--ack-timeout-ms",
+            "1000",
+        ]);
+        let file = RigConfig {
+            power_on_boot_wait_secs: Some(30),
+            resent_attempts: Some(5),
+            agent: Some("10.0.0.2:4444".to_string()),
+            ..Default::default()
+        };
+        let config = file.overridden_by(args.rig.to_config());
+
+        assert_eq!(config.power_on_boot_wait(), Duration::from_secs(70));
+        assert_eq!(config.power_on_uefi_wait(), Duration::from_secs(20));
+        assert_eq!(config.resent_attempts(), 3);
+        assert_eq!(config.ack_timeout(), Duration::from_millis(1000));
+        assert_eq!(config.agent(), "10.0.0.2:4444");
+
+        let args = TestArgs::parse_from(["fuzz_master"]);
+        assert_eq!(args.rig.to_config(), RigConfig::default());
+    }
+
+    #[test]
+    pub fn test_config_ota_key_from_env() {
+        let file = RigConfig {
+            ota_key: Some("file".to_string()),
+            ..Default::default()
+        };
+
+        std::env::set_var(OTA_KEY_ENV, "environment");
+        let env = RigConfig::from_env();
+        std::env::remove_var(OTA_KEY_ENV);
+        assert_eq!(
+            file.clone().overridden_by(env.clone()).ota_key(),
+            Some(OtaKey::from_passphrase("environment"))
+        );
+
+        let args = TestArgs::parse_from(["fuzz_master", "--ota-key", "flag"]);
+        let config = file.overridden_by(env).overridden_by(args.rig.to_config());
+        assert_eq!(config.ota_key(), Some(OtaKey::from_passphrase("flag")));
+    }
+}
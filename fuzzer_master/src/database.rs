@@ -3,11 +3,12 @@
 //! This module provides functionality for storing and managing fuzzing results and
 //! performance data.
 
+use data_types::addresses::{Address, UCInstructionAddress};
 use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
 use flate2::Compression;
-use fuzzer_data::genetic_pool::GeneticSampleRating;
-use fuzzer_data::{Code, ExecutionResult, ReportExecutionProblem};
+use fuzzer_data::genetic_pool::{GeneticSampleRating, PoolSnapshot, SampleProvenance};
+use fuzzer_data::{Code, CrashPhase, ExecutionResult, ReportExecutionProblem};
 use hypervisor::state::{VmExitReason, VmState};
 use itertools::Itertools;
 use lazy_static::lazy_static;
@@ -23,6 +24,9 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::io;
+use ucode_compiler_dynamic::instruction::Instruction;
+use ucode_compiler_dynamic::sequence_word::SequenceWord;
+use x86_perf_counter::PerfEventSpecifier;
 
 lazy_static! {
     /// Regular expression for matching numbers in text
@@ -68,6 +72,26 @@ pub enum CodeEvent {
     },
     /// Access to coverage collection area
     AccessCoverageArea,
+    /// Performance counter reported an impossible value
+    PmcAnomaly {
+        /// Index of the performance counter
+        counter_index: u8,
+        /// Event the performance counter was configured with
+        event: PerfEventSpecifier,
+        /// Observed counter value
+        observed: u64,
+        /// Inclusive range of plausible counter values
+        expected_range: (u64, u64),
+    },
+    /// Executions with two randomized initial register files diverged unexplainably
+    InitialStateSensitivity {
+        /// Seeds of the initial register files of both executions
+        state_seeds: (u64, u64),
+        /// Exit reasons of both executions, set if they differ
+        exits: Option<(VmExitReason, VmExitReason)>,
+        /// Registers with unexplained differences
+        registers: Vec<String>,
+    },
 }
 
 /// Information about due to which fuzzing run a code sample was found
@@ -116,8 +140,42 @@ pub struct CodeResult {
     /// Timestamps when this code was found
     #[serde(default)]
     pub found_on: Vec<Timestamp>,
+    /// How the code was generated, if known
+    #[serde(default)]
+    pub provenance: Option<SampleProvenance>,
+}
+
+/// Events reported during the speculative execution of a triad
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SpeculationEvents {
+    /// Instructions of the executed triad
+    pub triad: [Instruction; 3],
+    /// Sequence word of the executed triad
+    pub sequence_word: SequenceWord,
+    /// Events that occurred during execution
+    pub events: Vec<ReportExecutionProblem>,
+    /// When the events were reported
+    pub found_on: Timestamp,
+}
+
+/// Crash of the device while executing a sample, reported by the device after rebooting
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CrashRecord {
+    /// Fingerprint of the sample, see [`SampleProvenance::fingerprint`]
+    pub sample_fingerprint: u64,
+    /// Code of the sample, if it was the last sample sent to the device
+    pub code: Option<Code>,
+    /// Phase of the execution the device crashed in
+    pub phase: CrashPhase,
+    /// Crash counter of the device, including this crash
+    pub crash_count: u32,
+    /// When the crash was reported
+    pub found_on: Timestamp,
 }
 
+/// Number of crashes after which a sample is blacklisted
+pub const CRASH_BLACKLIST_THRESHOLD: usize = 3;
+
 /// Timestamp for tracking when events occur
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, Ord, PartialOrd, PartialEq, Eq)]
 #[repr(transparent)]
@@ -183,6 +241,21 @@ pub struct DatabaseData {
     /// Device performance measurements
     #[serde(default)]
     pub device_performance: MeasurementCollection<f64>,
+    /// Coverage imported from external sources (address -> hit count)
+    #[serde(default)]
+    pub coverage_baseline: BTreeMap<u16, u64>,
+    /// Events reported during speculative execution
+    #[serde(default)]
+    pub speculation_events: Vec<SpeculationEvents>,
+    /// Latest genetic pool of the breeding loop, to resume it after a restart
+    #[serde(default)]
+    pub pool_snapshot: Option<PoolSnapshot>,
+    /// Crashes of the device reported after rebooting
+    #[serde(default)]
+    pub crashes: Vec<CrashRecord>,
+    /// Fingerprints of samples that crashed the device repeatedly and are not executed anymore
+    #[serde(default)]
+    pub blacklisted_samples: BTreeSet<u64>,
 }
 
 impl DatabaseData {
@@ -196,6 +269,9 @@ impl DatabaseData {
                 Some(existing) => {
                     existing.found_at.extend(result.found_at);
                     existing.found_on.extend(result.found_on);
+                    if existing.provenance.is_none() {
+                        existing.provenance = result.provenance;
+                    }
                     for (cov_point, count) in result.coverage {
                         if count > *existing.coverage.get(&cov_point).unwrap_or(&0) {
                             existing.coverage.insert(cov_point, count);
@@ -210,6 +286,39 @@ impl DatabaseData {
         self.device_performance
             .data
             .extend(other.device_performance.data);
+
+        for (address, count) in other.coverage_baseline {
+            *self.coverage_baseline.entry(address).or_default() += count;
+        }
+
+        self.speculation_events.extend(other.speculation_events);
+        self.crashes.extend(other.crashes);
+        self.blacklisted_samples.extend(other.blacklisted_samples);
+
+        if let Some(snapshot) = other.pool_snapshot {
+            if self
+                .pool_snapshot
+                .as_ref()
+                .map_or(true, |x| x.generation < snapshot.generation)
+            {
+                self.pool_snapshot = Some(snapshot);
+            }
+        }
+    }
+
+    /// Accumulates the coverage of all results and the coverage baseline
+    ///
+    /// # Returns
+    ///
+    /// * `BTreeMap<u16, u64>` - Map of covered address to total hit count
+    pub fn accumulated_coverage(&self) -> BTreeMap<u16, u64> {
+        let mut coverage = self.coverage_baseline.clone();
+        for result in &self.results {
+            for (address, count) in &result.coverage {
+                *coverage.entry(*address).or_default() += *count as u64;
+            }
+        }
+        coverage
     }
 }
 
@@ -552,10 +661,201 @@ impl Database {
                 ReportExecutionProblem::AccessCoverageArea => {
                     entry.events.push(CodeEvent::AccessCoverageArea);
                 }
+                ReportExecutionProblem::PmcAnomaly {
+                    counter_index,
+                    event,
+                    observed,
+                    expected_range,
+                } => {
+                    entry.events.push(CodeEvent::PmcAnomaly {
+                        counter_index,
+                        event,
+                        observed,
+                        expected_range,
+                    });
+                }
+                ReportExecutionProblem::InitialStateSensitivity {
+                    state_seeds,
+                    exits,
+                    registers,
+                } => {
+                    entry.events.push(CodeEvent::InitialStateSensitivity {
+                        state_seeds,
+                        exits,
+                        registers,
+                    });
+                }
             }
         }
     }
 
+    /// Adds the events reported during a speculative execution to the database
+    ///
+    /// # Arguments
+    ///
+    /// * `triad` - Instructions of the executed triad
+    /// * `sequence_word` - Sequence word of the executed triad
+    /// * `events` - Events that occurred
+    pub fn push_speculation_events(
+        &mut self,
+        triad: [Instruction; 3],
+        sequence_word: SequenceWord,
+        events: Vec<ReportExecutionProblem>,
+    ) {
+        if events.is_empty() {
+            return;
+        }
+
+        self.data.speculation_events.push(SpeculationEvents {
+            triad,
+            sequence_word,
+            events,
+            found_on: Timestamp::now(),
+        });
+        self.mark_dirty();
+    }
+
+    /// Stores the latest snapshot of the genetic pool
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshot` - Snapshot of the genetic pool, `None` once the breeding run finished
+    pub fn set_pool_snapshot(&mut self, snapshot: Option<PoolSnapshot>) {
+        self.data.pool_snapshot = snapshot;
+        self.mark_dirty();
+    }
+
+    /// Returns the latest snapshot of the genetic pool, if any
+    pub fn pool_snapshot(&self) -> Option<&PoolSnapshot> {
+        self.data.pool_snapshot.as_ref()
+    }
+
+    /// Records a crash of the device reported after rebooting
+    ///
+    /// The sample is blacklisted once it crashed the device [`CRASH_BLACKLIST_THRESHOLD`] times.
+    ///
+    /// # Arguments
+    ///
+    /// * `crash` - The reported crash
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - Whether the sample was blacklisted by this crash
+    pub fn record_crash(&mut self, crash: CrashRecord) -> bool {
+        let fingerprint = crash.sample_fingerprint;
+        self.data.crashes.push(crash);
+        self.mark_dirty();
+
+        let crashes = self
+            .data
+            .crashes
+            .iter()
+            .filter(|x| x.sample_fingerprint == fingerprint)
+            .count();
+        crashes >= CRASH_BLACKLIST_THRESHOLD && self.data.blacklisted_samples.insert(fingerprint)
+    }
+
+    /// Checks if a sample crashed the device too often to be executed again
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - Code of the sample
+    pub fn is_sample_blacklisted(&self, code: &[u8]) -> bool {
+        self.data
+            .blacklisted_samples
+            .contains(&SampleProvenance::fingerprint(code))
+    }
+
+    /// Records how a code sample was generated
+    ///
+    /// The provenance of the first run that found the code is kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - Code sample the provenance belongs to
+    /// * `provenance` - Provenance of the code sample
+    pub fn set_provenance(&mut self, code: &[u8], provenance: SampleProvenance) {
+        if let Some(entry) = self
+            .data
+            .results
+            .iter_mut()
+            .find(|x| x.code == code && x.provenance.is_none())
+        {
+            entry.provenance = Some(provenance);
+            self.dirty = true;
+        }
+    }
+
+    /// Exports the covered addresses as a flat list, one address per line
+    ///
+    /// Each line has the format `<address in hex> <hit count>`, sorted by address.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file to write
+    ///
+    /// # Returns
+    ///
+    /// * `io::Result<()>` - Success or error
+    pub fn export_coverage<A: AsRef<Path>>(&self, path: A) -> io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        let mut writer = std::io::BufWriter::new(file);
+
+        for (address, count) in self.data.accumulated_coverage() {
+            writeln!(
+                writer,
+                "{} {}",
+                UCInstructionAddress::from_const(address as usize),
+                count
+            )?;
+        }
+
+        writer.flush()
+    }
+
+    /// Imports a flat coverage list (see [`Database::export_coverage`]) as coverage baseline
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file to read
+    ///
+    /// # Returns
+    ///
+    /// * `io::Result<usize>` - Number of imported addresses or error
+    pub fn import_coverage<A: AsRef<Path>>(&mut self, path: A) -> io::Result<usize> {
+        let content = std::fs::read_to_string(path)?;
+        let mut imported = 0;
+
+        for line in content.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid coverage line: {line:?}"),
+                )
+            };
+
+            let mut parts = line.split_whitespace();
+            let address = parts.next().ok_or_else(invalid)?;
+            let address = address
+                .parse::<UCInstructionAddress>()
+                .map_err(|_| invalid())?
+                .address() as u16;
+            let count = match parts.next() {
+                Some(count) => count.parse::<u64>().map_err(|_| invalid())?,
+                None => 1,
+            };
+
+            *self.data.coverage_baseline.entry(address).or_default() += count;
+            imported += 1;
+        }
+
+        if imported > 0 {
+            self.mark_dirty();
+        }
+
+        Ok(imported)
+    }
+
     /// Updates performance measurements
     fn update_perf_values(&mut self) {
         let measurements = performance_timing::measurements::mm_instance()
@@ -625,3 +925,67 @@ impl<W: Write> Write for ProgressWriter<W> {
         self.downstream.flush()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::database::{CodeResult, CrashRecord, Database, Timestamp};
+    use fuzzer_data::genetic_pool::SampleProvenance;
+    use fuzzer_data::CrashPhase;
+    use std::collections::BTreeMap;
+
+    #[test]
+    pub fn test_coverage_export_import() {
+        let path = std::env::temp_dir().join("fuzzer_master_test_coverage_export.txt");
+
+        let mut source = Database::empty("source.json");
+        source.data.results.push(CodeResult {
+            coverage: BTreeMap::from([(0x10, 2), (0x7c00, 1)]),
+            ..Default::default()
+        });
+        source.data.results.push(CodeResult {
+            code: vec![0x90],
+            coverage: BTreeMap::from([(0x10, 3), (0x2, 1)]),
+            ..Default::default()
+        });
+        source.export_coverage(&path).unwrap();
+
+        let mut target = Database::empty("target.json");
+        assert_eq!(target.import_coverage(&path).unwrap(), 3);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(
+            target.data.coverage_baseline,
+            BTreeMap::from([(0x2, 1), (0x10, 5), (0x7c00, 1)])
+        );
+        assert_eq!(
+            target.data.accumulated_coverage(),
+            source.data.accumulated_coverage()
+        );
+    }
+
+    #[test]
+    pub fn test_crash_blacklist() {
+        let mut database = Database::empty("crashes.json");
+        let code = vec![0x0F, 0x0B];
+        let crash = |code: &[u8], crash_count| CrashRecord {
+            sample_fingerprint: SampleProvenance::fingerprint(code),
+            code: Some(code.to_vec()),
+            phase: CrashPhase::Execution,
+            crash_count,
+            found_on: Timestamp::now(),
+        };
+
+        assert!(!database.record_crash(crash(&code, 1)));
+        assert!(!database.record_crash(crash(&[0x90], 2)));
+        assert!(!database.record_crash(crash(&code, 3)));
+        assert!(!database.is_sample_blacklisted(&code));
+
+        assert!(database.record_crash(crash(&code, 4)));
+        assert!(database.is_sample_blacklisted(&code));
+        assert!(!database.is_sample_blacklisted(&[0x90]));
+
+        // already blacklisted
+        assert!(!database.record_crash(crash(&code, 5)));
+        assert_eq!(database.data.crashes.len(), 5);
+    }
+}
@@ -7,9 +7,18 @@
 //! between the fuzzer master and devices, handling all the low-level details
 //! of network communication and protocol management.
 
+use crate::config::{
+    DEFAULT_ACK_TIMEOUT, DEFAULT_FRAGMENT_TIMEOUT, DEFAULT_HEARTBEAT_TIMEOUT,
+    DEFAULT_RESENT_ATTEMPTS,
+};
+use fuzzer_data::auth::OtaKey;
+use fuzzer_data::fragments::{
+    FragmentAssembler, FragmentError, FragmentPoll, FragmentSendBuffer, FragmentStatus,
+};
 use fuzzer_data::{
-    Ota, OtaC2D, OtaC2DTransport, OtaC2DUnreliable, OtaD2C, OtaD2CTransport, OtaD2CUnreliable,
-    OtaPacket, MAX_FRAGMENT_SIZE, MAX_PAYLOAD_SIZE,
+    encode_payload, ConnectionStats, CoverageGranularity, ExecutionConfig, LinkStats, Ota, OtaC2D,
+    OtaC2DTransport, OtaC2DUnreliable, OtaD2C, OtaD2CTransport, OtaD2CUnreliable, OtaDecodeError,
+    OtaPacket, MAX_FRAGMENT_SIZE, OTA_MESSAGE_VERSION,
 };
 use log::{debug, error, info, trace, warn, Level};
 use performance_timing::{track_time, TimeMeasurement};
@@ -19,7 +28,8 @@ use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
 use std::io;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::net::UdpSocket;
 use tokio::sync::mpsc::Receiver;
@@ -39,6 +49,13 @@ pub enum DeviceConnectionError {
     MessageTooLong(usize),
     /// No acknowledgment received for reliable message
     NoAckReceived,
+    /// The device speaks a different protocol version, see [`OTA_MESSAGE_VERSION`]
+    ProtocolVersionMismatch {
+        /// Protocol version of the controller
+        controller: u16,
+        /// Protocol version of the device, `None` if the device predates the handshake
+        device: Option<u16>,
+    },
 }
 
 impl DeviceConnectionError {
@@ -56,6 +73,22 @@ impl Display for DeviceConnectionError {
             DeviceConnectionError::Eof => write!(f, "EOF"),
             DeviceConnectionError::MessageTooLong(len) => write!(f, "Message too long: {}", len),
             DeviceConnectionError::NoAckReceived => write!(f, "No ack received"),
+            DeviceConnectionError::ProtocolVersionMismatch {
+                controller,
+                device: Some(device),
+            } => write!(
+                f,
+                "Protocol version mismatch: controller {}, device {}",
+                controller, device
+            ),
+            DeviceConnectionError::ProtocolVersionMismatch {
+                controller,
+                device: None,
+            } => write!(
+                f,
+                "Protocol version mismatch: controller {}, device predates the handshake",
+                controller
+            ),
         }
     }
 }
@@ -74,6 +107,109 @@ impl From<String> for DeviceConnectionError {
     }
 }
 
+/// Health status of the fuzzing agent, as reported by its latest heartbeat
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeartbeatStatus {
+    /// Uptime of the agent in milliseconds
+    pub uptime_ms: u64,
+    /// Free memory of the agent in bytes
+    pub free_heap_bytes: u64,
+    /// Number of samples executed by the agent since boot
+    pub samples_executed: u64,
+    /// Last error logged by the agent
+    pub last_error: Option<String>,
+    /// Health statistics of the connection of the agent
+    pub connection_stats: ConnectionStats,
+    /// Time the heartbeat was received
+    pub received_at: Instant,
+    /// Round trip time of the latest acknowledged reliable message
+    pub rtt: Option<Duration>,
+}
+
+impl HeartbeatStatus {
+    /// Calculates the sample throughput of the device since an earlier heartbeat
+    ///
+    /// # Arguments
+    ///
+    /// * `earlier` - An earlier heartbeat of the same device
+    ///
+    /// # Returns
+    ///
+    /// * `Option<f64>` - Samples per second, None if the device rebooted in between
+    pub fn throughput_since(&self, earlier: &HeartbeatStatus) -> Option<f64> {
+        if self.uptime_ms <= earlier.uptime_ms || self.samples_executed < earlier.samples_executed {
+            return None;
+        }
+        let samples = (self.samples_executed - earlier.samples_executed) as f64;
+        Some(samples * 1000.0 / (self.uptime_ms - earlier.uptime_ms) as f64)
+    }
+}
+
+/// Tracks the sample throughput reported by device heartbeats and logs its degradation
+#[derive(Debug, Default)]
+pub struct ThroughputMonitor {
+    /// Heartbeat of the previous update
+    last: Option<HeartbeatStatus>,
+    /// Highest throughput observed in samples per second
+    best: f64,
+}
+
+impl ThroughputMonitor {
+    /// Fraction of the best throughput below which a degradation is logged
+    const DEGRADATION_THRESHOLD: f64 = 0.5;
+
+    /// Updates the monitor with the latest heartbeat of the device
+    ///
+    /// # Arguments
+    ///
+    /// * `net` - Network connection to the device
+    pub fn update(&mut self, net: &DeviceConnection) {
+        let Some(heartbeat) = net.heartbeat() else {
+            return;
+        };
+        if let Some(last) = &self.last {
+            if last.received_at == heartbeat.received_at {
+                return;
+            }
+            match heartbeat.throughput_since(last) {
+                Some(throughput) => {
+                    debug!(
+                        "Device throughput: {:.2} samples/s, rtt {:?}, {} bytes free",
+                        throughput, heartbeat.rtt, heartbeat.free_heap_bytes
+                    );
+                    if throughput < self.best * Self::DEGRADATION_THRESHOLD {
+                        warn!(
+                            "Device throughput degraded: {:.2} samples/s (best {:.2}), error {:?}",
+                            throughput, self.best, heartbeat.last_error
+                        );
+                    }
+                    self.best = self.best.max(throughput);
+
+                    let stats = &heartbeat.connection_stats;
+                    let dropped = stats
+                        .link
+                        .dropped
+                        .saturating_sub(last.connection_stats.link.dropped);
+                    let failures = stats
+                        .reassembly_failures
+                        .saturating_sub(last.connection_stats.reassembly_failures);
+                    if dropped > 0 || failures > 0 {
+                        warn!(
+                            "Device link degraded: {} packets dropped, {} reassembly failures ({})",
+                            dropped, failures, stats
+                        );
+                    }
+                }
+                None => {
+                    info!("Device rebooted, resetting throughput statistics");
+                    self.best = 0.0;
+                }
+            }
+        }
+        self.last = Some(heartbeat);
+    }
+}
+
 /// Manages the connection to the fuzzing agent
 ///
 /// This structure handles all aspects of communication with a device,
@@ -101,6 +237,28 @@ pub struct DeviceConnection {
     sequence_number_tx: u64,
     /// Current session identifier
     session: u16,
+    /// Link quality statistics of this connection
+    link_stats: LinkStats,
+    /// Reassembly of received chunked transports
+    assembler: FragmentAssembler,
+    /// Sent chunked transports, kept to serve retransmission requests
+    send_buffer: FragmentSendBuffer,
+    /// Creation time of the connection, the epoch of the fragment timestamps
+    epoch: Instant,
+    /// Latest heartbeat of the device, updated by the receiver thread
+    heartbeat: Arc<Mutex<Option<HeartbeatStatus>>>,
+    /// Time without heartbeat until the device is considered stalled
+    heartbeat_timeout: Duration,
+    /// Round trip time of the latest acknowledged reliable message
+    last_rtt: Option<Duration>,
+    /// Execution configuration applied to the device whenever it is (re)connected
+    execution_config: Option<ExecutionConfig>,
+    /// Coverage granularity applied to the device whenever it is (re)connected
+    coverage_granularity: Option<CoverageGranularity>,
+    /// Pre-shared key authenticating the packets
+    key: Option<OtaKey>,
+    /// Number of received packets that failed authentication, counted by the receiver thread
+    unauthenticated: Arc<AtomicU64>,
 }
 
 impl DeviceConnection {
@@ -109,12 +267,14 @@ impl DeviceConnection {
     /// # Arguments
     ///
     /// * `target` - Socket address of the target device
+    /// * `key` - Pre-shared key authenticating the packets, `None` to communicate unauthenticated
     ///
     /// # Returns
     ///
     /// * `Result<DeviceConnection, DeviceConnectionError>` - New connection or error
     pub async fn new<A: ToSocketAddrs>(
         target: A,
+        key: Option<OtaKey>,
     ) -> Result<DeviceConnection, DeviceConnectionError> {
         let address = target
             .to_socket_addrs()?
@@ -128,18 +288,41 @@ impl DeviceConnection {
         .await?;
         socket.connect(address).await?;
 
+        Self::from_socket(socket, key).await
+    }
+
+    /// Creates a new connection to a fuzzing agent over an already connected socket
+    ///
+    /// # Arguments
+    ///
+    /// * `socket` - UDP socket connected to the device
+    /// * `key` - Pre-shared key authenticating the packets, `None` to communicate unauthenticated
+    ///
+    /// # Returns
+    ///
+    /// * `Result<DeviceConnection, DeviceConnectionError>` - New connection or error
+    pub async fn from_socket(
+        socket: UdpSocket,
+        key: Option<OtaKey>,
+    ) -> Result<DeviceConnection, DeviceConnectionError> {
         let socket = Arc::new(socket);
         let socket_clone = Arc::clone(&socket);
 
         let (sender, receiver) = tokio::sync::mpsc::channel(100);
 
+        let heartbeat = Arc::new(Mutex::new(None));
+        let heartbeat_clone = Arc::clone(&heartbeat);
+
+        let unauthenticated = Arc::new(AtomicU64::new(0));
+        let unauthenticated_clone = Arc::clone(&unauthenticated);
+
         let session = random();
         println!("Session: {}", session);
 
         {
             // ice-breaker
             let send_buf = OtaC2D::Unreliable(OtaC2DUnreliable::NOP)
-                .serialize()
+                .serialize_with_key(key.as_ref())
                 .expect("must work");
 
             for _ in 0..10 {
@@ -156,7 +339,7 @@ impl DeviceConnection {
 
             // ice-breaker
             let ice_breaker_send_buf = OtaC2D::Unreliable(OtaC2DUnreliable::NOP)
-                .serialize()
+                .serialize_with_key(key.as_ref())
                 .expect("must work");
 
             let mut last_ice_break = Instant::now();
@@ -175,8 +358,20 @@ impl DeviceConnection {
 
                 match socket_clone.recv(&mut buffer).await {
                     Ok(count) => {
-                        let data: OtaD2C = match OtaD2C::deserialize(&buffer[..count]) {
+                        let data = OtaD2C::deserialize_with_key(&buffer[..count], key.as_ref());
+                        let data: OtaD2C = match data {
                             Ok(d) => d,
+                            Err(e @ OtaDecodeError::ChecksumMismatch { .. }) => {
+                                // drop without acknowledgement, the device retransmits the packet
+                                warn!("Dropped corrupted packet: {}", e);
+                                continue;
+                            }
+                            Err(e) if e.is_authentication_error() => {
+                                // not from the device, or corrupted and retransmitted by the device
+                                warn!("Dropped unauthenticated packet: {}", e);
+                                unauthenticated_clone.fetch_add(1, Ordering::Relaxed);
+                                continue;
+                            }
                             Err(e) => {
                                 error!("Error parsing JSON: {:?}", e);
                                 continue;
@@ -184,7 +379,9 @@ impl DeviceConnection {
                         };
 
                         if let Some(ack) = data.ack() {
-                            let string = &OtaC2D::Unreliable(ack).serialize().expect("must work");
+                            let string = &OtaC2D::Unreliable(ack)
+                                .serialize_with_key(key.as_ref())
+                                .expect("must work");
                             if let Err(err) = socket_clone.send(&string).await {
                                 error!("Failed to send ack: {:?}", err);
                             }
@@ -200,6 +397,7 @@ impl DeviceConnection {
                             if let OtaD2CTransport::ResetSession = content {
                                 trace!("Reset network session");
                                 rx_sequence_number = 0;
+                                *heartbeat_clone.lock().expect("not poisoned") = None;
                                 last_packet = Instant::now();
                                 continue;
                             } else if session != *packet_session {
@@ -238,6 +436,33 @@ impl DeviceConnection {
                             continue; // do not send to frontend
                         }
 
+                        if let Ota::Unreliable(OtaD2CUnreliable::Heartbeat {
+                            uptime_ms,
+                            free_heap_bytes,
+                            samples_executed,
+                            last_error,
+                            connection_stats,
+                        }) = data
+                        {
+                            trace!(
+                                "Heartbeat: uptime {}ms, {} samples executed",
+                                uptime_ms,
+                                samples_executed
+                            );
+                            *heartbeat_clone.lock().expect("not poisoned") =
+                                Some(HeartbeatStatus {
+                                    uptime_ms,
+                                    free_heap_bytes,
+                                    samples_executed,
+                                    last_error,
+                                    connection_stats,
+                                    received_at: Instant::now(),
+                                    rtt: None,
+                                });
+
+                            continue; // do not send to frontend
+                        }
+
                         if let Err(_) = sender.send(data).await {
                             break; // shutdown
                         }
@@ -258,15 +483,106 @@ impl DeviceConnection {
             receiver_thread: Some(thread),
             receiver,
             virtual_receive_queue: VecDeque::new(),
-            ack_timeout: Duration::from_millis(200),
-            fragment_timeout: Duration::from_secs(1),
-            resent_attempts: 10,
+            ack_timeout: DEFAULT_ACK_TIMEOUT,
+            fragment_timeout: DEFAULT_FRAGMENT_TIMEOUT,
+            resent_attempts: DEFAULT_RESENT_ATTEMPTS,
 
             sequence_number_tx: 0,
             session,
+            link_stats: LinkStats::default(),
+            assembler: FragmentAssembler::new(
+                DEFAULT_FRAGMENT_TIMEOUT.as_millis() as u64,
+                DEFAULT_RESENT_ATTEMPTS,
+            ),
+            send_buffer: FragmentSendBuffer::new(
+                DEFAULT_FRAGMENT_TIMEOUT.as_millis() as u64 * (DEFAULT_RESENT_ATTEMPTS as u64 + 1),
+            ),
+            epoch: Instant::now(),
+            heartbeat,
+            heartbeat_timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+            last_rtt: None,
+            execution_config: None,
+            coverage_granularity: None,
+            key,
+            unauthenticated,
         })
     }
 
+    /// Sets the retransmission policy for reliable messages
+    ///
+    /// # Arguments
+    ///
+    /// * `resent_attempts` - Number of retransmission attempts
+    /// * `ack_timeout` - Timeout for acknowledgment of a single attempt
+    pub fn set_retry_policy(&mut self, resent_attempts: u8, ack_timeout: Duration) {
+        self.resent_attempts = resent_attempts;
+        self.ack_timeout = ack_timeout;
+    }
+
+    /// Returns the execution configuration applied to the device on (re)connection
+    pub fn execution_config(&self) -> Option<ExecutionConfig> {
+        self.execution_config
+    }
+
+    /// Sets the execution configuration, applied by [`crate::guarantee_initial_state`]
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Execution configuration, the device defaults are kept if `None`
+    pub fn set_execution_config(&mut self, config: Option<ExecutionConfig>) {
+        self.execution_config = config;
+    }
+
+    /// Returns the coverage granularity applied to the device on (re)connection
+    pub fn coverage_granularity(&self) -> Option<CoverageGranularity> {
+        self.coverage_granularity
+    }
+
+    /// Sets the coverage granularity, applied by [`crate::guarantee_initial_state`]
+    ///
+    /// # Arguments
+    ///
+    /// * `granularity` - Coverage granularity, the device default is kept if `None`
+    pub fn set_coverage_granularity(&mut self, granularity: Option<CoverageGranularity>) {
+        self.coverage_granularity = granularity;
+    }
+
+    /// Returns the link quality statistics of this connection
+    pub fn link_stats(&self) -> LinkStats {
+        LinkStats {
+            unauthenticated: self.unauthenticated.load(Ordering::Relaxed),
+            ..self.link_stats
+        }
+    }
+
+    /// Returns the latest heartbeat of the device
+    ///
+    /// # Returns
+    ///
+    /// * `Option<HeartbeatStatus>` - Latest heartbeat, or None if none was received yet
+    pub fn heartbeat(&self) -> Option<HeartbeatStatus> {
+        let mut status = self.heartbeat.lock().expect("not poisoned").clone();
+        if let Some(status) = status.as_mut() {
+            status.rtt = self.last_rtt;
+        }
+        status
+    }
+
+    /// Checks if the device stopped sending heartbeats
+    ///
+    /// Devices which did not send any heartbeat yet are never considered stalled.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the latest heartbeat is older than the heartbeat timeout
+    pub fn is_stalled(&self) -> bool {
+        self.heartbeat
+            .lock()
+            .expect("not poisoned")
+            .as_ref()
+            .is_some_and(|status| status.received_at.elapsed() > self.heartbeat_timeout)
+    }
+
     /// Sends a packet to the device
     ///
     /// # Arguments
@@ -276,13 +592,12 @@ impl DeviceConnection {
     /// # Returns
     ///
     /// * `Result<(), DeviceConnectionError>` - Success or error
-    #[allow(unreachable_code)]
     #[track_time("host::net::send")]
     pub async fn send<Packet: OtaPacket<OtaC2DUnreliable, OtaC2DTransport>>(
         &mut self,
         data: Packet,
     ) -> Result<(), DeviceConnectionError> {
-        let packet = if data.reliable_transport() {
+        let mut packet = if data.reliable_transport() {
             self.sequence_number_tx += 1;
 
             data.to_packet(self.sequence_number_tx, self.session)
@@ -290,13 +605,12 @@ impl DeviceConnection {
             data.to_packet(0, 0)
         };
 
-        let buf = packet.serialize().expect("Always works");
+        let buf = packet
+            .serialize_with_key(self.key.as_ref())
+            .expect("Always works");
 
         if buf.len() as u64 > MAX_FRAGMENT_SIZE {
             // fragment
-
-            return Err(DeviceConnectionError::MessageTooLong(buf.len())); // todo
-
             if let Ota::Transport { id, session, .. } = &mut packet {
                 *id = 0;
                 *session = 0;
@@ -304,23 +618,7 @@ impl DeviceConnection {
 
             drop(buf);
             let buf = packet.serialize().expect("Always works");
-
-            let chunks = buf
-                .chunks(MAX_FRAGMENT_SIZE as usize - 128)
-                .collect::<Vec<&[u8]>>();
-            for (i, chunk) in chunks.iter().enumerate() {
-                self.sequence_number_tx += 1;
-                let packet = OtaC2D::ChunkedTransport {
-                    session: self.session,
-                    id: self.sequence_number_tx,
-                    fragment: i as u64,
-                    total_fragments: chunks.len() as u64,
-                    content: chunk.to_vec(),
-                };
-                let buf = packet.serialize().expect("Always works");
-                self.send_native(&buf, true).await?;
-            }
-            Ok(())
+            self.send_chunked(&buf).await
         } else {
             // just send
             self.send_native(&buf, matches!(packet, OtaC2D::Transport { .. }))
@@ -328,6 +626,120 @@ impl DeviceConnection {
         }
     }
 
+    /// Sends a packet exceeding [`MAX_FRAGMENT_SIZE`] as chunked transport
+    ///
+    /// All fragments are sent at once, afterwards only the fragments requested by the device are
+    /// retransmitted. If the payload is not acknowledged, it stays buffered to serve late
+    /// requests, see [`FragmentSendBuffer`].
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The serialized packet
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), DeviceConnectionError>` - Success or error
+    async fn send_chunked(&mut self, data: &[u8]) -> Result<(), DeviceConnectionError> {
+        let (payload, compressed) = encode_payload(data);
+        self.sequence_number_tx += 1;
+        let id = self.sequence_number_tx;
+        let session = self.session;
+
+        let total_fragments = self
+            .send_buffer
+            .insert(session, id, &payload, compressed, self.now_millis())
+            .map_err(|_| DeviceConnectionError::MessageTooLong(payload.len()))?;
+        let all_fragments = (0..total_fragments).collect::<Vec<u64>>();
+        self.send_fragments(session, id, &all_fragments).await?;
+
+        let mut virtual_receive_buffer = VecDeque::new();
+
+        let mut status = None;
+        let mut attempt = 0;
+        while status.is_none() && attempt < self.resent_attempts {
+            match self.receive_native(Some(self.ack_timeout)).await {
+                Some(OtaD2C::Unreliable(OtaD2CUnreliable::Ack(sequence_number)))
+                    if sequence_number == id =>
+                {
+                    // OK received acknowledgement of the complete payload
+                    self.send_buffer.remove(session, id);
+                    status = Some(Ok(()));
+                }
+                Some(OtaD2C::Unreliable(OtaD2CUnreliable::MissingFragments {
+                    session,
+                    id,
+                    fragments,
+                })) => {
+                    if let Err(err) = self.send_fragments(session, id, &fragments).await {
+                        status = Some(Err(err));
+                    }
+                }
+                Some(packet) => virtual_receive_buffer.push_back(packet),
+                None => {
+                    // probe with the last fragment, the device answers with an ack or requests
+                    // the missing fragments
+                    attempt += 1;
+                    if let Err(err) = self
+                        .send_fragments(session, id, &[total_fragments - 1])
+                        .await
+                    {
+                        status = Some(Err(err));
+                    }
+                }
+            }
+        }
+
+        // requeue the received packets
+        for packet in virtual_receive_buffer.into_iter().rev() {
+            self.virtual_receive_queue.push_front(packet);
+        }
+
+        self.link_stats
+            .record_outcome(status, DeviceConnectionError::NoAckReceived)
+    }
+
+    /// Sends fragments of a buffered chunked transport without waiting for acknowledgment
+    ///
+    /// Requests for payloads that are no longer buffered are ignored, the device gives up on
+    /// them after its retransmission requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - Session identifier of the payload
+    /// * `id` - Message identifier of the payload
+    /// * `fragments` - Indices of the fragments to send
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), DeviceConnectionError>` - Success or error
+    async fn send_fragments(
+        &mut self,
+        session: u16,
+        id: u64,
+        fragments: &[u64],
+    ) -> Result<(), DeviceConnectionError> {
+        let packets: Vec<OtaC2D> = match self.send_buffer.fragments(session, id, fragments) {
+            Ok(packets) => packets,
+            Err(err) => {
+                warn!("Ignored request for fragments: {}", err);
+                return Ok(());
+            }
+        };
+
+        for packet in packets {
+            let buf = packet
+                .serialize_with_key(self.key.as_ref())
+                .expect("Always works");
+            self.send_raw(&buf, 0).await?;
+        }
+        Ok(())
+    }
+
+    /// Returns the milliseconds since the creation of the connection
+    fn now_millis(&self) -> u64 {
+        self.epoch.elapsed().as_millis() as u64
+    }
+
     /// Sends raw data to the device
     ///
     /// # Arguments
@@ -346,26 +758,19 @@ impl DeviceConnection {
         let mut virtual_receive_buffer = VecDeque::new();
 
         let mut status = None;
-        'attempt_loop: for _attempt in 0..self.resent_attempts {
+        'attempt_loop: for attempt in 0..self.resent_attempts {
+            let sent_at = Instant::now();
+
             // initial packet sending
-            match self.socket.send(data).await {
-                Ok(count) => {
-                    if count != data.len() {
-                        status = Some(Err(DeviceConnectionError::Eof));
-                        break 'attempt_loop;
-                    }
-                }
-                Err(e) => {
-                    status = Some(Err(DeviceConnectionError::Io(e)));
-                    break 'attempt_loop;
-                }
+            if let Err(err) = self.send_raw(data, attempt).await {
+                status = Some(Err(err));
+                break 'attempt_loop;
             }
 
             // check if requires ack
             if !requires_ack {
                 // does not require ack
-                status = Some(Ok(()));
-                break 'attempt_loop;
+                return Ok(());
             }
 
             // wait for ack
@@ -380,6 +785,7 @@ impl DeviceConnection {
                         virtual_receive_buffer.push_back(received_packet);
                     } else if sequence_number == self.sequence_number_tx {
                         // OK received acknowledgement
+                        self.last_rtt = Some(sent_at.elapsed());
                         status = Some(Ok(()));
                         break 'attempt_loop;
                     } else {
@@ -397,7 +803,53 @@ impl DeviceConnection {
             self.virtual_receive_queue.push_front(packet);
         }
 
-        status.unwrap_or(Err(DeviceConnectionError::NoAckReceived))
+        self.link_stats
+            .record_outcome(status, DeviceConnectionError::NoAckReceived)
+    }
+
+    /// Sends raw data to the device without waiting for acknowledgment
+    ///
+    /// Used for acknowledgments, retransmission requests and fragments, which must not wait
+    /// for incoming packets themselves.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to send
+    /// * `attempt` - Number of the transmission attempt, recorded in the link statistics
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), DeviceConnectionError>` - Success or error
+    async fn send_raw(&mut self, data: &[u8], attempt: u8) -> Result<(), DeviceConnectionError> {
+        let count = self
+            .socket
+            .send(data)
+            .await
+            .map_err(DeviceConnectionError::Io)?;
+        if count != data.len() {
+            return Err(DeviceConnectionError::Eof);
+        }
+        self.link_stats.record_sent(attempt);
+        Ok(())
+    }
+
+    /// Sends an unreliable packet to the device without waiting for acknowledgment
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The packet to send
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), DeviceConnectionError>` - Success or error
+    async fn send_unreliable(
+        &mut self,
+        data: OtaC2DUnreliable,
+    ) -> Result<(), DeviceConnectionError> {
+        let buf = OtaC2D::Unreliable(data)
+            .serialize_with_key(self.key.as_ref())
+            .expect("Always works");
+        self.send_raw(&buf, 0).await
     }
 
     /// Receives raw data from the device
@@ -425,6 +877,13 @@ impl DeviceConnection {
             } else {
                 return None;
             }
+            if self.is_stalled() {
+                // do not wait for the timeout, the device will not answer anyway;
+                // report the stall once, later waits fall back to their timeout
+                warn!("No heartbeat received for {:?}", self.heartbeat_timeout);
+                *self.heartbeat.lock().expect("not poisoned") = None;
+                return None;
+            }
             tokio::time::sleep(Duration::from_millis(10)).await;
         }
     }
@@ -438,8 +897,6 @@ impl DeviceConnection {
     /// # Returns
     ///
     /// * `Option<OtaD2C>` - Received packet or None
-    #[allow(unreachable_code)]
-    #[allow(unused_variables)]
     #[track_time("host::net::receive::raw")]
     pub async fn receive(&mut self, timeout: Option<Duration>) -> Option<OtaD2C> {
         let initial_packet = match self.receive_native(timeout).await {
@@ -449,6 +906,16 @@ impl DeviceConnection {
 
         match initial_packet {
             OtaD2C::Transport { .. } => Some(initial_packet),
+            OtaD2C::Unreliable(OtaD2CUnreliable::MissingFragments {
+                session,
+                id,
+                fragments,
+            }) => {
+                if let Err(err) = self.send_fragments(session, id, &fragments).await {
+                    error!("Failed to retransmit fragments: {:?}", err);
+                }
+                None
+            }
             OtaD2C::Unreliable(_) => Some(initial_packet),
             OtaD2C::ChunkedTransport {
                 session,
@@ -456,73 +923,176 @@ impl DeviceConnection {
                 content,
                 fragment,
                 total_fragments,
+                compressed,
             } => {
-                return None; // todo
-
-                if total_fragments.saturating_mul(MAX_FRAGMENT_SIZE as u64)
-                    > MAX_PAYLOAD_SIZE as u64
+                match self
+                    .insert_fragment(session, id, fragment, total_fragments, compressed, content)
+                    .await
                 {
-                    error!("Fragmented packet too large");
-                    return None;
-                }
-
-                if fragment != 0 {
-                    error!("Received fragment without initial packet");
-                    return None;
+                    Some(packet) => Some(packet),
+                    None => self.receive_fragments().await,
                 }
+            }
+        }
+    }
 
-                let mut packet_content: Vec<u8> = content;
-
-                trace!("Received chunked packet: {:?}", total_fragments);
+    /// Receives the remaining fragments of incomplete chunked transports
+    ///
+    /// Missing fragments are requested from the device if no fragment was received for the
+    /// fragment timeout, see [`FragmentAssembler::poll`].
+    ///
+    /// # Returns
+    ///
+    /// * `Option<OtaD2C>` - Reassembled packet or None
+    async fn receive_fragments(&mut self) -> Option<OtaD2C> {
+        let mut virtual_receive_buffer = VecDeque::new();
 
-                for i in 1..total_fragments {
-                    trace!("{}", i);
-                    let mut received = false;
-                    while let Some(received_packet) =
-                        { self.receive_native(Some(self.fragment_timeout)).await }
-                    {
-                        if let OtaD2C::ChunkedTransport {
-                            session: received_session,
-                            id: received_id,
-                            content: received_content,
-                            fragment: received_fragment,
-                            total_fragments: received_total_fragments,
-                        } = received_packet
-                        {
-                            trace!(" -> {:?}", received_content.len());
-                            if session != received_session {
-                                error!("Received packet with new session: {}", received_session);
-                                return None;
-                            }
-                            if i != received_fragment {
-                                error!(
-                                    "Received packet with wrong fragment: {}",
-                                    received_fragment
-                                );
-                                return None;
-                            }
-                            packet_content.extend(received_content);
-                            received = true;
-                            break;
-                        } else {
-                            // received other package
-                            trace!("Dropped packet: {:?}", received_packet);
-                        }
+        let mut result = None;
+        while self.assembler.is_pending() {
+            match self.receive_native(Some(self.fragment_timeout)).await {
+                Some(OtaD2C::ChunkedTransport {
+                    session,
+                    id,
+                    content,
+                    fragment,
+                    total_fragments,
+                    compressed,
+                }) => {
+                    result = self
+                        .insert_fragment(
+                            session,
+                            id,
+                            fragment,
+                            total_fragments,
+                            compressed,
+                            content,
+                        )
+                        .await;
+                    if result.is_some() {
+                        break;
                     }
-                    if !received {
-                        error!("Failed to receive fragment: {}", i);
-                        return None;
+                }
+                Some(OtaD2C::Unreliable(OtaD2CUnreliable::MissingFragments {
+                    session,
+                    id,
+                    fragments,
+                })) => {
+                    if let Err(err) = self.send_fragments(session, id, &fragments).await {
+                        error!("Failed to retransmit fragments: {:?}", err);
                     }
                 }
+                Some(packet) => {
+                    // received other package
+                    trace!("Deferred packet: {:?}", packet);
+                    virtual_receive_buffer.push_back(packet);
+                }
+                None => {}
+            }
+
+            self.poll_fragments().await;
+        }
+
+        // requeue the received packets
+        for packet in virtual_receive_buffer.into_iter().rev() {
+            self.virtual_receive_queue.push_front(packet);
+        }
 
-                let data: OtaD2C = match OtaD2C::deserialize(&packet_content) {
-                    Ok(data) => data,
+        result
+    }
+
+    /// Inserts a received fragment, acknowledging its payload once it is complete
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - Session identifier of the payload
+    /// * `id` - Message identifier of the payload
+    /// * `fragment` - Index of the fragment
+    /// * `total_fragments` - Total number of fragments of the payload
+    /// * `compressed` - Whether the payload is compressed
+    /// * `content` - Fragment content
+    ///
+    /// # Returns
+    ///
+    /// * `Option<OtaD2C>` - Reassembled packet, None if the payload is incomplete or invalid
+    async fn insert_fragment(
+        &mut self,
+        session: u16,
+        id: u64,
+        fragment: u64,
+        total_fragments: u64,
+        compressed: bool,
+        content: Vec<u8>,
+    ) -> Option<OtaD2C> {
+        let now = self.now_millis();
+        match self.assembler.insert(
+            session,
+            id,
+            fragment,
+            total_fragments,
+            compressed,
+            content,
+            now,
+        ) {
+            Ok(FragmentStatus::Incomplete) | Ok(FragmentStatus::Duplicate { completed: false }) => {
+                None
+            }
+            Ok(FragmentStatus::Duplicate { completed: true }) => {
+                // the device missed the acknowledgement
+                if let Err(err) = self.send_unreliable(OtaC2DUnreliable::Ack(id)).await {
+                    error!("Failed to send ack: {:?}", err);
+                }
+                None
+            }
+            Ok(FragmentStatus::Complete {
+                payload,
+                compressed,
+            }) => {
+                if let Err(err) = self.send_unreliable(OtaC2DUnreliable::Ack(id)).await {
+                    error!("Failed to send ack: {:?}", err);
+                }
+                match OtaD2C::deserialize_payload(&payload, compressed) {
+                    Ok(data) => Some(data),
                     Err(e) => {
                         error!("Failed to deserialize data: {:?}", e);
-                        return None;
+                        None
                     }
-                };
-                Some(data)
+                }
+            }
+            Err(err @ FragmentError::PayloadTooLarge(_)) => {
+                error!("Fragmented packet too large: {}", err);
+                None
+            }
+            Err(err) => {
+                warn!("Dropped fragment: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Requests the missing fragments of stalled chunked transports, see [`FragmentAssembler::poll`]
+    async fn poll_fragments(&mut self) {
+        let now = self.now_millis();
+        for action in self.assembler.poll(now) {
+            match action {
+                FragmentPoll::Missing {
+                    session,
+                    id,
+                    fragments,
+                } => {
+                    if let Err(err) = self
+                        .send_unreliable(OtaC2DUnreliable::MissingFragments {
+                            session,
+                            id,
+                            fragments,
+                        })
+                        .await
+                    {
+                        error!("Failed to request missing fragments: {:?}", err);
+                    }
+                }
+                FragmentPoll::Expired { session, id } => {
+                    error!("Gave up receiving payload {} of session {}", id, session);
+                }
             }
         }
     }
@@ -599,6 +1169,76 @@ impl DeviceConnection {
         }
         let _ = timing.stop();
     }
+
+    /// Performs the protocol version handshake with the device
+    ///
+    /// Must be called once the device is known to be responsive, see [`crate::wait_for_device`],
+    /// since a device that does not acknowledge the handshake is assumed to predate it.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Timeout duration for receiving the answer of the device
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - The device speaks the same protocol version
+    /// * `Err(DeviceConnectionError::ProtocolVersionMismatch)` - The protocol versions disagree
+    /// * `Err(DeviceConnectionError)` - The handshake failed otherwise
+    #[track_time("host::net::hello")]
+    pub async fn hello(&mut self, timeout: Duration) -> Result<(), DeviceConnectionError> {
+        let mismatch = |device| DeviceConnectionError::ProtocolVersionMismatch {
+            controller: OTA_MESSAGE_VERSION,
+            device,
+        };
+
+        match self
+            .send(OtaC2DTransport::Hello {
+                version: OTA_MESSAGE_VERSION,
+            })
+            .await
+        {
+            Ok(()) => {}
+            Err(DeviceConnectionError::NoAckReceived) => {
+                // the device could not deserialize the handshake
+                let err = mismatch(None);
+                error!("{}", err);
+                return Err(err);
+            }
+            Err(err) => return Err(err),
+        }
+
+        let answer = self
+            .receive_packet(
+                |p| {
+                    matches!(
+                        p,
+                        Ota::Transport {
+                            content: OtaD2CTransport::HelloAck { .. },
+                            ..
+                        }
+                    )
+                },
+                Some(timeout),
+            )
+            .await?;
+
+        match answer {
+            Some(Ota::Transport {
+                content: OtaD2CTransport::HelloAck { version },
+                ..
+            }) => {
+                if version == OTA_MESSAGE_VERSION {
+                    debug!("Device speaks protocol version {}", version);
+                    Ok(())
+                } else {
+                    let err = mismatch(Some(version));
+                    error!("{}", err);
+                    Err(err)
+                }
+            }
+            _ => Err(DeviceConnectionError::NoAckReceived),
+        }
+    }
 }
 
 impl Drop for DeviceConnection {
@@ -611,3 +1251,69 @@ impl Drop for DeviceConnection {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::device_connection::{DeviceConnection, DeviceConnectionError};
+    use fuzzer_data::{
+        LinkStats, Ota, OtaC2D, OtaC2DTransport, OtaD2C, OtaD2CUnreliable, OtaPacket,
+    };
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+    use tokio::net::UdpSocket;
+
+    #[tokio::test]
+    async fn test_link_stats() {
+        let device = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let controller = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        device
+            .connect(controller.local_addr().unwrap())
+            .await
+            .unwrap();
+        controller
+            .connect(device.local_addr().unwrap())
+            .await
+            .unwrap();
+
+        // mock device acknowledging the first packet on its second transmission and never
+        // acknowledging the second packet
+        let mock = tokio::spawn(async move {
+            let mut buffer = [0u8; 4096];
+            let mut transmissions = BTreeMap::new();
+            loop {
+                let count = device.recv(&mut buffer).await.unwrap();
+                if let Ok(Ota::Transport { id, .. }) = OtaC2D::deserialize(&buffer[..count]) {
+                    let seen = transmissions.entry(id).or_insert(0);
+                    *seen += 1;
+                    if id == 1 && *seen == 2 {
+                        let ack = OtaD2C::Unreliable(OtaD2CUnreliable::Ack(id));
+                        device.send(&ack.serialize().unwrap()).await.unwrap();
+                    }
+                }
+            }
+        });
+
+        let mut net = DeviceConnection::from_socket(controller, None)
+            .await
+            .unwrap();
+        net.set_retry_policy(3, Duration::from_millis(100));
+
+        net.send(OtaC2DTransport::GetLinkStats).await.unwrap();
+        assert!(matches!(
+            net.send(OtaC2DTransport::GetLinkStats).await,
+            Err(DeviceConnectionError::NoAckReceived)
+        ));
+        mock.abort();
+
+        assert_eq!(
+            net.link_stats(),
+            LinkStats {
+                sent: 5,
+                acked: 1,
+                retransmitted: 3,
+                dropped: 1,
+                unauthenticated: 0,
+            }
+        );
+    }
+}
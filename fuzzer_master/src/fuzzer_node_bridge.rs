@@ -2,6 +2,7 @@
 //!
 //! This module provides a bridge interface for communicating with fuzzing watchdog node.
 
+use crate::config::{DEFAULT_POWER_ON_BOOT_WAIT, DEFAULT_POWER_ON_UEFI_WAIT};
 use crate::wait_for_pi;
 use reqwest::Client;
 use std::time::Duration;
@@ -15,6 +16,10 @@ pub struct FuzzerNodeInterface {
     host: String,
     /// HTTP client for making requests
     client: Client,
+    /// Time to wait after pressing the power button until the BIOS screen is shown
+    power_on_boot_wait: Duration,
+    /// Time to wait after skipping the BIOS until the UEFI app is running
+    power_on_uefi_wait: Duration,
 }
 
 impl FuzzerNodeInterface {
@@ -31,9 +36,37 @@ impl FuzzerNodeInterface {
         FuzzerNodeInterface {
             host: host.to_string(),
             client: Self::client(),
+            power_on_boot_wait: DEFAULT_POWER_ON_BOOT_WAIT,
+            power_on_uefi_wait: DEFAULT_POWER_ON_UEFI_WAIT,
         }
     }
 
+    /// Sets the timings used when powering on the fuzzing agent
+    ///
+    /// # Arguments
+    ///
+    /// * `boot_wait` - Time to wait after pressing the power button until the BIOS screen is shown
+    /// * `uefi_wait` - Time to wait after skipping the BIOS until the UEFI app is running
+    ///
+    /// # Returns
+    ///
+    /// The modified `FuzzerNodeInterface` instance
+    pub fn with_power_on_timings(mut self, boot_wait: Duration, uefi_wait: Duration) -> Self {
+        self.power_on_boot_wait = boot_wait;
+        self.power_on_uefi_wait = uefi_wait;
+        self
+    }
+
+    /// Time to wait after pressing the power button until the BIOS screen is shown
+    pub fn power_on_boot_wait(&self) -> Duration {
+        self.power_on_boot_wait
+    }
+
+    /// Time to wait after skipping the BIOS until the UEFI app is running
+    pub fn power_on_uefi_wait(&self) -> Duration {
+        self.power_on_uefi_wait
+    }
+
     /// Creates a new HTTP client with configured timeouts
     ///
     /// # Returns
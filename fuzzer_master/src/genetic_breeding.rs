@@ -1,15 +1,18 @@
 //! This module implements genetic algorithm-based breeding of code samples for fuzzing.
 
 use crate::database::Database;
-use crate::device_connection::DeviceConnection;
+use crate::device_connection::{DeviceConnection, ThroughputMonitor};
 use crate::fuzzer_node_bridge::FuzzerNodeInterface;
 use crate::net::{net_execute_sample, net_fuzzing_pretext, ExecuteSampleResult};
 use crate::CommandExitResult;
-use fuzzer_data::genetic_pool::{GeneticPool, GeneticPoolSettings, GeneticSampleRating};
-use fuzzer_data::instruction_corpus::CorpusInstruction;
-use fuzzer_data::Code;
-use log::{error, info};
+use fuzzer_data::genetic_pool::{
+    GeneticPool, GeneticPoolSettings, GeneticSampleRating, RatingMode,
+};
+use fuzzer_data::instruction_corpus::InstructionCorpus;
+use fuzzer_data::{Code, OtaC2DTransport};
+use log::{error, info, warn};
 use rand::{random, SeedableRng};
+use std::collections::BTreeSet;
 
 /// Finite state machine states for breeding process
 #[derive(Debug, Default, PartialEq)]
@@ -38,12 +41,20 @@ pub struct BreedingState {
     seed: u64,
     /// Last code sample that was executed
     last_code_executed: Option<Code>,
+    /// Sample throughput reported by the device
+    throughput: ThroughputMonitor,
+    /// Coverage found so far in this experimentation run
+    coverage_sofar: BTreeSet<u16>,
+    /// Executed samples since coverage was last increased
+    iterations_since_last_gain: u64,
 }
 
 /// Timeout for sample execution in seconds
 pub const SAMPLE_TIMEOUT: u64 = 60;
 /// Maximum number of evolutions to perform
 pub const MAX_EVOLUTIONS: u64 = 8;
+/// Number of corpus instructions sent per dictionary message
+const DICTIONARY_CHUNK_SIZE: usize = 256;
 
 /// Main entry point for genetic breeding
 ///
@@ -58,6 +69,7 @@ pub const MAX_EVOLUTIONS: u64 = 8;
 /// * `state` - Current breeding state
 /// * `corpus` - Optional corpus of instructions for initialization
 /// * `fuzzing_feedback` - Whether to use fuzzing feedback for evolution
+/// * `rating_mode` - How the ratings of samples are compared
 ///
 /// # Returns
 ///
@@ -67,26 +79,48 @@ pub async fn main(
     interface: &FuzzerNodeInterface,
     database: &mut Database,
     state: &mut BreedingState,
-    corpus: Option<&Vec<CorpusInstruction>>,
+    corpus: Option<&InstructionCorpus>,
     fuzzing_feedback: bool,
+    rating_mode: RatingMode,
 ) -> CommandExitResult {
     // device is either restarted or new experimentation run
 
-    match state.fsm {
+    let restarted = match state.fsm {
         FSM::Uninitialized => {
-            // new experimentation run
-            let seed = random();
+            if let Some(snapshot) = database.pool_snapshot() {
+                // experimentation run of a previous session
+                let seed = snapshot.seed.unwrap_or_else(random);
+                info!(
+                    "Resuming experimentation run with seed {} at evolution {}",
+                    seed,
+                    snapshot.generation + 1
+                );
+                state.random_source = Some(rand_isaac::Isaac64Rng::seed_from_u64(
+                    seed ^ snapshot.generation,
+                ));
+                state.seed = seed;
+                state.evolution = snapshot.generation + 1;
+                state.genetic_pool = GeneticPool::from_snapshot(snapshot.clone());
+                state.fsm = FSM::Running;
+            } else {
+                // new experimentation run
+                let seed = random();
 
-            info!("Starting new experimentation run with seed: {}", seed);
-            state.random_source = Some(rand_isaac::Isaac64Rng::seed_from_u64(seed));
-            state.seed = seed;
-            state.evolution = 1;
+                info!("Starting new experimentation run with seed: {}", seed);
+                state.random_source = Some(rand_isaac::Isaac64Rng::seed_from_u64(seed));
+                state.seed = seed;
+                state.evolution = 1;
+            }
             state.last_code_executed = None;
+            state.coverage_sofar.clear();
+            state.iterations_since_last_gain = 0;
+            false
         }
         FSM::Running => {
             // device was restarted
+            true
         }
-    }
+    };
 
     let result = net_fuzzing_pretext(
         net,
@@ -99,23 +133,56 @@ pub async fn main(
         return result;
     }
 
+    if restarted {
+        // hand the population to the device, it ships it back before risky operations
+        if let Err(err) = net
+            .send(OtaC2DTransport::RestorePool(state.genetic_pool.snapshot()))
+            .await
+        {
+            error!("Failed to restore the pool snapshot: {:?}", err);
+        }
+    }
+
+    if let Some(corpus) = corpus {
+        // the dictionary of the device mutations is lost on every restart
+        let instructions = corpus.instructions.iter().cloned().collect::<Vec<_>>();
+        for chunk in instructions.chunks(DICTIONARY_CHUNK_SIZE) {
+            if let Err(err) = net
+                .send(OtaC2DTransport::ExtendDictionary {
+                    instructions: chunk.to_vec(),
+                })
+                .await
+            {
+                error!("Failed to send the mutation dictionary: {:?}", err);
+                break;
+            }
+        }
+    }
+
     if state.fsm == FSM::Uninitialized {
         // prepare for fuzzing
         // collect ground truth coverage
 
+        let settings = GeneticPoolSettings {
+            rating_mode,
+            ..Default::default()
+        };
+
         match corpus {
             None => {
                 state.genetic_pool = GeneticPool::new_random_population(
-                    GeneticPoolSettings::default(),
+                    settings,
                     state.random_source.as_mut().unwrap(),
                 )
+                .with_seed(state.seed)
             }
             Some(corpus) => {
                 state.genetic_pool = GeneticPool::new_random_population_from_corpus(
-                    GeneticPoolSettings::default(),
+                    settings.clone(),
                     state.random_source.as_mut().unwrap(),
                     corpus,
-                );
+                )
+                .with_seed(state.seed);
             }
         }
 
@@ -134,6 +201,12 @@ pub async fn main(
                     });
                 }
 
+                if database.is_sample_blacklisted(sample.code()) {
+                    warn!("Skipping a sample that repeatedly crashed the device");
+                    sample.rating = Some(GeneticSampleRating::default());
+                    continue;
+                }
+
                 if let Some((address, times)) = state.last_reported_exclusion {
                     if times > 5 {
                         error!("Device excluded the same address {address:?} more than 5 times");
@@ -159,6 +232,12 @@ pub async fn main(
                     };
 
                 sample.rating = Some(result.fitness.clone());
+                state.iterations_since_last_gain += 1;
+                for (address, count) in &result.coverage {
+                    if *count > 0 && state.coverage_sofar.insert(*address) {
+                        state.iterations_since_last_gain = 0;
+                    }
+                }
                 database.push_results(
                     sample.code().to_vec(),
                     result,
@@ -166,15 +245,27 @@ pub async fn main(
                     state.evolution,
                     state.seed,
                 );
+                if let Some(provenance) = &sample.provenance {
+                    database.set_provenance(sample.code(), provenance.clone());
+                }
                 state.last_reported_exclusion = None;
             }
 
+            state
+                .genetic_pool
+                .set_stagnation(state.iterations_since_last_gain);
+            let mutation_rate = state.genetic_pool.mutation_rate();
             state
                 .genetic_pool
                 .evolution(state.random_source.as_mut().unwrap(), fuzzing_feedback);
             state.evolution += 1;
 
-            info!("Evolution: {}", state.evolution);
+            info!(
+                "Evolution: {}, mutation rate: {}",
+                state.evolution, mutation_rate
+            );
+            state.throughput.update(net);
+            database.set_pool_snapshot(Some(state.genetic_pool.snapshot()));
 
             let _ = database.save().await.map_err(|e| {
                 error!("Failed to save the database: {:?}", e);
@@ -191,6 +282,7 @@ pub async fn main(
     // preparation done
     // start fuzzing
     state.fsm = FSM::Uninitialized;
+    database.set_pool_snapshot(None);
 
     CommandExitResult::Operational
 }
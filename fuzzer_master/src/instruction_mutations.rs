@@ -1,38 +1,213 @@
+//! This module implements mutation-based fuzzing at single-instruction granularity.
+//!
+//! Instead of breeding whole code blobs (see [`crate::genetic_breeding`]), single
+//! instructions are mutated and executed. Instructions that reach previously unseen
+//! microcode addresses are kept as seeds for the next mutation rounds.
+
 use crate::database::Database;
 use crate::device_connection::DeviceConnection;
 use crate::fuzzer_node_bridge::FuzzerNodeInterface;
-use crate::net::net_fuzzing_pretext;
+use crate::net::{net_execute_sample, net_fuzzing_pretext, ExecuteSampleResult};
 use crate::CommandExitResult;
+use fuzzer_data::instruction_corpus::InstructionCorpus;
 use fuzzer_data::Code;
 use log::{error, info};
-use rand::{random, SeedableRng};
+use rand::{random, RngCore, SeedableRng};
+use std::collections::{BTreeMap, BTreeSet};
 
+/// Finite state machine states for the mutation process
 #[derive(Debug, Default, PartialEq)]
 enum FSM {
+    /// Initial state before mutating starts
     #[default]
     Uninitialized,
+    /// Mutation rounds are in progress
     Running,
 }
 
+/// State management for the instruction mutation process
 #[derive(Default)]
 pub struct InstructionMutState {
+    /// Current state of the mutation process
     fsm: FSM,
-
+    /// Random number generator for mutations
     random_source: Option<rand_isaac::Isaac64Rng>,
-
+    /// Last reported address exclusion
     last_reported_exclusion: Option<(Option<u16>, u16)>, // address, times
-
+    /// Current mutation round
     evolution: u64,
+    /// Seed for random number generation
     seed: u64,
-
+    /// Last code sample that was executed
     last_code_executed: Option<Code>,
+    /// Instructions that discovered new coverage, mutated in later rounds
+    seeds: Vec<Code>,
+    /// Mutated instructions of the current round that still have to be executed
+    pending: Vec<Code>,
+    /// Microcode addresses covered by any executed instruction so far
+    coverage: BTreeSet<u16>,
 }
 
+/// Maximum number of mutation rounds to perform before starting a new run
+pub const MAX_MUTATION_ROUNDS: u64 = 8;
+/// Number of mutated instructions executed each round
+pub const MUTATIONS_PER_ROUND: usize = 100;
+/// Number of initial seeds if no corpus is given
+pub const INITIAL_RANDOM_SEEDS: usize = 32;
+/// Maximum length of an x86 instruction in bytes
+pub const MAX_INSTRUCTION_LENGTH: usize = 15;
+
+impl InstructionMutState {
+    /// Starts a new experimentation run
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - Seed for the random source
+    /// * `corpus` - Optional corpus of instructions to take the initial seeds from
+    fn initialize(&mut self, seed: u64, corpus: Option<&InstructionCorpus>) {
+        let mut random = rand_isaac::Isaac64Rng::seed_from_u64(seed);
+
+        self.seeds = match corpus {
+            Some(corpus) => corpus
+                .instructions
+                .iter()
+                .filter(|instruction| instruction.valid && !instruction.bytes.is_empty())
+                .map(|instruction| instruction.bytes.clone())
+                .collect(),
+            _ => Vec::new(),
+        };
+        if self.seeds.is_empty() {
+            for _ in 0..INITIAL_RANDOM_SEEDS {
+                let length = 1 + random.next_u32() as usize % MAX_INSTRUCTION_LENGTH;
+                self.seeds
+                    .push((0..length).map(|_| random.next_u32() as u8).collect());
+            }
+        }
+
+        self.random_source = Some(random);
+        self.seed = seed;
+        self.evolution = 1;
+        self.last_code_executed = None;
+        self.pending.clear();
+        self.coverage.clear();
+    }
+
+    /// Fills the queue of pending instructions with mutations of the current seeds
+    fn mutation_round(&mut self) {
+        let random = self
+            .random_source
+            .as_mut()
+            .expect("random source is set on initialization");
+
+        self.pending.clear();
+        for _ in 0..MUTATIONS_PER_ROUND {
+            let seed = &self.seeds[random.next_u32() as usize % self.seeds.len()];
+            let other = &self.seeds[random.next_u32() as usize % self.seeds.len()];
+            self.pending.push(mutate(seed, other, random));
+        }
+    }
+
+    /// Feeds the coverage of an executed instruction back into the state
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - Executed instruction
+    /// * `coverage` - Coverage reported by the device for this instruction
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - Whether the instruction covered a new address and was kept as seed
+    fn feedback(&mut self, code: &[u8], coverage: &BTreeMap<u16, u16>) -> bool {
+        let mut new_coverage = false;
+        for address in coverage.keys() {
+            new_coverage |= self.coverage.insert(*address);
+        }
+
+        if new_coverage && !self.seeds.iter().any(|seed| seed == code) {
+            self.seeds.push(code.to_vec());
+        }
+
+        new_coverage
+    }
+}
+
+/// Mutates a single instruction
+///
+/// # Arguments
+///
+/// * `instruction` - Instruction to mutate
+/// * `other` - Second instruction used for splicing
+/// * `random` - Random source
+///
+/// # Returns
+///
+/// * `Code` - The mutated instruction, never empty and at most [`MAX_INSTRUCTION_LENGTH`] bytes
+fn mutate<R: RngCore>(instruction: &[u8], other: &[u8], random: &mut R) -> Code {
+    let mut code = instruction.to_vec();
+    if code.is_empty() {
+        code.push(random.next_u32() as u8);
+    }
+
+    match random.next_u32() % 5 {
+        0 => {
+            // flip a single bit
+            let index = random.next_u32() as usize % code.len();
+            code[index] ^= 1 << (random.next_u32() % 8);
+        }
+        1 => {
+            // replace a byte
+            let index = random.next_u32() as usize % code.len();
+            code[index] = random.next_u32() as u8;
+        }
+        2 => {
+            // insert a byte, e.g. a prefix
+            let index = random.next_u32() as usize % (code.len() + 1);
+            code.insert(index, random.next_u32() as u8);
+        }
+        3 => {
+            // remove a byte
+            if code.len() > 1 {
+                let index = random.next_u32() as usize % code.len();
+                code.remove(index);
+            }
+        }
+        _ => {
+            // splice the tail of another instruction
+            if !other.is_empty() {
+                let split = random.next_u32() as usize % code.len();
+                let other_split = random.next_u32() as usize % other.len();
+                code.truncate(split);
+                code.extend_from_slice(&other[other_split..]);
+            }
+        }
+    }
+
+    code.truncate(MAX_INSTRUCTION_LENGTH);
+    code
+}
+
+/// Main entry point for instruction mutation
+///
+/// This function manages the instruction mutation process, including initialization,
+/// mutation rounds, and sample execution.
+///
+/// # Arguments
+///
+/// * `net` - Network connection to the device
+/// * `interface` - Interface to the fuzzing node
+/// * `database` - Database for storing results
+/// * `state` - Current mutation state
+/// * `corpus` - Optional corpus of instructions for initialization
+///
+/// # Returns
+///
+/// * `CommandExitResult` indicating the requests program state change
 pub async fn main(
     net: &mut DeviceConnection,
-    _interface: &FuzzerNodeInterface,
+    interface: &FuzzerNodeInterface,
     database: &mut Database,
     state: &mut InstructionMutState,
+    corpus: Option<&InstructionCorpus>,
 ) -> CommandExitResult {
     // device is either restarted or new experimentation run
 
@@ -42,10 +217,7 @@ pub async fn main(
             let seed = random();
 
             info!("Starting new experimentation run with seed: {}", seed);
-            state.random_source = Some(rand_isaac::Isaac64Rng::seed_from_u64(seed));
-            state.seed = seed;
-            state.evolution = 1;
-            state.last_code_executed = None;
+            state.initialize(seed, corpus);
         }
         FSM::Running => {
             // device was restarted
@@ -64,16 +236,142 @@ pub async fn main(
     }
 
     if state.fsm == FSM::Uninitialized {
-        // initialize
+        // prepare for fuzzing
+        state.mutation_round();
 
         state.fsm = FSM::Running;
     }
 
-    if state.fsm == FSM::Running {}
+    if state.fsm == FSM::Running && state.evolution < MAX_MUTATION_ROUNDS {
+        let mut i = 0;
+        while let Some(code) = state.pending.last().cloned() {
+            if (i % 10) == 0 {
+                let _ = database.save().await.map_err(|e| {
+                    error!("Failed to save the database: {:?}", e);
+                });
+            }
+            i += 1;
+
+            if let Some((address, times)) = state.last_reported_exclusion {
+                if times > 5 {
+                    error!("Device excluded the same address {address:?} more than 5 times");
+                    state.pending.pop();
+                    state.last_reported_exclusion = None;
+                    continue;
+                }
+            }
+
+            if state.last_code_executed.is_none() {
+                state.last_code_executed = Some(Vec::new())
+            }
+            if let Some(last_code) = state.last_code_executed.as_mut() {
+                last_code.clear();
+                last_code.extend(&code);
+            }
+
+            let (result, events) =
+                match net_execute_sample(net, interface, database, &code, true).await {
+                    ExecuteSampleResult::Timeout => return CommandExitResult::ForceReconnect,
+                    ExecuteSampleResult::Rerun => return CommandExitResult::Operational,
+                    ExecuteSampleResult::Success((a, b)) => (a, b),
+                };
+
+            state.pending.pop();
+            state.feedback(&code, &result.coverage);
+            database.push_results(code, result, events, state.evolution, state.seed);
+            state.last_reported_exclusion = None;
+        }
+
+        state.evolution += 1;
+        state.mutation_round();
+
+        info!(
+            "Mutation round: {}, seeds: {}, covered addresses: {}",
+            state.evolution,
+            state.seeds.len(),
+            state.coverage.len()
+        );
+
+        let _ = database.save().await.map_err(|e| {
+            error!("Failed to save the database: {:?}", e);
+        });
+
+        return CommandExitResult::Operational;
+    }
 
     let _ = database.save().await.map_err(|e| {
         error!("Failed to save the database: {:?}", e);
     });
 
-    CommandExitResult::ExitProgram
+    // run finished
+    // start a new experimentation run
+    state.fsm = FSM::Uninitialized;
+
+    CommandExitResult::Operational
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instruction_mutations::{
+        mutate, InstructionMutState, MAX_INSTRUCTION_LENGTH, MUTATIONS_PER_ROUND,
+    };
+    use fuzzer_data::instruction_corpus::{CorpusInstruction, InstructionCorpus};
+    use rand::SeedableRng;
+    use std::collections::BTreeMap;
+
+    #[test]
+    pub fn test_offline_mutation_round() {
+        let corpus = InstructionCorpus::new(vec![
+            CorpusInstruction {
+                bytes: vec![0x0f, 0xa2], // cpuid
+                valid: true,
+            },
+            CorpusInstruction {
+                bytes: vec![0x90], // nop
+                valid: true,
+            },
+            CorpusInstruction {
+                bytes: vec![0x0f, 0x0b], // ud2
+                valid: false,
+            },
+        ]);
+
+        let mut state = InstructionMutState::default();
+        state.initialize(42, Some(&corpus));
+        assert_eq!(state.seeds, vec![vec![0x0f, 0xa2], vec![0x90]]);
+
+        state.mutation_round();
+        assert_eq!(state.pending.len(), MUTATIONS_PER_ROUND);
+        assert!(state
+            .pending
+            .iter()
+            .all(|code| !code.is_empty() && code.len() <= MAX_INSTRUCTION_LENGTH));
+
+        // feed back fake device coverage in place of an execution
+        let code = state.pending.pop().unwrap();
+        assert!(state.feedback(&code, &BTreeMap::from([(0x10, 1), (0x7c00, 2)])));
+        assert_eq!(state.coverage.len(), 2);
+        assert!(state.seeds.contains(&code));
+
+        let seeds = state.seeds.len();
+        let code = state.pending.pop().unwrap();
+        assert!(!state.feedback(&code, &BTreeMap::from([(0x10, 3)])));
+        assert_eq!(state.seeds.len(), seeds);
+
+        state.mutation_round();
+        assert_eq!(state.pending.len(), MUTATIONS_PER_ROUND);
+    }
+
+    #[test]
+    pub fn test_random_seeds_without_corpus() {
+        let mut state = InstructionMutState::default();
+        state.initialize(1, None);
+        assert!(!state.seeds.is_empty());
+
+        let mut random = rand_isaac::Isaac64Rng::seed_from_u64(7);
+        for _ in 0..1000 {
+            let code = mutate(&[0x90; MAX_INSTRUCTION_LENGTH], &[0x0f, 0x05], &mut random);
+            assert!(!code.is_empty() && code.len() <= MAX_INSTRUCTION_LENGTH);
+        }
+    }
 }
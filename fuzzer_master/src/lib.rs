@@ -2,14 +2,17 @@
 //!
 //! This library implements the master control logic for coordinating fuzzing the fuzzing operations.
 
+use crate::database::Database;
 use crate::device_connection::{DeviceConnection, DeviceConnectionError};
 use crate::fuzzer_node_bridge::FuzzerNodeInterface;
+use crate::net::net_set_execution_config;
 use fuzzer_data::OtaC2DTransport;
 use log::{debug, error, info, trace, warn};
 use performance_timing::track_time;
 use std::time::Duration;
 use tokio::time::Instant;
 
+pub mod config;
 pub mod database;
 pub mod device_connection;
 pub mod fuzzer_node_bridge;
@@ -21,6 +24,8 @@ pub mod instruction_mutations;
 
 pub mod manual_execution;
 pub mod net;
+pub mod perf_tui;
+pub mod scenario;
 
 pub mod spec_fuzz;
 
@@ -37,6 +42,71 @@ pub enum CommandExitResult {
     Operational,
 }
 
+impl CommandExitResult {
+    /// Process exit code for this result, `None` if the program should continue
+    pub fn exit_code(&self) -> Option<MasterExitCode> {
+        match self {
+            CommandExitResult::ExitProgram => Some(MasterExitCode::Success),
+            CommandExitResult::RetryOrReconnect
+            | CommandExitResult::ForceReconnect
+            | CommandExitResult::Operational => None,
+        }
+    }
+}
+
+/// Process exit codes of the fuzzer master, for scripts orchestrating multiple rigs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MasterExitCode {
+    /// Run completed successfully
+    Success = 0,
+    /// Unspecific error
+    Failure = 1,
+    /// Invalid arguments or configuration file (same code as argument errors of clap)
+    BadArguments = 2,
+    /// Input file (database, corpus) could not be loaded
+    InputUnavailable = 3,
+    /// Device or fuzzer node is not reachable and could not be recovered, or the device
+    /// runs an incompatible protocol version
+    DeviceUnrecoverable = 4,
+    /// Host environment (e.g. performance timing) could not be initialized
+    EnvironmentUnavailable = 5,
+    /// Results (database) could not be saved
+    OutputUnavailable = 6,
+}
+
+impl MasterExitCode {
+    /// Exits the process with this exit code
+    pub fn exit(self) -> ! {
+        std::process::exit(self as i32)
+    }
+}
+
+impl From<MasterExitCode> for std::process::ExitCode {
+    fn from(value: MasterExitCode) -> Self {
+        std::process::ExitCode::from(value as u8)
+    }
+}
+
+/// Saves the database a last time before the program exits
+///
+/// # Arguments
+///
+/// * `database` - Database to save
+///
+/// # Returns
+///
+/// * `MasterExitCode::Success` if the database was saved, `MasterExitCode::OutputUnavailable` otherwise
+pub async fn save_before_exit(database: &mut Database) -> MasterExitCode {
+    match database.save().await {
+        Ok(()) => MasterExitCode::Success,
+        Err(err) => {
+            error!("Failed to save the database: {:?}", err);
+            MasterExitCode::OutputUnavailable
+        }
+    }
+}
+
 /// Base frequency for x86_64 development machine
 #[cfg(target_arch = "x86_64")]
 pub const P0_FREQ: f64 = 2_699_000_000.0; // Our development machine
@@ -50,20 +120,19 @@ compiler_error!("Unsupported architecture");
 
 /// Waits for a Raspberry Pi to complete its reboot sequence
 ///
+/// Exits the process with [`MasterExitCode::DeviceUnrecoverable`] if the Pi fails to respond
+/// within 5 min.
+///
 /// # Arguments
 ///
 /// * `interface` - Interface to the fuzzing node
-///
-/// # Panics
-///
-/// Panics if the Pi fails to respond within 5 min
 pub async fn wait_for_pi(interface: &FuzzerNodeInterface) {
     trace!("Waiting for the PI to reboot");
     let now = Instant::now();
     loop {
         if now.elapsed() > Duration::from_secs(300) {
             error!("Failed to reboot the PI");
-            std::process::exit(-1); // we cant do anything since the PI is not responding
+            MasterExitCode::DeviceUnrecoverable.exit(); // we cant do anything since the PI is not responding
         }
 
         if let Ok(true) = interface.alive().await {
@@ -92,6 +161,8 @@ pub enum WaitForDeviceResult {
     NoResponse,
     /// Socket error occurred during communication
     SocketError(DeviceConnectionError),
+    /// Device speaks a different protocol version
+    ProtocolVersionMismatch(DeviceConnectionError),
 }
 
 /// Waits for the fuzzing agent to respond to connection attempts
@@ -116,10 +187,22 @@ pub async fn wait_for_device(net: &mut DeviceConnection) -> WaitForDeviceResult
         }
 
         match net.send(OtaC2DTransport::AreYouThere).await {
-            Ok(_) => {
-                info!("Yes");
-                return WaitForDeviceResult::DeviceFound;
-            }
+            Ok(_) => match net.hello(Duration::from_secs(3)).await {
+                Ok(()) => {
+                    info!("Yes");
+                    return WaitForDeviceResult::DeviceFound;
+                }
+                Err(err @ DeviceConnectionError::ProtocolVersionMismatch { .. }) => {
+                    return WaitForDeviceResult::ProtocolVersionMismatch(err);
+                }
+                Err(DeviceConnectionError::NoAckReceived) => {
+                    // handshake answer lost
+                    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                }
+                Err(e) => {
+                    return WaitForDeviceResult::SocketError(e);
+                }
+            },
             Err(DeviceConnectionError::NoAckReceived) => {
                 // no response
                 tokio::time::sleep(std::time::Duration::from_secs(1)).await;
@@ -156,7 +239,7 @@ pub async fn power_on(interface: &FuzzerNodeInterface) -> bool {
     // device is on
 
     trace!("Waiting for the device to boot");
-    tokio::time::sleep(Duration::from_secs(50)).await;
+    tokio::time::sleep(interface.power_on_boot_wait()).await;
 
     // bios screen is shown
 
@@ -167,7 +250,7 @@ pub async fn power_on(interface: &FuzzerNodeInterface) -> bool {
     }
 
     trace!("Waiting for the device to boot UEFI");
-    tokio::time::sleep(Duration::from_secs(40)).await;
+    tokio::time::sleep(interface.power_on_uefi_wait()).await;
 
     true
 }
@@ -175,7 +258,8 @@ pub async fn power_on(interface: &FuzzerNodeInterface) -> bool {
 /// Ensures that the fuzzing agent is in a known initial state
 ///
 /// This function handles device power management and connection establishment,
-/// retrying as necessary until the device is in a usable state.
+/// retrying as necessary until the device is in a usable state. Exits the process with
+/// [`MasterExitCode::DeviceUnrecoverable`] if the device speaks an incompatible protocol version.
 ///
 /// # Arguments
 ///
@@ -204,6 +288,87 @@ pub async fn guarantee_initial_state(interface: &FuzzerNodeInterface, udp: &mut
                 // device is already on
                 break;
             }
+            WaitForDeviceResult::ProtocolVersionMismatch(err) => {
+                // retrying or rebooting cannot resolve this, the device binary must be redeployed
+                error!("{}", err);
+                eprintln!("Device and controller are incompatible: {}", err);
+                MasterExitCode::DeviceUnrecoverable.exit();
+            }
+        }
+    }
+
+    // the device forgets its execution config on reboot
+    if let Some(config) = udp.execution_config() {
+        match net_set_execution_config(udp, config, Duration::from_secs(3)).await {
+            Some(effective) => {
+                if effective != config {
+                    warn!("Device clamped the execution config to {:?}", effective);
+                }
+                info!("Effective execution config: {:?}", effective);
+            }
+            None => warn!("Device did not acknowledge the execution config"),
         }
     }
+
+    if let Some(coverage_granularity) = udp.coverage_granularity() {
+        if let Err(err) = udp
+            .send(OtaC2DTransport::SetExecutionPolicy {
+                coverage_granularity,
+            })
+            .await
+        {
+            warn!("Failed to set the coverage granularity: {:?}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::{save_before_exit, CommandExitResult, MasterExitCode};
+
+    #[test]
+    pub fn test_exit_code_mapping() {
+        assert_eq!(
+            CommandExitResult::ExitProgram.exit_code(),
+            Some(MasterExitCode::Success)
+        );
+        assert_eq!(CommandExitResult::Operational.exit_code(), None);
+        assert_eq!(CommandExitResult::RetryOrReconnect.exit_code(), None);
+        assert_eq!(CommandExitResult::ForceReconnect.exit_code(), None);
+
+        // orchestration scripts rely on these values
+        for (code, value) in [
+            (MasterExitCode::Success, 0),
+            (MasterExitCode::Failure, 1),
+            (MasterExitCode::BadArguments, 2),
+            (MasterExitCode::InputUnavailable, 3),
+            (MasterExitCode::DeviceUnrecoverable, 4),
+            (MasterExitCode::EnvironmentUnavailable, 5),
+            (MasterExitCode::OutputUnavailable, 6),
+        ] {
+            assert_eq!(
+                std::process::ExitCode::from(code),
+                std::process::ExitCode::from(value)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_save_before_exit() {
+        let path = std::env::temp_dir().join("fuzzer_master_test_save_before_exit.json");
+        let mut database = Database::empty(&path);
+        database.mark_dirty();
+        let saved = save_before_exit(&mut database).await;
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(saved, MasterExitCode::Success);
+
+        let mut database =
+            Database::empty(std::env::temp_dir().join("nonexistent_directory/database.json"));
+        database.mark_dirty();
+        assert_eq!(
+            save_before_exit(&mut database).await,
+            MasterExitCode::OutputUnavailable
+        );
+    }
 }
@@ -1,33 +1,46 @@
-use clap::{Parser, Subcommand};
+use clap::{Args as ClapArgs, Parser, Subcommand};
+use data_types::addresses::UCInstructionAddress;
+use data_types::patch::{PatchFile, Triad as PatchTriad};
 use flate2::Compression;
+use fuzzer_data::genetic_pool::RatingMode;
 use fuzzer_data::instruction_corpus::InstructionCorpus;
-use fuzzer_data::{Ota, OtaC2DTransport, OtaD2C, OtaD2CTransport};
+use fuzzer_data::{
+    CoverageGranularity, ExecutionConfig, Ota, OtaC2DTransport, OtaD2C, OtaD2CTransport,
+};
+use fuzzer_master::config::{RigConfig, RigConfigArgs};
 use fuzzer_master::database::Database;
 use fuzzer_master::device_connection::DeviceConnection;
 use fuzzer_master::fuzzer_node_bridge::FuzzerNodeInterface;
 use fuzzer_master::genetic_breeding::BreedingState;
 use fuzzer_master::instruction_mutations::InstructionMutState;
 use fuzzer_master::manual_execution::ManualExecutionState;
-use fuzzer_master::net::{net_reboot_device, net_receive_performance_timing, ExecuteSampleResult};
-use fuzzer_master::spec_fuzz::SpecFuzzMutState;
+use fuzzer_master::net::{
+    measure_latency, net_access_msr, net_reboot_device, net_receive_link_stats,
+    net_receive_performance_timing, net_run_scenario, ExecuteSampleResult,
+};
+use fuzzer_master::spec_fuzz::{analyze_leak, SpecFuzzMutState};
 use fuzzer_master::{
     afl_fuzzing, genetic_breeding, guarantee_initial_state, instruction_mutations,
-    manual_execution, net, power_on, spec_fuzz, CommandExitResult, P0_FREQ,
+    manual_execution, net, perf_tui, power_on, save_before_exit, scenario, spec_fuzz,
+    CommandExitResult, MasterExitCode,
 };
-use hypervisor::state::StateDifference;
+use hypervisor::state::{DifferenceFormatter, StateDifference};
 use itertools::Itertools;
 use libafl_bolts::rands::random_seed;
 use log::{error, info, trace, warn};
+use performance_timing::measurements::MeasurementCollection;
 use performance_timing::TimeMeasurement;
+use std::collections::BTreeMap;
 use std::io;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::Instant;
 use ucode_compiler_dynamic::instruction::Instruction;
 use ucode_compiler_dynamic::sequence_word::SequenceWord;
+use ucode_compiler_dynamic::Triad;
 
 pub mod main_compare;
 pub mod main_viewer;
@@ -46,12 +59,11 @@ struct Args {
     /// Dont reset address blacklist
     #[arg(long)]
     dont_reset: bool,
-    /// Address of the fuzzer instrumentor
-    #[arg(long, default_value = "http://10.83.3.198:8000")]
-    instrumentor: String,
-    /// Address of the fuzzer agent
-    #[arg(long, default_value = "10.83.3.6:4444")]
-    agent: String,
+    /// Rig configuration file (TOML); values given as flags take precedence
+    #[arg(long)]
+    config: Option<PathBuf>,
+    #[command(flatten)]
+    rig: RigConfigArgs,
     /// The command to execute
     #[command(subcommand)]
     cmd: Cmd,
@@ -71,9 +83,19 @@ enum Cmd {
         /// Disable the feedback loop, fuzzing input ratings will become randomized
         #[arg(short, long)]
         disable_feedback: bool,
+        /// Rank fuzzing inputs by pareto dominance of their coverage metrics instead of lexicographically
+        #[arg(short, long)]
+        pareto: bool,
+        #[command(flatten)]
+        execution: ExecutionConfigArgs,
+    },
+    /// Perform coverage fuzzing by mutating single instructions
+    /// == Requires the `fuzzer_device` app running on the agent ==
+    InstructionMutation {
+        /// A corpus file to take the initial instructions from
+        #[arg(short, long)]
+        corpus: Option<PathBuf>,
     },
-    /// Under-construction
-    InstructionMutation,
     /// Bring up the fuzzer agent to a usable state
     Init,
     /// Reboot the fuzzer agent
@@ -91,8 +113,44 @@ enum Cmd {
         #[arg(long, default_value = "0")]
         node: u32,
     },
+    /// Build an instruction corpus file for the `corpus` argument of the fuzzing commands
+    Corpus {
+        /// The corpus operation to perform
+        #[command(subcommand)]
+        action: CorpusCmd,
+    },
+    /// Read or write a model specific register of the fuzzer agent
+    Msr {
+        /// The register access to perform
+        #[command(subcommand)]
+        action: MsrCmd,
+    },
+    /// Measure the round-trip latency to the fuzzer agent, e.g. to tune the retransmission timeouts
+    Ping {
+        /// Number of pings to send
+        #[arg(short, long, default_value = "10")]
+        count: u32,
+    },
+    /// Run an evaluation scenario of the `poc_agent` on the fuzzer agent
+    /// == Requires the `fuzzer_device` app built with the `scenario` feature running on the agent ==
+    Scenario {
+        /// Name of the scenario, e.g. MICROCODE or SPECTRE
+        name: String,
+        /// File containing the payload of the scenario as JSON, e.g. "Reset" or {"Execute": "CPUID"}
+        payload: PathBuf,
+        /// Path to save the result of the scenario to as JSON; printed if not given
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
     /// Extract performance values from the fuzzer agent
-    Performance,
+    Performance {
+        /// Explore the values in an interactive, sortable table
+        #[arg(long)]
+        tui: bool,
+        /// Show only the values measured since the previous query instead of the lifetime totals
+        #[arg(long)]
+        delta: bool,
+    },
     /// Do speculative microcode fuzzing
     /// == Requires the `spec_fuzz` app running on the agent ==
     Spec {
@@ -113,16 +171,25 @@ enum Cmd {
         /// Run all PMC variants through the fuzzer; takes a long time
         #[arg(short, long)]
         fuzzy_pmc: bool,
+        /// Additionally execute fuzzing for every known opcode, not only those found in MSROM
+        #[arg(long)]
+        every_opcode: bool,
     },
     /// Executes a given speculative fuzzing payload manually
     /// == Requires the `spec_fuzz` app running on the agent ==
     SpecManual {
-        /// Instruction given as list of hex values
+        /// Instruction given as list of hex values or mnemonics, e.g. "tmp0:= ZEROEXT_DSZ64(0x42)"
         #[arg(short, long)]
         instruction: Option<Vec<String>>,
         /// Sequence word given as hex number
         #[arg(short, long)]
         sequence_word: Option<String>,
+        /// Baseline triad given as list of hex values or mnemonics, e.g. a fence, to compare the result against
+        #[arg(short, long)]
+        baseline: Option<Vec<String>>,
+        /// Save the triad to this patch file before sending it, to re-apply it later
+        #[arg(long)]
+        dump_patch: Option<PathBuf>,
     },
     /// Executes a single fuzzing input manually
     /// == Requires the `fuzzer_device` app running on the agent ==
@@ -148,6 +215,8 @@ enum Cmd {
         /// Disable coverage collection
         #[arg(short, long)]
         no_coverage_collection: bool,
+        #[command(flatten)]
+        execution: ExecutionConfigArgs,
     },
     /// Executes a corpus of fuzzing inputs; essentially runs the manual command using all files within the given directory
     /// == Requires the `fuzzer_device` app running on the agent ==
@@ -189,19 +258,254 @@ enum Cmd {
         /// these byte sequences are selected among printable ASCII characters
         #[arg(short, long)]
         printable_input_generation: bool,
+        #[command(flatten)]
+        execution: ExecutionConfigArgs,
+    },
+}
+
+/// Execution configuration of the agent, the agent defaults are kept if no flag is given
+#[derive(ClapArgs, Debug, Clone, Default)]
+struct ExecutionConfigArgs {
+    /// Maximum number of VM steps the agent traces a fuzzing input for
+    #[arg(long)]
+    max_vm_iterations: Option<u64>,
+    /// Maximum number of states recorded when state tracing a fuzzing input
+    #[arg(long)]
+    state_trace_len: Option<u16>,
+    /// Seed to randomize the initial general purpose registers of the fuzzing inputs
+    #[arg(long)]
+    initial_reg_seed: Option<u64>,
+    /// Number of coverage entries the agent sends per coverage message
+    #[arg(long)]
+    coverage_batch: Option<u8>,
+    /// Maximum number of TSC ticks a single VM entry of a fuzzing input may take
+    #[arg(long)]
+    sample_timeout_tsc: Option<u64>,
+    /// Execute every fuzzing input again with two randomized initial register files, seeded by
+    /// the fuzzing seed, and report divergences not explained by the differing registers
+    #[arg(long)]
+    differential_execution: bool,
+    /// Restore the writable guest memory of the agent from a snapshot before every execution
+    #[arg(long)]
+    reset_guest_memory: bool,
+    /// Granularity of the coverage collection of the agent: instruction or triad; hooking only
+    /// the first instruction of every triad trades feedback resolution for speed
+    #[arg(long)]
+    coverage_granularity: Option<CoverageGranularity>,
+}
+
+impl ExecutionConfigArgs {
+    /// Builds the execution configuration, unset flags take the agent defaults
+    ///
+    /// # Returns
+    ///
+    /// * `Option<ExecutionConfig>` - The execution configuration, `None` if no flag is given
+    fn to_config(&self) -> Option<ExecutionConfig> {
+        if self.max_vm_iterations.is_none()
+            && self.state_trace_len.is_none()
+            && self.initial_reg_seed.is_none()
+            && self.coverage_batch.is_none()
+            && self.sample_timeout_tsc.is_none()
+            && !self.differential_execution
+            && !self.reset_guest_memory
+        {
+            return None;
+        }
+
+        let default = ExecutionConfig::default();
+        Some(ExecutionConfig {
+            max_vm_iterations: self.max_vm_iterations.unwrap_or(default.max_vm_iterations),
+            state_trace_len: self.state_trace_len.unwrap_or(default.state_trace_len),
+            initial_reg_seed: self.initial_reg_seed,
+            coverage_batch: self.coverage_batch.unwrap_or(default.coverage_batch),
+            sample_timeout_tsc: self
+                .sample_timeout_tsc
+                .unwrap_or(default.sample_timeout_tsc),
+            differential_execution: self.differential_execution,
+            reset_guest_memory: self.reset_guest_memory,
+        })
+    }
+}
+
+/// Operations of the `corpus` command
+#[derive(Subcommand, Debug, Clone)]
+enum CorpusCmd {
+    /// Decode raw machine code files and collect their instructions into a corpus
+    Build {
+        /// Files containing raw machine code, e.g. `.text` sections extracted with `objcopy`
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Corpus file to write
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Bitness of the machine code: 16, 32 or 64
+        #[arg(short, long, default_value = "64")]
+        bitness: u32,
+    },
+}
+
+/// Builds an instruction corpus from raw machine code files and saves it as JSON
+///
+/// # Arguments
+///
+/// * `files` - Files containing raw machine code
+/// * `output` - Path of the corpus file
+/// * `bitness` - Bitness of the machine code
+fn build_corpus(files: &[PathBuf], output: &Path, bitness: u32) -> MasterExitCode {
+    let mut corpus = InstructionCorpus::new(Vec::new());
+    for file in files {
+        let code = match std::fs::read(file) {
+            Ok(code) => code,
+            Err(err) => {
+                error!("Failed to read {:?}: {:?}", file, err);
+                return MasterExitCode::InputUnavailable;
+            }
+        };
+        match InstructionCorpus::from_binary(&code, bitness) {
+            Ok(part) => {
+                info!(
+                    "Decoded {} unique instructions from {:?}",
+                    part.instructions.len(),
+                    file
+                );
+                corpus.merge(part);
+            }
+            Err(err) => {
+                error!("Failed to decode {:?}: {}", file, err);
+                return MasterExitCode::BadArguments;
+            }
+        }
+    }
+
+    let result = std::fs::File::create(output)
+        .map_err(|e| format!("Failed to create file: {:?} - {:?}", output, e))
+        .and_then(|file| {
+            serde_json::to_writer(std::io::BufWriter::new(file), &corpus)
+                .map_err(|e| format!("Failed to write file: {:?} - {:?}", output, e))
+        });
+    match result {
+        Ok(()) => {
+            println!(
+                "Saved corpus of {} instructions to {:?}",
+                corpus.instructions.len(),
+                output
+            );
+            MasterExitCode::Success
+        }
+        Err(err) => {
+            error!("{}", err);
+            MasterExitCode::Failure
+        }
+    }
+}
+
+/// Model specific register accesses of the `msr` command
+#[derive(Subcommand, Debug, Clone)]
+enum MsrCmd {
+    /// Read a model specific register
+    Read {
+        /// Register number, hexadecimal with `0x` prefix or decimal
+        #[arg(value_parser = parse_number::<u32>)]
+        msr: u32,
     },
+    /// Write a model specific register; the agent refuses registers that are not known to be safe
+    Write {
+        /// Register number, hexadecimal with `0x` prefix or decimal
+        #[arg(value_parser = parse_number::<u32>)]
+        msr: u32,
+        /// Value to write, hexadecimal with `0x` prefix or decimal
+        #[arg(value_parser = parse_number::<u64>)]
+        value: u64,
+    },
+}
+
+/// Parses a number given either as hex value with `0x` prefix or as decimal value
+fn parse_number<T: TryFrom<u64>>(text: &str) -> Result<T, String> {
+    let value = match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse::<u64>(),
+    }
+    .map_err(|e| format!("Invalid number {:?}: {}", text, e))?;
+    T::try_from(value).map_err(|_| format!("Number out of range: {:?}", text))
+}
+
+/// Saves a triad as single-triad patch file at the start of the MSRAM, not bound to a processor
+///
+/// # Arguments
+///
+/// * `path` - Path of the patch file
+/// * `triad` - The triad to save
+fn dump_patch_file(path: &Path, triad: &Triad) -> Result<(), String> {
+    let triad = PatchTriad::try_from(triad)
+        .map_err(|e| format!("Failed to assemble the triad: {:?}", e))?;
+    let file = PatchFile {
+        cpuid: 0,
+        addr: UCInstructionAddress::MSRAM_START,
+        ucode_patch: vec![[
+            triad.instructions[0] as usize,
+            triad.instructions[1] as usize,
+            triad.instructions[2] as usize,
+            triad.sequence_word as usize,
+        ]],
+        labels: Vec::new(),
+    };
+
+    let mut writer = std::fs::File::create(path)
+        .map_err(|e| format!("Failed to create file: {:?} - {:?}", path, e))?;
+    file.save(&mut writer)
+        .map_err(|e| format!("Failed to write file: {:?} - {:?}", path, e))
+}
+
+/// Parses an instruction given either as mnemonic, e.g. `tmp0:= ZEROEXT_DSZ64(0x42)`, or as hex value
+fn parse_instruction(text: &str) -> Option<Instruction> {
+    match Instruction::parse(text) {
+        Ok(instruction) => Some(instruction),
+        Err(err) => match u64::from_str_radix(text, 16) {
+            Ok(value) => Some(Instruction::disassemble(value)),
+            Err(_) => {
+                error!("Failed to parse instruction {:?}: {:?}", text, err);
+                None
+            }
+        },
+    }
 }
 
 #[tokio::main]
-async fn main() {
+async fn main() -> std::process::ExitCode {
     env_logger::init();
 
     let args = Args::parse();
     let mut reboot_state = false;
 
-    if let Err(err) = performance_timing::initialize(P0_FREQ) {
+    if let Cmd::Corpus {
+        action:
+            CorpusCmd::Build {
+                files,
+                output,
+                bitness,
+            },
+    } = &args.cmd
+    {
+        return build_corpus(files, output, *bitness).into();
+    }
+
+    let file_config = match &args.config {
+        Some(path) => match RigConfig::from_file(path) {
+            Ok(config) => config,
+            Err(err) => {
+                error!("Failed to load the config file {:?}: {:?}", path, err);
+                return MasterExitCode::BadArguments.into();
+            }
+        },
+        None => RigConfig::default(),
+    };
+    let config = file_config
+        .overridden_by(RigConfig::from_env())
+        .overridden_by(args.rig.to_config());
+
+    if let Err(err) = performance_timing::initialize(config.p0_freq()) {
         error!("Failed to initialize performance timing: {:?}", err);
-        return;
+        return MasterExitCode::EnvironmentUnavailable.into();
     }
 
     let database_file = args
@@ -225,7 +529,7 @@ async fn main() {
                 let db = Database::empty(&database_file);
                 db
             } else {
-                std::process::exit(1);
+                MasterExitCode::InputUnavailable.exit();
             }
         },
         |x| x,
@@ -233,27 +537,36 @@ async fn main() {
     info!("Loaded database from {:?}", &database.path);
     database.compression = Compression::new(args.compression);
 
-    let interface = Arc::new(FuzzerNodeInterface::new(&args.instrumentor));
-    let mut udp = DeviceConnection::new(&args.agent)
+    let interface = Arc::new(
+        FuzzerNodeInterface::new(config.instrumentor())
+            .with_power_on_timings(config.power_on_boot_wait(), config.power_on_uefi_wait()),
+    );
+    let mut udp = DeviceConnection::new(config.agent(), config.ota_key())
         .await
         .expect("failed to create agent socket");
+    udp.set_retry_policy(config.resent_attempts(), config.ack_timeout());
 
-    let corpus_vec = if let Cmd::Genetic { corpus, .. } | Cmd::AFL { corpus, .. } = &args.cmd {
+    let corpus_vec = if let Cmd::Genetic { corpus, .. }
+    | Cmd::AFL { corpus, .. }
+    | Cmd::InstructionMutation { corpus } = &args.cmd
+    {
         if let Some(corpus) = corpus {
             let file_reader = match std::fs::File::open(&corpus) {
                 Ok(f) => f,
                 Err(e) => {
                     error!("Failed to open the corpus file: {:?}", e);
-                    return;
+                    return MasterExitCode::InputUnavailable.into();
                 }
             };
             let buf_reader = std::io::BufReader::new(file_reader);
             let result: serde_json::Result<InstructionCorpus> = serde_json::from_reader(buf_reader);
             match result {
                 Ok(corpus) => {
-                    info!("Loaded corpus");
-                    let data = corpus.instructions.into_iter().collect_vec();
-                    Some(data)
+                    info!(
+                        "Loaded corpus of {} instructions",
+                        corpus.instructions.len()
+                    );
+                    Some(corpus)
                 }
                 Err(e) => {
                     error!("Failed to load the corpus: {:?}", e);
@@ -271,15 +584,23 @@ async fn main() {
         Ok(x) => {
             if !x {
                 eprintln!("Fuzzer node HTTP is not alive");
-                std::process::exit(-1);
+                return MasterExitCode::DeviceUnrecoverable.into();
             }
         }
         Err(e) => {
             eprintln!("Failed to connect to the fuzzer node HTTP: {:?}", e);
-            std::process::exit(-1);
+            return MasterExitCode::DeviceUnrecoverable.into();
         }
     }
 
+    if let Cmd::Manual { execution, .. }
+    | Cmd::AFL { execution, .. }
+    | Cmd::Genetic { execution, .. } = &args.cmd
+    {
+        udp.set_execution_config(execution.to_config());
+        udp.set_coverage_granularity(execution.coverage_granularity);
+    }
+
     guarantee_initial_state(&interface, &mut udp).await;
 
     // get blacklisted
@@ -340,9 +661,10 @@ async fn main() {
         solutions,
         printable_input_generation,
         afl_corpus,
+        execution: _,
     } = &args.cmd
     {
-        return afl_fuzzing::afl_main(
+        afl_fuzzing::afl_main(
             &mut udp,
             &interface,
             &mut database,
@@ -355,6 +677,7 @@ async fn main() {
             *printable_input_generation,
         )
         .await;
+        return save_before_exit(&mut database).await.into();
     }
 
     let mut state_breeding = BreedingState::default();
@@ -385,6 +708,8 @@ async fn main() {
                 corpus,
                 timeout_hours,
                 disable_feedback,
+                pareto,
+                execution: _,
             } => {
                 if let Some(timeout) = timeout_hours {
                     if start_time.elapsed().as_secs_f64() / (60.0 * 60.0) > *timeout as f64 {
@@ -402,7 +727,7 @@ async fn main() {
                 let _timing = TimeMeasurement::begin("host::fuzzing_loop");
                 if corpus.is_some() && corpus_vec.is_none() {
                     error!("Corpus file is not loaded");
-                    return;
+                    return MasterExitCode::InputUnavailable.into();
                 }
                 genetic_breeding::main(
                     &mut udp,
@@ -411,16 +736,26 @@ async fn main() {
                     &mut state_breeding,
                     corpus.as_ref().map(|_v| corpus_vec.as_ref().unwrap()),
                     !disable_feedback,
+                    if *pareto {
+                        RatingMode::Pareto
+                    } else {
+                        RatingMode::Lexicographic
+                    },
                 )
                 .await
             }
-            Cmd::InstructionMutation => {
+            Cmd::InstructionMutation { corpus } => {
                 let _timing = TimeMeasurement::begin("host::fuzzing_loop");
+                if corpus.is_some() && corpus_vec.is_none() {
+                    error!("Corpus file is not loaded");
+                    return MasterExitCode::InputUnavailable.into();
+                }
                 instruction_mutations::main(
                     &mut udp,
                     &interface,
                     &mut database,
                     &mut state_instructions,
+                    corpus_vec.as_ref(),
                 )
                 .await
             }
@@ -431,6 +766,7 @@ async fn main() {
                 no_crbus,
                 exclude,
                 fuzzy_pmc,
+                every_opcode,
             } => {
                 let _timing = TimeMeasurement::begin("host::spec_fuzz_loop");
                 spec_fuzz::main(
@@ -443,22 +779,21 @@ async fn main() {
                     *no_crbus,
                     exclude.as_ref(),
                     *fuzzy_pmc,
+                    *every_opcode,
                 )
                 .await
             }
             Cmd::SpecManual {
                 instruction,
                 sequence_word,
+                baseline,
+                dump_patch,
             } => {
                 let mut instruction = instruction
                     .as_ref()
-                    .map(|hex| {
-                        hex.iter()
-                            .map(|hex| {
-                                u64::from_str_radix(hex, 16)
-                                    .ok()
-                                    .map(Instruction::disassemble)
-                            })
+                    .map(|text| {
+                        text.iter()
+                            .map(|text| parse_instruction(text))
                             .collect_vec()
                     })
                     .unwrap_or(vec![Some(Instruction::NOP)]);
@@ -480,7 +815,7 @@ async fn main() {
                 let sequence_word = sequence_word
                     .as_ref()
                     .and_then(|hex| u32::from_str_radix(hex, 16).ok())
-                    .map(SequenceWord::disassemble)
+                    .map(SequenceWord::disassemble_checked)
                     .map(|s| {
                         s.unwrap_or_else(|e| {
                             error!("Invalid sequence word: {:?}", e);
@@ -488,14 +823,48 @@ async fn main() {
                         })
                     });
 
-                let result = net::net_speculative_sample(
-                    &mut udp,
+                let baseline_triad = baseline.as_ref().map(|hex| {
+                    let baseline = hex
+                        .iter()
+                        .map(|hex| match parse_instruction(hex) {
+                            Some(x) => x,
+                            None => {
+                                error!("Invalid baseline instruction provided");
+                                Instruction::NOP
+                            }
+                        })
+                        .collect_vec();
                     [
+                        baseline.get(0).map(|x| *x).unwrap_or(Instruction::NOP),
+                        baseline.get(1).map(|x| *x).unwrap_or(Instruction::NOP),
+                        baseline.get(2).map(|x| *x).unwrap_or(Instruction::NOP),
+                    ]
+                });
+
+                let triad = Triad {
+                    instructions: [
                         instruction.get(0).map(|x| *x).unwrap_or(Instruction::NOP),
                         instruction.get(1).map(|x| *x).unwrap_or(Instruction::NOP),
                         instruction.get(2).map(|x| *x).unwrap_or(Instruction::NOP),
                     ],
-                    sequence_word.unwrap_or(SequenceWord::NOP),
+                    sequence_word: sequence_word.unwrap_or(SequenceWord::NOP),
+                };
+
+                if let Some(path) = dump_patch {
+                    match dump_patch_file(path, &triad) {
+                        Ok(()) => info!("Saved the patch to {:?}", path),
+                        Err(err) => {
+                            error!("Failed to save the patch: {}", err);
+                            return MasterExitCode::Failure.into();
+                        }
+                    }
+                }
+
+                let result = net::net_speculative_sample_with_baseline(
+                    &mut udp,
+                    triad.instructions,
+                    baseline_triad,
+                    triad.sequence_word,
                     vec![
                         x86_perf_counter::INSTRUCTIONS_RETIRED,
                         x86_perf_counter::MS_DECODED_MS_ENTRY,
@@ -511,20 +880,48 @@ async fn main() {
                         CommandExitResult::ExitProgram
                     }
                     ExecuteSampleResult::Rerun => CommandExitResult::RetryOrReconnect,
-                    ExecuteSampleResult::Success(mut data) => {
-                        for (val, name) in data.perf_counters.iter().zip([
-                            "iRetired",
-                            "msDecoded",
-                            "uOpsIssued",
-                            "uOpsRetired",
-                        ]) {
-                            println!("{}: {}", name, val);
+                    ExecuteSampleResult::Success((mut data, baseline, events)) => {
+                        for event in &events {
+                            warn!("Execution event: {:?}", event);
+                        }
+                        database.push_speculation_events(
+                            triad.instructions,
+                            triad.sequence_word,
+                            events,
+                        );
+
+                        let counter_names = ["iRetired", "msDecoded", "uOpsIssued", "uOpsRetired"];
+                        for (index, (val, name)) in
+                            data.perf_counters.iter().zip(counter_names).enumerate()
+                        {
+                            if data.overflowed.get(index).copied().unwrap_or(false) {
+                                println!("{}: {} (overflowed)", name, val);
+                            } else {
+                                println!("{}: {}", name, val);
+                            }
                         }
 
                         data.arch_before.rflags = 0x202; // a bit hacky, todo: do it properly
 
-                        for (name, before, after) in data.arch_before.difference(&data.arch_after) {
-                            println!("DIFF {}: {:#x?} -> {:#x?}", name, before, after);
+                        print!(
+                            "{}",
+                            DifferenceFormatter::default()
+                                .with_prefix("DIFF ", "", "")
+                                .with_alternate()
+                                .format(&data.arch_before.difference(&data.arch_after))
+                        );
+
+                        if let Some(mut baseline) = baseline {
+                            baseline.arch_before.rflags = 0x202;
+                            println!("Baseline: {:?}", baseline.perf_counters);
+                            for (delta, name) in data
+                                .counter_deltas(&baseline)
+                                .into_iter()
+                                .zip(counter_names)
+                            {
+                                println!("{} delta: {:+}", name, delta);
+                            }
+                            println!("Verdict: {:?}", analyze_leak(&data, &baseline));
                         }
 
                         CommandExitResult::ExitProgram
@@ -546,12 +943,14 @@ async fn main() {
                     content:
                         OtaD2CTransport::Capabilities {
                             coverage_collection,
+                            coverage_batch_size,
                             manufacturer,
                             processor_version_eax,
                             processor_version_ebx,
                             processor_version_ecx,
                             processor_version_edx,
                             pmc_number,
+                            ucode_revision,
                         },
                     ..
                 })) = udp
@@ -571,6 +970,7 @@ async fn main() {
                 {
                     println!("Capabilities:");
                     println!(" - Coverage collection: {}", coverage_collection);
+                    println!(" - Coverage batch size: {}", coverage_batch_size);
                     println!(" - Manufacturer: {}", manufacturer);
                     println!(" - PMC count: {}", pmc_number);
                     println!(
@@ -580,20 +980,111 @@ async fn main() {
                         processor_version_ecx,
                         processor_version_edx
                     );
+                    println!(" - Microcode revision: {:#x}", ucode_revision);
                     CommandExitResult::ExitProgram
                 } else {
                     CommandExitResult::RetryOrReconnect
                 }
             }
-            Cmd::Performance => {
+            Cmd::Msr { action } => {
+                let (msr, write) = match action {
+                    MsrCmd::Read { msr } => (*msr, None),
+                    MsrCmd::Write { msr, value } => (*msr, Some(*value)),
+                };
+
+                match net_access_msr(&mut udp, msr, write, Duration::from_secs(3)).await {
+                    Some(Ok(value)) => {
+                        println!("MSR {:#x}: {:#018x}", msr, value);
+                        CommandExitResult::ExitProgram
+                    }
+                    Some(Err(err)) => {
+                        error!("Failed to access MSR {:#x}: {}", msr, err);
+                        CommandExitResult::ExitProgram
+                    }
+                    None => CommandExitResult::RetryOrReconnect,
+                }
+            }
+            Cmd::Ping { count } => {
+                let mut latencies = Vec::with_capacity(*count as usize);
+                for _ in 0..*count {
+                    match measure_latency(&mut udp, Duration::from_secs(3)).await {
+                        Some(latency) => latencies.push(latency),
+                        None => warn!("No pong received"),
+                    }
+                }
+
+                if latencies.is_empty() {
+                    CommandExitResult::RetryOrReconnect
+                } else {
+                    let average = latencies.iter().sum::<Duration>() / latencies.len() as u32;
+                    println!(
+                        "Round-trip latency of {} out of {} pings:",
+                        latencies.len(),
+                        count
+                    );
+                    println!(" - min: {:?}", latencies.iter().min().unwrap());
+                    println!(" - avg: {:?}", average);
+                    println!(" - max: {:?}", latencies.iter().max().unwrap());
+                    CommandExitResult::ExitProgram
+                }
+            }
+            Cmd::Scenario {
+                name,
+                payload,
+                output,
+            } => {
+                let payload = match std::fs::read_to_string(payload) {
+                    Ok(json) => json,
+                    Err(err) => {
+                        error!("Failed to read scenario payload {:?}: {}", payload, err);
+                        return MasterExitCode::InputUnavailable.into();
+                    }
+                };
+                let payload = match scenario::encode_payload(name, &payload) {
+                    Ok(payload) => payload,
+                    Err(err) => {
+                        error!("{}", err);
+                        return MasterExitCode::BadArguments.into();
+                    }
+                };
+
+                match net_run_scenario(&mut udp, name, payload, Duration::from_secs(30)).await {
+                    Some(Ok(data)) => {
+                        match scenario::decode_result(name, &data) {
+                            Ok(result) => {
+                                if let Some(output) = output {
+                                    if let Err(err) = std::fs::write(output, &result) {
+                                        error!("Failed to write scenario result: {}", err);
+                                    }
+                                } else {
+                                    println!("{}", result);
+                                }
+                            }
+                            Err(err) => error!("{}", err),
+                        }
+                        CommandExitResult::ExitProgram
+                    }
+                    Some(Err(err)) => {
+                        error!("Agent failed to run scenario {}: {}", name, err);
+                        CommandExitResult::ExitProgram
+                    }
+                    None => CommandExitResult::RetryOrReconnect,
+                }
+            }
+            Cmd::Performance { tui, delta } => {
                 let x = udp.send(OtaC2DTransport::AreYouThere).await;
                 if let Err(_) = x {
                     CommandExitResult::RetryOrReconnect
                 } else {
                     let data =
-                        net_receive_performance_timing(&mut udp, Duration::from_secs(5)).await;
+                        net_receive_performance_timing(&mut udp, Duration::from_secs(5), *delta)
+                            .await;
 
-                    let mut acc = database.data.performance.normalize();
+                    let mut acc = if *delta {
+                        MeasurementCollection::from(BTreeMap::new())
+                    } else {
+                        database.data.performance.normalize()
+                    };
 
                     if let Some(data) = data {
                         acc.data
@@ -603,7 +1094,20 @@ async fn main() {
                             .extend(data.into_iter());
                     }
 
-                    println!("{}", acc);
+                    if *tui {
+                        if let Err(err) = perf_tui::main(&acc) {
+                            error!("Failed to show performance measurements: {:?}", err);
+                            println!("{}", acc);
+                        }
+                    } else {
+                        println!("{}", acc);
+                    }
+
+                    println!("Controller link: {}", udp.link_stats());
+                    match net_receive_link_stats(&mut udp, Duration::from_secs(5)).await {
+                        Some(stats) => println!("Agent link: {}", stats),
+                        None => warn!("Agent did not report link statistics"),
+                    }
 
                     CommandExitResult::ExitProgram
                 }
@@ -616,6 +1120,7 @@ async fn main() {
                 max_iterations,
                 print_mem_access,
                 no_coverage_collection,
+                execution: _,
             } => {
                 manual_execution::main(
                     &mut udp,
@@ -727,6 +1232,9 @@ async fn main() {
             Cmd::AFL { .. } => {
                 unreachable!("if statement above already governs this path")
             }
+            Cmd::Corpus { .. } => {
+                unreachable!("corpus commands are handled before connecting to the agent")
+            }
         };
 
         let connection_lost = match result {
@@ -765,7 +1273,8 @@ async fn main() {
         if last_time_perf_from_device.elapsed() > Duration::from_secs(60 * 60) {
             // each 60 min
             trace!("Querying device performance values");
-            let perf = net_receive_performance_timing(&mut udp, Duration::from_secs(5)).await;
+            let perf =
+                net_receive_performance_timing(&mut udp, Duration::from_secs(5), false).await;
             if let Some(perf) = perf {
                 last_time_perf_from_device = Instant::now();
                 database.set_device_performance(perf.into());
@@ -775,7 +1284,5 @@ async fn main() {
         let _ = timing.stop();
     }
 
-    let _ = database.save().await.map_err(|e| {
-        error!("Failed to save the database: {:?}", e);
-    });
+    save_before_exit(&mut database).await.into()
 }
@@ -4,7 +4,7 @@
 
 use clap::Parser;
 use fuzzer_data::ExecutionResult;
-use hypervisor::state::StateDifference;
+use hypervisor::state::{DifferenceFormatter, StateDifference};
 use itertools::Itertools;
 use std::path::PathBuf;
 
@@ -73,9 +73,12 @@ pub fn main() {
 
     if exit1.state != exit2.state {
         println!("Different state");
-        for (name, a, b) in exit1.state.difference(&exit2.state) {
-            println!(" - {name} {a:x?} -> {b:x?}");
-        }
+        print!(
+            "{}",
+            DifferenceFormatter::default()
+                .with_field_separator(" ")
+                .format(&exit1.state.difference(&exit2.state))
+        );
     } else {
         println!("- same state - ");
     }
@@ -13,6 +13,7 @@ use std::io::BufReader;
 use std::io::Write;
 use std::path::PathBuf;
 use ucode_compiler_dynamic::instruction::Instruction;
+use ucode_compiler_dynamic::opcodes::Opcode;
 
 /// Command-line arguments for the speculative execution comparison tool
 #[derive(Parser, Debug)]
@@ -102,6 +103,24 @@ pub fn main() {
     println!("  Unstable: {}", count_unstable);
     println!("  Total: {}", resulting_grade.len());
 
+    let mut by_class = BTreeMap::new();
+    for (key, value) in resulting_grade.iter() {
+        for class in opcode_classes(key.0.opcode()) {
+            let entry = by_class.entry(class).or_insert([0usize; 3]);
+            match value {
+                ResultFlag::StableRuns => entry[0] += 1,
+                ResultFlag::StableTimeout => entry[1] += 1,
+                ResultFlag::Unstable => entry[2] += 1,
+            }
+        }
+    }
+
+    println!();
+    println!("Opcode classes (stable runs, stable timeouts, unstable):");
+    for (class, [runs, timeouts, unstable]) in by_class.iter() {
+        println!("  {class:<18} {runs:>6} {timeouts:>6} {unstable:>6}");
+    }
+
     if let Some(output_unstable) = args.output_unstable {
         let file = File::create(output_unstable).expect("Failed to create output file");
         let mut buf_writer = std::io::BufWriter::new(file);
@@ -225,6 +244,35 @@ pub fn main() {
     }
 }
 
+/// Gets the names of the classes an opcode belongs to
+///
+/// # Arguments
+///
+/// * `opcode` - The opcode to classify
+///
+/// # Returns
+///
+/// An iterator over the class names, empty for unknown opcodes
+fn opcode_classes(opcode: Opcode) -> impl Iterator<Item = &'static str> {
+    [
+        ("crbus", opcode.is_crbus_access()),
+        ("uram", opcode.is_uram_access()),
+        ("memory load", opcode.is_memory_load()),
+        ("memory store", opcode.is_memory_store()),
+        ("branch", opcode.is_branch()),
+        ("arithmetic", opcode.is_arithmetic()),
+        ("writes flags", opcode.writes_flags()),
+        ("data move", opcode.is_data_move()),
+        ("simd", opcode.is_simd()),
+        ("floating point", opcode.is_floating_point()),
+        ("sequencer control", opcode.is_sequencer_control()),
+        ("unknown", opcode.is_group_UNKNOWN()),
+    ]
+    .into_iter()
+    .filter(|(_, member)| *member)
+    .map(|(name, _)| name)
+}
+
 /// Disassembles an microcode operation using the UASM tool
 ///
 /// # Arguments
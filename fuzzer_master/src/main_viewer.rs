@@ -6,7 +6,7 @@ use clap::Parser;
 use coverage::harness::coverage_harness::modification_engine::{
     modify_triad_for_hooking, ModificationEngineSettings,
 };
-use data_types::addresses::UCInstructionAddress;
+use data_types::addresses::{Address, UCInstructionAddress};
 use fuzzer_master::database::{BlacklistEntry, CodeEvent, ExcludeType, Timestamp};
 use hypervisor::state::{StateDifference, VmExitReason};
 use itertools::Itertools;
@@ -79,16 +79,33 @@ pub fn main() {
                 let content = content
                     .iter()
                     .filter(|x| **x != 0)
-                    .filter(|x| x.is_ascii_hexdigit() || x.is_ascii_whitespace())
                     .map(|x| *x as char)
                     .collect::<String>();
+                let mut malformed_lines = 0;
                 let additional_blacklist: BTreeSet<u16> = content
                     .lines()
-                    .filter_map(|line| u16::from_str_radix(line.trim(), 16).ok())
+                    .map(str::trim)
+                    .filter(|line| {
+                        !(line.starts_with("//") || line.starts_with('#') || line.is_empty())
+                    })
+                    .filter_map(|line| match line.parse::<UCInstructionAddress>() {
+                        Ok(address) => Some(address.address() as u16),
+                        Err(e) => {
+                            eprintln!(
+                                "Skipping malformed line in {:?}: {:?}: {}",
+                                file.path(),
+                                line,
+                                e
+                            );
+                            malformed_lines += 1;
+                            None
+                        }
+                    })
                     .collect();
                 println!(
-                    " -> {} additional blacklisted addresses: {:x?}",
+                    " -> {} additional blacklisted addresses, {} malformed lines skipped: {:x?}",
                     additional_blacklist.len(),
+                    malformed_lines,
                     additional_blacklist
                 );
                 for address in additional_blacklist {
@@ -126,8 +143,10 @@ pub fn main() {
                 code,
             } = event
             {
-                if *serialized_exit == Some(VmExitReason::TimerExpiration)
-                    || result.exit == VmExitReason::TimerExpiration
+                if serialized_exit
+                    .as_ref()
+                    .is_some_and(VmExitReason::is_timeout)
+                    || result.exit.is_timeout()
                 {
                     continue;
                 }
@@ -159,8 +178,10 @@ pub fn main() {
                 code: _,
             } = event
             {
-                if *serialized_exit == Some(VmExitReason::TimerExpiration)
-                    || result.exit == VmExitReason::TimerExpiration
+                if serialized_exit
+                    .as_ref()
+                    .is_some_and(VmExitReason::is_timeout)
+                    || result.exit.is_timeout()
                 {
                     continue;
                 }
@@ -207,8 +228,8 @@ pub fn main() {
                 coverage_state,
             } = event
             {
-                if matches!(coverage_exit, Some(VmExitReason::TimerExpiration))
-                    | matches!(result.exit, VmExitReason::TimerExpiration)
+                if coverage_exit.as_ref().is_some_and(VmExitReason::is_timeout)
+                    | result.exit.is_timeout()
                 {
                     continue;
                 }
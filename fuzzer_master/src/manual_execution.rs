@@ -215,12 +215,22 @@ pub async fn main<A: AsRef<Path>, B: AsRef<Path>>(
                 .iter()
                 .filter(|e| matches!(e, fuzzer_data::ReportExecutionProblem::AccessCoverageArea))
                 .count();
+            let count_initial_state_sensitivity = events
+                .iter()
+                .filter(|e| {
+                    matches!(
+                        e,
+                        fuzzer_data::ReportExecutionProblem::InitialStateSensitivity { .. }
+                    )
+                })
+                .count();
             let other = events.len()
                 - count_very_like_bug
                 - count_serialized_mismatch
                 - count_trace_mismatch
                 - count_coverage_mismatch
-                - count_mem_override;
+                - count_mem_override
+                - count_initial_state_sensitivity;
 
             println!("\nEvent summary:");
             println!("  - Very likely bug: {}", count_very_like_bug);
@@ -228,6 +238,10 @@ pub async fn main<A: AsRef<Path>, B: AsRef<Path>>(
             println!("  - State trace mismatch: {}", count_trace_mismatch);
             println!("  - Coverage mismatch: {}", count_coverage_mismatch);
             println!("  - Memory override: {}", count_mem_override);
+            println!(
+                "  - Initial state sensitivity: {}",
+                count_initial_state_sensitivity
+            );
             println!("  - Other: {}", other);
 
             output_text.push_str("\nHREs:\n");
@@ -410,6 +424,25 @@ pub async fn main<A: AsRef<Path>, B: AsRef<Path>>(
                     }
                 }
             }
+            for event in events.iter() {
+                if let ReportExecutionProblem::InitialStateSensitivity {
+                    state_seeds,
+                    exits,
+                    registers,
+                } = event
+                {
+                    output_text.push_str(&format!(
+                        "- Initial state sensitivity (seeds {:016x}, {:016x}):\n",
+                        state_seeds.0, state_seeds.1
+                    ));
+                    if let Some((exit_a, exit_b)) = exits {
+                        output_text.push_str(&format!("   Exit: {:x?} -> {:x?}\n", exit_a, exit_b));
+                    }
+                    if !registers.is_empty() {
+                        output_text.push_str(&format!("   Registers: {}\n", registers.join(", ")));
+                    }
+                }
+            }
 
             output_text.push_str("\n\nEvents:\n");
             output_text.push_str(&format!(
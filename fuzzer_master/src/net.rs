@@ -2,23 +2,26 @@
 //!
 //! This module provides utility functions to command a fuzzing agent via network communication
 
-use crate::database::{Database, ExcludeType};
+use crate::database::{CrashRecord, Database, ExcludeType, Timestamp};
 use crate::device_connection::DeviceConnection;
 use crate::fuzzer_node_bridge::FuzzerNodeInterface;
 use crate::genetic_breeding::SAMPLE_TIMEOUT;
 use crate::manual_execution::disassemble_code;
 use crate::{wait_for_device, CommandExitResult, WaitForDeviceResult};
+use fuzzer_data::decoder::disassemble_code_to_string;
+use fuzzer_data::genetic_pool::SampleProvenance;
 use fuzzer_data::{
-    Code, ExecutionResult, MemoryAccess, Ota, OtaC2DTransport, OtaD2CTransport,
-    ReportExecutionProblem, SpeculationResult, TraceResult,
+    Code, CrashPhase, ExecutionConfig, ExecutionResult, LinkStats, MemoryAccess, MsrAccessError,
+    Ota, OtaC2DTransport, OtaD2CTransport, ReportExecutionProblem, SpeculationResult, TraceResult,
 };
 use hypervisor::state::{VmExitReason, VmState};
 use itertools::Itertools;
 use log::{error, info, trace, warn};
 use performance_timing::measurements::MeasureValues;
+use poc_data::ScenarioResponse;
 use rand::random;
 use std::collections::BTreeMap;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use ucode_compiler_dynamic::instruction::Instruction;
 use ucode_compiler_dynamic::sequence_word::SequenceWord;
 use x86_perf_counter::PerfEventSpecifier;
@@ -257,6 +260,59 @@ pub async fn net_receive_excluded_addresses(net: &mut DeviceConnection) -> Optio
     }
 }
 
+/// Takes the crash report the device sends after booting from the received packets
+///
+/// The report is sent before the device accepts commands, so it is received before the
+/// answer to any command; it is not waited for.
+///
+/// # Arguments
+///
+/// * `net` - Network connection to the device
+///
+/// # Returns
+///
+/// * `Option<(u32, u64, CrashPhase)>` - Crash count, fingerprint of the crashed sample and
+///   phase of the crash, `None` if the device did not crash
+pub async fn net_receive_crash_report(
+    net: &mut DeviceConnection,
+) -> Option<(u32, u64, CrashPhase)> {
+    let result = net
+        .receive_packet(
+            |p| {
+                matches!(
+                    p,
+                    Ota::Transport {
+                        content: OtaD2CTransport::CrashReport { .. },
+                        ..
+                    }
+                )
+            },
+            None,
+        )
+        .await;
+
+    match result {
+        Ok(Some(Ota::Transport {
+            content:
+                OtaD2CTransport::CrashReport {
+                    crash_count,
+                    sample_fingerprint,
+                    phase,
+                },
+            ..
+        })) => Some((crash_count, sample_fingerprint, phase)),
+        Ok(Some(x)) => {
+            warn!("Unexpected packet: {:?}", x);
+            None
+        }
+        Ok(None) => None,
+        Err(e) => {
+            error!("Failed to receive the crash report: {:?}", e);
+            None
+        }
+    }
+}
+
 /// Reboots the fuzzing device
 ///
 /// # Arguments
@@ -354,6 +410,8 @@ pub async fn net_receive_execution_result(
 ///
 /// * `net` - Network connection to the device
 /// * `timeout` - Timeout duration for receiving data
+/// * `delta` - Request the measurements taken since the previous report instead of the
+///   lifetime totals
 ///
 /// # Returns
 ///
@@ -361,11 +419,12 @@ pub async fn net_receive_execution_result(
 pub async fn net_receive_performance_timing(
     net: &mut DeviceConnection,
     timeout: Duration,
+    delta: bool,
 ) -> Option<BTreeMap<String, MeasureValues<f64>>> {
     let mut data = BTreeMap::default();
 
     let _ = net
-        .send(OtaC2DTransport::ReportPerformanceTiming)
+        .send(OtaC2DTransport::ReportPerformanceTiming { delta })
         .await
         .map_err(|e| {
             error!("Failed to send ReportPerformanceTiming: {:?}", e);
@@ -468,6 +527,39 @@ pub async fn net_fuzzing_pretext(
             }
         }
 
+        if let Some((crash_count, sample_fingerprint, phase)) = net_receive_crash_report(net).await
+        {
+            // the crashed sample is only known if this controller sent it
+            let code = last_code_executed
+                .as_ref()
+                .filter(|code| SampleProvenance::fingerprint(code) == sample_fingerprint)
+                .cloned();
+            warn!(
+                "Device crashed executing sample {:016x} ({:?}), {} crashes so far",
+                sample_fingerprint, phase, crash_count
+            );
+            if let Some(code) = &code {
+                // the device does not report the instruction pointer of a crash
+                warn!(
+                    "Crashed sample:\n{}",
+                    disassemble_code_to_string(code, None)
+                );
+            }
+            let blacklisted = database.record_crash(CrashRecord {
+                sample_fingerprint,
+                code,
+                phase,
+                crash_count,
+                found_on: Timestamp::now(),
+            });
+            if blacklisted {
+                warn!(
+                    "Sample {:016x} repeatedly crashed the device and is blacklisted",
+                    sample_fingerprint
+                );
+            }
+        }
+
         match last_reported_exclusion {
             Some((last_address, times)) if *last_address == address => {
                 *last_reported_exclusion = Some((*last_address, *times + 1));
@@ -493,19 +585,28 @@ pub async fn net_fuzzing_pretext(
 ///
 /// # Returns
 ///
-/// * `Option<SpeculationResult>` containing speculative execution results
+/// * `Option<(SpeculationResult, Option<SpeculationResult>, Vec<ReportExecutionProblem>)>`
+///   containing the speculative execution results of the tested triad and of the baseline
+///   triad, if one was requested, and the events reported during execution
 pub async fn net_receive_speculative_result(
     net: &mut DeviceConnection,
     timeout: Duration,
-) -> Option<SpeculationResult> {
+) -> Option<(
+    SpeculationResult,
+    Option<SpeculationResult>,
+    Vec<ReportExecutionProblem>,
+)> {
+    let mut events = Vec::new();
+
     loop {
         let packet = net.receive(Some(timeout)).await;
 
         if let Some(packet) = packet {
             if let Ota::Transport { content, .. } = packet {
                 match content {
-                    OtaD2CTransport::UCodeSpeculationResult(result) => {
-                        return Some(result);
+                    OtaD2CTransport::ExecutionEvents(new_events) => events.extend(new_events),
+                    OtaD2CTransport::UCodeSpeculationResult { result, baseline } => {
+                        return Some((result, baseline, events));
                     }
                     _ => {
                         warn!("Unexpected packet: {:?}", content);
@@ -529,18 +630,56 @@ pub async fn net_receive_speculative_result(
 ///
 /// # Returns
 ///
-/// * `ExecuteSampleResult<SpeculationResult>` containing speculative execution results
+/// * `ExecuteSampleResult<(SpeculationResult, Vec<ReportExecutionProblem>)>` containing
+///   speculative execution results and the events reported during execution
 pub async fn net_speculative_sample(
     net: &mut DeviceConnection,
     triad: [Instruction; 3],
     sequence_word: SequenceWord,
     perf_counter_setup: Vec<PerfEventSpecifier>,
-) -> ExecuteSampleResult<SpeculationResult> {
+) -> ExecuteSampleResult<(SpeculationResult, Vec<ReportExecutionProblem>)> {
+    match net_speculative_sample_with_baseline(net, triad, None, sequence_word, perf_counter_setup)
+        .await
+    {
+        ExecuteSampleResult::Timeout => ExecuteSampleResult::Timeout,
+        ExecuteSampleResult::Rerun => ExecuteSampleResult::Rerun,
+        ExecuteSampleResult::Success((result, _, events)) => {
+            ExecuteSampleResult::Success((result, events))
+        }
+    }
+}
+
+/// Executes a speculative sample together with a baseline triad on the device
+///
+/// # Arguments
+///
+/// * `net` - Network connection to the device
+/// * `triad` - Array of three instructions to execute
+/// * `baseline_triad` - Optional triad to execute as baseline, e.g. a fence
+/// * `sequence_word` - Sequence word for execution
+/// * `perf_counter_setup` - Performance counter configuration
+///
+/// # Returns
+///
+/// * `ExecuteSampleResult<(..)>` containing the results of the tested triad and of the baseline
+///   triad, and the events reported during execution
+pub async fn net_speculative_sample_with_baseline(
+    net: &mut DeviceConnection,
+    triad: [Instruction; 3],
+    baseline_triad: Option<[Instruction; 3]>,
+    sequence_word: SequenceWord,
+    perf_counter_setup: Vec<PerfEventSpecifier>,
+) -> ExecuteSampleResult<(
+    SpeculationResult,
+    Option<SpeculationResult>,
+    Vec<ReportExecutionProblem>,
+)> {
     if let Err(err) = net
         .send(OtaC2DTransport::UCodeSpeculation {
             triad,
             sequence_word,
             perf_counter_setup,
+            baseline_triad,
         })
         .await
     {
@@ -555,3 +694,417 @@ pub async fn net_speculative_sample(
         },
     )
 }
+
+/// Measures the round-trip latency to the device
+///
+/// # Arguments
+///
+/// * `net` - Network connection to the device
+/// * `timeout` - Timeout duration for receiving the pong
+///
+/// # Returns
+///
+/// * `Option<Duration>` containing the round-trip time, `None` if no pong was received
+pub async fn measure_latency(net: &mut DeviceConnection, timeout: Duration) -> Option<Duration> {
+    let nonce: u64 = random();
+    let sent_timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default();
+
+    let start = Instant::now();
+    if let Err(err) = net
+        .send(OtaC2DTransport::Ping {
+            nonce,
+            sent_timestamp,
+        })
+        .await
+    {
+        error!("Failed to send ping: {:?}", err);
+        return None;
+    }
+
+    let result = net
+        .receive_packet(
+            |p| {
+                matches!(
+                    p,
+                    Ota::Transport {
+                        content: OtaD2CTransport::Pong { nonce: n, .. },
+                        ..
+                    } if *n == nonce
+                )
+            },
+            Some(timeout),
+        )
+        .await;
+
+    match result {
+        Ok(Some(_)) => {
+            let latency = start.elapsed();
+            trace!("Round-trip latency: {:?}", latency);
+            Some(latency)
+        }
+        Ok(None) => None,
+        Err(err) => {
+            error!("Failed to receive pong: {:?}", err);
+            None
+        }
+    }
+}
+
+/// Reads or writes a model specific register of the device
+///
+/// # Arguments
+///
+/// * `net` - Network connection to the device
+/// * `msr` - Model specific register to access
+/// * `write` - Value to write, the register is read if `None`
+/// * `timeout` - Timeout duration for receiving the reply
+///
+/// # Returns
+///
+/// * `Option<Result<u64, MsrAccessError>>` containing the value of the register or the reason
+///   the device could not access it, `None` if no reply was received
+pub async fn net_access_msr(
+    net: &mut DeviceConnection,
+    msr: u32,
+    write: Option<u64>,
+    timeout: Duration,
+) -> Option<Result<u64, MsrAccessError>> {
+    let request = match write {
+        None => OtaC2DTransport::ReadMsr { msr },
+        Some(value) => OtaC2DTransport::WriteMsr { msr, value },
+    };
+    if let Err(err) = net.send(request).await {
+        error!("Failed to send msr request: {:?}", err);
+        return None;
+    }
+
+    let result = net
+        .receive_packet(
+            |p| {
+                matches!(
+                    p,
+                    Ota::Transport {
+                        content: OtaD2CTransport::MsrValue { msr: m, .. }
+                            | OtaD2CTransport::MsrAccessFailed { msr: m, .. },
+                        ..
+                    } if *m == msr
+                )
+            },
+            Some(timeout),
+        )
+        .await;
+
+    match result {
+        Ok(Some(Ota::Transport {
+            content: OtaD2CTransport::MsrValue { value, .. },
+            ..
+        })) => Some(Ok(value)),
+        Ok(Some(Ota::Transport {
+            content: OtaD2CTransport::MsrAccessFailed { error, .. },
+            ..
+        })) => Some(Err(error)),
+        Ok(_) => None,
+        Err(err) => {
+            error!("Failed to receive msr value: {:?}", err);
+            None
+        }
+    }
+}
+
+/// Requests the link statistics of the device
+///
+/// # Arguments
+///
+/// * `net` - Network connection to the device
+/// * `timeout` - Timeout duration for receiving the statistics
+///
+/// # Returns
+///
+/// * `Option<LinkStats>` containing the link statistics of the device, `None` if none were received
+pub async fn net_receive_link_stats(
+    net: &mut DeviceConnection,
+    timeout: Duration,
+) -> Option<LinkStats> {
+    if let Err(err) = net.send(OtaC2DTransport::GetLinkStats).await {
+        error!("Failed to send GetLinkStats: {:?}", err);
+        return None;
+    }
+
+    let result = net
+        .receive_packet(
+            |p| {
+                matches!(
+                    p,
+                    Ota::Transport {
+                        content: OtaD2CTransport::LinkStats(_),
+                        ..
+                    }
+                )
+            },
+            Some(timeout),
+        )
+        .await;
+
+    match result {
+        Ok(Some(Ota::Transport {
+            content: OtaD2CTransport::LinkStats(stats),
+            ..
+        })) => Some(stats),
+        Ok(_) => None,
+        Err(err) => {
+            error!("Failed to receive link stats: {:?}", err);
+            None
+        }
+    }
+}
+
+/// Runs an evaluation scenario of the `poc_agent` on the device
+///
+/// # Arguments
+///
+/// * `net` - Network connection to the device
+/// * `name` - Name of the scenario
+/// * `payload` - Payload of the scenario, serialized with `poc_data::serialize`
+/// * `timeout` - Timeout duration for receiving the scenario result
+///
+/// # Returns
+///
+/// * `Option<ScenarioResponse>` containing the serialized result of the scenario or the reason
+///   the device could not execute it, `None` if no valid response was received
+pub async fn net_run_scenario(
+    net: &mut DeviceConnection,
+    name: &str,
+    payload: Vec<u8>,
+    timeout: Duration,
+) -> Option<ScenarioResponse> {
+    if let Err(err) = net
+        .send(OtaC2DTransport::RunScenario(name.to_string(), payload))
+        .await
+    {
+        error!("Failed to send scenario request: {:?}", err);
+        return None;
+    }
+
+    let result = net
+        .receive_packet(
+            |p| {
+                matches!(
+                    p,
+                    Ota::Transport {
+                        content: OtaD2CTransport::ScenarioResult(n, _),
+                        ..
+                    } if n == name
+                )
+            },
+            Some(timeout),
+        )
+        .await;
+
+    match result {
+        Ok(Some(Ota::Transport {
+            content: OtaD2CTransport::ScenarioResult(_, data),
+            ..
+        })) => match poc_data::deserialize(&data) {
+            Ok(response) => Some(response),
+            Err(err) => {
+                error!("Failed to decode scenario response: {}", err);
+                None
+            }
+        },
+        Ok(_) => None,
+        Err(err) => {
+            error!("Failed to receive scenario result: {:?}", err);
+            None
+        }
+    }
+}
+
+/// Sets the execution configuration of the device
+///
+/// # Arguments
+///
+/// * `net` - Network connection to the device
+/// * `config` - Requested execution configuration
+/// * `timeout` - Timeout duration for receiving the acknowledgment
+///
+/// # Returns
+///
+/// * `Option<ExecutionConfig>` containing the effective configuration of the device, which may
+///   differ from the requested one due to clamping, `None` if no acknowledgment was received
+pub async fn net_set_execution_config(
+    net: &mut DeviceConnection,
+    config: ExecutionConfig,
+    timeout: Duration,
+) -> Option<ExecutionConfig> {
+    let request = OtaC2DTransport::SetExecutionConfig {
+        max_vm_iterations: config.max_vm_iterations,
+        state_trace_len: config.state_trace_len,
+        initial_reg_seed: config.initial_reg_seed,
+        coverage_batch: config.coverage_batch,
+        sample_timeout_tsc: config.sample_timeout_tsc,
+        differential_execution: config.differential_execution,
+        reset_guest_memory: config.reset_guest_memory,
+    };
+    if let Err(err) = net.send(request).await {
+        error!("Failed to send execution config: {:?}", err);
+        return None;
+    }
+
+    let result = net
+        .receive_packet(
+            |p| {
+                matches!(
+                    p,
+                    Ota::Transport {
+                        content: OtaD2CTransport::ExecutionConfig(_),
+                        ..
+                    }
+                )
+            },
+            Some(timeout),
+        )
+        .await;
+
+    match result {
+        Ok(Some(Ota::Transport {
+            content: OtaD2CTransport::ExecutionConfig(config),
+            ..
+        })) => Some(config),
+        Ok(_) => None,
+        Err(err) => {
+            error!("Failed to receive execution config: {:?}", err);
+            None
+        }
+    }
+}
+
+/// Re-executes a code sample on the device with a hook only on a single address
+///
+/// Allows to cheaply confirm that reported coverage of the address is not a measurement artifact.
+///
+/// # Arguments
+///
+/// * `net` - Network connection to the device
+/// * `sample` - Code sample to execute
+/// * `address` - Microcode address to confirm
+/// * `timeout` - Timeout duration for receiving the result
+///
+/// # Returns
+///
+/// * `Option<bool>` whether the address was hit, `None` if the device could not confirm the coverage
+pub async fn net_confirm_coverage(
+    net: &mut DeviceConnection,
+    sample: &[u8],
+    address: u16,
+    timeout: Duration,
+) -> Option<bool> {
+    if let Err(err) = net
+        .send(OtaC2DTransport::ConfirmCoverage {
+            sample: sample.to_vec(),
+            address,
+        })
+        .await
+    {
+        error!("Failed to send ConfirmCoverage: {:?}", err);
+        return None;
+    }
+
+    let result = net
+        .receive_packet(
+            |p| {
+                matches!(
+                    p,
+                    Ota::Transport {
+                        content: OtaD2CTransport::CoverageConfirmation { address: a, .. },
+                        ..
+                    } if *a == address
+                )
+            },
+            Some(timeout),
+        )
+        .await;
+
+    match result {
+        Ok(Some(Ota::Transport {
+            content: OtaD2CTransport::CoverageConfirmation { hit, .. },
+            ..
+        })) => hit,
+        Ok(_) => None,
+        Err(err) => {
+            error!("Failed to receive coverage confirmation: {:?}", err);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::device_connection::DeviceConnection;
+    use crate::net::measure_latency;
+    use fuzzer_data::{Ota, OtaC2D, OtaC2DTransport, OtaD2C, OtaD2CTransport, OtaD2CUnreliable};
+    use std::time::Duration;
+    use tokio::net::UdpSocket;
+
+    #[tokio::test]
+    async fn test_measure_latency() {
+        let device = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let controller = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        device
+            .connect(controller.local_addr().unwrap())
+            .await
+            .unwrap();
+        controller
+            .connect(device.local_addr().unwrap())
+            .await
+            .unwrap();
+
+        // mock device answering a ping after a delay
+        let delay = Duration::from_millis(100);
+        let mock = tokio::spawn(async move {
+            let mut buffer = [0u8; 4096];
+            loop {
+                let count = device.recv(&mut buffer).await.unwrap();
+                if let Ok(Ota::Transport {
+                    session,
+                    id,
+                    content:
+                        OtaC2DTransport::Ping {
+                            nonce,
+                            sent_timestamp,
+                        },
+                }) = OtaC2D::deserialize(&buffer[..count])
+                {
+                    let ack = OtaD2C::Unreliable(OtaD2CUnreliable::Ack(id));
+                    device.send(&ack.serialize().unwrap()).await.unwrap();
+
+                    tokio::time::sleep(delay).await;
+                    let pong = OtaD2C::Transport {
+                        session,
+                        id: 1,
+                        content: OtaD2CTransport::Pong {
+                            nonce,
+                            sent_timestamp,
+                            device_cycles: 0,
+                        },
+                    };
+                    device.send(&pong.serialize().unwrap()).await.unwrap();
+                    return;
+                }
+            }
+        });
+
+        let mut net = DeviceConnection::from_socket(controller, None)
+            .await
+            .unwrap();
+        let latency = measure_latency(&mut net, Duration::from_secs(2))
+            .await
+            .expect("pong received");
+        mock.await.unwrap();
+
+        assert!(latency >= delay);
+        assert!(latency < Duration::from_secs(2));
+    }
+}
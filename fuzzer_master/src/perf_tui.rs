@@ -0,0 +1,86 @@
+//! Interactive terminal view of performance measurements
+//!
+//! Shows the accumulated measurements of the database and the fuzzer agent
+//! in a sortable table, see [`performance_timing::tui::MeasurementTable`].
+
+use performance_timing::measurements::MeasurementCollection;
+use performance_timing::tui::MeasurementTable;
+use ratatui::crossterm::event;
+use ratatui::crossterm::event::{KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::Stylize;
+use ratatui::text::Line;
+use ratatui::widgets::TableState;
+use ratatui::DefaultTerminal;
+use std::io;
+
+/// Number of rows skipped by page up/down
+const PAGE_SIZE: usize = 20;
+
+/// Shows the performance measurements until the user quits
+///
+/// # Arguments
+///
+/// * `measurements` - Normalized performance measurements
+///
+/// # Returns
+///
+/// * `io::Result<()>` - Error if the terminal could not be drawn to
+pub fn main(measurements: &MeasurementCollection<f64>) -> io::Result<()> {
+    let mut table = MeasurementTable::new(measurements);
+
+    let mut terminal = ratatui::init();
+    let result = view(&mut terminal, &mut table);
+    drop(terminal);
+    ratatui::restore();
+
+    result
+}
+
+fn view(term: &mut DefaultTerminal, table: &mut MeasurementTable) -> io::Result<()> {
+    let mut state = TableState::default().with_selected((!table.is_empty()).then_some(0));
+    let last = table.len().saturating_sub(1);
+
+    loop {
+        term.draw(|frame| {
+            let [table_area, help_area] =
+                Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+
+            frame.render_stateful_widget(&*table, table_area, &mut state);
+            frame.render_widget(
+                Line::from(vec![
+                    " Move ".into(),
+                    "<UP/DOWN/PGUP/PGDN>".blue().bold(),
+                    " Sort column ".into(),
+                    "<S>".blue().bold(),
+                    " Reverse ".into(),
+                    "<R>".blue().bold(),
+                    " Quit ".into(),
+                    "<Q> ".blue().bold(),
+                ])
+                .centered(),
+                help_area,
+            );
+        })?;
+
+        if let event::Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            let selected = state.selected().unwrap_or(0);
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('s') => table.sort_by(table.sort_key().next()),
+                KeyCode::Char('r') => table.toggle_order(),
+                KeyCode::Up => state.select(Some(selected.saturating_sub(1))),
+                KeyCode::Down => state.select(Some((selected + 1).min(last))),
+                KeyCode::PageUp => state.select(Some(selected.saturating_sub(PAGE_SIZE))),
+                KeyCode::PageDown => state.select(Some((selected + PAGE_SIZE).min(last))),
+                KeyCode::Home => state.select(Some(0)),
+                KeyCode::End => state.select(Some(last)),
+                _ => {}
+            }
+        }
+    }
+}
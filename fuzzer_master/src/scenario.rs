@@ -0,0 +1,86 @@
+//! Evaluation Scenario Module
+//!
+//! Converts the payloads and results of the `poc_agent` evaluation scenarios between their JSON
+//! representation and the serialized form exchanged with the device.
+
+use poc_data::{f0_microcode, f1_microspectre, SCENARIOS};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Parses a JSON payload of a scenario and serializes it for the device
+///
+/// # Arguments
+///
+/// * `name` - Name of the scenario
+/// * `json` - Payload of the scenario as JSON
+///
+/// # Returns
+///
+/// * `Result<Vec<u8>, String>` - The serialized payload or an error if the scenario is unknown or the payload is invalid
+pub fn encode_payload(name: &str, json: &str) -> Result<Vec<u8>, String> {
+    fn encode<T: Serialize + DeserializeOwned>(json: &str) -> Result<Vec<u8>, String> {
+        let payload: T = serde_json::from_str(json)
+            .map_err(|err| format!("Failed to parse scenario payload: {}", err))?;
+        poc_data::serialize(&payload)
+    }
+
+    match name {
+        f0_microcode::NAME => encode::<f0_microcode::Payload>(json),
+        f1_microspectre::NAME => encode::<f1_microspectre::Payload>(json),
+        _ => Err(unknown_scenario(name)),
+    }
+}
+
+/// Deserializes a scenario result received from the device and converts it to JSON
+///
+/// # Arguments
+///
+/// * `name` - Name of the scenario
+/// * `data` - Serialized result of the scenario
+///
+/// # Returns
+///
+/// * `Result<String, String>` - The result as pretty printed JSON or an error if the result is invalid
+pub fn decode_result(name: &str, data: &[u8]) -> Result<String, String> {
+    fn decode<T: Serialize + DeserializeOwned>(data: &[u8]) -> Result<String, String> {
+        let result: T = poc_data::deserialize(data)?;
+        serde_json::to_string_pretty(&result)
+            .map_err(|err| format!("Failed to convert scenario result: {}", err))
+    }
+
+    match name {
+        f0_microcode::NAME => decode::<f0_microcode::ResultingData>(data),
+        f1_microspectre::NAME => decode::<f1_microspectre::ResultingData>(data),
+        _ => Err(unknown_scenario(name)),
+    }
+}
+
+fn unknown_scenario(name: &str) -> String {
+    format!(
+        "Unknown scenario: {}, available scenarios: {}",
+        name,
+        SCENARIOS.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scenario::{decode_result, encode_payload};
+    use poc_data::f1_microspectre::{FenceType, Payload, ResultingData};
+
+    #[test]
+    fn test_scenario_roundtrip() {
+        let payload = encode_payload(poc_data::f1_microspectre::NAME, r#"{"Execute":"CPUID"}"#)
+            .expect("valid payload");
+        assert!(matches!(
+            poc_data::deserialize(&payload),
+            Ok(Payload::Execute(FenceType::CPUID))
+        ));
+
+        let result = poc_data::serialize(&ResultingData::CacheTimings(vec![1, 2])).unwrap();
+        let json = decode_result(poc_data::f1_microspectre::NAME, &result).expect("valid result");
+        assert!(json.contains("CacheTimings"));
+
+        assert!(encode_payload("UNKNOWN", "null").is_err());
+    }
+}
@@ -6,9 +6,11 @@ use crate::database::Database;
 use crate::device_connection::DeviceConnection;
 use crate::net::{net_speculative_sample, ExecuteSampleResult};
 use crate::CommandExitResult;
-use hypervisor::state::StateDifference;
+use data_types::patch::Triad as PatchTriad;
+use fuzzer_data::SpeculationResult;
+use hypervisor::state::{GuestRegisters, StateDifference};
 use itertools::Itertools;
-use log::{error, info, trace};
+use log::{error, info, trace, warn};
 use rand::{random, SeedableRng};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::{BTreeMap, BTreeSet, VecDeque};
@@ -18,6 +20,7 @@ use std::path::Path;
 use ucode_compiler_dynamic::instruction::Instruction;
 use ucode_compiler_dynamic::opcodes::Opcode;
 use ucode_compiler_dynamic::sequence_word::SequenceWord;
+use ucode_compiler_dynamic::Triad;
 use x86_perf_counter::PerfEventSpecifier;
 
 /// Results of speculative execution
@@ -45,6 +48,77 @@ impl SpecResult {
     }
 }
 
+/// Classification of a speculation result compared to a baseline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LeakVerdict {
+    /// The result behaves like the baseline
+    NoLeak,
+    /// Architectural registers changed, that did not change in the baseline
+    ArchitecturalChange {
+        /// Names of the registers that changed unexpectedly
+        registers: BTreeSet<String>,
+        /// Delta of each performance counter to the baseline
+        pmc_delta: Vec<i64>,
+    },
+    /// Only performance counters deviate from the baseline
+    PerformanceCounterDeviation {
+        /// Delta of each performance counter to the baseline
+        pmc_delta: Vec<i64>,
+    },
+}
+
+impl LeakVerdict {
+    /// Checks if the verdict flags the result as suspicious
+    pub fn is_leak(&self) -> bool {
+        !matches!(self, LeakVerdict::NoLeak)
+    }
+}
+
+/// Classifies a speculation result by comparing it against a baseline (e.g. a NOP triad)
+///
+/// A result is flagged if an architectural register changed that did not change during the
+/// baseline execution, or if any performance counter deviates from the baseline. Counters
+/// that are not stable (see `TestIfPMCStable`) should be excluded beforehand.
+///
+/// # Arguments
+///
+/// * `result` - Speculation result of the triad under test
+/// * `baseline` - Speculation result of the baseline triad, using the same counter setup
+///
+/// # Returns
+///
+/// * `LeakVerdict` classifying the behavior of the triad
+pub fn analyze_leak(result: &SpeculationResult, baseline: &SpeculationResult) -> LeakVerdict {
+    let baseline_changes = baseline
+        .arch_before
+        .difference(&baseline.arch_after)
+        .into_iter()
+        .map(|(name, _, _)| name)
+        .collect::<BTreeSet<&'static str>>();
+
+    let registers = result
+        .arch_before
+        .difference(&result.arch_after)
+        .into_iter()
+        .map(|(name, _, _)| name)
+        .filter(|name| !baseline_changes.contains(name))
+        .map(|name| name.to_string())
+        .collect::<BTreeSet<String>>();
+
+    let pmc_delta = result.counter_deltas(baseline);
+
+    if !registers.is_empty() {
+        LeakVerdict::ArchitecturalChange {
+            registers,
+            pmc_delta,
+        }
+    } else if pmc_delta.iter().any(|delta| *delta != 0) {
+        LeakVerdict::PerformanceCounterDeviation { pmc_delta }
+    } else {
+        LeakVerdict::NoLeak
+    }
+}
+
 /// Wrapper for types that need string-based serialization
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
 pub struct StringBox<T>(pub T);
@@ -116,6 +190,9 @@ pub struct SpecReport {
     /// Map of PMC event specifiers to their baseline values
     #[serde(default)]
     pub pmc_baseline: BTreeMap<StringBox<PerfEventSpecifier>, u64>,
+    /// Map of instructions to the assembled triad that was sent for them, for re-execution
+    #[serde(default)]
+    pub patch_triads: BTreeMap<StringBox<Instruction>, PatchTriad>,
 }
 
 impl SpecReport {
@@ -125,6 +202,7 @@ impl SpecReport {
             opcodes: BTreeMap::new(),
             pmc_blacklist_event_select: BTreeSet::new(),
             pmc_baseline: BTreeMap::default(),
+            patch_triads: BTreeMap::new(),
         }
     }
 
@@ -250,6 +328,7 @@ pub fn copy_database_template<A: AsRef<Path>, B: AsRef<Path>>(source_path: A, ta
 
     File::create(target_path.as_ref()).expect("Failed to create target file");
     report.opcodes.clear();
+    report.patch_triads.clear();
     if let Err(err) = report.save_file(target_path.as_ref()) {
         error!("Failed to save the report: {:?}", err);
     } else {
@@ -268,6 +347,7 @@ pub async fn main<A: AsRef<Path>, B: AsRef<Path>>(
     no_crbus: bool,
     exclude: Option<B>,
     fuzzy_pmc: bool,
+    every_opcode: bool,
 ) -> CommandExitResult {
     // device is either restarted or new experimentation run
 
@@ -281,33 +361,51 @@ pub async fn main<A: AsRef<Path>, B: AsRef<Path>>(
 
             state.report = SpecReport::load_file(&report).unwrap_or_default();
 
-            let instructions = ucode_dump::dump::ROM_cpu_000506CA
-                .instructions()
+            let instructions = ucode_dump::dump::ROMS
                 .iter()
+                .flat_map(|rom| rom.iter_triads().flat_map(|(_, triad)| triad.instructions))
                 .map(|x| {
                     if all {
-                        Instruction::disassemble(*x)
+                        Instruction::disassemble(x)
                     } else {
-                        Instruction::from_opcode(Instruction::disassemble(*x).opcode())
+                        Instruction::from_opcode(Instruction::disassemble(x).opcode())
                     }
                 })
                 .chain(
-                    ucode_dump::dump::ROM_cpu_000506C9
-                        .instructions()
-                        .iter()
-                        .map(|x| {
-                            if all {
-                                Instruction::disassemble(*x)
-                            } else {
-                                Instruction::from_opcode(Instruction::disassemble(*x).opcode())
-                            }
-                        }),
+                    Opcode::all()
+                        .filter(|opcode| every_opcode && !opcode.is_group_UNKNOWN())
+                        .map(Instruction::from_opcode),
                 )
                 .unique()
-                .sorted_by_key(|x| x.assemble_no_crc());
+                .collect_vec();
 
-            let excluded = match exclude {
-                None => vec![],
+            // rarely used opcodes are tested first
+            let mut histogram = BTreeMap::new();
+            for rom in ucode_dump::dump::ROMS.iter() {
+                for (opcode, count) in rom.opcode_histogram() {
+                    *histogram.entry(opcode).or_insert(0) += count;
+                }
+            }
+            let occurrences = |x: &Instruction| {
+                let opcode = ((x.assemble_no_crc() >> 32) & 0xfff) as u16;
+                histogram.get(&opcode).copied().unwrap_or(0)
+            };
+
+            // instructions that only differ in their immediate value behave alike
+            let distinct = instructions.len();
+            let instructions = instructions
+                .into_iter()
+                .unique_by(|x| x.structural_key())
+                .sorted_by_key(|x| (occurrences(x), x.assemble_no_crc()))
+                .collect_vec();
+            info!(
+                "Deduplicated {} instructions to {} structurally distinct ones",
+                distinct,
+                instructions.len()
+            );
+
+            let (excluded, excluded_opcodes) = match exclude {
+                None => (vec![], vec![]),
                 Some(exclude) => {
                     let mut excluded_text = String::new();
                     File::open(exclude)
@@ -317,6 +415,7 @@ pub async fn main<A: AsRef<Path>, B: AsRef<Path>>(
                     let lines = excluded_text.lines();
 
                     let mut result = Vec::new();
+                    let mut opcodes = Vec::new();
                     for line in lines {
                         if line.is_empty() {
                             continue;
@@ -326,17 +425,24 @@ pub async fn main<A: AsRef<Path>, B: AsRef<Path>>(
                             .next()
                             .map(|v| v.to_string())
                             .unwrap_or(line.to_string());
+                        // a mnemonic excludes all instructions with this opcode
+                        if let Ok(opcode) = before_first_comma.parse::<Opcode>() {
+                            opcodes.push(opcode);
+                            continue;
+                        }
                         let parsed_hex_num = u64::from_str_radix(&before_first_comma, 16)
-                            .expect("Non hex number in exclude file");
+                            .expect("Neither mnemonic nor hex number in exclude file");
                         let instruction = Instruction::from(parsed_hex_num);
                         result.push(instruction);
                     }
-                    result
+                    (result, opcodes)
                 }
             };
 
             for instruction in instructions {
-                if !excluded.contains(&instruction) {
+                if !excluded.contains(&instruction)
+                    && !excluded_opcodes.contains(&instruction.opcode())
+                {
                     state.all_ucodes.push(instruction);
                 }
             }
@@ -390,17 +496,13 @@ pub async fn main<A: AsRef<Path>, B: AsRef<Path>>(
                     continue;
                 }
 
-                let result = net_speculative_sample(
-                    net,
-                    [
-                        Instruction::from_opcode(Opcode::ADD_DSZ32),
-                        Instruction::NOP,
-                        Instruction::NOP,
-                    ],
-                    SequenceWord::NOP,
-                    vec![pmc.clone()],
-                )
-                .await;
+                let triad = [
+                    Instruction::from_opcode(Opcode::ADD_DSZ32),
+                    Instruction::NOP,
+                    Instruction::NOP,
+                ];
+                let result =
+                    net_speculative_sample(net, triad, SequenceWord::NOP, vec![pmc.clone()]).await;
 
                 let result = match result {
                     ExecuteSampleResult::Timeout => {
@@ -419,7 +521,10 @@ pub async fn main<A: AsRef<Path>, B: AsRef<Path>>(
                         state.pmc_queue.push_back(vec![pmc.clone()]);
                         return CommandExitResult::Operational;
                     }
-                    ExecuteSampleResult::Success(x) => x,
+                    ExecuteSampleResult::Success((result, events)) => {
+                        database.push_speculation_events(triad, SequenceWord::NOP, events);
+                        result
+                    }
                 };
 
                 state.expected_rflags = result.arch_after.rflags;
@@ -474,14 +579,43 @@ pub async fn main<A: AsRef<Path>, B: AsRef<Path>>(
                             }
                         }
                     }
-                    if no_crbus && instruction.opcode().is_group_MOVETOCREG() {
+                    if no_crbus && instruction.opcode().is_crbus_access() {
+                        // the instruction has to be dequeued before skipping it, otherwise
+                        // the loop sees it again as the front of the queue and never ends
+                        state.ucode_queue.pop_front();
                         continue;
                     }
 
+                    // replay the triad sent before, if any
+                    let triad = match state
+                        .report
+                        .patch_triads
+                        .get(&StringBox(*instruction))
+                        .map(|patch| Triad::try_from(*patch))
+                    {
+                        Some(Ok(triad)) => triad,
+                        _ => {
+                            let triad = Triad {
+                                instructions: [*instruction, Instruction::NOP, Instruction::NOP],
+                                sequence_word: SequenceWord::NOP,
+                            };
+                            match PatchTriad::try_from(&triad) {
+                                Ok(patch) => {
+                                    state
+                                        .report
+                                        .patch_triads
+                                        .insert(StringBox(*instruction), patch);
+                                }
+                                Err(err) => error!("Failed to assemble triad {}: {:?}", triad, err),
+                            }
+                            triad
+                        }
+                    };
+
                     let result = net_speculative_sample(
                         net,
-                        [*instruction, Instruction::NOP, Instruction::NOP],
-                        SequenceWord::NOP,
+                        triad.instructions,
+                        triad.sequence_word,
                         vec![
                             x86_perf_counter::INSTRUCTIONS_RETIRED,
                             x86_perf_counter::MS_DECODED_MS_ENTRY,
@@ -507,22 +641,44 @@ pub async fn main<A: AsRef<Path>, B: AsRef<Path>>(
                         ExecuteSampleResult::Rerun => {
                             return CommandExitResult::Operational;
                         }
-                        ExecuteSampleResult::Success(x) => x,
+                        ExecuteSampleResult::Success((result, events)) => {
+                            database.push_speculation_events(
+                                triad.instructions,
+                                triad.sequence_word,
+                                events,
+                            );
+                            result
+                        }
                     };
 
+                    let baseline = SpeculationResult {
+                        arch_before: GuestRegisters::default(),
+                        arch_after: GuestRegisters::default(),
+                        perf_counters: pmc
+                            .iter()
+                            .map(|pmc_key| state.baseline.get(pmc_key).copied().unwrap_or(0))
+                            .collect(),
+                        overflowed: Vec::new(),
+                    };
                     let mut pmc_delta = BTreeMap::new();
-                    for (i, value) in result.perf_counters.iter().enumerate() {
-                        if let Some(pmc_key) = pmc.get(i) {
-                            if let Some(baseline) = state.baseline.get(pmc_key) {
-                                let delta = *value as i64 - *baseline as i64;
-                                pmc_delta.insert(StringBox::from(pmc_key.clone()), delta);
-                            } else {
-                                error!("No baseline for pmc {pmc_key:?}");
-                            }
+                    for (pmc_key, delta) in pmc.iter().zip(result.counter_deltas(&baseline)) {
+                        if state.baseline.contains_key(pmc_key) {
+                            pmc_delta.insert(StringBox::from(pmc_key.clone()), delta);
                         } else {
-                            error!("unknown pmc index {i}")
+                            error!("No baseline for pmc {pmc_key:?}");
                         }
                     }
+                    for (pmc_key, _) in pmc
+                        .iter()
+                        .zip(result.overflowed.iter())
+                        .filter(|(_, overflowed)| **overflowed)
+                    {
+                        warn!(
+                            "PMC {pmc_key:?} overflowed: {} : {:04x}",
+                            instruction.opcode(),
+                            instruction.assemble()
+                        );
+                    }
 
                     result.arch_before.rflags = state.expected_rflags; // todo: hacky
 
@@ -572,3 +728,73 @@ pub async fn main<A: AsRef<Path>, B: AsRef<Path>>(
 
     CommandExitResult::ExitProgram
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::spec_fuzz::{analyze_leak, LeakVerdict};
+    use fuzzer_data::SpeculationResult;
+    use hypervisor::state::GuestRegisters;
+    use std::collections::BTreeSet;
+
+    fn nop_baseline() -> SpeculationResult {
+        let before = GuestRegisters::default();
+        let mut after = before.clone();
+        after.rflags = 0x202;
+        SpeculationResult {
+            arch_before: before,
+            arch_after: after,
+            perf_counters: vec![10, 2, 30, 30],
+            overflowed: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_analyze_leak_no_leak() {
+        let baseline = nop_baseline();
+        let result = nop_baseline();
+        let verdict = analyze_leak(&result, &baseline);
+        assert_eq!(verdict, LeakVerdict::NoLeak);
+        assert!(!verdict.is_leak());
+    }
+
+    #[test]
+    fn test_analyze_leak_clear_leak() {
+        let baseline = nop_baseline();
+        let mut result = nop_baseline();
+        result.arch_after.rcx = 0x1337;
+        result.perf_counters[2] += 5;
+
+        let verdict = analyze_leak(&result, &baseline);
+        assert!(verdict.is_leak());
+        assert_eq!(
+            verdict,
+            LeakVerdict::ArchitecturalChange {
+                registers: BTreeSet::from(["rcx".to_string()]),
+                pmc_delta: vec![0, 0, 5, 0],
+            }
+        );
+
+        result.arch_after.rcx = 0;
+        assert_eq!(
+            analyze_leak(&result, &baseline),
+            LeakVerdict::PerformanceCounterDeviation {
+                pmc_delta: vec![0, 0, 5, 0],
+            }
+        );
+    }
+
+    #[test]
+    fn test_analyze_leak_counter_wraparound() {
+        let mut baseline = nop_baseline();
+        baseline.perf_counters[1] = (1 << 48) - 2;
+        let mut result = nop_baseline();
+        result.perf_counters[1] = 1;
+
+        assert_eq!(
+            analyze_leak(&result, &baseline),
+            LeakVerdict::PerformanceCounterDeviation {
+                pmc_delta: vec![0, 3, 0, 0],
+            }
+        );
+    }
+}
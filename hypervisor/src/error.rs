@@ -15,6 +15,10 @@ pub enum HypervisorError {
     /// Indicates a failure to enable virtualization features on the host CPU
     #[error("Failed to enable virtualization on the CPU")]
     FailedToInitializeHost(&'static str),
+
+    /// Indicates that a guest physical address has no translation to a host page
+    #[error("Guest physical address {0:#x} is not mapped")]
+    GuestPageNotMapped(usize),
 }
 
 /// A type alias for `Result<T, HypervisorError>`
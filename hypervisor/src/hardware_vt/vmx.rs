@@ -730,7 +730,7 @@ impl hardware_vt::HardwareVt for Vmx {
                 ))
             }
             // See: 26.5.1 VMX-Preemption Timer
-            VMX_EXIT_REASON_VMX_PREEMPTION_TIMER => VmExitReason::TimerExpiration,
+            VMX_EXIT_REASON_VMX_PREEMPTION_TIMER => VmExitReason::PreemptionTimeout,
             // See: 26.2 OTHER CAUSES OF VM EXITS
             VMX_EXIT_REASON_TRIPLE_FAULT => VmExitReason::Shutdown(vmread(vmcs::ro::EXIT_REASON)),
             VMX_EXIT_REASON_CPUID => VmExitReason::Cpuid,
@@ -4,6 +4,7 @@
 //! in the hypervisor. It includes representations of CPU registers, and VM exit handling.
 
 use alloc::boxed::Box;
+use alloc::string::String;
 use alloc::vec::Vec;
 use core::fmt::Debug;
 use core::hash::Hash;
@@ -113,14 +114,74 @@ impl GuestRegisters {
             && self.xmm14 == other.xmm14
             && self.xmm15 == other.xmm15
     }
+
+    /// Reads a register by its name
+    ///
+    /// XMM registers are accessed by `xmmN.low` and `xmmN.high`, `xmmN` is an alias for the lower half.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<u64>` - The register value, `None` if there is no register with that name
+    pub fn get_field(&self, name: &str) -> Option<u64> {
+        match name {
+            "rax" => Some(self.rax),
+            "rbx" => Some(self.rbx),
+            "rcx" => Some(self.rcx),
+            "rdx" => Some(self.rdx),
+            "rdi" => Some(self.rdi),
+            "rsi" => Some(self.rsi),
+            "rbp" => Some(self.rbp),
+            "r8" => Some(self.r8),
+            "r9" => Some(self.r9),
+            "r10" => Some(self.r10),
+            "r11" => Some(self.r11),
+            "r12" => Some(self.r12),
+            "r13" => Some(self.r13),
+            "r14" => Some(self.r14),
+            "r15" => Some(self.r15),
+            "rip" => Some(self.rip),
+            "rsp" => Some(self.rsp),
+            "rflags" => Some(self.rflags),
+            "xmm0" | "xmm0.low" => Some(self.xmm0.low),
+            "xmm0.high" => Some(self.xmm0.high as u64),
+            "xmm1" | "xmm1.low" => Some(self.xmm1.low),
+            "xmm1.high" => Some(self.xmm1.high as u64),
+            "xmm2" | "xmm2.low" => Some(self.xmm2.low),
+            "xmm2.high" => Some(self.xmm2.high as u64),
+            "xmm3" | "xmm3.low" => Some(self.xmm3.low),
+            "xmm3.high" => Some(self.xmm3.high as u64),
+            "xmm4" | "xmm4.low" => Some(self.xmm4.low),
+            "xmm4.high" => Some(self.xmm4.high as u64),
+            "xmm5" | "xmm5.low" => Some(self.xmm5.low),
+            "xmm5.high" => Some(self.xmm5.high as u64),
+            "xmm6" | "xmm6.low" => Some(self.xmm6.low),
+            "xmm6.high" => Some(self.xmm6.high as u64),
+            "xmm7" | "xmm7.low" => Some(self.xmm7.low),
+            "xmm7.high" => Some(self.xmm7.high as u64),
+            "xmm8" | "xmm8.low" => Some(self.xmm8.low),
+            "xmm8.high" => Some(self.xmm8.high as u64),
+            "xmm9" | "xmm9.low" => Some(self.xmm9.low),
+            "xmm9.high" => Some(self.xmm9.high as u64),
+            "xmm10" | "xmm10.low" => Some(self.xmm10.low),
+            "xmm10.high" => Some(self.xmm10.high as u64),
+            "xmm11" | "xmm11.low" => Some(self.xmm11.low),
+            "xmm11.high" => Some(self.xmm11.high as u64),
+            "xmm12" | "xmm12.low" => Some(self.xmm12.low),
+            "xmm12.high" => Some(self.xmm12.high as u64),
+            "xmm13" | "xmm13.low" => Some(self.xmm13.low),
+            "xmm13.high" => Some(self.xmm13.high as u64),
+            "xmm14" | "xmm14.low" => Some(self.xmm14.low),
+            "xmm14.high" => Some(self.xmm14.high as u64),
+            "xmm15" | "xmm15.low" => Some(self.xmm15.low),
+            "xmm15.high" => Some(self.xmm15.high as u64),
+            _ => None,
+        }
+    }
 }
 
 impl StateDifference for GuestRegisters {
-    fn difference<'a, 'b>(
-        &'a self,
-        other: &'b Self,
-    ) -> Vec<(&'static str, Box<&'a dyn Debug>, Box<&'b dyn Debug>)> {
-        let mut result: Vec<(&'static str, Box<&'a dyn Debug>, Box<&'b dyn Debug>)> = Vec::new();
+    fn difference<'a, 'b>(&'a self, other: &'b Self) -> Vec<Difference<'a, 'b>> {
+        let mut result: Vec<Difference<'a, 'b>> = Vec::new();
 
         if self.rax != other.rax {
             result.push(("rax", Box::new(&self.rax), Box::new(&other.rax)));
@@ -278,6 +339,49 @@ pub struct VmStateExtendedRegisters {
     pub ds_base: u64,
 }
 
+impl VmStateExtendedRegisters {
+    /// Reads a system register by its name
+    ///
+    /// The descriptor table pointers are accessed by `gdtr.base`, `gdtr.limit`, `idtr.base` and `idtr.limit`.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<u64>` - The register value, `None` if there is no register with that name
+    pub fn get_field(&self, name: &str) -> Option<u64> {
+        match name {
+            "gdtr.base" => Some(self.gdtr.base as u64),
+            "gdtr.limit" => Some(self.gdtr.limit as u64),
+            "idtr.base" => Some(self.idtr.base as u64),
+            "idtr.limit" => Some(self.idtr.limit as u64),
+            "ldtr_base" => Some(self.ldtr_base),
+            "ldtr" => Some(self.ldtr as u64),
+            "es" => Some(self.es as u64),
+            "cs" => Some(self.cs as u64),
+            "ss" => Some(self.ss as u64),
+            "ds" => Some(self.ds as u64),
+            "fs" => Some(self.fs as u64),
+            "gs" => Some(self.gs as u64),
+            "tr" => Some(self.tr as u64),
+            "efer" => Some(self.efer),
+            "cr0" => Some(self.cr0),
+            "cr3" => Some(self.cr3),
+            "cr4" => Some(self.cr4),
+            "fs_base" => Some(self.fs_base),
+            "gs_base" => Some(self.gs_base),
+            "tr_base" => Some(self.tr_base),
+            "sysenter_cs" => Some(self.sysenter_cs),
+            "sysenter_esp" => Some(self.sysenter_esp),
+            "sysenter_eip" => Some(self.sysenter_eip),
+            "dr7" => Some(self.dr7),
+            "es_base" => Some(self.es_base),
+            "cs_base" => Some(self.cs_base),
+            "ss_base" => Some(self.ss_base),
+            "ds_base" => Some(self.ds_base),
+            _ => None,
+        }
+    }
+}
+
 /// Wrapper for descriptor table pointers
 ///
 /// This structure wraps the `DescriptorTablePointer` type to provide
@@ -351,31 +455,191 @@ impl Hash for DescriptorTablePointerWrapper<u64> {
     }
 }
 
+/// A difference between two states
+///
+/// Contains the field name, a boxed reference to the value from the first state and a boxed
+/// reference to the value from the second state.
+pub type Difference<'a, 'b> = (&'static str, Box<&'a dyn Debug>, Box<&'b dyn Debug>);
+
 /// Trait for comparing states and identifying differences
 ///
 /// This trait provides functionality to compare two states and identify
 /// specific differences between them, returning a vector of tuples containing
 /// the field name and the differing values.
 pub trait StateDifference {
-    /// Returns a vector of differences between two states
-    ///
-    /// Each difference is represented as a tuple containing:
-    /// - The field name as a static string
-    /// - A boxed reference to the value from the first state
-    /// - A boxed reference to the value from the second state
-    fn difference<'a, 'b>(
-        &'a self,
-        other: &'b Self,
-    ) -> Vec<(&'static str, Box<&'a dyn Debug>, Box<&'b dyn Debug>)>;
+    /// Returns a vector of differences between two states, see [`Difference`]
+    fn difference<'a, 'b>(&'a self, other: &'b Self) -> Vec<Difference<'a, 'b>>;
+}
+
+/// Radix used to print the values of a state difference
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Radix {
+    /// Hexadecimal values (`{:x?}`)
+    #[default]
+    Hexadecimal,
+    /// Decimal values (`{:?}`)
+    Decimal,
+}
+
+/// Formatter for the output of [`StateDifference::difference`]
+///
+/// Each difference is printed on its own line, by default e.g. ` - rax: 1 -> 2`, fields
+/// matching the marker predicate are highlighted with a `*` instead of a `-`.
+#[derive(Debug, Clone, Copy)]
+pub struct DifferenceFormatter {
+    /// Radix of the printed values
+    pub radix: Radix,
+    /// Minimum width of the printed values
+    pub width: usize,
+    /// Whether the values are printed in the alternate form (`{:#x?}`)
+    pub alternate: bool,
+    /// Text printed at the start of each line
+    pub prefix: &'static str,
+    /// Bullet printed before the field name, and before the names of highlighted fields
+    pub bullets: (&'static str, &'static str),
+    /// Whether the field names are printed quoted (`{:?}`)
+    pub quote_fields: bool,
+    /// Separator printed between the field name and the first value
+    pub field_separator: &'static str,
+    /// Label printed before the value of the first state
+    pub before_label: &'static str,
+    /// Separator printed between the two values
+    pub separator: &'static str,
+    /// Label printed before the value of the second state
+    pub after_label: &'static str,
+    /// Predicate selecting the fields to highlight
+    pub marker: fn(&str) -> bool,
+}
+
+impl Default for DifferenceFormatter {
+    fn default() -> Self {
+        Self {
+            radix: Radix::Hexadecimal,
+            width: 0,
+            alternate: false,
+            prefix: " ",
+            bullets: ("- ", "* "),
+            quote_fields: false,
+            field_separator: ": ",
+            before_label: "",
+            separator: " -> ",
+            after_label: "",
+            marker: |_| false,
+        }
+    }
+}
+
+impl DifferenceFormatter {
+    /// Sets the radix of the printed values
+    pub fn with_radix(mut self, radix: Radix) -> Self {
+        self.radix = radix;
+        self
+    }
+
+    /// Sets the minimum width of the printed values
+    pub fn with_width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// Prints the values in the alternate form, e.g. `0x10` instead of `10`
+    pub fn with_alternate(mut self) -> Self {
+        self.alternate = true;
+        self
+    }
+
+    /// Sets the text printed at the start of each line and the bullets before the field names
+    pub fn with_prefix(
+        mut self,
+        prefix: &'static str,
+        bullet: &'static str,
+        marked_bullet: &'static str,
+    ) -> Self {
+        self.prefix = prefix;
+        self.bullets = (bullet, marked_bullet);
+        self
+    }
+
+    /// Prints the field names quoted, e.g. `"rax"`
+    pub fn with_quoted_fields(mut self) -> Self {
+        self.quote_fields = true;
+        self
+    }
+
+    /// Sets the separator printed between the field name and the first value
+    pub fn with_field_separator(mut self, field_separator: &'static str) -> Self {
+        self.field_separator = field_separator;
+        self
+    }
+
+    /// Sets the labels printed before the values and the separator between them
+    pub fn with_labels(
+        mut self,
+        before_label: &'static str,
+        separator: &'static str,
+        after_label: &'static str,
+    ) -> Self {
+        self.before_label = before_label;
+        self.separator = separator;
+        self.after_label = after_label;
+        self
+    }
+
+    /// Sets the predicate selecting the fields to highlight
+    pub fn with_marker(mut self, marker: fn(&str) -> bool) -> Self {
+        self.marker = marker;
+        self
+    }
+
+    /// Writes a single value with the configured radix and width
+    fn write_value<W: fmt::Write>(&self, f: &mut W, value: &dyn Debug) -> fmt::Result {
+        match (self.radix, self.alternate) {
+            (Radix::Hexadecimal, false) => write!(f, "{:width$x?}", value, width = self.width),
+            (Radix::Hexadecimal, true) => write!(f, "{:#width$x?}", value, width = self.width),
+            (Radix::Decimal, false) => write!(f, "{:width$?}", value, width = self.width),
+            (Radix::Decimal, true) => write!(f, "{:#width$?}", value, width = self.width),
+        }
+    }
+
+    /// Writes a single difference entry (without line break)
+    pub fn write_entry<W: fmt::Write>(
+        &self,
+        f: &mut W,
+        field: &str,
+        before: &dyn Debug,
+        after: &dyn Debug,
+    ) -> fmt::Result {
+        let bullet = if (self.marker)(field) {
+            self.bullets.1
+        } else {
+            self.bullets.0
+        };
+        write!(f, "{}{}", self.prefix, bullet)?;
+        if self.quote_fields {
+            write!(f, "{:?}", field)?;
+        } else {
+            write!(f, "{}", field)?;
+        }
+        write!(f, "{}{}", self.field_separator, self.before_label)?;
+        self.write_value(f, before)?;
+        write!(f, "{}{}", self.separator, self.after_label)?;
+        self.write_value(f, after)
+    }
+
+    /// Formats all differences, one entry per line
+    pub fn format(&self, differences: &[Difference<'_, '_>]) -> String {
+        let mut output = String::new();
+        for (field, before, after) in differences {
+            let _ = self.write_entry(&mut output, field, **before, **after);
+            output.push('\n');
+        }
+        output
+    }
 }
 
 impl StateDifference for VmState {
-    fn difference<'a, 'b>(
-        &'a self,
-        other: &'b Self,
-    ) -> Vec<(&'static str, Box<&'a dyn Debug>, Box<&'b dyn Debug>)> {
-        let mut differences: Vec<(&'static str, Box<&'a dyn Debug>, Box<&'b dyn Debug>)> =
-            Vec::new();
+    fn difference<'a, 'b>(&'a self, other: &'b Self) -> Vec<Difference<'a, 'b>> {
+        let mut differences: Vec<Difference<'a, 'b>> = Vec::new();
 
         // todo implement reflection etc
 
@@ -603,6 +867,19 @@ impl VmState {
             .is_equal_no_address_compare(&other.standard_registers)
             && self.extended_registers == other.extended_registers
     }
+
+    /// Reads a register by its name
+    ///
+    /// See [`GuestRegisters::get_field`] and [`VmStateExtendedRegisters::get_field`] for the supported names.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<u64>` - The register value, `None` if there is no register with that name
+    pub fn get_field(&self, name: &str) -> Option<u64> {
+        self.standard_registers
+            .get_field(name)
+            .or_else(|| self.extended_registers.get_field(name))
+    }
 }
 
 /// Reasons for VM exits
@@ -643,6 +920,8 @@ pub enum VmExitReason {
     ExternalInterrupt,
     /// Timer expiration
     TimerExpiration,
+    /// The VMX-preemption timer expired, the guest ran longer than the configured timeout
+    PreemptionTimeout,
     /// Processor shutdown
     Shutdown(u64),
     /// Unhandled VM exit
@@ -671,6 +950,15 @@ impl VmExitReason {
         }
         self
     }
+
+    /// Checks if the guest was stopped by a timer rather than by its own behavior
+    pub fn is_timeout(&self) -> bool {
+        matches!(
+            self,
+            VmExitReason::TimerExpiration | VmExitReason::PreemptionTimeout
+        )
+    }
+
     pub fn is_same_kind(&self, other: &Self) -> bool {
         match self {
             VmExitReason::MonitorTrap => matches!(other, VmExitReason::MonitorTrap),
@@ -688,6 +976,7 @@ impl VmExitReason {
             VmExitReason::MsrUse => matches!(other, VmExitReason::MsrUse),
             VmExitReason::ExternalInterrupt => matches!(other, VmExitReason::ExternalInterrupt),
             VmExitReason::TimerExpiration => matches!(other, VmExitReason::TimerExpiration),
+            VmExitReason::PreemptionTimeout => matches!(other, VmExitReason::PreemptionTimeout),
             VmExitReason::Invd => matches!(other, VmExitReason::Invd),
             VmExitReason::VMCommand(x) => {
                 if let VmExitReason::VMCommand(y) = other {
@@ -751,11 +1040,8 @@ impl Default for VmExitReason {
 }
 
 impl StateDifference for VmExitReason {
-    fn difference<'a, 'b>(
-        &'a self,
-        other: &'b Self,
-    ) -> Vec<(&'static str, Box<&'a dyn Debug>, Box<&'b dyn Debug>)> {
-        let mut result: Vec<(&'static str, Box<&'a dyn Debug>, Box<&'b dyn Debug>)> = Vec::new();
+    fn difference<'a, 'b>(&'a self, other: &'b Self) -> Vec<Difference<'a, 'b>> {
+        let mut result: Vec<Difference<'a, 'b>> = Vec::new();
 
         match (self, other) {
             (VmExitReason::EPTPageFault(a), VmExitReason::EPTPageFault(b)) => {
@@ -828,11 +1114,8 @@ pub struct EPTPageFaultQualification {
 }
 
 impl StateDifference for EPTPageFaultQualification {
-    fn difference<'a, 'b>(
-        &'a self,
-        other: &'b Self,
-    ) -> Vec<(&'static str, Box<&'a dyn Debug>, Box<&'b dyn Debug>)> {
-        let mut result: Vec<(&'static str, Box<&'a dyn Debug>, Box<&'b dyn Debug>)> = Vec::new();
+    fn difference<'a, 'b>(&'a self, other: &'b Self) -> Vec<Difference<'a, 'b>> {
+        let mut result: Vec<Difference<'a, 'b>> = Vec::new();
 
         if self.rip != other.rip {
             result.push(("rip", Box::new(&self.rip), Box::new(&other.rip)));
@@ -936,11 +1219,8 @@ pub struct ExceptionQualification {
 }
 
 impl StateDifference for ExceptionQualification {
-    fn difference<'a, 'b>(
-        &'a self,
-        other: &'b Self,
-    ) -> Vec<(&'static str, Box<&'a dyn Debug>, Box<&'b dyn Debug>)> {
-        let mut result: Vec<(&'static str, Box<&'a dyn Debug>, Box<&'b dyn Debug>)> = Vec::new();
+    fn difference<'a, 'b>(&'a self, other: &'b Self) -> Vec<Difference<'a, 'b>> {
+        let mut result: Vec<Difference<'a, 'b>> = Vec::new();
 
         if self.rip != other.rip {
             result.push(("rip", Box::new(&self.rip), Box::new(&other.rip)));
@@ -1069,3 +1349,94 @@ impl<T> Debug for DescriptorTablePointer<T> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::state::{DifferenceFormatter, Radix, StateDifference, VmState, M128A};
+
+    #[test]
+    fn test_get_field_by_name() {
+        let mut state = VmState::default();
+        state.standard_registers.rax = 0x1234;
+        state.standard_registers.rip = 0x1000;
+        state.standard_registers.r15 = u64::MAX;
+        state.standard_registers.xmm3 = M128A {
+            low: 0xaa,
+            high: -1,
+        };
+        state.extended_registers.cs = 0x8;
+        state.extended_registers.cr3 = 0x5000;
+
+        assert_eq!(state.get_field("rax"), Some(state.standard_registers.rax));
+        assert_eq!(state.get_field("rip"), Some(state.standard_registers.rip));
+        assert_eq!(state.get_field("r15"), Some(state.standard_registers.r15));
+        assert_eq!(state.get_field("xmm3"), Some(0xaa));
+        assert_eq!(state.get_field("xmm3.high"), Some(u64::MAX));
+        assert_eq!(
+            state.get_field("cs"),
+            Some(state.extended_registers.cs as u64)
+        );
+        assert_eq!(state.get_field("cr3"), Some(state.extended_registers.cr3));
+        assert_eq!(
+            state.standard_registers.get_field("rflags"),
+            Some(state.standard_registers.rflags)
+        );
+        assert_eq!(state.standard_registers.get_field("cr3"), None);
+        assert_eq!(state.get_field("unknown"), None);
+    }
+
+    #[test]
+    fn test_difference_formatter() {
+        let mut normal = VmState::default();
+        let mut serialized = VmState::default();
+        normal.standard_registers.rax = 0x10;
+        serialized.standard_registers.rax = 0x20;
+        normal.standard_registers.rip = 0x1000;
+        serialized.standard_registers.rip = 0x1004;
+
+        let difference = normal.difference(&serialized);
+
+        // fuzzer_device, coverage collection mismatch
+        let formatter = DifferenceFormatter::default()
+            .with_quoted_fields()
+            .with_labels("expected ", ", ", "got ");
+        assert_eq!(
+            formatter.format(&difference),
+            " - \"rax\": expected 10, got 20\n - \"rip\": expected 1000, got 1004\n"
+        );
+
+        // fuzzer_device, serialized execution mismatch
+        let formatter = DifferenceFormatter::default()
+            .with_quoted_fields()
+            .with_labels("normal ", ", ", "serialized ")
+            .with_marker(|field| field == "rip");
+        assert_eq!(
+            formatter.format(&difference),
+            " - \"rax\": normal 10, serialized 20\n * \"rip\": normal 1000, serialized 1004\n"
+        );
+
+        // fuzzer_master, spec_manual command
+        let formatter = DifferenceFormatter::default()
+            .with_prefix("DIFF ", "", "")
+            .with_alternate();
+        assert_eq!(
+            formatter.format(&difference),
+            "DIFF rax: 0x10 -> 0x20\nDIFF rip: 0x1000 -> 0x1004\n"
+        );
+
+        // fuzzer_master, fuzz_compare
+        let formatter = DifferenceFormatter::default().with_field_separator(" ");
+        assert_eq!(
+            formatter.format(&difference),
+            " - rax 10 -> 20\n - rip 1000 -> 1004\n"
+        );
+
+        let formatter = DifferenceFormatter::default()
+            .with_radix(Radix::Decimal)
+            .with_width(5);
+        assert_eq!(
+            formatter.format(&difference),
+            " - rax:    16 ->    32\n - rip:  4096 ->  4100\n"
+        );
+    }
+}
@@ -2,7 +2,7 @@
 //!
 //! This module provides the core functionality for managing virtual machines
 //! in the hypervisor. It handles VM creation, memory management through nested
-//! paging, and hardware-assisted virtualization.
+//! paging, guest memory snapshots, and hardware-assisted virtualization.
 
 use crate::error::HypervisorError;
 use crate::{
@@ -13,8 +13,18 @@ use crate::{
     Page,
 };
 use alloc::boxed::Box;
+use alloc::vec::Vec;
 use core::ptr::addr_of;
 use log::trace;
+use x86::current::paging::BASE_PAGE_SHIFT;
+
+/// Copy of a guest page, restored by [`Vm::reset_guest_memory`]
+struct GuestPageSnapshot {
+    /// Host page the guest page is mapped to
+    page: *mut Page,
+    /// Content of the page when the snapshot was taken
+    content: Box<Page>,
+}
 
 /// Represents a virtual machine in the hypervisor
 ///
@@ -48,8 +58,23 @@ pub struct Vm {
     /// Tracks how many structures from `nested_paging_structures` have been
     /// consumed for building the page tables.
     used_nps_count: usize,
+
+    /// Maximum duration of a single VM entry in TSC ticks
+    ///
+    /// Applied to the VMX-preemption timer on every initialization. Expiry is reported as
+    /// [`VmExitReason::PreemptionTimeout`](crate::state::VmExitReason::PreemptionTimeout).
+    preemption_timeout: u64,
+
+    /// Snapshot of the writable guest pages
+    ///
+    /// Taken by [`Vm::snapshot_guest_memory`], restored by [`Vm::reset_guest_memory`].
+    #[derivative(Debug = "ignore")]
+    memory_snapshot: Vec<GuestPageSnapshot>,
 }
 
+/// Default maximum duration of a single VM entry in TSC ticks
+pub const DEFAULT_PREEMPTION_TIMEOUT: u64 = 1_000_000;
+
 impl Vm {
     /// Creates a new virtual machine instance
     pub fn new() -> Self {
@@ -78,6 +103,8 @@ impl Vm {
             nested_pml4,
             nested_paging_structures,
             used_nps_count: 0,
+            preemption_timeout: DEFAULT_PREEMPTION_TIMEOUT,
+            memory_snapshot: Vec::new(),
         }
     }
 
@@ -215,14 +242,106 @@ impl Vm {
         Ok(entry)
     }
 
+    /// Returns the maximum duration of a single VM entry in TSC ticks
+    pub fn preemption_timeout(&self) -> u64 {
+        self.preemption_timeout
+    }
+
+    /// Sets the maximum duration of a single VM entry
+    ///
+    /// Takes effect with the next call to [`Vm::initialize`]. Without processor support
+    /// for the VMX-preemption timer the guest is not bounded.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout_in_tsc` - Timeout in TSC ticks
+    pub fn set_preemption_timeout(&mut self, timeout_in_tsc: u64) {
+        self.preemption_timeout = timeout_in_tsc;
+    }
+
+    /// Captures the content of guest pages as the snapshot restored by [`Vm::reset_guest_memory`]
+    ///
+    /// Replaces any previous snapshot. The translations of the pages must not be remapped to
+    /// other host pages afterward, permission changes are fine.
+    ///
+    /// # Arguments
+    ///
+    /// * `gpas` - Guest physical addresses of the pages, usually all guest writable pages
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())`, or Err if a page is not mapped
+    pub fn snapshot_guest_memory(&mut self, gpas: &[usize]) -> crate::Result<()> {
+        let mut snapshot = Vec::with_capacity(gpas.len());
+        for &gpa in gpas {
+            let pa = self.get_translation(gpa)?.pfn() << BASE_PAGE_SHIFT;
+            if pa == 0 {
+                return Err(HypervisorError::GuestPageNotMapped(gpa));
+            }
+
+            let page = pa as *mut Page;
+            let mut content = Page::alloc_zeroed();
+            content
+                .as_slice_mut()
+                .copy_from_slice(unsafe { (*page).as_slice() });
+            snapshot.push(GuestPageSnapshot { page, content });
+        }
+        self.memory_snapshot = snapshot;
+        Ok(())
+    }
+
+    /// Discards the guest memory snapshot, [`Vm::reset_guest_memory`] does nothing afterward
+    pub fn clear_guest_memory_snapshot(&mut self) {
+        self.memory_snapshot.clear();
+    }
+
+    /// Checks if a guest memory snapshot was taken
+    pub fn has_guest_memory_snapshot(&self) -> bool {
+        !self.memory_snapshot.is_empty()
+    }
+
+    /// Restores the guest pages of the snapshot
+    ///
+    /// Only pages that differ from the snapshot are copied. Dirty pages are detected by
+    /// comparing them instead of using EPT dirty flags: with EPT accessed and dirty flags
+    /// enabled every guest page walk counts as a write to the read-only guest page tables.
+    ///
+    /// # Returns
+    ///
+    /// The number of restored pages
+    pub fn reset_guest_memory(&mut self) -> usize {
+        let mut restored = 0;
+        for snapshot in self.memory_snapshot.iter() {
+            let page = unsafe { &mut *snapshot.page };
+            if page.as_slice() != snapshot.content.as_slice() {
+                page.as_slice_mut()
+                    .copy_from_slice(snapshot.content.as_slice());
+                restored += 1;
+            }
+        }
+        restored
+    }
+
+    /// Checks if the guest memory matches the snapshot
+    ///
+    /// Used to verify [`Vm::reset_guest_memory`].
+    pub fn guest_memory_matches_snapshot(&self) -> bool {
+        self.memory_snapshot.iter().all(|snapshot| {
+            let page = unsafe { &*snapshot.page };
+            page.as_slice() == snapshot.content.as_slice()
+        })
+    }
+
     /// Initializes the virtual machine
     ///
     /// This function sets up the hardware virtualization environment by
     /// providing the address of the nested PML4 table to the hardware
-    /// virtualization implementation.
+    /// virtualization implementation and arms the preemption timer.
     pub fn initialize(&mut self) -> Result<(), &'static str> {
         let addr = self.nested_pml4_addr() as u64;
-        self.vt.initialize(addr)
+        self.vt.initialize(addr)?;
+        self.vt.set_preemption_timer(self.preemption_timeout);
+        Ok(())
     }
 }
 
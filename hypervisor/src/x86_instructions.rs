@@ -49,6 +49,149 @@ pub fn wrmsr(msr: u32, value: u64) {
     unsafe { x86::msr::wrmsr(msr, value) };
 }
 
+/// A model specific register access raised a general protection fault
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GeneralProtectionFault;
+
+/// Interrupt vector of the general protection fault
+const GENERAL_PROTECTION_VECTOR: usize = 13;
+
+// General protection fault handler used by `rdmsr_safe` and `wrmsr_safe`. It skips the
+// faulting two byte RDMSR / WRMSR instruction and signals the fault by setting R8 to one.
+core::arch::global_asm!(
+    ".global hypervisor_msr_fault_handler",
+    "hypervisor_msr_fault_handler:",
+    "add rsp, 8",
+    "add qword ptr [rsp], 2",
+    "mov r8d, 1",
+    "iretq",
+);
+
+extern "C" {
+    fn hypervisor_msr_fault_handler();
+}
+
+/// Executes an MSR access while general protection faults are redirected to
+/// `hypervisor_msr_fault_handler`
+///
+/// Interrupts are disabled and the firmware gate is restored afterward.
+///
+/// # Arguments
+///
+/// * `access` - The MSR access, must clear R8 right before the RDMSR / WRMSR instruction
+fn with_msr_fault_handler<R>(access: impl FnOnce() -> R) -> R {
+    let mut idtr = DescriptorTablePointer::<u64>::default();
+    sidt(&mut idtr);
+
+    let interrupts_enabled =
+        x86::bits64::rflags::read().contains(x86::bits64::rflags::RFlags::FLAGS_IF);
+    cli();
+
+    // 64-bit gates span two quadwords
+    let gate = idtr
+        .base
+        .cast_mut()
+        .wrapping_add(2 * GENERAL_PROTECTION_VECTOR);
+    let handler = hypervisor_msr_fault_handler as *const () as u64;
+
+    // Safety: the gate lies within the active IDT, the selector and attributes are kept.
+    let previous = unsafe {
+        let previous = [gate.read_volatile(), gate.add(1).read_volatile()];
+        gate.write_volatile(
+            (previous[0] & 0x0000_FFFF_FFFF_0000)
+                | (handler & 0xFFFF)
+                | ((handler >> 16) & 0xFFFF) << 48,
+        );
+        gate.add(1).write_volatile(handler >> 32);
+        previous
+    };
+
+    let result = access();
+
+    // Safety: restores the gate read above.
+    unsafe {
+        gate.write_volatile(previous[0]);
+        gate.add(1).write_volatile(previous[1]);
+    }
+
+    if interrupts_enabled {
+        // Safety: this project runs at CPL0.
+        unsafe { x86::irq::enable() };
+    }
+
+    result
+}
+
+/// Reads a Model-Specific Register (MSR), catching general protection faults
+///
+/// # Arguments
+///
+/// * `msr` - The MSR register number to read
+///
+/// # Returns
+///
+/// * `Ok(u64)` - The 64-bit value stored in the specified MSR
+/// * `Err(GeneralProtectionFault)` - The MSR does not exist or cannot be read
+pub fn rdmsr_safe(msr: u32) -> Result<u64, GeneralProtectionFault> {
+    with_msr_fault_handler(|| {
+        let low: u32;
+        let high: u32;
+        let faulted: u64;
+        // Safety: this project runs at CPL0, faults are caught by the handler.
+        unsafe {
+            asm!(
+                "xor r8d, r8d",
+                "rdmsr",
+                in("ecx") msr,
+                out("eax") low,
+                out("edx") high,
+                out("r8") faulted,
+                options(nomem),
+            );
+        }
+
+        if faulted != 0 {
+            Err(GeneralProtectionFault)
+        } else {
+            Ok(((high as u64) << 32) | low as u64)
+        }
+    })
+}
+
+/// Writes a value to a Model-Specific Register (MSR), catching general protection faults
+///
+/// # Arguments
+///
+/// * `msr` - The MSR register number to write to
+/// * `value` - The 64-bit value to write
+///
+/// # Returns
+///
+/// * `Ok(())` - The value was written
+/// * `Err(GeneralProtectionFault)` - The MSR does not exist or rejected the value
+pub fn wrmsr_safe(msr: u32, value: u64) -> Result<(), GeneralProtectionFault> {
+    with_msr_fault_handler(|| {
+        let faulted: u64;
+        // Safety: this project runs at CPL0, faults are caught by the handler.
+        unsafe {
+            asm!(
+                "xor r8d, r8d",
+                "wrmsr",
+                in("ecx") msr,
+                in("eax") value as u32,
+                in("edx") (value >> 32) as u32,
+                out("r8") faulted,
+            );
+        }
+
+        if faulted != 0 {
+            Err(GeneralProtectionFault)
+        } else {
+            Ok(())
+        }
+    })
+}
+
 /// Reads the CR0 control register
 pub fn cr0() -> Cr0 {
     // Safety: this project runs at CPL0.
@@ -1,5 +1,5 @@
 use crate::measurements::mm_initialize;
-use crate::{instance, Availability, Instant, INITIALIZED, INSTANCE};
+use crate::{Availability, Instant, INITIALIZED, INSTANCE};
 use core::arch::asm;
 use core::fmt::{Display, Formatter};
 use core::sync::atomic::Ordering;
@@ -52,16 +52,21 @@ impl TimeKeeper {
     }
 }
 
-pub fn initialize(system_p0_frequency: f64) -> Result<&'static TimeKeeper, impl Error> {
+/// Initializes the global timekeeper
+///
+/// # Returns
+///
+/// * `Result<Availability, impl Error>` - Detected availability of the timing functionality
+pub fn initialize(system_p0_frequency: f64) -> Result<Availability, impl Error> {
     mm_initialize();
     if INITIALIZED.load(Ordering::Relaxed) {
-        return Ok(instance());
+        return Ok(TimeKeeper::availability());
     }
     unsafe {
         INSTANCE = Some(TimeKeeper::new(system_p0_frequency)?);
     }
     INITIALIZED.store(true, Ordering::Relaxed);
-    Ok::<&'static TimeKeeper, CreationError>(instance())
+    Ok::<Availability, CreationError>(TimeKeeper::availability())
 }
 
 pub type TimeStamp = u64;
@@ -5,6 +5,8 @@
 #![cfg_attr(all(not(test), not(target_arch = "aarch64")), no_std)]
 
 pub mod measurements;
+#[cfg(feature = "tui")]
+pub mod tui;
 
 extern crate alloc;
 
@@ -96,12 +98,22 @@ pub fn is_available() -> bool {
 }
 
 /// Get the global timekeeper instance
-#[allow(static_mut_refs)]
+///
+/// Panics if timing was not initialized, see [`try_instance`] for a non-panicking variant.
 pub fn instance() -> &'static TimeKeeper {
+    match try_instance() {
+        Some(instance) => instance,
+        None => panic!("Not initialized yet!"),
+    }
+}
+
+/// Get the global timekeeper instance, `None` if timing was not initialized (or is not available)
+#[allow(static_mut_refs)]
+pub fn try_instance() -> Option<&'static TimeKeeper> {
     if !INITIALIZED.load(Ordering::Relaxed) {
-        panic!("Not initialized yet!");
+        None
     } else {
-        unsafe { INSTANCE.as_ref().unwrap() }
+        unsafe { INSTANCE.as_ref() }
     }
 }
 
@@ -119,11 +131,21 @@ pub struct TimeMeasurement {
 
 impl TimeMeasurement {
     /// Begin a new time measurement
+    ///
+    /// If timing is not initialized, the measurement is inactive and does not record anything.
     pub fn begin(name: &'static str) -> Self {
+        let Some(timekeeper) = try_instance() else {
+            return Self {
+                name,
+                start: Instant::default(),
+                exclusive: None,
+                stack_guard: None,
+            };
+        };
         let mut guard = mm_instance().borrow_mut();
         Self {
             name,
-            start: instance().now(),
+            start: timekeeper.now(),
             exclusive: Some(guard.register_exclusive_measurement(name)),
             stack_guard: Some(guard.begin_stack_frame(name)),
         }
@@ -146,12 +168,25 @@ impl TimeMeasurement {
 
     fn __drop(&mut self) -> (Duration, Duration) {
         drop(self.stack_guard.take());
-        let now = instance().now();
+        // the start was never recorded (timing unavailable at begin) or was already stopped
+        let Some(exclusive) = self.exclusive.take() else {
+            return (
+                Duration(TimeStamp::default()),
+                Duration(TimeStamp::default()),
+            );
+        };
+        let Some(timekeeper) = try_instance() else {
+            return (
+                Duration(TimeStamp::default()),
+                Duration(TimeStamp::default()),
+            );
+        };
+        let now = timekeeper.now();
         let total_duration = now - self.start;
         let mut exclusive_duration = total_duration;
-        if let Some(exclusive) = self.exclusive.take().map(ExclusiveMeasurementGuard::stop) {
-            exclusive_duration.0 = exclusive_duration.0.saturating_sub(exclusive.0);
-        }
+        exclusive_duration.0 = exclusive_duration
+            .0
+            .saturating_sub(ExclusiveMeasurementGuard::stop(exclusive).0);
         mm_instance().borrow_mut().register_data_point(
             self.name,
             total_duration,
@@ -3,7 +3,7 @@
 //! This module provides types and functionality for managing performance
 //! measurements.
 
-use crate::{instance, Duration};
+use crate::{try_instance, Duration};
 use alloc::collections::{BTreeMap, VecDeque};
 use alloc::string::{String, ToString};
 use alloc::vec;
@@ -61,13 +61,19 @@ pub struct MeasureValues<T> {
 
 impl From<&MeasureValues<u64>> for MeasureValues<f64> {
     fn from(value: &MeasureValues<u64>) -> Self {
+        // without a timekeeper no data points are recorded and the tick rate is unknown
+        let to_seconds = |ticks: u64| {
+            try_instance().map_or(0.0, |timekeeper| {
+                timekeeper.duration_to_seconds(ticks as f64)
+            })
+        };
         Self {
             exclusive_cumulative_average: value.exclusive_cumulative_average,
             exclusive_cumulative_sum_of_squares: value.exclusive_cumulative_sum_of_squares,
             total_cumulative_average: value.total_cumulative_average,
             total_cumulative_sum_of_squares: value.total_cumulative_sum_of_squares,
-            total_time: instance().duration_to_seconds(value.total_time as f64),
-            exclusive_time: instance().duration_to_seconds(value.exclusive_time as f64),
+            total_time: to_seconds(value.total_time),
+            exclusive_time: to_seconds(value.exclusive_time),
             number_of_measurements: value.number_of_measurements,
         }
     }
@@ -101,6 +107,63 @@ impl<T: AsPrimitive<f64> + Copy> MeasureValues<T> {
     }
 }
 
+impl MeasureValues<u64> {
+    /// Calculate the values of the measurements taken since an earlier snapshot
+    ///
+    /// Inverts the combination step of Welford's online algorithm, so that averages and sums of
+    /// squares describe only the measurements after `earlier`.
+    ///
+    /// # Arguments
+    ///
+    /// * `earlier` - Snapshot of the same measurement taken before `self`
+    ///
+    /// # Returns
+    ///
+    /// * `MeasureValues<u64>` - Values of the measurements taken in between
+    pub fn since(&self, earlier: &Self) -> Self {
+        if earlier.number_of_measurements == 0 {
+            return *self;
+        }
+        if self.number_of_measurements <= earlier.number_of_measurements {
+            return Self::default();
+        }
+
+        let n = self.number_of_measurements as f64;
+        let old_n = earlier.number_of_measurements as f64;
+        let new_n = n - old_n;
+
+        let split = |average: f64, sum_of_squares: f64, old_average: f64, old_sum: f64| {
+            let new_average = (average * n - old_average * old_n) / new_n;
+            let delta = new_average - old_average;
+            let new_sum = sum_of_squares - old_sum - delta * delta * old_n * new_n / n;
+            (new_average, new_sum.max(0.0))
+        };
+
+        let (exclusive_average, exclusive_sum) = split(
+            self.exclusive_cumulative_average,
+            self.exclusive_cumulative_sum_of_squares,
+            earlier.exclusive_cumulative_average,
+            earlier.exclusive_cumulative_sum_of_squares,
+        );
+        let (total_average, total_sum) = split(
+            self.total_cumulative_average,
+            self.total_cumulative_sum_of_squares,
+            earlier.total_cumulative_average,
+            earlier.total_cumulative_sum_of_squares,
+        );
+
+        Self {
+            exclusive_cumulative_average: exclusive_average,
+            exclusive_cumulative_sum_of_squares: exclusive_sum,
+            exclusive_time: self.exclusive_time.saturating_sub(earlier.exclusive_time),
+            total_cumulative_average: total_average,
+            total_cumulative_sum_of_squares: total_sum,
+            total_time: self.total_time.saturating_sub(earlier.total_time),
+            number_of_measurements: self.number_of_measurements - earlier.number_of_measurements,
+        }
+    }
+}
+
 /// Map of measurement names to their values
 pub type MeasurementData<T> = BTreeMap<String, MeasureValues<T>>;
 
@@ -319,8 +382,11 @@ impl MeasurementManager {
         total_duration: Duration,
         exclusive_duration: Duration,
     ) {
-        let x_tot = instance().duration_to_seconds(total_duration);
-        let x_exclusive = instance().duration_to_seconds(exclusive_duration);
+        let Some(timekeeper) = try_instance() else {
+            return;
+        };
+        let x_tot = timekeeper.duration_to_seconds(total_duration);
+        let x_exclusive = timekeeper.duration_to_seconds(exclusive_duration);
 
         let data = self.data.entry(name).or_default();
         if data.number_of_measurements == 0 {
@@ -430,3 +496,56 @@ impl Drop for ExclusiveMeasurementGuard {
         let _ = self.__drop();
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::measurements::MeasureValues;
+
+    fn welford(samples: &[f64]) -> MeasureValues<u64> {
+        let mut result = MeasureValues::<u64>::default();
+        for (i, x) in samples.iter().enumerate() {
+            let delta = x - result.total_cumulative_average;
+            result.total_cumulative_average += delta / (i + 1) as f64;
+            result.total_cumulative_sum_of_squares += delta * (x - result.total_cumulative_average);
+            result.total_time += *x as u64;
+        }
+        result.exclusive_cumulative_average = result.total_cumulative_average;
+        result.exclusive_cumulative_sum_of_squares = result.total_cumulative_sum_of_squares;
+        result.exclusive_time = result.total_time;
+        result.number_of_measurements = samples.len() as u64;
+        result
+    }
+
+    #[test]
+    fn test_since() {
+        let samples = [3.0, 7.0, 2.0, 9.0, 4.0, 12.0, 5.0];
+        let earlier = welford(&samples[..3]);
+        let now = welford(&samples);
+        let expected = welford(&samples[3..]);
+
+        let delta = now.since(&earlier);
+        assert_eq!(delta.number_of_measurements, 4);
+        assert_eq!(delta.total_time, expected.total_time);
+        assert_eq!(delta.exclusive_time, expected.exclusive_time);
+        assert!((delta.total_cumulative_average - expected.total_cumulative_average).abs() < 1e-9);
+        assert!(
+            (delta.total_cumulative_sum_of_squares - expected.total_cumulative_sum_of_squares)
+                .abs()
+                < 1e-9
+        );
+        assert!(
+            (delta.exclusive_cumulative_sum_of_squares
+                - expected.exclusive_cumulative_sum_of_squares)
+                .abs()
+                < 1e-9
+        );
+
+        // nothing measured in between
+        assert_eq!(now.since(&now).number_of_measurements, 0);
+        // nothing measured before
+        assert_eq!(
+            now.since(&MeasureValues::default()).total_time,
+            now.total_time
+        );
+    }
+}
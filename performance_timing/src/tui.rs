@@ -0,0 +1,304 @@
+//! Terminal user interface for performance measurements
+//!
+//! This module provides a sortable table widget rendering accumulated
+//! measurements with [`ratatui`]. Requires the `tui` feature.
+
+use crate::measurements::{format_duration, MeasureValues, MeasurementCollection};
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use ratatui::buffer::Buffer;
+use ratatui::layout::{Constraint, Rect};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::widgets::{Block, Cell, Row, StatefulWidget, Table, TableState};
+
+/// Column a [`MeasurementTable`] is sorted by
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub enum SortKey {
+    /// Sort by measurement name
+    Name,
+    /// Sort by total time
+    #[default]
+    TotalTime,
+    /// Sort by total exclusive time
+    ExclusiveTime,
+    /// Sort by number of measurements
+    Measurements,
+}
+
+impl SortKey {
+    /// Get the next sort key, wrapping around after the last one
+    pub fn next(self) -> Self {
+        match self {
+            SortKey::Name => SortKey::TotalTime,
+            SortKey::TotalTime => SortKey::ExclusiveTime,
+            SortKey::ExclusiveTime => SortKey::Measurements,
+            SortKey::Measurements => SortKey::Name,
+        }
+    }
+
+    /// Human-readable name of the sorted column
+    pub fn label(&self) -> &'static str {
+        match self {
+            SortKey::Name => "Name",
+            SortKey::TotalTime => "Total time",
+            SortKey::ExclusiveTime => "Total excl",
+            SortKey::Measurements => "n",
+        }
+    }
+
+    /// Compare two measurements by this key in ascending order
+    fn compare(
+        &self,
+        a: &(String, MeasureValues<f64>),
+        b: &(String, MeasureValues<f64>),
+    ) -> Ordering {
+        match self {
+            SortKey::Name => a.0.cmp(&b.0),
+            SortKey::TotalTime => a.1.total_time.total_cmp(&b.1.total_time),
+            SortKey::ExclusiveTime => a.1.exclusive_time.total_cmp(&b.1.exclusive_time),
+            SortKey::Measurements => a.1.number_of_measurements.cmp(&b.1.number_of_measurements),
+        }
+        .then_with(|| a.0.cmp(&b.0))
+    }
+}
+
+/// Sortable table of accumulated measurements
+#[derive(Debug, Clone, Default)]
+pub struct MeasurementTable {
+    /// Measurements in display order
+    entries: Vec<(String, MeasureValues<f64>)>,
+    /// Column the entries are sorted by
+    sort_key: SortKey,
+    /// Sort in descending order
+    descending: bool,
+}
+
+impl MeasurementTable {
+    /// Create a new table from a measurement collection
+    ///
+    /// # Arguments
+    ///
+    /// * `collection` - Normalized measurements, accumulated before display
+    pub fn new(collection: &MeasurementCollection<f64>) -> Self {
+        Self::from_values(collection.accumulate())
+    }
+
+    /// Create a new table from accumulated measurements, sorted by total time
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Map of measurement names to their values
+    pub fn from_values(data: BTreeMap<String, MeasureValues<f64>>) -> Self {
+        let mut result = Self {
+            entries: data.into_iter().collect(),
+            sort_key: SortKey::TotalTime,
+            descending: true,
+        };
+        result.sort();
+        result
+    }
+
+    /// Sort the table by a column; names ascending, values descending
+    pub fn sort_by(&mut self, key: SortKey) {
+        self.sort_key = key;
+        self.descending = key != SortKey::Name;
+        self.sort();
+    }
+
+    /// Reverse the sort order
+    pub fn toggle_order(&mut self) {
+        self.descending = !self.descending;
+        self.sort();
+    }
+
+    /// Column the table is currently sorted by
+    pub fn sort_key(&self) -> SortKey {
+        self.sort_key
+    }
+
+    /// Whether the table is sorted in descending order
+    pub fn is_descending(&self) -> bool {
+        self.descending
+    }
+
+    /// Measurements in display order
+    pub fn entries(&self) -> &[(String, MeasureValues<f64>)] {
+        &self.entries
+    }
+
+    /// Number of measurements in the table
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the table contains no measurements
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn sort(&mut self) {
+        let key = self.sort_key;
+        let descending = self.descending;
+        self.entries.sort_by(|a, b| {
+            let ordering = key.compare(a, b);
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+    }
+
+    /// Header row of the table, marking the sorted column
+    pub fn header(&self) -> Row<'static> {
+        let columns = [
+            (None, "Ex. AVG"),
+            (None, "Ex. sdev"),
+            (None, "Total AVG"),
+            (None, "Total sdev"),
+            (Some(SortKey::Measurements), "n"),
+            (Some(SortKey::ExclusiveTime), "Total excl"),
+            (Some(SortKey::TotalTime), "Total time"),
+            (Some(SortKey::Name), "Name"),
+        ];
+        let arrow = if self.descending { " v" } else { " ^" };
+
+        Row::new(columns.into_iter().map(|(key, name)| {
+            if key == Some(self.sort_key) {
+                Cell::from(format!("{name}{arrow}")).underlined()
+            } else {
+                Cell::from(name)
+            }
+        }))
+        .bold()
+    }
+
+    /// Rows of the table in display order
+    pub fn rows(&self) -> Vec<Row<'static>> {
+        fn duration(value: f64) -> Cell<'static> {
+            let (value, unit) = format_duration(value);
+            Cell::from(format!("{:.3} {}", value, unit))
+        }
+
+        self.entries
+            .iter()
+            .map(|(k, v)| {
+                Row::new([
+                    duration(v.exclusive_cumulative_average),
+                    duration(v.std_derivation_exclusive()),
+                    duration(v.total_cumulative_average),
+                    duration(v.std_derivation_total()),
+                    Cell::from(format!("{:.1e}", v.number_of_measurements as f64)),
+                    duration(v.exclusive_time),
+                    duration(v.total_time),
+                    Cell::from(k.clone()),
+                ])
+            })
+            .collect()
+    }
+}
+
+impl StatefulWidget for &MeasurementTable {
+    type State = TableState;
+
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
+        let widths = [
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(8),
+            Constraint::Length(12),
+            Constraint::Length(12),
+            Constraint::Min(10),
+        ];
+
+        let title = format!(
+            " Performance measurements ({}) - sorted by {} ",
+            self.entries.len(),
+            self.sort_key.label()
+        );
+
+        StatefulWidget::render(
+            Table::new(self.rows(), widths)
+                .header(self.header())
+                .block(Block::bordered().title(title))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+            area,
+            buf,
+            state,
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::measurements::MeasureValues;
+    use crate::tui::{MeasurementTable, SortKey};
+    use alloc::collections::BTreeMap;
+    use alloc::string::ToString;
+    use ratatui::buffer::Buffer;
+    use ratatui::layout::Rect;
+    use ratatui::widgets::{StatefulWidget, TableState};
+
+    fn values(total_time: f64, exclusive_time: f64, n: u64) -> MeasureValues<f64> {
+        MeasureValues {
+            exclusive_cumulative_average: exclusive_time / n.max(1) as f64,
+            exclusive_cumulative_sum_of_squares: 1e-9,
+            exclusive_time,
+            total_cumulative_average: total_time / n.max(1) as f64,
+            total_cumulative_sum_of_squares: 1e-6,
+            total_time,
+            number_of_measurements: n,
+        }
+    }
+
+    #[test]
+    fn test_measurement_table_rows() {
+        let data = BTreeMap::from([
+            ("execute".to_string(), values(12.0, 2.0, 1000)),
+            ("main@execute".to_string(), values(12.0, 2.0, 1000)),
+            ("coverage".to_string(), values(3.5, 3.0, 20)),
+            ("idle".to_string(), values(0.0, 0.0, 0)),
+            ("trace".to_string(), values(7200.0, 1e-7, 3)),
+        ]);
+
+        let mut table = MeasurementTable::from_values(data);
+        assert_eq!(table.len(), 5);
+        assert_eq!(table.rows().len(), 5);
+        assert_eq!(table.sort_key(), SortKey::TotalTime);
+        assert_eq!(table.entries()[0].0, "trace");
+
+        table.sort_by(SortKey::ExclusiveTime);
+        assert_eq!(table.entries()[0].0, "coverage");
+
+        table.sort_by(SortKey::Name);
+        assert!(!table.is_descending());
+        assert_eq!(table.entries()[0].0, "coverage");
+
+        table.toggle_order();
+        assert_eq!(table.entries()[0].0, "trace");
+
+        table.sort_by(table.sort_key().next());
+        assert_eq!(table.sort_key(), SortKey::TotalTime);
+
+        let mut state = TableState::default().with_selected(Some(2));
+        for area in [Rect::new(0, 0, 120, 10), Rect::new(0, 0, 5, 2)] {
+            let mut buffer = Buffer::empty(area);
+            StatefulWidget::render(&table, area, &mut buffer, &mut state);
+        }
+
+        let empty = MeasurementTable::from_values(BTreeMap::new());
+        assert!(empty.is_empty());
+        let area = Rect::new(0, 0, 80, 5);
+        StatefulWidget::render(
+            &empty,
+            area,
+            &mut Buffer::empty(area),
+            &mut TableState::default(),
+        );
+    }
+}
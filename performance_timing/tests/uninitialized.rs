@@ -0,0 +1,33 @@
+//! Runs in its own process, so the global timekeeper is guaranteed to be uninitialized.
+
+use performance_timing::measurements::mm_instance;
+use performance_timing::{initialize, is_available, try_instance, TimeMeasurement};
+
+#[test]
+pub fn try_instance_before_initialization() {
+    assert!(!is_available());
+    assert!(try_instance().is_none());
+
+    // measurements degrade to no-ops instead of panicking
+    let measurement = TimeMeasurement::begin("uninitialized");
+    let (total, exclusive) = measurement.stop();
+    assert_eq!(f64::from(total), 0.0);
+    assert_eq!(f64::from(exclusive), 0.0);
+
+    // the start of this measurement was never recorded, stopping it must not record garbage
+    let started_before = TimeMeasurement::begin("started_before_initialization");
+    initialize(2_699_000_000f64).expect("Unable to initialize timing");
+    assert!(try_instance().is_some());
+    drop(started_before);
+
+    let measurement = TimeMeasurement::begin("started_after_initialization");
+    let _ = measurement.stop();
+
+    let manager = mm_instance().borrow();
+    assert!(!manager.data.contains_key("uninitialized"));
+    assert!(!manager.data.contains_key("started_before_initialization"));
+    assert_eq!(
+        manager.data["started_after_initialization"].number_of_measurements,
+        1
+    );
+}
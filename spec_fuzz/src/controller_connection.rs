@@ -9,9 +9,11 @@ use alloc::format;
 use alloc::vec::Vec;
 use core::fmt::Display;
 use core::pin::Pin;
+use fuzzer_data::auth::OtaKey;
 use fuzzer_data::{
-    Ota, OtaC2D, OtaC2DUnreliable, OtaD2C, OtaD2CTransport, OtaD2CUnreliable, OtaPacket,
-    MAX_FRAGMENT_SIZE, MAX_PAYLOAD_SIZE,
+    encode_payload, LinkStats, Ota, OtaC2D, OtaC2DTransport, OtaC2DUnreliable, OtaD2C,
+    OtaD2CTransport, OtaD2CUnreliable, OtaDecodeError, OtaPacket, MAX_FRAGMENT_SIZE,
+    MAX_PAYLOAD_SIZE, OTA_MESSAGE_VERSION,
 };
 use log::{error, info, trace, warn};
 #[cfg(feature = "__debug_performance_trace")]
@@ -65,6 +67,13 @@ pub enum ConnectionError {
     FragmentOutOfOrder,
     /// Fragment session was closed
     FragmentSessionClosed,
+    /// Controller speaks a different protocol version, see [`OTA_MESSAGE_VERSION`]
+    ProtocolVersionMismatch {
+        /// Protocol version of the controller
+        controller: u16,
+        /// Protocol version of this device
+        device: u16,
+    },
 }
 
 /// Configuration settings for UDP connection
@@ -85,6 +94,8 @@ pub struct ConnectionSettings {
     pub ack_timeout: u64,
     /// Timeout for fragment reception in milliseconds
     pub fragment_timeout: u64,
+    /// Pre-shared key authenticating the packets, `None` to communicate unauthenticated
+    pub key: Option<OtaKey>,
 }
 
 impl Default for ConnectionSettings {
@@ -103,6 +114,7 @@ impl Default for ConnectionSettings {
             resent_attempts: 10,
             ack_timeout: 200,
             fragment_timeout: 1000,
+            key: option_env!("UFUZZ_OTA_KEY").map(OtaKey::from_passphrase),
         }
     }
 }
@@ -131,6 +143,12 @@ pub struct ControllerConnection {
     sequence_number_rx: u64,
     /// Transmit sequence number
     sequence_number_tx: u64,
+    /// Link quality statistics
+    link_stats: LinkStats,
+    /// Protocol version of the controller, `None` before the handshake
+    controller_version: Option<u16>,
+    /// Pre-shared key authenticating the packets
+    key: Option<OtaKey>,
 }
 
 #[cfg_attr(feature = "__debug_performance_trace", track_time)]
@@ -289,6 +307,9 @@ impl ControllerConnection {
             remote_session: 0,
             sequence_number_rx: 0,
             sequence_number_tx: 0,
+            link_stats: LinkStats::default(),
+            controller_version: None,
+            key: settings.key,
         })
     }
 
@@ -316,7 +337,9 @@ impl ControllerConnection {
             data.to_packet(0, 0)
         };
 
-        let buf = packet.serialize().expect("Must always serialize");
+        let buf = packet
+            .serialize_with_key(self.key.as_ref())
+            .expect("Must always serialize");
 
         if buf.len() as u64 > MAX_FRAGMENT_SIZE {
             // fragment
@@ -331,7 +354,8 @@ impl ControllerConnection {
             drop(buf);
             let buf = packet.serialize().expect("Must always serialize");
 
-            let chunks = buf
+            let (payload, compressed) = encode_payload(&buf);
+            let chunks = payload
                 .chunks(MAX_FRAGMENT_SIZE as usize - 128)
                 .collect::<Vec<&[u8]>>();
             for (i, chunk) in chunks.iter().enumerate() {
@@ -341,9 +365,12 @@ impl ControllerConnection {
                     id: self.sequence_number_tx,
                     fragment: i as u64,
                     total_fragments: chunks.len() as u64,
+                    compressed,
                     content: chunk.to_vec(),
                 };
-                let buf = packet.serialize().expect("Must always serialize");
+                let buf = packet
+                    .serialize_with_key(self.key.as_ref())
+                    .expect("Must always serialize");
                 self.send_native(&buf, true)?;
             }
             Ok(())
@@ -373,19 +400,19 @@ impl ControllerConnection {
         let mut virtual_receive_buffer = VecDeque::new();
 
         let mut status = None;
-        'attempt_loop: for _attempt in 0..self.resent_attempts {
+        'attempt_loop: for attempt in 0..self.resent_attempts {
             // initial packet sending
             if let Err(err) = self.channel.as_mut().unwrap().transmit(None, None, data) {
                 error!("Failed to transmit data: {:?}", err);
                 status = Some(Err(ConnectionError::TransmitPacket(err)));
                 break 'attempt_loop;
             }
+            self.link_stats.record_sent(attempt);
 
             // check if requires ack
             if !require_ack {
                 // does not require ack
-                status = Some(Ok(()));
-                break 'attempt_loop;
+                return Ok(());
             }
 
             // wait for ack
@@ -433,7 +460,8 @@ impl ControllerConnection {
             self.virtual_receive_buffer.push_front(packet);
         }
 
-        status.unwrap_or(Err(ConnectionError::TransmitNotAcknowledged))
+        self.link_stats
+            .record_outcome(status, ConnectionError::TransmitNotAcknowledged)
     }
 
     /// Receives a packet from the fuzzing controller
@@ -467,8 +495,19 @@ impl ControllerConnection {
             }
         };
 
-        let data: OtaC2D = match OtaC2D::deserialize(&data) {
+        let data: OtaC2D = match OtaC2D::deserialize_with_key(&data, self.key.as_ref()) {
             Ok(data) => data,
+            Err(e @ OtaDecodeError::ChecksumMismatch { .. }) => {
+                // drop without acknowledgement, the controller retransmits the packet
+                warn!("Dropped corrupted packet: {}", e);
+                return Ok(None);
+            }
+            Err(e) if e.is_authentication_error() => {
+                // not from the controller, or corrupted and retransmitted by the controller
+                warn!("Dropped unauthenticated packet: {}", e);
+                self.link_stats.record_unauthenticated();
+                return Ok(None);
+            }
             Err(e) => {
                 error!("Failed to deserialize data: {:?}", e);
                 return Err(ConnectionError::ReceiveNotDeserializable);
@@ -545,13 +584,16 @@ impl ControllerConnection {
         };
 
         match initial_packet {
-            OtaC2D::Transport { .. } | OtaC2D::Unreliable(_) => Ok(Some(initial_packet)),
+            OtaC2D::Transport { .. } | OtaC2D::Unreliable(_) => {
+                self.handle_handshake(initial_packet)
+            }
             OtaC2D::ChunkedTransport {
                 session,
                 id,
                 content,
                 fragment,
                 total_fragments,
+                compressed,
             } => {
                 return Err(ConnectionError::TransmitLengthExceeded); // todo
 
@@ -583,6 +625,7 @@ impl ControllerConnection {
                             content: received_content,
                             fragment: received_fragment,
                             total_fragments: received_total_fragments,
+                            ..
                         } = received_packet
                         {
                             if session != received_session {
@@ -600,18 +643,68 @@ impl ControllerConnection {
                     }
                 }
 
-                let data: OtaC2D = match OtaC2D::deserialize(&packet_content) {
+                let data: OtaC2D = match OtaC2D::deserialize_payload(&packet_content, compressed) {
                     Ok(data) => data,
                     Err(e) => {
                         error!("Failed to deserialize data: {:?}", e);
                         return Err(ConnectionError::ReceiveNotDeserializable);
                     }
                 };
-                Ok(Some(data))
+                self.handle_handshake(data)
             }
         }
     }
 
+    /// Answers the [`OtaC2DTransport::Hello`] handshake of the controller
+    ///
+    /// Once the controller announced a different [`OTA_MESSAGE_VERSION`], all its packets are
+    /// refused until it announces a matching version.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The received packet
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(OtaC2D))` - Packet to be processed by the caller
+    /// * `Ok(None)` - The packet was a handshake with a matching version
+    /// * `Err(ConnectionError)` - The protocol versions disagree or the answer could not be sent
+    fn handle_handshake(&mut self, packet: OtaC2D) -> Result<Option<OtaC2D>, ConnectionError> {
+        if let OtaC2D::Transport {
+            content: OtaC2DTransport::Hello { version },
+            ..
+        } = &packet
+        {
+            self.controller_version = Some(*version);
+            self.send(OtaD2CTransport::HelloAck {
+                version: OTA_MESSAGE_VERSION,
+            })?;
+            if *version == OTA_MESSAGE_VERSION {
+                info!("Controller speaks protocol version {}", version);
+                return Ok(None);
+            }
+        }
+
+        match self.controller_version {
+            Some(controller) if controller != OTA_MESSAGE_VERSION => {
+                error!(
+                    "Protocol version mismatch: controller {}, device {}",
+                    controller, OTA_MESSAGE_VERSION
+                );
+                Err(ConnectionError::ProtocolVersionMismatch {
+                    controller,
+                    device: OTA_MESSAGE_VERSION,
+                })
+            }
+            _ => Ok(Some(packet)),
+        }
+    }
+
+    /// Returns the link quality statistics of this connection
+    pub fn link_stats(&self) -> LinkStats {
+        self.link_stats
+    }
+
     /// Sends an unreliable log message
     ///
     /// # Arguments
@@ -20,16 +20,27 @@ use alloc::vec::Vec;
 use alloc::{format, vec};
 use core::arch::asm;
 use core::mem;
-use custom_processing_unit::{ms_patch_instruction_write, HookGuard};
+use custom_processing_unit::{
+    apply_ldat_read_func, ms_patch_instruction_write_verified, HookGuard,
+    DEFAULT_WRITE_VERIFY_RETRIES,
+};
+use data_types::addresses::MSRAMHookIndex;
 use fuzzer_data::SpeculationResult;
 use hypervisor::state::GuestRegisters;
 use itertools::Itertools;
 use log::{trace, Level};
 use ucode_compiler_dynamic::instruction::Instruction;
+use ucode_compiler_dynamic::listing::format_listing;
 use ucode_compiler_dynamic::sequence_word::SequenceWord;
+use ucode_compiler_dynamic::Triad;
 use x86::msr::{IA32_PERFEVTSEL0, IA32_PERFEVTSEL1, IA32_PERFEVTSEL2, IA32_PERFEVTSEL3};
 use x86_perf_counter::{PerfEventSpecifier, PerformanceCounter};
 
+/// Hook index redirecting `rdrand` to the experiment
+pub const EXPERIMENT_HOOK: MSRAMHookIndex = MSRAMHookIndex::ZERO;
+/// Hook index redirecting `rdseed` to a SYNCFULL
+pub const SYNCFULL_HOOK: MSRAMHookIndex = MSRAMHookIndex::from_const(1);
+
 /// Checks if the performance monitoring counters (PMCs) are stable
 ///
 /// This function executes a series of speculative microcode NOP instructions multiple times and checks
@@ -103,9 +114,14 @@ pub fn execute_speculation(
         ),
     );
     trace!(
-        "Execute speculation_x86: {} {:04x}",
-        triad[0].opcode(),
-        triad[0].assemble()
+        "Execute speculation_x86:\n{}",
+        format_listing(
+            &[Triad {
+                instructions: triad,
+                sequence_word,
+            }],
+            patches::patch::LABEL_SPECULATIVE_WINDOW
+        )
     );
 
     let _sequence_word = match sequence_word.assemble() {
@@ -119,14 +135,29 @@ pub fn execute_speculation(
                 arch_before: GuestRegisters::default(),
                 arch_after: GuestRegisters::default(),
                 perf_counters: Vec::new(),
+                overflowed: Vec::new(),
             };
         }
     };
 
-    ms_patch_instruction_write(
+    // a failed write would measure whatever instruction was left in the speculative window
+    if let Err(err) = ms_patch_instruction_write_verified(
+        apply_ldat_read_func(),
         patches::patch::LABEL_SPECULATIVE_WINDOW,
         triad[0].assemble() as usize,
-    );
+        DEFAULT_WRITE_VERIFY_RETRIES,
+    ) {
+        let _ = udp.log_reliable(
+            Level::Error,
+            &format!("Failed to patch speculative window: {}", err),
+        );
+        return SpeculationResult {
+            arch_before: GuestRegisters::default(),
+            arch_after: GuestRegisters::default(),
+            perf_counters: Vec::new(),
+            overflowed: Vec::new(),
+        };
+    }
 
     unsafe {
         asm!("rdseed rax", out("rax")_); // SYNCFULL
@@ -155,6 +186,9 @@ pub fn execute_speculation(
 /// This function sets up performance counters, executes the instructions,
 /// and collect the counter values along with architectural state.
 ///
+/// The patch must be applied and `rdrand` and `rdseed` must be hooked with
+/// [`EXPERIMENT_HOOK`] and [`SYNCFULL_HOOK`] before calling this function.
+///
 /// # Arguments
 ///
 /// * `perf_counter_setup` - Array of optional performance event specifiers
@@ -164,7 +198,7 @@ pub fn execute_speculation(
 /// Returns a `SpeculationResult` containing the architectural state and performance
 /// counter values
 #[inline(always)]
-fn collect_perf_counters_values(
+pub fn collect_perf_counters_values(
     perf_counter_setup: [Option<PerfEventSpecifier>; 4],
 ) -> SpeculationResult {
     let mut initial_state = GuestRegisters::default();
@@ -204,6 +238,13 @@ fn collect_perf_counters_values(
         .event()
         .set_enable_counters(perf_counter_setup[3].is_some());
 
+    perf0.clear_overflow();
+    perf1.clear_overflow();
+    perf2.clear_overflow();
+    perf3.clear_overflow();
+
+    // only the experiment and SYNCFULL hooks take part in the measurement
+    let entries = HookGuard::enable(&[EXPERIMENT_HOOK, SYNCFULL_HOOK]);
     let guard = HookGuard::enable_all();
 
     unsafe {
@@ -673,9 +714,16 @@ fn collect_perf_counters_values(
     }
 
     guard.restore();
+    entries.restore();
 
     SpeculationResult {
         perf_counters: vec![perf0.read(), perf1.read(), perf2.read(), perf3.read()],
+        overflowed: vec![
+            perf0.overflowed(),
+            perf1.overflowed(),
+            perf2.overflowed(),
+            perf3.overflowed(),
+        ],
         arch_after: final_state,
         arch_before: initial_state,
     }
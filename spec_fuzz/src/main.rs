@@ -15,17 +15,20 @@ use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use coverage::page_allocation::PageAllocation;
-use custom_processing_unit::{
-    apply_hook_patch_func, apply_patch, hook, CustomProcessingUnit, HookGuard,
+use custom_processing_unit::{apply_hook_patch_func, CustomProcessingUnit, HookGuard};
+use fuzzer_data::{
+    MsrAccessError, OtaC2D, OtaC2DTransport, OtaD2CTransport, ReportExecutionProblem,
+    SpeculationResult, WRITABLE_MSRS,
 };
-use data_types::addresses::MSRAMHookIndex;
-use fuzzer_data::{OtaC2D, OtaC2DTransport, OtaD2CTransport};
+use hypervisor::x86_instructions::{rdmsr_safe, wrmsr_safe};
 use itertools::Itertools;
 use log::{error, trace, warn, Level};
 #[cfg(feature = "__debug_performance_trace")]
 use performance_timing::track_time;
 use spec_fuzz::controller_connection::{ConnectionSettings, ControllerConnection};
-use spec_fuzz::{check_if_pmc_stable, execute_speculation, patches};
+use spec_fuzz::{
+    check_if_pmc_stable, execute_speculation, patches, EXPERIMENT_HOOK, SYNCFULL_HOOK,
+};
 use uefi::boot::ScopedProtocol;
 use uefi::proto::loaded_image::LoadedImage;
 use uefi::{entry, println, CString16, Status};
@@ -82,6 +85,35 @@ fn get_program_args() -> Vec<String> {
 ///
 /// This function initializes the system, sets up the CPU and microcode patches,
 /// establishes communication with the controller, and enters the main fuzzing loop.
+/// Reads or writes a model specific register on behalf of the controller
+///
+/// Writes are restricted to [`WRITABLE_MSRS`] unless the `unsafe-msr` feature is enabled.
+///
+/// # Arguments
+///
+/// * `msr` - Model specific register to access
+/// * `write` - Value to write, the register is read if `None`
+///
+/// # Returns
+///
+/// * `OtaD2CTransport` - The reply to the controller
+fn access_msr(msr: u32, write: Option<u64>) -> OtaD2CTransport {
+    let result = match write {
+        None => rdmsr_safe(msr).map_err(|_| MsrAccessError::GeneralProtectionFault),
+        Some(_) if !cfg!(feature = "unsafe-msr") && !WRITABLE_MSRS.contains(&msr) => {
+            Err(MsrAccessError::NotAllowed)
+        }
+        Some(value) => wrmsr_safe(msr, value)
+            .map(|_| value)
+            .map_err(|_| MsrAccessError::GeneralProtectionFault),
+    };
+
+    match result {
+        Ok(value) => OtaD2CTransport::MsrValue { msr, value },
+        Err(error) => OtaD2CTransport::MsrAccessFailed { msr, error },
+    }
+}
+
 #[entry]
 unsafe fn main() -> Status {
     uefi::helpers::init().unwrap();
@@ -172,15 +204,15 @@ unsafe fn main() -> Status {
         return Status::ABORTED;
     }
 
-    if let Err(err) = apply_patch(&patches::patch::PATCH) {
+    if let Err(err) = cpu.patch_space_mut().apply_fixed(&patches::patch::PATCH) {
         error!("Failed to apply patch: {:?}", err);
         return Status::ABORTED;
     }
 
     // hook rdrand -> EXPERIMENT
-    if let Err(err) = hook(
+    if let Err(err) = cpu.hook(
         apply_hook_patch_func(),
-        MSRAMHookIndex::ZERO,
+        EXPERIMENT_HOOK,
         0x428,
         patches::patch::LABEL_ENTRY,
         true,
@@ -190,9 +222,9 @@ unsafe fn main() -> Status {
     }
 
     // hook rdseed -> SYNCFULL
-    if let Err(err) = hook(
+    if let Err(err) = cpu.hook(
         apply_hook_patch_func(),
-        MSRAMHookIndex::ZERO + 1,
+        SYNCFULL_HOOK,
         0x430,
         patches::patch::LABEL_SYNCFULL,
         true,
@@ -254,12 +286,14 @@ unsafe fn main() -> Status {
                 let processor_version = cpuid!(leaf, node);
                 let capabilities = OtaD2CTransport::Capabilities {
                     coverage_collection: false,
+                    coverage_batch_size: 0,
                     manufacturer: vendor_str,
                     pmc_number: PerformanceCounter::number_of_counters(),
                     processor_version_eax: processor_version.eax,
                     processor_version_ebx: processor_version.ebx,
                     processor_version_ecx: processor_version.ecx,
                     processor_version_edx: processor_version.edx,
+                    ucode_revision: custom_processing_unit::read_ucode_revision(),
                 };
                 if let Err(err) = udp.send(capabilities) {
                     error!("Failed to send capabilities: {:?}", err);
@@ -286,6 +320,65 @@ unsafe fn main() -> Status {
                 break;
             }
             OtaC2DTransport::AreYouThere => {}
+            OtaC2DTransport::Hello { .. } => {
+                // answered by the connection
+            }
+            OtaC2DTransport::ReadMsr { msr } => {
+                if let Err(err) = udp.send(access_msr(msr, None)) {
+                    error!("Failed to send msr value: {:?}", err);
+                    let _ = udp
+                        .log_reliable(Level::Error, format!("Failed to send msr value: {:?}", err));
+                }
+            }
+            OtaC2DTransport::WriteMsr { msr, value } => {
+                let reply = access_msr(msr, Some(value));
+                if let OtaD2CTransport::MsrValue { .. } = reply {
+                    warn!("Wrote {:#x} to msr {:#x}", value, msr);
+                }
+                if let Err(err) = udp.send(reply) {
+                    error!("Failed to send msr value: {:?}", err);
+                    let _ = udp
+                        .log_reliable(Level::Error, format!("Failed to send msr value: {:?}", err));
+                }
+            }
+            OtaC2DTransport::RestorePool(_) => {
+                let _ = udp.log_reliable(Level::Error, "Genetic pool not supported!".to_string());
+            }
+            OtaC2DTransport::SetExecutionPolicy { .. } => {
+                let _ =
+                    udp.log_reliable(Level::Error, "Execution policy not supported!".to_string());
+            }
+            OtaC2DTransport::SetExecutionConfig { .. } => {
+                let _ =
+                    udp.log_reliable(Level::Error, "Execution config not supported!".to_string());
+            }
+            OtaC2DTransport::ExtendDictionary { .. } => {
+                let _ = udp.log_reliable(
+                    Level::Error,
+                    "Mutation dictionary not supported!".to_string(),
+                );
+            }
+            OtaC2DTransport::Ping {
+                nonce,
+                sent_timestamp,
+            } => {
+                let pong = OtaD2CTransport::Pong {
+                    nonce,
+                    sent_timestamp,
+                    device_cycles: unsafe { x86::time::rdtsc() },
+                };
+                if let Err(err) = udp.send(pong) {
+                    error!("Failed to send pong: {:?}", err);
+                    let _ =
+                        udp.log_reliable(Level::Error, format!("Failed to send pong: {:?}", err));
+                }
+            }
+            OtaC2DTransport::GetLinkStats => {
+                let stats = OtaD2CTransport::LinkStats(udp.link_stats());
+                if let Err(err) = udp.send(stats) {
+                    error!("Failed to send link stats: {:?}", err);
+                }
+            }
             OtaC2DTransport::GiveMeYourBlacklistedAddresses =>
             #[cfg_attr(
                 feature = "__debug_performance_trace",
@@ -294,13 +387,19 @@ unsafe fn main() -> Status {
             {
                 let _ = udp.log_reliable(Level::Error, "Blacklist list not supported!".to_string());
             }
-            OtaC2DTransport::ReportPerformanceTiming => {
+            OtaC2DTransport::ReportPerformanceTiming { .. } => {
                 let _ =
                     udp.log_reliable(Level::Error, "Perf measurement not supported!".to_string());
             }
             OtaC2DTransport::SetRandomSeed { seed: _ } => {
                 let _ = udp.log_reliable(Level::Error, "Random seed not supported!".to_string());
             }
+            OtaC2DTransport::ConfirmCoverage { .. } => {
+                let _ = udp.log_reliable(
+                    Level::Error,
+                    "Coverage confirmation not supported!".to_string(),
+                );
+            }
             OtaC2DTransport::ExecuteSample { .. } => {
                 let _ =
                     udp.log_reliable(Level::Error, "Sample execution not supported!".to_string());
@@ -312,10 +411,37 @@ unsafe fn main() -> Status {
                 triad,
                 sequence_word,
                 perf_counter_setup,
+                baseline_triad,
             } => {
-                let result =
-                    execute_speculation(&mut udp, triad, sequence_word, perf_counter_setup);
-                if let Err(err) = udp.send(OtaD2CTransport::UCodeSpeculationResult(result)) {
+                let (result, baseline) =
+                    SpeculationResult::execute_paired(triad, baseline_triad, |triad| {
+                        execute_speculation(
+                            &mut udp,
+                            triad,
+                            sequence_word,
+                            perf_counter_setup.clone(),
+                        )
+                    });
+
+                let anomalies = result.pmc_anomalies(&perf_counter_setup).into_iter().chain(
+                    baseline
+                        .iter()
+                        .flat_map(|baseline| baseline.pmc_anomalies(&perf_counter_setup)),
+                );
+                for events in (&anomalies.chunks(ReportExecutionProblem::MAX_PER_PACKET))
+                    .into_iter()
+                    .map(|chunk| chunk.collect_vec())
+                {
+                    if let Err(err) = udp.send(OtaD2CTransport::ExecutionEvents(events)) {
+                        error!("Failed to send event: {:?}", err);
+                        let _ = udp
+                            .log_reliable(Level::Error, format!("Failed to send event: {:?}", err));
+                    }
+                }
+
+                if let Err(err) =
+                    udp.send(OtaD2CTransport::UCodeSpeculationResult { result, baseline })
+                {
                     error!("Failed to speculation_x86 results: {:?}", err);
                     let _ = udp.log_reliable(
                         Level::Error,
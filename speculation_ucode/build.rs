@@ -14,6 +14,7 @@ fn main() {
         allow_unused: false,
         cpuid: None,
         avoid_unknown_256: false,
+        strict: false,
     };
     ucode_compiler_bridge::preprocess_scripts("patches", "src/patches", "patches")
         .expect("Failed to compile microcode");
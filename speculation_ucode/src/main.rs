@@ -13,7 +13,8 @@ use core::mem;
 use core::mem::offset_of;
 use coverage::page_allocation::PageAllocation;
 use custom_processing_unit::{
-    CustomProcessingUnit, HookGuard, apply_hook_patch_func, apply_patch, hook, hooks_enabled,
+    CustomProcessingUnit, HookGuard, apply_hook_patch_func, apply_patch, hooks_enabled,
+    validate_hook_target,
 };
 use data_types::addresses::MSRAMHookIndex;
 use hypervisor::state::{GuestRegisters, StateDifference};
@@ -54,6 +55,22 @@ unsafe fn main() -> Status {
         return Status::ABORTED;
     }
 
+    let symbols = cpu.rom().symbols();
+    let (Some(rdrand), Some(rdseed)) = (
+        symbols.address("RDRAND_XLAT"),
+        symbols.address("RDSEED_XLAT"),
+    ) else {
+        println!("RDRAND_XLAT or RDSEED_XLAT is unknown for this CPU");
+        return Status::ABORTED;
+    };
+    for target in [rdrand, rdseed] {
+        if let Err(err) = validate_hook_target(cpu.rom(), target) {
+            error!("Invalid hook target: {}", err);
+            return Status::ABORTED;
+        }
+    }
+
+    let hooks_enabled_before = hooks_enabled();
     let disable_hooks = HookGuard::enable_all(); // will be dropped on end of method
 
     if let Err(err) = apply_patch(&patches::patch::PATCH) {
@@ -61,25 +78,25 @@ unsafe fn main() -> Status {
         return Status::ABORTED;
     }
 
-    if let Err(err) = hook(
+    if let Err(err) = cpu.hook(
         apply_hook_patch_func(),
         MSRAMHookIndex::ZERO,
-        ucode_dump::dump::cpu_000506CA::RDRAND_XLAT,
+        rdrand,
         patches::patch::LABEL_EXPERIMENT,
         true,
     ) {
-        println!("Failed to apply hook: {:?}", err);
+        error!("Failed to apply hook: {}", err);
         return Status::ABORTED;
     }
 
-    if let Err(err) = hook(
+    if let Err(err) = cpu.hook(
         apply_hook_patch_func(),
         MSRAMHookIndex::ZERO + 1,
-        ucode_dump::dump::cpu_000506CA::RDSEED_XLAT,
+        rdseed,
         patches::patch::LABEL_SYNCFULL,
         true,
     ) {
-        println!("Failed to apply hook: {:?}", err);
+        error!("Failed to apply hook: {}", err);
         return Status::ABORTED;
     }
 
@@ -90,6 +107,13 @@ unsafe fn main() -> Status {
 
     disable_hooks.restore();
 
+    if hooks_enabled() && !hooks_enabled_before {
+        println!(
+            "Hooks are unexpectedly enabled:\n{}",
+            cpu.snapshot_match_patch()
+        );
+    }
+
     Status::SUCCESS
 }
 
@@ -11,7 +11,8 @@ extern crate alloc;
 use alloc::vec::Vec;
 use core::arch::asm;
 use custom_processing_unit::{
-    CustomProcessingUnit, HookGuard, StagingBufferAddress, apply_hook_patch_func, apply_patch, hook,
+    CustomProcessingUnit, HookGuard, StagingBufferAddress, apply_hook_patch_func, apply_patch,
+    hook, hooks_enabled,
 };
 use data_types::addresses::MSRAMHookIndex;
 use log::info;
@@ -72,6 +73,13 @@ unsafe fn main() -> Status {
         enable_hooks.restore();
     }
 
+    if hooks_enabled() {
+        println!(
+            "Hooks are unexpectedly enabled:\n{}",
+            cpu.snapshot_match_patch()
+        );
+    }
+
     disable_hooks.restore();
 
     Status::SUCCESS
@@ -0,0 +1,202 @@
+//! # Ucode Assemble
+//!
+//! Assembles a single microcode source file and prints the resulting triads.
+//!
+//! ```shell
+//! ucode_assemble <file.u> [--json] [--output <file>] [--include <dir>] [--cpuid <cpuid>] [--avoid-unknown-256] [--strict] [--crate-dir <dir>]
+//! ```
+//!
+//! Includes and functions are resolved relative to the `--include` directory, which defaults
+//! to the directory of the source file. Since the assembler logs to stdout, use `--output`
+//! to obtain only the triads.
+
+use clap::Parser;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use ucode_compiler_bridge::{assemble_file, CompilerOptions};
+
+/// Arguments of the assembler
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Microcode source file to assemble
+    source: PathBuf,
+
+    /// Print the triads as JSON array instead of a Rust array
+    #[arg(long)]
+    json: bool,
+
+    /// Write the triads to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Directory for resolving includes and functions, defaults to the directory of the source
+    #[arg(long)]
+    include: Option<PathBuf>,
+
+    /// CPUID to assemble for
+    #[arg(long)]
+    cpuid: Option<String>,
+
+    /// Refuse to emit the unk_256 encoding
+    #[arg(long)]
+    avoid_unknown_256: bool,
+
+    /// Treat warnings and unused labels as errors
+    #[arg(long)]
+    strict: bool,
+
+    /// Crate directory used to locate the assembler (unless UASM is set), defaults to this crate
+    #[arg(long, default_value = env!("CARGO_MANIFEST_DIR"))]
+    crate_dir: PathBuf,
+}
+
+impl Args {
+    /// Directory used to resolve includes
+    fn include_folder(&self) -> PathBuf {
+        self.include.clone().unwrap_or_else(|| {
+            self.source
+                .parent()
+                .map(|p| p.to_path_buf())
+                .unwrap_or_default()
+        })
+    }
+
+    /// Compiler options selected by the arguments
+    fn compiler_options(&self) -> CompilerOptions {
+        CompilerOptions {
+            cpuid: self.cpuid.clone(),
+            avoid_unknown_256: self.avoid_unknown_256,
+            allow_unused: true,
+            strict: self.strict,
+        }
+    }
+}
+
+/// Formats the triads as JSON array
+fn format_json(triads: &[[u64; 4]]) -> String {
+    let entries = triads
+        .iter()
+        .map(|t| format!("  [{}, {}, {}, {}]", t[0], t[1], t[2], t[3]))
+        .collect::<Vec<String>>()
+        .join(",\n");
+    format!("[\n{entries}\n]")
+}
+
+/// Formats the triads as Rust array
+fn format_rust(triads: &[[u64; 4]]) -> String {
+    let entries = triads
+        .iter()
+        .map(|t| {
+            format!(
+                "    [0x{:012x}, 0x{:012x}, 0x{:012x}, 0x{:08x}],\n",
+                t[0], t[1], t[2], t[3]
+            )
+        })
+        .collect::<String>();
+    format!("[\n{entries}]")
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    match assemble_file(
+        &args.source,
+        args.include_folder(),
+        &args.crate_dir,
+        args.compiler_options(),
+    ) {
+        Ok(triads) => {
+            let text = if args.json {
+                format_json(&triads)
+            } else {
+                format_rust(&triads)
+            };
+            match &args.output {
+                None => println!("{text}"),
+                Some(path) => {
+                    if let Err(err) = std::fs::write(path, format!("{text}\n")) {
+                        eprintln!("Failed to write {:?}: {}", path, err);
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("Failed to assemble {:?}: {}", args.source, err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{format_json, format_rust, Args};
+    use clap::Parser;
+    use std::path::PathBuf;
+    use ucode_compiler_bridge::{assemble_file, ErrorKind};
+
+    #[test]
+    fn test_args() {
+        let args = Args::parse_from(["ucode_assemble", "patches/hook.u"]);
+        assert_eq!(args.include_folder(), PathBuf::from("patches"));
+        assert_eq!(args.crate_dir, PathBuf::from(env!("CARGO_MANIFEST_DIR")));
+        let options = args.compiler_options();
+        assert!(!options.strict && !options.avoid_unknown_256 && options.cpuid.is_none());
+
+        let args = Args::parse_from([
+            "ucode_assemble",
+            "hook.u",
+            "--include",
+            "shared",
+            "--cpuid",
+            "806f8",
+            "--strict",
+            "--avoid-unknown-256",
+            "--crate-dir",
+            "/opt/ufuzz/coverage",
+            "-o",
+            "hook.json",
+            "--json",
+        ]);
+        assert_eq!(args.include_folder(), PathBuf::from("shared"));
+        assert_eq!(args.crate_dir, PathBuf::from("/opt/ufuzz/coverage"));
+        assert_eq!(args.output, Some(PathBuf::from("hook.json")));
+        assert!(args.json);
+        let options = args.compiler_options();
+        assert!(options.strict && options.avoid_unknown_256);
+        assert_eq!(options.cpuid.as_deref(), Some("806f8"));
+
+        assert!(Args::try_parse_from(["ucode_assemble"]).is_err());
+        assert!(Args::try_parse_from(["ucode_assemble", "hook.u", "--unknown"]).is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        let triads = [[0x1, 0x2, 0x3, 0x4], [0xa, 0xb, 0xc, 0xd]];
+        assert_eq!(
+            format_json(&triads),
+            "[\n  [1, 2, 3, 4],\n  [10, 11, 12, 13]\n]"
+        );
+        assert_eq!(
+            format_rust(&triads[..1]),
+            "[\n    [0x000000000001, 0x000000000002, 0x000000000003, 0x00000004],\n]"
+        );
+    }
+
+    #[test]
+    fn test_assemble_missing_source() {
+        let args = Args::parse_from(["ucode_assemble", "/nonexistent/hook.u"]);
+        let result = assemble_file(
+            &args.source,
+            args.include_folder(),
+            &args.crate_dir,
+            args.compiler_options(),
+        );
+        assert!(matches!(
+            result.map_err(|e| e.0),
+            Err(ErrorKind::SourceFileDoesNotExist(_))
+        ));
+    }
+}
@@ -11,10 +11,9 @@
 use error_chain::error_chain;
 use regex::{Captures, Replacer};
 use std::ffi::OsStr;
-use std::io::Read;
 use std::path::Path;
 use std::path::PathBuf;
-use std::process::Command;
+use std::process::{Command, Stdio};
 use std::{env, fs};
 
 error_chain! {
@@ -79,6 +78,11 @@ error_chain! {
             description("File exists but does not contain AUTOGEN_NOTICE")
             display("File {:?} exists but does not contain AUTOGEN_NOTICE", path)
         }
+        /// Error when strict mode is enabled and the patch produced warnings
+        StrictModeViolation(path: PathBuf, warnings: String) {
+            description("Strict mode violation")
+            display("Strict mode violation in {:?}:\n{}", path, warnings)
+        }
     }
 
     skip_msg_variant
@@ -90,9 +94,20 @@ pub struct CompilerOptions {
     /// Optional CPU ID to use during compilation
     pub cpuid: Option<String>,
     /// Whether to avoid unknown 256-bit instructions
+    ///
+    /// Passed to the assembler as `--avoid_unk_256`, which then refuses to emit
+    /// the `unk_256` encoding instead of silently using it.
     pub avoid_unknown_256: bool,
     /// Whether to allow unused code
+    ///
+    /// Annotates the generated patch constants with `#[allow(dead_code)]` and the
+    /// generated module imports with `#[allow(unused_imports)]`.
     pub allow_unused: bool,
+    /// Whether to treat warnings as errors
+    ///
+    /// Unused labels in the patch source and warnings of the assembler (e.g. unknown
+    /// encodings) fail the compilation with [`ErrorKind::StrictModeViolation`].
+    pub strict: bool,
 }
 
 impl AsRef<CompilerOptions> for CompilerOptions {
@@ -180,32 +195,42 @@ impl UcodeCompiler {
             command.arg("--avoid_unk_256");
         }
 
-        let mut cmd = command
-            .spawn()
-            .map_err(|_| ErrorKind::CompilerInvocationError)?;
-
-        let result = cmd.wait().map_err(|_| ErrorKind::CompilerInvocationError)?;
-
-        let mut error_text = String::new();
-
-        if let Some(mut stdout) = cmd.stdout {
-            let mut output = String::new();
-            stdout
-                .read_to_string(&mut output)
+        let (result, error_text) = if compiler_options.as_ref().strict {
+            // wait_with_output drains stdout and stderr concurrently, reading them one after
+            // another could block forever once the assembler fills the other pipe
+            let output = command
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .and_then(|cmd| cmd.wait_with_output())
                 .map_err(|_| ErrorKind::CompilerInvocationError)?;
-            println!("{}", output);
-            error_text.push_str(&output);
-        }
-        if let Some(mut stderr) = cmd.stderr {
-            let mut output = String::new();
-            stderr
-                .read_to_string(&mut output)
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            println!("{}", stdout);
+            eprintln!("{}", stderr);
+            (output.status, format!("{stdout}{stderr}"))
+        } else {
+            let result = command
+                .spawn()
+                .and_then(|mut cmd| cmd.wait())
                 .map_err(|_| ErrorKind::CompilerInvocationError)?;
-            eprintln!("{}", output);
-            error_text.push_str(&output);
-        }
+            (result, String::new())
+        };
 
         if result.success() {
+            if compiler_options.as_ref().strict {
+                let warnings = error_text
+                    .lines()
+                    .filter(|line| line.trim_start().starts_with(UASM_WARNING_PREFIX))
+                    .collect::<Vec<&str>>();
+                if !warnings.is_empty() {
+                    return Err(ErrorKind::StrictModeViolation(
+                        input.to_owned(),
+                        warnings.join("\n"),
+                    )
+                    .into());
+                }
+            }
             Ok(())
         } else {
             match result.code() {
@@ -216,6 +241,9 @@ impl UcodeCompiler {
     }
 }
 
+/// Prefix of the warnings printed by the assembler, errors are prefixed with `[ERROR]`
+const UASM_WARNING_PREFIX: &str = "[WARNING]";
+
 /// Marker string indicating that a file is automatically generated
 pub const AUTOGEN: &str = "// AUTOGEN_NOTICE: this file is automatically generated. Do not change stuff. This file will be overriden without further notice.";
 
@@ -365,6 +393,35 @@ pub fn build_script_compile_folder<P: AsRef<Path>, Q: AsRef<Path>, C: AsRef<Comp
     if !current_directory.exists() {
         panic!("CARGO_MANIFEST_DIR directory does not exist");
     }
+
+    compile_folder(
+        patch_source_folder,
+        target_rust_folder,
+        current_directory,
+        compiler_options,
+    )
+}
+
+/// Compiles all source files in the source directory to the target directory
+///
+/// # Arguments
+///
+/// * `patch_source_folder` - Directory containing the source UASM files
+/// * `target_rust_folder` - Directory where the compiled Rust files will be written
+/// * `current_directory` - Directory of the crate using the patches, used to locate the
+///   assembler (unless `UASM` is set) and the Rust sources for the strict checks
+/// * `compiler_options` - Compiler configuration options
+///
+/// # Returns
+///
+/// Returns a Result containing a vector of patch names if successful
+pub fn compile_folder<P: AsRef<Path>, Q: AsRef<Path>, R: AsRef<Path>, C: AsRef<CompilerOptions>>(
+    patch_source_folder: P,
+    target_rust_folder: Q,
+    current_directory: R,
+    compiler_options: C,
+) -> Result<Vec<String>> {
+    let current_directory = current_directory.as_ref().to_path_buf();
     if !patch_source_folder.as_ref().exists() {
         return Err(
             ErrorKind::SourceFileDoesNotExist(patch_source_folder.as_ref().to_owned()).into(),
@@ -387,6 +444,10 @@ pub fn build_script_compile_folder<P: AsRef<Path>, Q: AsRef<Path>, C: AsRef<Comp
         );
     }
 
+    if compiler_options.as_ref().strict {
+        check_strict_folder(&patch_source_folder, current_directory.join("src"))?;
+    }
+
     let ucode_compiler = if let Ok(path) = env::var("UASM") {
         PathBuf::from(path)
     } else {
@@ -484,6 +545,155 @@ pub fn build_script_compile_folder<P: AsRef<Path>, Q: AsRef<Path>, C: AsRef<Comp
     Ok(patch_names)
 }
 
+/// Collects the labels that are defined in a patch source but never referenced
+///
+/// # Arguments
+///
+/// * `source` - Content of the (preprocessed) patch source
+/// * `ucode_references` - Content of other patch sources and includes that may jump to the labels
+/// * `rust_references` - Rust sources that may use the exported `LABEL_*` constants
+///
+/// # Returns
+///
+/// Returns the names of all unused labels in order of definition
+pub fn unused_labels(source: &str, ucode_references: &str, rust_references: &str) -> Vec<String> {
+    let definition_regex =
+        regex::Regex::new(r"(?m)^\s*<([^>\s]+)>\s*(#.*)?$").expect("regex compile error");
+    let reference_regex = regex::Regex::new(r"<([^>\s]+)>").expect("regex compile error");
+
+    let defined = definition_regex
+        .captures_iter(source)
+        .map(|c| c.get(1).expect("Capture not found").as_str())
+        .collect::<Vec<&str>>();
+
+    defined
+        .iter()
+        .filter(|label| {
+            let local_references = reference_regex
+                .captures_iter(source)
+                .filter(|c| c.get(1).map(|m| m.as_str()) == Some(label))
+                .count();
+            let ucode_referenced = reference_regex
+                .captures_iter(ucode_references)
+                .any(|c| c.get(1).map(|m| m.as_str()) == Some(label));
+            let rust_regex = regex::Regex::new(&format!(
+                r"\bLABEL_{}\b",
+                regex::escape(&label.to_uppercase())
+            ))
+            .expect("regex compile error");
+
+            local_references < 2 && !ucode_referenced && !rust_regex.is_match(rust_references)
+        })
+        .map(|label| label.to_string())
+        .collect()
+}
+
+/// Reads all files with one of the given extensions below a folder
+///
+/// # Arguments
+///
+/// * `folder` - Folder to search recursively
+/// * `extensions` - File extensions to include
+///
+/// # Returns
+///
+/// Returns a Result containing the paths and contents of the files
+fn read_sources<P: AsRef<Path>>(folder: P, extensions: &[&str]) -> Result<Vec<(PathBuf, String)>> {
+    let mut sources = Vec::new();
+    if !folder.as_ref().exists() {
+        return Ok(sources);
+    }
+
+    for entry in folder.as_ref().read_dir().or_else(|e| {
+        Err(ErrorKind::FailedToRead(
+            folder.as_ref().to_owned(),
+            "strict check folder".to_string(),
+            e,
+        ))
+    })? {
+        let path = entry
+            .or_else(|err| {
+                Err(ErrorKind::FailedToRead(
+                    folder.as_ref().to_owned(),
+                    "strict check folder content".to_string(),
+                    err,
+                ))
+            })?
+            .path();
+        if path.is_dir() {
+            sources.extend(read_sources(&path, extensions)?);
+        } else if path
+            .extension()
+            .and_then(|v| v.to_str())
+            .is_some_and(|extension| extensions.contains(&extension))
+        {
+            let content = std::fs::read_to_string(&path).or_else(|e| {
+                Err::<String, Error>(
+                    ErrorKind::FailedToRead(path.clone(), "strict check".to_string(), e).into(),
+                )
+            })?;
+            sources.push((path, content));
+        }
+    }
+
+    Ok(sources)
+}
+
+/// Checks the patch sources of a folder for constructs rejected in strict mode
+///
+/// A label counts as used if any patch source or include of the folder jumps to it, or if
+/// its exported `LABEL_*` constant is referenced by the handwritten Rust sources.
+///
+/// # Arguments
+///
+/// * `patch_source_folder` - Directory containing the (preprocessed) patch sources
+/// * `rust_source_folder` - Directory containing the Rust sources using the compiled patches
+///
+/// # Returns
+///
+/// Returns a Result indicating whether all patches pass the strict checks
+pub fn check_strict_folder<P: AsRef<Path>, Q: AsRef<Path>>(
+    patch_source_folder: P,
+    rust_source_folder: Q,
+) -> Result<()> {
+    let ucode_sources = read_sources(&patch_source_folder, &["u", "up"])?;
+    // generated patch modules export every label, they do not count as usage
+    let rust_references = read_sources(&rust_source_folder, &["rs"])?
+        .into_iter()
+        .filter(|(_, content)| !content.starts_with(AUTOGEN_PREFIX))
+        .map(|(_, content)| content)
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    for (patch, content) in &ucode_sources {
+        if patch.extension().and_then(|v| v.to_str()) != Some("u") {
+            continue;
+        }
+
+        let ucode_references = ucode_sources
+            .iter()
+            .filter(|(other, _)| other != patch)
+            .map(|(_, content)| content.as_str())
+            .collect::<Vec<&str>>()
+            .join("\n");
+
+        let unused = unused_labels(content, &ucode_references, &rust_references);
+        if !unused.is_empty() {
+            return Err(ErrorKind::StrictModeViolation(
+                patch.to_owned(),
+                unused
+                    .iter()
+                    .map(|label| format!("unused label <{label}>"))
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+            )
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
 /// Transforms a C header patch file (output of the microcode compiler) into a Rust file
 ///
 ///
@@ -677,6 +887,109 @@ pub fn transform_h_patch_to_rs_patch<P: AsRef<Path>, Q: AsRef<Path>>(
     Ok(())
 }
 
+/// Reads the assembled triads from a Rust patch file generated by [`transform_h_patch_to_rs_patch`]
+///
+/// # Arguments
+///
+/// * `content` - Content of the generated Rust patch file
+///
+/// # Returns
+///
+/// Returns the triads of the patch, each as three instructions followed by the sequence word
+pub fn read_patch_content(content: &str) -> Vec<[u64; 4]> {
+    let start = match content.find("UCODE_PATCH_CONTENT") {
+        Some(start) => start,
+        None => return Vec::new(),
+    };
+    let regex_entry = regex::Regex::new(
+        r"\[\s*(0[xX][0-9a-fA-F]+)\s*,\s*(0[xX][0-9a-fA-F]+)\s*,\s*(0[xX][0-9a-fA-F]+)\s*,\s*(0[xX][0-9a-fA-F]+)\s*,?\s*\]",
+    )
+    .expect("regex compile error");
+
+    regex_entry
+        .captures_iter(&content[start..])
+        .map(|capture| {
+            let mut entry = [0u64; 4];
+            for (i, value) in entry.iter_mut().enumerate() {
+                let text = capture.get(i + 1).expect("Capture not found").as_str();
+                *value = u64::from_str_radix(&text[2..], 16).expect("Value parse error");
+            }
+            entry
+        })
+        .collect()
+}
+
+/// Preprocesses and assembles a single microcode source file
+///
+/// # Arguments
+///
+/// * `source` - Path to the UASM file
+/// * `include_folder` - Directory for resolving includes and functions
+/// * `current_directory` - Directory used to locate the assembler, see [`compile_folder`]
+/// * `compiler_options` - Compiler configuration options
+///
+/// # Returns
+///
+/// Returns a Result containing the assembled triads if successful
+pub fn assemble_file<P: AsRef<Path>, Q: AsRef<Path>, R: AsRef<Path>, C: AsRef<CompilerOptions>>(
+    source: P,
+    include_folder: Q,
+    current_directory: R,
+    compiler_options: C,
+) -> Result<Vec<[u64; 4]>> {
+    if !source.as_ref().exists() {
+        return Err(ErrorKind::SourceFileDoesNotExist(source.as_ref().to_owned()).into());
+    }
+
+    let work_folder = env::temp_dir().join(format!("ucode_assemble_{}", std::process::id()));
+    let original_folder = work_folder.join("original");
+    let processed_folder = work_folder.join("processed");
+    let compiled_folder = work_folder.join("compiled");
+    for folder in [&original_folder, &processed_folder, &compiled_folder] {
+        fs::create_dir_all(folder).or_else(|e| {
+            Err(ErrorKind::FailedToWrite(
+                folder.to_owned(),
+                "assemble: dir creation".to_string(),
+                e,
+            ))
+        })?;
+    }
+
+    let result = fs::copy(&source, original_folder.join("patch.u"))
+        .or_else(|e| {
+            Err::<u64, Error>(
+                ErrorKind::FailedToRead(
+                    source.as_ref().to_owned(),
+                    "assemble: copy source".to_string(),
+                    e,
+                )
+                .into(),
+            )
+        })
+        .and_then(|_| preprocess_scripts(&original_folder, &processed_folder, &include_folder))
+        .and_then(|_| {
+            compile_folder(
+                &processed_folder,
+                &compiled_folder,
+                &current_directory,
+                &compiler_options,
+            )
+        })
+        .and_then(|_| {
+            let target = compiled_folder.join("patch.rs");
+            fs::read_to_string(&target).or_else(|e| {
+                Err::<String, Error>(
+                    ErrorKind::FailedToRead(target, "assemble: read result".to_string(), e).into(),
+                )
+            })
+        })
+        .map(|content| read_patch_content(&content));
+
+    let _ = fs::remove_dir_all(&work_folder);
+
+    result
+}
+
 /// Marker string for preprocessor errors
 const ERROR_MARKER: &str = "PREPROCESSOR_ERROR_MARKER: ";
 
@@ -855,6 +1168,12 @@ impl Replacer for RepeatReplacer {
 
 /// Execute the preprocessing stage on all files in the source directory and write the processed files to the destination directory.
 ///
+/// Supported directives:
+/// - `include <path>`: inserts the file `path`, resolved relative to `cwd`
+/// - `func name(arg0, arg1)`: inserts `name.func` from `cwd`, replacing its `ARGn` placeholders
+/// - `def [name] := value`: replaces all occurrences of `[name]` with `value`, also across included files
+/// - `repeat n: content`: repeats the line `content` `n` times
+///
 /// # Arguments
 ///
 /// * `src` - Source directory containing UASM files
@@ -1046,3 +1365,176 @@ impl<T: AsRef<Path>> IsTemporaryPathExt for T {
         first[0] == "/" && first[1] == "tmp"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::uasm::{
+        build_script_compile_folder, check_strict_folder, preprocess_scripts, read_patch_content,
+        unused_labels, CompilerOptions, ErrorKind,
+    };
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const PATCH_WITH_UNUSED_LABEL: &str = ".org 7c00
+
+<entry>
+rax := ZEROEXT_DSZ64(0xabab)
+UJMP(<entry>)
+
+<unused> # never jumped to
+NOP
+";
+
+    /// Creates a temporary folder unique to this test process and call
+    fn unique_temp_dir(name: &str) -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        std::env::temp_dir().join(format!(
+            "ucode_compiler_bridge_{name}_{}_{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ))
+    }
+
+    #[test]
+    fn test_unused_labels() {
+        assert_eq!(
+            unused_labels(PATCH_WITH_UNUSED_LABEL, "", ""),
+            vec!["unused"]
+        );
+        assert!(unused_labels(".org 7c00\n<a>\nUJMP(<a>)\n", "", "").is_empty());
+        // jumped to from another patch or an include
+        assert!(unused_labels(PATCH_WITH_UNUSED_LABEL, "UJMP(<unused>)", "").is_empty());
+        // used as entry point by the Rust code, split up since the strict mode test scans this file
+        let rust_usage = concat!("hook(patch::LABEL_", "UNUSED);");
+        assert!(unused_labels(PATCH_WITH_UNUSED_LABEL, "", rust_usage).is_empty());
+        let rust_usage = concat!("hook(patch::LABEL_", "UNUSED_END);");
+        assert_eq!(
+            unused_labels(PATCH_WITH_UNUSED_LABEL, "", rust_usage),
+            vec!["unused"]
+        );
+    }
+
+    #[test]
+    fn test_strict_mode_rejects_unused_label() {
+        let src = unique_temp_dir("test_strict_src");
+        let dst = unique_temp_dir("test_strict_dst");
+        let _ = fs::create_dir_all(&src);
+        let _ = fs::create_dir_all(&dst);
+        fs::write(src.join("patch.u"), PATCH_WITH_UNUSED_LABEL).unwrap();
+
+        let options = CompilerOptions {
+            cpuid: None,
+            avoid_unknown_256: false,
+            allow_unused: false,
+            strict: true,
+        };
+        let strict = build_script_compile_folder(&src, &dst, &options);
+        let relaxed = build_script_compile_folder(
+            &src,
+            &dst,
+            CompilerOptions {
+                strict: false,
+                ..options
+            },
+        );
+        let _ = fs::remove_dir_all(&src);
+        let _ = fs::remove_dir_all(&dst);
+
+        assert!(matches!(
+            strict.map_err(|e| e.0),
+            Err(ErrorKind::StrictModeViolation(_, warnings)) if warnings.contains("<unused>")
+        ));
+        // without strict mode the patch reaches the assembler (which might not be installed)
+        assert!(!matches!(
+            relaxed.map_err(|e| e.0),
+            Err(ErrorKind::StrictModeViolation(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_strict_mode_accepts_labels_used_by_other_sources() {
+        let src = unique_temp_dir("test_strict_shared_src");
+        let rust = unique_temp_dir("test_strict_shared_rust");
+        let _ = fs::create_dir_all(src.join("shared"));
+        let _ = fs::create_dir_all(&rust);
+        fs::write(src.join("patch.u"), PATCH_WITH_UNUSED_LABEL).unwrap();
+        fs::write(src.join("shared").join("jump.up"), "UJMP(<unused>)\n").unwrap();
+        fs::write(src.join("other.u"), ".org 7d00\n<entry_point>\nNOP\n").unwrap();
+        fs::write(
+            rust.join("lib.rs"),
+            "hook(patches::other::LABEL_ENTRY_POINT);\n",
+        )
+        .unwrap();
+
+        let result = check_strict_folder(&src, &rust);
+        let _ = fs::remove_dir_all(&src);
+        let _ = fs::remove_dir_all(&rust);
+
+        result.unwrap();
+    }
+
+    #[test]
+    fn test_preprocess_include_definitions() {
+        let base = unique_temp_dir("test_include");
+        let src = base.join("src");
+        let dst = base.join("dst");
+        let patches = base.join("patches");
+        let _ = fs::create_dir_all(&src);
+        let _ = fs::create_dir_all(patches.join("shared"));
+
+        fs::write(
+            patches.join("shared").join("constants.up"),
+            "def [magic] := 0xabab\ndef [scratch] := tmp5\n<shared_label>\nNOP\n",
+        )
+        .unwrap();
+        fs::write(
+            src.join("patch.u"),
+            ".org 7c00\ninclude <shared/constants.up>\n[scratch] := ZEROEXT_DSZ64([magic])\nUJMP(<shared_label>)\n",
+        )
+        .unwrap();
+
+        let result = preprocess_scripts(&src, &dst, &patches);
+        let processed = fs::read_to_string(dst.join("patch.u"));
+        let _ = fs::remove_dir_all(&base);
+
+        result.unwrap();
+        let processed = processed.unwrap();
+        assert!(processed.contains("tmp5 := ZEROEXT_DSZ64(0xabab)"));
+        assert!(processed.contains("<shared_label>"));
+        assert!(!processed.contains("[magic]"));
+        assert!(!processed.contains("include"));
+    }
+
+    #[test]
+    fn test_read_patch_content() {
+        let content = "
+        pub const PATCH: Patch<'static, 'static, 'static> = Patch {
+            addr: UCInstructionAddress::from_const(0x7c00),
+            ucode_patch: &UCODE_PATCH_CONTENT,
+            hook_address: None,
+            hook_index: None,
+            labels: &LABELS,
+        };
+
+        pub const UCODE_PATCH_CONTENT: [UcodePatchEntry; 2] = [
+            [0x6e75406aa00d, 0x300000030030, 0x000000000000, 0x0300a8c0],
+            [
+                0x0000000000000001,
+                0x0000000000000002,
+                0x0000000000000003,
+                0x00000004,
+            ],
+        ];
+        ";
+
+        assert_eq!(
+            read_patch_content(content),
+            vec![
+                [0x6e75406aa00d, 0x300000030030, 0x0, 0x0300a8c0],
+                [0x1, 0x2, 0x3, 0x4],
+            ]
+        );
+        assert!(read_patch_content("").is_empty());
+    }
+}
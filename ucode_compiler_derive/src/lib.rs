@@ -70,6 +70,7 @@ fn compile(text: &str) -> String {
         CompilerOptions {
             allow_unused: true,
             avoid_unknown_256: true,
+            strict: false,
             cpuid: None,
         },
     )
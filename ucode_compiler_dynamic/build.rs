@@ -8,6 +8,314 @@ fn main() {
     generate_opcode_file("src/opcodes.txt", "src/opcodes.rs");
 }
 
+/// Static classification of the known opcodes
+///
+/// Each entry generates a predicate on `Opcode` matching all opcodes whose mnemonic equals one
+/// of the prefixes or starts with one of them followed by `_`. Every known opcode must be part
+/// of at least one class.
+const CLASSIFICATION: &[(&str, &str, &[&str])] = &[
+    (
+        "is_crbus_access",
+        "Reads or writes a control register via the CRBUS",
+        &["MOVETOCREG", "MOVEFROMCREG", "MSR2CR"],
+    ),
+    (
+        "is_uram_access",
+        "Reads or writes the microcode scratch RAM",
+        &["READURAM", "WRITEURAM"],
+    ),
+    (
+        "is_memory_load",
+        "Loads from memory or an I/O port",
+        &[
+            "LDZX",
+            "LDTICKLE",
+            "LDPPHYS",
+            "LDPPHYSTICKLE",
+            "LDSTGBUF",
+            "LDHINT",
+            "FETCHFROMEIP0",
+            "FETCHFROMEIP1",
+            "PORTIN",
+        ],
+    ),
+    (
+        "is_memory_store",
+        "Stores to memory or an I/O port, the destination field selects the stored data register",
+        &[
+            "STAD",
+            "STADTICKLE",
+            "STADPPHYS",
+            "STADPPHYSTICKLE",
+            "STADSTGBUF",
+            "SIMDHSTADSTGBUF",
+            "SIMDLSTADSTGBUF",
+            "SIMDSTADPPHYS",
+            "PORTOUT",
+        ],
+    ),
+    (
+        "is_branch",
+        "Changes the microcode or macrocode control flow",
+        &[
+            "UJMP",
+            "UJMPCC_DIRECT",
+            "BTUJB_DIRECT",
+            "BTUJNB_DIRECT",
+            "CMPUJZ_DIRECT",
+            "CMPUJNZ_DIRECT",
+            "URET",
+            "MJMPCC",
+            "MJMPTARGET_INDIRECT",
+            "MCALL_DIRECT",
+            "UCALLPARAM_INDIRECT",
+            "SUBR",
+            "TESTUSTATE",
+        ],
+    ),
+    (
+        "is_arithmetic",
+        "Integer arithmetic, logic, shift or bit operation",
+        &[
+            "ADC",
+            "ADD",
+            "SUB",
+            "SBB",
+            "INC",
+            "DEC",
+            "NEG",
+            "MUL",
+            "IMUL",
+            "IMUL32L",
+            "IMUL64L",
+            "AND",
+            "OR",
+            "XOR",
+            "NOTAND",
+            "TEST",
+            "BT",
+            "BTC",
+            "BTR",
+            "BTS",
+            "SHL",
+            "SHR",
+            "SAR",
+            "ROL",
+            "ROR",
+            "RCL",
+            "SHLD",
+            "SHRD",
+            "BSWAP",
+            "GENARITHFLAGS",
+            "RCXBTCNTMSK",
+            "LEA",
+            "LA2LIN",
+            "SETCC",
+            "SELECTCC",
+        ],
+    ),
+    (
+        "writes_flags",
+        "Writes the arithmetic flags",
+        &[
+            "ADC",
+            "ADD",
+            "SUB",
+            "SBB",
+            "INC",
+            "DEC",
+            "NEG",
+            "MUL",
+            "IMUL",
+            "IMUL32L",
+            "IMUL64L",
+            "AND",
+            "OR",
+            "XOR",
+            "NOTAND",
+            "TEST",
+            "BT",
+            "BTC",
+            "BTR",
+            "BTS",
+            "SHL",
+            "SHR",
+            "SAR",
+            "ROL",
+            "ROR",
+            "RCL",
+            "SHLD",
+            "SHRD",
+            "GENARITHFLAGS",
+            "CLC",
+            "STC",
+            "CMC",
+            "SAHF",
+            "MOVEINSERTFLGS",
+            "MOVEMERGEFLGS",
+        ],
+    ),
+    (
+        "is_data_move",
+        "Moves, extends or combines values without computing on them",
+        &[
+            "MOVE",
+            "MOVSX",
+            "MOVZX",
+            "ZEROEXT",
+            "CMOVCC",
+            "CONCAT",
+            "READAFLAGS",
+            "LAHF",
+            "MOVEINSERTFLGS",
+            "MOVEMERGEFLGS",
+            "CLC",
+            "STC",
+            "CMC",
+            "SAHF",
+            "READUIP_REGOVR",
+            "SAVEUIP",
+            "SAVEUIP_REGOVR",
+            "RDSEGFLD",
+            "WRSEGFLD",
+            "RDVMCSPLA",
+            "PSELECT_CPL0",
+            "MPUSH",
+            "MPOP",
+        ],
+    ),
+    (
+        "is_simd",
+        "SIMD or SSE operation",
+        &[
+            "ADDPD",
+            "ADDSUB",
+            "ANDNPD",
+            "ANDPD",
+            "CMPPD",
+            "COMISD",
+            "CVTDQ2PS",
+            "CVTPD2PI",
+            "CVTPD2PS",
+            "CVTPI2PD",
+            "CVTTPD2PI",
+            "DIVPD",
+            "MASKMOVDQU",
+            "MAXPD",
+            "MINPD",
+            "MOVDQU",
+            "MOVHLPS",
+            "MOVHPD",
+            "MOVLPD",
+            "MOVMSKPD",
+            "MOVNTDQ",
+            "MOVNTPD",
+            "MOVUPD",
+            "MULPD",
+            "ORPD",
+            "PACKSSDW",
+            "PACKSSWB",
+            "PACKUSWB",
+            "PADDB",
+            "PADDD",
+            "PADDQ",
+            "PADDSB",
+            "PADDSW",
+            "PADDUSB",
+            "PADDUSW",
+            "PADDW",
+            "PALIGNR",
+            "PAND",
+            "PANDN",
+            "PAVGB",
+            "PAVGW",
+            "PCMPEQB",
+            "PCMPEQD",
+            "PCMPEQW",
+            "PCMPGTB",
+            "PCMPGTD",
+            "PCMPGTW",
+            "PEXTRW",
+            "PINSRW",
+            "PINTMOVDI2MM",
+            "PINTMOVDMM2I",
+            "PINTMOVDTMM2I",
+            "PMADDWD",
+            "PMAXSW",
+            "PMAXUB",
+            "PMINSW",
+            "PMINUB",
+            "PMOVMSKB",
+            "PMULHUW",
+            "PMULHW",
+            "PMULLW",
+            "PMULUDQ",
+            "POR",
+            "PSADBW",
+            "PSHUFD",
+            "PSLLD",
+            "PSLLQ",
+            "PSLLW",
+            "PSRAD",
+            "PSRAW",
+            "PSRLD",
+            "PSRLQ",
+            "PSRLW",
+            "PSUBB",
+            "PSUBD",
+            "PSUBQ",
+            "PSUBSB",
+            "PSUBSW",
+            "PSUBUSB",
+            "PSUBUSW",
+            "PSUBW",
+            "PUNPCKHBW",
+            "PUNPCKHDQ",
+            "PUNPCKHWD",
+            "PUNPCKLBW",
+            "PUNPCKLDQ",
+            "PUNPCKLWD",
+            "PXOR",
+            "RSQRTPS",
+            "SHUFPD",
+            "SQRTPD",
+            "SUBPD",
+            "UCOMISD",
+            "UNPCKHPD",
+            "UNPCKLPD",
+            "XORPD",
+        ],
+    ),
+    (
+        "is_floating_point",
+        "x87 floating point operation",
+        &[
+            "FADDP",
+            "FCMOVNE",
+            "FCOM2",
+            "FCOMIP",
+            "FDIV",
+            "FILD",
+            "FMOV",
+            "FPREADROM_DTYPENOP",
+        ],
+    ),
+    (
+        "is_sequencer_control",
+        "Controls the microcode sequencer, synchronization or tracing",
+        &[
+            "SFENCE",
+            "LBSYNC",
+            "UFLOWCTRL",
+            "UPDATEUSTATE",
+            "TESTUSTATE",
+            "SIGEVENT",
+            "AETTRACE",
+            "WRMSLOOPCTRFBR",
+        ],
+    ),
+];
+
 fn generate_opcode_file<A: AsRef<Path>, B: AsRef<Path>>(opcodes: A, target_file: B) {
     let opcode_file = fs::read_to_string(&opcodes).expect("Failed to read opcodes file");
     println!(
@@ -124,6 +432,62 @@ fn generate_opcode_file<A: AsRef<Path>, B: AsRef<Path>>(opcodes: A, target_file:
         .as_str(),
     );
 
+    // is statically predicted as taken
+    result.push_str(
+        format!(
+            "pub const fn is_predicted_taken(&self) -> bool {{ matches!(self, {}) }}\n",
+            definitions
+                .iter()
+                .filter(|(mm, _, _)| mm.contains("_TAKEN"))
+                .map(|(mm, _, _)| format!("Self::{mm}"))
+                .join("|")
+        )
+        .as_str(),
+    );
+
+    // classification predicates, see `CLASSIFICATION`
+    for (name, doc, prefixes) in CLASSIFICATION {
+        let match_text = definitions
+            .iter()
+            .filter(|(mm, _, _)| {
+                prefixes
+                    .iter()
+                    .any(|prefix| mm == prefix || mm.starts_with(format!("{prefix}_").as_str()))
+            })
+            .map(|(mm, _, _)| format!("Self::{mm}"))
+            .join("|");
+        result.push_str(
+            format!("/// {doc}\npub const fn {name}(&self) -> bool {{ matches!(self, {match_text}) }}\n")
+                .as_str(),
+        );
+    }
+
+    // accesses memory, the immediate bits of the instruction encode the address mode
+    result.push_str(
+        format!(
+            "pub const fn is_memory_access(&self) -> bool {{ matches!(self, {}) }}\n",
+            definitions
+                .iter()
+                .filter(|(mm, _, _)| mm.contains("_ASZ"))
+                .map(|(mm, _, _)| format!("Self::{mm}"))
+                .join("|")
+        )
+        .as_str(),
+    );
+
+    // mnemonic lookup, inverse of the `Display` implementation
+    result.push_str(
+        "/// Gets the opcode from its mnemonic, as printed by the `Display` implementation\n",
+    );
+    result.push_str("pub fn from_mnemonic(mnemonic: &str) -> Option<Self> {\n");
+    result.push_str("match mnemonic {\n");
+    for (mm, _, _) in definitions.iter() {
+        result.push_str(format!("\"{mm}\" => Some(Self::{mm}),\n").as_str());
+    }
+    result.push_str("_ => None,\n");
+    result.push_str("}\n");
+    result.push_str("}\n");
+
     result.push_str("}\n");
 
     fs::write(&target_file, result).expect("Failed to write opcode file");
@@ -3,17 +3,21 @@
 //! This module provides functionality for working with individual microcode instructions.
 //! It includes tools for assembling, disassembling, and manipulating microcode instructions.
 
-use crate::even_odd_parity_u64;
 use crate::opcodes::Opcode;
+use crate::EvenOddParity;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 use core::fmt::Display;
 use data_types::addresses::{Address, UCInstructionAddress};
 use num_traits::FromPrimitive;
+#[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
 /// A microcode instruction
 ///
 /// This type represents a single microcode instruction.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Ord, PartialOrd)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(transparent)]
 pub struct Instruction {
     /// The raw instruction value
@@ -39,6 +43,49 @@ impl Instruction {
         }
     }
 
+    /// Creates a new instruction from an encoded microcode operation, validating its parity
+    ///
+    /// Use this for instructions read from the MSRAM, e.g. to detect failed or corrupted writes.
+    /// The two parity bits are the even and odd parity of the instruction, see
+    /// [`crate::even_odd_parity_u64`].
+    ///
+    /// # Arguments
+    ///
+    /// * `micro_operation` - The 48-bit microcode operation value including the parity bits
+    ///
+    /// # Returns
+    ///
+    /// Returns the instruction, or an error if the value is longer than 48 bits or the
+    /// parity does not match
+    pub fn disassemble_checked(
+        micro_operation: u64,
+    ) -> Result<Instruction, InstructionDisassembleError> {
+        if micro_operation >> 48 != 0 {
+            return Err(InstructionDisassembleError::InvalidLength(micro_operation));
+        }
+
+        let instruction = Instruction::disassemble(micro_operation);
+        let expected = crate::even_odd_parity_u64(instruction.instruction);
+        let found = micro_operation >> 46;
+        if expected != found {
+            return Err(InstructionDisassembleError::ParityMismatch { expected, found });
+        }
+
+        Ok(instruction)
+    }
+
+    /// Gets the raw instruction bits without the parity
+    ///
+    /// Unlike [`Instruction::opcode`] and the operand accessors, this includes the reserved
+    /// bits 44-45, so unknown encodings round-trip unchanged through [`Instruction::disassemble`].
+    ///
+    /// # Returns
+    ///
+    /// Returns the 46-bit instruction value, like stored in the MSROM
+    pub const fn raw_bits(&self) -> u64 {
+        self.instruction
+    }
+
     /// Creates a new instruction from an opcode
     ///
     /// # Arguments
@@ -85,7 +132,345 @@ impl Instruction {
     /// Returns the complete instruction value including CRC (like stored in the MSRAM)
     pub fn assemble(&self) -> u64 {
         let instruction = self.assemble_no_crc();
-        instruction | even_odd_parity_u64(instruction) << 46
+        instruction | instruction.even_odd_parity() << 46
+    }
+
+    /// Decodes the immediate value of source operand 1
+    ///
+    /// # Returns
+    ///
+    /// Returns the immediate value if source operand 1 is a (non-macro) immediate
+    pub fn immediate_src1(&self) -> Option<usize> {
+        match self.decode_operand((self.instruction >> 6) & 0x3f) {
+            Operand::Immediate(immediate) => Some(immediate as usize),
+            _ => None,
+        }
+    }
+
+    /// Decodes the operand selected by a 6-bit operand selector
+    ///
+    /// # Arguments
+    ///
+    /// * `selector` - The operand selector
+    ///
+    /// # Returns
+    ///
+    /// Returns the decoded operand, [`Operand::Unknown`] if the opcode is not known
+    fn operand(&self, selector: u64) -> Operand {
+        if self.opcode().is_group_UNKNOWN() {
+            Operand::Unknown
+        } else {
+            self.decode_operand(selector)
+        }
+    }
+
+    /// Decodes the operand selected by a 6-bit operand selector, regardless of the opcode
+    ///
+    /// Immediate values are split across the instruction: the lower 8 bits are stored at
+    /// bits 24-31, bits 8-12 at bits 18-22 and bits 13-15 in the selector itself. Macro
+    /// immediates only use the lower 8 bits.
+    ///
+    /// # Arguments
+    ///
+    /// * `selector` - The operand selector
+    ///
+    /// # Returns
+    ///
+    /// Returns the decoded operand, never [`Operand::Unknown`]
+    fn decode_operand(&self, selector: u64) -> Operand {
+        if selector == 0 {
+            Operand::None
+        } else if selector & 0x38 != 0x08 {
+            Operand::Register(selector as u8)
+        } else if (self.instruction >> 23) & 1 != 0 {
+            Operand::MacroImmediate((self.instruction >> 24) as u8)
+        } else {
+            Operand::Immediate(
+                (((self.instruction >> 24) & 0xff)
+                    | ((self.instruction >> 10) & 0x1f00)
+                    | ((selector & 0x7) << 13)) as u16,
+            )
+        }
+    }
+
+    /// Gets source operand 0
+    ///
+    /// # Returns
+    ///
+    /// Returns the first source operand, [`Operand::Unknown`] if the opcode is not known
+    pub fn src0(&self) -> Operand {
+        self.operand(self.instruction & 0x3f)
+    }
+
+    /// Gets source operand 1
+    ///
+    /// # Returns
+    ///
+    /// Returns the second source operand, [`Operand::Unknown`] if the opcode is not known
+    pub fn src1(&self) -> Operand {
+        self.operand((self.instruction >> 6) & 0x3f)
+    }
+
+    /// Gets the destination operand
+    ///
+    /// Stores use the destination field to select the stored data register and
+    /// conditional jumps to encode further implicit operands, in both cases no register is written.
+    ///
+    /// # Returns
+    ///
+    /// Returns the destination register if any, [`Operand::Unknown`] if the opcode is not known
+    pub fn dst(&self) -> Operand {
+        let opcode = self.opcode();
+        if opcode.is_memory_store() || opcode.is_conditional_jump() {
+            return Operand::None;
+        }
+
+        match self.operand((self.instruction >> 12) & 0x3f) {
+            Operand::Immediate(_) | Operand::MacroImmediate(_) => Operand::Unknown,
+            operand => operand,
+        }
+    }
+
+    /// Gets the immediate operand
+    ///
+    /// An instruction encodes at most one immediate, either as source operand 0 or 1.
+    /// For direct jumps, this is the jump target.
+    ///
+    /// # Returns
+    ///
+    /// Returns the immediate operand, [`Operand::None`] if there is none and
+    /// [`Operand::Unknown`] if the opcode is not known
+    pub fn immediate(&self) -> Operand {
+        match (self.src0(), self.src1()) {
+            (Operand::Unknown, _) => Operand::Unknown,
+            (operand @ (Operand::Immediate(_) | Operand::MacroImmediate(_)), _) => operand,
+            (_, operand @ (Operand::Immediate(_) | Operand::MacroImmediate(_))) => operand,
+            _ => Operand::None,
+        }
+    }
+
+    /// Gets the registers read by the instruction
+    ///
+    /// # Returns
+    ///
+    /// Returns the selectors of all read registers, including the data register of stores.
+    /// Returns `None` if the opcode is not known
+    pub fn registers_read(&self) -> Option<Vec<u8>> {
+        let data = if self.opcode().is_memory_store() {
+            self.operand((self.instruction >> 12) & 0x3f)
+        } else {
+            Operand::None
+        };
+
+        let mut result = Vec::new();
+        for operand in [self.src0(), self.src1(), data] {
+            match operand {
+                Operand::Unknown => return None,
+                Operand::Register(register) if !result.contains(&register) => result.push(register),
+                _ => {}
+            }
+        }
+        Some(result)
+    }
+
+    /// Gets the register written by the instruction
+    ///
+    /// # Returns
+    ///
+    /// Returns the selector of the written register, `None` if no register is written or
+    /// the opcode is not known
+    pub fn register_written(&self) -> Option<u8> {
+        match self.dst() {
+            Operand::Register(register) => Some(register),
+            _ => None,
+        }
+    }
+
+    /// Gets the structure of the instruction, with the immediate value masked out
+    ///
+    /// Instructions with the same key only differ in their immediate value. Register
+    /// operands and the opcode, including its data size, are kept. Macro immediates and
+    /// instructions with an unknown operand encoding, i.e. unknown opcodes and memory
+    /// accesses without immediate operand, are kept unchanged.
+    ///
+    /// # Returns
+    ///
+    /// Returns the structural key of the instruction
+    pub fn structural_key(&self) -> StructuralKey {
+        let src0 = self.instruction & 0x3f;
+        let src1 = (self.instruction >> 6) & 0x3f;
+        let src0_immediate = src0 & 0x38 == 0x08;
+        let src1_immediate = src1 & 0x38 == 0x08;
+
+        if self.opcode().is_group_UNKNOWN()
+            || (self.instruction >> 23) & 1 != 0
+            || !(src0_immediate || src1_immediate)
+        {
+            return StructuralKey(self.instruction);
+        }
+
+        // keep the immediate marker of the selector, but drop its value bits
+        let mut value = self.instruction & !IMMEDIATE_MASK;
+        if src0_immediate {
+            value &= !0x7;
+        }
+        if src1_immediate {
+            value &= !(0x7 << 6);
+        }
+        StructuralKey(value)
+    }
+
+    /// Checks if two instructions only differ in their immediate value
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The instruction to compare with
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if both instructions have the same [`Instruction::structural_key`]
+    pub fn structural_eq(&self, other: &Instruction) -> bool {
+        self.structural_key() == other.structural_key()
+    }
+
+    /// Fails if the opcode is not known, since its operand encoding is unknown as well
+    fn check_opcode_known(&self) -> EncodeResult<()> {
+        if self.opcode().is_group_UNKNOWN() {
+            Err(EncodeError::UnknownOpcode(self.opcode_raw()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Replaces an operand selector with a register selector
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - Name of the operand field, used for error reporting
+    /// * `shift` - Bit offset of the operand selector
+    /// * `register` - The register selector, `0` to leave the operand unused
+    ///
+    /// # Returns
+    ///
+    /// Returns the re-encoded instruction
+    fn with_register(&self, field: &'static str, shift: u32, register: u8) -> EncodeResult<Self> {
+        self.check_opcode_known()?;
+        if register >= 0x40 || register & 0x38 == 0x08 {
+            return Err(EncodeError::InvalidRegister(field, register));
+        }
+
+        let mut value = self.instruction;
+        if (value >> shift) & 0x38 == 0x08 {
+            // the replaced operand was the immediate
+            value &= !IMMEDIATE_MASK;
+        }
+        value = (value & !(0x3f << shift)) | (register as u64) << shift;
+        Ok(Instruction::disassemble(value))
+    }
+
+    /// Replaces source operand 0 with a register
+    ///
+    /// # Arguments
+    ///
+    /// * `register` - The register selector, e.g. `0x30` for `tmp0`, or `0` for no operand
+    ///
+    /// # Returns
+    ///
+    /// Returns the re-encoded instruction, or an error if the selector is not a register
+    /// or the opcode is not known
+    pub fn with_src0(&self, register: u8) -> EncodeResult<Self> {
+        self.with_register("src0", 0, register)
+    }
+
+    /// Replaces source operand 1 with a register
+    ///
+    /// # Arguments
+    ///
+    /// * `register` - The register selector, e.g. `0x30` for `tmp0`, or `0` for no operand
+    ///
+    /// # Returns
+    ///
+    /// Returns the re-encoded instruction, or an error if the selector is not a register
+    /// or the opcode is not known
+    pub fn with_src1(&self, register: u8) -> EncodeResult<Self> {
+        self.with_register("src1", 6, register)
+    }
+
+    /// Replaces the destination register
+    ///
+    /// For stores, this is the stored data register. Conditional jumps use the destination
+    /// field for implicit operands and can not be re-encoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `register` - The register selector, e.g. `0x30` for `tmp0`, or `0` for no destination
+    ///
+    /// # Returns
+    ///
+    /// Returns the re-encoded instruction, or an error if the selector is not a register
+    /// or the opcode does not have a destination register
+    pub fn with_dst(&self, register: u8) -> EncodeResult<Self> {
+        if self.opcode().is_conditional_jump() {
+            return Err(EncodeError::UnsupportedField("dst"));
+        }
+        self.with_register("dst", 12, register)
+    }
+
+    /// Replaces the immediate operand
+    ///
+    /// The immediate replaces an existing immediate operand, otherwise it is placed
+    /// in the first unused source operand, starting with source operand 1.
+    ///
+    /// # Arguments
+    ///
+    /// * `immediate` - The immediate value, at most 16 bits
+    ///
+    /// # Returns
+    ///
+    /// Returns the re-encoded instruction, or an error if the value is out of range or
+    /// there is no source operand available for it
+    pub fn with_immediate(&self, immediate: u64) -> EncodeResult<Self> {
+        self.check_opcode_known()?;
+        if immediate > 0xffff {
+            return Err(EncodeError::ValueOutOfRange("immediate", immediate));
+        }
+
+        let src0 = self.instruction & 0x3f;
+        let src1 = (self.instruction >> 6) & 0x3f;
+        let src0_immediate = src0 & 0x38 == 0x08;
+        let src1_immediate = src1 & 0x38 == 0x08;
+        if !src0_immediate && !src1_immediate && self.opcode().is_memory_access() {
+            // the immediate bits hold the address mode and displacement
+            return Err(EncodeError::UnsupportedField("immediate"));
+        }
+
+        let value = self.instruction & !IMMEDIATE_MASK;
+        let value = if src0_immediate || (!src1_immediate && src1 != 0 && src0 == 0) {
+            (value & !0x3f) | Self::immediate_src0_encode(immediate)
+        } else if src1_immediate || src1 == 0 {
+            (value & !(0x3f << 6)) | Self::immediate_src1_encode(immediate)
+        } else {
+            return Err(EncodeError::UnsupportedField("immediate"));
+        };
+        Ok(Instruction::disassemble(value))
+    }
+
+    /// Replaces the opcode, keeping all operands
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - The new opcode
+    ///
+    /// # Returns
+    ///
+    /// Returns the re-encoded instruction, or an error if the opcode is not known
+    pub fn with_opcode(&self, opcode: Opcode) -> EncodeResult<Self> {
+        if opcode.is_group_UNKNOWN() {
+            return Err(EncodeError::UnknownOpcode(opcode as u16));
+        }
+        Ok(Instruction::disassemble(
+            (self.instruction & !(0xfff << 32)) | Self::opcode_encode(opcode),
+        ))
     }
 
     /// Encodes an immediate value for source operand 1
@@ -104,6 +489,22 @@ impl Instruction {
             | (1 << 9)
     }
 
+    /// Encodes an immediate value for source operand 0
+    ///
+    /// # Arguments
+    ///
+    /// * `src0_imm` - The immediate value to encode
+    ///
+    /// # Returns
+    ///
+    /// Returns the encoded immediate value
+    const fn immediate_src0_encode(src0_imm: u64) -> u64 {
+        (((src0_imm) & 0xff) << 24)
+            | (((src0_imm) & 0x1f00) << 10)
+            | (((src0_imm) & 0xe000) >> 13)
+            | (1 << 3)
+    }
+
     /// Encodes an instruction with a specifyable opcode
     ///
     /// # Arguments
@@ -132,6 +533,252 @@ impl Instruction {
             | Self::immediate_src1_encode(address.into().address() as u64);
         Instruction::disassemble(value)
     }
+
+    /// Parses an instruction from its textual representation
+    ///
+    /// Accepts the syntax `[dst:=] MNEMONIC[(src0, src1)]` as printed by the microcode
+    /// disassembler, e.g. `tmp1:= SUB_DSZ32(tmp0, 0x10)`, as well as the plain mnemonic
+    /// printed by the `Display` implementation. Source operands are either registers
+    /// (`tmp0`-`tmp15`, `rax`-`r15`, ...) or a single immediate value of at most 16 bits.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text to parse
+    ///
+    /// # Returns
+    ///
+    /// Returns a Result containing the parsed instruction, or the offending token and its offset
+    pub fn parse(text: &str) -> Result<Instruction, ParseError> {
+        let (dst, rest, rest_offset) = match text.find(":=") {
+            Some(index) => {
+                let (offset, dst) = token(&text[..index], 0);
+                let dst = register_selector(dst)
+                    .ok_or_else(|| ParseError::UnknownRegister(offset, dst.to_string()))?;
+                (Some(dst), &text[index + 2..], index + 2)
+            }
+            None => (None, text, 0),
+        };
+
+        let (head, operands) = match rest.find('(') {
+            Some(open) => {
+                let (offset, tail) = token(&rest[open + 1..], rest_offset + open + 1);
+                let Some(operands) = tail.strip_suffix(')') else {
+                    return Err(ParseError::UnexpectedToken(offset, tail.to_string()));
+                };
+                (&rest[..open], Some((offset, operands)))
+            }
+            None => (rest, None),
+        };
+
+        let (offset, mnemonic) = token(head, rest_offset);
+        if mnemonic == "NOP" && dst.is_none() && operands.is_none() {
+            return Ok(Instruction::NOP);
+        }
+        let opcode = Opcode::from_mnemonic(mnemonic)
+            .ok_or_else(|| ParseError::UnknownOpcode(offset, mnemonic.to_string()))?;
+
+        let mut value = Self::opcode_encode(opcode) | dst.unwrap_or(0) << 12;
+        let mut immediate = false;
+
+        if let Some((mut offset, operands)) = operands {
+            for (index, raw) in operands.split(',').enumerate() {
+                let (operand_offset, operand) = token(raw, offset);
+                offset += raw.len() + 1;
+
+                if operand.is_empty() {
+                    if index == 0 && operands.trim().is_empty() {
+                        break; // no operands
+                    }
+                    continue; // operand skipped, e.g. `UJMP( , tmp0)`
+                }
+                if index > 1 {
+                    return Err(ParseError::UnexpectedToken(
+                        operand_offset,
+                        operand.to_string(),
+                    ));
+                }
+
+                if operand.starts_with(|c: char| c.is_ascii_digit()) {
+                    let number = match operand.strip_prefix("0x") {
+                        Some(hex) => u64::from_str_radix(hex, 16),
+                        None => operand.parse::<u64>(),
+                    };
+                    let number = match number {
+                        Ok(number) if number <= 0xffff && !immediate => number,
+                        _ => {
+                            return Err(ParseError::InvalidImmediate(
+                                operand_offset,
+                                operand.to_string(),
+                            ))
+                        }
+                    };
+                    immediate = true;
+                    value |= if index == 0 {
+                        Self::immediate_src0_encode(number)
+                    } else {
+                        Self::immediate_src1_encode(number)
+                    };
+                } else {
+                    let register = register_selector(operand).ok_or_else(|| {
+                        ParseError::UnknownRegister(operand_offset, operand.to_string())
+                    })?;
+                    value |= register << (6 * index);
+                }
+            }
+        }
+
+        Ok(Instruction::disassemble(value))
+    }
+}
+
+/// Errors that can occur while disassembling an instruction with parity validation
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum InstructionDisassembleError {
+    /// Bits above the 48-bit encoding are set
+    InvalidLength(u64),
+    /// The parity bits do not match the instruction
+    ParityMismatch {
+        /// The parity calculated from the instruction
+        expected: u64,
+        /// The parity stored in the encoded instruction
+        found: u64,
+    },
+}
+
+/// Bits holding the immediate value outside of the operand selectors, including the macro flag
+const IMMEDIATE_MASK: u64 = (0xff << 24) | (1 << 23) | (0x1f << 18);
+
+/// Result type for re-encoding instructions
+pub type EncodeResult<T> = Result<T, EncodeError>;
+
+/// Errors that can occur while re-encoding an instruction
+///
+/// Field related errors contain the name of the field, e.g. `"src0"` or `"immediate"`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum EncodeError {
+    /// The opcode is not known, so its operand encoding is not known either
+    UnknownOpcode(u16),
+    /// The selector is not a register selector
+    InvalidRegister(&'static str, u8),
+    /// The value does not fit into the field
+    ValueOutOfRange(&'static str, u64),
+    /// The field is not available for this opcode
+    UnsupportedField(&'static str),
+}
+
+/// An operand of a microcode instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operand {
+    /// The operand is not used
+    None,
+    /// A register, identified by its 6-bit operand selector, e.g. `0x30` for `tmp0`
+    Register(u8),
+    /// An immediate value of at most 16 bits
+    Immediate(u16),
+    /// An immediate value provided by the decoded macro instruction, e.g. a displacement
+    MacroImmediate(u8),
+    /// The operand encoding of the opcode is not known
+    Unknown,
+}
+
+impl Operand {
+    /// Gets the index of a temporary register
+    ///
+    /// # Returns
+    ///
+    /// Returns the index `n` if the operand is the temporary register `tmpn`
+    pub fn temporary(&self) -> Option<u8> {
+        match self {
+            Operand::Register(register) if (0x30..0x40).contains(register) => Some(register - 0x30),
+            _ => None,
+        }
+    }
+}
+
+/// The structure of an instruction, i.e. opcode and operands without the immediate value
+///
+/// See [`Instruction::structural_key`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd)]
+pub struct StructuralKey(u64);
+
+impl StructuralKey {
+    /// Gets the opcode of the instruction
+    pub fn opcode(&self) -> Opcode {
+        Instruction::disassemble(self.0).opcode()
+    }
+}
+
+/// Errors that can occur while parsing an instruction
+///
+/// Each error contains the byte offset of the offending token in the parsed text and the token itself.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ParseError {
+    /// The opcode mnemonic is not known
+    UnknownOpcode(usize, String),
+    /// The register name is not known
+    UnknownRegister(usize, String),
+    /// The immediate value is malformed, larger than 16 bits or not the only immediate
+    InvalidImmediate(usize, String),
+    /// The token was not expected at this position
+    UnexpectedToken(usize, String),
+}
+
+/// Trims a token
+///
+/// # Arguments
+///
+/// * `text` - The untrimmed token
+/// * `offset` - Offset of the untrimmed token in the parsed text
+///
+/// # Returns
+///
+/// Returns the offset of the trimmed token in the parsed text and the trimmed token
+fn token(text: &str, offset: usize) -> (usize, &str) {
+    let trimmed = text.trim_start();
+    (offset + text.len() - trimmed.len(), trimmed.trim_end())
+}
+
+/// General purpose registers in the order of their selector values
+const GENERAL_PURPOSE_REGISTERS: [&str; 16] = [
+    "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15",
+];
+
+/// Gets the operand selector value of a register
+///
+/// # Arguments
+///
+/// * `name` - The register name
+///
+/// # Returns
+///
+/// Returns the 6-bit selector value if the register is known
+fn register_selector(name: &str) -> Option<u64> {
+    let numbered = |prefix: &str, count: u64| {
+        name.strip_prefix(prefix)
+            .filter(|index| !index.starts_with('0') || *index == "0")
+            .and_then(|index| index.parse::<u64>().ok())
+            .filter(|index| *index < count)
+    };
+
+    match name {
+        "r64dst" => Some(0x01),
+        "r64src" => Some(0x02),
+        "r64base" => Some(0x04),
+        "r64idx" => Some(0x05),
+        _ => {
+            if let Some(index) = numbered("tmpv", 3) {
+                Some(0x14 + index)
+            } else if let Some(index) = numbered("tmp", 16) {
+                Some(0x30 + index)
+            } else {
+                GENERAL_PURPOSE_REGISTERS
+                    .iter()
+                    .position(|register| *register == name)
+                    .map(|index| 0x20 + index as u64)
+            }
+        }
+    }
 }
 
 impl Display for Instruction {
@@ -152,4 +799,316 @@ impl From<u64> for Instruction {
 
 // todo: add further disassembly/assembly methods
 
-// todo: add tests
+#[cfg(test)]
+mod test {
+    use crate::instruction::{
+        EncodeError, Instruction, InstructionDisassembleError, Operand, ParseError,
+    };
+    use crate::opcodes::Opcode;
+    use alloc::string::{String, ToString};
+    use alloc::vec;
+    use num_traits::FromPrimitive;
+
+    #[test]
+    fn test_parse_display_round_trip() {
+        for value in 0..0x1000 {
+            let instruction = Instruction::from_opcode(Opcode::from_u16(value).unwrap());
+            assert_eq!(
+                Instruction::parse(&instruction.to_string()),
+                Ok(instruction),
+                "opcode {:03x}",
+                value
+            );
+        }
+        assert_eq!(Instruction::parse("NOP"), Ok(Instruction::NOP));
+    }
+
+    #[test]
+    fn test_parse_operands() {
+        // encodings taken from the ROM dump of cpu_000506CA
+        assert_eq!(
+            Instruction::parse("tmp0:= ZEROEXT_DSZ32(0x00000001)"),
+            Ok(Instruction::disassemble(0x000801030008))
+        );
+        assert_eq!(
+            Instruction::parse("tmp0:= RCXBTCNTMSK_DSZ8(rcx)"),
+            Ok(Instruction::disassemble(0x01e200030021))
+        );
+        assert_eq!(
+            Instruction::parse("UJMP( , tmp11)"),
+            Ok(Instruction::disassemble(0x015d00000ec0))
+        );
+        assert_eq!(
+            Instruction::parse(" tmp13 :=ZEROEXT_DSZ64( tmp7 ) "),
+            Ok(Instruction::disassemble(0x00480003d037))
+        );
+        assert_eq!(
+            Instruction::parse("UJMP(, 0x7c00)"),
+            Ok(Instruction::UJMP(0x7c00))
+        );
+        assert_eq!(
+            Instruction::parse("tmp3:= ADD_DSZ64(0xabcd, rsp)"),
+            Ok(Instruction::disassemble(
+                0x040 << 32 | 0xcd << 24 | 0x0b << 18 | 0x33 << 12 | 0x24 << 6 | 0x8 | 0x5
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        fn error(kind: fn(usize, String) -> ParseError, offset: usize, token: &str) -> ParseError {
+            kind(offset, token.to_string())
+        }
+
+        assert_eq!(
+            Instruction::parse("tmp0:= ADD_DSZ99(tmp1)"),
+            Err(error(ParseError::UnknownOpcode, 7, "ADD_DSZ99"))
+        );
+        assert_eq!(
+            Instruction::parse("tmp0:= ADD_DSZ64(tmp1, tmp16)"),
+            Err(error(ParseError::UnknownRegister, 23, "tmp16"))
+        );
+        assert_eq!(
+            Instruction::parse("tmp00:= ADD_DSZ64(tmp1)"),
+            Err(error(ParseError::UnknownRegister, 0, "tmp00"))
+        );
+        assert_eq!(
+            Instruction::parse("tmp0:= ADD_DSZ64(tmp1, 0x10000)"),
+            Err(error(ParseError::InvalidImmediate, 23, "0x10000"))
+        );
+        assert_eq!(
+            Instruction::parse("tmp0:= ADD_DSZ64(0x1, 0x2)"),
+            Err(error(ParseError::InvalidImmediate, 22, "0x2"))
+        );
+        assert_eq!(
+            Instruction::parse("tmp0:= ADD_DSZ64(tmp1, tmp2, tmp3)"),
+            Err(error(ParseError::UnexpectedToken, 29, "tmp3"))
+        );
+        assert_eq!(
+            Instruction::parse("ADD_DSZ64(tmp1"),
+            Err(error(ParseError::UnexpectedToken, 10, "tmp1"))
+        );
+    }
+
+    #[test]
+    fn test_operand_accessors() {
+        // encodings taken from the ROM dump of cpu_000506CA
+        let zeroext = Instruction::disassemble(0x000801030008); // tmp0:= ZEROEXT_DSZ32(0x00000001)
+        assert_eq!(zeroext.src0(), Operand::Immediate(1));
+        assert_eq!(zeroext.src1(), Operand::None);
+        assert_eq!(zeroext.dst(), Operand::Register(0x30));
+        assert_eq!(zeroext.dst().temporary(), Some(0));
+        assert_eq!(zeroext.immediate(), Operand::Immediate(1));
+        assert_eq!(zeroext.registers_read(), Some(vec![]));
+        assert_eq!(zeroext.register_written(), Some(0x30));
+
+        let sub = Instruction::disassemble(0x100543831230); // tmp1:= SUB_DSZN(tmp0, IMM_MACRO_43)
+        assert_eq!(sub.src0(), Operand::Register(0x30));
+        assert_eq!(sub.src1(), Operand::MacroImmediate(0x43));
+        assert_eq!(sub.dst(), Operand::Register(0x31));
+        assert_eq!(sub.immediate(), Operand::MacroImmediate(0x43));
+
+        // UJMPCC_DIRECT_NOTTAKEN_CONDNZ(tmp1, U0414)
+        let jump = Instruction::disassemble(0x015114100231);
+        assert_eq!(jump.src0(), Operand::Register(0x31));
+        assert_eq!(jump.immediate(), Operand::Immediate(0x414));
+        assert_eq!(jump.register_written(), None);
+
+        // BTUJNB_DIRECT_NOTTAKEN(tmp0, 0x00000006, U5f74), the destination field is implicit
+        let jump = Instruction::disassemble(0x286b74bd0270);
+        assert_eq!(jump.src0(), Operand::Register(0x30));
+        assert_eq!(jump.dst(), Operand::None);
+        assert_eq!(jump.registers_read(), Some(vec![0x30]));
+
+        // PORTOUT_DSZ32_ASZ16_SC1(0x00c4,  , tmp5), the destination field holds the data
+        let store = Instruction::disassemble(0x2d0fc4035008);
+        assert_eq!(store.immediate(), Operand::Immediate(0xc4));
+        assert_eq!(store.dst(), Operand::None);
+        assert_eq!(store.registers_read(), Some(vec![0x35]));
+
+        let split = Instruction::parse("tmp15:= ADD_DSZ32(rax, 0xfedc)").unwrap();
+        assert_eq!(split.src0(), Operand::Register(0x20));
+        assert_eq!(split.src1(), Operand::Immediate(0xfedc));
+        assert_eq!(split.src0().temporary(), None);
+        assert_eq!(split.dst().temporary(), Some(15));
+
+        let unknown = Instruction::disassemble(0x0d6808030e74); // unk_d68(tmp4, tmp9, tmp0)
+        assert!(unknown.opcode().is_group_UNKNOWN());
+        assert_eq!(unknown.src0(), Operand::Unknown);
+        assert_eq!(unknown.src1(), Operand::Unknown);
+        assert_eq!(unknown.dst(), Operand::Unknown);
+        assert_eq!(unknown.immediate(), Operand::Unknown);
+        assert_eq!(unknown.registers_read(), None);
+        assert_eq!(unknown.register_written(), None);
+
+        assert_eq!(Instruction::NOP.src0(), Operand::None);
+        assert_eq!(Instruction::NOP.immediate(), Operand::None);
+    }
+
+    #[test]
+    fn test_structural_key() {
+        let parse = |text: &str| Instruction::parse(text).unwrap();
+
+        let small = parse("tmp0:= ZEROEXT_DSZ32(0x0001)");
+        let large = parse("tmp0:= ZEROEXT_DSZ32(0xe123)");
+        assert_ne!(small, large);
+        assert_eq!(small.structural_key(), large.structural_key());
+        assert!(small.structural_eq(&large));
+        assert_eq!(small.structural_key().opcode(), Opcode::ZEROEXT_DSZ32);
+
+        let add = parse("tmp12:= ADD_DSZ32(0x0080, tmp12)");
+        assert!(add.structural_eq(&parse("tmp12:= ADD_DSZ32(0x0004, tmp12)")));
+
+        for different in [
+            "tmp0:= ZEROEXT_DSZ64(0x0001)",
+            "tmp0:= ZEROEXT_DSZ32(tmp1)",
+            "tmp1:= ZEROEXT_DSZ32(0x0001)",
+            "tmp0:= ZEROEXT_DSZ32()",
+        ] {
+            assert!(!small.structural_eq(&parse(different)), "{different}");
+        }
+        assert!(!add.structural_eq(&parse("tmp12:= ADD_DSZ32(tmp1, tmp12)")));
+
+        // without known operand encoding the whole instruction is kept
+        let unknown = Instruction::disassemble(0x0e7b0f000cb0);
+        assert!(unknown.opcode().is_group_UNKNOWN());
+        assert!(!unknown.structural_eq(&Instruction::disassemble(0x0e7b0e000cb0)));
+    }
+
+    #[test]
+    fn test_mutating_setters() {
+        let parse = |text: &str| Instruction::parse(text).unwrap();
+        let sub = parse("tmp1:= SUB_DSZ32(tmp0, 0x0010)");
+
+        let mutated = [
+            (sub.with_src1(0x35), "tmp1:= SUB_DSZ32(tmp0, tmp5)"),
+            (sub.with_src0(0x20), "tmp1:= SUB_DSZ32(rax, 0x0010)"),
+            (sub.with_dst(0x3f), "tmp15:= SUB_DSZ32(tmp0, 0x0010)"),
+            (sub.with_dst(0), "SUB_DSZ32(tmp0, 0x0010)"),
+            (sub.with_immediate(0xfedc), "tmp1:= SUB_DSZ32(tmp0, 0xfedc)"),
+            (
+                sub.with_opcode(Opcode::ADD_DSZ32),
+                "tmp1:= ADD_DSZ32(tmp0, 0x0010)",
+            ),
+            (
+                parse("tmp0:= ZEROEXT_DSZ32(0x0001)").with_immediate(0xffff),
+                "tmp0:= ZEROEXT_DSZ32(0xffff)",
+            ),
+            (
+                parse("tmp0:= ZEROEXT_DSZ32( , tmp1)").with_immediate(7),
+                "tmp0:= ZEROEXT_DSZ32(0x0007, tmp1)",
+            ),
+            (
+                parse("tmp0:= ZEROEXT_DSZ32(tmp1)").with_immediate(7),
+                "tmp0:= ZEROEXT_DSZ32(tmp1, 0x0007)",
+            ),
+        ];
+        for (instruction, expected) in mutated {
+            let instruction = instruction.unwrap();
+            assert_eq!(instruction, parse(expected), "{}", expected);
+            assert_eq!(
+                Instruction::disassemble(instruction.assemble()),
+                instruction
+            );
+        }
+
+        assert_eq!(
+            sub.with_immediate(0x10000),
+            Err(EncodeError::ValueOutOfRange("immediate", 0x10000))
+        );
+        assert_eq!(
+            parse("tmp1:= SUB_DSZ32(tmp0, tmp2)").with_immediate(1),
+            Err(EncodeError::UnsupportedField("immediate"))
+        );
+        assert_eq!(
+            sub.with_src0(0x08),
+            Err(EncodeError::InvalidRegister("src0", 0x08))
+        );
+        assert_eq!(
+            sub.with_src1(0x40),
+            Err(EncodeError::InvalidRegister("src1", 0x40))
+        );
+        assert_eq!(
+            sub.with_dst(0x0f),
+            Err(EncodeError::InvalidRegister("dst", 0x0f))
+        );
+
+        // encodings taken from the ROM dump of cpu_000506CA
+        let jump = Instruction::disassemble(0x015114100231); // UJMPCC_DIRECT_NOTTAKEN_CONDNZ(tmp1, U0414)
+        assert_eq!(
+            jump.with_dst(0x30),
+            Err(EncodeError::UnsupportedField("dst"))
+        );
+        assert_eq!(
+            jump.with_immediate(0x0420).unwrap().immediate(),
+            Operand::Immediate(0x0420)
+        );
+
+        // tmp4:= LDZX_DSZN_ASZ32_SC1(r64base, DS, r64idx, IMM_MACRO_ALIAS_DISPLACEMENT, mode=0x18)
+        let load = Instruction::disassemble(0x1c1000e34144);
+        assert_eq!(
+            load.with_immediate(1),
+            Err(EncodeError::UnsupportedField("immediate"))
+        );
+        assert_eq!(
+            load.with_dst(0x35).unwrap().assemble() & 0xfff,
+            load.assemble() & 0xfff
+        );
+
+        let unknown = Instruction::disassemble(0x0d6808030e74); // unk_d68(tmp4, tmp9, tmp0)
+        assert_eq!(
+            unknown.with_src0(0x30),
+            Err(EncodeError::UnknownOpcode(0xd68))
+        );
+        assert_eq!(
+            sub.with_opcode(unknown.opcode()),
+            Err(EncodeError::UnknownOpcode(0xd68))
+        );
+    }
+
+    #[test]
+    fn test_disassemble_checked() {
+        // encodings taken from the ROM dump of cpu_000506CA, stored without parity
+        for rom in [
+            0x000801030008,
+            0x100543831230,
+            0x286b74bd0270,
+            0x0d6808030e74,
+        ] {
+            let instruction = Instruction::disassemble(rom);
+            assert_eq!(instruction.raw_bits(), rom);
+
+            let msram = instruction.assemble();
+            assert_eq!(Instruction::disassemble_checked(msram), Ok(instruction));
+
+            let parity = msram >> 46;
+            let corrupted = [
+                // instruction bits covered by the even and the odd parity bit
+                (msram ^ (1 << 0), parity ^ 0b01, parity),
+                (msram ^ (1 << 33), parity ^ 0b10, parity),
+                // the parity bits themselves
+                (msram ^ (1 << 46), parity, parity ^ 0b01),
+                (msram ^ (1 << 47), parity, parity ^ 0b10),
+            ];
+            for (value, expected, found) in corrupted {
+                assert_eq!(
+                    Instruction::disassemble_checked(value),
+                    Err(InstructionDisassembleError::ParityMismatch { expected, found }),
+                    "{:012x}",
+                    value
+                );
+            }
+
+            assert_eq!(
+                Instruction::disassemble_checked(msram | 1 << 48),
+                Err(InstructionDisassembleError::InvalidLength(msram | 1 << 48))
+            );
+        }
+
+        // the reserved bits 44-45 are not part of the opcode but survive a round trip
+        let reserved = Instruction::disassemble(0x286b74bd0270);
+        assert_eq!(reserved.opcode(), Opcode::BTUJNB_DIRECT_NOTTAKEN);
+        assert_eq!(reserved.raw_bits() >> 44, 0b10);
+        assert_eq!(Instruction::disassemble(reserved.raw_bits()), reserved);
+    }
+}
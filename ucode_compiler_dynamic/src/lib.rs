@@ -9,7 +9,9 @@
 //!
 //! The main components are:
 //! - [`instruction`]: Module for handling individual microcode instructions
+//! - [`listing`]: Module for formatting triads as disassembly listing
 //! - [`opcodes`]: Module containing all available microcode opcodes
+//! - [`program`]: Module for assembling triads with labels to a base address
 //! - [`sequence_word`]: Module for handling sequence words
 //! - [`Triad`]: A group of three instructions with an associated sequence word
 #![no_std]
@@ -18,12 +20,23 @@ extern crate alloc;
 extern crate core;
 
 use crate::instruction::Instruction;
-use crate::sequence_word::{DisassembleError, SequenceWord};
+use crate::opcodes::Opcode;
+use crate::sequence_word::{AssembleError, DisassembleError, DisassembleResult, SequenceWord};
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
 use core::fmt;
 use core::fmt::{Display, Formatter};
+use core::str::FromStr;
+use data_types::addresses::UCInstructionAddress;
+use num_traits::FromPrimitive;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 pub mod instruction;
+pub mod listing;
 pub mod opcodes;
+pub mod program;
 pub mod sequence_word;
 
 impl Display for opcodes::Opcode {
@@ -32,10 +45,44 @@ impl Display for opcodes::Opcode {
     }
 }
 
+/// Error returned when parsing an opcode from an unknown mnemonic
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UnknownMnemonicError(pub String);
+
+impl FromStr for opcodes::Opcode {
+    type Err = UnknownMnemonicError;
+
+    /// Parses an opcode from its mnemonic, as printed by the `Display` implementation
+    ///
+    /// The mnemonic is matched case-insensitively, e.g. `add_dsz32` or `unknown_1A3`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mnemonic = s.trim().to_ascii_uppercase();
+        Opcode::from_mnemonic(&mnemonic)
+            .or_else(|| {
+                // unknown opcodes are named by their lowercase hex value
+                mnemonic
+                    .strip_prefix("UNKNOWN_")
+                    .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+                    .and_then(Opcode::from_u16)
+                    .filter(|opcode| opcode.is_group_UNKNOWN())
+            })
+            .ok_or_else(|| UnknownMnemonicError(s.to_string()))
+    }
+}
+
+impl opcodes::Opcode {
+    /// Iterates over all opcodes, including the unknown ones, ordered by their value
+    pub fn all() -> impl Iterator<Item = Opcode> {
+        (0..1u16 << 13).filter_map(Opcode::from_u16)
+    }
+}
+
 /// A group of three microcode instructions with an associated sequence word
 ///
 /// A triad is the basic unit of microcode execution, containing three instructions.
+/// With the `serde` feature, it is serialized as its instructions and sequence word.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Triad {
     /// The three instructions in this triad
     pub instructions: [Instruction; 3],
@@ -59,6 +106,61 @@ impl Triad {
             self.sequence_word.assemble()? as u64,
         ])
     }
+
+    /// Disassembles a triad from its binary representation, checking all CRCs
+    ///
+    /// This is the inverse of [`Triad::assemble`].
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - The three assembled instructions followed by the assembled sequence word
+    ///
+    /// # Returns
+    ///
+    /// Returns a Result containing the disassembled triad if successful. Instruction errors
+    /// report the failing slot, all other errors concern the sequence word in slot 3.
+    pub fn disassemble(values: [u64; 4]) -> DisassembleResult<Triad> {
+        for (slot, value) in values[..3].iter().enumerate() {
+            if Instruction::disassemble_checked(*value).is_err() {
+                return Err(DisassembleError::InvalidInstructionCRC(slot as u8, *value));
+            }
+        }
+
+        Ok(Triad {
+            instructions: Self::disassemble_instructions(values),
+            sequence_word: SequenceWord::disassemble_checked(Self::sequence_word_slot(values)?)?,
+        })
+    }
+
+    /// Disassembles a triad from its binary representation without checking the CRCs
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - The three instructions followed by the sequence word, e.g. read from the MSROM
+    ///
+    /// # Returns
+    ///
+    /// Returns a Result containing the disassembled triad if the sequence word is valid
+    pub fn disassemble_no_crc_check(values: [u64; 4]) -> DisassembleResult<Triad> {
+        Ok(Triad {
+            instructions: Self::disassemble_instructions(values),
+            sequence_word: SequenceWord::disassemble_no_crc_check(Self::sequence_word_slot(
+                values,
+            )?)?,
+        })
+    }
+
+    fn disassemble_instructions(values: [u64; 4]) -> [Instruction; 3] {
+        [
+            Instruction::disassemble(values[0]),
+            Instruction::disassemble(values[1]),
+            Instruction::disassemble(values[2]),
+        ]
+    }
+
+    fn sequence_word_slot(values: [u64; 4]) -> DisassembleResult<u32> {
+        u32::try_from(values[3]).map_err(|_| DisassembleError::InvalidLength(values[3] as u32))
+    }
 }
 
 impl TryFrom<data_types::patch::Triad> for Triad {
@@ -66,6 +168,8 @@ impl TryFrom<data_types::patch::Triad> for Triad {
 
     /// Attempts to create a Triad from a patch triad
     ///
+    /// The CRCs are not checked, use [`Triad::disassemble`] for strict checking.
+    ///
     /// # Arguments
     ///
     /// * `value` - The patch triad to convert
@@ -74,13 +178,36 @@ impl TryFrom<data_types::patch::Triad> for Triad {
     ///
     /// Returns a Result containing the converted Triad if successful
     fn try_from(value: data_types::patch::Triad) -> Result<Self, Self::Error> {
-        Ok(Triad {
-            instructions: [
-                Instruction::disassemble(value.instructions[0]),
-                Instruction::disassemble(value.instructions[1]),
-                Instruction::disassemble(value.instructions[2]),
-            ],
-            sequence_word: SequenceWord::disassemble_no_crc_check(value.sequence_word)?,
+        Triad::disassemble_no_crc_check([
+            value.instructions[0],
+            value.instructions[1],
+            value.instructions[2],
+            value.sequence_word as u64,
+        ])
+    }
+}
+
+impl TryFrom<&Triad> for data_types::patch::Triad {
+    type Error = AssembleError;
+
+    /// Attempts to assemble a Triad into a patch triad
+    ///
+    /// The CRCs are recomputed, see [`Triad::assemble`]. Converting back with
+    /// [`Triad::try_from`] restores the triad, but a patch triad with invalid CRCs does not
+    /// survive the round trip.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The triad to assemble
+    ///
+    /// # Returns
+    ///
+    /// Returns a Result containing the patch triad if the sequence word could be assembled
+    fn try_from(value: &Triad) -> Result<Self, Self::Error> {
+        let [uop0, uop1, uop2, sequence_word] = value.assemble()?;
+        Ok(data_types::patch::Triad {
+            instructions: [uop0, uop1, uop2],
+            sequence_word: sequence_word as u32,
         })
     }
 }
@@ -95,6 +222,39 @@ impl Display for Triad {
     }
 }
 
+/// Even-odd parity calculation over integer widths
+///
+/// The parity is calculated by XOR-ing all consecutive 2-bit groups of the value,
+/// the result is a 2-bit value (bit 0: parity of even bits, bit 1: parity of odd bits).
+pub trait EvenOddParity: Sized {
+    /// Calculates the even-odd parity of this value
+    ///
+    /// # Returns
+    ///
+    /// Returns the calculated parity value
+    fn even_odd_parity(self) -> Self;
+}
+
+macro_rules! impl_even_odd_parity {
+    ($($t:ty),*) => {
+        $(
+            impl EvenOddParity for $t {
+                fn even_odd_parity(self) -> Self {
+                    let mut value = self;
+                    let mut result = 0;
+                    while value > 0 {
+                        result ^= value & 3;
+                        value >>= 2;
+                    }
+                    result
+                }
+            }
+        )*
+    };
+}
+
+impl_even_odd_parity!(u32, u64);
+
 /// Calculates even-odd parity for a 32-bit value
 ///
 /// # Arguments
@@ -104,13 +264,8 @@ impl Display for Triad {
 /// # Returns
 ///
 /// Returns the calculated parity value
-pub fn even_odd_parity_u32(mut value: u32) -> u32 {
-    let mut result = 0;
-    while value > 0 {
-        result ^= value & 3;
-        value >>= 2;
-    }
-    result
+pub fn even_odd_parity_u32(value: u32) -> u32 {
+    value.even_odd_parity()
 }
 
 /// Calculates even-odd parity for a 64-bit value
@@ -122,16 +277,10 @@ pub fn even_odd_parity_u32(mut value: u32) -> u32 {
 /// # Returns
 ///
 /// Returns the calculated parity value
-pub fn even_odd_parity_u64(mut value: u64) -> u64 {
-    let mut result = 0;
-    while value > 0 {
-        result ^= value & 3;
-        value >>= 2;
-    }
-    result
+pub fn even_odd_parity_u64(value: u64) -> u64 {
+    value.even_odd_parity()
 }
 
-/*
 /// Mode for executing the next instruction
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ControlFlowNextMode {
@@ -169,8 +318,8 @@ pub enum ControlFlow {
         prediction: ControlFlowBranchPrediction,
         /// Target address if branch is taken (if immediate)
         taken: Option<UCInstructionAddress>,
-        /// Target address if branch is not taken
-        not_taken: UCInstructionAddress,
+        /// Target address if branch is not taken (if not ending or returning)
+        not_taken: Option<UCInstructionAddress>,
     },
 
     /// Unconditional branch instruction
@@ -185,14 +334,498 @@ pub enum ControlFlow {
     Unknown,
 }
 
+impl ControlFlow {
+    /// Gets the statically known successors
+    ///
+    /// # Returns
+    ///
+    /// Returns the addresses that may be executed next, dynamic targets (e.g. indirect jumps,
+    /// returns or the end of microcode execution) are not included
+    pub fn successors(&self) -> Vec<UCInstructionAddress> {
+        match self {
+            ControlFlow::Next { target, .. } => vec![*target],
+            ControlFlow::ConditionalBranch {
+                taken, not_taken, ..
+            } => taken.iter().chain(not_taken.iter()).copied().collect(),
+            ControlFlow::UnconditionalBranch { next, .. } => next.iter().copied().collect(),
+            ControlFlow::Unknown => Vec::new(),
+        }
+    }
+}
+
 impl Opcode {
     /// Determines the control flow behavior of this opcode
     ///
+    /// Only the opcode is considered, branch targets given as operands and the sequence word
+    /// are unknown, see [`Instruction::control_flow`] for the full picture.
+    ///
+    /// # Arguments
+    ///
+    /// * `next` - The address following the instruction
+    ///
     /// # Returns
     ///
     /// Returns the control flow information for this opcode
-    pub fn control_flow(&self) -> ControlFlow {
-        todo!() // todo, also see "Analyzing and Exploiting Branch Mispredictions in Microcode"
+    pub fn control_flow(&self, next: UCInstructionAddress) -> ControlFlow {
+        let prediction = if self.is_predicted_taken() {
+            ControlFlowBranchPrediction::Taken
+        } else {
+            ControlFlowBranchPrediction::NotTaken
+        };
+
+        match self {
+            Opcode::UJMP | Opcode::URET => ControlFlow::UnconditionalBranch {
+                prediction: ControlFlowBranchPrediction::NotPredicted,
+                next: None,
+            },
+            Opcode::TESTUSTATE | Opcode::TESTUSTATE_2 => ControlFlow::Next {
+                mode: ControlFlowNextMode::Direct,
+                target: next,
+            },
+            _ if self.is_conditional_jump() => ControlFlow::ConditionalBranch {
+                prediction,
+                taken: None,
+                not_taken: Some(next),
+            },
+            _ if self.is_group_UNKNOWN() || self.is_group_MCALL() || self.is_group_UCALLPARAM() => {
+                ControlFlow::Unknown
+            }
+            _ => ControlFlow::Next {
+                mode: ControlFlowNextMode::Direct,
+                target: next,
+            },
+        }
+    }
+}
+
+impl Instruction {
+    /// Determines the control flow behavior of this instruction
+    ///
+    /// Immediate branch targets are decoded and the sequence word of the triad is applied,
+    /// i.e. `SEQW GOTO`, `SEQW UEND` and `SEQW URET` as well as conditional `TESTUSTATE` gotos.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address of this instruction
+    /// * `sequence_word` - The sequence word of the triad containing this instruction
+    ///
+    /// # Returns
+    ///
+    /// Returns the control flow information for this instruction
+    pub fn control_flow(
+        &self,
+        address: UCInstructionAddress,
+        sequence_word: &SequenceWord,
+    ) -> ControlFlow {
+        let index = address.triad_offset();
+        let goto = sequence_word
+            .goto()
+            .as_ref()
+            .filter(|goto| goto.apply_to_index == index)
+            .map(|goto| goto.value);
+        let terminator = sequence_word
+            .control()
+            .as_ref()
+            .filter(|control| control.apply_to_index == index)
+            .is_some_and(|control| control.value.is_terminator());
+
+        // fall through path with respect to the sequence word
+        let fall_through = match goto {
+            Some(target) => ControlFlow::Next {
+                mode: ControlFlowNextMode::Indirect,
+                target,
+            },
+            None if terminator => ControlFlow::UnconditionalBranch {
+                prediction: ControlFlowBranchPrediction::NotPredicted,
+                next: None,
+            },
+            None => ControlFlow::Next {
+                mode: ControlFlowNextMode::Direct,
+                target: address.next_address(),
+            },
+        };
+
+        let opcode = self.opcode();
+        match opcode.control_flow(address.next_address()) {
+            ControlFlow::UnconditionalBranch { prediction, .. } => {
+                let next = if opcode == Opcode::UJMP {
+                    self.immediate_src1().map(UCInstructionAddress::from_const)
+                } else {
+                    None
+                };
+                ControlFlow::UnconditionalBranch { prediction, next }
+            }
+            ControlFlow::ConditionalBranch { prediction, .. } => {
+                let taken = if opcode.is_group_UJMPCC_DIRECT() {
+                    self.immediate_src1().map(UCInstructionAddress::from_const)
+                } else {
+                    None
+                };
+                ControlFlow::ConditionalBranch {
+                    prediction,
+                    taken,
+                    not_taken: fall_through.successors().first().copied(),
+                }
+            }
+            ControlFlow::Next { .. }
+                if (opcode == Opcode::TESTUSTATE || opcode == Opcode::TESTUSTATE_2)
+                    && (goto.is_some() || terminator) =>
+            {
+                // the sequence word is only applied if the tested condition holds
+                ControlFlow::ConditionalBranch {
+                    prediction: ControlFlowBranchPrediction::NotPredicted,
+                    taken: goto,
+                    not_taken: Some(address.next_address()),
+                }
+            }
+            ControlFlow::Next { .. } => fall_through,
+            ControlFlow::Unknown => ControlFlow::Unknown,
+        }
+    }
+}
+
+impl Triad {
+    /// Determines the control flow behavior of an instruction of this triad
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address of the instruction, the triad offset selects the instruction
+    ///
+    /// # Returns
+    ///
+    /// Returns the control flow information for this instruction, [`ControlFlow::Unknown`]
+    /// for the unused fourth triad offset
+    pub fn control_flow(&self, address: UCInstructionAddress) -> ControlFlow {
+        match self.instructions.get(address.triad_offset() as usize) {
+            Some(instruction) => instruction.control_flow(address, &self.sequence_word),
+            None => ControlFlow::Unknown,
+        }
+    }
+
+    /// Finds the registers passed between the instructions of this triad
+    ///
+    /// # Returns
+    ///
+    /// Returns all read-after-write dependencies in execution order, each read paired with
+    /// the last preceding write of the register. Returns `None` if an opcode is not known
+    pub fn register_dependencies(&self) -> Option<Vec<RegisterDependency>> {
+        let mut result = Vec::new();
+        for (usage, instruction) in self.instructions.iter().enumerate() {
+            for register in instruction.registers_read()? {
+                let definition = self.instructions[..usage]
+                    .iter()
+                    .rposition(|previous| previous.register_written() == Some(register));
+                if let Some(definition) = definition {
+                    result.push(RegisterDependency {
+                        definition,
+                        usage,
+                        register,
+                    });
+                }
+            }
+        }
+        Some(result)
+    }
+}
+
+/// A register written by one instruction of a triad and read by a later one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RegisterDependency {
+    /// Index of the instruction writing the register
+    pub definition: usize,
+    /// Index of the instruction reading the register
+    pub usage: usize,
+    /// Operand selector of the register
+    pub register: u8,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::opcodes::Opcode;
+    use crate::{even_odd_parity_u32, even_odd_parity_u64, EvenOddParity, UnknownMnemonicError};
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+    use num_traits::FromPrimitive;
+
+    #[test]
+    fn test_opcode_from_str() {
+        for opcode in Opcode::all() {
+            let text = opcode.to_string();
+            assert_eq!(text.parse::<Opcode>(), Ok(opcode));
+            assert_eq!(text.to_lowercase().parse::<Opcode>(), Ok(opcode));
+        }
+        // one opcode per line of opcodes.txt
+        let known = Opcode::all()
+            .filter(|opcode| !opcode.is_group_UNKNOWN())
+            .collect::<Vec<_>>();
+        assert_eq!(known.len(), 645);
+        for opcode in [Opcode::ADD_DSZ32, Opcode::OR_DSZ32, Opcode::XOR_DSZ32] {
+            assert!(known.contains(&opcode));
+        }
+        assert!(Opcode::all().any(|opcode| opcode.is_group_UNKNOWN()));
+
+        assert_eq!(" Add_Dsz32 ".parse::<Opcode>(), Ok(Opcode::ADD_DSZ32));
+        assert_eq!(
+            "ADD_DSZ33".parse::<Opcode>(),
+            Err(UnknownMnemonicError("ADD_DSZ33".to_string()))
+        );
+        let known = Opcode::ADD_DSZ32 as u16;
+        assert!(alloc::format!("UNKNOWN_{known:x}")
+            .parse::<Opcode>()
+            .is_err());
+    }
+
+    fn reference_parity(mut value: u64) -> u64 {
+        let mut result = 0;
+        for _ in 0..32 {
+            result ^= value & 3;
+            value >>= 2;
+        }
+        result
+    }
+
+    #[test]
+    fn test_even_odd_parity() {
+        let mut value: u64 = 0x0123_4567_89ab_cdef;
+        for _ in 0..10000 {
+            // xorshift
+            value ^= value << 13;
+            value ^= value >> 7;
+            value ^= value << 17;
+
+            assert_eq!(value.even_odd_parity(), even_odd_parity_u64(value));
+            assert_eq!(value.even_odd_parity(), reference_parity(value));
+
+            let small = value as u32;
+            assert_eq!(small.even_odd_parity(), even_odd_parity_u32(small));
+            assert_eq!(
+                small.even_odd_parity() as u64,
+                reference_parity(small as u64)
+            );
+        }
+
+        assert_eq!(0u32.even_odd_parity(), 0);
+        assert_eq!(1u64.even_odd_parity(), 1);
+        assert_eq!(0b11_01u32.even_odd_parity(), 0b10);
+    }
+
+    /// `opcodes.txt` is the only opcode table in this tree, the generated enum and its
+    /// `Display` impl have to agree with it on mnemonics and values.
+    #[test]
+    fn test_opcodes_agree_with_table() {
+        let mut seen: Vec<&str> = Vec::new();
+        for line in include_str!("opcodes.txt").lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let split = line.split(':').map(|v| v.trim()).collect::<Vec<_>>();
+            let value = u16::from_str_radix(split[0], 16).unwrap();
+            let count = seen.iter().filter(|&&m| m == split[1]).count();
+            let mnemonic = if count > 0 {
+                alloc::format!("{}_{}", split[1], count + 1)
+            } else {
+                String::from(split[1])
+            };
+            seen.push(split[1]);
+
+            let opcode = Opcode::from_u16(value).unwrap();
+            assert_eq!(opcode as u16, value);
+            assert_eq!(opcode.to_string(), mnemonic, "opcode {:03x}", value);
+        }
+
+        assert_eq!(Opcode::ADD_DSZ32.to_string(), "ADD_DSZ32");
+        assert_eq!(Opcode::ADD_DSZ32 as u16, 0x000);
+    }
+
+    #[test]
+    fn test_opcode_classification() {
+        let mut known = 0;
+        for value in 0..0x2000 {
+            let Some(opcode) = Opcode::from_u16(value) else {
+                continue;
+            };
+            let classes = [
+                opcode.is_crbus_access(),
+                opcode.is_uram_access(),
+                opcode.is_memory_load(),
+                opcode.is_memory_store(),
+                opcode.is_branch(),
+                opcode.is_arithmetic(),
+                opcode.writes_flags(),
+                opcode.is_data_move(),
+                opcode.is_simd(),
+                opcode.is_floating_point(),
+                opcode.is_sequencer_control(),
+            ];
+            if opcode.is_group_UNKNOWN() {
+                assert!(!classes.contains(&true), "{}", opcode);
+            } else {
+                assert!(classes.contains(&true), "{} is not classified", opcode);
+                known += 1;
+            }
+        }
+        assert!(known > 600);
+
+        assert!(Opcode::MOVETOCREG_DSZ64.is_crbus_access());
+        assert!(Opcode::MOVEFROMCREG_DSZ64.is_crbus_access());
+        assert!(!Opcode::READURAM.is_crbus_access());
+        assert!(Opcode::LDZX_DSZ64_ASZ32_SC1.is_memory_load());
+        assert!(Opcode::STAD_DSZ64_ASZ64_SC1.is_memory_store());
+        assert!(!Opcode::STAD_DSZ64_ASZ64_SC1.is_memory_load());
+        assert!(Opcode::UJMP.is_branch());
+        assert!(Opcode::UJMPCC_DIRECT_NOTTAKEN_CONDZ.is_branch());
+        assert!(Opcode::ADD_DSZ32.is_arithmetic() && Opcode::ADD_DSZ32.writes_flags());
+        assert!(!Opcode::ADDPD.is_arithmetic() && Opcode::ADDPD.is_simd());
+        assert!(Opcode::ZEROEXT_DSZ32.is_data_move() && !Opcode::ZEROEXT_DSZ32.writes_flags());
+    }
+
+    #[test]
+    fn test_triad_register_dependencies() {
+        use crate::instruction::Instruction;
+        use crate::sequence_word::SequenceWord;
+        use crate::{RegisterDependency, Triad};
+        use alloc::vec;
+
+        let triad = Triad {
+            instructions: [
+                Instruction::parse("tmp5:= ADD_DSZ32(tmp0, 0x1)").unwrap(),
+                Instruction::parse("tmp5:= ZEROEXT_DSZ32(tmp5)").unwrap(),
+                // PORTOUT_DSZ32_ASZ16_SC1(0x00c4,  , tmp5)
+                Instruction::disassemble(0x2d0fc4035008),
+            ],
+            sequence_word: SequenceWord::NOP,
+        };
+        assert_eq!(
+            triad.register_dependencies(),
+            Some(vec![
+                RegisterDependency {
+                    definition: 0,
+                    usage: 1,
+                    register: 0x35,
+                },
+                RegisterDependency {
+                    definition: 1,
+                    usage: 2,
+                    register: 0x35,
+                },
+            ])
+        );
+
+        let mut unknown = triad;
+        unknown.instructions[0] = Instruction::disassemble(0x0d6808030e74); // unk_d68
+        assert_eq!(unknown.register_dependencies(), None);
+    }
+
+    #[test]
+    fn test_patch_triad_round_trip() {
+        use crate::instruction::Instruction;
+        use crate::sequence_word::{AssembleError, SequenceWord, SequenceWordControl};
+        use crate::Triad;
+        use data_types::addresses::UCInstructionAddress;
+
+        let triad = Triad {
+            instructions: [
+                Instruction::parse("tmp0:= ADD_DSZ32(tmp1, 0x0010)").unwrap(),
+                Instruction::NOP,
+                Instruction::NOP,
+            ],
+            sequence_word: SequenceWord::builder()
+                .control(2, SequenceWordControl::UEND0)
+                .build()
+                .unwrap(),
+        };
+
+        let patch = data_types::patch::Triad::try_from(&triad).unwrap();
+        let assembled = triad.assemble().unwrap();
+        assert_eq!(
+            patch.instructions,
+            [assembled[0], assembled[1], assembled[2]]
+        );
+        assert_eq!(patch.sequence_word as u64, assembled[3]);
+        assert_eq!(Triad::try_from(patch), Ok(triad));
+        assert_eq!(Triad::disassemble(assembled), Ok(triad));
+
+        // the CRCs are dropped on disassembly and recomputed on assembly
+        let mut corrupted = patch;
+        corrupted.instructions[0] ^= 1 << 46;
+        corrupted.sequence_word ^= 1 << 28;
+        let restored = Triad::try_from(corrupted).unwrap();
+        assert_eq!(restored, triad);
+        assert_eq!(data_types::patch::Triad::try_from(&restored), Ok(patch));
+
+        let mut invalid = triad;
+        invalid.sequence_word = SequenceWord::new();
+        invalid
+            .sequence_word
+            .set_control(0, SequenceWordControl::UEND0)
+            .set_goto(0, UCInstructionAddress::from_const(0x19c8));
+        assert_eq!(
+            data_types::patch::Triad::try_from(&invalid),
+            Err(AssembleError::InvalidCombination)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_patch_triad_serde_round_trip() {
+        use crate::instruction::Instruction;
+        use crate::sequence_word::SequenceWord;
+        use crate::Triad;
+
+        let triad = Triad {
+            instructions: [Instruction::NOP; 3],
+            sequence_word: SequenceWord::NOP,
+        };
+        let patch = data_types::patch::Triad::try_from(&triad).unwrap();
+
+        let json = serde_json::to_string(&patch).unwrap();
+        let loaded = serde_json::from_str::<data_types::patch::Triad>(&json).unwrap();
+        assert_eq!(loaded, patch);
+        assert_eq!(Triad::try_from(loaded), Ok(triad));
+
+        let bytes = postcard::to_allocvec(&patch).unwrap();
+        assert_eq!(
+            postcard::from_bytes::<data_types::patch::Triad>(&bytes).unwrap(),
+            patch
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_triad_serde_round_trip() {
+        use crate::instruction::Instruction;
+        use crate::sequence_word::{SequenceWord, SequenceWordControl, SequenceWordSync};
+        use crate::Triad;
+        use data_types::addresses::UCInstructionAddress;
+
+        let triad = Triad {
+            instructions: [
+                Instruction::parse("tmp0:= ADD_DSZ32(tmp1, 0x0010)").unwrap(),
+                Instruction::NOP,
+                Instruction::from_opcode(Opcode::MOVEFROMCREG_DSZ64),
+            ],
+            sequence_word: SequenceWord::builder()
+                .sync(1, SequenceWordSync::LFNCEWAIT)
+                .goto(2, UCInstructionAddress::from_const(0x19c8))
+                .build()
+                .unwrap(),
+        };
+        let end = Triad {
+            instructions: [Instruction::NOP; 3],
+            sequence_word: SequenceWord::builder()
+                .control(0, SequenceWordControl::UEND0)
+                .build()
+                .unwrap(),
+        };
+
+        for triad in [triad, end] {
+            let bytes = postcard::to_allocvec(&triad).unwrap();
+            assert_eq!(postcard::from_bytes::<Triad>(&bytes).unwrap(), triad);
+
+            let json = serde_json::to_string(&triad).unwrap();
+            assert!(json.contains("\"instructions\""));
+            assert!(json.contains("\"sequence_word\""));
+            assert_eq!(serde_json::from_str::<Triad>(&json).unwrap(), triad);
+        }
     }
 }
-*/
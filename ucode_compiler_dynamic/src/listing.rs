@@ -0,0 +1,202 @@
+//! Disassembly Listing Module
+//!
+//! This module formats triads as a disassembly listing in the layout of the MSROM dump
+//! disassembly, so listings of the MSRAM can be compared line by line with the static dump.
+//!
+//! Every uop is printed on its own line as `Uxxxx: <hex> <sync-> ><mnemonic> <SEQW ...>`,
+//! triads are separated by an empty line. Since only the mnemonic of an instruction is
+//! known, operands are not printed.
+
+use crate::sequence_word::{SequenceWord, SequenceWordControl};
+use crate::Triad;
+use alloc::string::String;
+use core::fmt::Write;
+use data_types::addresses::{Address, UCInstructionAddress};
+
+/// Formats triads as disassembly listing
+///
+/// # Arguments
+///
+/// * `triads` - The triads to format, placed consecutively
+/// * `base` - The address of the first instruction of the first triad
+///
+/// # Returns
+///
+/// Returns the listing, with one line per uop
+pub fn format_listing(triads: &[Triad], base: UCInstructionAddress) -> String {
+    let mut result = String::new();
+    let base = base.triad_base().address();
+
+    for (index, triad) in triads.iter().enumerate() {
+        if index > 0 {
+            result.push('\n');
+        }
+        let triad_address = base + 4 * index;
+
+        for (offset, instruction) in triad.instructions.iter().enumerate() {
+            let address = triad_address + offset;
+            let (prefix, suffix) = sequence_word_effects(&triad.sequence_word, offset, address);
+
+            let _ = writeln!(
+                result,
+                "U{:04x}: {:012x} {}{} {}",
+                address,
+                instruction.raw_bits(),
+                prefix,
+                instruction,
+                suffix
+            );
+        }
+    }
+
+    result
+}
+
+/// Formats the sequence word effects of a single uop like the MSROM dump disassembly
+///
+/// # Arguments
+///
+/// * `sequence_word` - The sequence word of the triad
+/// * `offset` - The index of the uop in the triad (0-2)
+/// * `address` - The address of the uop
+///
+/// # Returns
+///
+/// Returns the text before the mnemonic (synchronization and flow markers) and after it
+/// (control operations and jumps)
+fn sequence_word_effects(
+    sequence_word: &SequenceWord,
+    offset: usize,
+    address: usize,
+) -> (String, String) {
+    let mut prefix = String::new();
+    let mut suffix = String::new();
+
+    let control = sequence_word
+        .control()
+        .as_ref()
+        .filter(|control| control.apply_to_index as usize == offset)
+        .map(|control| control.value);
+    let sync = sequence_word
+        .sync()
+        .as_ref()
+        .filter(|sync| sync.apply_to_index as usize == offset)
+        .map(|sync| sync.value);
+
+    if matches!(
+        control,
+        Some(SequenceWordControl::ROVR_SAVEUPIP0 | SequenceWordControl::ROVR_SAVEUPIP1)
+    ) {
+        prefix.push_str("ROVR<-");
+    }
+    if let Some(sync) = sync {
+        let _ = write!(prefix, "{:?}->", sync);
+    }
+
+    // the saved uip is the address of the following uop
+    let next = if address & 3 == 2 {
+        address + 2
+    } else {
+        address + 1
+    };
+    match control {
+        None => {}
+        Some(
+            control @ (SequenceWordControl::WRTAGW
+            | SequenceWordControl::MSLOOP
+            | SequenceWordControl::MSSTOP),
+        ) => {
+            let _ = write!(prefix, "{:?}->", control);
+        }
+        Some(SequenceWordControl::SAVEUPIP0 | SequenceWordControl::ROVR_SAVEUPIP0) => {
+            let _ = write!(suffix, "SEQW SAVEUIP0 U{:04x}", next);
+        }
+        Some(SequenceWordControl::SAVEUPIP1 | SequenceWordControl::ROVR_SAVEUPIP1) => {
+            let _ = write!(suffix, "SEQW SAVEUIP1 U{:04x}", next);
+        }
+        Some(control) => {
+            let _ = write!(suffix, "SEQW {:?}", control);
+        }
+    }
+
+    if let Some(goto) = sequence_word
+        .goto()
+        .as_ref()
+        .filter(|goto| goto.apply_to_index as usize == offset)
+    {
+        let _ = write!(suffix, "SEQW GOTO {}", goto.value);
+    }
+
+    if !prefix.is_empty() {
+        prefix.push(' ');
+    }
+
+    (prefix, suffix)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::instruction::Instruction;
+    use crate::listing::format_listing;
+    use crate::sequence_word::{SequenceWord, SequenceWordControl, SequenceWordSync};
+    use crate::Triad;
+    use data_types::addresses::UCInstructionAddress;
+
+    #[test]
+    fn test_format_listing() {
+        let first = Triad {
+            instructions: [
+                Instruction::disassemble(0x00626803f200),
+                Instruction::disassemble(0x000801030008),
+                Instruction::disassemble(0x004800013000),
+            ],
+            sequence_word: *SequenceWord::new().set_goto(1, 0x3452usize),
+        };
+        let second = Triad {
+            instructions: [
+                Instruction::disassemble(0x000c7497e208),
+                Instruction::NOP,
+                Instruction::disassemble(0x015d990002c0),
+            ],
+            sequence_word: *SequenceWord::new()
+                .set_sync(2, SequenceWordSync::SYNCFULL)
+                .set_control(2, SequenceWordControl::SAVEUPIP1)
+                .set_goto(0, 0x0909usize),
+        };
+        let third = Triad {
+            instructions: [Instruction::NOP; 3],
+            sequence_word: *SequenceWord::new()
+                .set_sync(0, SequenceWordSync::LFNCEMARK)
+                .set_control(0, SequenceWordControl::ROVR_SAVEUPIP0)
+                .set_goto(0, 0x1c79usize),
+        };
+
+        let listing = format_listing(
+            &[first, second, third],
+            UCInstructionAddress::from_const(0x0000),
+        );
+        assert_eq!(
+            listing,
+            "U0000: 00626803f200 MOVEFROMCREG_DSZ64 \n\
+             U0001: 000801030008 ZEROEXT_DSZ32 SEQW GOTO U3452\n\
+             U0002: 004800013000 ZEROEXT_DSZ64 \n\
+             \n\
+             U0004: 000c7497e208 SAVEUIP SEQW GOTO U0909\n\
+             U0005: 000000000000 NOP \n\
+             U0006: 015d990002c0 SYNCFULL-> UJMP SEQW SAVEUIP1 U0008\n\
+             \n\
+             U0008: 000000000000 ROVR<-LFNCEMARK-> NOP SEQW SAVEUIP0 U0009SEQW GOTO U1c79\n\
+             U0009: 000000000000 NOP \n\
+             U000a: 000000000000 NOP \n"
+        );
+
+        let nop = Triad {
+            instructions: [Instruction::NOP; 3],
+            sequence_word: SequenceWord::NOP,
+        };
+        let listing = format_listing(&[nop, nop], UCInstructionAddress::MAX);
+        assert!(listing.starts_with("U7dfc: "));
+        assert!(listing.contains("U7e00: "));
+        assert_eq!(format_listing(&[], UCInstructionAddress::MSRAM_START), "");
+    }
+}
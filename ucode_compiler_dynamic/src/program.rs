@@ -0,0 +1,330 @@
+//! Microcode Program Module
+//!
+//! This module provides a sequence of triads with symbolic labels, that is assembled
+//! to a concrete address at runtime. Jumps between the triads are expressed as labels
+//! and resolved relative to the base address on assembly.
+
+use crate::sequence_word;
+use crate::Triad;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use data_types::addresses::{Address, UCInstructionAddress};
+
+/// Errors that can occur during program assembly
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AssembleError {
+    /// A sequence word jumps to a label that is not defined
+    UndefinedLabel(String),
+    /// The label is defined more than once
+    DuplicateLabel(String),
+    /// The address is not a valid jump target, e.g. the base is not the start of a triad
+    MisalignedTarget(UCInstructionAddress),
+    /// The address is outside of the addressable microcode
+    AddressOutOfRange(usize),
+    /// The sequence word of the triad with this index can not be assembled
+    InvalidSequenceWord(usize, sequence_word::AssembleError),
+}
+
+/// Result type for program assembly
+pub type AssembleResult<T> = Result<T, AssembleError>;
+
+/// An ordered list of triads with symbolic labels
+///
+/// Labels mark the start of the next appended triad. Sequence words jump to labels via
+/// [`UcodeProgram::goto_label`], which are resolved by [`UcodeProgram::assemble`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UcodeProgram {
+    /// The triads in program order, with the instruction index and label of their jump
+    triads: Vec<(Triad, Option<(u8, String)>)>,
+    /// Labels mapped to the index of the triad they mark
+    labels: BTreeMap<String, usize>,
+    /// Labels that were defined more than once, reported on assembly
+    duplicate_labels: Vec<String>,
+}
+
+impl UcodeProgram {
+    /// Creates a new empty program
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a triad to the program
+    ///
+    /// # Arguments
+    ///
+    /// * `triad` - The triad to append
+    ///
+    /// # Returns
+    ///
+    /// Returns a mutable reference to self for method chaining
+    pub fn append_triad(&mut self, triad: Triad) -> &mut Self {
+        self.triads.push((triad, None));
+        self
+    }
+
+    /// Defines a label at the start of the next appended triad
+    ///
+    /// A label defined after the last triad marks the address directly after the program.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the label
+    ///
+    /// # Returns
+    ///
+    /// Returns a mutable reference to self for method chaining
+    pub fn label(&mut self, name: &str) -> &mut Self {
+        if self
+            .labels
+            .insert(name.to_string(), self.triads.len())
+            .is_some()
+        {
+            self.duplicate_labels.push(name.to_string());
+        }
+        self
+    }
+
+    /// Lets the sequence word of the last appended triad jump to a label
+    ///
+    /// Replaces any jump target already set in the sequence word.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The instruction index (0-2) after which to jump
+    /// * `name` - The name of the label to jump to
+    ///
+    /// # Returns
+    ///
+    /// Returns a mutable reference to self for method chaining
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is invalid or no triad was appended yet
+    pub fn goto_label(&mut self, index: u8, name: &str) -> &mut Self {
+        assert!(index < 3);
+        let (_, goto) = self
+            .triads
+            .last_mut()
+            .expect("a triad has to be appended before jumping to a label");
+        *goto = Some((index, name.to_string()));
+        self
+    }
+
+    /// Gets the number of triads in the program
+    pub fn len(&self) -> usize {
+        self.triads.len()
+    }
+
+    /// Checks if the program contains no triads
+    pub fn is_empty(&self) -> bool {
+        self.triads.is_empty()
+    }
+
+    /// Assembles the program to be placed at a base address
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - The address of the first triad, has to be the start of a triad
+    ///
+    /// # Returns
+    ///
+    /// Returns the assembled triads in program order, see [`Triad::assemble`]
+    pub fn assemble(&self, base: UCInstructionAddress) -> AssembleResult<Vec<[u64; 4]>> {
+        if let Some(name) = self.duplicate_labels.first() {
+            return Err(AssembleError::DuplicateLabel(name.clone()));
+        }
+        if base.triad_offset() != 0 {
+            return Err(AssembleError::MisalignedTarget(base));
+        }
+
+        let last = base.address() + 4 * self.triads.len();
+        if !self.triads.is_empty() && last > UCInstructionAddress::MAX.address() + 1 {
+            return Err(AssembleError::AddressOutOfRange(last - 1));
+        }
+
+        self.triads
+            .iter()
+            .enumerate()
+            .map(|(index, (triad, goto))| {
+                let mut triad = *triad;
+                if let Some((slot, name)) = goto {
+                    let target = self
+                        .labels
+                        .get(name)
+                        .ok_or_else(|| AssembleError::UndefinedLabel(name.clone()))?;
+                    let address = base.address() + 4 * target;
+                    if address > UCInstructionAddress::MAX.address() {
+                        return Err(AssembleError::AddressOutOfRange(address));
+                    }
+                    triad.sequence_word.set_goto(*slot, address);
+                }
+
+                if let Some(goto) = triad.sequence_word.goto() {
+                    if goto.value.triad_offset() == 3 {
+                        return Err(AssembleError::MisalignedTarget(goto.value));
+                    }
+                }
+
+                triad
+                    .assemble()
+                    .map_err(|e| AssembleError::InvalidSequenceWord(index, e))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::instruction::Instruction;
+    use crate::program::{AssembleError, UcodeProgram};
+    use crate::sequence_word::{self, SequenceWord, SequenceWordControl};
+    use crate::Triad;
+    use alloc::string::ToString;
+    use data_types::addresses::UCInstructionAddress;
+
+    fn triad(text: &str) -> Triad {
+        Triad {
+            instructions: [
+                Instruction::parse(text).unwrap(),
+                Instruction::NOP,
+                Instruction::NOP,
+            ],
+            sequence_word: SequenceWord::NOP,
+        }
+    }
+
+    #[test]
+    fn test_program_assemble() {
+        let mut end = triad("tmp0:= ZEROEXT_DSZ32(0x0002)");
+        end.sequence_word.set_control(0, SequenceWordControl::UEND0);
+
+        let mut program = UcodeProgram::new();
+        program
+            .label("entry")
+            .append_triad(triad("tmp0:= ZEROEXT_DSZ32(0x0001)"))
+            .goto_label(2, "exit")
+            .append_triad(triad("tmp0:= ZEROEXT_DSZ32(0x0003)"))
+            .goto_label(0, "entry")
+            .label("exit")
+            .append_triad(end);
+        assert_eq!(program.len(), 3);
+
+        let base = UCInstructionAddress::from_const(0x7c40);
+        let assembled = program.assemble(base).unwrap();
+        assert_eq!(assembled.len(), 3);
+
+        let decoded = assembled
+            .iter()
+            .map(|triad| Triad::disassemble(*triad).unwrap())
+            .collect::<alloc::vec::Vec<_>>();
+        let goto = |index: usize| decoded[index].sequence_word.goto().map(|g| g.value);
+        assert_eq!(goto(0), Some(UCInstructionAddress::from_const(0x7c48)));
+        assert_eq!(goto(1), Some(base));
+        assert_eq!(goto(2), None);
+        assert_eq!(decoded[2], end);
+        assert_eq!(
+            decoded[0].instructions,
+            triad("tmp0:= ZEROEXT_DSZ32(0x0001)").instructions
+        );
+
+        // relocating only changes the jump targets
+        let relocated = program
+            .assemble(UCInstructionAddress::from_const(0x7d00))
+            .unwrap();
+        let decoded = Triad::disassemble(relocated[1]).unwrap();
+        assert_eq!(
+            decoded.sequence_word.goto().map(|g| g.value),
+            Some(UCInstructionAddress::from_const(0x7d00))
+        );
+
+        assert_eq!(UcodeProgram::new().assemble(base), Ok(alloc::vec![]));
+    }
+
+    #[test]
+    fn test_program_errors() {
+        let base = UCInstructionAddress::MSRAM_START;
+
+        let mut program = UcodeProgram::new();
+        program.append_triad(triad("NOP")).goto_label(0, "missing");
+        assert_eq!(
+            program.assemble(base),
+            Err(AssembleError::UndefinedLabel("missing".to_string()))
+        );
+
+        let mut program = UcodeProgram::new();
+        program
+            .label("twice")
+            .append_triad(triad("NOP"))
+            .label("twice")
+            .append_triad(triad("NOP"));
+        assert_eq!(
+            program.assemble(base),
+            Err(AssembleError::DuplicateLabel("twice".to_string()))
+        );
+
+        let mut program = UcodeProgram::new();
+        program
+            .label("loop")
+            .append_triad(triad("NOP"))
+            .goto_label(1, "loop");
+        assert_eq!(
+            program.assemble(base + 2),
+            Err(AssembleError::MisalignedTarget(base + 2))
+        );
+        assert!(program.assemble(base).is_ok());
+
+        let mut misaligned = triad("NOP");
+        misaligned
+            .sequence_word
+            .set_goto(0, UCInstructionAddress::from_const(0x1233));
+        let mut program = UcodeProgram::new();
+        program.append_triad(misaligned);
+        assert_eq!(
+            program.assemble(base),
+            Err(AssembleError::MisalignedTarget(
+                UCInstructionAddress::from_const(0x1233)
+            ))
+        );
+
+        let mut program = UcodeProgram::new();
+        program
+            .append_triad(triad("NOP"))
+            .goto_label(0, "after")
+            .label("after");
+        assert_eq!(
+            program.assemble(UCInstructionAddress::from_const(0x7dfc)),
+            Err(AssembleError::AddressOutOfRange(0x7e00))
+        );
+        assert!(program
+            .assemble(UCInstructionAddress::from_const(0x7df8))
+            .is_ok());
+
+        let mut program = UcodeProgram::new();
+        program
+            .append_triad(triad("NOP"))
+            .append_triad(triad("NOP"));
+        assert_eq!(
+            program.assemble(UCInstructionAddress::from_const(0x7dfc)),
+            Err(AssembleError::AddressOutOfRange(0x7e03))
+        );
+
+        let mut conflicting = triad("NOP");
+        conflicting
+            .sequence_word
+            .set_control(1, SequenceWordControl::UEND0);
+        let mut program = UcodeProgram::new();
+        program
+            .label("start")
+            .append_triad(triad("NOP"))
+            .append_triad(conflicting)
+            .goto_label(1, "start");
+        assert_eq!(
+            program.assemble(base),
+            Err(AssembleError::InvalidSequenceWord(
+                1,
+                sequence_word::AssembleError::InvalidCombination
+            ))
+        );
+    }
+}
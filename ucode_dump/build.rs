@@ -42,7 +42,7 @@ fn create_cpu_files() {
             let labels = generate_labels(cpu_arch.join("labels.csv"));
 
             let disassembly = format!(
-                "pub const DISSASSEMBLY: &str = \"{}\";\n",
+                "#[cfg(feature = \"text_disassembly\")]\npub const DISSASSEMBLY: &str = \"{}\";\n",
                 fs::read_to_string(cpu_arch.join("glm.ucode"))
                     .expect("Failed to read disassembly file"),
             );
@@ -58,8 +58,8 @@ fn create_cpu_files() {
                 cpu_model_name
             ));
 
-            module_file_contents.push_str(format!("#[allow(non_snake_case, non_upper_case_globals)] pub const ROM_{cpu_model_name}: RomDump<'static, 'static> = RomDump::new(&{cpu_model_name}::ROM_INSTRUCTION, &{cpu_model_name}::ROM_SEQUENCE, 0x{cpu_model});\n").as_str());
-            module_file_contents.push_str(format!("#[allow(non_snake_case, non_upper_case_globals)] pub const ROM_DISASM_{cpu_model_name}: RomDumpDissasembly<'static> = RomDumpDissasembly::new({cpu_model_name}::DISSASSEMBLY, 0x{cpu_model});\n").as_str());
+            module_file_contents.push_str(format!("#[allow(non_snake_case, non_upper_case_globals)] pub const ROM_{cpu_model_name}: RomDump<'static, 'static> = RomDump::new(&{cpu_model_name}::ROM_INSTRUCTION, &{cpu_model_name}::ROM_SEQUENCE, 0x{cpu_model}).with_symbols(&{cpu_model_name}::SYMBOLS);\n").as_str());
+            module_file_contents.push_str(format!("#[cfg(feature = \"text_disassembly\")] #[allow(non_snake_case, non_upper_case_globals)] pub const ROM_DISASM_{cpu_model_name}: RomDumpDissasembly<'static> = RomDumpDissasembly::new({cpu_model_name}::DISSASSEMBLY, 0x{cpu_model});\n").as_str());
 
             array_dumps.push(cpu_model_name);
         }
@@ -79,7 +79,7 @@ fn create_cpu_files() {
 
     module_file_contents.push_str(
         format!(
-            "\n\npub const ROMS_DISASM: [&RomDumpDissasembly<'static>; {}] = [",
+            "\n\n#[cfg(feature = \"text_disassembly\")]\npub const ROMS_DISASM: [&RomDumpDissasembly<'static>; {}] = [",
             array_dumps.len()
         )
         .as_str(),
@@ -102,6 +102,7 @@ fn generate_labels<P: AsRef<Path>>(labels: P) -> String {
     let content = fs::read_to_string(labels.as_ref()).expect("Unable to read file with labels");
 
     let mut labels = String::new();
+    let mut symbols = Vec::new();
 
     labels.push_str(AUTOGEN);
     labels.push_str("\nuse data_types::addresses::UCInstructionAddress;\n");
@@ -127,8 +128,19 @@ fn generate_labels<P: AsRef<Path>>(labels: P) -> String {
             label.to_uppercase(),
             address
         ));
+        symbols.push(label.to_uppercase());
     }
 
+    labels.push_str("/// Known entry points by name, see `RomDump::symbols`\n");
+    labels.push_str(&format!(
+        "pub const SYMBOLS: [(&str, UCInstructionAddress); {}] = [\n",
+        symbols.len()
+    ));
+    for symbol in symbols {
+        labels.push_str(&format!("    (\"{symbol}\", {symbol}),\n"));
+    }
+    labels.push_str("];\n");
+
     labels
 }
 
@@ -0,0 +1,242 @@
+//! # ROM dump comparison
+//!
+//! Compares two microcode ROM dumps, e.g. of different CPU revisions, and aggregates
+//! the differences into regions of consecutive changed triads.
+
+use crate::RomDump;
+use alloc::vec::Vec;
+use core::fmt;
+use data_types::addresses::UCInstructionAddress;
+use ucode_compiler_dynamic::instruction::Instruction;
+
+/// Kind of change within a region
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ChangeKind {
+    /// Only immediate values of instructions changed
+    Immediate,
+    /// Opcodes, register operands or sequence words changed
+    Structural,
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChangeKind::Immediate => write!(f, "immediate"),
+            ChangeKind::Structural => write!(f, "structural"),
+        }
+    }
+}
+
+/// A range of consecutive triads that differ between two dumps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DiffRegion {
+    /// Address of the first instruction of the first changed triad
+    pub start: UCInstructionAddress,
+    /// Address of the last instruction of the last changed triad
+    pub end: UCInstructionAddress,
+    /// Kind of the most severe change in this region
+    pub kind: ChangeKind,
+}
+
+impl fmt::Display for DiffRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..={}: {}", self.start, self.end, self.kind)
+    }
+}
+
+/// Differences between two ROM dumps, see [`diff`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RomDiff {
+    /// Addresses of all differing instructions
+    pub instructions: Vec<UCInstructionAddress>,
+    /// Triad addresses of all differing sequence words
+    pub sequence_words: Vec<UCInstructionAddress>,
+    /// Changed regions in ascending address order
+    pub regions: Vec<DiffRegion>,
+}
+
+impl RomDiff {
+    /// Checks if both dumps are identical
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// Iterates over the changed regions in ascending address order
+    pub fn iter(&self) -> core::slice::Iter<'_, DiffRegion> {
+        self.regions.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a RomDiff {
+    type Item = &'a DiffRegion;
+    type IntoIter = core::slice::Iter<'a, DiffRegion>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl fmt::Display for RomDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} instructions and {} sequence words differ in {} regions",
+            self.instructions.len(),
+            self.sequence_words.len(),
+            self.regions.len()
+        )?;
+        for region in self {
+            writeln!(f, "{}", region)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares two ROM dumps triad by triad
+///
+/// # Arguments
+/// * `a` - The first dump
+/// * `b` - The second dump
+///
+/// # Returns
+/// * `RomDiff` - The differing instructions and sequence words, aggregated into regions
+pub fn diff(a: &RomDump, b: &RomDump) -> RomDiff {
+    let mut result = RomDiff::default();
+    let mut current: Option<DiffRegion> = None;
+
+    for ((base, first), (_, second)) in a.iter_triads().zip(b.iter_triads()) {
+        let mut kind = None;
+
+        for (offset, (x, y)) in first
+            .instructions
+            .iter()
+            .zip(second.instructions.iter())
+            .enumerate()
+        {
+            if x != y {
+                result.instructions.push(base + offset);
+                let structural =
+                    !Instruction::disassemble(*x).structural_eq(&Instruction::disassemble(*y));
+                kind = kind.max(Some(if structural {
+                    ChangeKind::Structural
+                } else {
+                    ChangeKind::Immediate
+                }));
+            }
+        }
+
+        if first.sequence_word != second.sequence_word {
+            result.sequence_words.push(base);
+            kind = Some(ChangeKind::Structural);
+        }
+
+        match (kind, current.as_mut()) {
+            (Some(kind), Some(region)) => {
+                region.end = base + 2;
+                region.kind = region.kind.max(kind);
+            }
+            (Some(kind), None) => {
+                current = Some(DiffRegion {
+                    start: base,
+                    end: base + 2,
+                    kind,
+                })
+            }
+            (None, _) => result.regions.extend(current.take()),
+        }
+    }
+    result.regions.extend(current);
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diff::{diff, ChangeKind, DiffRegion};
+    use crate::dump::{ROM_cpu_000506C9, ROM_cpu_000506CA};
+    use crate::RomDump;
+    use data_types::addresses::UCInstructionAddress;
+    use std::boxed::Box;
+    use std::string::ToString;
+    use std::vec;
+    use std::vec::Vec;
+    use ucode_compiler_dynamic::instruction::Instruction;
+    extern crate std;
+
+    #[test]
+    fn test_diff_synthetic() {
+        let instruction = |text: &str| Instruction::parse(text).unwrap().assemble_no_crc();
+
+        let mut instructions: Box<[u64; 0x7c00]> = vec![0; 0x7c00].try_into().unwrap();
+        let sequences: Box<[u32; 0x7c00 / 4]> = vec![0; 0x7c00 / 4].try_into().unwrap();
+        instructions[0x10] = instruction("tmp0:= ZEROEXT_DSZ32(0x0001)");
+        instructions[0x20] = instruction("tmp0:= ZEROEXT_DSZ32(0x0001)");
+
+        let mut changed_instructions = instructions.clone();
+        let mut changed_sequences = sequences.clone();
+        // only the immediate changes
+        changed_instructions[0x10] = instruction("tmp0:= ZEROEXT_DSZ32(0x0002)");
+        // opcode changes across two adjacent triads
+        changed_instructions[0x20] = instruction("tmp0:= ZEROEXT_DSZ64(0x0001)");
+        changed_instructions[0x26] = instruction("tmp1:= ZEROEXT_DSZ64(0x0001)");
+        // the sequence word of the last triad changes
+        changed_sequences[0x7bfc / 4] = 0x01b45240;
+
+        let a = RomDump::new(&instructions, &sequences, 0);
+        let b = RomDump::new(&changed_instructions, &changed_sequences, 1);
+        let result = diff(&a, &b);
+
+        let address = UCInstructionAddress::from_const;
+        assert_eq!(
+            result.instructions,
+            vec![address(0x10), address(0x20), address(0x26)]
+        );
+        assert_eq!(result.sequence_words, vec![address(0x7bfc)]);
+        assert_eq!(
+            result.iter().copied().collect::<Vec<_>>(),
+            vec![
+                DiffRegion {
+                    start: address(0x10),
+                    end: address(0x12),
+                    kind: ChangeKind::Immediate,
+                },
+                DiffRegion {
+                    start: address(0x20),
+                    end: address(0x26),
+                    kind: ChangeKind::Structural,
+                },
+                DiffRegion {
+                    start: address(0x7bfc),
+                    end: address(0x7bfe),
+                    kind: ChangeKind::Structural,
+                },
+            ]
+        );
+        assert_eq!(
+            result.to_string(),
+            "3 instructions and 1 sequence words differ in 3 regions\n\
+             U0010..=U0012: immediate\n\
+             U0020..=U0026: structural\n\
+             U7bfc..=U7bfe: structural\n"
+        );
+
+        assert!(diff(&a, &a).is_empty());
+    }
+
+    #[test]
+    fn test_diff_glm_revisions() {
+        let result = diff(&ROM_cpu_000506C9, &ROM_cpu_000506CA);
+        assert!(!result.is_empty());
+        assert!(result
+            .regions
+            .windows(2)
+            .all(|pair| pair[0].end < pair[1].start));
+        assert!(result
+            .instructions
+            .iter()
+            .chain(result.sequence_words.iter())
+            .all(|address| result
+                .iter()
+                .any(|region| region.start <= *address && *address <= region.end)));
+    }
+}
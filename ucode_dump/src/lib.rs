@@ -4,10 +4,28 @@
 //! This crate provides functionality for working with microcode dumps and includes dumps of known architectures.
 #![no_std]
 
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
 use data_types::addresses::{Address, UCInstructionAddress};
 use data_types::patch::Triad;
+use ucode_compiler_dynamic::instruction::Instruction;
+use ucode_compiler_dynamic::listing::format_listing;
+use ucode_compiler_dynamic::sequence_word::{DisassembleError, SequenceWord};
 
+pub mod diff;
 pub mod dump;
+pub mod owned;
+pub mod statistics;
+pub mod symbols;
+
+pub use diff::{diff, RomDiff};
+pub use owned::{LoadError, OwnedRomDump};
+pub use symbols::SymbolTable;
 
 /// Represents a disassembled ROM dump with model information
 pub struct RomDumpDissasembly<'a> {
@@ -44,6 +62,7 @@ pub struct RomDump<'a, 'b> {
     instructions: &'a [u64; 0x7c00],
     sequences: &'b [u32; 0x7c00 / 4],
     model: u32,
+    symbols: &'static [(&'static str, UCInstructionAddress)],
 }
 
 impl<'a, 'b> RomDump<'a, 'b> {
@@ -62,9 +81,31 @@ impl<'a, 'b> RomDump<'a, 'b> {
             instructions,
             sequences,
             model,
+            symbols: &[],
         }
     }
 
+    /// Attaches the known entry points of the dump
+    ///
+    /// # Arguments
+    /// * `symbols` - Pairs of name and address
+    pub const fn with_symbols(
+        self,
+        symbols: &'static [(&'static str, UCInstructionAddress)],
+    ) -> Self {
+        RomDump { symbols, ..self }
+    }
+
+    /// Returns the symbol table of the known entry points of the dump
+    ///
+    /// The table can be extended with user provided symbols, see [`SymbolTable::insert`].
+    ///
+    /// # Returns
+    /// * `SymbolTable` - The symbols of the dump
+    pub fn symbols(&self) -> SymbolTable {
+        SymbolTable::new(self.symbols)
+    }
+
     /// Returns the array of microcode instructions
     pub fn instructions(&self) -> &'a [u64; 0x7c00] {
         self.instructions
@@ -135,4 +176,462 @@ impl<'a, 'b> RomDump<'a, 'b> {
         };
         Some(triad)
     }
+
+    /// Iterates over all triads of the ROM
+    ///
+    /// # Returns
+    /// * `impl Iterator<Item = (UCInstructionAddress, Triad)>` - The triads with the address of their first instruction
+    pub fn iter_triads(&self) -> impl Iterator<Item = (UCInstructionAddress, Triad)> + '_ {
+        self.iter_triads_from(UCInstructionAddress::ZERO)
+    }
+
+    /// Iterates over the triads of the ROM, starting at the specified address
+    ///
+    /// # Arguments
+    /// * `start` - The first address, the triad containing it is the first one returned
+    ///
+    /// # Returns
+    /// * `impl Iterator<Item = (UCInstructionAddress, Triad)>` - The triads with the address of their first instruction
+    pub fn iter_triads_from(
+        &self,
+        start: UCInstructionAddress,
+    ) -> impl Iterator<Item = (UCInstructionAddress, Triad)> + '_ {
+        (start.triad_base().address()..self.instructions.len())
+            .step_by(4)
+            .map(UCInstructionAddress::from_const)
+            .filter_map(|address| Some((address, self.triad(address)?)))
+    }
+
+    /// Finds all instructions matching a predicate
+    ///
+    /// The unused fourth slot of each triad is skipped.
+    ///
+    /// # Arguments
+    /// * `f` - Predicate called with the raw instruction and its address
+    ///
+    /// # Returns
+    /// * `Vec<UCInstructionAddress>` - The addresses of all matching instructions in ascending order
+    pub fn find_instructions<F: Fn(u64, UCInstructionAddress) -> bool>(
+        &self,
+        f: F,
+    ) -> Vec<UCInstructionAddress> {
+        self.iter_triads()
+            .flat_map(|(base, triad)| {
+                (0..3).map(move |offset| (base + offset, triad.instructions[offset]))
+            })
+            .filter(|(address, instruction)| f(*instruction, *address))
+            .map(|(address, _)| address)
+            .collect()
+    }
+
+    /// Finds all instructions with the specified opcode
+    ///
+    /// # Arguments
+    /// * `opcode_value` - The raw opcode value, e.g. `ucode_compiler_dynamic::opcodes::Opcode::WRITEURAM as u16`
+    ///
+    /// # Returns
+    /// * `Vec<UCInstructionAddress>` - The addresses of all instructions with this opcode in ascending order
+    pub fn find_opcode(&self, opcode_value: u16) -> Vec<UCInstructionAddress> {
+        self.find_instructions(|instruction, _| statistics::raw_opcode(instruction) == opcode_value)
+    }
+
+    /// Finds all instructions that may transfer control to an address
+    ///
+    /// Considers sequence word jumps, immediate branch targets and falling through to the
+    /// next instruction, as determined by [`ucode_compiler_dynamic::Triad::control_flow`].
+    /// Indirect branches, e.g. returns, are not resolved.
+    ///
+    /// # Arguments
+    /// * `target` - The address control is transferred to
+    ///
+    /// # Returns
+    /// * `Vec<UCInstructionAddress>` - The addresses of all transferring instructions in ascending order
+    pub fn callers_of(&self, target: UCInstructionAddress) -> Vec<UCInstructionAddress> {
+        self.iter_triads()
+            .filter_map(|(base, _)| Some((base, self.decoded_triad(base)?.ok()?)))
+            .flat_map(|(base, triad)| {
+                (0..3)
+                    .map(move |offset| base + offset)
+                    .filter(move |address| {
+                        triad.control_flow(*address).successors().contains(&target)
+                    })
+            })
+            .collect()
+    }
+
+    /// Disassembles triads of the ROM to a listing
+    ///
+    /// The listing has the format of [`ucode_compiler_dynamic::listing::format_listing`].
+    /// Sequence words that cannot be disassembled are printed as raw value with a `.unk`
+    /// marker after the last instruction of their triad.
+    ///
+    /// # Arguments
+    /// * `start` - The first address, the triad containing it is the first one listed
+    /// * `count` - The maximum number of triads to list
+    ///
+    /// # Returns
+    /// * `String` - The listing, with one line per instruction
+    pub fn disassemble_range(&self, start: UCInstructionAddress, count: usize) -> String {
+        let mut result = String::new();
+
+        for (index, (address, triad)) in self.iter_triads_from(start).take(count).enumerate() {
+            if index > 0 {
+                result.push('\n');
+            }
+
+            match self
+                .decoded_triad(address)
+                .expect("iterated triads are part of the ROM")
+            {
+                Ok(decoded) => result.push_str(&format_listing(&[decoded], address)),
+                Err(_) => {
+                    let decoded = ucode_compiler_dynamic::Triad {
+                        instructions: triad.instructions.map(Instruction::disassemble),
+                        sequence_word: SequenceWord::NOP,
+                    };
+                    result.push_str(format_listing(&[decoded], address).trim_end_matches('\n'));
+                    let _ = writeln!(result, ".unk {:08x}", triad.sequence_word);
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Retrieves a disassembled triad (3 instructions and sequence word) at the specified address
+    ///
+    /// Sequence words that cannot be disassembled are reported instead of being skipped. The CRC
+    /// of the sequence word is not checked, as the dumps do not contain it.
+    ///
+    /// # Arguments
+    /// * `address` - The instruction address
+    ///
+    /// # Returns
+    /// * `Option<Result<ucode_compiler_dynamic::Triad, DisassembleError>>` - The disassembled triad or the reason its sequence word is invalid, None if the address is not part of the ROM
+    pub fn decoded_triad(
+        &self,
+        address: UCInstructionAddress,
+    ) -> Option<Result<ucode_compiler_dynamic::Triad, DisassembleError>> {
+        Some(ucode_compiler_dynamic::Triad::try_from(
+            self.triad(address)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dump::ROM_cpu_000506CA;
+    use data_types::addresses::{Address, UCInstructionAddress};
+    extern crate std;
+
+    use crate::RomDump;
+    use std::boxed::Box;
+    use std::collections::BTreeSet;
+    use std::vec;
+    use std::vec::Vec;
+    use ucode_compiler_dynamic::instruction::Instruction;
+    use ucode_compiler_dynamic::opcodes::Opcode;
+    use ucode_compiler_dynamic::sequence_word::{DisassembleError, SequenceWord};
+    use ucode_compiler_dynamic::{ControlFlow, ControlFlowBranchPrediction, Triad};
+
+    #[test]
+    fn test_decoded_triad() {
+        let rom = ROM_cpu_000506CA;
+
+        let triad = rom
+            .decoded_triad(UCInstructionAddress::from_const(0x0001))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            triad.instructions,
+            [
+                Instruction::disassemble(0x00626803f200),
+                Instruction::disassemble(0x000801030008),
+                Instruction::disassemble(0x004800013000),
+            ]
+        );
+        assert_eq!(
+            triad.sequence_word.goto().map(|goto| goto.value),
+            Some(UCInstructionAddress::from_const(0x3452))
+        );
+        for address in [0x0, 0x428, 0x42a, 0x7bfc] {
+            let address = UCInstructionAddress::from_const(address);
+            let manual = rom
+                .triad(address)
+                .map(ucode_compiler_dynamic::Triad::try_from);
+            assert_eq!(rom.decoded_triad(address), manual);
+        }
+        assert_eq!(rom.decoded_triad(UCInstructionAddress::MSRAM_START), None);
+
+        // a sequence word with an invalid control index
+        let instructions: Box<[u64; 0x7c00]> = vec![0; 0x7c00].try_into().unwrap();
+        let mut sequences: Box<[u32; 0x7c00 / 4]> = vec![0; 0x7c00 / 4].try_into().unwrap();
+        sequences[1] = 0x3;
+        let dump = RomDump::new(&instructions, &sequences, 0);
+        assert_eq!(
+            dump.decoded_triad(UCInstructionAddress::from_const(0x5)),
+            Some(Err(DisassembleError::InvalidControlIndex(0x3)))
+        );
+    }
+
+    #[test]
+    fn test_iter_triads() {
+        let rom = ROM_cpu_000506CA;
+        assert_eq!(rom.iter_triads().count(), 0x7c00 / 4);
+
+        for (address, triad) in rom.iter_triads() {
+            assert_eq!(address.triad_offset(), 0);
+            assert_eq!(rom.triad(address), Some(triad));
+        }
+        let spot_checked = rom
+            .iter_triads()
+            .filter(|(address, _)| [0x0, 0x428, 0x19d4, 0x7bfc].contains(&address.address()))
+            .collect::<Vec<_>>();
+        assert_eq!(spot_checked.len(), 4);
+        assert_eq!(
+            spot_checked[3],
+            (
+                UCInstructionAddress::from_const(0x7bfc),
+                rom.triad(UCInstructionAddress::from_const(0x7bfe)).unwrap()
+            )
+        );
+
+        let mut bounded = rom.iter_triads_from(UCInstructionAddress::from_const(0x19d5));
+        assert_eq!(
+            bounded.next().map(|(address, _)| address),
+            Some(UCInstructionAddress::from_const(0x19d4))
+        );
+        assert_eq!(bounded.count(), (0x7c00 - 0x19d8) / 4);
+        assert_eq!(
+            rom.iter_triads_from(UCInstructionAddress::MSRAM_START)
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_find_instructions() {
+        let rom = ROM_cpu_000506CA;
+        let rdrand = crate::dump::cpu_000506CA::RDRAND_XLAT;
+        let opcode = Instruction::disassemble(rom.get_instruction(rdrand).unwrap()).opcode();
+
+        let found = rom.find_opcode(opcode as u16);
+        assert!(found.contains(&rdrand));
+        assert!(found.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(found.iter().all(|address| address.triad_offset() != 3));
+        assert!(found.iter().all(|address| {
+            Instruction::disassemble(rom.get_instruction(*address).unwrap()).opcode() == opcode
+        }));
+
+        let writeuram = rom.find_instructions(|instruction, _| {
+            Instruction::disassemble(instruction).opcode() == Opcode::WRITEURAM
+        });
+        assert!(!writeuram.is_empty());
+        assert_eq!(writeuram, rom.find_opcode(Opcode::WRITEURAM as u16));
+
+        let by_address = rom.find_instructions(|_, address| address.address() < 8);
+        assert_eq!(by_address.len(), 6);
+    }
+
+    #[test]
+    fn test_callers_of() {
+        let rom = ROM_cpu_000506CA;
+        let callers = |target: usize| rom.callers_of(UCInstructionAddress::from_const(target));
+        let address = UCInstructionAddress::from_const;
+
+        // U0001: SEQW GOTO U3452, no fall through to U0002
+        assert!(callers(0x3452).contains(&address(0x0001)));
+        assert!(callers(0x0001).contains(&address(0x0000)));
+        // U0005: TESTUSTATE ? SEQW GOTO U0002
+        assert!(callers(0x0002).contains(&address(0x0005)));
+        assert!(!callers(0x0002).contains(&address(0x0001)));
+        // U000a falls through into the next triad
+        assert!(callers(0x000c).contains(&address(0x000a)));
+        // U000d: UJMPCC_DIRECT_NOTTAKEN_CONDZ(tmp13, U3701)
+        assert!(callers(0x3701).contains(&address(0x000d)));
+        // U0052: UJMP( , U6099), the saved uip is no successor
+        assert!(callers(0x6099).contains(&address(0x0052)));
+        assert!(!callers(0x0054).contains(&address(0x0052)));
+
+        let rdrand = callers(crate::dump::cpu_000506CA::RDRAND_XLAT.address());
+        assert!(rdrand.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(callers(0x0003).is_empty());
+    }
+
+    #[test]
+    fn test_disassemble_range() {
+        let rom = ROM_cpu_000506CA;
+
+        let listing = rom.disassemble_range(UCInstructionAddress::from_const(0x0001), 2);
+        assert_eq!(
+            listing,
+            "U0000: 00626803f200 MOVEFROMCREG_DSZ64 \n\
+             U0001: 000801030008 ZEROEXT_DSZ32 SEQW GOTO U3452\n\
+             U0002: 004800013000 ZEROEXT_DSZ64 \n\
+             \n\
+             U0004: 05b900013000 UNKNOWN_5b9 \n\
+             U0005: 000a01000200 TESTUSTATE SEQW GOTO U0002\n\
+             U0006: 014800000000 SYNCWAIT-> URET \n"
+        );
+
+        // a sequence word with an invalid control index
+        let instructions: Box<[u64; 0x7c00]> = vec![0; 0x7c00].try_into().unwrap();
+        let mut sequences: Box<[u32; 0x7c00 / 4]> = vec![0; 0x7c00 / 4].try_into().unwrap();
+        sequences[1] = 0x3;
+        let dump = RomDump::new(&instructions, &sequences, 0);
+        assert_eq!(
+            dump.disassemble_range(UCInstructionAddress::from_const(0x4), 1),
+            "U0004: 000000000000 NOP \n\
+             U0005: 000000000000 NOP \n\
+             U0006: 000000000000 NOP .unk 00000003\n"
+        );
+
+        assert_eq!(
+            rom.disassemble_range(UCInstructionAddress::MSRAM_START, 1),
+            ""
+        );
+        assert_eq!(
+            rom.disassemble_range(UCInstructionAddress::ZERO, 3)
+                .lines()
+                .count(),
+            3 * 3 + 2
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "text_disassembly")]
+    fn test_disassemble_range_matches_text_disassembly() {
+        let rom = ROM_cpu_000506CA;
+
+        // the address column and sequence words agree with the embedded disassembly
+        let text = crate::dump::cpu_000506CA::DISSASSEMBLY;
+        for line in rom
+            .disassemble_range(UCInstructionAddress::ZERO, 0x100)
+            .lines()
+            .filter(|line| !line.is_empty())
+        {
+            let (address, _) = line.split_once(' ').unwrap();
+            let rom_line = text
+                .lines()
+                .find(|rom_line| rom_line.starts_with(address))
+                .unwrap();
+            assert_eq!(line[..19], rom_line[..19]);
+            assert_eq!(
+                line.split_once("SEQW").map(|(_, seqw)| seqw),
+                rom_line.split_once("SEQW").map(|(_, seqw)| seqw),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_triad_assemble_disassemble_round_trip() {
+        let rom = ROM_cpu_000506CA;
+        // every ROM triad decodes and assembles, none may be skipped
+        for address in (0..0x7c00).step_by(4) {
+            let triad = rom
+                .decoded_triad(UCInstructionAddress::from_const(address))
+                .and_then(Result::ok)
+                .unwrap_or_else(|| panic!("decoding failed @ {:04x}", address));
+            let assembled = triad
+                .assemble()
+                .unwrap_or_else(|err| panic!("assembling failed @ {:04x}: {:?}", address, err));
+
+            assert_eq!(
+                Triad::disassemble(assembled),
+                Ok(triad),
+                "round trip failed @ {:04x}",
+                address
+            );
+            assert_eq!(Triad::disassemble_no_crc_check(assembled), Ok(triad));
+        }
+
+        let assembled = rom
+            .decoded_triad(UCInstructionAddress::from_const(0x428))
+            .unwrap()
+            .unwrap()
+            .assemble()
+            .unwrap();
+
+        let mut broken = assembled;
+        broken[1] ^= 1 << 46;
+        assert_eq!(
+            Triad::disassemble(broken),
+            Err(DisassembleError::InvalidInstructionCRC(1, broken[1]))
+        );
+        assert!(Triad::disassemble_no_crc_check(broken).is_ok());
+
+        let mut broken = assembled;
+        broken[3] ^= 1 << 28;
+        let expected = (assembled[3] >> 28) as u32;
+        assert_eq!(
+            Triad::disassemble(broken),
+            Err(DisassembleError::CrcMismatch {
+                expected,
+                found: expected ^ 1,
+            })
+        );
+        assert!(Triad::disassemble_no_crc_check(broken).is_ok());
+    }
+
+    #[test]
+    fn test_sequence_word_disassemble_assemble_round_trip() {
+        for seqw in ROM_cpu_000506CA.sequence_words().iter() {
+            if *seqw == 0 {
+                continue;
+            }
+
+            let disasm = match SequenceWord::disassemble_no_crc_check(*seqw) {
+                Ok(disasm) => disasm,
+                Err(err) => panic!("Failed to disassemble: {:x} @ {:?}", seqw, err),
+            };
+            let asm = disasm.assemble_no_crc().unwrap();
+            assert_eq!(
+                *seqw, asm,
+                "DISASM -> ASM mismatch: {:x} -> {:x}",
+                seqw, asm
+            );
+        }
+    }
+
+    #[test]
+    fn test_rdrand_control_flow() {
+        let rom = ROM_cpu_000506CA;
+
+        let mut reachable = BTreeSet::new();
+        let mut exits = 0;
+        let mut queue = Vec::from([crate::dump::cpu_000506CA::RDRAND_XLAT]);
+        while let Some(address) = queue.pop() {
+            if !reachable.insert(address) {
+                continue;
+            }
+
+            let control_flow = rom
+                .decoded_triad(address)
+                .expect("reachable address is part of the ROM")
+                .expect("ROM sequence words are valid")
+                .control_flow(address);
+            if let ControlFlow::UnconditionalBranch { next: None, .. } = control_flow {
+                exits += 1;
+            }
+            assert_ne!(control_flow, ControlFlow::Unknown, "@ {}", address);
+            queue.extend(control_flow.successors());
+        }
+
+        // shared tail of rdrand and rdseed, ends with `SEQW UEND0` at U19d4
+        assert!(reachable.contains(&UCInstructionAddress::from_const(0x19c9)));
+        assert!(reachable.contains(&UCInstructionAddress::from_const(0x19d4)));
+        assert_eq!(
+            rom.decoded_triad(UCInstructionAddress::from_const(0x19d4))
+                .unwrap()
+                .unwrap()
+                .control_flow(UCInstructionAddress::from_const(0x19d4)),
+            ControlFlow::UnconditionalBranch {
+                prediction: ControlFlowBranchPrediction::NotPredicted,
+                next: None,
+            }
+        );
+        assert!(exits > 0);
+    }
 }
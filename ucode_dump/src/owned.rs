@@ -0,0 +1,288 @@
+//! # Runtime loaded dumps
+//!
+//! Loads ROM dumps at runtime, e.g. for CPUs that have no dump compiled into this crate.
+//!
+//! The binary format consists of
+//! * the magic bytes [`MAGIC`]
+//! * the CPU model identifier as little endian u32
+//! * 0x7c00 instructions as little endian u64
+//! * 0x1f00 sequence words as little endian u32
+
+use crate::RomDump;
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Magic bytes at the start of a binary ROM dump
+pub const MAGIC: [u8; 4] = *b"UCRD";
+
+/// Number of instructions in a dump
+const INSTRUCTION_COUNT: usize = 0x7c00;
+/// Number of sequence words in a dump
+const SEQUENCE_WORD_COUNT: usize = 0x7c00 / 4;
+/// Size of the header (magic bytes and model)
+const HEADER_SIZE: usize = MAGIC.len() + 4;
+
+/// Size of a binary ROM dump in bytes
+pub const DUMP_SIZE: usize = HEADER_SIZE + INSTRUCTION_COUNT * 8 + SEQUENCE_WORD_COUNT * 4;
+
+/// Errors that can occur when loading a ROM dump
+#[derive(Debug)]
+pub enum LoadError {
+    /// The file could not be read
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+    /// The data does not start with [`MAGIC`]
+    InvalidMagic([u8; 4]),
+    /// The data ends before the section is complete
+    Truncated {
+        /// The section that is incomplete
+        section: &'static str,
+        /// The expected size of the data in bytes
+        expected: usize,
+        /// The actual size of the data in bytes
+        found: usize,
+    },
+    /// The data is longer than a dump
+    TrailingData {
+        /// The expected size of the data in bytes
+        expected: usize,
+        /// The actual size of the data in bytes
+        found: usize,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            LoadError::Io(err) => write!(f, "failed to read dump: {}", err),
+            LoadError::InvalidMagic(magic) => write!(
+                f,
+                "invalid magic bytes {:02x?}, expected {:02x?}",
+                magic, MAGIC
+            ),
+            LoadError::Truncated {
+                section,
+                expected,
+                found,
+            } => write!(
+                f,
+                "dump is truncated in the {}: expected at least {} bytes, found {}",
+                section, expected, found
+            ),
+            LoadError::TrailingData { expected, found } => write!(
+                f,
+                "dump has trailing data: expected {} bytes, found {}",
+                expected, found
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LoadError {}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for LoadError {
+    fn from(err: std::io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
+
+/// A ROM dump that owns its instructions and sequence words
+///
+/// Use [`OwnedRomDump::dump`] to analyze it like the dumps compiled into this crate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedRomDump {
+    instructions: Box<[u64; INSTRUCTION_COUNT]>,
+    sequences: Box<[u32; SEQUENCE_WORD_COUNT]>,
+    model: u32,
+}
+
+impl OwnedRomDump {
+    /// Parses a ROM dump from its binary format
+    ///
+    /// # Arguments
+    /// * `data` - The binary dump, see the [module documentation](self)
+    ///
+    /// # Returns
+    /// * `Result<OwnedRomDump, LoadError>` - The dump, or the reason the data is malformed
+    pub fn from_bytes(data: &[u8]) -> Result<Self, LoadError> {
+        let check_section = |section: &'static str, end: usize| {
+            if data.len() < end {
+                Err(LoadError::Truncated {
+                    section,
+                    expected: end,
+                    found: data.len(),
+                })
+            } else {
+                Ok(())
+            }
+        };
+
+        check_section("header", HEADER_SIZE)?;
+        let magic: [u8; 4] = data[..4].try_into().unwrap();
+        if magic != MAGIC {
+            return Err(LoadError::InvalidMagic(magic));
+        }
+        let model = u32::from_le_bytes(data[4..HEADER_SIZE].try_into().unwrap());
+
+        let instructions_end = HEADER_SIZE + INSTRUCTION_COUNT * 8;
+        check_section("instructions", instructions_end)?;
+        check_section("sequence words", DUMP_SIZE)?;
+        if data.len() > DUMP_SIZE {
+            return Err(LoadError::TrailingData {
+                expected: DUMP_SIZE,
+                found: data.len(),
+            });
+        }
+
+        let mut instructions: Box<[u64; INSTRUCTION_COUNT]> =
+            vec![0; INSTRUCTION_COUNT].try_into().unwrap();
+        for (index, instruction) in instructions.iter_mut().enumerate() {
+            let offset = HEADER_SIZE + index * 8;
+            *instruction = u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+        }
+
+        let mut sequences: Box<[u32; SEQUENCE_WORD_COUNT]> =
+            vec![0; SEQUENCE_WORD_COUNT].try_into().unwrap();
+        for (index, sequence_word) in sequences.iter_mut().enumerate() {
+            let offset = instructions_end + index * 4;
+            *sequence_word = u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+        }
+
+        Ok(OwnedRomDump {
+            instructions,
+            sequences,
+            model,
+        })
+    }
+
+    /// Returns a view on the dump for analysis
+    pub fn dump(&self) -> RomDump<'_, '_> {
+        RomDump::new(&self.instructions, &self.sequences, self.model)
+    }
+
+    /// Returns the CPU model identifier
+    pub fn model(&self) -> u32 {
+        self.model
+    }
+}
+
+impl RomDump<'_, '_> {
+    /// Serializes the dump to the binary format read by [`OwnedRomDump::from_bytes`]
+    ///
+    /// # Returns
+    /// * `Vec<u8>` - The binary dump
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(DUMP_SIZE);
+        result.extend_from_slice(&MAGIC);
+        result.extend_from_slice(&self.model().to_le_bytes());
+        for instruction in self.instructions() {
+            result.extend_from_slice(&instruction.to_le_bytes());
+        }
+        for sequence_word in self.sequence_words() {
+            result.extend_from_slice(&sequence_word.to_le_bytes());
+        }
+        result
+    }
+
+    /// Loads a ROM dump from a file in the binary format
+    ///
+    /// # Arguments
+    /// * `path` - The path of the file, see [`RomDump::to_bytes`] for creating it
+    ///
+    /// # Returns
+    /// * `Result<OwnedRomDump, LoadError>` - The dump, or the reason it could not be loaded
+    #[cfg(feature = "std")]
+    pub fn load_from_file<P: AsRef<std::path::Path>>(path: P) -> Result<OwnedRomDump, LoadError> {
+        OwnedRomDump::from_bytes(&std::fs::read(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dump::ROM_cpu_000506CA;
+    use crate::owned::{LoadError, OwnedRomDump, DUMP_SIZE, HEADER_SIZE, MAGIC};
+    use data_types::addresses::UCInstructionAddress;
+    extern crate std;
+
+    #[test]
+    fn test_from_bytes() {
+        let bytes = ROM_cpu_000506CA.to_bytes();
+        assert_eq!(bytes.len(), DUMP_SIZE);
+
+        let owned = OwnedRomDump::from_bytes(&bytes).unwrap();
+        let dump = owned.dump();
+        assert_eq!(owned.model(), 0x506CA);
+        assert_eq!(dump.model(), ROM_cpu_000506CA.model());
+        assert_eq!(dump.instructions(), ROM_cpu_000506CA.instructions());
+        assert_eq!(dump.sequence_words(), ROM_cpu_000506CA.sequence_words());
+        assert_eq!(
+            dump.triad(UCInstructionAddress::from_const(0x428)),
+            ROM_cpu_000506CA.triad(UCInstructionAddress::from_const(0x428))
+        );
+        assert!(crate::diff(&dump, &ROM_cpu_000506CA).is_empty());
+    }
+
+    #[test]
+    fn test_from_bytes_errors() {
+        let bytes = ROM_cpu_000506CA.to_bytes();
+
+        let mut broken = bytes.clone();
+        broken[0] = b'X';
+        assert!(matches!(
+            OwnedRomDump::from_bytes(&broken),
+            Err(LoadError::InvalidMagic([b'X', b'C', b'R', b'D']))
+        ));
+
+        assert!(matches!(
+            OwnedRomDump::from_bytes(&MAGIC),
+            Err(LoadError::Truncated {
+                section: "header",
+                expected: HEADER_SIZE,
+                found: 4
+            })
+        ));
+        assert!(matches!(
+            OwnedRomDump::from_bytes(&bytes[..0x100]),
+            Err(LoadError::Truncated {
+                section: "instructions",
+                found: 0x100,
+                ..
+            })
+        ));
+        assert!(matches!(
+            OwnedRomDump::from_bytes(&bytes[..DUMP_SIZE - 1]),
+            Err(LoadError::Truncated {
+                section: "sequence words",
+                expected: DUMP_SIZE,
+                found,
+            }) if found == DUMP_SIZE - 1
+        ));
+
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert!(matches!(
+            OwnedRomDump::from_bytes(&longer),
+            Err(LoadError::TrailingData { expected: DUMP_SIZE, found }) if found == DUMP_SIZE + 1
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_load_from_file() {
+        let path = std::env::temp_dir().join("ucode_dump_test_load_from_file.bin");
+        std::fs::write(&path, ROM_cpu_000506CA.to_bytes()).unwrap();
+        let owned = crate::RomDump::load_from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(owned.dump().instructions(), ROM_cpu_000506CA.instructions());
+
+        assert!(matches!(
+            crate::RomDump::load_from_file(&path),
+            Err(LoadError::Io(_))
+        ));
+    }
+}
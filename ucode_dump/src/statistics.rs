@@ -0,0 +1,145 @@
+//! # ROM statistics
+//!
+//! Counts opcodes and the occupancy of ROM regions, e.g. to find rarely used opcodes
+//! or densely populated regions of the MSROM.
+
+use crate::RomDump;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use data_types::addresses::UCInstructionAddress;
+use ucode_compiler_dynamic::instruction::Instruction;
+
+/// Instruction counts of a region of the ROM, see [`RomDump::region_statistics`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RegionStatistics {
+    /// Address of the first instruction of the region
+    pub start: UCInstructionAddress,
+    /// Number of instructions in the region, the unused fourth slot of each triad is not counted
+    pub instructions: usize,
+    /// Number of NOP instructions in the region
+    pub nops: usize,
+}
+
+impl RegionStatistics {
+    /// Returns the fraction of NOP instructions in the region (0.0-1.0)
+    pub fn nop_density(&self) -> f64 {
+        if self.instructions == 0 {
+            0.0
+        } else {
+            self.nops as f64 / self.instructions as f64
+        }
+    }
+}
+
+impl RomDump<'_, '_> {
+    /// Counts the occurrences of each opcode
+    ///
+    /// The unused fourth slot of each triad is skipped.
+    ///
+    /// # Returns
+    /// * `BTreeMap<u16, u32>` - The number of instructions per raw opcode value, see [`RomDump::find_opcode`]
+    pub fn opcode_histogram(&self) -> BTreeMap<u16, u32> {
+        let mut histogram = BTreeMap::new();
+        for (_, triad) in self.iter_triads() {
+            for instruction in triad.instructions {
+                *histogram.entry(raw_opcode(instruction)).or_insert(0) += 1;
+            }
+        }
+        histogram
+    }
+
+    /// Counts instructions and NOPs in consecutive regions of the ROM
+    ///
+    /// # Arguments
+    /// * `chunk` - The size of each region in addresses, rounded up to whole triads
+    ///
+    /// # Returns
+    /// * `Vec<RegionStatistics>` - The statistics of each region in ascending address order
+    ///
+    /// # Panics
+    /// Panics if `chunk` is zero
+    pub fn region_statistics(&self, chunk: usize) -> Vec<RegionStatistics> {
+        assert!(chunk > 0, "region size must not be zero");
+        let triads_per_chunk = chunk.div_ceil(4);
+
+        let triads = self.iter_triads().collect::<Vec<_>>();
+        triads
+            .chunks(triads_per_chunk)
+            .map(|region| RegionStatistics {
+                start: region[0].0,
+                instructions: region.len() * 3,
+                nops: region
+                    .iter()
+                    .flat_map(|(_, triad)| triad.instructions)
+                    .filter(|instruction| *instruction == Instruction::NOP.assemble())
+                    .count(),
+            })
+            .collect()
+    }
+}
+
+/// Extracts the raw opcode field of an instruction
+///
+/// # Arguments
+/// * `instruction` - The assembled instruction
+///
+/// # Returns
+/// * `u16` - The raw opcode value
+pub(crate) fn raw_opcode(instruction: u64) -> u16 {
+    ((instruction >> 32) & 0xfff) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dump::ROM_cpu_000506C9;
+    use alloc::vec::Vec;
+    use data_types::addresses::UCInstructionAddress;
+
+    #[test]
+    fn test_opcode_histogram() {
+        let rom = ROM_cpu_000506C9;
+        let histogram = rom.opcode_histogram();
+
+        assert_eq!(histogram.values().sum::<u32>(), 0x7c00 / 4 * 3);
+        assert_eq!(histogram.len(), 618);
+        assert_eq!(histogram.get(&0x008), Some(&1588));
+        assert_eq!(histogram.get(&0x000), Some(&1565));
+        assert_eq!(histogram.get(&0x062), Some(&1259));
+        assert_eq!(histogram.get(&0xfcc), Some(&1));
+        for opcode in [0x000, 0x048, 0x00c] {
+            assert_eq!(
+                histogram.get(&opcode).copied().unwrap_or(0) as usize,
+                rom.find_opcode(opcode).len()
+            );
+        }
+    }
+
+    #[test]
+    fn test_region_statistics() {
+        let rom = ROM_cpu_000506C9;
+
+        let regions = rom.region_statistics(0x400);
+        assert_eq!(regions.len(), 0x7c00 / 0x400);
+        assert_eq!(regions[1].start, UCInstructionAddress::from_const(0x400));
+        assert!(regions.iter().all(|region| region.instructions == 0x300));
+        assert!(regions
+            .iter()
+            .all(|region| (0.0..=1.0).contains(&region.nop_density())));
+
+        // regions that are not a multiple of a triad are rounded up
+        let regions = rom.region_statistics(9);
+        assert_eq!(regions[1].start, UCInstructionAddress::from_const(0xc));
+        assert_eq!(regions.last().unwrap().instructions, 3);
+
+        let total = rom.region_statistics(0x7c00);
+        assert_eq!(total.len(), 1);
+        assert_eq!(total[0].instructions, 23808);
+        assert_eq!(total[0].nops, 1217);
+
+        let regions = rom.region_statistics(0x1000);
+        assert_eq!(
+            regions.iter().map(|region| region.nops).collect::<Vec<_>>(),
+            [73, 5, 94, 173, 186, 10, 7, 669]
+        );
+    }
+}
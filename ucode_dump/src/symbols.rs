@@ -0,0 +1,135 @@
+//! # Symbol tables
+//!
+//! Maps names of known microcode entry points, e.g. `RDRAND_XLAT`, to their addresses,
+//! independent of the CPU model. See [`crate::RomDump::symbols`].
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use data_types::addresses::UCInstructionAddress;
+
+/// Name to address lookup of microcode entry points
+///
+/// Consists of the static symbols of a ROM dump, extended by user provided symbols.
+/// User provided symbols take precedence over static ones with the same name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    /// Symbols known for the ROM dump
+    builtin: &'static [(&'static str, UCInstructionAddress)],
+    /// Symbols added at runtime
+    user: BTreeMap<String, UCInstructionAddress>,
+}
+
+impl SymbolTable {
+    /// Creates a new symbol table
+    ///
+    /// # Arguments
+    /// * `builtin` - Static symbols, pairs of name and address
+    pub const fn new(builtin: &'static [(&'static str, UCInstructionAddress)]) -> Self {
+        SymbolTable {
+            builtin,
+            user: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a user provided symbol, replacing any symbol with the same name
+    ///
+    /// # Arguments
+    /// * `name` - The name of the symbol
+    /// * `address` - The address of the symbol
+    pub fn insert<S: Into<String>>(&mut self, name: S, address: UCInstructionAddress) {
+        self.user.insert(name.into(), address);
+    }
+
+    /// Looks up the address of a symbol
+    ///
+    /// # Arguments
+    /// * `name` - The name of the symbol, e.g. `RDRAND_XLAT`
+    ///
+    /// # Returns
+    /// * `Option<UCInstructionAddress>` - The address of the symbol, None if the name is unknown
+    pub fn address(&self, name: &str) -> Option<UCInstructionAddress> {
+        self.user.get(name).copied().or_else(|| {
+            self.builtin
+                .iter()
+                .find(|(symbol, _)| *symbol == name)
+                .map(|(_, address)| *address)
+        })
+    }
+
+    /// Looks up the symbol at an address
+    ///
+    /// # Arguments
+    /// * `address` - The address of the symbol
+    ///
+    /// # Returns
+    /// * `Option<&str>` - The name of a symbol at this address, None if there is none
+    pub fn symbol_for(&self, address: UCInstructionAddress) -> Option<&str> {
+        self.iter()
+            .find(|(_, symbol_address)| *symbol_address == address)
+            .map(|(name, _)| name)
+    }
+
+    /// Iterates over all symbols, user provided symbols first
+    ///
+    /// # Returns
+    /// * `impl Iterator<Item = (&str, UCInstructionAddress)>` - Pairs of name and address
+    pub fn iter(&self) -> impl Iterator<Item = (&str, UCInstructionAddress)> {
+        self.user
+            .iter()
+            .map(|(name, address)| (name.as_str(), *address))
+            .chain(
+                self.builtin
+                    .iter()
+                    .filter(|(name, _)| !self.user.contains_key(*name))
+                    .map(|(name, address)| (*name, *address)),
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dump::{cpu_000506C9, cpu_000506CA, ROM_cpu_000506C9, ROM_cpu_000506CA};
+    use data_types::addresses::UCInstructionAddress;
+
+    #[test]
+    fn test_symbols() {
+        for (rom, rdrand, rdseed) in [
+            (
+                ROM_cpu_000506CA,
+                cpu_000506CA::RDRAND_XLAT,
+                cpu_000506CA::RDSEED_XLAT,
+            ),
+            (
+                ROM_cpu_000506C9,
+                cpu_000506C9::RDRAND_XLAT,
+                cpu_000506C9::RDSEED_XLAT,
+            ),
+        ] {
+            let symbols = rom.symbols();
+            assert_eq!(symbols.address("RDRAND_XLAT"), Some(rdrand));
+            assert_eq!(symbols.address("RDSEED_XLAT"), Some(rdseed));
+            assert_eq!(symbols.symbol_for(rdrand), Some("RDRAND_XLAT"));
+            assert_eq!(symbols.address("rdrand_xlat"), None);
+            assert_eq!(symbols.address("UNKNOWN_XLAT"), None);
+            assert_eq!(symbols.symbol_for(UCInstructionAddress::MSRAM_START), None);
+            assert_eq!(symbols.iter().count(), cpu_000506CA::SYMBOLS.len());
+        }
+
+        let mut symbols = ROM_cpu_000506CA.symbols();
+        symbols.insert("EXPERIMENT", UCInstructionAddress::MSRAM_START);
+        assert_eq!(
+            symbols.address("EXPERIMENT"),
+            Some(UCInstructionAddress::MSRAM_START)
+        );
+        assert_eq!(
+            symbols.symbol_for(UCInstructionAddress::MSRAM_START),
+            Some("EXPERIMENT")
+        );
+
+        let moved = UCInstructionAddress::from_const(0x1234);
+        symbols.insert("RDRAND_XLAT", moved);
+        assert_eq!(symbols.address("RDRAND_XLAT"), Some(moved));
+        assert_eq!(symbols.symbol_for(cpu_000506CA::RDRAND_XLAT), None);
+        assert_eq!(symbols.iter().count(), cpu_000506CA::SYMBOLS.len() + 1);
+    }
+}
@@ -16,7 +16,7 @@ extern crate core;
 /// This struct contains the fields needed to configure a performance counter
 ///
 /// For details on the fields, see the Intel manual.
-#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Ord, PartialOrd)]
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
 pub struct PerfEventSpecifier {
     /// Event select
     pub event_select: u8,
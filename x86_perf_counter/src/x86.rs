@@ -8,7 +8,9 @@ use crate::PerfEventSpecifier;
 use bitfield::bitfield;
 use core::arch::asm;
 use x86::cpuid::CpuId;
-use x86::msr::{wrmsr, IA32_PERFEVTSEL0, IA32_PMC0};
+use x86::msr::{
+    rdmsr, wrmsr, IA32_PERFEVTSEL0, IA32_PERF_GLOBAL_OVF_CTRL, IA32_PERF_GLOBAL_STAUS, IA32_PMC0,
+};
 
 bitfield! {
     /// Performance Event Select Register
@@ -297,6 +299,20 @@ impl PerformanceCounter {
         unsafe { write_pmc(self.index, value) }
     }
 
+    /// Checks whether this performance counter overflowed since its overflow status was cleared
+    ///
+    /// # Returns
+    ///
+    /// Returns the overflow bit of this counter in IA32_PERF_GLOBAL_STATUS
+    pub fn overflowed(&self) -> bool {
+        unsafe { rdmsr(IA32_PERF_GLOBAL_STAUS) & (1 << self.index) != 0 }
+    }
+
+    /// Clears the overflow status of this performance counter
+    pub fn clear_overflow(&mut self) {
+        unsafe { wrmsr(IA32_PERF_GLOBAL_OVF_CTRL, 1 << self.index) }
+    }
+
     /// Resets this performance counter
     ///
     /// This function disables the counter and sets its value to 0.
@@ -14,6 +14,8 @@ use std::{
 pub(crate) struct Bochs {
     pub(crate) cpu: Cpu,
     pub(crate) port: Option<u16>,
+    /// CPUID signature (leaf 1 eax) to report instead of the one of the CPU model
+    pub(crate) cpuid: Option<u32>,
 }
 
 pub struct BochsEnviroment {
@@ -24,11 +26,12 @@ pub struct BochsEnviroment {
 impl VM for Bochs {
     type T = BochsEnviroment;
 
-    fn deploy<A: AsRef<Path>, B: AsRef<Path>, C: AsRef<Path>>(
+    fn deploy<A: AsRef<Path>, B: AsRef<Path>>(
         &self,
         working_directory: A,
         config_directory: B,
-        build_output: C,
+        source: &DiskSource,
+        extra_files: &[PathBuf],
     ) -> Result<BochsEnviroment, DynError> {
         // copy disk to working directory
 
@@ -51,74 +54,7 @@ impl VM for Bochs {
             }
         }
 
-        if !Command::new("dd")
-            .args([
-                "if=/dev/zero",
-                &format!("of={}", disk.to_str().unwrap()),
-                "bs=1M",
-                "count=64",
-            ])
-            .status()
-            .map_err(|_| "dd failed")?
-            .success()
-        {
-            return Err("dd failed".into());
-        }
-
-        // Create a GPT partition table and a FAT32 partition
-        /*if !Command::new("parted")
-            .args([disk.to_str().unwrap(), "--script", "mktable", "gpt", "mkpart", "primary", "fat32", "2048s", "100%", "align-check", "optimal", "1", "name", "1", "UEFI", "quit"])
-            .status()
-            .map_err(|_| "parted failed")?
-            .success() {
-            return Err("parted failed".into());
-        }*/
-
-        // Format the partition as FAT32
-        if !Command::new("mkfs.fat")
-            .args([disk.to_str().unwrap(), "-F", "32"])
-            .status()
-            .map_err(|e| {
-                eprintln!("mkfs.vfat failed {e:?}");
-                e
-            })?
-            .success()
-        {
-            return Err("mkfs.vfat failed".into());
-        }
-
-        // Copy startup.nsh to the FAT32 partition
-        if !Command::new("mcopy")
-            .args([
-                "-i",
-                disk.to_str().unwrap(),
-                &format!(
-                    "{}/startup.nsh",
-                    config_directory.as_ref().to_str().unwrap()
-                ),
-                "::",
-            ])
-            .status()
-            .map_err(|_| "mcopy startup.nsh failed")?
-            .success()
-        {
-            return Err("mcopy startup.nsh failed".into());
-        }
-
-        // Copy hypervisor.efi to the FAT32 partition
-        if !Command::new("mcopy")
-            .args([
-                "-i",
-                disk.to_str().unwrap(),
-                build_output.as_ref().to_str().unwrap(),
-                "::app.efi",
-            ])
-            .status()
-            .map_err(|_| "mcopy app.efi failed")?
-            .success()
-        {
-            return Err("mcopy app.efi failed".into());
-        }
+        prepare_disk(&disk, &config_directory, source, extra_files)?;
 
         let port = self.port.unwrap_or(rand::rng().random_range(1024..65535));
 
@@ -126,16 +62,13 @@ impl VM for Bochs {
             .as_ref()
             .join(format!("{}.bxrc", env::consts::OS));
         let data = std::fs::read_to_string(data).map_err(|_| "read bxrc failed")?;
-        let data = data
-            .replace(
-                "{CPU}",
-                match self.cpu.to_string().to_lowercase().as_str() {
-                    "intel" => "tigerlake",
-                    "amd" => "ryzen",
-                    _ => panic!("unknown cpu"),
-                },
-            )
-            .replace("{PORT}", port.to_string().as_str());
+        if let Some(cpuid) = self.cpuid {
+            println!(
+                "CPUID {cpuid:#x} given, emulating the generic bochs CPU model instead of the {} one",
+                self.cpu
+            );
+        }
+        let data = bochs_config(&data, &self.cpu, port, self.cpuid);
 
         let config = working_directory.as_ref().join("config.bxrc");
 
@@ -255,6 +188,197 @@ impl VM for Bochs {
     }
 }
 
+/// Content of the boot disk of the emulator
+pub(crate) enum DiskSource {
+    /// Fresh FAT image containing `startup.nsh` and the given UEFI application as `app.efi`
+    App(PathBuf),
+    /// Existing FAT image, copied as-is
+    Image(PathBuf),
+}
+
+/// Creates the boot disk of the emulator and injects additional files into it
+///
+/// # Arguments
+///
+/// * `disk` - Path of the disk image to create
+/// * `config_directory` - Directory containing `startup.nsh`
+/// * `source` - Content of the disk
+/// * `extra_files` - Files to copy into the root directory of the disk
+pub(crate) fn prepare_disk<A: AsRef<Path>, B: AsRef<Path>>(
+    disk: A,
+    config_directory: B,
+    source: &DiskSource,
+    extra_files: &[PathBuf],
+) -> Result<(), DynError> {
+    let disk = disk.as_ref();
+
+    match source {
+        DiskSource::Image(image) => {
+            std::fs::copy(image, disk).map_err(|e| format!("copy image {image:?} failed: {e}"))?;
+        }
+        DiskSource::App(app) => {
+            if !Command::new("dd")
+                .args([
+                    "if=/dev/zero",
+                    &format!("of={}", disk.to_str().unwrap()),
+                    "bs=1M",
+                    "count=64",
+                ])
+                .status()
+                .map_err(|_| "dd failed")?
+                .success()
+            {
+                return Err("dd failed".into());
+            }
+
+            // Create a GPT partition table and a FAT32 partition
+            /*if !Command::new("parted")
+                .args([disk.to_str().unwrap(), "--script", "mktable", "gpt", "mkpart", "primary", "fat32", "2048s", "100%", "align-check", "optimal", "1", "name", "1", "UEFI", "quit"])
+                .status()
+                .map_err(|_| "parted failed")?
+                .success() {
+                return Err("parted failed".into());
+            }*/
+
+            // Format the partition as FAT32
+            if !Command::new("mkfs.fat")
+                .args([disk.to_str().unwrap(), "-F", "32"])
+                .status()
+                .map_err(|e| {
+                    eprintln!("mkfs.vfat failed {e:?}");
+                    e
+                })?
+                .success()
+            {
+                return Err("mkfs.vfat failed".into());
+            }
+
+            // Copy startup.nsh to the FAT32 partition
+            if !Command::new("mcopy")
+                .args([
+                    "-i",
+                    disk.to_str().unwrap(),
+                    &format!(
+                        "{}/startup.nsh",
+                        config_directory.as_ref().to_str().unwrap()
+                    ),
+                    "::",
+                ])
+                .status()
+                .map_err(|_| "mcopy startup.nsh failed")?
+                .success()
+            {
+                return Err("mcopy startup.nsh failed".into());
+            }
+
+            // Copy hypervisor.efi to the FAT32 partition
+            if !Command::new("mcopy")
+                .args([
+                    "-i",
+                    disk.to_str().unwrap(),
+                    app.to_str().unwrap(),
+                    "::app.efi",
+                ])
+                .status()
+                .map_err(|_| "mcopy app.efi failed")?
+                .success()
+            {
+                return Err("mcopy app.efi failed".into());
+            }
+        }
+    }
+
+    for file in extra_files {
+        let name = file
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| format!("invalid extra file {file:?}"))?;
+        if !Command::new("mcopy")
+            .args([
+                "-o",
+                "-i",
+                disk.to_str().unwrap(),
+                file.to_str().unwrap(),
+                &format!("::{name}"),
+            ])
+            .status()
+            .map_err(|_| "mcopy extra file failed")?
+            .success()
+        {
+            return Err(format!("mcopy {file:?} failed").into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Fills the placeholders of a Bochs configuration template
+///
+/// # Arguments
+///
+/// * `template` - Configuration template containing `{CPU}` and `{PORT}`
+/// * `cpu` - CPU model to emulate
+/// * `port` - Port of the serial console
+/// * `cpuid` - CPUID signature overriding the CPU model, e.g. `GLM_OLD`
+///
+/// Bochs ignores the `cpuid` options for all predefined models except the generic `bochs`
+/// model. Therefore, if a CPUID signature is given, the predefined model of `cpu`
+/// (`tigerlake` or `ryzen`) is replaced by the `bochs` model reporting this signature.
+pub(crate) fn bochs_config(template: &str, cpu: &Cpu, port: u16, cpuid: Option<u32>) -> String {
+    let model = match (cpuid, cpu) {
+        (Some(_), _) => "bochs",
+        (None, Cpu::Intel) => "tigerlake",
+        (None, Cpu::Amd) => "ryzen",
+    };
+    let mut data = template
+        .replace("{CPU}", model)
+        .replace("{PORT}", port.to_string().as_str());
+
+    if let Some(signature) = cpuid {
+        let (family, model, stepping) = cpuid_signature(signature);
+        data.push_str(
+            format!("cpuid: level=6, family={family}, model=0x{model:x}, stepping={stepping}\n")
+                .as_str(),
+        );
+    }
+
+    data
+}
+
+/// Splits a CPUID signature (leaf 1 eax) into the displayed family, model and stepping
+pub(crate) fn cpuid_signature(signature: u32) -> (u32, u32, u32) {
+    let stepping = signature & 0xf;
+    let base_model = (signature >> 4) & 0xf;
+    let base_family = (signature >> 8) & 0xf;
+    let extended_model = (signature >> 16) & 0xf;
+    let extended_family = (signature >> 20) & 0xff;
+
+    let family = if base_family == 0xf {
+        base_family + extended_family
+    } else {
+        base_family
+    };
+    let model = if base_family == 0x6 || base_family == 0xf {
+        (extended_model << 4) | base_model
+    } else {
+        base_model
+    };
+
+    (family, model, stepping)
+}
+
+/// Parses a CPUID signature given as `glm-old`, `glm-new` or hexadecimal number
+pub(crate) fn parse_cpuid(value: &str) -> Result<u32, String> {
+    match value.to_lowercase().as_str() {
+        // custom_processing_unit::GLM_OLD
+        "glm-old" | "glm_old" => Ok(0x506c9),
+        // custom_processing_unit::GLM_NEW
+        "glm-new" | "glm_new" => Ok(0x506ca),
+        other => u32::from_str_radix(other.trim_start_matches("0x"), 16)
+            .map_err(|e| format!("invalid cpuid {value:?}: {e}")),
+    }
+}
+
 #[derive(Debug)]
 pub(crate) enum Cpu {
     Intel,
@@ -265,3 +389,72 @@ impl fmt::Display for Cpu {
         fmt::Debug::fmt(self, f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::bochs::{bochs_config, cpuid_signature, parse_cpuid, prepare_disk, Cpu, DiskSource};
+    use std::fs;
+    use std::process::Command;
+
+    #[test]
+    #[ignore = "requires mkfs.fat and mtools"]
+    fn test_prepared_image_contains_extra_file() {
+        let folder = std::env::temp_dir().join("xtask_test_prepared_image");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+
+        let image = folder.join("prepared.img");
+        let disk = folder.join("bochs_disk.img");
+        let marker = folder.join("marker.txt");
+        fs::write(&marker, "fixture marker").unwrap();
+
+        assert!(Command::new("mkfs.fat")
+            .args(["-C", image.to_str().unwrap(), "-F", "32", "65536"])
+            .status()
+            .unwrap()
+            .success());
+
+        prepare_disk(
+            &disk,
+            &folder,
+            &DiskSource::Image(image.clone()),
+            &[marker.clone()],
+        )
+        .unwrap();
+
+        let listing = Command::new("mtype")
+            .args(["-i", disk.to_str().unwrap(), "::marker.txt"])
+            .output()
+            .unwrap();
+        // the prepared image stays untouched
+        let original = Command::new("mtype")
+            .args(["-i", image.to_str().unwrap(), "::marker.txt"])
+            .output()
+            .unwrap();
+        let _ = fs::remove_dir_all(&folder);
+
+        assert_eq!(String::from_utf8_lossy(&listing.stdout), "fixture marker");
+        assert!(!original.status.success());
+    }
+
+    #[test]
+    fn test_config_contains_cpuid() {
+        let template = "cpu: count=1, model={CPU}\ncom1: dev=\"localhost:{PORT}\"\n";
+
+        let glm_old = parse_cpuid("glm-old").unwrap();
+        assert_eq!(cpuid_signature(glm_old), (6, 0x5c, 9));
+        assert_eq!(
+            cpuid_signature(parse_cpuid("0x506ca").unwrap()),
+            (6, 0x5c, 10)
+        );
+
+        let config = bochs_config(template, &Cpu::Intel, 4444, Some(glm_old));
+        assert!(config.contains("model=bochs"));
+        assert!(config.contains("localhost:4444"));
+        assert!(config.contains("cpuid: level=6, family=6, model=0x5c, stepping=9"));
+
+        let config = bochs_config(template, &Cpu::Intel, 4444, None);
+        assert!(config.contains("model=tigerlake"));
+        assert!(!config.contains("cpuid:"));
+    }
+}
@@ -6,7 +6,7 @@
 
 #![allow(clippy::multiple_crate_versions)]
 
-use bochs::{Bochs, Cpu};
+use bochs::{parse_cpuid, Bochs, Cpu, DiskSource};
 use clap::{command, Parser, Subcommand};
 use std::io::Write;
 use std::process::Stdio;
@@ -57,6 +57,8 @@ enum Cli {
     UpdateNode,
     /// Generate documentation
     Doc,
+    /// Check that the documentation build rejects broken intra-doc links
+    CheckDoc,
     /// Compile all examples and subprojects
     Check,
 }
@@ -71,8 +73,23 @@ struct EmulateCli {
     #[arg(short, long)]
     port: Option<u16>,
 
-    /// The project name
-    project: String,
+    /// The project name, not required if an image is given
+    #[arg(required_unless_present = "image")]
+    project: Option<String>,
+
+    /// Boot an existing FAT disk image instead of building the project
+    #[arg(short, long)]
+    image: Option<PathBuf>,
+
+    /// Additional file to inject into the root directory of the disk (repeatable)
+    #[arg(short, long)]
+    extra_file: Vec<PathBuf>,
+
+    /// CPUID signature to emulate: `glm-old`, `glm-new` or a hexadecimal leaf 1 eax value.
+    /// Replaces the CPU model of the subcommand with the generic bochs model, since bochs
+    /// applies the signature only to this model
+    #[arg(long, value_parser = parse_cpuid)]
+    cpuid: Option<u32>,
 
     #[arg(short, long, default_value = "false")]
     ctrlc: bool,
@@ -111,6 +128,7 @@ fn main() {
         ),
         Cli::ControlRemote { args } => main_control_remote(args),
         Cli::Doc => main_generate_doc(),
+        Cli::CheckDoc => main_check_doc(),
         Cli::Check => main_compile_all(),
     }
 }
@@ -312,19 +330,17 @@ fn main_run(cli: EmulateCli) {
             Bochs {
                 cpu: Cpu::Intel,
                 port: cli.port,
+                cpuid: cli.cpuid,
             },
-            cli.release,
-            &cli.project,
-            !cli.ctrlc,
+            &cli,
         ),
         Commands::BochsAmd => start_vm(
             Bochs {
                 cpu: Cpu::Amd,
                 port: cli.port,
+                cpuid: cli.cpuid,
             },
-            cli.release,
-            &cli.project,
-            !cli.ctrlc,
+            &cli,
         ),
     };
     if let Err(e) = result {
@@ -335,20 +351,31 @@ fn main_run(cli: EmulateCli) {
 
 trait VM {
     type T;
-    fn deploy<A: AsRef<Path>, B: AsRef<Path>, C: AsRef<Path>>(
+    fn deploy<A: AsRef<Path>, B: AsRef<Path>>(
         &self,
         working_directory: A,
         config_directory: B,
-        build_output: C,
+        source: &DiskSource,
+        extra_files: &[PathBuf],
     ) -> Result<Self::T, DynError>;
     fn run(&self, environment: Self::T, ctrlc: bool) -> Result<(), DynError>;
 }
 
-fn start_vm<T: VM>(vm: T, release: bool, project: &str, ctrlc: bool) -> Result<(), DynError> {
-    let build_output = build_app(project, release, false)?;
+fn start_vm<T: VM>(vm: T, cli: &EmulateCli) -> Result<(), DynError> {
+    let source = match (&cli.image, &cli.project) {
+        (Some(image), _) => DiskSource::Image(image.clone()),
+        (None, Some(project)) => DiskSource::App(build_app(project, cli.release, false)?),
+        (None, None) => Err("Either a project or an image is required")?,
+    };
     let project_root = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
 
-    let env = vm.deploy("/tmp", project_root.join("config"), build_output)?;
+    let env = vm.deploy(
+        "/tmp",
+        project_root.join("config"),
+        &source,
+        &cli.extra_file,
+    )?;
+    let ctrlc = !cli.ctrlc;
     vm.run(env, ctrlc)?;
 
     Ok(())
@@ -627,23 +654,39 @@ fn main_compile_all() {
     }
 }
 
+/// Rustdoc flags for the documentation build, broken intra-doc links (also to other
+/// workspace crates) fail the build
+const RUSTDOCFLAGS: &str = "-D rustdoc::broken_intra_doc_links";
+
+/// Creates the cargo command used to build the documentation of a single project
+///
+/// Private items are documented as well and all projects share the `target/doc`
+/// directory, so that links to items of other workspace crates resolve.
+fn doc_command(target: Option<&str>, safe: bool, extra_rustdoc_flags: &str) -> Command {
+    let mut cmd = Command::new("cargo");
+    cmd.arg("doc")
+        .env("RUSTFLAGS", "-D warnings")
+        .env(
+            "RUSTDOCFLAGS",
+            format!("{RUSTDOCFLAGS} {extra_rustdoc_flags}").trim(),
+        )
+        .arg("--locked")
+        .arg("--no-deps")
+        .arg("--document-private-items");
+    if let Some(target) = target {
+        cmd.arg("--target").arg(target);
+    }
+    if safe {
+        cmd.arg("-j2");
+    }
+    cmd
+}
+
 fn main_generate_doc() {
     let project_root = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
 
     fn cmd(target: Option<&str>, safe: bool) -> Command {
-        let mut cmd = Command::new("cargo");
-        cmd.arg("doc")
-            .env("RUSTFLAGS", "-D warnings")
-            .arg("--locked")
-            .arg("--no-deps")
-            .arg("--document-private-items");
-        if let Some(target) = target {
-            cmd.arg("--target").arg(target);
-        }
-        if safe {
-            cmd.arg("-j2");
-        }
-        cmd
+        doc_command(target, safe, "")
     }
 
     for target in ["x86_64-unknown-uefi", "x86_64-unknown-linux-gnu"] {
@@ -706,8 +749,7 @@ fn main_generate_doc() {
         }
     }
 
-    if !cmd(None, false)
-        .env("RUSTDOCFLAGS", "--enable-index-page -Zunstable-options")
+    if !doc_command(None, false, "--enable-index-page -Zunstable-options")
         .args(["-p", "xtask"])
         .current_dir(&project_root)
         .status()
@@ -718,3 +760,46 @@ fn main_generate_doc() {
         std::process::exit(-1);
     }
 }
+
+/// Builds the documentation of a scratch project once with a valid and once with a broken
+/// intra-doc link, and fails unless only the broken link is rejected
+fn main_check_doc() {
+    let project = env::temp_dir().join(format!("xtask_check_doc_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&project);
+    fs::create_dir_all(project.join("src")).expect("Failed to create the check project");
+    fs::write(
+        project.join("Cargo.toml"),
+        "[package]\nname = \"broken_doc\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[workspace]\n",
+    )
+    .expect("Failed to write the check project");
+    fs::write(
+        project.join("Cargo.lock"),
+        "version = 3\n\n[[package]]\nname = \"broken_doc\"\nversion = \"0.1.0\"\n",
+    )
+    .expect("Failed to write the check project");
+
+    let build = |source: &str| {
+        fs::write(project.join("src").join("lib.rs"), source)
+            .expect("Failed to write the check project");
+        doc_command(None, false, "")
+            .current_dir(&project)
+            .env("CARGO_TARGET_DIR", project.join("target"))
+            .status()
+            .expect("Failed to run cargo doc")
+            .success()
+    };
+
+    let valid = build("//! Links to [`Existing`]\n\n/// Existing item\npub struct Existing;\n");
+    let broken = build("//! Links to [`Missing`]\n\n/// Existing item\npub struct Existing;\n");
+    let _ = fs::remove_dir_all(&project);
+
+    if !valid {
+        eprintln!("Documentation with valid links failed to build");
+        std::process::exit(-1);
+    }
+    if broken {
+        eprintln!("Documentation with a broken link was not rejected");
+        std::process::exit(-1);
+    }
+    println!("Documentation lints reject broken links");
+}